@@ -1,20 +1,34 @@
 pub mod array;
+pub mod binding;
 pub mod class;
+pub mod coverage;
+pub mod dir;
+pub mod encoding;
 pub mod enumerator;
 pub mod errorobj;
 pub mod fiber;
 pub mod file;
+pub mod fileutils;
 pub mod float;
+pub mod format;
 pub mod hash;
 pub mod integer;
+pub mod io;
 pub mod kernel;
+pub mod matchdata;
 pub mod math;
 pub mod method;
 pub mod module;
 pub mod object;
+pub mod pack;
 pub mod process;
 pub mod procobj;
+pub mod random;
 pub mod range;
 pub mod regexp;
+pub mod rubyvm;
 pub mod string;
 pub mod structobj;
+pub mod symbol;
+pub mod time;
+pub mod timezone;
@@ -1,5 +1,23 @@
+use crate::builtin::random;
 use crate::error::RubyError;
 use crate::*;
+use rand::seq::SliceRandom;
+use rand::RngCore;
+use std::cmp::Ordering;
+
+/// Evaluates to the block given to the method `$name`, or returns an Enumerator when no block is given.
+macro_rules! block_or_enum {
+    ($vm:ident, $self_val:ident, $args:ident, $name:expr) => {
+        match $args.block {
+            Some(block) => block,
+            None => {
+                let id = $vm.globals.get_ident_id($name);
+                let val = Value::enumerator(&$vm.globals, id, $self_val, $args.clone());
+                return Ok(val);
+            }
+        }
+    };
+}
 
 pub fn init_array(globals: &mut Globals) -> Value {
     let array_id = globals.get_ident_id("Array");
@@ -50,6 +68,54 @@ pub fn init_array(globals: &mut Globals) -> Value {
     globals.add_builtin_instance_method(class, "zip", zip);
     globals.add_builtin_instance_method(class, "grep", grep);
     globals.add_builtin_instance_method(class, "sort", sort);
+    globals.add_builtin_instance_method(class, "sort!", sort_);
+    globals.add_builtin_instance_method(class, "sort_by!", sort_by_);
+    globals.add_builtin_instance_method(class, "each_with_index", each_with_index);
+    globals.add_builtin_instance_method(class, "each_index", each_index);
+    globals.add_builtin_instance_method(class, "each_slice", each_slice);
+    globals.add_builtin_instance_method(class, "cycle", cycle);
+    globals.add_builtin_instance_method(class, "select", select);
+    globals.add_builtin_instance_method(class, "filter", select);
+    globals.add_builtin_instance_method(class, "reject", reject);
+    globals.add_builtin_instance_method(class, "select!", select_);
+    globals.add_builtin_instance_method(class, "filter!", select_);
+    globals.add_builtin_instance_method(class, "keep_if", keep_if);
+    globals.add_builtin_instance_method(class, "reject!", reject_);
+    globals.add_builtin_instance_method(class, "delete_if", delete_if);
+    globals.add_builtin_instance_method(class, "map!", map_);
+    globals.add_builtin_instance_method(class, "collect!", map_);
+    globals.add_builtin_instance_method(class, "delete", delete);
+    globals.add_builtin_instance_method(class, "delete_at", delete_at);
+    globals.add_builtin_instance_method(class, "insert", insert);
+    globals.add_builtin_instance_method(class, "index", index);
+    globals.add_builtin_instance_method(class, "find_index", index);
+    globals.add_builtin_instance_method(class, "rindex", rindex);
+    globals.add_builtin_instance_method(class, "compact", compact);
+    globals.add_builtin_instance_method(class, "compact!", compact_);
+    globals.add_builtin_instance_method(class, "flatten", flatten);
+    globals.add_builtin_instance_method(class, "flatten!", flatten_);
+    globals.add_builtin_instance_method(class, "sample", sample);
+    globals.add_builtin_instance_method(class, "shuffle", shuffle);
+    globals.add_builtin_instance_method(class, "shuffle!", shuffle_);
+    globals.add_builtin_instance_method(class, "product", product);
+    globals.add_builtin_instance_method(class, "combination", combination);
+    globals.add_builtin_instance_method(class, "permutation", permutation);
+    globals.add_builtin_instance_method(class, "&", and);
+    globals.add_builtin_instance_method(class, "|", or);
+    globals.add_builtin_instance_method(class, "intersection", intersection);
+    globals.add_builtin_instance_method(class, "union", union);
+    globals.add_builtin_instance_method(class, "difference", difference);
+    globals.add_builtin_instance_method(class, "assoc", assoc);
+    globals.add_builtin_instance_method(class, "dig", dig);
+    globals.add_builtin_instance_method(class, "sum", sum);
+    globals.add_builtin_instance_method(class, "minmax", minmax);
+    globals.add_builtin_instance_method(class, "bsearch", bsearch);
+    globals.add_builtin_instance_method(class, "count", count);
+    globals.add_builtin_instance_method(class, "take_while", take_while);
+    globals.add_builtin_instance_method(class, "drop_while", drop_while);
+    globals.add_builtin_instance_method(class, "values_at", values_at);
+    globals.add_builtin_instance_method(class, "fetch", fetch);
+    globals.add_builtin_instance_method(class, "to_h", to_h);
     globals.add_builtin_class_method(obj, "new", array_new);
     obj
 }
@@ -463,47 +529,40 @@ fn transpose(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     Ok(res)
 }
 
-fn min(vm: &mut VM, self_val: Value, _args: &Args) -> VMResult {
-    fn to_float(vm: &VM, val: Value) -> Result<f64, RubyError> {
-        if val.is_packed_fixnum() {
-            Ok(val.as_packed_fixnum() as f64)
-        } else if val.is_packed_num() {
-            Ok(val.as_packed_flonum())
-        } else {
-            Err(vm.error_type("Currently, each element must be Numeric."))
-        }
-    }
-
-    let aref = self_val.as_array().unwrap();
-    if aref.elements.len() == 0 {
-        return Ok(Value::nil());
-    }
-    let mut min_obj = aref.elements[0];
-    let mut min = to_float(vm, min_obj)?;
-    for elem in &aref.elements {
-        let elem_f = to_float(vm, *elem)?;
-        if elem_f < min {
-            min_obj = *elem;
-            min = elem_f;
-        }
-    }
-
-    return Ok(min_obj);
+fn min(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    min_max(vm, self_val, args, Ordering::Less)
 }
 
 fn max(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
-    vm.check_args_num(args.len(), 0)?;
-    let aref = self_val.as_array().unwrap();
-    if aref.elements.len() == 0 {
-        return Ok(Value::nil());
-    }
-    let mut max = aref.elements[0];
-    for elem in &aref.elements {
-        if vm.eval_gt(max, *elem)? == Value::true_val() {
-            max = *elem;
+    min_max(vm, self_val, args, Ordering::Greater)
+}
+
+/// `min` and `max`, where `ord` is the ordering of the wanted element against the others.
+fn min_max(vm: &mut VM, self_val: Value, args: &Args, ord: Ordering) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let elements = self_val.as_array().unwrap().elements.clone();
+    let block = args.block;
+    if args.len() == 0 {
+        let mut res = match elements.first() {
+            Some(elem) => *elem,
+            None => return Ok(Value::nil()),
         };
+        for elem in &elements[1..] {
+            if compare(vm, *elem, res, block)? == ord {
+                res = *elem;
+            }
+        }
+        return Ok(res);
     }
-    Ok(max)
+    let n = args[0].expect_integer(vm, "1st arg")?;
+    if n < 0 {
+        return Err(vm.error_argument(format!("negative size ({})", n)));
+    }
+    let mut res = merge_sort(vm, &elements, &mut |vm, lhs, rhs| {
+        Ok(compare(vm, lhs, rhs, block)? == ord.reverse())
+    })?;
+    res.truncate(n as usize);
+    Ok(Value::array_from(&vm.globals, res))
 }
 
 fn fill(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
@@ -587,17 +646,11 @@ fn dup(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
 }
 
 fn pack(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
-    vm.check_args_range(args.len(), 0, 1)?;
+    vm.check_args_num(args.len(), 1)?;
     let aref = self_val.as_array().unwrap();
-    let mut v = vec![];
-    for elem in &aref.elements {
-        let i = match elem.as_fixnum() {
-            Some(i) => i as i8 as u8,
-            None => return Err(vm.error_argument("Must be Array of Integer.")),
-        };
-        v.push(i);
-    }
-    Ok(Value::bytes(&vm.globals, v))
+    let template = vm.expect_string(&args[0], "Template")?.to_string();
+    let res = crate::builtin::pack::pack(vm, &aref.elements, &template)?;
+    Ok(Value::rstring(&vm.globals, res))
 }
 
 fn join(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
@@ -681,18 +734,57 @@ fn grep(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
 }
 
 fn sort(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
-    //use std::cmp::Ordering;
     vm.check_args_num(args.len(), 0)?;
     let aref = vm.expect_array(self_val, "Receiver")?.dup();
-    match args.block {
-        None => {
-            vm.sort_array(aref)?;
-        }
-        Some(_block) => return Err(vm.error_argument("Currently, can not use block.")),
-    };
+    sort_elements(vm, aref, args.block)?;
     Ok(Value::array(&vm.globals, aref))
 }
 
+fn sort_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let aref = self_val.as_array().unwrap();
+    sort_elements(vm, aref, args.block)?;
+    Ok(self_val)
+}
+
+/// Sort the elements of `aref` with `<=>`, or with the block when it is given.
+fn sort_elements(
+    vm: &mut VM,
+    mut aref: ArrayRef,
+    block: Option<MethodRef>,
+) -> Result<(), RubyError> {
+    match block {
+        None => vm.sort_array(aref),
+        Some(block) => {
+            let elements = aref.elements.clone();
+            aref.elements = merge_sort(vm, &elements, &mut |vm, lhs, rhs| {
+                Ok(compare(vm, lhs, rhs, Some(block))? == Ordering::Greater)
+            })?;
+            Ok(())
+        }
+    }
+}
+
+fn sort_by_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let block = block_or_enum!(vm, self_val, args, "sort_by!");
+    let mut aref = self_val.as_array().unwrap();
+    let elements = aref.elements.clone();
+    if block == MethodRef::from(0) {
+        return Ok(Value::array_from(&vm.globals, elements));
+    }
+    let mut keyed = vec![];
+    for elem in elements {
+        let key = vm.eval_block(block, &Args::new1(elem))?;
+        keyed.push((key, elem));
+    }
+    let sorted = merge_sort(vm, &keyed, &mut |vm, lhs, rhs| {
+        Ok(compare(vm, lhs.0, rhs.0, None)? == Ordering::Greater)
+    })?;
+    aref.elements = sorted.into_iter().map(|(_, elem)| elem).collect();
+    Ok(self_val)
+}
+
 use std::collections::HashSet;
 fn uniq(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     vm.check_args_num(args.len(), 0)?;
@@ -712,6 +804,926 @@ fn uniq(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     Ok(Value::array_from(&vm.globals, v))
 }
 
+/// Compare `lhs` and `rhs` with the block, or with `<=>` when no block is given.
+pub fn compare(
+    vm: &mut VM,
+    lhs: Value,
+    rhs: Value,
+    block: Option<MethodRef>,
+) -> Result<Ordering, RubyError> {
+    let res = match block {
+        Some(block) => vm.eval_block(block, &Args::new2(lhs, rhs))?,
+        None => vm.eval_cmp(rhs, lhs)?,
+    };
+    match res.as_fixnum() {
+        Some(i) => Ok(i.cmp(&0)),
+        None => {
+            let lhs = vm.globals.get_class_name(lhs);
+            let rhs = vm.globals.get_class_name(rhs);
+            Err(vm.error_argument(format!("comparison of {} with {} failed", lhs, rhs)))
+        }
+    }
+}
+
+/// Stable sort of `items`. `after(vm, a, b)` returns true when `a` must be placed after `b`.
+/// Unlike `slice::sort_by`, errors raised by the comparison are propagated.
+pub fn merge_sort<T: Copy>(
+    vm: &mut VM,
+    items: &[T],
+    after: &mut dyn FnMut(&mut VM, T, T) -> Result<bool, RubyError>,
+) -> Result<Vec<T>, RubyError> {
+    if items.len() <= 1 {
+        return Ok(items.to_vec());
+    }
+    let (left, right) = items.split_at(items.len() / 2);
+    let left = merge_sort(vm, left, after)?;
+    let right = merge_sort(vm, right, after)?;
+    let mut res = Vec::with_capacity(items.len());
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        if after(vm, left[i], right[j])? {
+            res.push(right[j]);
+            j += 1;
+        } else {
+            res.push(left[i]);
+            i += 1;
+        }
+    }
+    res.extend_from_slice(&left[i..]);
+    res.extend_from_slice(&right[j..]);
+    Ok(res)
+}
+
+fn each_with_index(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let block = block_or_enum!(vm, self_val, args, "each_with_index");
+    let elements = self_val.as_array().unwrap().elements.clone();
+    let pairs = elements
+        .into_iter()
+        .enumerate()
+        .map(|(i, elem)| (elem, Value::fixnum(i as i64)));
+    if block == MethodRef::from(0) {
+        let pairs = pairs
+            .map(|(elem, i)| Value::array_from(&vm.globals, vec![elem, i]))
+            .collect();
+        return Ok(Value::array_from(&vm.globals, pairs));
+    }
+    for (elem, i) in pairs {
+        vm.eval_block(block, &Args::new2(elem, i))?;
+    }
+    Ok(self_val)
+}
+
+fn each_index(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let block = block_or_enum!(vm, self_val, args, "each_index");
+    let len = self_val.as_array().unwrap().elements.len();
+    let indices = (0..len).map(|i| Value::fixnum(i as i64));
+    if block == MethodRef::from(0) {
+        return Ok(Value::array_from(&vm.globals, indices.collect()));
+    }
+    for i in indices {
+        vm.eval_block(block, &Args::new1(i))?;
+    }
+    Ok(self_val)
+}
+
+fn each_slice(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let size = args[0].expect_integer(vm, "1st arg")?;
+    if size <= 0 {
+        return Err(vm.error_argument("invalid slice size"));
+    }
+    let block = block_or_enum!(vm, self_val, args, "each_slice");
+    let elements = self_val.as_array().unwrap().elements.clone();
+    let slices: Vec<Value> = elements
+        .chunks(size as usize)
+        .map(|slice| Value::array_from(&vm.globals, slice.to_vec()))
+        .collect();
+    if block == MethodRef::from(0) {
+        return Ok(Value::array_from(&vm.globals, slices));
+    }
+    for slice in slices {
+        vm.eval_block(block, &Args::new1(slice))?;
+    }
+    Ok(self_val)
+}
+
+fn cycle(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let count = match args.first() {
+        Some(n) if !n.is_nil() => Some(n.expect_integer(vm, "1st arg")?.max(0) as usize),
+        _ => None,
+    };
+    let block = block_or_enum!(vm, self_val, args, "cycle");
+    let aref = self_val.as_array().unwrap();
+    if block == MethodRef::from(0) {
+        let count = match count {
+            Some(count) => count,
+            None => {
+                return Err(vm.error_argument("Currently, endless cycle can not be enumerated."))
+            }
+        };
+        let mut res = vec![];
+        for _ in 0..count {
+            res.extend_from_slice(&aref.elements);
+        }
+        return Ok(Value::array_from(&vm.globals, res));
+    }
+    let mut n = 0;
+    while count.is_none_or(|count| n < count) && !aref.elements.is_empty() {
+        let mut i = 0;
+        while i < aref.elements.len() {
+            vm.eval_block(block, &Args::new1(aref.elements[i]))?;
+            i += 1;
+        }
+        n += 1;
+    }
+    Ok(Value::nil())
+}
+
+/// Evaluate the block for each element, returning the elements for which the block returns `keep`.
+fn filter_by_block(
+    vm: &mut VM,
+    elements: Vec<Value>,
+    block: MethodRef,
+    keep: bool,
+) -> Result<Vec<Value>, RubyError> {
+    let mut res = vec![];
+    for elem in elements {
+        let val = vm.eval_block(block, &Args::new1(elem))?;
+        if vm.val_to_bool(val) == keep {
+            res.push(elem);
+        }
+    }
+    Ok(res)
+}
+
+/// Keep the elements for which the block returns `keep`. Returns whether any element was removed.
+/// When `name` is evaluated for an Enumerator, the elements are returned instead.
+fn retain_by_block(
+    vm: &mut VM,
+    self_val: Value,
+    args: &Args,
+    name: &str,
+    keep: bool,
+) -> Result<Result<bool, Value>, RubyError> {
+    vm.check_args_num(args.len(), 0)?;
+    let mut aref = self_val.as_array().unwrap();
+    let elements = aref.elements.clone();
+    let block = match args.block {
+        Some(block) if block == MethodRef::from(0) => {
+            return Ok(Err(Value::array_from(&vm.globals, elements)))
+        }
+        Some(block) => block,
+        None => {
+            let id = vm.globals.get_ident_id(name);
+            let val = Value::enumerator(&vm.globals, id, self_val, args.clone());
+            return Ok(Err(val));
+        }
+    };
+    let len = elements.len();
+    aref.elements = filter_by_block(vm, elements, block, keep)?;
+    Ok(Ok(aref.elements.len() != len))
+}
+
+fn select(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let block = block_or_enum!(vm, self_val, args, "select");
+    let elements = self_val.as_array().unwrap().elements.clone();
+    if block == MethodRef::from(0) {
+        return Ok(Value::array_from(&vm.globals, elements));
+    }
+    let res = filter_by_block(vm, elements, block, true)?;
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn reject(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let block = block_or_enum!(vm, self_val, args, "reject");
+    let elements = self_val.as_array().unwrap().elements.clone();
+    if block == MethodRef::from(0) {
+        return Ok(Value::array_from(&vm.globals, elements));
+    }
+    let res = filter_by_block(vm, elements, block, false)?;
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn select_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    match retain_by_block(vm, self_val, args, "select!", true)? {
+        Ok(true) => Ok(self_val),
+        Ok(false) => Ok(Value::nil()),
+        Err(val) => Ok(val),
+    }
+}
+
+fn keep_if(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    match retain_by_block(vm, self_val, args, "keep_if", true)? {
+        Ok(_) => Ok(self_val),
+        Err(val) => Ok(val),
+    }
+}
+
+fn reject_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    match retain_by_block(vm, self_val, args, "reject!", false)? {
+        Ok(true) => Ok(self_val),
+        Ok(false) => Ok(Value::nil()),
+        Err(val) => Ok(val),
+    }
+}
+
+fn delete_if(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    match retain_by_block(vm, self_val, args, "delete_if", false)? {
+        Ok(_) => Ok(self_val),
+        Err(val) => Ok(val),
+    }
+}
+
+fn map_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let block = block_or_enum!(vm, self_val, args, "map!");
+    let mut aref = self_val.as_array().unwrap();
+    let elements = aref.elements.clone();
+    if block == MethodRef::from(0) {
+        return Ok(Value::array_from(&vm.globals, elements));
+    }
+    let mut res = vec![];
+    for elem in elements {
+        res.push(vm.eval_block(block, &Args::new1(elem))?);
+    }
+    aref.elements = res;
+    Ok(self_val)
+}
+
+fn delete(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let mut aref = self_val.as_array().unwrap();
+    let mut deleted = None;
+    let mut res = vec![];
+    for elem in &aref.elements {
+        if vm.eval_eq(*elem, args[0])? {
+            deleted = Some(*elem);
+        } else {
+            res.push(*elem);
+        }
+    }
+    match deleted {
+        Some(deleted) => {
+            aref.elements = res;
+            Ok(deleted)
+        }
+        None => match args.block {
+            Some(block) => vm.eval_block(block, &Args::new1(args[0])),
+            None => Ok(Value::nil()),
+        },
+    }
+}
+
+fn delete_at(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let mut aref = self_val.as_array().unwrap();
+    let len = aref.elements.len() as i64;
+    let index = args[0].expect_integer(vm, "1st arg")?;
+    let index = if index < 0 { index + len } else { index };
+    if index < 0 || index >= len {
+        return Ok(Value::nil());
+    }
+    Ok(aref.elements.remove(index as usize))
+}
+
+fn insert(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_min(args.len(), 1)?;
+    let mut aref = self_val.as_array().unwrap();
+    if args.len() == 1 {
+        return Ok(self_val);
+    }
+    let len = aref.elements.len() as i64;
+    let index = args[0].expect_integer(vm, "1st arg")?;
+    let pos = if index < 0 { index + len + 1 } else { index };
+    if pos < 0 {
+        return Err(vm.error_index(format!(
+            "index {} too small for array; minimum: -{}",
+            index,
+            len + 1
+        )));
+    }
+    let pos = pos as usize;
+    if pos > aref.elements.len() {
+        aref.elements.resize(pos, Value::nil());
+    }
+    let tail = aref.elements.split_off(pos);
+    aref.elements.extend_from_slice(&args[1..args.len()]);
+    aref.elements.extend(tail);
+    Ok(self_val)
+}
+
+/// The position of the first element equal to the argument, or for which the block returns true.
+fn find_position(
+    vm: &mut VM,
+    elements: &[Value],
+    args: &Args,
+    rev: bool,
+) -> Result<Option<usize>, RubyError> {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let mut indices: Box<dyn Iterator<Item = usize>> = match rev {
+        false => Box::new(0..elements.len()),
+        true => Box::new((0..elements.len()).rev()),
+    };
+    match (args.len(), args.block) {
+        (1, _) => Ok(indices.find(|i| elements[*i].equal(args[0]))),
+        (_, Some(block)) => {
+            for i in indices {
+                let res = vm.eval_block(block, &Args::new1(elements[i]))?;
+                if vm.val_to_bool(res) {
+                    return Ok(Some(i));
+                }
+            }
+            Ok(None)
+        }
+        _ => Err(vm.error_argument("Currently, an argument or a block is required.")),
+    }
+}
+
+fn index(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let elements = self_val.as_array().unwrap().elements.clone();
+    match find_position(vm, &elements, args, false)? {
+        Some(i) => Ok(Value::fixnum(i as i64)),
+        None => Ok(Value::nil()),
+    }
+}
+
+fn rindex(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let elements = self_val.as_array().unwrap().elements.clone();
+    match find_position(vm, &elements, args, true)? {
+        Some(i) => Ok(Value::fixnum(i as i64)),
+        None => Ok(Value::nil()),
+    }
+}
+
+fn compact(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let aref = self_val.as_array().unwrap();
+    let res = aref
+        .elements
+        .iter()
+        .filter(|x| !x.is_nil())
+        .cloned()
+        .collect();
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn compact_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let mut aref = self_val.as_array().unwrap();
+    let len = aref.elements.len();
+    aref.elements.retain(|x| !x.is_nil());
+    if aref.elements.len() == len {
+        Ok(Value::nil())
+    } else {
+        Ok(self_val)
+    }
+}
+
+/// Flatten `elements` into `res` down to `depth` levels, or all levels for None.
+/// Returns whether any element was flattened.
+fn flatten_into(
+    vm: &VM,
+    elements: &[Value],
+    depth: Option<usize>,
+    stack: &mut Vec<ArrayRef>,
+    res: &mut Vec<Value>,
+) -> Result<bool, RubyError> {
+    let mut flattened = false;
+    for elem in elements {
+        match elem.as_array() {
+            Some(aref) if depth != Some(0) => {
+                if stack.contains(&aref) {
+                    return Err(vm.error_argument("tried to flatten recursive array"));
+                }
+                stack.push(aref);
+                flatten_into(vm, &aref.elements, depth.map(|d| d - 1), stack, res)?;
+                stack.pop();
+                flattened = true;
+            }
+            _ => res.push(*elem),
+        }
+    }
+    Ok(flattened)
+}
+
+/// Flatten the receiver for `flatten` and `flatten!`.
+fn flatten_elements(
+    vm: &mut VM,
+    self_val: Value,
+    args: &Args,
+) -> Result<(Vec<Value>, bool), RubyError> {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let depth = match args.first() {
+        Some(depth) if !depth.is_nil() => match depth.expect_integer(vm, "1st arg")? {
+            d if d < 0 => None,
+            d => Some(d as usize),
+        },
+        _ => None,
+    };
+    let aref = self_val.as_array().unwrap();
+    let mut res = vec![];
+    let flattened = flatten_into(vm, &aref.elements, depth, &mut vec![aref], &mut res)?;
+    Ok((res, flattened))
+}
+
+fn flatten(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let (res, _) = flatten_elements(vm, self_val, args)?;
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn flatten_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let (res, flattened) = flatten_elements(vm, self_val, args)?;
+    if !flattened {
+        return Ok(Value::nil());
+    }
+    self_val.as_array().unwrap().elements = res;
+    Ok(self_val)
+}
+
+fn sample(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let aref = self_val.as_array().unwrap();
+    let mut random = random::random_arg(vm, args)?;
+    let mut thread_rng = rand::thread_rng();
+    let rng: &mut dyn RngCore = match &mut random {
+        Some(random) => random.rng(),
+        None => &mut thread_rng,
+    };
+    if args.len() == 0 {
+        return Ok(aref.elements.choose(rng).cloned().unwrap_or_default());
+    }
+    let n = args[0].expect_integer(vm, "1st arg")?;
+    if n < 0 {
+        return Err(vm.error_argument("negative sample number"));
+    }
+    let res = aref
+        .elements
+        .choose_multiple(rng, n as usize)
+        .cloned()
+        .collect();
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn shuffle(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let res = Value::array_from(&vm.globals, self_val.as_array().unwrap().elements.clone());
+    shuffle_(vm, res, args)
+}
+
+fn shuffle_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let mut aref = self_val.as_array().unwrap();
+    match random::random_arg(vm, args)? {
+        Some(mut random) => aref.elements.shuffle(random.rng()),
+        None => aref.elements.shuffle(&mut rand::thread_rng()),
+    }
+    Ok(self_val)
+}
+
+/// Evaluate the block with each of `arrays`, or return them as an Array when no block is given
+/// or the method is evaluated for an Enumerator.
+fn yield_arrays(
+    vm: &mut VM,
+    self_val: Value,
+    arrays: Vec<Vec<Value>>,
+    block: Option<MethodRef>,
+) -> VMResult {
+    let arrays = arrays
+        .into_iter()
+        .map(|ary| Value::array_from(&vm.globals, ary));
+    match block {
+        Some(block) if block != MethodRef::from(0) => {
+            for ary in arrays.collect::<Vec<_>>() {
+                vm.eval_block(block, &Args::new1(ary))?;
+            }
+            Ok(self_val)
+        }
+        _ => Ok(Value::array_from(&vm.globals, arrays.collect())),
+    }
+}
+
+fn product(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let mut res = vec![vec![]];
+    let mut arrays = vec![self_val.as_array().unwrap().elements.clone()];
+    for arg in args.iter() {
+        arrays.push(vm.expect_array(*arg, "Args")?.elements.clone());
+    }
+    for ary in arrays {
+        res = res
+            .into_iter()
+            .flat_map(|prefix: Vec<Value>| {
+                ary.iter().map(move |elem| {
+                    let mut v = prefix.clone();
+                    v.push(*elem);
+                    v
+                })
+            })
+            .collect();
+    }
+    yield_arrays(vm, self_val, res, args.block)
+}
+
+fn combination(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let n = args[0].expect_integer(vm, "1st arg")?;
+    let block = block_or_enum!(vm, self_val, args, "combination");
+    let elements = self_val.as_array().unwrap().elements.clone();
+    let mut res = vec![];
+    if n >= 0 && n as usize <= elements.len() {
+        let n = n as usize;
+        let mut indices: Vec<usize> = (0..n).collect();
+        loop {
+            res.push(indices.iter().map(|i| elements[*i]).collect());
+            // Advance the rightmost index that has room to move.
+            let i = match (0..n).rev().find(|i| indices[*i] < elements.len() - n + i) {
+                Some(i) => i,
+                None => break,
+            };
+            indices[i] += 1;
+            for j in i + 1..n {
+                indices[j] = indices[j - 1] + 1;
+            }
+        }
+    }
+    yield_arrays(vm, self_val, res, Some(block))
+}
+
+fn permutation(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let elements = self_val.as_array().unwrap().elements.clone();
+    let n = match args.first() {
+        Some(n) => n.expect_integer(vm, "1st arg")?,
+        None => elements.len() as i64,
+    };
+    let block = block_or_enum!(vm, self_val, args, "permutation");
+    fn permute(
+        elements: &[Value],
+        n: usize,
+        used: &mut Vec<bool>,
+        current: &mut Vec<Value>,
+        res: &mut Vec<Vec<Value>>,
+    ) {
+        if current.len() == n {
+            res.push(current.clone());
+            return;
+        }
+        for i in 0..elements.len() {
+            if !used[i] {
+                used[i] = true;
+                current.push(elements[i]);
+                permute(elements, n, used, current, res);
+                current.pop();
+                used[i] = false;
+            }
+        }
+    }
+    let mut res = vec![];
+    if n >= 0 && n as usize <= elements.len() {
+        let mut used = vec![false; elements.len()];
+        permute(&elements, n as usize, &mut used, &mut vec![], &mut res);
+    }
+    yield_arrays(vm, self_val, res, Some(block))
+}
+
+/// The elements of the receiver followed by the elements of `args`, without duplicates.
+fn union_elements(vm: &mut VM, self_val: Value, args: &[Value]) -> Result<Vec<Value>, RubyError> {
+    let mut set = HashSet::new();
+    let mut res = vec![];
+    let mut arrays = vec![self_val.as_array().unwrap()];
+    for arg in args {
+        arrays.push(vm.expect_array(*arg, "Args")?);
+    }
+    for ary in arrays {
+        for elem in &ary.elements {
+            if set.insert(HashKey(*elem)) {
+                res.push(*elem);
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// The elements of the receiver which are included in all of `args`, without duplicates.
+fn intersection_elements(
+    vm: &mut VM,
+    self_val: Value,
+    args: &[Value],
+) -> Result<Vec<Value>, RubyError> {
+    let mut sets = vec![];
+    for arg in args {
+        let ary = vm.expect_array(*arg, "Args")?;
+        let set: HashSet<HashKey> = ary.elements.iter().map(|x| HashKey(*x)).collect();
+        sets.push(set);
+    }
+    let mut found = HashSet::new();
+    let res = self_val
+        .as_array()
+        .unwrap()
+        .elements
+        .iter()
+        .filter(|x| sets.iter().all(|set| set.contains(&HashKey(**x))))
+        .filter(|x| found.insert(HashKey(**x)))
+        .cloned()
+        .collect();
+    Ok(res)
+}
+
+fn and(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let res = intersection_elements(vm, self_val, args)?;
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn or(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let res = union_elements(vm, self_val, args)?;
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn intersection(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let res = intersection_elements(vm, self_val, args)?;
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn union(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let res = union_elements(vm, self_val, args)?;
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn difference(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let mut set = HashSet::new();
+    for arg in args.iter() {
+        let ary = vm.expect_array(*arg, "Args")?;
+        set.extend(ary.elements.iter().map(|x| HashKey(*x)));
+    }
+    let res = self_val
+        .as_array()
+        .unwrap()
+        .elements
+        .iter()
+        .filter(|x| !set.contains(&HashKey(**x)))
+        .cloned()
+        .collect();
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn assoc(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let aref = self_val.as_array().unwrap();
+    for elem in &aref.elements {
+        if let Some(ary) = elem.as_array() {
+            if let Some(key) = ary.elements.first() {
+                if vm.eval_eq(*key, args[0])? {
+                    return Ok(*elem);
+                }
+            }
+        }
+    }
+    Ok(Value::nil())
+}
+
+fn dig(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_min(args.len(), 1)?;
+    let aref = self_val.as_array().unwrap();
+    let val = aref.get_elem(vm, &Args::new1(args[0]))?;
+    if args.len() == 1 || val.is_nil() {
+        return Ok(val);
+    }
+    let id = vm.globals.get_ident_id("dig");
+    match vm.get_method(val, id) {
+        Ok(method) => {
+            let mut rest = Args::new0();
+            for arg in &args[1..args.len()] {
+                rest.push(*arg);
+            }
+            vm.eval_send(method, val, &rest)
+        }
+        Err(_) => {
+            let class = vm.globals.get_class_name(val);
+            Err(vm.error_type(format!("{} does not have #dig method", class)))
+        }
+    }
+}
+
+fn sum(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let elements = self_val.as_array().unwrap().elements.clone();
+    let mut sum = match args.first() {
+        Some(init) => *init,
+        None => Value::fixnum(0),
+    };
+    // Floats are summed with the Kahan-Babuska algorithm, as CRuby does.
+    let mut compensation = 0.0;
+    for elem in elements {
+        let elem = match args.block {
+            Some(block) => vm.eval_block(block, &Args::new1(elem))?,
+            None => elem,
+        };
+        sum = match (sum.unpack(), elem.unpack()) {
+            (RV::Integer(lhs), RV::Integer(rhs)) => Value::fixnum(lhs + rhs),
+            (RV::Integer(_), RV::Float(_))
+            | (RV::Float(_), RV::Integer(_))
+            | (RV::Float(_), RV::Float(_)) => {
+                let to_f = |val: Value| match val.unpack() {
+                    RV::Integer(i) => i as f64,
+                    RV::Float(f) => f,
+                    _ => unreachable!(),
+                };
+                let (lhs, rhs) = (to_f(sum), to_f(elem));
+                let t = lhs + rhs;
+                if lhs.abs() >= rhs.abs() {
+                    compensation += (lhs - t) + rhs;
+                } else {
+                    compensation += (rhs - t) + lhs;
+                }
+                Value::flonum(t)
+            }
+            _ => {
+                if let Some(f) = sum.as_flonum() {
+                    sum = Value::flonum(f + compensation);
+                    compensation = 0.0;
+                }
+                let method = vm.get_method(sum, IdentId::_ADD)?;
+                vm.eval_send(method, sum, &Args::new1(elem))?
+            }
+        };
+    }
+    match sum.as_flonum() {
+        Some(f) => Ok(Value::flonum(f + compensation)),
+        None => Ok(sum),
+    }
+}
+
+fn minmax(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let min = min_max(vm, self_val, args, Ordering::Less)?;
+    let max = min_max(vm, self_val, args, Ordering::Greater)?;
+    Ok(Value::array_from(&vm.globals, vec![min, max]))
+}
+
+fn bsearch(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let block = block_or_enum!(vm, self_val, args, "bsearch");
+    let elements = self_val.as_array().unwrap().elements.clone();
+    let (mut low, mut high) = (0, elements.len());
+    let mut found = None;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let res = vm.eval_block(block, &Args::new1(elements[mid]))?;
+        // Find-minimum mode with true/false/nil, or find-any mode with a number.
+        let go_left = match res.unpack() {
+            RV::Bool(true) => {
+                found = Some(elements[mid]);
+                true
+            }
+            RV::Bool(false) | RV::Nil => false,
+            RV::Integer(0) => return Ok(elements[mid]),
+            RV::Integer(i) => i < 0,
+            RV::Float(0.0) => return Ok(elements[mid]),
+            RV::Float(f) => f < 0.0,
+            _ => {
+                let class = vm.globals.get_class_name(res);
+                return Err(vm.error_type(format!(
+                    "wrong argument type {} (must be numeric, true, false or nil)",
+                    class
+                )));
+            }
+        };
+        if go_left {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    Ok(found.unwrap_or_default())
+}
+
+fn count(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let elements = self_val.as_array().unwrap().elements.clone();
+    let count = match (args.len(), args.block) {
+        (1, _) => elements.iter().filter(|x| x.equal(args[0])).count(),
+        (_, Some(block)) => filter_by_block(vm, elements, block, true)?.len(),
+        _ => elements.len(),
+    };
+    Ok(Value::fixnum(count as i64))
+}
+
+/// The number of leading elements for which the block returns true.
+fn leading_len(vm: &mut VM, elements: &[Value], block: MethodRef) -> Result<usize, RubyError> {
+    for (i, elem) in elements.iter().enumerate() {
+        let res = vm.eval_block(block, &Args::new1(*elem))?;
+        if !vm.val_to_bool(res) {
+            return Ok(i);
+        }
+    }
+    Ok(elements.len())
+}
+
+fn take_while(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let block = block_or_enum!(vm, self_val, args, "take_while");
+    let elements = self_val.as_array().unwrap().elements.clone();
+    if block == MethodRef::from(0) {
+        return Ok(Value::array_from(&vm.globals, elements));
+    }
+    let len = leading_len(vm, &elements, block)?;
+    Ok(Value::array_from(&vm.globals, elements[..len].to_vec()))
+}
+
+fn drop_while(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let block = block_or_enum!(vm, self_val, args, "drop_while");
+    let elements = self_val.as_array().unwrap().elements.clone();
+    if block == MethodRef::from(0) {
+        return Ok(Value::array_from(&vm.globals, elements));
+    }
+    let len = leading_len(vm, &elements, block)?;
+    Ok(Value::array_from(&vm.globals, elements[len..].to_vec()))
+}
+
+fn values_at(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let aref = self_val.as_array().unwrap();
+    let len = aref.elements.len() as i64;
+    let at = |i: i64| match aref.elements.get(i as usize) {
+        Some(val) if i >= 0 => *val,
+        _ => Value::nil(),
+    };
+    let mut res = vec![];
+    for arg in args.iter() {
+        if let Some(range) = arg.as_range() {
+            let start = range.start.expect_integer(vm, "Range")?;
+            let end = range.end.expect_integer(vm, "Range")?;
+            let start = if start < 0 { start + len } else { start };
+            let end = if end < 0 { end + len } else { end };
+            if start < 0 || start > len {
+                continue;
+            }
+            let end = if range.exclude { end } else { end + 1 };
+            res.extend((start..end).map(at));
+        } else {
+            let i = arg.expect_integer(vm, "Index")?;
+            res.push(at(if i < 0 { i + len } else { i }));
+        }
+    }
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn fetch(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let aref = self_val.as_array().unwrap();
+    let len = aref.elements.len() as i64;
+    let index = args[0].expect_integer(vm, "1st arg")?;
+    let i = if index < 0 { index + len } else { index };
+    if i >= 0 && i < len {
+        return Ok(aref.elements[i as usize]);
+    }
+    match (args.block, args.len()) {
+        (Some(block), _) => vm.eval_block(block, &Args::new1(args[0])),
+        (None, 2) => Ok(args[1]),
+        _ => Err(vm.error_index(format!(
+            "index {} outside of array bounds: {}...{}",
+            index, -len, len
+        ))),
+    }
+}
+
+fn to_h(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let elements = self_val.as_array().unwrap().elements.clone();
+    let mut map = std::collections::HashMap::new();
+    for (i, elem) in elements.into_iter().enumerate() {
+        let pair = match args.block {
+            Some(block) => vm.eval_block(block, &Args::new1(elem))?,
+            None => elem,
+        };
+        match pair.as_array() {
+            Some(ary) if ary.elements.len() == 2 => {
+                map.insert(HashKey(ary.elements[0]), ary.elements[1]);
+            }
+            Some(ary) => {
+                return Err(vm.error_argument(format!(
+                    "wrong array length at {} (expected 2, was {})",
+                    i,
+                    ary.elements.len()
+                )))
+            }
+            None => {
+                let class = vm.globals.get_class_name(pair);
+                return Err(vm.error_type(format!(
+                    "wrong element type {} at {} (expected array)",
+                    class, i
+                )));
+            }
+        }
+    }
+    Ok(Value::hash_from(&vm.globals, map))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::test::*;
@@ -848,13 +1860,16 @@ mod tests {
     fn array_min_max() {
         let program = r#"
         assert nil, [].min
-        #assert [], [].min(1)
+        assert [], [].min(1)
         assert 2, [2, 5, 3.7].min
-        #assert [2, 3], [2, 5, 3].min(2)
+        assert [2, 3], [2, 5, 3].min(2)
         assert nil, [].max
-        #assert [], [].max(1)
+        assert [], [].max(1)
         assert 5, [2, 5, 3].max
-        #assert [5, 3], [2.1, 5, 3].max(2)
+        assert [5, 3], [2.1, 5, 3].max(2)
+        assert "c", ["b", "c", "a"].max
+        assert "aaa", ["b", "aaa", "cc"].max { |a, b| a.size <=> b.size }
+        assert [1, 5], [3, 1, 5].minmax
         "#;
         assert_script(program);
     }
@@ -988,4 +2003,214 @@ mod tests {
         "#;
         assert_script(program);
     }
+
+    #[test]
+    fn array_iteration() {
+        let program = r#"
+        a = []
+        assert ["a", "b"], ["a", "b"].each_with_index { |x, i| a << [x, i] }
+        assert [["a", 0], ["b", 1]], a
+        assert [["a", 0], ["b", 1]], ["a", "b"].each_with_index.map { |x| x }
+        a = []
+        [5, 6, 7].each_index { |i| a << i }
+        assert [0, 1, 2], a
+        a = []
+        [1, 2, 3, 4, 5].each_slice(2) { |s| a << s }
+        assert [[1, 2], [3, 4], [5]], a
+        assert [[1, 2], [3]], [1, 2, 3].each_slice(2).map { |s| s }
+        a = []
+        assert nil, [1, 2].cycle(2) { |x| a << x }
+        assert [1, 2, 1, 2], a
+        a = []
+        [1, 2, 3].cycle { |x| a << x; break if a.size == 5 }
+        assert [1, 2, 3, 1, 2], a
+        assert [1, 2], [1, 2, 3, 4].take_while { |x| x < 3 }
+        assert [3, 4, 1], [1, 2, 3, 4, 1].drop_while { |x| x < 3 }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn array_filter() {
+        let program = r#"
+        assert [2, 4], [1, 2, 3, 4].select { |x| x.even? }
+        assert [1, 3], [1, 2, 3, 4].reject { |x| x.even? }
+        a = [1, 2, 3, 4]
+        assert [2, 4], a.select! { |x| x.even? }
+        assert [2, 4], a
+        assert nil, a.select! { |x| x.even? }
+        assert [2, 4], a.keep_if { |x| x.even? }
+        a = [1, 2, 3, 4]
+        assert [1, 3], a.reject! { |x| x.even? }
+        assert nil, a.reject! { |x| x.even? }
+        assert [3], a.delete_if { |x| x == 1 }
+        a = [1, 2, 3]
+        assert [2, 4, 6], a.map! { |x| x * 2 }
+        assert [2, 4, 6], a
+        assert [1, 2], [[1, 0], [2, 1]].map { |x, y| x }
+        assert 2, [1, 2, 3, 2].count(2)
+        assert 2, [1, 2, 3, 4].count { |x| x > 2 }
+        assert 4, [1, 2, 3, 4].count
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn array_edit() {
+        let program = r#"
+        a = [1, 2, 3, 2]
+        assert 2, a.delete(2)
+        assert [1, 3], a
+        assert nil, a.delete(5)
+        assert "none", a.delete(5) { "none" }
+        a = [1, 2, 3]
+        assert 2, a.delete_at(1)
+        assert 3, a.delete_at(-1)
+        assert nil, a.delete_at(5)
+        assert [1], a
+        a = [1, 2, 3]
+        assert [1, :a, :b, 2, 3], a.insert(1, :a, :b)
+        assert [1, :a, :b, 2, 3, :c], a.insert(-1, :c)
+        assert [1, :a, :b, 2, 3, :d, :c], a.insert(-2, :d)
+        assert [1, nil, 2], [1].insert(2, 2)
+        assert [1, 2, 3], [1, nil, 2, nil, 3].compact
+        a = [1, nil, 2]
+        assert [1, 2], a.compact!
+        assert nil, a.compact!
+        assert [1, 2, 3, 4, 5], [1, [2, [3, [4]]], 5].flatten
+        assert [1, 2, [3, [4]], 5], [1, [2, [3, [4]]], 5].flatten(1)
+        a = [1, [2]]
+        assert [1, 2], a.flatten!
+        assert nil, a.flatten!
+        a = [1]
+        a << a
+        assert_error { a.flatten }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn array_search() {
+        let program = r#"
+        a = [1, 2, 3, 2]
+        assert 1, a.index(2)
+        assert 2, a.find_index { |x| x > 2 }
+        assert nil, a.index(5)
+        assert 3, a.rindex(2)
+        assert 0, a.rindex { |x| x < 2 }
+        assert [2, :b], [[1, :a], [2, :b]].assoc(2)
+        assert nil, [[1, :a]].assoc(3)
+        a = [[1, [2, 3]]]
+        assert 3, a.dig(0, 1, 1)
+        assert nil, a.dig(1, 0)
+        assert_error { [1].dig(0, 1) }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn array_search2() {
+        let program = r#"
+        a = [0, 4, 7, 10, 12]
+        assert 4, a.bsearch { |x| x >= 4 }
+        assert 10, a.bsearch { |x| x >= 8 }
+        assert nil, a.bsearch { |x| x >= 100 }
+        assert 7, a.bsearch { |x| 7 <=> x }
+        assert nil, a.bsearch { |x| 5 <=> x }
+        assert [2, 4, nil], [1, 2, 3, 4].values_at(1, 3, 5)
+        assert [4, 2, 3, 4, nil], [1, 2, 3, 4].values_at(-1, 1..4)
+        assert 2, [1, 2].fetch(1)
+        assert 1, [1, 2].fetch(-2)
+        assert :x, [1, 2].fetch(5, :x)
+        assert 10, [1, 2].fetch(5) { |i| i * 2 }
+        assert({1 => 2, 3 => 4}, [[1, 2], [3, 4]].to_h)
+        assert({1 => 2, 2 => 4}, [1, 2].to_h { |x| [x, x * 2] })
+        assert_error { [1, 2].fetch(5) }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn array_sort() {
+        let program = r#"
+        a = [3, 1, 2]
+        assert [1, 2, 3], a.sort!
+        assert [1, 2, 3], a
+        assert [3, 2, 1], a.sort { |x, y| y <=> x }
+        assert [3, 2, 1], a.sort! { |x, y| y <=> x }
+        a = ["ccc", "a", "bb"]
+        assert ["a", "bb", "ccc"], a.sort_by! { |x| x.size }
+        assert ["a", "bb", "ccc"], a
+        assert_error { [1, "a"].sort! }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn array_random() {
+        let program = r#"
+        a = [1, 2, 3, 4, 5]
+        assert true, a.include?(a.sample)
+        assert nil, [].sample
+        assert 3, a.sample(3).uniq.size
+        assert 5, a.sample(10).size
+        assert [1, 2, 3, 4, 5], a.shuffle.sort
+        assert [1, 2, 3, 4, 5], a
+        assert a.shuffle(random: Random.new(1)), a.shuffle(random: Random.new(1))
+        assert a.sample(2, random: Random.new(3)), a.sample(2, random: Random.new(3))
+        b = a.dup
+        assert b, b.shuffle!
+        assert [1, 2, 3, 4, 5], b.sort
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn array_combinatorics() {
+        let program = r#"
+        assert [[1, 3], [1, 4], [2, 3], [2, 4]], [1, 2].product([3, 4])
+        assert [[1, 3, 5], [1, 4, 5]], [1].product([3, 4], [5])
+        assert [], [1, 2].product([])
+        assert [[1, 2], [1, 3], [2, 3]], [1, 2, 3].combination(2).map { |x| x }
+        assert [[]], [1, 2].combination(0).map { |x| x }
+        assert [], [1, 2].combination(3).map { |x| x }
+        a = []
+        [1, 2, 3].combination(3) { |x| a << x }
+        assert [[1, 2, 3]], a
+        assert [[1, 2], [2, 1]], [1, 2].permutation.map { |x| x }
+        assert [[1, 2], [1, 3], [2, 1], [2, 3], [3, 1], [3, 2]], [1, 2, 3].permutation(2).map { |x| x }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn array_set_operations() {
+        let program = r#"
+        assert [1, 3], [1, 1, 3, 5] & [3, 1, 2]
+        assert [1, 2, 3, 4], [1, 2, 2] | [3, 2, 4]
+        assert [1, 2, 3, 4, 5], [1, 2].union([2, 3], [4, 5])
+        assert [2], [1, 2, 3].intersection([2, 3], [2])
+        assert [1, 1], [1, 1, 2, 3].difference([2], [3])
+        assert [1, 2, 3], [1, 2, 3].union
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn array_sum() {
+        let program = r#"
+        assert 6, [1, 2, 3].sum
+        assert 16, [1, 2, 3].sum(10)
+        assert 3, [3].sum
+        assert 3, [1, 2].sum
+        assert 3, [2, 1].sum(0)
+        assert 5.5, [1, 2].sum(2.5)
+        assert 0.6, [0.1, 0.2, 0.3].sum
+        assert 4.5, [1, 2.5, 1].sum
+        assert 12, [1, 2, 3].sum { |x| x * 2 }
+        assert "abc", ["a", "b", "c"].sum("")
+        assert [1, 2], [[1], [2]].sum([])
+        "#;
+        assert_script(program);
+    }
 }
@@ -0,0 +1,240 @@
+use crate::*;
+use std::path::PathBuf;
+
+pub fn init_binding(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("Binding");
+    let class = ClassRef::from(id, globals.builtins.object);
+    globals.add_builtin_instance_method(class, "local_variable_get", local_variable_get);
+    globals.add_builtin_instance_method(class, "local_variable_set", local_variable_set);
+    globals.add_builtin_instance_method(class, "local_variable_defined?", local_variable_defined);
+    globals.add_builtin_instance_method(class, "local_variables", local_variables);
+    globals.add_builtin_instance_method(class, "receiver", receiver);
+    globals.add_builtin_instance_method(class, "eval", eval);
+    Value::class(globals, class)
+}
+
+fn expect_binding(vm: &VM, val: Value) -> Result<ContextRef, RubyError> {
+    match val.as_binding() {
+        Some(ctx) => Ok(ctx),
+        None => Err(vm.error_unimplemented("Expected Binding object.")),
+    }
+}
+
+fn expect_lvar_name(vm: &mut VM, val: Value) -> Result<IdentId, RubyError> {
+    let id = match val.as_symbol() {
+        Some(id) => id,
+        None => {
+            let name = vm.expect_string(&val, "Local variable name")?.to_string();
+            vm.globals.get_ident_id(name)
+        }
+    };
+    Ok(id)
+}
+
+/// Search the binding and its outer scopes for the local variable `id`.
+fn find_lvar(binding: ContextRef, id: IdentId) -> Option<(ContextRef, LvarId)> {
+    let mut context = Some(binding);
+    while let Some(ctx) = context {
+        if let Some(lvar) = ctx.iseq_ref.lvar.get(&id) {
+            return Some((ctx, *lvar));
+        }
+        if let ISeqKind::Method(_) = ctx.kind {
+            break;
+        }
+        context = ctx.outer;
+    }
+    None
+}
+
+// Instance methods
+
+fn local_variable_get(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let binding = expect_binding(vm, self_val)?;
+    let id = expect_lvar_name(vm, args[0])?;
+    match find_lvar(binding, id) {
+        Some((ctx, lvar)) => {
+            let val = ctx[lvar];
+            if val.is_uninitialized() {
+                Ok(Value::nil())
+            } else {
+                Ok(val)
+            }
+        }
+        None => {
+            let name = vm.globals.get_ident_name(id);
+            Err(vm.error_name(format!(
+                "local variable `{}' is not defined for binding",
+                name
+            )))
+        }
+    }
+}
+
+fn local_variable_set(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 2)?;
+    let mut binding = expect_binding(vm, self_val)?;
+    let id = expect_lvar_name(vm, args[0])?;
+    let val = args[1];
+    match find_lvar(binding, id) {
+        Some((mut ctx, lvar)) => ctx[lvar] = val,
+        None => {
+            // A new local variable is defined in the scope of the binding.
+            let mut iseq = binding.iseq_ref;
+            let lvar = iseq.lvar.insert(id);
+            iseq.lvars = iseq.lvar.len();
+            binding.adjust_lvar_size();
+            binding[lvar] = val;
+        }
+    };
+    Ok(val)
+}
+
+fn local_variable_defined(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let binding = expect_binding(vm, self_val)?;
+    let id = expect_lvar_name(vm, args[0])?;
+    Ok(Value::bool(find_lvar(binding, id).is_some()))
+}
+
+fn local_variables(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let binding = expect_binding(vm, self_val)?;
+    let mut names: Vec<IdentId> = vec![];
+    let mut context = Some(binding);
+    while let Some(ctx) = context {
+        let mut table: Vec<(IdentId, LvarId)> = ctx
+            .iseq_ref
+            .lvar
+            .table()
+            .iter()
+            .map(|(k, v)| (*k, *v))
+            .collect();
+        table.sort_by_key(|(_, v)| v.as_usize());
+        for (id, _) in table {
+            if !id.is_internal() && !names.contains(&id) {
+                names.push(id);
+            }
+        }
+        if let ISeqKind::Method(_) = ctx.kind {
+            break;
+        }
+        context = ctx.outer;
+    }
+    let ary = names.into_iter().map(|id| Value::symbol(id)).collect();
+    Ok(Value::array_from(&vm.globals, ary))
+}
+
+fn receiver(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let binding = expect_binding(vm, self_val)?;
+    Ok(binding.self_value)
+}
+
+fn eval(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 3)?;
+    let binding = expect_binding(vm, self_val)?;
+    let program = vm.expect_string(&args[0], "1st arg")?.to_string();
+    let env_name = if args.len() > 1 {
+        vm.expect_string(&args[1], "2nd arg")?.to_string()
+    } else {
+        "(eval)".to_string()
+    };
+    let line = match args.get(2) {
+        Some(line) => line_arg(vm, *line, "3rd arg")?,
+        None => 1,
+    };
+    vm.eval_binding(PathBuf::from(env_name), &program, line, binding)
+}
+
+/// The line number argument of `eval`, which is the line of the first line of the program.
+pub fn line_arg(vm: &mut VM, val: Value, msg: &str) -> Result<usize, RubyError> {
+    let line = vm.expect_integer(val, msg)?;
+    if line < 1 {
+        return Err(vm.error_argument(format!("{} must be positive. (given:{})", msg, line)));
+    }
+    Ok(line as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn binding_local_variables() {
+        let program = r#"
+    def foo
+        a = 100
+        b = 200
+        binding
+    end
+    bind = foo
+    assert(100, bind.local_variable_get(:a))
+    assert(true, bind.local_variable_defined?(:b))
+    assert(false, bind.local_variable_defined?(:c))
+    bind.local_variable_set(:a, 7)
+    bind.local_variable_set(:c, 9)
+    assert(7, bind.local_variable_get(:a))
+    assert(9, bind.local_variable_get(:c))
+    assert(true, bind.local_variable_defined?(:c))
+    assert([:c, :a, :b], bind.local_variables)
+    assert_error { bind.local_variable_get(:d) }
+    def anon(a, *, **, &)
+        binding.local_variables
+    end
+    def fwd(a, ...)
+        binding.local_variables
+    end
+    assert([:a], anon(1))
+    assert([:a], fwd(1))
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn binding_eval() {
+        let program = r#"
+    class Foo
+        def initialize
+            @x = 5
+        end
+        def get_binding
+            y = 10
+            binding
+        end
+    end
+    obj = Foo.new
+    bind = obj.get_binding
+    assert(obj, bind.receiver)
+    assert(15, bind.eval("@x + y"))
+    bind.eval("z = y * 2")
+    assert(20, bind.eval("z"))
+    assert(20, bind.local_variable_get(:z))
+    assert(30, eval("y + z", bind))
+    eval("y = 1", bind)
+    assert(21, bind.eval("y + z"))
+    assert(7, bind.eval("__LINE__", "f.rb", 7))
+    assert(8, bind.eval("y\n__LINE__", "f.rb", 7))
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn binding_in_block() {
+        let program = r#"
+    a = 1
+    bind = nil
+    [2].each do |b|
+        c = 3
+        bind = binding
+    end
+    assert(6, bind.eval("a + b + c"))
+    bind.eval("a = 10")
+    assert(10, a)
+    pr = Proc.new { a + 1 }
+    assert(10, pr.binding.local_variable_get(:a))
+    assert(11, eval("a + 1", pr.binding))
+    "#;
+        assert_script(program);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::*;
+use std::collections::HashMap;
+
+pub fn init_coverage(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("Coverage");
+    let class = ClassRef::from(id, globals.builtins.object);
+    let obj = Value::class(globals, class);
+    globals.add_builtin_class_method(obj, "start", start);
+    globals.add_builtin_class_method(obj, "result", result);
+    globals.add_builtin_class_method(obj, "peek_result", peek_result);
+    globals.add_builtin_class_method(obj, "running?", running);
+    obj
+}
+
+// Class methods
+
+/// Start measuring the coverage of source files loaded after this call.
+/// `Coverage.start` measures lines only, and its result is an Array of line counts per file.
+/// `Coverage.start(lines: true, branches: true, methods: true)` or `Coverage.start(:all)`
+/// chooses the kinds of coverage, and the result is a Hash per file.
+fn start(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    if vm.globals.coverage.running {
+        return Err(vm.error_internal("coverage measurement is already setup"));
+    }
+    let option = if args.len() == 1 {
+        Some(args[0])
+    } else {
+        args.kw_arg
+    };
+    let mode = match option {
+        None => CoverageMode {
+            lines: true,
+            legacy: true,
+            ..CoverageMode::default()
+        },
+        Some(option) => {
+            if option.as_symbol() == Some(vm.globals.get_ident_id("all")) {
+                CoverageMode::all()
+            } else if let Some(hash) = option.as_hash() {
+                let mut flag = |name: &str| {
+                    let key = Value::symbol(vm.globals.get_ident_id(name));
+                    match hash.get(&key) {
+                        Some(val) => vm.val_to_bool(*val),
+                        None => false,
+                    }
+                };
+                CoverageMode {
+                    lines: flag("lines"),
+                    branches: flag("branches"),
+                    methods: flag("methods"),
+                    legacy: false,
+                }
+            } else {
+                return Err(vm.error_type("Expected a Hash or :all."));
+            }
+        }
+    };
+    vm.globals.coverage.start(mode);
+    Ok(Value::nil())
+}
+
+/// Returns the coverage, and stops the measurement.
+fn result(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    let res = peek_result(vm, Value::nil(), args)?;
+    vm.globals.coverage.stop();
+    Ok(res)
+}
+
+/// Returns the coverage while continuing the measurement.
+fn peek_result(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    if !vm.globals.coverage.running {
+        return Err(vm.error_internal("coverage measurement is not enabled"));
+    }
+    let mode = vm.globals.coverage.mode;
+    let files = vm.globals.coverage.result(&vm.globals);
+    let mut res = HashMap::new();
+    for (path, file) in files {
+        let lines: Vec<Value> = file
+            .lines
+            .iter()
+            .map(|count| match count {
+                Some(count) => Value::fixnum(*count as i64),
+                None => Value::nil(),
+            })
+            .collect();
+        let lines = Value::array_from(&vm.globals, lines);
+        let val = if mode.legacy {
+            lines
+        } else {
+            let mut cov = HashMap::new();
+            if mode.lines {
+                let key = Value::symbol(vm.globals.get_ident_id("lines"));
+                cov.insert(HashKey(key), lines);
+            }
+            if mode.branches {
+                let key = Value::symbol(vm.globals.get_ident_id("branches"));
+                cov.insert(HashKey(key), branches(vm, &file.branches));
+            }
+            if mode.methods {
+                let key = Value::symbol(vm.globals.get_ident_id("methods"));
+                cov.insert(HashKey(key), methods(vm, &file.methods));
+            }
+            Value::hash_from(&vm.globals, cov)
+        };
+        res.insert(HashKey(Value::string(&vm.globals, path)), val);
+    }
+    Ok(Value::hash_from(&vm.globals, res))
+}
+
+fn running(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::bool(vm.globals.coverage.running))
+}
+
+/// `[kind, id, start line, start column, end line, end column]`
+fn branch_key(vm: &mut VM, kind: &str, id: usize, range: [usize; 4]) -> Value {
+    let mut key = vec![
+        Value::symbol(vm.globals.get_ident_id(kind)),
+        Value::fixnum(id as i64),
+    ];
+    key.extend(range.iter().map(|n| Value::fixnum(*n as i64)));
+    Value::array_from(&vm.globals, key)
+}
+
+fn branches(vm: &mut VM, branches: &[BranchCoverage]) -> Value {
+    let mut res = HashMap::new();
+    for branch in branches {
+        let mut targets = HashMap::new();
+        let then_key = branch_key(vm, "then", branch.id + 1, branch.then_range);
+        targets.insert(HashKey(then_key), Value::fixnum(branch.then_count as i64));
+        let else_key = branch_key(vm, "else", branch.id + 2, branch.else_range);
+        targets.insert(HashKey(else_key), Value::fixnum(branch.else_count as i64));
+        let key = branch_key(vm, "if", branch.id, branch.range);
+        res.insert(HashKey(key), Value::hash_from(&vm.globals, targets));
+    }
+    Value::hash_from(&vm.globals, res)
+}
+
+fn methods(vm: &mut VM, methods: &[MethodCoverage]) -> Value {
+    let mut res = HashMap::new();
+    for method in methods {
+        let mut key = vec![method.class, Value::symbol(method.name)];
+        key.extend(method.range.iter().map(|n| Value::fixnum(*n as i64)));
+        let key = Value::array_from(&vm.globals, key);
+        res.insert(HashKey(key), Value::fixnum(method.count as i64));
+    }
+    Value::hash_from(&vm.globals, res)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn coverage() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("ruruby_coverage_{}.rb", std::process::id()));
+        std::fs::write(
+            &path,
+            "class Foo\n  def foo(x)\n    x > 1 ? 1 : 2\n  end\nend\nFoo.new.foo(2)\n",
+        )
+        .unwrap();
+        let program = format!(
+            r#"
+        assert false, Coverage.running?
+        Coverage.start(lines: true, methods: true, branches: true)
+        assert true, Coverage.running?
+        require "{}"
+        res = Coverage.peek_result["{}"]
+        assert [1, 1, 1, nil, nil, 1], res[:lines]
+        assert({{[Foo, :foo, 2, 2, 4, 5] => 1}}, res[:methods])
+        assert 1, res[:branches].values[0][[:then, 1, 3, 12, 3, 13]]
+        assert 0, res[:branches].values[0][[:else, 2, 3, 16, 3, 17]]
+        assert true, Coverage.running?
+        Coverage.result
+        assert false, Coverage.running?
+        Coverage.start
+        assert({{}}, Coverage.result)
+        assert_error {{ Coverage.result }}
+        "#,
+            path.to_string_lossy(),
+            path.to_string_lossy()
+        );
+        assert_script(program);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,594 @@
+use crate::builtin::io;
+use crate::*;
+use std::path::{Path, PathBuf};
+
+/// Flags of `File.fnmatch` and `Dir.glob`.
+pub const FNM_NOESCAPE: i64 = 1;
+pub const FNM_PATHNAME: i64 = 2;
+pub const FNM_DOTMATCH: i64 = 4;
+pub const FNM_CASEFOLD: i64 = 8;
+pub const FNM_EXTGLOB: i64 = 16;
+
+pub fn init_dir(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("Dir");
+    let class = ClassRef::from(id, globals.builtins.object);
+    let obj = Value::class(globals, class);
+    globals.add_builtin_class_method(obj, "pwd", pwd);
+    globals.add_builtin_class_method(obj, "getwd", pwd);
+    globals.add_builtin_class_method(obj, "chdir", chdir);
+    globals.add_builtin_class_method(obj, "mkdir", mkdir);
+    globals.add_builtin_class_method(obj, "rmdir", rmdir);
+    globals.add_builtin_class_method(obj, "delete", rmdir);
+    globals.add_builtin_class_method(obj, "unlink", rmdir);
+    globals.add_builtin_class_method(obj, "exist?", exist);
+    globals.add_builtin_class_method(obj, "entries", entries);
+    globals.add_builtin_class_method(obj, "children", children);
+    globals.add_builtin_class_method(obj, "each_child", each_child);
+    globals.add_builtin_class_method(obj, "glob", glob);
+    globals.add_builtin_class_method(obj, "[]", index);
+    obj
+}
+
+/// Names in the directory `dir` except "." and "..".
+fn read_names(vm: &VM, dir: &str) -> Result<Vec<String>, RubyError> {
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(err) => return Err(io::error_errno(vm, &err, dir)),
+    };
+    let mut names = vec![];
+    for entry in read_dir {
+        match entry {
+            Ok(entry) => names.push(entry.file_name().to_string_lossy().to_string()),
+            Err(err) => return Err(io::error_errno(vm, &err, dir)),
+        }
+    }
+    Ok(names)
+}
+
+/// The `base:` keyword argument of `Dir.glob` and `Dir[]`.
+fn base_arg(vm: &mut VM, args: &Args) -> Result<Option<String>, RubyError> {
+    let val = match args.kw_arg {
+        Some(opt) => {
+            let key = Value::symbol(vm.globals.get_ident_id("base"));
+            opt.as_hash().unwrap().get(&key).cloned()
+        }
+        None => None,
+    };
+    match val {
+        Some(val) if !val.is_nil() => Ok(Some(vm.expect_string(&val, "base")?.to_string())),
+        _ => Ok(None),
+    }
+}
+
+// Pattern matching
+
+/// Expand `{a,b}` in `pattern` into the patterns of each alternative.
+fn expand_braces(pattern: &str, flags: i64) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let escape = flags & FNM_NOESCAPE == 0;
+    let mut open = None;
+    let mut depth = 0;
+    let mut commas = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if escape => i += 1,
+            '{' => {
+                if depth == 0 {
+                    open = Some(i);
+                }
+                depth += 1;
+            }
+            ',' if depth == 1 => commas.push(i),
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    let open = open.unwrap();
+                    let prefix: String = chars[..open].iter().collect();
+                    let suffix: String = chars[i + 1..].iter().collect();
+                    let mut bounds = vec![open];
+                    bounds.extend(commas);
+                    bounds.push(i);
+                    let mut res = vec![];
+                    for w in bounds.windows(2) {
+                        let alt: String = chars[w[0] + 1..w[1]].iter().collect();
+                        let expanded = format!("{}{}{}", prefix, alt, suffix);
+                        res.extend(expand_braces(&expanded, flags));
+                    }
+                    return res;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    vec![pattern.to_string()]
+}
+
+/// Match a bracket expression at the start of `pattern` against `c`.
+/// Returns whether it matched and the length of the expression, or None if it is not closed.
+fn match_bracket(pattern: &[char], c: char, flags: i64) -> Option<(bool, usize)> {
+    let fold = |c: char| {
+        if flags & FNM_CASEFOLD != 0 {
+            c.to_ascii_lowercase()
+        } else {
+            c
+        }
+    };
+    let c = fold(c);
+    let mut i = 1;
+    let negate = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let mut start = *pattern.get(i)?;
+        if start == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        if start == '\\' && flags & FNM_NOESCAPE == 0 {
+            i += 1;
+            start = *pattern.get(i)?;
+        }
+        i += 1;
+        if pattern.get(i) == Some(&'-') && pattern.get(i + 1).is_some_and(|c| *c != ']') {
+            let mut end = pattern[i + 1];
+            i += 2;
+            if end == '\\' && flags & FNM_NOESCAPE == 0 {
+                end = *pattern.get(i)?;
+                i += 1;
+            }
+            if fold(start) <= c && c <= fold(end) {
+                matched = true;
+            }
+        } else if fold(start) == c {
+            matched = true;
+        }
+    }
+}
+
+/// Match `string` against `pattern` with `*`, `?`, `[...]` and escapes.
+fn match_chars(pattern: &[char], string: &[char], flags: i64) -> bool {
+    let fold = |c: char| {
+        if flags & FNM_CASEFOLD != 0 {
+            c.to_ascii_lowercase()
+        } else {
+            c
+        }
+    };
+    let (mut pi, mut si) = (0, 0);
+    // The positions just after the last '*' and the string position it is tried at.
+    let mut star = None;
+    while si < string.len() {
+        let step = match pattern.get(pi) {
+            Some('*') => {
+                pi += 1;
+                star = Some((pi, si));
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match match_bracket(&pattern[pi..], string[si], flags) {
+                Some((true, len)) => Some(len),
+                Some((false, _)) => None,
+                None if string[si] == '[' => Some(1),
+                None => None,
+            },
+            Some('\\') if flags & FNM_NOESCAPE == 0 && pi + 1 < pattern.len() => {
+                if fold(pattern[pi + 1]) == fold(string[si]) {
+                    Some(2)
+                } else {
+                    None
+                }
+            }
+            Some(c) if fold(*c) == fold(string[si]) => Some(1),
+            _ => None,
+        };
+        match (step, star) {
+            (Some(step), _) => {
+                pi += step;
+                si += 1;
+            }
+            (None, Some((star_pi, star_si))) => {
+                pi = star_pi;
+                si = star_si + 1;
+                star = Some((star_pi, si));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[pi..].iter().all(|c| *c == '*')
+}
+
+/// Match a file name against a pattern without '/'.
+/// A leading period must be matched explicitly unless FNM_DOTMATCH is given.
+fn match_name(pattern: &str, name: &str, flags: i64) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    if name.first() == Some(&'.') && flags & FNM_DOTMATCH == 0 {
+        let explicit = match pattern.first() {
+            Some('.') => true,
+            Some('\\') => flags & FNM_NOESCAPE == 0 && pattern.get(1) == Some(&'.'),
+            _ => false,
+        };
+        if !explicit {
+            return false;
+        }
+    }
+    match_chars(&pattern, &name, flags)
+}
+
+/// Match path segments, where a "**" segment matches zero or more directories.
+fn match_segments(pattern: &[&str], path: &[&str], flags: i64) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) if !rest.is_empty() => {
+            for i in 0..=path.len() {
+                if match_segments(rest, &path[i..], flags) {
+                    return true;
+                }
+                match path.get(i) {
+                    Some(dir) if !dir.starts_with('.') || flags & FNM_DOTMATCH != 0 => {}
+                    _ => return false,
+                }
+            }
+            false
+        }
+        Some((seg, rest)) => match path.split_first() {
+            Some((name, path)) => match_name(seg, name, flags) && match_segments(rest, path, flags),
+            None => false,
+        },
+    }
+}
+
+/// Match `path` against the shell glob `pattern` as `File.fnmatch` does.
+pub fn fnmatch(pattern: &str, path: &str, flags: i64) -> bool {
+    if flags & FNM_EXTGLOB != 0 {
+        return expand_braces(pattern, flags)
+            .iter()
+            .any(|pattern| fnmatch(pattern, path, flags & !FNM_EXTGLOB));
+    }
+    if flags & FNM_PATHNAME != 0 {
+        let pattern: Vec<&str> = pattern.split('/').collect();
+        let path: Vec<&str> = path.split('/').collect();
+        match_segments(&pattern, &path, flags)
+    } else {
+        match_name(pattern, path, flags)
+    }
+}
+
+fn has_magic(segment: &str, flags: i64) -> bool {
+    segment.contains(|c| match c {
+        '*' | '?' | '[' => true,
+        '\\' => flags & FNM_NOESCAPE == 0,
+        _ => flags & FNM_CASEFOLD != 0 && c.is_ascii_alphabetic(),
+    })
+}
+
+/// Sorted names in `dir` for `Dir.glob`, which ignores unreadable directories.
+fn sorted_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect(),
+        Err(_) => vec![],
+    };
+    names.sort();
+    names
+}
+
+/// Collect the paths under `dir` which match `segments`.
+/// `prefix` is the path of `dir` as written in the results.
+fn glob_dir(
+    dir: &Path,
+    prefix: &str,
+    segments: &[&str],
+    dir_only: bool,
+    flags: i64,
+    res: &mut Vec<String>,
+) {
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let found = |path: PathBuf, name: &str, res: &mut Vec<String>| {
+        let matched = format!("{}{}", prefix, name);
+        if !rest.is_empty() {
+            if path.is_dir() {
+                glob_dir(&path, &format!("{}/", matched), rest, dir_only, flags, res);
+            }
+        } else if dir_only {
+            if path.is_dir() {
+                res.push(format!("{}/", matched));
+            }
+        } else if path.symlink_metadata().is_ok() {
+            res.push(matched);
+        }
+    };
+    if *segment == "**" && !rest.is_empty() {
+        glob_dir(dir, prefix, rest, dir_only, flags, res);
+        for name in sorted_names(dir) {
+            let path = dir.join(&name);
+            let is_dir = match path.symlink_metadata() {
+                Ok(meta) => meta.is_dir(),
+                Err(_) => false,
+            };
+            if is_dir && (!name.starts_with('.') || flags & FNM_DOTMATCH != 0) {
+                let prefix = format!("{}{}/", prefix, name);
+                glob_dir(&path, &prefix, segments, dir_only, flags, res);
+            }
+        }
+    } else if !has_magic(segment, flags) {
+        found(dir.join(segment), segment, res);
+    } else {
+        for name in sorted_names(dir) {
+            if match_name(segment, &name, flags) {
+                found(dir.join(&name), &name, res);
+            }
+        }
+    }
+}
+
+/// The paths which match `pattern`, relative to `base` or the current directory.
+fn glob_paths(pattern: &str, base: Option<&str>, flags: i64) -> Vec<String> {
+    let mut res = vec![];
+    for pattern in expand_braces(pattern, flags) {
+        let (dir, prefix, rest) = if pattern.starts_with('/') {
+            ("/", "/", pattern.trim_start_matches('/'))
+        } else {
+            (base.unwrap_or("."), "", pattern.as_str())
+        };
+        let segments: Vec<&str> = rest.split('/').filter(|seg| !seg.is_empty()).collect();
+        let dir_only = rest.ends_with('/');
+        glob_dir(Path::new(dir), prefix, &segments, dir_only, flags, &mut res);
+    }
+    res
+}
+
+/// Yield the paths to the block, or return them as an Array without a block.
+fn yield_paths(vm: &mut VM, paths: Vec<String>, block: Option<MethodRef>) -> VMResult {
+    let paths: Vec<Value> = paths
+        .into_iter()
+        .map(|path| Value::string(&vm.globals, path))
+        .collect();
+    match block {
+        Some(block) if block != MethodRef::from(0) => {
+            for path in paths {
+                vm.eval_block(block, &Args::new1(path))?;
+            }
+            Ok(Value::nil())
+        }
+        _ => Ok(Value::array_from(&vm.globals, paths)),
+    }
+}
+
+// Class methods
+
+fn pwd(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    match std::env::current_dir() {
+        Ok(dir) => Ok(Value::string(
+            &vm.globals,
+            dir.to_string_lossy().to_string(),
+        )),
+        Err(err) => Err(io::error_errno(vm, &err, ".")),
+    }
+}
+
+/// Dir.chdir(path = ENV["HOME"])
+/// With a block, the current directory is restored when the block ends.
+fn chdir(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let path = match args.first() {
+        Some(path) => vm.expect_string(path, "Path")?.to_string(),
+        None => match std::env::var("HOME") {
+            Ok(home) => home,
+            Err(_) => return Err(vm.error_argument("HOME/LOGDIR not set")),
+        },
+    };
+    let prev = match std::env::current_dir() {
+        Ok(dir) => dir,
+        Err(err) => return Err(io::error_errno(vm, &err, ".")),
+    };
+    if let Err(err) = std::env::set_current_dir(&path) {
+        return Err(io::error_errno(vm, &err, &path));
+    }
+    let block = match args.block {
+        Some(block) => block,
+        None => return Ok(Value::fixnum(0)),
+    };
+    let res = vm.eval_block(block, &Args::new1(Value::string(&vm.globals, path)));
+    if let Err(err) = std::env::set_current_dir(&prev) {
+        return Err(io::error_errno(vm, &err, &prev.to_string_lossy()));
+    }
+    res
+}
+
+fn mkdir(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let path = vm.expect_string(&args[0], "Path")?.to_string();
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        let mode = match args.get(1) {
+            Some(mode) if !mode.is_nil() => mode.expect_integer(vm, "Mode")?,
+            _ => 0o777,
+        };
+        builder.mode(mode as u32);
+    }
+    match builder.create(&path) {
+        Ok(()) => Ok(Value::fixnum(0)),
+        Err(err) => Err(io::error_errno(vm, &err, &path)),
+    }
+}
+
+fn rmdir(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let path = vm.expect_string(&args[0], "Path")?.to_string();
+    match std::fs::remove_dir(&path) {
+        Ok(()) => Ok(Value::fixnum(0)),
+        Err(err) => Err(io::error_errno(vm, &err, &path)),
+    }
+}
+
+fn exist(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let path = vm.expect_string(&args[0], "Path")?;
+    Ok(Value::bool(Path::new(path).is_dir()))
+}
+
+fn entries(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let path = vm.expect_string(&args[0], "Path")?.to_string();
+    let mut names = vec![".".to_string(), "..".to_string()];
+    names.extend(read_names(vm, &path)?);
+    let names = names
+        .into_iter()
+        .map(|name| Value::string(&vm.globals, name))
+        .collect();
+    Ok(Value::array_from(&vm.globals, names))
+}
+
+fn children(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let path = vm.expect_string(&args[0], "Path")?.to_string();
+    let names = read_names(vm, &path)?
+        .into_iter()
+        .map(|name| Value::string(&vm.globals, name))
+        .collect();
+    Ok(Value::array_from(&vm.globals, names))
+}
+
+fn each_child(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let block = match args.block {
+        Some(block) if block == MethodRef::from(0) => return children(vm, self_val, args),
+        Some(block) => block,
+        None => {
+            let id = vm.globals.get_ident_id("each_child");
+            return Ok(Value::enumerator(&vm.globals, id, self_val, args.clone()));
+        }
+    };
+    let path = vm.expect_string(&args[0], "Path")?.to_string();
+    for name in read_names(vm, &path)? {
+        vm.eval_block(block, &Args::new1(Value::string(&vm.globals, name)))?;
+    }
+    Ok(Value::nil())
+}
+
+/// Dir.glob(pattern, flags = 0, base: nil)
+/// `pattern` may be an Array of patterns.
+fn glob(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let flags = match args.get(1) {
+        Some(flags) if !flags.is_nil() => flags.expect_integer(vm, "Flags")?,
+        _ => 0,
+    };
+    let base = base_arg(vm, args)?;
+    let patterns = match args[0].as_array() {
+        Some(ary) => ary.elements.clone(),
+        None => vec![args[0]],
+    };
+    let mut paths = vec![];
+    for pattern in patterns {
+        let pattern = vm.expect_string(&pattern, "Pattern")?.to_string();
+        paths.extend(glob_paths(&pattern, base.as_deref(), flags));
+    }
+    yield_paths(vm, paths, args.block)
+}
+
+/// Dir[pattern, ..., base: nil]
+fn index(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    let base = base_arg(vm, args)?;
+    let mut paths = vec![];
+    for pattern in args.iter() {
+        let pattern = vm.expect_string(pattern, "Pattern")?.to_string();
+        paths.extend(glob_paths(&pattern, base.as_deref(), 0));
+    }
+    yield_paths(vm, paths, None)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn dir() {
+        let program = r#"
+        assert false, Dir.exist?("dir_test")
+        assert 0, Dir.mkdir("dir_test")
+        assert true, Dir.exist?("dir_test")
+        assert false, Dir.exist?("Cargo.toml")
+        File.write("dir_test/b.rb", "")
+        File.write("dir_test/a.txt", "")
+        File.write("dir_test/.hidden", "")
+        Dir.mkdir("dir_test/sub")
+        File.write("dir_test/sub/c.rb", "")
+        assert [".", "..", ".hidden", "a.txt", "b.rb", "sub"], Dir.entries("dir_test").sort
+        assert [".hidden", "a.txt", "b.rb", "sub"], Dir.children("dir_test").sort
+        a = []
+        Dir.each_child("dir_test") { |name| a.push name }
+        assert [".hidden", "a.txt", "b.rb", "sub"], a.sort
+        assert ["dir_test/a.txt", "dir_test/b.rb", "dir_test/sub"], Dir.glob("dir_test/*")
+        assert ["dir_test/b.rb", "dir_test/sub/c.rb"], Dir.glob("dir_test/**/*.rb")
+        assert ["dir_test/a.txt", "dir_test/b.rb"], Dir["dir_test/*.{txt,rb}"]
+        assert ["dir_test/sub/"], Dir.glob("dir_test/*/")
+        assert ["a.txt", "b.rb"], Dir.glob("?.*", base: "dir_test")
+        assert [".hidden", "a.txt"], Dir.glob(["[.a]*"], File::FNM_DOTMATCH, base: "dir_test").sort
+        a = []
+        Dir.glob("dir_test/sub/*") { |path| a.push path }
+        assert ["dir_test/sub/c.rb"], a
+        assert [], Dir.glob("no_such_dir/*")
+        pwd = Dir.pwd
+        assert 3, Dir.chdir(pwd) { |dir| 3 }
+        assert pwd, Dir.pwd
+        File.delete("dir_test/sub/c.rb")
+        assert 0, Dir.rmdir("dir_test/sub")
+        File.delete("dir_test/a.txt", "dir_test/b.rb", "dir_test/.hidden")
+        Dir.rmdir("dir_test")
+        assert false, Dir.exist?("dir_test")
+        assert_error { Dir.rmdir("dir_test") }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn fnmatch() {
+        let program = r#"
+        assert true, File.fnmatch("cat", "cat")
+        assert false, File.fnmatch("cat", "category")
+        assert true, File.fnmatch("c{at,ub}s", "cats", File::FNM_EXTGLOB)
+        assert false, File.fnmatch("c{at,ub}s", "cats")
+        assert true, File.fnmatch("c?t", "cat")
+        assert false, File.fnmatch("c??t", "cat")
+        assert true, File.fnmatch("c*", "cats")
+        assert true, File.fnmatch("c*t", "c/a/b/t")
+        assert true, File.fnmatch("ca[a-z]", "cat")
+        assert false, File.fnmatch("ca[^t]", "cat")
+        assert false, File.fnmatch("cat", "CAT")
+        assert true, File.fnmatch("cat", "CAT", File::FNM_CASEFOLD)
+        assert false, File.fnmatch("?", "/", File::FNM_PATHNAME)
+        assert false, File.fnmatch("*", "/", File::FNM_PATHNAME)
+        assert false, File.fnmatch("[/]", "/", File::FNM_PATHNAME)
+        assert true, File.fnmatch("\\?", "?")
+        assert true, File.fnmatch("\\a", "a")
+        assert false, File.fnmatch("\\a", "\\a")
+        assert true, File.fnmatch("\\a", "\\a", File::FNM_NOESCAPE)
+        assert true, File.fnmatch?("[\\?]", "?")
+        assert false, File.fnmatch("*", ".profile")
+        assert true, File.fnmatch("*", ".profile", File::FNM_DOTMATCH)
+        assert true, File.fnmatch(".*", ".profile")
+        assert true, File.fnmatch("**/*.rb", "main.rb", File::FNM_PATHNAME)
+        assert true, File.fnmatch("**/*.rb", "lib/song.rb", File::FNM_PATHNAME)
+        assert false, File.fnmatch("**/foo", "a/.b/c/foo", File::FNM_PATHNAME)
+        assert true, File.fnmatch("**/foo", "a/.b/c/foo", File::FNM_PATHNAME | File::FNM_DOTMATCH)
+        assert true, File.fnmatch("*", "dave/.profile")
+        assert false, File.fnmatch("*/*", "dave/.profile", File::FNM_PATHNAME)
+        "#;
+        assert_script(program);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::*;
+
+/// Character encodings of Strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    UTF8,
+    ASCII8BIT,
+    USASCII,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 3] = [Encoding::ASCII8BIT, Encoding::UTF8, Encoding::USASCII];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::UTF8 => "UTF-8",
+            Encoding::ASCII8BIT => "ASCII-8BIT",
+            Encoding::USASCII => "US-ASCII",
+        }
+    }
+
+    /// Look up an encoding by its name or an alias, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "UTF-8" | "CP65001" => Some(Encoding::UTF8),
+            "ASCII-8BIT" | "BINARY" => Some(Encoding::ASCII8BIT),
+            "US-ASCII" | "ASCII" | "ANSI_X3.4-1968" | "646" => Some(Encoding::USASCII),
+            _ => None,
+        }
+    }
+
+    fn const_name(self) -> &'static str {
+        match self {
+            Encoding::UTF8 => "UTF_8",
+            Encoding::ASCII8BIT => "ASCII_8BIT",
+            Encoding::USASCII => "US_ASCII",
+        }
+    }
+
+    /// The Encoding object for this encoding.
+    pub fn to_value(self, globals: &mut Globals) -> Value {
+        let id = globals.get_ident_id(self.const_name());
+        globals.builtins.encoding.get_var(id).unwrap()
+    }
+
+    /// Convert an Encoding object or an encoding name to Encoding.
+    pub fn from_value(vm: &mut VM, val: Value) -> Result<Self, RubyError> {
+        if let Some(enc) = val.as_encoding() {
+            return Ok(enc);
+        }
+        match val.as_string() {
+            Some(name) => match Encoding::from_name(name) {
+                Some(enc) => Ok(enc),
+                None => Err(vm.error_argument(format!("unknown encoding name - {}", name))),
+            },
+            None => {
+                let inspect = vm.val_inspect(val);
+                Err(vm.error_type(format!(
+                    "wrong argument type {} (expected Encoding)",
+                    inspect
+                )))
+            }
+        }
+    }
+}
+
+pub fn init_encoding(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("Encoding");
+    let class = ClassRef::from(id, globals.builtins.object);
+    let obj = Value::class(globals, class);
+    globals.builtins.encoding = obj;
+    for enc in &Encoding::ALL {
+        let val = Value::encoding(globals, *enc);
+        let id = globals.get_ident_id(enc.const_name());
+        obj.set_var(globals, id, val);
+    }
+    let id = globals.get_ident_id("BINARY");
+    let binary = Encoding::ASCII8BIT.to_value(globals);
+    obj.set_var(globals, id, binary);
+    globals.add_builtin_class_method(obj, "find", find);
+    globals.add_builtin_class_method(obj, "list", list);
+    globals.add_builtin_class_method(obj, "default_external", default_external);
+    globals.add_builtin_class_method(obj, "default_internal", default_internal);
+    globals.add_builtin_instance_method(class, "name", name);
+    globals.add_builtin_instance_method(class, "to_s", name);
+    globals.add_builtin_instance_method(class, "inspect", inspect);
+    globals.add_builtin_instance_method(class, "ascii_compatible?", ascii_compatible);
+    obj
+}
+
+fn find(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let enc = Encoding::from_value(vm, args[0])?;
+    Ok(enc.to_value(&mut vm.globals))
+}
+
+fn list(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let list = Encoding::ALL
+        .iter()
+        .map(|enc| enc.to_value(&mut vm.globals))
+        .collect();
+    Ok(Value::array_from(&vm.globals, list))
+}
+
+fn default_external(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Encoding::UTF8.to_value(&mut vm.globals))
+}
+
+fn default_internal(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::nil())
+}
+
+fn name(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let enc = self_val.as_encoding().unwrap();
+    Ok(Value::string(&vm.globals, enc.name().to_string()))
+}
+
+fn inspect(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let enc = self_val.as_encoding().unwrap();
+    let inspect = match enc {
+        Encoding::ASCII8BIT => "#<Encoding:BINARY (ASCII-8BIT)>".to_string(),
+        _ => format!("#<Encoding:{}>", enc.name()),
+    };
+    Ok(Value::string(&vm.globals, inspect))
+}
+
+fn ascii_compatible(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::true_val())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn encoding() {
+        let program = r##"
+        assert Encoding::UTF_8, "a".encoding
+        assert Encoding::BINARY, Encoding::ASCII_8BIT
+        assert Encoding::US_ASCII, Encoding.find("ascii")
+        assert Encoding::ASCII_8BIT, Encoding.find("binary")
+        assert "UTF-8", Encoding::UTF_8.name
+        assert "US-ASCII", Encoding::US_ASCII.to_s
+        assert "#<Encoding:UTF-8>", Encoding::UTF_8.inspect
+        assert "#<Encoding:BINARY (ASCII-8BIT)>", Encoding::BINARY.inspect
+        assert 3, Encoding.list.size
+        assert Encoding::UTF_8, Encoding.default_external
+        assert nil, Encoding.default_internal
+        assert_error { Encoding.find("EUC-JP") }
+        "##;
+        assert_script(program);
+    }
+}
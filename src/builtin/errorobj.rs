@@ -1,7 +1,16 @@
 use crate::*;
 
 pub fn init_error(globals: &mut Globals) -> Value {
-    let id = globals.get_ident_id("RuntimeError");
+    error_class(globals, "RuntimeError")
+}
+
+/// SystemStackError, which is raised when the depth of calls exceeds the limit.
+pub fn init_system_stack_error(globals: &mut Globals) -> Value {
+    error_class(globals, "SystemStackError")
+}
+
+fn error_class(globals: &mut Globals, name: &str) -> Value {
+    let id = globals.get_ident_id(name);
     let class = ClassRef::from(id, globals.builtins.object);
     Value::class(globals, class)
 }
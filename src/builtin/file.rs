@@ -1,19 +1,91 @@
-use std::fs::File;
+use std::fs::{File, Metadata, OpenOptions};
 use std::io::Read;
 use std::path::*;
 //#[macro_use]
+use crate::builtin::dir;
+use crate::builtin::io::{self, IOInfo};
+use crate::builtin::time::{TimeInfo, Zone};
 use crate::*;
 
+pub type StatRef = Ref<Metadata>;
+
+const LOCK_SH: i64 = 1;
+const LOCK_EX: i64 = 2;
+const LOCK_NB: i64 = 4;
+const LOCK_UN: i64 = 8;
+
 pub fn init_file(globals: &mut Globals) -> Value {
     let id = globals.get_ident_id("File");
-    let class = ClassRef::from(id, globals.builtins.object);
+    let class = ClassRef::from(id, globals.builtins.io);
     let obj = Value::class(globals, class);
+    globals.builtins.file = obj;
+    let consts = [
+        ("LOCK_SH", LOCK_SH),
+        ("LOCK_EX", LOCK_EX),
+        ("LOCK_NB", LOCK_NB),
+        ("LOCK_UN", LOCK_UN),
+        ("FNM_SYSCASE", 0),
+        ("FNM_NOESCAPE", dir::FNM_NOESCAPE),
+        ("FNM_PATHNAME", dir::FNM_PATHNAME),
+        ("FNM_DOTMATCH", dir::FNM_DOTMATCH),
+        ("FNM_CASEFOLD", dir::FNM_CASEFOLD),
+        ("FNM_EXTGLOB", dir::FNM_EXTGLOB),
+    ];
+    for (name, val) in consts.iter() {
+        let id = globals.get_ident_id(*name);
+        obj.set_var(globals, id, Value::fixnum(*val));
+    }
+    let id = globals.get_ident_id("Stat");
+    let stat = init_stat(globals);
+    obj.set_var(globals, id, stat);
     globals.add_builtin_class_method(obj, "join", join);
     globals.add_builtin_class_method(obj, "basename", basename);
     globals.add_builtin_class_method(obj, "extname", extname);
     globals.add_builtin_class_method(obj, "binread", binread);
     globals.add_builtin_class_method(obj, "read", read);
     globals.add_builtin_class_method(obj, "write", write);
+    globals.add_builtin_class_method(obj, "new", file_new);
+    globals.add_builtin_class_method(obj, "open", open);
+    globals.add_builtin_class_method(obj, "foreach", foreach);
+    globals.add_builtin_class_method(obj, "readlines", readlines);
+    globals.add_builtin_class_method(obj, "exist?", exist);
+    globals.add_builtin_class_method(obj, "file?", is_file);
+    globals.add_builtin_class_method(obj, "directory?", is_directory);
+    globals.add_builtin_class_method(obj, "size", size);
+    globals.add_builtin_class_method(obj, "mtime", mtime);
+    globals.add_builtin_class_method(obj, "stat", stat_);
+    globals.add_builtin_class_method(obj, "delete", delete);
+    globals.add_builtin_class_method(obj, "unlink", delete);
+    globals.add_builtin_class_method(obj, "rename", rename);
+    globals.add_builtin_class_method(obj, "expand_path", expand_path);
+    globals.add_builtin_class_method(obj, "absolute_path", absolute_path);
+    globals.add_builtin_class_method(obj, "dirname", dirname);
+    globals.add_builtin_class_method(obj, "split", split);
+    globals.add_builtin_class_method(obj, "fnmatch", fnmatch);
+    globals.add_builtin_class_method(obj, "fnmatch?", fnmatch);
+    globals.add_builtin_instance_method(class, "path", path);
+    globals.add_builtin_instance_method(class, "to_path", path);
+    globals.add_builtin_instance_method(class, "size", file_size);
+    globals.add_builtin_instance_method(class, "truncate", truncate);
+    globals.add_builtin_instance_method(class, "flock", flock);
+    globals.add_builtin_instance_method(class, "stat", file_stat);
+    globals.add_builtin_instance_method(class, "mtime", file_mtime);
+    obj
+}
+
+fn init_stat(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("File::Stat");
+    let class = ClassRef::from(id, globals.builtins.object);
+    let obj = Value::class(globals, class);
+    globals.builtins.file_stat = obj;
+    globals.add_builtin_instance_method(class, "size", stat_size);
+    globals.add_builtin_instance_method(class, "zero?", stat_zero);
+    globals.add_builtin_instance_method(class, "mode", stat_mode);
+    globals.add_builtin_instance_method(class, "mtime", stat_mtime);
+    globals.add_builtin_instance_method(class, "file?", stat_file);
+    globals.add_builtin_instance_method(class, "directory?", stat_directory);
+    globals.add_builtin_instance_method(class, "symlink?", stat_symlink);
+    globals.add_builtin_instance_method(class, "ftype", stat_ftype);
     obj
 }
 
@@ -24,6 +96,105 @@ fn string_to_path(vm: &mut VM, string: Value) -> Result<PathBuf, RubyError> {
     Ok(PathBuf::from(file))
 }
 
+/// Options for opening a file in `mode`, such as "r", "w+" or "ab".
+/// Returns the options and whether the file is readable and writable.
+fn open_options(vm: &VM, mode: &str) -> Result<(OpenOptions, bool, bool), RubyError> {
+    // The external encoding after ':' and the text flag do not matter here,
+    // and the binary flag is checked by `open_file`.
+    let access = mode.split(':').next().unwrap_or("");
+    let access: String = access.chars().filter(|c| *c != 'b' && *c != 't').collect();
+    let mut options = OpenOptions::new();
+    let (readable, writable) = match access.as_str() {
+        "r" => (true, false),
+        "r+" => (true, true),
+        "w" => (false, true),
+        "w+" => (true, true),
+        "a" => (false, true),
+        "a+" => (true, true),
+        _ => return Err(vm.error_argument(format!("invalid access mode {}", mode))),
+    };
+    options.read(readable).write(writable);
+    match access.chars().next() {
+        Some('w') => options.create(true).truncate(true),
+        Some('a') => options.create(true).append(true),
+        _ => &mut options,
+    };
+    Ok((options, readable, writable))
+}
+
+/// Open a file with the arguments of `File.open`: a path and an optional mode.
+fn open_file(vm: &mut VM, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 3)?;
+    let path = vm.expect_string(&args[0], "1st arg")?.to_string();
+    let mode = match args.get(1) {
+        Some(mode) if !mode.is_nil() => vm.expect_string(mode, "2nd arg")?.to_string(),
+        _ => "r".to_string(),
+    };
+    let (options, readable, writable) = open_options(vm, &mode)?;
+    match options.open(&path) {
+        Ok(file) => {
+            let mut info = IOInfo::new_file(file, path, readable, writable);
+            info.binmode = mode.split(':').next().unwrap_or("").contains('b');
+            Ok(Value::file(&vm.globals, IORef::new(info)))
+        }
+        Err(err) => Err(io::error_errno(vm, &err, &path)),
+    }
+}
+
+fn metadata(vm: &mut VM, path: Value) -> Result<Metadata, RubyError> {
+    let path = vm.expect_string(&path, "1st arg")?.to_string();
+    std::fs::metadata(&path).map_err(|err| io::error_errno(vm, &err, &path))
+}
+
+/// The modification time of a file.
+fn mtime_value(vm: &VM, meta: &Metadata) -> VMResult {
+    let time = match meta.modified() {
+        Ok(time) => time,
+        Err(err) => return Err(vm.error_io(err.to_string())),
+    };
+    let time = TimeInfo::from_system_time(time, Zone::Local);
+    Ok(Value::time(&vm.globals, TimeRef::new(time)))
+}
+
+/// The absolute path of `path` without "." and "..".
+/// A relative path is resolved from `dir`, or from the current directory when `dir` is None.
+fn absolute(vm: &VM, path: &str, dir: Option<&str>) -> Result<String, RubyError> {
+    let mut full = match dir {
+        Some(dir) => PathBuf::from(absolute(vm, dir, None)?),
+        None => match std::env::current_dir() {
+            Ok(dir) => dir,
+            Err(err) => return Err(vm.error_io(err.to_string())),
+        },
+    };
+    full.push(path);
+    let mut res = PathBuf::new();
+    for component in full.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                res.pop();
+            }
+            _ => res.push(component),
+        }
+    }
+    Ok(res.to_string_lossy().to_string())
+}
+
+/// The directory part of `path` in the manner of `File.dirname`.
+fn dirname_str(path: &str) -> &str {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return if path.is_empty() { "." } else { "/" };
+    }
+    match trimmed.rfind('/') {
+        Some(i) => match trimmed[..i].trim_end_matches('/') {
+            "" => "/",
+            dir => dir,
+        },
+        None => ".",
+    }
+}
+
 // Class methods
 
 fn join(vm: &mut VM, _: Value, args: &Args) -> VMResult {
@@ -129,6 +300,323 @@ fn write(vm: &mut VM, _: Value, args: &Args) -> VMResult {
     Ok(Value::fixnum(contents.len() as i64))
 }
 
+fn file_new(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    open_file(vm, args)
+}
+
+/// File.open(path, mode = "r")
+/// With a block, the file is passed to the block and closed when the block ends.
+fn open(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    let file = open_file(vm, args)?;
+    let block = match args.block {
+        Some(block) => block,
+        None => return Ok(file),
+    };
+    let res = vm.eval_block(block, &Args::new1(file));
+    file.as_io().unwrap().close();
+    res
+}
+
+/// The arguments of `File.foreach` and `File.readlines` after the path.
+fn rest_args(args: &Args) -> Args {
+    let mut rest = Args::new0();
+    for arg in &args[1..args.len()] {
+        rest.push(*arg);
+    }
+    rest.block = args.block;
+    rest.kw_arg = args.kw_arg;
+    rest
+}
+
+fn foreach(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    if args.block.is_none() {
+        let id = vm.globals.get_ident_id("foreach");
+        return Ok(Value::enumerator(&vm.globals, id, self_val, args.clone()));
+    }
+    let file = open_file(vm, &Args::new1(args[0]))?;
+    let res = io::each_line(vm, file, &rest_args(args));
+    file.as_io().unwrap().close();
+    match res {
+        Ok(_) if args.block != Some(MethodRef::from(0)) => Ok(Value::nil()),
+        res => res,
+    }
+}
+
+fn readlines(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let file = open_file(vm, &Args::new1(args[0]))?;
+    let res = io::readlines(vm, file, &rest_args(args));
+    file.as_io().unwrap().close();
+    res
+}
+
+fn exist(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let path = string_to_path(vm, args[0])?;
+    Ok(Value::bool(path.exists()))
+}
+
+fn is_file(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let path = string_to_path(vm, args[0])?;
+    Ok(Value::bool(path.is_file()))
+}
+
+fn is_directory(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let path = string_to_path(vm, args[0])?;
+    Ok(Value::bool(path.is_dir()))
+}
+
+fn size(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let meta = metadata(vm, args[0])?;
+    Ok(Value::fixnum(meta.len() as i64))
+}
+
+fn mtime(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let meta = metadata(vm, args[0])?;
+    mtime_value(vm, &meta)
+}
+
+fn stat_(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let meta = metadata(vm, args[0])?;
+    Ok(Value::file_stat(&vm.globals, StatRef::new(meta)))
+}
+
+fn delete(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    for arg in args.iter() {
+        let path = vm.expect_string(arg, "Path")?.to_string();
+        if let Err(err) = std::fs::remove_file(&path) {
+            return Err(io::error_errno(vm, &err, &path));
+        }
+    }
+    Ok(Value::fixnum(args.len() as i64))
+}
+
+fn rename(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 2)?;
+    let from = vm.expect_string(&args[0], "1st arg")?.to_string();
+    let to = vm.expect_string(&args[1], "2nd arg")?.to_string();
+    match std::fs::rename(&from, &to) {
+        Ok(()) => Ok(Value::fixnum(0)),
+        Err(err) => Err(io::error_errno(vm, &err, &format!("({}, {})", from, to))),
+    }
+}
+
+/// File.expand_path(path, dir = nil)
+/// A leading "~" is replaced by the home directory.
+fn expand_path(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let mut path = vm.expect_string(&args[0], "1st arg")?.to_string();
+    if path == "~" || path.starts_with("~/") {
+        let home = match std::env::var("HOME") {
+            Ok(home) => home,
+            Err(_) => return Err(vm.error_argument("couldn't find login name -- expanding '~'")),
+        };
+        path = format!("{}{}", home, &path[1..]);
+    }
+    let dir = match args.get(1) {
+        Some(dir) if !dir.is_nil() => Some(vm.expect_string(dir, "2nd arg")?.to_string()),
+        _ => None,
+    };
+    let res = absolute(vm, &path, dir.as_deref())?;
+    Ok(Value::string(&vm.globals, res))
+}
+
+fn absolute_path(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let path = vm.expect_string(&args[0], "1st arg")?.to_string();
+    let dir = match args.get(1) {
+        Some(dir) if !dir.is_nil() => Some(vm.expect_string(dir, "2nd arg")?.to_string()),
+        _ => None,
+    };
+    let res = absolute(vm, &path, dir.as_deref())?;
+    Ok(Value::string(&vm.globals, res))
+}
+
+fn dirname(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let path = vm.expect_string(&args[0], "1st arg")?;
+    let dir = dirname_str(path).to_string();
+    Ok(Value::string(&vm.globals, dir))
+}
+
+fn split(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let dir = dirname(vm, self_val, args)?;
+    let base = basename(vm, self_val, args)?;
+    Ok(Value::array_from(&vm.globals, vec![dir, base]))
+}
+
+/// File.fnmatch(pattern, path, flags = 0)
+fn fnmatch(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 2, 3)?;
+    let flags = match args.get(2) {
+        Some(flags) => flags.expect_integer(vm, "Flags")?,
+        None => 0,
+    };
+    let pattern = vm.expect_string(&args[0], "1st arg")?;
+    let path = vm.expect_string(&args[1], "2nd arg")?;
+    Ok(Value::bool(dir::fnmatch(pattern, path, flags)))
+}
+
+// Instance methods
+
+fn path(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let io = self_val.as_io().unwrap();
+    let path = io.path.clone().unwrap_or_default();
+    Ok(Value::string(&vm.globals, path))
+}
+
+fn file_metadata(vm: &mut VM, self_val: Value) -> Result<Metadata, RubyError> {
+    let mut io = self_val.as_io().unwrap();
+    let stream = io.file_stream(vm)?;
+    stream
+        .file()
+        .metadata()
+        .map_err(|err| vm.error_io(err.to_string()))
+}
+
+fn file_size(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let meta = file_metadata(vm, self_val)?;
+    Ok(Value::fixnum(meta.len() as i64))
+}
+
+fn file_stat(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let meta = file_metadata(vm, self_val)?;
+    Ok(Value::file_stat(&vm.globals, StatRef::new(meta)))
+}
+
+fn file_mtime(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let meta = file_metadata(vm, self_val)?;
+    mtime_value(vm, &meta)
+}
+
+fn truncate(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let len = args[0].expect_integer(vm, "Length")?;
+    if len < 0 {
+        return Err(vm.error_errno("EINVAL", "Invalid argument"));
+    }
+    let mut io = self_val.as_io().unwrap();
+    let stream = io.file_stream(vm)?;
+    match stream.file().set_len(len as u64) {
+        Ok(()) => Ok(Value::fixnum(0)),
+        Err(err) => Err(vm.error_io(err.to_string())),
+    }
+}
+
+/// File#flock(operation)
+/// Returns false when the lock is not acquired with LOCK_NB.
+fn flock(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let op = args[0].expect_integer(vm, "Operation")?;
+    let mut io = self_val.as_io().unwrap();
+    let file = io.file_stream(vm)?.file();
+    let nonblock = op & LOCK_NB != 0;
+    let res = match (op & !LOCK_NB, nonblock) {
+        (LOCK_SH, false) => file.lock_shared(),
+        (LOCK_EX, false) => file.lock(),
+        (LOCK_SH, true) | (LOCK_EX, true) => {
+            let res = if op & LOCK_EX != 0 {
+                file.try_lock()
+            } else {
+                file.try_lock_shared()
+            };
+            match res {
+                Ok(()) => Ok(()),
+                Err(std::fs::TryLockError::WouldBlock) => return Ok(Value::false_val()),
+                Err(std::fs::TryLockError::Error(err)) => Err(err),
+            }
+        }
+        (LOCK_UN, _) => file.unlock(),
+        _ => return Err(vm.error_errno("EINVAL", "Invalid argument")),
+    };
+    match res {
+        Ok(()) => Ok(Value::fixnum(0)),
+        Err(err) => Err(vm.error_io(err.to_string())),
+    }
+}
+
+// File::Stat
+
+fn stat_size(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let stat = self_val.as_file_stat().unwrap();
+    Ok(Value::fixnum(stat.len() as i64))
+}
+
+fn stat_zero(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let stat = self_val.as_file_stat().unwrap();
+    Ok(Value::bool(stat.len() == 0))
+}
+
+fn stat_mode(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let stat = self_val.as_file_stat().unwrap();
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::MetadataExt;
+        stat.mode() as i64
+    };
+    #[cfg(not(unix))]
+    let mode = match (stat.is_dir(), stat.permissions().readonly()) {
+        (true, _) => 0o40755,
+        (false, true) => 0o100444,
+        (false, false) => 0o100644,
+    };
+    Ok(Value::fixnum(mode))
+}
+
+fn stat_mtime(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let stat = self_val.as_file_stat().unwrap();
+    mtime_value(vm, &stat)
+}
+
+fn stat_file(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let stat = self_val.as_file_stat().unwrap();
+    Ok(Value::bool(stat.is_file()))
+}
+
+fn stat_directory(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let stat = self_val.as_file_stat().unwrap();
+    Ok(Value::bool(stat.is_dir()))
+}
+
+fn stat_symlink(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let stat = self_val.as_file_stat().unwrap();
+    Ok(Value::bool(stat.file_type().is_symlink()))
+}
+
+fn stat_ftype(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let stat = self_val.as_file_stat().unwrap();
+    let file_type = stat.file_type();
+    let ftype = if file_type.is_file() {
+        "file"
+    } else if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_symlink() {
+        "link"
+    } else {
+        "unknown"
+    };
+    Ok(Value::string(&vm.globals, ftype.to_string()))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::test::*;
@@ -145,4 +633,88 @@ mod tests {
         "#;
         assert_script(program);
     }
+
+    #[test]
+    fn file_open() {
+        let program = r#"
+            res = File.open("file_open.txt", "w") do |f|
+              f.puts "one"
+              f.write "two\n", "three\n"
+              7
+            end
+            assert 7, res
+            f = File.open("file_open.txt")
+            assert "file_open.txt", f.path
+            assert "one\n", f.gets
+            assert 4, f.pos
+            assert "two\nthree\n", f.read
+            f.rewind
+            a = []
+            f.each_line { |line| a.push line }
+            assert ["one\n", "two\n", "three\n"], a
+            f.seek(-6, IO::SEEK_END)
+            assert "three\n", f.read
+            assert false, f.closed?
+            f.close
+            assert true, f.closed?
+            File.open("file_open.txt", "a") { |f| f.print "four" }
+            assert ["one", "two", "three", "four"], File.readlines("file_open.txt", chomp: true)
+            a = []
+            File.foreach("file_open.txt") { |line| a.push line }
+            assert ["one\n", "two\n", "three\n", "four"], a
+            File.open("file_open.txt", "r+") do |f|
+              f.write "ONE"
+              f.truncate(8)
+              assert 0, f.flock(File::LOCK_EX)
+              assert 0, f.flock(File::LOCK_UN)
+            end
+            assert "ONE\ntwo\n", File.read("file_open.txt")
+            File.open("file_open.txt", "w+") do |f|
+              f.write "abc"
+              f.rewind
+              assert "abc", f.read
+            end
+            assert Encoding::UTF_8, File.open("file_open.txt") { |f| f.read }.encoding
+            File.open("file_open.txt", "rb") do |f|
+              assert Encoding::ASCII_8BIT, f.getc.encoding
+              assert Encoding::ASCII_8BIT, f.gets.encoding
+              f.rewind
+              assert Encoding::ASCII_8BIT, f.read.encoding
+            end
+            assert 3, File.size("file_open.txt")
+            File.delete("file_open.txt")
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn file_stat() {
+        let program = r#"
+            File.write("file_stat.txt", "hello")
+            assert true, File.exist?("file_stat.txt")
+            assert true, File.file?("file_stat.txt")
+            assert false, File.directory?("file_stat.txt")
+            assert true, File.directory?(".")
+            s = File.stat("file_stat.txt")
+            assert 5, s.size
+            assert false, s.zero?
+            assert true, s.file?
+            assert "file", s.ftype
+            assert "directory", File.stat(".").ftype
+            assert File::Stat, s.class
+            assert "File::Stat", File::Stat.inspect
+            assert 32768, s.mode & 61440
+            File.rename("file_stat.txt", "file_stat2.txt")
+            assert false, File.exist?("file_stat.txt")
+            assert 1, File.delete("file_stat2.txt")
+            assert false, File.exist?("file_stat2.txt")
+            assert "/usr/lib", File.dirname("/usr/lib/file.rb")
+            assert ".", File.dirname("file.rb")
+            assert ["/usr/lib", "file.rb"], File.split("/usr/lib/file.rb")
+            assert "/usr/file.rb", File.expand_path("../file.rb", "/usr/lib")
+            assert "/a/c", File.absolute_path("/a/./b/../c")
+            assert_error { File.open("no_such_file.txt") }
+        "#;
+        assert_script(program);
+    }
 }
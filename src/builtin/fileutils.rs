@@ -0,0 +1,263 @@
+use crate::builtin::io;
+use crate::*;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+
+/// FileUtils, which is loaded by `require "fileutils"`.
+pub fn init_fileutils(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("FileUtils");
+    let class = ClassRef::from(id, None);
+    let obj = Value::module(globals, class);
+    globals.add_builtin_class_method(obj, "mkdir_p", mkdir_p);
+    globals.add_builtin_class_method(obj, "makedirs", mkdir_p);
+    globals.add_builtin_class_method(obj, "rm_rf", rm_rf);
+    globals.add_builtin_class_method(obj, "cp", cp);
+    globals.add_builtin_class_method(obj, "cp_r", cp_r);
+    globals.add_builtin_class_method(obj, "mv", mv);
+    globals.add_builtin_class_method(obj, "move", mv);
+    globals.add_builtin_class_method(obj, "touch", touch);
+    obj
+}
+
+/// A path or an Array of paths.
+fn path_list(vm: &mut VM, val: Value) -> Result<Vec<String>, RubyError> {
+    let vals = match val.as_array() {
+        Some(ary) => ary.elements.clone(),
+        None => vec![val],
+    };
+    let mut list = vec![];
+    for val in vals {
+        list.push(vm.expect_string(&val, "Path")?.to_string());
+    }
+    Ok(list)
+}
+
+fn list_value(vm: &VM, list: Vec<String>) -> Value {
+    let list = list
+        .into_iter()
+        .map(|path| Value::string(&vm.globals, path))
+        .collect();
+    Value::array_from(&vm.globals, list)
+}
+
+/// The destination of copying or moving `src` to `dest`.
+/// When `dest` is a directory, `src` goes into it.
+fn destination(src: &str, dest: &str) -> PathBuf {
+    let dest = Path::new(dest);
+    match Path::new(src).file_name() {
+        Some(name) if dest.is_dir() => dest.join(name),
+        _ => dest.to_path_buf(),
+    }
+}
+
+fn copy_file(vm: &VM, src: &str, dest: &Path) -> Result<(), RubyError> {
+    match std::fs::copy(src, dest) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(io::error_errno(vm, &err, src)),
+    }
+}
+
+/// Copy `src` to `dest` recursively.
+/// Symbolic links are copied as links, and are not followed.
+fn copy_tree(vm: &VM, src: &Path, dest: &Path) -> Result<(), RubyError> {
+    let errno = |err: std::io::Error| io::error_errno(vm, &err, &src.to_string_lossy());
+    let meta = src.symlink_metadata().map_err(errno)?;
+    if meta.file_type().is_symlink() {
+        let target = std::fs::read_link(src).map_err(errno)?;
+        return copy_link(&target, dest).map_err(errno);
+    }
+    if !meta.is_dir() {
+        return copy_file(vm, &src.to_string_lossy(), dest);
+    }
+    if !dest.is_dir() {
+        std::fs::create_dir(dest).map_err(errno)?;
+    }
+    for entry in std::fs::read_dir(src).map_err(errno)? {
+        let entry = entry.map_err(errno)?;
+        copy_tree(vm, &entry.path(), &dest.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_link(target: &Path, dest: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, dest)
+}
+
+#[cfg(windows)]
+fn copy_link(target: &Path, dest: &Path) -> std::io::Result<()> {
+    // A relative target is resolved from the directory of the link.
+    let resolved = dest.parent().unwrap_or(Path::new(".")).join(target);
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, dest)
+    } else {
+        std::os::windows::fs::symlink_file(target, dest)
+    }
+}
+
+/// Whether the directory `src` contains `dest`, which may not exist yet.
+fn is_inside(src: &Path, dest: &Path) -> bool {
+    let src = match src.canonicalize() {
+        Ok(src) => src,
+        Err(_) => return false,
+    };
+    let dest = match (dest.canonicalize(), dest.file_name()) {
+        (Ok(dest), _) => dest,
+        (Err(_), Some(name)) => {
+            let parent = match dest.parent() {
+                Some(parent) if parent != Path::new("") => parent,
+                _ => Path::new("."),
+            };
+            match parent.canonicalize() {
+                Ok(parent) => parent.join(name),
+                Err(_) => return false,
+            }
+        }
+        (Err(_), None) => return false,
+    };
+    dest.starts_with(src)
+}
+
+// Module functions
+
+fn mkdir_p(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let list = path_list(vm, args[0])?;
+    for path in &list {
+        if let Err(err) = std::fs::create_dir_all(path) {
+            return Err(io::error_errno(vm, &err, path));
+        }
+    }
+    Ok(list_value(vm, list))
+}
+
+/// Remove files and directories recursively, ignoring errors.
+fn rm_rf(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let list = path_list(vm, args[0])?;
+    for path in &list {
+        let _ = match Path::new(path).symlink_metadata() {
+            Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(path),
+            Ok(_) => std::fs::remove_file(path),
+            Err(_) => continue,
+        };
+    }
+    Ok(list_value(vm, list))
+}
+
+fn cp(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 2)?;
+    let dest = vm.expect_string(&args[1], "Destination")?.to_string();
+    for src in path_list(vm, args[0])? {
+        copy_file(vm, &src, &destination(&src, &dest))?;
+    }
+    Ok(Value::nil())
+}
+
+fn cp_r(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 2)?;
+    let dest = vm.expect_string(&args[1], "Destination")?.to_string();
+    for src in path_list(vm, args[0])? {
+        let dest = destination(&src, &dest);
+        if Path::new(&src).is_dir() && is_inside(Path::new(&src), &dest) {
+            return Err(vm.error_argument(format!(
+                "Cannot copy directory {} to itself {}.",
+                src,
+                dest.to_string_lossy()
+            )));
+        }
+        copy_tree(vm, Path::new(&src), &dest)?;
+    }
+    Ok(Value::nil())
+}
+
+fn mv(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 2)?;
+    let dest = vm.expect_string(&args[1], "Destination")?.to_string();
+    for src in path_list(vm, args[0])? {
+        if let Err(err) = std::fs::rename(&src, destination(&src, &dest)) {
+            return Err(io::error_errno(vm, &err, &src));
+        }
+    }
+    Ok(Value::fixnum(0))
+}
+
+/// Create the files, or update their modification time if they exist.
+fn touch(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let list = path_list(vm, args[0])?;
+    for path in &list {
+        let res = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .and_then(|file| file.set_modified(std::time::SystemTime::now()));
+        if let Err(err) = res {
+            return Err(io::error_errno(vm, &err, path));
+        }
+    }
+    Ok(list_value(vm, list))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn fileutils() {
+        let program = r#"
+        assert true, require("fileutils")
+        assert false, require("fileutils")
+        assert ["fu_test/a/b"], FileUtils.mkdir_p("fu_test/a/b")
+        FileUtils.mkdir_p(["fu_test/a/b", "fu_test/c"])
+        assert true, Dir.exist?("fu_test/a/b")
+        FileUtils.touch("fu_test/a/x.txt")
+        assert 0, File.size("fu_test/a/x.txt")
+        File.write("fu_test/a/b/y.txt", "yyy")
+        FileUtils.cp("fu_test/a/b/y.txt", "fu_test/c")
+        assert "yyy", File.read("fu_test/c/y.txt")
+        FileUtils.cp("fu_test/a/b/y.txt", "fu_test/c/z.txt")
+        assert "yyy", File.read("fu_test/c/z.txt")
+        FileUtils.cp_r("fu_test/a", "fu_test/d")
+        assert ["fu_test/d/x.txt", "fu_test/d/b/y.txt"], Dir.glob("fu_test/d/**/*.txt")
+        FileUtils.cp_r("fu_test/a", "fu_test/d")
+        assert true, File.exist?("fu_test/d/a/b/y.txt")
+        FileUtils.mv("fu_test/c/z.txt", "fu_test/a")
+        assert false, File.exist?("fu_test/c/z.txt")
+        assert "yyy", File.read("fu_test/a/z.txt")
+        FileUtils.mv("fu_test/a/z.txt", "fu_test/w.txt")
+        assert "yyy", File.read("fu_test/w.txt")
+        FileUtils.rm_rf(["fu_test", "fu_test_none"])
+        assert false, Dir.exist?("fu_test")
+        assert_error { FileUtils.cp("fu_test/none", "fu_test/c") }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn fileutils_cp_r_into_itself() {
+        let program = r#"
+        require "fileutils"
+        FileUtils.mkdir_p("fu_self/a")
+        assert_error { FileUtils.cp_r("fu_self/a", "fu_self/a/b") }
+        assert false, Dir.exist?("fu_self/a/b")
+        FileUtils.rm_rf("fu_self")
+        "#;
+        assert_script(program);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fileutils_cp_r_symlink() {
+        std::fs::create_dir_all("fu_link/a").unwrap();
+        std::os::unix::fs::symlink("..", "fu_link/a/up").unwrap();
+        let program = r#"
+        require "fileutils"
+        FileUtils.cp_r("fu_link/a", "fu_link/b")
+        "#;
+        assert_script(program);
+        let meta = std::fs::symlink_metadata("fu_link/b/up");
+        std::fs::remove_dir_all("fu_link").unwrap();
+        assert!(meta.unwrap().file_type().is_symlink());
+    }
+}
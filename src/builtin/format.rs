@@ -0,0 +1,776 @@
+//! The formatter shared by `Kernel#format`, `Kernel#sprintf`, `Kernel#printf` and `String#%`.
+use crate::*;
+
+#[derive(Debug, Clone, Default)]
+struct Spec {
+    minus: bool,
+    plus: bool,
+    space: bool,
+    zero: bool,
+    sharp: bool,
+    width: Option<usize>,
+    prec: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArgMode {
+    None,
+    Unnumbered,
+    Numbered,
+    Named,
+}
+
+/// Arguments of a format string, consumed sequentially, by position (`%1$s`) or by name (`%<a>s`).
+struct ArgList<'a> {
+    args: &'a [Value],
+    next: usize,
+    mode: ArgMode,
+}
+
+impl<'a> ArgList<'a> {
+    fn next(&mut self, vm: &VM) -> Result<Value, RubyError> {
+        match self.mode {
+            ArgMode::Numbered => {
+                return Err(
+                    vm.error_argument(format!("unnumbered({}) mixed with numbered", self.next + 1))
+                )
+            }
+            ArgMode::Named => {
+                return Err(
+                    vm.error_argument(format!("unnumbered({}) mixed with named", self.next + 1))
+                )
+            }
+            _ => {}
+        }
+        self.mode = ArgMode::Unnumbered;
+        match self.args.get(self.next) {
+            Some(val) => {
+                self.next += 1;
+                Ok(*val)
+            }
+            None => Err(vm.error_argument("too few arguments")),
+        }
+    }
+
+    fn numbered(&mut self, vm: &VM, n: usize) -> Result<Value, RubyError> {
+        match self.mode {
+            ArgMode::Unnumbered => {
+                return Err(
+                    vm.error_argument(format!("numbered({}) after unnumbered({})", n, self.next))
+                )
+            }
+            ArgMode::Named => return Err(vm.error_argument(format!("numbered({}) after named", n))),
+            _ => {}
+        }
+        self.mode = ArgMode::Numbered;
+        if n == 0 {
+            return Err(vm.error_argument("invalid index - 0$"));
+        }
+        match self.args.get(n - 1) {
+            Some(val) => Ok(*val),
+            None => Err(vm.error_argument("too few arguments")),
+        }
+    }
+
+    fn named(&mut self, vm: &mut VM, name: &str) -> Result<Value, RubyError> {
+        match self.mode {
+            ArgMode::Unnumbered => {
+                return Err(
+                    vm.error_argument(format!("named<{}> after unnumbered({})", name, self.next))
+                )
+            }
+            ArgMode::Numbered => {
+                return Err(vm.error_argument(format!("named<{}> after numbered", name)))
+            }
+            _ => {}
+        }
+        self.mode = ArgMode::Named;
+        let hash = match self.args {
+            [arg] => match arg.as_hash() {
+                Some(hash) => hash,
+                None => return Err(vm.error_argument("one hash required")),
+            },
+            _ => return Err(vm.error_argument("one hash required")),
+        };
+        let key = Value::symbol(vm.globals.get_ident_id(name));
+        match hash.get(&key) {
+            Some(val) => Ok(*val),
+            None => Err(vm.error_index(format!("key<{}> not found", name))),
+        }
+    }
+}
+
+/// Format the arguments of `format`, `printf` and `IO#printf`: a format string followed by values.
+pub fn format_args(vm: &mut VM, args: &Args) -> Result<String, RubyError> {
+    vm.check_args_min(args.len(), 1)?;
+    let fmt = match args[0].as_string() {
+        Some(s) => s.to_string(),
+        None => {
+            let class = vm.globals.get_class_name(args[0]);
+            return Err(vm.error_type(format!("no implicit conversion of {} into String", class)));
+        }
+    };
+    let mut arguments = args[1..args.len()].to_vec();
+    if let Some(kw) = args.kw_arg {
+        arguments.push(kw);
+    }
+    format(vm, &fmt, &arguments)
+}
+
+/// Format `args` according to the format string `fmt` in the manner of `Kernel#format`.
+/// Named references take their values from a Hash given as the only argument.
+pub fn format(vm: &mut VM, fmt: &str, args: &[Value]) -> Result<String, RubyError> {
+    let mut list = ArgList {
+        args,
+        next: 0,
+        mode: ArgMode::None,
+    };
+    let chars: Vec<char> = fmt.chars().collect();
+    let mut res = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        i += 1;
+        if ch != '%' {
+            res.push(ch);
+            continue;
+        }
+        match chars.get(i) {
+            Some('%') => {
+                res.push('%');
+                i += 1;
+                continue;
+            }
+            Some('\n') | Some('\0') => {
+                res.push('%');
+                continue;
+            }
+            _ => {}
+        }
+        let mut spec = Spec::default();
+        let mut arg = None;
+        loop {
+            let ch = match chars.get(i) {
+                Some(ch) => *ch,
+                None => {
+                    return Err(
+                        vm.error_argument("incomplete format specifier; use %% (double %) instead")
+                    )
+                }
+            };
+            i += 1;
+            match ch {
+                ' ' | '#' | '+' | '-' | '0' => {
+                    if spec.prec.is_some() {
+                        return Err(vm.error_argument("flag after precision"));
+                    } else if spec.width.is_some() {
+                        return Err(vm.error_argument("flag after width"));
+                    }
+                    match ch {
+                        ' ' => spec.space = true,
+                        '#' => spec.sharp = true,
+                        '+' => spec.plus = true,
+                        '-' => spec.minus = true,
+                        _ => spec.zero = true,
+                    }
+                }
+                '1'..='9' => {
+                    let n = read_number(&chars, &mut i, ch);
+                    if chars.get(i) == Some(&'$') {
+                        i += 1;
+                        if arg.is_some() {
+                            return Err(vm.error_argument(format!("value given twice - {}$", n)));
+                        }
+                        arg = Some(list.numbered(vm, n)?);
+                    } else {
+                        if spec.prec.is_some() {
+                            return Err(vm.error_argument("width after precision"));
+                        } else if spec.width.is_some() {
+                            return Err(vm.error_argument("width given twice"));
+                        }
+                        spec.width = Some(n);
+                    }
+                }
+                '<' | '{' => {
+                    let term = if ch == '<' { '>' } else { '}' };
+                    let start = i;
+                    while i < chars.len() && chars[i] != term {
+                        i += 1;
+                    }
+                    if i == chars.len() {
+                        return Err(vm.error_argument("malformed name - unmatched parenthesis"));
+                    }
+                    let name: String = chars[start..i].iter().collect();
+                    i += 1;
+                    if arg.is_some() {
+                        return Err(vm.error_argument(format!("named<{}> after <{}>", name, name)));
+                    }
+                    let val = list.named(vm, &name)?;
+                    if term == '}' {
+                        let s = vm.val_to_s(val);
+                        res += &format_str(s, &spec);
+                        break;
+                    }
+                    arg = Some(val);
+                }
+                '*' => {
+                    if spec.prec.is_some() {
+                        return Err(vm.error_argument("width after precision"));
+                    } else if spec.width.is_some() {
+                        return Err(vm.error_argument("width given twice"));
+                    }
+                    let w = star_arg(vm, &chars, &mut i, &mut list)?;
+                    if w < 0 {
+                        spec.minus = true;
+                    }
+                    spec.width = Some(w.unsigned_abs() as usize);
+                }
+                '.' => {
+                    if spec.prec.is_some() {
+                        return Err(vm.error_argument("precision given twice"));
+                    }
+                    match chars.get(i) {
+                        Some('*') => {
+                            i += 1;
+                            let p = star_arg(vm, &chars, &mut i, &mut list)?;
+                            // A negative precision is taken as if it were omitted.
+                            if p >= 0 {
+                                spec.prec = Some(p as usize);
+                            }
+                        }
+                        Some(c) if c.is_ascii_digit() => {
+                            let c = *c;
+                            i += 1;
+                            spec.prec = Some(read_number(&chars, &mut i, c));
+                        }
+                        _ => spec.prec = Some(0),
+                    }
+                }
+                'd' | 'i' | 'u' | 'x' | 'X' | 'o' | 'b' | 'B' | 'f' | 'e' | 'E' | 'g' | 'G'
+                | 'a' | 'A' | 's' | 'p' | 'c' => {
+                    let val = match arg {
+                        Some(val) => val,
+                        None => list.next(vm)?,
+                    };
+                    res += &format_value(vm, ch, val, &spec)?;
+                    break;
+                }
+                _ => return Err(vm.error_argument(format!("malformed format string - %{}", ch))),
+            }
+        }
+    }
+    Ok(res)
+}
+
+fn read_number(chars: &[char], i: &mut usize, first: char) -> usize {
+    let mut n = first as usize - '0' as usize;
+    while let Some(c) = chars.get(*i) {
+        match c.to_digit(10) {
+            Some(d) => {
+                n = n.saturating_mul(10).saturating_add(d as usize);
+                *i += 1;
+            }
+            None => break,
+        }
+    }
+    n
+}
+
+/// Fetch the argument for a `*` width or precision, which may be positional (`*1$`).
+fn star_arg(
+    vm: &mut VM,
+    chars: &[char],
+    i: &mut usize,
+    list: &mut ArgList,
+) -> Result<i64, RubyError> {
+    let start = *i;
+    let mut n = 0;
+    while let Some(d) = chars.get(*i).and_then(|c| c.to_digit(10)) {
+        n = n * 10 + d as usize;
+        *i += 1;
+    }
+    let val = if *i > start && chars.get(*i) == Some(&'$') {
+        *i += 1;
+        list.numbered(vm, n)?
+    } else {
+        *i = start;
+        list.next(vm)?
+    };
+    to_integer(vm, val)
+}
+
+fn format_value(vm: &mut VM, ch: char, val: Value, spec: &Spec) -> Result<String, RubyError> {
+    let res = match ch {
+        'd' | 'i' | 'u' | 'x' | 'X' | 'o' | 'b' | 'B' => {
+            let n = to_integer(vm, val)?;
+            format_integer(ch, n, spec)
+        }
+        'f' | 'e' | 'E' | 'g' | 'G' | 'a' | 'A' => {
+            let f = to_float(vm, val)?;
+            format_float(ch, f, spec)
+        }
+        's' => format_str(vm.val_to_s(val), spec),
+        'p' => format_str(vm.val_inspect(val), spec),
+        'c' => {
+            let c = if let Some(s) = val.as_string() {
+                match s.chars().next() {
+                    Some(c) => c,
+                    None => return Err(vm.error_argument("%c requires a character")),
+                }
+            } else {
+                let n = to_integer(vm, val)?;
+                match std::char::from_u32(n as u32) {
+                    Some(c) if n >= 0 && n <= u32::MAX as i64 => c,
+                    _ => return Err(vm.error_argument(format!("invalid character - {}", n))),
+                }
+            };
+            let spec = Spec {
+                prec: None,
+                ..spec.clone()
+            };
+            format_str(c.to_string(), &spec)
+        }
+        _ => unreachable!(),
+    };
+    Ok(res)
+}
+
+fn to_integer(vm: &mut VM, val: Value) -> Result<i64, RubyError> {
+    if let Some(i) = val.as_fixnum() {
+        Ok(i)
+    } else if let Some(f) = val.as_flonum() {
+        if f.is_nan() {
+            Err(vm.error_argument("NaN"))
+        } else if f.is_infinite() {
+            Err(vm.error_argument(if f < 0.0 { "-Infinity" } else { "Infinity" }))
+        } else if f < i64::MIN as f64 || f >= i64::MAX as f64 {
+            Err(vm.error_argument(format!("{} is out of range for Integer", f)))
+        } else {
+            Ok(f.trunc() as i64)
+        }
+    } else if let Some(s) = val.as_string() {
+        match parse_integer(s) {
+            Some(i) => Ok(i),
+            None => {
+                let inspect = vm.val_inspect(val);
+                Err(vm.error_argument(format!("invalid value for Integer(): {}", inspect)))
+            }
+        }
+    } else {
+        let class = vm.globals.get_class_name(val);
+        Err(vm.error_type(format!("can't convert {} into Integer", class)))
+    }
+}
+
+fn to_float(vm: &mut VM, val: Value) -> Result<f64, RubyError> {
+    if let Some(i) = val.as_fixnum() {
+        Ok(i as f64)
+    } else if let Some(f) = val.as_flonum() {
+        Ok(f)
+    } else if let Some(s) = val.as_string() {
+        let s = s.trim().replace('_', "");
+        let valid = !s.is_empty()
+            && s.chars().all(|c| c.is_ascii_digit() || "+-.eE".contains(c))
+            && !s.starts_with('.')
+            && !s.ends_with('.');
+        match s.parse::<f64>() {
+            Ok(f) if valid => Ok(f),
+            _ => {
+                let inspect = vm.val_inspect(val);
+                Err(vm.error_argument(format!("invalid value for Float(): {}", inspect)))
+            }
+        }
+    } else {
+        let class = vm.globals.get_class_name(val);
+        Err(vm.error_type(format!("can't convert {} into Float", class)))
+    }
+}
+
+/// Parse a String as `Integer()` does, accepting a sign, a radix prefix and underscores.
+fn parse_integer(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (neg, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let lower = s.to_ascii_lowercase();
+    let (radix, digits) = if lower.starts_with("0x") {
+        (16, &s[2..])
+    } else if lower.starts_with("0b") {
+        (2, &s[2..])
+    } else if lower.starts_with("0o") {
+        (8, &s[2..])
+    } else if lower.starts_with("0d") {
+        (10, &s[2..])
+    } else if s.len() > 1 && s.starts_with('0') {
+        (8, &s[1..])
+    } else {
+        (10, s)
+    };
+    if digits.is_empty()
+        || digits.starts_with('_')
+        || digits.ends_with('_')
+        || digits.contains("__")
+    {
+        return None;
+    }
+    let digits = digits.replace('_', "");
+    let n = i64::from_str_radix(&format!("{}{}", if neg { "-" } else { "" }, digits), radix);
+    n.ok()
+}
+
+fn format_str(s: String, spec: &Spec) -> String {
+    let s = match spec.prec {
+        Some(prec) => s.chars().take(prec).collect(),
+        None => s,
+    };
+    let len = s.chars().count();
+    let fill = " ".repeat(spec.width.unwrap_or(0).saturating_sub(len));
+    if spec.minus {
+        s + &fill
+    } else {
+        fill + &s
+    }
+}
+
+fn format_integer(ch: char, n: i64, spec: &Spec) -> String {
+    let (base, upper) = match ch {
+        'x' => (16, false),
+        'X' => (16, true),
+        'o' => (8, false),
+        'b' | 'B' => (2, false),
+        _ => (10, false),
+    };
+    let prefix = match ch {
+        _ if !spec.sharp || n == 0 => "",
+        'x' => "0x",
+        'X' => "0X",
+        'o' => "0",
+        'b' => "0b",
+        'B' => "0B",
+        _ => "",
+    };
+    // Negative numbers are shown in two's complement, like "..f85", unless a sign is requested.
+    let dots = n < 0 && base != 10 && !spec.plus && !spec.space;
+    let (sign, digits) = if dots {
+        ("", twos_complement(n, base, upper))
+    } else {
+        let sign = if n < 0 {
+            "-"
+        } else if spec.plus {
+            "+"
+        } else if spec.space {
+            " "
+        } else {
+            ""
+        };
+        let m = n.unsigned_abs();
+        let digits = match base {
+            16 if upper => format!("{:X}", m),
+            16 => format!("{:x}", m),
+            8 => format!("{:o}", m),
+            2 => format!("{:b}", m),
+            _ => m.to_string(),
+        };
+        (sign, digits)
+    };
+    let mut width = spec.width.unwrap_or(0) as isize - (sign.len() + prefix.len()) as isize;
+    let mut prec = spec.prec.map(|p| p as isize);
+    if dots {
+        width -= 2;
+        prec = prec.map(|p| p - 2);
+    }
+    if spec.zero && !spec.minus && prec.is_none() {
+        prec = Some(width);
+        width = 0;
+    }
+    let len = digits.len() as isize;
+    let prec = prec.unwrap_or(0).max(len);
+    width -= prec;
+    let fill = if dots {
+        digits.chars().next().unwrap()
+    } else {
+        '0'
+    };
+    let mut res = String::new();
+    if !spec.minus && width > 0 {
+        res += &" ".repeat(width as usize);
+    }
+    res += sign;
+    res += prefix;
+    if dots {
+        res += "..";
+    }
+    res += &fill.to_string().repeat((prec - len) as usize);
+    res += &digits;
+    if spec.minus && width > 0 {
+        res += &" ".repeat(width as usize);
+    }
+    res
+}
+
+/// Digits of a negative `n` in two's complement, with a single leading sign digit.
+fn twos_complement(mut n: i64, base: u32, upper: bool) -> String {
+    let bits = match base {
+        16 => 4,
+        8 => 3,
+        _ => 1,
+    };
+    let mask = (base - 1) as i64;
+    let mut digits = vec![];
+    loop {
+        digits.push((n & mask) as u32);
+        n >>= bits;
+        if n == -1 {
+            break;
+        }
+    }
+    if *digits.last().unwrap() != base - 1 {
+        digits.push(base - 1);
+    }
+    digits
+        .iter()
+        .rev()
+        .map(|d| {
+            let c = std::char::from_digit(*d, base).unwrap();
+            if upper {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn format_float(ch: char, f: f64, spec: &Spec) -> String {
+    let sign = if f.is_sign_negative() && !f.is_nan() {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    };
+    if !f.is_finite() {
+        let body = if f.is_nan() { "NaN" } else { "Inf" };
+        let spec = Spec {
+            zero: false,
+            ..spec.clone()
+        };
+        return pad_number(sign, "", body, &spec);
+    }
+    let x = f.abs();
+    let prec = spec.prec.unwrap_or(6);
+    let upper = ch.is_ascii_uppercase();
+    let (prefix, body) = match ch {
+        'f' => {
+            let mut s = format!("{:.*}", prec, x);
+            if spec.sharp && prec == 0 {
+                s.push('.');
+            }
+            ("", s)
+        }
+        'e' | 'E' => ("", format_exp(x, prec, spec.sharp, upper)),
+        'g' | 'G' => ("", format_general(x, prec, spec.sharp, upper)),
+        _ => (
+            if upper { "0X" } else { "0x" },
+            format_hex_float(x, spec.prec, spec.sharp, upper),
+        ),
+    };
+    pad_number(sign, prefix, &body, spec)
+}
+
+/// `x` in the form "d.ddde+dd".
+fn format_exp(x: f64, prec: usize, sharp: bool, upper: bool) -> String {
+    let s = format!("{:.*e}", prec, x);
+    let (mantissa, exp) = split_exp(&s);
+    let mut res = mantissa.to_string();
+    if sharp && prec == 0 {
+        res.push('.');
+    }
+    res.push(if upper { 'E' } else { 'e' });
+    res.push(if exp < 0 { '-' } else { '+' });
+    res += &format!("{:02}", exp.abs());
+    res
+}
+
+fn split_exp(s: &str) -> (&str, i32) {
+    let pos = s.find('e').unwrap();
+    (&s[..pos], s[pos + 1..].parse().unwrap())
+}
+
+/// `x` in the shorter of the "%e" and "%f" forms, as C's "%g" does.
+fn format_general(x: f64, prec: usize, sharp: bool, upper: bool) -> String {
+    let p = if prec == 0 { 1 } else { prec };
+    let exp = if x == 0.0 {
+        0
+    } else {
+        split_exp(&format!("{:.*e}", p - 1, x)).1
+    };
+    let mut res = if exp < -4 || exp >= p as i32 {
+        format_exp(x, p - 1, sharp, upper)
+    } else {
+        format!("{:.*}", (p as i32 - 1 - exp) as usize, x)
+    };
+    if sharp {
+        if !res.contains('.') {
+            match res.find(['e', 'E']) {
+                Some(pos) => res.insert(pos, '.'),
+                None => res.push('.'),
+            }
+        }
+    } else if res.contains('.') {
+        let (mantissa, rest) = match res.find(['e', 'E']) {
+            Some(pos) => res.split_at(pos),
+            None => (res.as_str(), ""),
+        };
+        let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+        res = format!("{}{}", mantissa, rest);
+    }
+    res
+}
+
+/// `x` as a hexadecimal fraction and a binary exponent, without the "0x" prefix.
+fn format_hex_float(x: f64, prec: Option<usize>, sharp: bool, upper: bool) -> String {
+    let bits = x.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i64;
+    let mut mantissa = bits & ((1 << 52) - 1);
+    let (mut lead, exp) = if biased == 0 {
+        (0, if mantissa == 0 { 0 } else { -1022 })
+    } else {
+        (1, biased - 1023)
+    };
+    let frac = match prec {
+        Some(p) if p < 13 => {
+            let shift = (13 - p) * 4;
+            let rem = mantissa & ((1 << shift) - 1);
+            let half = 1 << (shift - 1);
+            mantissa >>= shift;
+            if rem > half || (rem == half && mantissa & 1 == 1) {
+                mantissa += 1;
+                if mantissa >> (p * 4) != 0 {
+                    mantissa = 0;
+                    lead += 1;
+                }
+            }
+            if p == 0 {
+                String::new()
+            } else {
+                format!("{:0w$x}", mantissa, w = p)
+            }
+        }
+        Some(p) => format!("{:013x}{}", mantissa, "0".repeat(p - 13)),
+        None => format!("{:013x}", mantissa)
+            .trim_end_matches('0')
+            .to_string(),
+    };
+    let point = if frac.is_empty() && !sharp { "" } else { "." };
+    let res = format!(
+        "{}{}{}p{}{}",
+        lead,
+        point,
+        frac,
+        if exp < 0 { '-' } else { '+' },
+        exp.abs()
+    );
+    if upper {
+        res.to_ascii_uppercase()
+    } else {
+        res
+    }
+}
+
+fn pad_number(sign: &str, prefix: &str, body: &str, spec: &Spec) -> String {
+    let len = sign.len() + prefix.len() + body.len();
+    let fill = spec.width.unwrap_or(0).saturating_sub(len);
+    if spec.minus {
+        format!("{}{}{}{}", sign, prefix, body, " ".repeat(fill))
+    } else if spec.zero {
+        format!("{}{}{}{}", sign, prefix, "0".repeat(fill), body)
+    } else {
+        format!("{}{}{}{}", " ".repeat(fill), sign, prefix, body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn format_integer() {
+        let program = r#"
+        assert "  42|42  |0042|+42| 42", format("%4d|%-4d|%04d|%+d|% d", 42, 42, 42, 42, 42)
+        assert "-0042|   -42|  00042", format("%05d|%6i|%7.5u", -42, -42, 42)
+        assert "ff|FF|0xff|0XFF|377|0377", format("%x|%X|%#x|%#X|%o|%#o", 255, 255, 255, 255, 255, 255)
+        assert "101|0b101|0B101|00000101", format("%b|%#b|%#B|%08b", 5, 5, 5, 5)
+        assert "..f85|0x..f85|-7b|..1011|..7605", format("%x|%#x|%+x|%b|%o", -123, -123, -123, -5, -123)
+        assert "..ffffffffffffffff85", sprintf("%020x", -123)
+        assert "            ..ffff85", sprintf("%20.8x", -123)
+        assert "9223372036854775807 -9223372036854775808", format("%d %d", 9223372036854775807, -9223372036854775807 - 1)
+        assert "3 -3 31 10", format("%d %d %d %d", 3.99, -3.99, "0x1f", "0b1010")
+        assert "0 0", format("%#x %#o", 0, 0)
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn format_float() {
+        let program = r#"
+        assert "3.141593|3.14|  3.1|3.1  |003.1|+3.1", format("%f|%.2f|%5.1f|%-5.1f|%05.1f|%+.1f", 3.14159265, 3.14159265, 3.14159265, 3.14159265, 3.14159265, 3.14159265)
+        assert "1.234500e+03|1.23E-05|3.", format("%e|%.2E|%#.0f", 1234.5, 0.0000123, 3.0)
+        assert "1234.5|1.23457e+06|1e-05|100000|1e+10", format("%g|%g|%g|%g|%g", 1234.5, 1234567.0, 0.00001, 100000.0, 10000000000.0)
+        assert "0x1p+0|0x1.8p+1|-0x1p-2|0X1.8P+1|0x0p+0", format("%a|%a|%a|%A|%a", 1.0, 3.0, -0.25, 3.0, 0.0)
+        assert "       Inf|      -Inf|NaN", format("%10f|%010f|%f", 1.0 / 0, -1.0 / 0, 0.0 / 0)
+        assert "2.000000", format("%f", 2)
+        assert "3.000000|5.00|1.000000e+00|7", format("%f|%.2f|%e|%g", 3, 5, 1, 7)
+        assert "5.00", "%.2f" % 5
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn format_string() {
+        let program = r#"
+        assert "abc|  abc|abc  |ab|%", format("%s|%5s|%-5s|%.2s|%%", "abc", "abc", "abc", "abc")
+        assert "[1, 2]|\"a\"|:b|nil", format("%s|%p|%p|%p", [1, 2], "a", :b, nil)
+        assert "A|B|  C", format("%c|%c|%3c", 65, "BCD", "C")
+        assert "  7|7  |  3.14", format("%*d|%-*d|%*.*f", 3, 7, 3, 7, 6, 2, 3.14159)
+        assert "  7|7  ", format("%*d|%*d", 3, 7, -3, 7)
+        assert "b a b", format("%2$s %1$s %2$s", "a", "b")
+        assert "   b|a", format("%2$*3$s|%1$s", "a", "b", 4)
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn format_named() {
+        let program = r#"
+        assert "x=001 y=2.50", format("x=%<x>03d y=%<y>.2f", x: 1, y: 2.5)
+        assert "Hello, world   !", format("Hello, %-8{name}!", {name: "world"})
+        assert "a-b", format("%{a}-%{b}", a: "a", b: :b)
+        assert "1 + 2 = 3", "%d + %d = %d" % [1, 2, 3]
+        assert "0005", "%04d" % 5
+        assert "world!", "%{w}!" % {w: "world"}
+        assert_error { format("%<z>s", x: 1) }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn format_error() {
+        let program = r#"
+        assert_error { format("%d %d", 1) }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn format_error2() {
+        let program = r#"
+        assert_error { format("%1$s %s", 1, 2) }
+        "#;
+        assert_script(program);
+    }
+}
@@ -1,6 +1,24 @@
+use crate::builtin::array;
 use crate::*;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
+/// Get the block, or return an Enumerator when no block is given.
+/// `$yielded` is the Array of the values to be yielded, returned when evaluated by an Enumerator.
+macro_rules! block_or_enum {
+    ($vm:ident, $self_val:ident, $args:ident, $name:expr, $yielded:expr) => {
+        match $args.block {
+            Some(block) if block == MethodRef::from(0) => return Ok($yielded),
+            Some(block) => block,
+            None => {
+                let id = $vm.globals.get_ident_id($name);
+                let val = Value::enumerator(&$vm.globals, id, $self_val, $args.clone());
+                return Ok(val);
+            }
+        }
+    };
+}
+
 pub fn init_hash(globals: &mut Globals) -> Value {
     let id = globals.get_ident_id("Hash");
     let class = ClassRef::from(id, globals.builtins.object);
@@ -30,9 +48,132 @@ pub fn init_hash(globals: &mut Globals) -> Value {
     globals.add_builtin_instance_method(class, "compare_by_identity", compare_by_identity);
     globals.add_builtin_instance_method(class, "sort", sort);
     globals.add_builtin_instance_method(class, "invert", invert);
-    Value::class(globals, class)
+    globals.add_builtin_instance_method(class, "[]", index);
+    globals.add_builtin_instance_method(class, "[]=", store);
+    globals.add_builtin_instance_method(class, "store", store);
+    globals.add_builtin_instance_method(class, "==", eq);
+    globals.add_builtin_instance_method(class, "default", default);
+    globals.add_builtin_instance_method(class, "default=", set_default);
+    globals.add_builtin_instance_method(class, "default_proc", default_proc);
+    globals.add_builtin_instance_method(class, "dig", dig);
+    globals.add_builtin_instance_method(class, "to_a", to_a);
+    globals.add_builtin_instance_method(class, "each_pair", each);
+    globals.add_builtin_instance_method(class, "map", map);
+    globals.add_builtin_instance_method(class, "collect", map);
+    globals.add_builtin_instance_method(class, "filter_map", filter_map);
+    globals.add_builtin_instance_method(class, "reject", reject);
+    globals.add_builtin_instance_method(class, "any?", any);
+    globals.add_builtin_instance_method(class, "all?", all);
+    globals.add_builtin_instance_method(class, "count", count);
+    globals.add_builtin_instance_method(class, "transform_keys", transform_keys);
+    globals.add_builtin_instance_method(class, "transform_keys!", transform_keys_);
+    globals.add_builtin_instance_method(class, "transform_values", transform_values);
+    globals.add_builtin_instance_method(class, "transform_values!", transform_values_);
+    globals.add_builtin_instance_method(class, "group_by", group_by);
+    globals.add_builtin_instance_method(class, "min_by", min_by);
+    globals.add_builtin_instance_method(class, "max_by", max_by);
+    globals.add_builtin_instance_method(class, "sum", sum);
+    globals.add_builtin_instance_method(class, "find", find);
+    globals.add_builtin_instance_method(class, "detect", find);
+    globals.add_builtin_instance_method(class, "sort_by", sort_by);
+    globals.add_builtin_instance_method(class, "to_h", to_h);
+    globals.add_builtin_instance_method(class, "slice", slice);
+    globals.add_builtin_instance_method(class, "except", except);
+    globals.add_builtin_instance_method(class, "update", update);
+    globals.add_builtin_instance_method(class, "merge!", update);
+    globals.add_builtin_instance_method(class, "key", key);
+    globals.add_builtin_instance_method(class, "values_at", values_at);
+    let class = Value::class(globals, class);
+    globals.add_builtin_class_method(class, "new", hash_new);
+    globals.add_builtin_class_method(class, "[]", hash_elem);
+    class
+}
+
+/// The key-value pairs of `hash`. They are copied so that blocks may modify the Hash.
+fn pairs(hash: &HashInfo) -> Vec<(Value, Value)> {
+    hash.iter().collect()
+}
+
+/// An Array of `[key, value]` Arrays.
+fn pair_array(vm: &VM, hash: &HashInfo) -> Value {
+    let ary = hash
+        .iter()
+        .map(|(k, v)| Value::array_from(&vm.globals, vec![k, v]))
+        .collect();
+    Value::array_from(&vm.globals, ary)
+}
+
+fn pair_value(vm: &VM, (k, v): (Value, Value)) -> Value {
+    Value::array_from(&vm.globals, vec![k, v])
+}
+
+// Class methods
+
+fn hash_new(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let mut hash = HashRef::from(HashMap::new());
+    if let Some(default) = args.first() {
+        if args.block.is_some() {
+            return Err(vm.error_argument(format!(
+                "wrong number of arguments (given {}, expected 0)",
+                args.len()
+            )));
+        }
+        hash.default = *default;
+    }
+    if let Some(block) = args.block {
+        hash.default_proc = Some(vm.create_proc(block)?);
+    }
+    Ok(Value::hash(&vm.globals, hash))
 }
 
+/// Hash[hash], Hash[[[key, value], ...]] or Hash[key, value, ...]
+fn hash_elem(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    let mut map = HashMap::new();
+    if args.len() == 1 {
+        if let Some(hash) = args[0].as_hash() {
+            for (k, v) in hash.iter() {
+                map.insert(HashKey(k), v);
+            }
+            return Ok(Value::hash_from(&vm.globals, map));
+        }
+        if let Some(aref) = args[0].as_array() {
+            for elem in &aref.elements {
+                let pair = match elem.as_array() {
+                    Some(pair) => pair,
+                    None => {
+                        let inspect = vm.val_inspect(*elem);
+                        return Err(vm.error_argument(format!(
+                            "wrong element type {} (expected array)",
+                            inspect
+                        )));
+                    }
+                };
+                match pair.elements.len() {
+                    1 => map.insert(HashKey(pair.elements[0]), Value::nil()),
+                    2 => map.insert(HashKey(pair.elements[0]), pair.elements[1]),
+                    n => {
+                        return Err(vm.error_argument(format!(
+                            "invalid number of elements ({} for 1..2)",
+                            n
+                        )))
+                    }
+                };
+            }
+            return Ok(Value::hash_from(&vm.globals, map));
+        }
+    }
+    if !args.len().is_multiple_of(2) {
+        return Err(vm.error_argument("odd number of arguments for Hash"));
+    }
+    for pair in args.chunks(2) {
+        map.insert(HashKey(pair[0]), pair[1]);
+    }
+    Ok(Value::hash_from(&vm.globals, map))
+}
+
+// Instance methods
+
 fn inspect(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     vm.check_args_num(args.len(), 0)?;
     let hash = self_val.as_hash().unwrap();
@@ -56,9 +197,9 @@ fn clone(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
 fn compact(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     vm.check_args_num(args.len(), 0)?;
     let hash = vm.expect_hash(self_val, "Receiver")?.dup();
-    match hash.inner_mut() {
-        HashInfo::Map(map) => map.retain(|_, &mut v| v != Value::nil()),
-        HashInfo::IdentMap(map) => map.retain(|_, &mut v| v != Value::nil()),
+    match &mut hash.inner_mut().table {
+        HashTable::Map(map) => map.retain(|_, &mut v| v != Value::nil()),
+        HashTable::IdentMap(map) => map.retain(|_, &mut v| v != Value::nil()),
     }
     Ok(Value::hash(&vm.globals, hash))
 }
@@ -159,10 +300,10 @@ fn each_key(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
 fn each(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     vm.check_args_num(args.len(), 0)?;
     let hash = self_val.as_hash().unwrap();
-    let method = vm.expect_block(args.block)?;
+    let method = block_or_enum!(vm, self_val, args, "each", pair_array(vm, &hash));
     let mut arg = Args::new2(Value::nil(), Value::nil());
 
-    for (k, v) in hash.iter() {
+    for (k, v) in pairs(&hash) {
         arg[0] = k;
         arg[1] = v;
         vm.eval_block(method, &arg)?;
@@ -171,18 +312,39 @@ fn each(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     Ok(self_val)
 }
 
-fn merge(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
-    let mut new = vm.expect_hash(self_val, "Receiver")?.dup();
-    for arg in args.iter() {
+/// Insert the pairs of `others` into `hash`.
+/// When a key already exists, the value is the result of the block called with the key and both values.
+fn merge_into(
+    vm: &mut VM,
+    mut hash: HashRef,
+    others: &[Value],
+    block: Option<MethodRef>,
+) -> Result<(), RubyError> {
+    for arg in others {
         let other = vm.expect_hash(*arg, "First arg")?;
-        for (k, v) in other.iter() {
-            new.insert(k, v);
+        for (k, v) in pairs(&other) {
+            let v = match (block, hash.get(&k)) {
+                (Some(block), Some(old)) => vm.eval_block(block, &Args::new3(None, k, *old, v))?,
+                _ => v,
+            };
+            hash.insert(k, v);
         }
     }
+    Ok(())
+}
 
+fn merge(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let new = vm.expect_hash(self_val, "Receiver")?.dup();
+    merge_into(vm, new, args, args.block)?;
     Ok(Value::hash(&vm.globals, new))
 }
 
+fn update(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let hash = vm.expect_hash(self_val, "Receiver")?;
+    merge_into(vm, hash, args, args.block)?;
+    Ok(self_val)
+}
+
 fn fetch(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     vm.check_args_range(args.len(), 1, 2)?;
     let key = args[0];
@@ -198,8 +360,8 @@ fn fetch(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
                     if args.len() == 2 {
                         args[1]
                     } else {
-                        // TODO: Should be KeyError.
-                        return Err(vm.error_argument("Key not found."));
+                        let inspect = vm.val_inspect(key);
+                        return Err(vm.error_key(format!("key not found: {}", inspect)));
                     }
                 }
             }
@@ -212,13 +374,13 @@ fn fetch(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
 fn compare_by_identity(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     vm.check_args_num(args.len(), 0)?;
     let hash = self_val.as_hash().unwrap();
-    let inner = hash.inner_mut();
-    match inner {
-        HashInfo::Map(map) => {
-            let new_map = map.into_iter().map(|(k, v)| (IdentKey(k.0), *v)).collect();
-            *inner = HashInfo::IdentMap(new_map);
+    let table = &mut hash.inner_mut().table;
+    match table {
+        HashTable::Map(map) => {
+            let new_map = map.iter_mut().map(|(k, v)| (IdentKey(k.0), *v)).collect();
+            *table = HashTable::IdentMap(new_map);
         }
-        HashInfo::IdentMap(_) => {}
+        HashTable::IdentMap(_) => {}
     };
     Ok(self_val)
 }
@@ -246,6 +408,362 @@ fn invert(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     Ok(Value::hash_from(&vm.globals, new_hash))
 }
 
+fn index(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let hash = self_val.as_hash().unwrap();
+    match hash.get(&args[0]) {
+        Some(val) => Ok(*val),
+        None => hash.default_value(vm, self_val, args[0]),
+    }
+}
+
+fn store(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 2)?;
+    let mut hash = self_val.as_hash().unwrap();
+    hash.insert(args[0], args[1]);
+    Ok(args[1])
+}
+
+fn eq(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    Ok(Value::bool(self_val.equal(args[0])))
+}
+
+fn default(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let hash = self_val.as_hash().unwrap();
+    match (hash.default_proc, args.first()) {
+        (Some(_), Some(key)) => hash.default_value(vm, self_val, *key),
+        (Some(_), None) => Ok(Value::nil()),
+        (None, _) => Ok(hash.default),
+    }
+}
+
+fn set_default(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let mut hash = self_val.as_hash().unwrap();
+    hash.default = args[0];
+    hash.default_proc = None;
+    Ok(args[0])
+}
+
+fn default_proc(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let hash = self_val.as_hash().unwrap();
+    Ok(hash.default_proc.unwrap_or_else(Value::nil))
+}
+
+fn dig(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_min(args.len(), 1)?;
+    let val = index(vm, self_val, &Args::new1(args[0]))?;
+    if args.len() == 1 || val.is_nil() {
+        return Ok(val);
+    }
+    let id = vm.globals.get_ident_id("dig");
+    match vm.get_method(val, id) {
+        Ok(method) => {
+            let mut rest = Args::new0();
+            for arg in &args[1..args.len()] {
+                rest.push(*arg);
+            }
+            vm.eval_send(method, val, &rest)
+        }
+        Err(_) => {
+            let class = vm.globals.get_class_name(val);
+            Err(vm.error_type(format!("{} does not have #dig method", class)))
+        }
+    }
+}
+
+fn to_a(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let hash = self_val.as_hash().unwrap();
+    Ok(pair_array(vm, &hash))
+}
+
+fn map(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let hash = self_val.as_hash().unwrap();
+    let block = block_or_enum!(vm, self_val, args, "map", pair_array(vm, &hash));
+    let mut res = vec![];
+    for (k, v) in pairs(&hash) {
+        res.push(vm.eval_block(block, &Args::new2(k, v))?);
+    }
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn filter_map(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let hash = self_val.as_hash().unwrap();
+    let block = block_or_enum!(vm, self_val, args, "filter_map", pair_array(vm, &hash));
+    let mut res = vec![];
+    for (k, v) in pairs(&hash) {
+        let val = vm.eval_block(block, &Args::new2(k, v))?;
+        if vm.val_to_bool(val) {
+            res.push(val);
+        }
+    }
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn reject(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let hash = self_val.as_hash().unwrap();
+    let block = block_or_enum!(vm, self_val, args, "reject", pair_array(vm, &hash));
+    let mut res = HashMap::new();
+    for (k, v) in pairs(&hash) {
+        let b = vm.eval_block(block, &Args::new2(k, v))?;
+        if !vm.val_to_bool(b) {
+            res.insert(HashKey(k), v);
+        }
+    }
+    Ok(Value::hash_from(&vm.globals, res))
+}
+
+/// The number of pairs for which the block returns true.
+/// Without a block, a pair is matched when `[key, value] == pattern`.
+fn count_pairs(vm: &mut VM, hash: &HashInfo, args: &Args) -> Result<usize, RubyError> {
+    let mut count = 0;
+    for pair in pairs(hash) {
+        let matched = match (args.first(), args.block) {
+            (Some(pattern), _) => pattern.equal(pair_value(vm, pair)),
+            (None, Some(block)) => {
+                let res = vm.eval_block(block, &Args::new2(pair.0, pair.1))?;
+                vm.val_to_bool(res)
+            }
+            (None, None) => true,
+        };
+        if matched {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn any(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let hash = self_val.as_hash().unwrap();
+    Ok(Value::bool(count_pairs(vm, &hash, args)? > 0))
+}
+
+fn all(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let hash = self_val.as_hash().unwrap();
+    Ok(Value::bool(count_pairs(vm, &hash, args)? == hash.len()))
+}
+
+fn count(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let hash = self_val.as_hash().unwrap();
+    Ok(Value::fixnum(count_pairs(vm, &hash, args)? as i64))
+}
+
+/// A Hash of the keys and values of `hash` converted by the block.
+fn transform(
+    vm: &mut VM,
+    hash: &HashInfo,
+    block: MethodRef,
+    keys: bool,
+) -> Result<HashRef, RubyError> {
+    let mut res = HashRef::from(HashMap::new());
+    for (k, v) in pairs(hash) {
+        if keys {
+            let k = vm.eval_block(block, &Args::new1(k))?;
+            res.insert(k, v);
+        } else {
+            let v = vm.eval_block(block, &Args::new1(v))?;
+            res.insert(k, v);
+        }
+    }
+    Ok(res)
+}
+
+fn transform_keys(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let hash = self_val.as_hash().unwrap();
+    let keys = Value::array_from(&vm.globals, hash.keys());
+    let block = block_or_enum!(vm, self_val, args, "transform_keys", keys);
+    let res = transform(vm, &hash, block, true)?;
+    Ok(Value::hash(&vm.globals, res))
+}
+
+fn transform_keys_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let mut hash = self_val.as_hash().unwrap();
+    let keys = Value::array_from(&vm.globals, hash.keys());
+    let block = block_or_enum!(vm, self_val, args, "transform_keys!", keys);
+    let res = transform(vm, &hash, block, true)?;
+    hash.table = res.table.clone();
+    Ok(self_val)
+}
+
+fn transform_values(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let hash = self_val.as_hash().unwrap();
+    let values = Value::array_from(&vm.globals, hash.values());
+    let block = block_or_enum!(vm, self_val, args, "transform_values", values);
+    let res = transform(vm, &hash, block, false)?;
+    Ok(Value::hash(&vm.globals, res))
+}
+
+fn transform_values_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let mut hash = self_val.as_hash().unwrap();
+    let values = Value::array_from(&vm.globals, hash.values());
+    let block = block_or_enum!(vm, self_val, args, "transform_values!", values);
+    for (k, v) in pairs(&hash) {
+        let v = vm.eval_block(block, &Args::new1(v))?;
+        hash.insert(k, v);
+    }
+    Ok(self_val)
+}
+
+fn group_by(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let hash = self_val.as_hash().unwrap();
+    let block = block_or_enum!(vm, self_val, args, "group_by", pair_array(vm, &hash));
+    let mut res = HashRef::from(HashMap::new());
+    for (k, v) in pairs(&hash) {
+        let group = vm.eval_block(block, &Args::new2(k, v))?;
+        let pair = pair_value(vm, (k, v));
+        match res.get(&group) {
+            Some(ary) => ary.as_array().unwrap().elements.push(pair),
+            None => res.insert(group, Value::array_from(&vm.globals, vec![pair])),
+        }
+    }
+    Ok(Value::hash(&vm.globals, res))
+}
+
+/// The pair for which the block returns the minimum (or maximum, by `ord`) value.
+fn min_max_by(vm: &mut VM, self_val: Value, args: &Args, name: &str, ord: Ordering) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let hash = self_val.as_hash().unwrap();
+    let block = block_or_enum!(vm, self_val, args, name, pair_array(vm, &hash));
+    let mut res: Option<(Value, (Value, Value))> = None;
+    for (k, v) in pairs(&hash) {
+        let key = vm.eval_block(block, &Args::new2(k, v))?;
+        match res {
+            Some((min, _)) if array::compare(vm, key, min, None)? != ord => {}
+            _ => res = Some((key, (k, v))),
+        }
+    }
+    match res {
+        Some((_, pair)) => Ok(pair_value(vm, pair)),
+        None => Ok(Value::nil()),
+    }
+}
+
+fn min_by(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    min_max_by(vm, self_val, args, "min_by", Ordering::Less)
+}
+
+fn max_by(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    min_max_by(vm, self_val, args, "max_by", Ordering::Greater)
+}
+
+fn sum(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let hash = self_val.as_hash().unwrap();
+    let ary = pair_array(vm, &hash);
+    let id = vm.globals.get_ident_id("sum");
+    let method = vm.get_method(ary, id)?;
+    vm.eval_send(method, ary, args)
+}
+
+fn find(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let hash = self_val.as_hash().unwrap();
+    let block = block_or_enum!(vm, self_val, args, "find", pair_array(vm, &hash));
+    for (k, v) in pairs(&hash) {
+        let res = vm.eval_block(block, &Args::new2(k, v))?;
+        if vm.val_to_bool(res) {
+            return Ok(pair_value(vm, (k, v)));
+        }
+    }
+    Ok(Value::nil())
+}
+
+fn sort_by(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let hash = self_val.as_hash().unwrap();
+    let block = block_or_enum!(vm, self_val, args, "sort_by", pair_array(vm, &hash));
+    let mut keyed = vec![];
+    for (k, v) in pairs(&hash) {
+        let key = vm.eval_block(block, &Args::new2(k, v))?;
+        keyed.push((key, pair_value(vm, (k, v))));
+    }
+    let sorted = array::merge_sort(vm, &keyed, &mut |vm, lhs, rhs| {
+        Ok(array::compare(vm, lhs.0, rhs.0, None)? == Ordering::Greater)
+    })?;
+    let res = sorted.into_iter().map(|(_, pair)| pair).collect();
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn to_h(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let hash = self_val.as_hash().unwrap();
+    let block = match args.block {
+        Some(block) => block,
+        None => return Ok(self_val),
+    };
+    let mut res = HashMap::new();
+    for (k, v) in pairs(&hash) {
+        let pair = vm.eval_block(block, &Args::new2(k, v))?;
+        let pair = match pair.as_array() {
+            Some(pair) => pair,
+            None => {
+                let class = vm.globals.get_class_name(pair);
+                return Err(vm.error_type(format!("wrong element type {} (expected array)", class)));
+            }
+        };
+        if pair.elements.len() != 2 {
+            return Err(vm.error_argument(format!(
+                "element has wrong array length (expected 2, was {})",
+                pair.elements.len()
+            )));
+        }
+        res.insert(HashKey(pair.elements[0]), pair.elements[1]);
+    }
+    Ok(Value::hash_from(&vm.globals, res))
+}
+
+fn slice(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let hash = self_val.as_hash().unwrap();
+    let mut res = HashRef::from(HashMap::new());
+    for key in args.iter() {
+        if let Some(val) = hash.get(key) {
+            res.insert(*key, *val);
+        }
+    }
+    Ok(Value::hash(&vm.globals, res))
+}
+
+fn except(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let mut res = self_val.as_hash().unwrap().dup();
+    for key in args.iter() {
+        res.remove(*key);
+    }
+    Ok(Value::hash(&vm.globals, res))
+}
+
+fn key(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let hash = self_val.as_hash().unwrap();
+    for (k, v) in pairs(&hash) {
+        if vm.eval_eq(args[0], v)? {
+            return Ok(k);
+        }
+    }
+    Ok(Value::nil())
+}
+
+fn values_at(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let mut res = vec![];
+    for key in args.iter() {
+        res.push(index(vm, self_val, &Args::new1(*key))?);
+    }
+    Ok(Value::array_from(&vm.globals, res))
+}
+
 #[cfg(test)]
 #[allow(unused_imports, dead_code)]
 mod test {
@@ -401,9 +919,116 @@ mod test {
                 "#{key} not exist"                  #  warning: block supersedes default value argument
             }        
             assert("two not exist", res)
-            #h.default = "default"
+            h.default = "default"
             assert_error { h.fetch(:two) }          # エラー key not found (KeyError)
         "##;
         assert_script(program);
     }
+
+    #[test]
+    fn hash_default() {
+        let program = r#"
+            h = Hash.new(0)
+            "abca".each_char { |c| h[c] += 1 }
+            assert({"a" => 2, "b" => 1, "c" => 1}, h)
+            assert(0, h["z"])
+            assert(0, h.default)
+            assert(nil, h.default_proc)
+            h.default = 5
+            assert(5, h[:none])
+            assert(false, h.has_key?(:none))
+            g = Hash.new { |hash, key| hash[key] = [] }
+            g[:x] << 1
+            g[:x] << 2
+            assert({x: [1, 2]}, g)
+            assert([], g.default(:y))
+            assert(nil, g.default)
+            assert(Proc, g.default_proc.class)
+            assert([[], [1, 2]], g.values_at(:z, :x))
+            assert(3, g.store(:w, 3))
+            assert(3, g[:w])
+            assert_error { Hash.new(0) { |h, k| k } }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn hash_elem_dig() {
+        let program = r#"
+            assert({1 => 2, 3 => 4}, Hash[1, 2, 3, 4])
+            assert({1 => 2, 3 => nil}, Hash[[[1, 2], [3]]])
+            assert({a: 1}, Hash[{a: 1}])
+            h = {a: {b: [10, 20]}, c: nil}
+            assert(20, h.dig(:a, :b, 1))
+            assert(nil, h.dig(:c, :d))
+            assert([[:a, 1]], {a: 1}.to_a)
+            assert(true, {a: 1, b: 2} == {b: 2, a: 1})
+            assert(false, {a: 1}.==({a: 2}))
+            assert_error { h.dig(:a, :b, 0, 1) }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn hash_iterators() {
+        let program = r#"
+            h = {a: 1, b: 2, c: 3}
+            a = []
+            h.each_pair { |k, v| a << k }
+            assert([:a, :b, :c], a.sort)
+            assert([2, 4, 6], h.map { |k, v| v * 2 }.sort)
+            assert([2, 4, 6], h.each.map { |k, v| v * 2 }.sort)
+            assert([:b, :c], h.filter_map { |k, v| k if v > 1 }.sort)
+            assert({a: 1, c: 3}, h.reject { |k, v| v == 2 })
+            assert(true, h.any? { |k, v| v > 2 })
+            assert(false, h.any? { |k, v| v > 3 })
+            assert(true, h.all? { |k, v| v > 0 })
+            assert(true, h.any?([:a, 1]))
+            assert(2, h.count { |k, v| v % 2 == 1 })
+            assert(3, h.count)
+            assert([:b, 2], h.find { |k, v| v == 2 })
+            assert(nil, h.detect { |k, v| v == 5 })
+            assert([:a, 1], h.min_by { |k, v| v })
+            assert([:c, 3], h.max_by { |k, v| v })
+            assert(6, h.sum { |k, v| v })
+            assert([[:c, 3], [:b, 2], [:a, 1]], h.sort_by { |k, v| -v })
+            assert({true => [[:a, 1], [:c, 3]], false => [[:b, 2]]}, h.group_by { |k, v| v % 2 == 1 }.transform_values { |v| v.sort })
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn hash_transform() {
+        let program = r#"
+            h = {a: 1, b: 2}
+            assert({a: 10, b: 20}, h.transform_values { |v| v * 10 })
+            assert({"a" => 1, "b" => 2}, h.transform_keys { |k| k.to_s })
+            assert({1 => :a, 2 => :b}, h.to_h { |k, v| [v, k] })
+            assert(h, h.to_h)
+            h.transform_values! { |v| -v }
+            assert({a: -1, b: -2}, h)
+            h.transform_keys! { |k| k.to_s }
+            assert({"a" => -1, "b" => -2}, h)
+            assert_error { h.to_h { |k, v| k } }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn hash_subset_update() {
+        let program = r#"
+            h = {a: 1, b: 2, c: 3}
+            assert({a: 1, c: 3}, h.slice(:a, :c, :z))
+            assert({b: 2}, h.except(:a, :c))
+            assert(:b, h.key(2))
+            assert(nil, h.key(5))
+            assert([1, nil], h.values_at(:a, :z))
+            assert({a: 1, b: 12, c: 3}, h.merge({b: 10}) { |k, old, new| old + new })
+            h.update({c: 4, d: 5}) { |k, old, new| old * new }
+            assert({a: 1, b: 2, c: 12, d: 5}, h)
+            h.merge!({a: 0})
+            assert(0, h[:a])
+        "#;
+        assert_script(program);
+    }
 }
@@ -0,0 +1,793 @@
+use crate::builtin::format;
+use crate::*;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+#[derive(Debug)]
+pub enum Stream {
+    Stdin,
+    Stdout,
+    Stderr,
+    /// ARGF: the files named in ARGV one after another, or stdin when ARGV is empty.
+    Argf(Option<BufReader<File>>),
+    File(FileStream),
+    Closed,
+}
+
+/// A file opened by `File.open`.
+#[derive(Debug)]
+pub struct FileStream {
+    reader: BufReader<File>,
+    readable: bool,
+    writable: bool,
+}
+
+impl FileStream {
+    pub fn file(&self) -> &File {
+        self.reader.get_ref()
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        // Discard the bytes read ahead so that the write starts at the current position.
+        let pos = self.reader.stream_position()?;
+        self.reader.seek(SeekFrom::Start(pos))?;
+        self.reader.get_mut().write_all(bytes)
+    }
+}
+
+#[derive(Debug)]
+pub struct IOInfo {
+    stream: Stream,
+    /// The path of a File.
+    pub path: Option<String>,
+    /// Flush after every write.
+    pub sync: bool,
+    /// The number of lines read by `gets`.
+    pub lineno: i64,
+    /// Opened with the `b` flag, so that strings read are ASCII-8BIT.
+    pub binmode: bool,
+}
+
+impl IOInfo {
+    pub fn new(stream: Stream) -> Self {
+        let sync = matches!(stream, Stream::Stderr);
+        IOInfo {
+            stream,
+            path: None,
+            sync,
+            lineno: 0,
+            binmode: false,
+        }
+    }
+
+    pub fn new_file(file: File, path: String, readable: bool, writable: bool) -> Self {
+        let stream = Stream::File(FileStream {
+            reader: BufReader::new(file),
+            readable,
+            writable,
+        });
+        IOInfo {
+            path: Some(path),
+            ..IOInfo::new(stream)
+        }
+    }
+
+    /// The encoding of strings read from the stream.
+    fn encoding(&self) -> Encoding {
+        if self.binmode {
+            Encoding::ASCII8BIT
+        } else {
+            Encoding::UTF8
+        }
+    }
+
+    fn fileno(&self) -> Option<i64> {
+        match &self.stream {
+            Stream::Stdin => Some(0),
+            Stream::Stdout => Some(1),
+            Stream::Stderr => Some(2),
+            #[cfg(unix)]
+            Stream::File(stream) => {
+                use std::os::unix::io::AsRawFd;
+                Some(stream.file().as_raw_fd() as i64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        matches!(self.stream, Stream::Closed)
+    }
+
+    fn inspect(&self) -> String {
+        match (&self.stream, &self.path) {
+            (Stream::Stdin, _) => "#<IO:<STDIN>>".to_string(),
+            (Stream::Stdout, _) => "#<IO:<STDOUT>>".to_string(),
+            (Stream::Stderr, _) => "#<IO:<STDERR>>".to_string(),
+            (Stream::Argf(_), _) => "ARGF".to_string(),
+            (Stream::Closed, Some(path)) => format!("#<File:{} (closed)>", path),
+            (Stream::Closed, None) => "#<IO:(closed)>".to_string(),
+            (_, path) => format!("#<File:{}>", path.as_deref().unwrap_or("")),
+        }
+    }
+
+    pub fn write(&mut self, vm: &VM, bytes: &[u8]) -> Result<(), RubyError> {
+        // Valid UTF-8 goes through print! so that the output is captured in tests.
+        let res = match (&mut self.stream, std::str::from_utf8(bytes)) {
+            (Stream::Stdout, Ok(s)) => {
+                print!("{}", s);
+                Ok(())
+            }
+            (Stream::Stdout, Err(_)) => std::io::stdout().write_all(bytes),
+            (Stream::Stderr, Ok(s)) => {
+                eprint!("{}", s);
+                Ok(())
+            }
+            (Stream::Stderr, Err(_)) => std::io::stderr().write_all(bytes),
+            (Stream::File(stream), _) if stream.writable => stream.write(bytes),
+            (Stream::Closed, _) => return Err(vm.error_io("closed stream")),
+            _ => return Err(vm.error_io("not opened for writing")),
+        };
+        res.map_err(|err| vm.error_io(err.to_string()))?;
+        if self.sync {
+            self.flush(vm)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self, vm: &VM) -> Result<(), RubyError> {
+        let res = match &mut self.stream {
+            Stream::Stdout => std::io::stdout().flush(),
+            Stream::Stderr => std::io::stderr().flush(),
+            Stream::File(stream) => stream.reader.get_mut().flush(),
+            Stream::Closed => return Err(vm.error_io("closed stream")),
+            _ => Ok(()),
+        };
+        res.map_err(|err| vm.error_io(err.to_string()))
+    }
+
+    /// The opened file. Seeking is illegal on the other streams.
+    pub fn file_stream(&mut self, vm: &VM) -> Result<&mut FileStream, RubyError> {
+        match &mut self.stream {
+            Stream::File(stream) => Ok(stream),
+            Stream::Closed => Err(vm.error_io("closed stream")),
+            _ => Err(vm.error_errno("ESPIPE", "Illegal seek")),
+        }
+    }
+
+    pub fn seek(&mut self, vm: &VM, pos: SeekFrom) -> Result<u64, RubyError> {
+        let stream = self.file_stream(vm)?;
+        stream
+            .reader
+            .seek(pos)
+            .map_err(|err| vm.error_io(err.to_string()))
+    }
+
+    pub fn close(&mut self) {
+        self.stream = Stream::Closed;
+    }
+
+    /// Call `f` with the reader of the stream.
+    /// ARGF moves on to the next file in ARGV when the current one is exhausted.
+    fn read_with<T>(
+        &mut self,
+        vm: &mut VM,
+        f: impl FnOnce(&mut dyn BufRead) -> std::io::Result<T>,
+    ) -> Result<T, RubyError> {
+        let res = match &mut self.stream {
+            Stream::Stdin => f(&mut std::io::stdin().lock()),
+            Stream::Argf(file) => {
+                loop {
+                    let exhausted = match file {
+                        Some(reader) => match reader.fill_buf() {
+                            Ok(buf) => buf.is_empty(),
+                            Err(err) => return Err(vm.error_io(err.to_string())),
+                        },
+                        None => true,
+                    };
+                    if !exhausted {
+                        break;
+                    }
+                    match shift_argv(vm) {
+                        Some(path) => match File::open(&path) {
+                            Ok(f) => *file = Some(BufReader::new(f)),
+                            Err(err) => return Err(error_errno(vm, &err, &path)),
+                        },
+                        None => break,
+                    }
+                }
+                match file {
+                    Some(reader) => f(reader),
+                    None => f(&mut std::io::stdin().lock()),
+                }
+            }
+            Stream::File(stream) if stream.readable => f(&mut stream.reader),
+            Stream::Closed => return Err(vm.error_io("closed stream")),
+            _ => return Err(vm.error_io("not opened for reading")),
+        };
+        res.map_err(|err| vm.error_io(err.to_string()))
+    }
+
+    /// Read a line terminated by `sep`, or the rest of the stream when `sep` is None.
+    /// Returns None at the end of the stream.
+    fn read_line(&mut self, vm: &mut VM, sep: Option<&[u8]>) -> Result<Option<Vec<u8>>, RubyError> {
+        let line = match sep {
+            None => self.read_all(vm)?,
+            Some(sep) => self.read_with(vm, |reader| {
+                let mut line = vec![];
+                let last = sep[sep.len() - 1];
+                while reader.read_until(last, &mut line)? != 0 && !line.ends_with(sep) {}
+                Ok(line)
+            })?,
+        };
+        if line.is_empty() {
+            return Ok(None);
+        }
+        self.lineno += 1;
+        Ok(Some(line))
+    }
+
+    fn read_all(&mut self, vm: &mut VM) -> Result<Vec<u8>, RubyError> {
+        let mut buf = vec![];
+        loop {
+            self.read_with(vm, |reader| reader.read_to_end(&mut buf))?;
+            // ARGF goes on with the rest of the files.
+            if !matches!(self.stream, Stream::Argf(_)) || argv_is_empty(vm) {
+                return Ok(buf);
+            }
+        }
+    }
+
+    /// Read at most `len` bytes.
+    fn read_bytes(&mut self, vm: &mut VM, len: usize) -> Result<Vec<u8>, RubyError> {
+        let mut buf = vec![];
+        while buf.len() < len {
+            let rest = (len - buf.len()) as u64;
+            let n = self.read_with(vm, |reader| Read::take(reader, rest).read_to_end(&mut buf))?;
+            if n == 0 {
+                break;
+            }
+        }
+        Ok(buf)
+    }
+
+    /// Read a character. Returns None at the end of the stream.
+    fn read_char(&mut self, vm: &mut VM) -> Result<Option<Vec<u8>>, RubyError> {
+        self.read_with(vm, |reader| {
+            let len = match reader.fill_buf()?.first() {
+                None => return Ok(None),
+                Some(0xc0..=0xdf) => 2,
+                Some(0xe0..=0xef) => 3,
+                Some(0xf0..=0xf7) => 4,
+                Some(_) => 1,
+            };
+            let mut buf = vec![];
+            Read::take(reader, len).read_to_end(&mut buf)?;
+            Ok(Some(buf))
+        })
+    }
+
+    fn is_eof(&mut self, vm: &mut VM) -> Result<bool, RubyError> {
+        self.read_with(vm, |reader| Ok(reader.fill_buf()?.is_empty()))
+    }
+}
+
+pub type IORef = Ref<IOInfo>;
+
+pub fn init_io(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("IO");
+    let class = ClassRef::from(id, globals.builtins.object);
+    let obj = Value::class(globals, class);
+    globals.builtins.io = obj;
+    for (i, name) in ["SEEK_SET", "SEEK_CUR", "SEEK_END"].iter().enumerate() {
+        let id = globals.get_ident_id(*name);
+        obj.set_var(globals, id, Value::fixnum(i as i64));
+    }
+    globals.add_builtin_instance_method(class, "puts", puts);
+    globals.add_builtin_instance_method(class, "print", print);
+    globals.add_builtin_instance_method(class, "write", write);
+    globals.add_builtin_instance_method(class, "printf", printf);
+    globals.add_builtin_instance_method(class, "<<", shl);
+    globals.add_builtin_instance_method(class, "flush", flush);
+    globals.add_builtin_instance_method(class, "sync", sync);
+    globals.add_builtin_instance_method(class, "sync=", set_sync);
+    globals.add_builtin_instance_method(class, "fileno", fileno);
+    globals.add_builtin_instance_method(class, "gets", gets);
+    globals.add_builtin_instance_method(class, "readline", readline);
+    globals.add_builtin_instance_method(class, "readlines", readlines);
+    globals.add_builtin_instance_method(class, "each_line", each_line);
+    globals.add_builtin_instance_method(class, "read", read);
+    globals.add_builtin_instance_method(class, "getc", getc);
+    globals.add_builtin_instance_method(class, "eof?", eof);
+    globals.add_builtin_instance_method(class, "eof", eof);
+    globals.add_builtin_instance_method(class, "lineno", lineno);
+    globals.add_builtin_instance_method(class, "seek", seek);
+    globals.add_builtin_instance_method(class, "pos", pos);
+    globals.add_builtin_instance_method(class, "tell", pos);
+    globals.add_builtin_instance_method(class, "pos=", set_pos);
+    globals.add_builtin_instance_method(class, "rewind", rewind);
+    globals.add_builtin_instance_method(class, "close", close);
+    globals.add_builtin_instance_method(class, "closed?", closed);
+    globals.add_builtin_instance_method(class, "inspect", inspect);
+    let streams = vec![
+        ("STDIN", "$stdin", Stream::Stdin),
+        ("STDOUT", "$stdout", Stream::Stdout),
+        ("STDERR", "$stderr", Stream::Stderr),
+        ("ARGF", "$<", Stream::Argf(None)),
+    ];
+    for (name, var, stream) in streams {
+        let val = Value::io(globals, IORef::new(IOInfo::new(stream)));
+        let id = globals.get_ident_id(name);
+        globals.builtins.object.set_var(globals, id, val);
+        let id = globals.get_ident_id(var);
+        globals.global_var.insert(id, val);
+    }
+    obj
+}
+
+// Utils
+
+fn shift_argv(vm: &mut VM) -> Option<String> {
+    let id = vm.globals.get_ident_id("ARGV");
+    let mut argv = vm.globals.builtins.object.get_var(id)?.as_array()?;
+    if argv.elements.is_empty() {
+        return None;
+    }
+    let path = argv.elements.remove(0);
+    Some(vm.val_to_s(path))
+}
+
+fn argv_is_empty(vm: &mut VM) -> bool {
+    let id = vm.globals.get_ident_id("ARGV");
+    match vm.globals.builtins.object.get_var(id) {
+        Some(argv) => match argv.as_array() {
+            Some(argv) => argv.elements.is_empty(),
+            None => true,
+        },
+        None => true,
+    }
+}
+
+/// The Errno::EXXX error for `err`, which is raised by an operation on `path`.
+pub fn error_errno(vm: &VM, err: &std::io::Error, path: &str) -> RubyError {
+    use std::io::ErrorKind::*;
+    let (name, msg) = match err.kind() {
+        NotFound => ("ENOENT", "No such file or directory"),
+        PermissionDenied => ("EACCES", "Permission denied"),
+        AlreadyExists => ("EEXIST", "File exists"),
+        IsADirectory => ("EISDIR", "Is a directory"),
+        NotADirectory => ("ENOTDIR", "Not a directory"),
+        DirectoryNotEmpty => ("ENOTEMPTY", "Directory not empty"),
+        _ => return vm.error_io(format!("{} - {}", err, path)),
+    };
+    vm.error_errno(name, format!("{} - {}", msg, path))
+}
+
+/// The current value of `$stdout`.
+pub fn stdout(vm: &mut VM) -> Value {
+    let id = vm.globals.get_ident_id("$stdout");
+    vm.get_global_var(id)
+}
+
+/// ARGF, which `Kernel#gets` reads from.
+pub fn argf(vm: &mut VM) -> Value {
+    let id = vm.globals.get_ident_id("ARGF");
+    vm.globals.builtins.object.get_var(id).unwrap()
+}
+
+/// Write `bytes` to `out`, which is an IO or any object with a `write` method.
+pub fn write_to(vm: &mut VM, out: Value, bytes: Vec<u8>) -> Result<(), RubyError> {
+    match out.as_io() {
+        Some(mut io) => io.write(vm, &bytes),
+        None => {
+            let string = Value::rstring(&vm.globals, RString::with_encoding(bytes, Encoding::UTF8));
+            let id = vm.globals.get_ident_id("write");
+            let method = vm.get_method(out, id)?;
+            vm.eval_send(method, out, &Args::new1(string))?;
+            Ok(())
+        }
+    }
+}
+
+fn to_bytes(vm: &mut VM, val: Value) -> Vec<u8> {
+    match val.as_bytes() {
+        Some(bytes) => bytes.to_vec(),
+        None => vm.val_to_s(val).into_bytes(),
+    }
+}
+
+/// The output of `puts`. Arrays are flattened and a newline is added to each line without one.
+pub fn puts_bytes(vm: &mut VM, args: &[Value]) -> Vec<u8> {
+    fn push_line(vm: &mut VM, val: Value, buf: &mut Vec<u8>) {
+        match val.as_array() {
+            Some(aref) => {
+                for val in &aref.elements {
+                    push_line(vm, *val, buf);
+                }
+            }
+            None => {
+                let line = to_bytes(vm, val);
+                let newline = !line.ends_with(b"\n");
+                buf.extend(line);
+                if newline {
+                    buf.push(b'\n');
+                }
+            }
+        }
+    }
+    let mut buf = vec![];
+    if args.is_empty() {
+        buf.push(b'\n');
+    }
+    for arg in args {
+        push_line(vm, *arg, &mut buf);
+    }
+    buf
+}
+
+/// The output of `print`. Without arguments, `$_` is printed.
+pub fn print_bytes(vm: &mut VM, args: &[Value]) -> Vec<u8> {
+    let mut buf = vec![];
+    if args.is_empty() {
+        let id = vm.globals.get_ident_id("$_");
+        let last_line = vm.get_global_var(id);
+        if !last_line.is_nil() {
+            buf.extend(to_bytes(vm, last_line));
+        }
+    }
+    for arg in args {
+        buf.extend(to_bytes(vm, *arg));
+    }
+    buf
+}
+
+/// The separator and the `chomp:` option of `gets`, `readline`, `readlines` and `each_line`.
+/// The separator is None when the whole stream is read as a line.
+fn line_args(vm: &mut VM, args: &Args) -> Result<(Option<Vec<u8>>, bool), RubyError> {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let sep = match args.first() {
+        None => Some(b"\n".to_vec()),
+        Some(sep) if sep.is_nil() => None,
+        Some(sep) => match sep.as_bytes() {
+            // Paragraph mode.
+            Some(b"") => Some(b"\n\n".to_vec()),
+            Some(bytes) => Some(bytes.to_vec()),
+            None => {
+                let class = vm.globals.get_class_name(*sep);
+                return Err(
+                    vm.error_type(format!("no implicit conversion of {} into String", class))
+                );
+            }
+        },
+    };
+    let chomp = match args.kw_arg {
+        Some(opt) => {
+            let key = Value::symbol(vm.globals.get_ident_id("chomp"));
+            match opt.as_hash().unwrap().get(&key) {
+                Some(val) => vm.val_to_bool(*val),
+                None => false,
+            }
+        }
+        None => false,
+    };
+    Ok((sep, chomp))
+}
+
+fn next_line(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let mut io = self_val.as_io().unwrap();
+    let (sep, chomp) = line_args(vm, args)?;
+    let line = match io.read_line(vm, sep.as_deref())? {
+        Some(mut line) => {
+            if let Some(sep) = &sep {
+                if chomp && line.ends_with(sep) {
+                    line.truncate(line.len() - sep.len());
+                }
+            }
+            Value::rstring(&vm.globals, RString::with_encoding(line, io.encoding()))
+        }
+        None => Value::nil(),
+    };
+    let id = vm.globals.get_ident_id("$_");
+    vm.set_global_var(id, line);
+    Ok(line)
+}
+
+fn all_lines(vm: &mut VM, self_val: Value, args: &Args) -> Result<Vec<Value>, RubyError> {
+    let mut lines = vec![];
+    loop {
+        let line = next_line(vm, self_val, args)?;
+        if line.is_nil() {
+            return Ok(lines);
+        }
+        lines.push(line);
+    }
+}
+
+// Instance methods
+
+fn puts(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let bytes = puts_bytes(vm, args);
+    write_to(vm, self_val, bytes)?;
+    Ok(Value::nil())
+}
+
+fn print(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let bytes = print_bytes(vm, args);
+    write_to(vm, self_val, bytes)?;
+    Ok(Value::nil())
+}
+
+fn write(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let mut bytes = vec![];
+    for arg in args.iter() {
+        bytes.extend(to_bytes(vm, *arg));
+    }
+    let len = bytes.len();
+    write_to(vm, self_val, bytes)?;
+    Ok(Value::fixnum(len as i64))
+}
+
+fn printf(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    if args.is_empty() {
+        return Ok(Value::nil());
+    }
+    let res = format::format_args(vm, args)?;
+    write_to(vm, self_val, res.into_bytes())?;
+    Ok(Value::nil())
+}
+
+fn shl(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let bytes = to_bytes(vm, args[0]);
+    write_to(vm, self_val, bytes)?;
+    Ok(self_val)
+}
+
+fn flush(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let mut io = self_val.as_io().unwrap();
+    io.flush(vm)?;
+    Ok(self_val)
+}
+
+fn sync(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let io = self_val.as_io().unwrap();
+    Ok(Value::bool(io.sync))
+}
+
+fn set_sync(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let mut io = self_val.as_io().unwrap();
+    io.sync = vm.val_to_bool(args[0]);
+    Ok(args[0])
+}
+
+fn fileno(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let io = self_val.as_io().unwrap();
+    match io.fileno() {
+        Some(fd) => Ok(Value::fixnum(fd)),
+        None => Err(vm.error_argument("no stream")),
+    }
+}
+
+pub fn gets(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    next_line(vm, self_val, args)
+}
+
+pub fn readline(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let line = next_line(vm, self_val, args)?;
+    if line.is_nil() {
+        return Err(vm.error_eof());
+    }
+    Ok(line)
+}
+
+pub fn readlines(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let lines = all_lines(vm, self_val, args)?;
+    Ok(Value::array_from(&vm.globals, lines))
+}
+
+pub fn each_line(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let block = match args.block {
+        Some(block) if block == MethodRef::from(0) => return readlines(vm, self_val, args),
+        Some(block) => block,
+        None => {
+            let id = vm.globals.get_ident_id("each_line");
+            let val = Value::enumerator(&vm.globals, id, self_val, args.clone());
+            return Ok(val);
+        }
+    };
+    loop {
+        let line = next_line(vm, self_val, args)?;
+        if line.is_nil() {
+            return Ok(self_val);
+        }
+        vm.eval_block(block, &Args::new1(line))?;
+    }
+}
+
+fn read(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let mut io = self_val.as_io().unwrap();
+    match args.first() {
+        Some(len) if !len.is_nil() => {
+            let len = len.expect_integer(vm, "Length")?;
+            if len < 0 {
+                return Err(vm.error_argument(format!("negative length {} given", len)));
+            }
+            let bytes = io.read_bytes(vm, len as usize)?;
+            if bytes.is_empty() && len > 0 {
+                return Ok(Value::nil());
+            }
+            let string = RString::with_encoding(bytes, Encoding::ASCII8BIT);
+            Ok(Value::rstring(&vm.globals, string))
+        }
+        _ => {
+            let bytes = io.read_all(vm)?;
+            let string = RString::with_encoding(bytes, io.encoding());
+            Ok(Value::rstring(&vm.globals, string))
+        }
+    }
+}
+
+fn getc(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let mut io = self_val.as_io().unwrap();
+    match io.read_char(vm)? {
+        Some(bytes) => {
+            let string = RString::with_encoding(bytes, io.encoding());
+            Ok(Value::rstring(&vm.globals, string))
+        }
+        None => Ok(Value::nil()),
+    }
+}
+
+fn eof(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let mut io = self_val.as_io().unwrap();
+    Ok(Value::bool(io.is_eof(vm)?))
+}
+
+fn lineno(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let io = self_val.as_io().unwrap();
+    Ok(Value::fixnum(io.lineno))
+}
+
+fn seek(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let mut io = self_val.as_io().unwrap();
+    let offset = args[0].expect_integer(vm, "Offset")?;
+    let whence = match args.get(1) {
+        Some(whence) => whence.expect_integer(vm, "Whence")?,
+        None => 0,
+    };
+    let pos = match whence {
+        0 if offset >= 0 => SeekFrom::Start(offset as u64),
+        0 => return Err(vm.error_errno("EINVAL", "Invalid argument")),
+        1 => SeekFrom::Current(offset),
+        2 => SeekFrom::End(offset),
+        _ => return Err(vm.error_argument(format!("unknown whence: {}", whence))),
+    };
+    io.seek(vm, pos)?;
+    Ok(Value::fixnum(0))
+}
+
+fn pos(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let mut io = self_val.as_io().unwrap();
+    let stream = io.file_stream(vm)?;
+    match stream.reader.stream_position() {
+        Ok(pos) => Ok(Value::fixnum(pos as i64)),
+        Err(err) => Err(vm.error_io(err.to_string())),
+    }
+}
+
+fn set_pos(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let mut io = self_val.as_io().unwrap();
+    let pos = args[0].expect_integer(vm, "Position")?;
+    if pos < 0 {
+        return Err(vm.error_errno("EINVAL", "Invalid argument"));
+    }
+    io.seek(vm, SeekFrom::Start(pos as u64))?;
+    Ok(args[0])
+}
+
+fn rewind(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let mut io = self_val.as_io().unwrap();
+    io.seek(vm, SeekFrom::Start(0))?;
+    io.lineno = 0;
+    Ok(Value::fixnum(0))
+}
+
+fn close(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let mut io = self_val.as_io().unwrap();
+    io.close();
+    Ok(Value::nil())
+}
+
+fn closed(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let io = self_val.as_io().unwrap();
+    Ok(Value::bool(io.is_closed()))
+}
+
+fn inspect(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let io = self_val.as_io().unwrap();
+    Ok(Value::string(&vm.globals, io.inspect()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn io_write() {
+        let program = r##"
+        class Capture
+          attr_reader :buf
+          def initialize
+            @buf = ""
+          end
+          def write(s)
+            @buf += s
+            s.size
+          end
+        end
+        assert STDOUT, $stdout
+        assert STDIN, $stdin
+        assert IO, STDERR.class
+        assert [0, 1, 2], [STDIN.fileno, STDOUT.fileno, STDERR.fileno]
+        assert "#<IO:<STDOUT>>", STDOUT.inspect
+        assert true, STDERR.sync
+        STDOUT.sync = true
+        assert true, STDOUT.sync
+        assert 5, STDOUT.write("abc", 12)
+        assert STDOUT, STDOUT << "a" << 1
+        assert STDOUT, STDOUT.flush
+        c = Capture.new
+        $stdout = c
+        puts "a", [1, [nil, :b]]
+        puts "c\n"
+        print "d", 2
+        p "e"
+        printf("%03d", 7)
+        $stdout = STDOUT
+        assert "a\n1\n\nb\nc\nd2\"e\"\n007", c.buf
+        "##;
+        assert_script(program);
+    }
+
+    #[test]
+    fn io_argf() {
+        let program = r#"
+        File.write("argf_test1.txt", "one\ntwo\n")
+        File.write("argf_test2.txt", "thrée\nfour\nfive")
+        ARGV = ["argf_test1.txt", "argf_test2.txt"]
+        assert "one\n", gets
+        assert "one\n", $_
+        assert 1, ARGF.lineno
+        assert "two", readline(chomp: true)
+        assert "t", $<.getc
+        assert "hr", ARGF.read(2)
+        assert "é", ARGF.getc
+        assert false, ARGF.eof?
+        a = []
+        ARGF.each_line { |line| a << line }
+        assert ["e\n", "four\n", "five"], a
+        assert true, ARGF.eof?
+        assert nil, gets
+        assert [], readlines
+        assert "", ARGF.read
+        assert nil, ARGF.read(1)
+        assert_error { readline }
+        "#;
+        assert_script(program);
+    }
+}
@@ -1,3 +1,8 @@
+use crate::builtin::fileutils;
+use crate::builtin::format;
+use crate::builtin::io;
+use crate::builtin::random;
+use crate::builtin::time;
 use crate::loader::*;
 use crate::*;
 use rand;
@@ -9,13 +14,23 @@ pub fn init(globals: &mut Globals) -> Value {
     globals.add_builtin_instance_method(kernel_class, "puts", puts);
     globals.add_builtin_instance_method(kernel_class, "p", p);
     globals.add_builtin_instance_method(kernel_class, "print", print);
+    globals.add_builtin_instance_method(kernel_class, "format", format);
+    globals.add_builtin_instance_method(kernel_class, "sprintf", format);
+    globals.add_builtin_instance_method(kernel_class, "printf", printf);
+    globals.add_builtin_instance_method(kernel_class, "gets", gets);
+    globals.add_builtin_instance_method(kernel_class, "readline", readline);
+    globals.add_builtin_instance_method(kernel_class, "readlines", readlines);
     globals.add_builtin_instance_method(kernel_class, "assert", assert);
     globals.add_builtin_instance_method(kernel_class, "assert_error", assert_error);
     globals.add_builtin_instance_method(kernel_class, "require", require);
     globals.add_builtin_instance_method(kernel_class, "require_relative", require_relative);
     globals.add_builtin_instance_method(kernel_class, "block_given?", block_given);
     globals.add_builtin_instance_method(kernel_class, "method", method);
+    globals.add_builtin_instance_method(kernel_class, "binding", binding);
+    globals.add_builtin_instance_method(kernel_class, "proc", proc);
+    globals.add_builtin_instance_method(kernel_class, "lambda", lambda);
     globals.add_builtin_instance_method(kernel_class, "is_a?", isa);
+    globals.add_builtin_instance_method(kernel_class, "kind_of?", isa);
     globals.add_builtin_instance_method(kernel_class, "Integer", integer);
     globals.add_builtin_instance_method(kernel_class, "__dir__", dir);
     globals.add_builtin_instance_method(kernel_class, "__FILE__", file_);
@@ -26,28 +41,22 @@ pub fn init(globals: &mut Globals) -> Value {
     let kernel = Value::class(globals, kernel_class);
     return kernel;
 
-    /// Built-in function "puts".
+    /// Built-in function "puts". The output goes to `$stdout`.
     fn puts(vm: &mut VM, _: Value, args: &Args) -> VMResult {
-        fn flatten(vm: &mut VM, val: Value) {
-            match val.as_array() {
-                None => println!("{}", vm.val_to_s(val)),
-                Some(aref) => {
-                    for val in &aref.elements {
-                        flatten(vm, val.clone());
-                    }
-                }
-            }
-        }
-        for arg in args.iter() {
-            flatten(vm, *arg);
-        }
+        let bytes = io::puts_bytes(vm, args);
+        let out = io::stdout(vm);
+        io::write_to(vm, out, bytes)?;
         Ok(Value::nil())
     }
 
     fn p(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+        let mut bytes = vec![];
         for arg in args.iter() {
-            println!("{}", vm.val_inspect(*arg));
+            bytes.extend(vm.val_inspect(*arg).into_bytes());
+            bytes.push(b'\n');
         }
+        let out = io::stdout(vm);
+        io::write_to(vm, out, bytes)?;
         if args.len() == 1 {
             Ok(args[0])
         } else {
@@ -57,18 +66,45 @@ pub fn init(globals: &mut Globals) -> Value {
 
     /// Built-in function "print".
     fn print(vm: &mut VM, _: Value, args: &Args) -> VMResult {
-        for arg in args.iter() {
-            match arg.as_bytes() {
-                Some(bytes) => {
-                    use std::io::{self, Write};
-                    io::stdout().write(bytes).unwrap();
-                }
-                None => print!("{}", vm.val_to_s(*arg)),
-            }
+        let bytes = io::print_bytes(vm, args);
+        let out = io::stdout(vm);
+        io::write_to(vm, out, bytes)?;
+        Ok(Value::nil())
+    }
+
+    /// Built-in function "format" and "sprintf".
+    fn format(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+        let res = format::format_args(vm, args)?;
+        Ok(Value::string(&vm.globals, res))
+    }
+
+    /// Built-in function "printf".
+    fn printf(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+        if args.is_empty() {
+            return Ok(Value::nil());
         }
+        let res = format::format_args(vm, args)?;
+        let out = io::stdout(vm);
+        io::write_to(vm, out, res.into_bytes())?;
         Ok(Value::nil())
     }
 
+    /// Built-in function "gets". Lines are read from ARGF.
+    fn gets(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+        let argf = io::argf(vm);
+        io::gets(vm, argf, args)
+    }
+
+    fn readline(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+        let argf = io::argf(vm);
+        io::readline(vm, argf, args)
+    }
+
+    fn readlines(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+        let argf = io::argf(vm);
+        io::readlines(vm, argf, args)
+    }
+
     /// Built-in function "assert".
     fn assert(vm: &mut VM, _: Value, args: &Args) -> VMResult {
         vm.check_args_num(args.len(), 2)?;
@@ -102,6 +138,9 @@ pub fn init(globals: &mut Globals) -> Value {
             Some(string) => string,
             None => return Err(vm.error_argument("file name must be a string.")),
         };
+        if let Some(loaded) = require_builtin(vm, file_name) {
+            return Ok(Value::bool(loaded));
+        }
         let mut path = std::env::current_dir().unwrap();
         path.push(file_name);
         require_main(vm, path)?;
@@ -130,6 +169,29 @@ pub fn init(globals: &mut Globals) -> Value {
         Ok(Value::bool(true))
     }
 
+    /// Load a library built into the interpreter, such as "fileutils".
+    /// Returns None if `name` is not a built-in library, or whether it is loaded for the first time.
+    fn require_builtin(vm: &mut VM, name: &str) -> Option<bool> {
+        let feature = match name.trim_end_matches(".rb") {
+            "fileutils" => "fileutils",
+            "time" => "time",
+            _ => return None,
+        };
+        if vm.globals.loaded_features.contains(&feature) {
+            return Some(false);
+        }
+        vm.globals.loaded_features.push(feature);
+        match feature {
+            "fileutils" => {
+                let id = vm.globals.get_ident_id("FileUtils");
+                let module = fileutils::init_fileutils(&mut vm.globals);
+                vm.globals.builtins.object.set_var(&vm.globals, id, module);
+            }
+            _ => time::init_time_lib(&mut vm.globals),
+        }
+        Some(true)
+    }
+
     fn require_main(vm: &mut VM, path: PathBuf) -> Result<(), RubyError> {
         let file_name = path.to_string_lossy().to_string();
         let (absolute_path, program) = match load_file(file_name.clone()) {
@@ -171,22 +233,32 @@ pub fn init(globals: &mut Globals) -> Value {
             None => return Err(vm.error_type("An argument must be a Symbol.")),
         };
         let method = vm.get_method(self_val, name)?;
-        let val = Value::method(&vm.globals, name, self_val, method);
+        let class = self_val.get_class_object_for_method(&vm.globals);
+        let owner = vm.get_method_owner(class, name).unwrap_or(class);
+        let val = Value::method(&vm.globals, name, self_val, method, owner);
         Ok(val)
     }
 
+    fn binding(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+        vm.check_args_num(args.len(), 0)?;
+        vm.create_binding()
+    }
+
+    fn proc(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+        vm.check_args_num(args.len(), 0)?;
+        let method = vm.expect_block(args.block)?;
+        vm.create_proc(method)
+    }
+
+    fn lambda(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+        vm.check_args_num(args.len(), 0)?;
+        let method = vm.expect_block(args.block)?;
+        vm.create_lambda(method)
+    }
+
     fn isa(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
         vm.check_args_num(args.len(), 1)?;
-        let mut recv_class = self_val.get_class_object(&vm.globals);
-        loop {
-            if recv_class.id() == args[0].id() {
-                return Ok(Value::true_val());
-            }
-            recv_class = recv_class.as_class().superclass;
-            if recv_class.is_nil() {
-                return Ok(Value::false_val());
-            }
-        }
+        Ok(Value::bool(vm.is_kind_of(self_val, args[0])))
     }
 
     fn integer(vm: &mut VM, _: Value, args: &Args) -> VMResult {
@@ -258,9 +330,24 @@ pub fn init(globals: &mut Globals) -> Value {
         Err(vm.error_unimplemented("error"))
     }
 
-    fn rand(_vm: &mut VM, _: Value, _args: &Args) -> VMResult {
-        let num = rand::random();
-        Ok(Value::flonum(num))
+    /// Built-in function "rand". A numeric limit is truncated to its absolute integer value,
+    /// and a limit of 0 gives a Float like `rand()`.
+    fn rand(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+        vm.check_args_range(args.len(), 0, 1)?;
+        let max = match args.first() {
+            Some(max) => match (max.as_fixnum(), max.as_flonum()) {
+                (Some(n), _) => Value::fixnum(n.abs()),
+                (_, Some(f)) => Value::fixnum((f as i64).abs()),
+                _ => *max,
+            },
+            None => Value::nil(),
+        };
+        let max = if max.as_fixnum() == Some(0) {
+            None
+        } else {
+            Some(max)
+        };
+        random::gen_value(vm, &mut rand::thread_rng(), max)
     }
 
     fn loop_(vm: &mut VM, _: Value, args: &Args) -> VMResult {
@@ -303,6 +390,15 @@ mod test {
         assert false, obj.is_a?(Integer)
         assert false, obj.is_a?(Array)
         assert false, obj.is_a?(M)
+
+        module N
+          include M
+        end
+        class D
+          include N
+        end
+        assert true, D.new.is_a?(N)
+        assert true, D.new.kind_of?(M)
         ";
         assert_script(program);
     }
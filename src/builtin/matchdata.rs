@@ -0,0 +1,272 @@
+use crate::*;
+use fancy_regex::Captures;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchDataInfo {
+    /// The target string of the match.
+    string: String,
+    /// Byte ranges of the whole match and each group.
+    groups: Vec<Option<(usize, usize)>>,
+    /// Names of the groups. The name of the whole match is None.
+    names: Vec<Option<String>>,
+}
+
+impl MatchDataInfo {
+    pub fn new(re: &Regexp, captures: &Captures, given: &str) -> Self {
+        let groups = (0..captures.len())
+            .map(|i| captures.get(i).map(|m| (m.start(), m.end())))
+            .collect();
+        let names = re
+            .capture_names()
+            .map(|name| name.map(|s| s.to_string()))
+            .collect();
+        MatchDataInfo {
+            string: given.to_string(),
+            groups,
+            names,
+        }
+    }
+
+    fn group(&self, i: usize) -> Option<&str> {
+        match self.groups.get(i) {
+            Some(Some((start, end))) => Some(&self.string[*start..*end]),
+            _ => None,
+        }
+    }
+
+    fn group_index(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .rposition(|n| n.as_ref().map(|s| s.as_str()) == Some(name))
+    }
+
+    /// The matched string.
+    pub fn to_s(&self) -> String {
+        self.group(0).unwrap_or("").to_string()
+    }
+
+    /// Character offset of the byte position `pos`.
+    fn char_pos(&self, pos: usize) -> usize {
+        self.string[..pos].chars().count()
+    }
+}
+
+pub type MatchDataRef = Ref<MatchDataInfo>;
+
+pub fn init_matchdata(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("MatchData");
+    let class = ClassRef::from(id, globals.builtins.object);
+    globals.add_builtin_instance_method(class, "[]", index);
+    globals.add_builtin_instance_method(class, "to_a", to_a);
+    globals.add_builtin_instance_method(class, "captures", captures);
+    globals.add_builtin_instance_method(class, "named_captures", named_captures);
+    globals.add_builtin_instance_method(class, "names", names);
+    globals.add_builtin_instance_method(class, "pre_match", pre_match);
+    globals.add_builtin_instance_method(class, "post_match", post_match);
+    globals.add_builtin_instance_method(class, "begin", begin);
+    globals.add_builtin_instance_method(class, "end", end);
+    globals.add_builtin_instance_method(class, "size", size);
+    globals.add_builtin_instance_method(class, "length", size);
+    globals.add_builtin_instance_method(class, "string", string);
+    globals.add_builtin_instance_method(class, "to_s", to_s);
+    globals.add_builtin_instance_method(class, "inspect", inspect);
+    Value::class(globals, class)
+}
+
+fn group_value(vm: &VM, info: &MatchDataInfo, i: usize) -> Value {
+    match info.group(i) {
+        Some(s) => Value::string(&vm.globals, s.to_string()),
+        None => Value::nil(),
+    }
+}
+
+/// Convert an index or a group name to the index of the group.
+fn expect_group(vm: &VM, info: &MatchDataInfo, val: Value) -> Result<Option<usize>, RubyError> {
+    let name = if let Some(i) = val.as_fixnum() {
+        let len = info.groups.len() as i64;
+        let i = if i < 0 { i + len } else { i };
+        return Ok(if i < 0 || i >= len {
+            None
+        } else {
+            Some(i as usize)
+        });
+    } else if let Some(id) = val.as_symbol() {
+        vm.globals.get_ident_name(id).to_string()
+    } else if let Some(s) = val.as_string() {
+        s.to_string()
+    } else {
+        return Err(vm.error_type("Index must be Integer, String or Symbol."));
+    };
+    match info.group_index(&name) {
+        Some(i) => Ok(Some(i)),
+        None => Err(vm.error_index(format!("undefined group name reference: {}", name))),
+    }
+}
+
+fn index(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let info = self_val.as_matchdata().unwrap();
+    match expect_group(vm, &info, args[0])? {
+        Some(i) => Ok(group_value(vm, &info, i)),
+        None => Ok(Value::nil()),
+    }
+}
+
+fn to_a(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let info = self_val.as_matchdata().unwrap();
+    let ary = (0..info.groups.len())
+        .map(|i| group_value(vm, &info, i))
+        .collect();
+    Ok(Value::array_from(&vm.globals, ary))
+}
+
+fn captures(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let info = self_val.as_matchdata().unwrap();
+    let ary = (1..info.groups.len())
+        .map(|i| group_value(vm, &info, i))
+        .collect();
+    Ok(Value::array_from(&vm.globals, ary))
+}
+
+fn named_captures(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let info = self_val.as_matchdata().unwrap();
+    let mut map = std::collections::HashMap::new();
+    for (i, name) in info.names.iter().enumerate() {
+        if let Some(name) = name {
+            let key = Value::string(&vm.globals, name.to_string());
+            map.insert(HashKey(key), group_value(vm, &info, i));
+        }
+    }
+    Ok(Value::hash_from(&vm.globals, map))
+}
+
+fn names(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let info = self_val.as_matchdata().unwrap();
+    let ary = info
+        .names
+        .iter()
+        .filter_map(|name| name.as_ref())
+        .map(|name| Value::string(&vm.globals, name.to_string()))
+        .collect();
+    Ok(Value::array_from(&vm.globals, ary))
+}
+
+fn pre_match(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let info = self_val.as_matchdata().unwrap();
+    let (start, _) = info.groups[0].unwrap();
+    Ok(Value::string(&vm.globals, info.string[..start].to_string()))
+}
+
+fn post_match(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let info = self_val.as_matchdata().unwrap();
+    let (_, end) = info.groups[0].unwrap();
+    Ok(Value::string(&vm.globals, info.string[end..].to_string()))
+}
+
+fn begin(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let info = self_val.as_matchdata().unwrap();
+    let i = match expect_group(vm, &info, args[0])? {
+        Some(i) => i,
+        None => {
+            return Err(vm.error_index(format!(
+                "index {} out of matches",
+                args[0].as_fixnum().unwrap_or(0)
+            )))
+        }
+    };
+    match info.groups[i] {
+        Some((start, _)) => Ok(Value::fixnum(info.char_pos(start) as i64)),
+        None => Ok(Value::nil()),
+    }
+}
+
+fn end(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let info = self_val.as_matchdata().unwrap();
+    let i = match expect_group(vm, &info, args[0])? {
+        Some(i) => i,
+        None => {
+            return Err(vm.error_index(format!(
+                "index {} out of matches",
+                args[0].as_fixnum().unwrap_or(0)
+            )))
+        }
+    };
+    match info.groups[i] {
+        Some((_, end)) => Ok(Value::fixnum(info.char_pos(end) as i64)),
+        None => Ok(Value::nil()),
+    }
+}
+
+fn size(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let info = self_val.as_matchdata().unwrap();
+    Ok(Value::fixnum(info.groups.len() as i64))
+}
+
+fn string(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let info = self_val.as_matchdata().unwrap();
+    Ok(Value::string(&vm.globals, info.string.clone()))
+}
+
+fn to_s(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let info = self_val.as_matchdata().unwrap();
+    Ok(group_value(vm, &info, 0))
+}
+
+fn inspect(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let info = self_val.as_matchdata().unwrap();
+    let mut s = format!("#<MatchData {:?}", info.group(0).unwrap_or(""));
+    for i in 1..info.groups.len() {
+        let name = match &info.names[i] {
+            Some(name) => name.to_string(),
+            None => i.to_string(),
+        };
+        match info.group(i) {
+            Some(group) => s += &format!(" {}:{:?}", name, group),
+            None => s += &format!(" {}:nil", name),
+        }
+    }
+    Ok(Value::string(&vm.globals, s + ">"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn matchdata() {
+        let program = r##"
+        m = "hello world".match(/(?<first>\w+) (\w+)?/)
+        assert "hello world", m[0]
+        assert "hello", m[1]
+        assert "hello", m[:first]
+        assert "hello", m["first"]
+        assert "world", m[-1]
+        assert nil, m[3]
+        assert ["hello world", "hello", "world"], m.to_a
+        assert ["hello", "world"], m.captures
+        assert ["first"], m.names
+        assert({"first" => "hello"}, m.named_captures)
+        assert 3, m.size
+        assert "hello world", m.to_s
+        m = "héllo wörld".match(/w(.)r/)
+        assert "héllo ", m.pre_match
+        assert "ld", m.post_match
+        assert [6, 9], [m.begin(0), m.end(0)]
+        assert [7, 8], [m.begin(1), m.end(1)]
+        assert "#<MatchData \"wör\" 1:\"ö\">", m.inspect
+        "##;
+        assert_script(program);
+    }
+}
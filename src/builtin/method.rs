@@ -4,14 +4,179 @@ pub fn init_method(globals: &mut Globals) -> Value {
     let proc_id = globals.get_ident_id("Method");
     let class = ClassRef::from(proc_id, globals.builtins.object);
     globals.add_builtin_instance_method(class, "call", method_call);
+    globals.add_builtin_instance_method(class, "to_proc", to_proc);
+    globals.add_builtin_instance_method(class, "arity", arity);
+    globals.add_builtin_instance_method(class, "owner", owner);
+    globals.add_builtin_instance_method(class, "receiver", receiver);
+    globals.add_builtin_instance_method(class, "unbind", unbind);
+    globals.add_builtin_instance_method(class, "source_location", source_location);
     Value::class(globals, class)
 }
 
+pub fn init_unbound_method(globals: &mut Globals) -> Value {
+    let proc_id = globals.get_ident_id("UnboundMethod");
+    let class = ClassRef::from(proc_id, globals.builtins.object);
+    globals.add_builtin_instance_method(class, "bind", bind);
+    globals.add_builtin_instance_method(class, "arity", arity);
+    globals.add_builtin_instance_method(class, "owner", owner);
+    globals.add_builtin_instance_method(class, "source_location", source_location);
+    Value::class(globals, class)
+}
+
+fn expect_method(vm: &VM, val: Value) -> Result<MethodObjRef, RubyError> {
+    match val.as_method() {
+        Some(method) => Ok(method),
+        None => Err(vm.error_unimplemented("Expected Method object.")),
+    }
+}
+
 pub fn method_call(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
-    let method = match self_val.as_method() {
-        Some(method) => method,
-        None => return Err(vm.error_unimplemented("Expected Method object.")),
-    };
+    let method = expect_method(vm, self_val)?;
     let res = vm.eval_send(method.method, method.receiver, args)?;
     Ok(res)
 }
+
+/// Returns a lambda which calls the method with the given arguments.
+fn to_proc(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let method = expect_method(vm, self_val)?;
+    let arity = method_arity(vm, method);
+    Ok(Value::builtin_proc(
+        &vm.globals,
+        call_captured,
+        vec![self_val],
+        arity,
+        true,
+    ))
+}
+
+/// The body of a Proc made by Method#to_proc, which captures the Method object.
+fn call_captured(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let method = self_val.as_proc().unwrap().captures()[0];
+    method_call(vm, method, args)
+}
+
+fn method_arity(vm: &VM, method: MethodObjRef) -> i64 {
+    match vm.globals.get_method_info(method.method) {
+        MethodInfo::RubyFunc { iseq } => iseq.arity(true),
+        MethodInfo::AttrReader { .. } => 0,
+        MethodInfo::AttrWriter { .. } => 1,
+        MethodInfo::BuiltinFunc { .. } => -1,
+        MethodInfo::ProcBlock { procobj } => procobj.as_proc().unwrap().arity(),
+    }
+}
+
+fn arity(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let method = expect_method(vm, self_val)?;
+    Ok(Value::fixnum(method_arity(vm, method)))
+}
+
+fn owner(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let method = expect_method(vm, self_val)?;
+    Ok(method.owner)
+}
+
+fn receiver(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let method = expect_method(vm, self_val)?;
+    Ok(method.receiver)
+}
+
+fn unbind(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let method = expect_method(vm, self_val)?;
+    let val = Value::unbound_method(&vm.globals, method.name, method.method, method.owner);
+    Ok(val)
+}
+
+/// Returns [file name, line number] of the method, or nil for builtin methods.
+fn source_location(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let method = expect_method(vm, self_val)?;
+    let iseq = match vm.globals.get_method_info(method.method) {
+        MethodInfo::RubyFunc { iseq } => *iseq,
+        _ => return Ok(Value::nil()),
+    };
+    let path = iseq.source_info.path.to_string_lossy().to_string();
+    let line = iseq.source_info.get_line(&iseq.loc);
+    let ary = vec![Value::string(&vm.globals, path), Value::fixnum(line as i64)];
+    Ok(Value::array_from(&vm.globals, ary))
+}
+
+fn bind(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let method = expect_method(vm, self_val)?;
+    let receiver = args[0];
+    if !vm.is_kind_of(receiver, method.owner) {
+        let owner = vm.val_inspect(method.owner);
+        return Err(vm.error_type(format!("Bind argument must be an instance of {}.", owner)));
+    }
+    let val = Value::method(
+        &vm.globals,
+        method.name,
+        receiver,
+        method.method,
+        method.owner,
+    );
+    Ok(val)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn method_object() {
+        let program = r#"
+    class Foo
+        def initialize(x)
+            @x = x
+        end
+        def add(a, b = 1)
+            @x + a + b
+        end
+        def get(*args)
+            args
+        end
+    end
+    class Bar < Foo
+        def initialize(x)
+            @x = x
+        end
+    end
+    obj = Bar.new(10)
+    m = obj.method(:add)
+    assert(-2, m.arity)
+    assert(0, obj.method(:get).arity + 1)
+    assert(Foo, m.owner)
+    assert(obj, m.receiver)
+    assert(13, m.call(2))
+    um = m.unbind
+    assert(UnboundMethod, um.class)
+    m2 = um.bind(Foo.new(100))
+    assert(103, m2.call(2, 1))
+    assert_error { um.bind(3) }
+    assert(Array, m.source_location.class)
+    assert(nil, 3.method(:to_s).source_location)
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn method_to_proc() {
+        let program = r#"
+    def double(x)
+        x * 2
+    end
+    assert([2, 4, 6], [1, 2, 3].map(&method(:double)))
+    pr = method(:double).to_proc
+    assert(true, pr.lambda?)
+    assert(10, pr.call(5))
+    assert(1, pr.arity)
+    assert_error { pr.call(1, 2) }
+    "#;
+        assert_script(program);
+    }
+}
@@ -9,9 +9,26 @@ pub fn init(globals: &mut Globals) {
     globals.add_builtin_instance_method(class, "attr_reader", attr_reader);
     globals.add_builtin_instance_method(class, "attr_writer", attr_writer);
     globals.add_builtin_instance_method(class, "module_function", module_function);
+    globals.add_builtin_instance_method(class, "public", public);
+    globals.add_builtin_instance_method(class, "private", private);
+    globals.add_builtin_instance_method(class, "protected", protected);
+    globals.add_builtin_instance_method(class, "public_class_method", public_class_method);
+    globals.add_builtin_instance_method(class, "private_class_method", private_class_method);
+    globals.add_builtin_instance_method(class, "private_constant", private_constant);
+    globals.add_builtin_instance_method(class, "public_constant", public_constant);
+    globals.add_builtin_instance_method(class, "public_method_defined?", public_method_defined);
+    globals.add_builtin_instance_method(class, "private_method_defined?", private_method_defined);
+    globals.add_builtin_instance_method(
+        class,
+        "protected_method_defined?",
+        protected_method_defined,
+    );
     globals.add_builtin_instance_method(class, "singleton_class?", singleton_class);
     globals.add_builtin_instance_method(class, "const_get", const_get);
+    globals.add_builtin_instance_method(class, "const_set", const_set);
+    globals.add_builtin_instance_method(class, "remove_const", remove_const);
     globals.add_builtin_instance_method(class, "include", include);
+    globals.add_builtin_instance_method(class, "remove_method", remove_method);
     globals.add_builtin_instance_method(class, "included_modules", included_modules);
     globals.add_builtin_instance_method(class, "ancestors", ancestors);
 }
@@ -23,17 +40,18 @@ fn constants(vm: &mut VM, self_val: Value, _: &Args) -> VMResult {
         v.append(
             &mut class
                 .as_object()
-                .var_table()
-                .keys()
+                .vars()
+                .map(|(id, _)| id)
                 .filter(|x| {
                     vm.globals
-                        .get_ident_name(**x)
+                        .get_ident_name(*x)
                         .chars()
                         .nth(0)
                         .unwrap()
                         .is_ascii_uppercase()
+                        && !class.as_module().unwrap().is_private_constant(*x)
                 })
-                .map(|k| Value::symbol(*k))
+                .map(Value::symbol)
                 .collect(),
         );
         match class.superclass() {
@@ -60,6 +78,43 @@ fn const_get(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     Ok(val)
 }
 
+fn const_set(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 2)?;
+    vm.expect_module(self_val)?;
+    let id = expect_name(vm, args[0])?;
+    let name = vm.globals.get_ident_name(id);
+    if !name.starts_with(|c: char| c.is_ascii_uppercase()) {
+        return Err(vm.error_name(format!("wrong constant name {}", name)));
+    }
+    let val = args[1];
+    if let Some(mut cref) = val.as_module() {
+        if cref.name.is_none() {
+            cref.name = Some(id);
+        }
+    }
+    self_val.set_var(&vm.globals, id, val);
+    vm.globals.const_version += 1;
+    Ok(val)
+}
+
+fn remove_const(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let mut class = vm.expect_module(self_val)?;
+    let id = expect_name(vm, args[0])?;
+    match self_val.as_object().remove_var(&vm.globals, id) {
+        Some(val) => {
+            class.private_constants.remove(&id);
+            vm.globals.const_version += 1;
+            Ok(val)
+        }
+        None => {
+            let inspect = vm.val_inspect(self_val);
+            let name = vm.globals.get_ident_name(id);
+            Err(vm.error_name(format!("constant {}::{} not defined", inspect, name)))
+        }
+    }
+}
+
 fn instance_methods(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     let mut class = vm.expect_module(self_val)?;
     vm.check_args_range(args.len(), 0, 1)?;
@@ -69,6 +124,7 @@ fn instance_methods(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
             let v = class
                 .method_table
                 .keys()
+                .filter(|k| class.get_visibility(**k) != Visibility::Private)
                 .map(|k| Value::symbol(*k))
                 .collect();
             Ok(Value::array_from(&vm.globals, v))
@@ -81,6 +137,7 @@ fn instance_methods(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
                         &class
                             .method_table
                             .keys()
+                            .filter(|k| class.get_visibility(**k) != Visibility::Private)
                             .map(|k| Value::symbol(*k))
                             .collect(),
                     )
@@ -157,12 +214,152 @@ fn get_instance_var(vm: &mut VM, id: IdentId) -> IdentId {
     vm.globals.get_ident_id(format!("@{}", s))
 }
 
-fn module_function(vm: &mut VM, _: Value, args: &Args) -> VMResult {
-    vm.check_args_num(args.len(), 0)?;
-    vm.module_function(true);
+fn module_function(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    if args.len() == 0 {
+        vm.module_function(true);
+        return Ok(Value::nil());
+    }
+    for id in expect_names(vm, args)? {
+        let method = vm.get_instance_method(self_val, id)?;
+        vm.add_singleton_method(self_val, id, method)?;
+        vm.set_method_visibility(self_val, id, Visibility::Private)?;
+    }
+    Ok(visibility_result(vm, args))
+}
+
+fn public(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    set_visibility(vm, self_val, args, Visibility::Public)
+}
+
+fn private(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    set_visibility(vm, self_val, args, Visibility::Private)
+}
+
+fn protected(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    set_visibility(vm, self_val, args, Visibility::Protected)
+}
+
+/// Set visibility of the methods given by `args`.
+/// With no argument, set default visibility for subsequently defined methods.
+fn set_visibility(vm: &mut VM, self_val: Value, args: &Args, visibility: Visibility) -> VMResult {
+    if args.len() == 0 {
+        vm.default_visibility(visibility);
+        return Ok(Value::nil());
+    }
+    for id in expect_names(vm, args)? {
+        vm.set_method_visibility(self_val, id, visibility)?;
+    }
+    Ok(visibility_result(vm, args))
+}
+
+fn public_class_method(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let singleton = vm.get_singleton_class(self_val)?;
+    for id in expect_names(vm, args)? {
+        vm.set_method_visibility(singleton, id, Visibility::Public)?;
+    }
     Ok(Value::nil())
 }
 
+fn private_class_method(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let singleton = vm.get_singleton_class(self_val)?;
+    for id in expect_names(vm, args)? {
+        vm.set_method_visibility(singleton, id, Visibility::Private)?;
+    }
+    Ok(Value::nil())
+}
+
+fn private_constant(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let mut class = vm.expect_module(self_val)?;
+    for id in expect_names(vm, args)? {
+        if self_val.get_var(id).is_none() {
+            let inspect = vm.val_inspect(self_val);
+            let name = vm.globals.get_ident_name(id);
+            return Err(vm.error_name(format!("constant {}::{} not defined", inspect, name)));
+        }
+        class.private_constants.insert(id);
+    }
+    Ok(Value::nil())
+}
+
+fn public_constant(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let mut class = vm.expect_module(self_val)?;
+    for id in expect_names(vm, args)? {
+        if self_val.get_var(id).is_none() {
+            let inspect = vm.val_inspect(self_val);
+            let name = vm.globals.get_ident_name(id);
+            return Err(vm.error_name(format!("constant {}::{} not defined", inspect, name)));
+        }
+        class.private_constants.remove(&id);
+    }
+    Ok(Value::nil())
+}
+
+fn public_method_defined(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    method_defined_with(vm, self_val, args, Visibility::Public)
+}
+
+fn private_method_defined(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    method_defined_with(vm, self_val, args, Visibility::Private)
+}
+
+fn protected_method_defined(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    method_defined_with(vm, self_val, args, Visibility::Protected)
+}
+
+fn method_defined_with(
+    vm: &mut VM,
+    self_val: Value,
+    args: &Args,
+    visibility: Visibility,
+) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    vm.expect_module(self_val)?;
+    let id = expect_names(vm, args)?[0];
+    if vm.get_method_owner(self_val, id).is_none() {
+        return Ok(Value::false_val());
+    }
+    let res = vm.get_method_visibility(self_val, id) == visibility;
+    Ok(Value::bool(res))
+}
+
+/// Convert args (Symbols, Strings, or Arrays of them) to a list of method names.
+fn expect_names(vm: &mut VM, args: &Args) -> Result<Vec<IdentId>, RubyError> {
+    let mut names = vec![];
+    for arg in args.iter() {
+        match arg.as_array() {
+            Some(aref) => {
+                for elem in &aref.elements {
+                    names.push(expect_name(vm, *elem)?);
+                }
+            }
+            None => names.push(expect_name(vm, *arg)?),
+        }
+    }
+    Ok(names)
+}
+
+fn expect_name(vm: &mut VM, val: Value) -> Result<IdentId, RubyError> {
+    if let Some(id) = val.as_symbol() {
+        return Ok(id);
+    }
+    match val.as_string() {
+        Some(s) => Ok(vm.globals.get_ident_id(s)),
+        None => {
+            let inspect = vm.val_inspect(val);
+            Err(vm.error_type(format!("{} is not a symbol nor a string", inspect)))
+        }
+    }
+}
+
+/// Return value of `public`, `private`, `protected` and `module_function` with arguments.
+fn visibility_result(vm: &VM, args: &Args) -> Value {
+    if args.len() == 1 {
+        args[0]
+    } else {
+        Value::array_from(&vm.globals, args.to_vec())
+    }
+}
+
 fn singleton_class(vm: &mut VM, self_val: Value, _: &Args) -> VMResult {
     let class = vm.expect_module(self_val)?;
     Ok(Value::bool(class.is_singleton))
@@ -173,9 +370,27 @@ fn include(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     let mut class = vm.expect_module(self_val)?;
     let module = args[0];
     class.include.push(module);
+    vm.globals.class_version += 1;
+    vm.globals.const_version += 1;
     Ok(Value::nil())
 }
 
+fn remove_method(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let mut class = vm.expect_module(self_val)?;
+    for id in expect_names(vm, args)? {
+        if class.method_table.remove(&id).is_none() {
+            let class_name = vm.val_inspect(self_val);
+            return Err(vm.error_name(format!(
+                "method `{}' not defined in {}",
+                vm.globals.get_ident_name(id),
+                class_name
+            )));
+        }
+    }
+    vm.globals.class_version += 1;
+    Ok(self_val)
+}
+
 fn included_modules(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     vm.check_args_num(args.len(), 0)?;
     let mut class = self_val;
@@ -243,7 +458,142 @@ mod test {
         end
     end
     assert(123, Foo.bar)
-    assert(123, Foo.new.bar)
+    assert_error { Foo.new.bar }
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn module_function_with_args() {
+        let program = r#"
+    module M
+        def foo
+            7
+        end
+        def bar
+            foo * 2
+        end
+        module_function :foo
+    end
+    class C
+        include M
+        def baz
+            foo + bar
+        end
+    end
+    assert(7, M.foo)
+    assert(21, C.new.baz)
+    assert(14, C.new.bar)
+    assert_error { C.new.foo }
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn visibility() {
+        let program = r#"
+    class Foo
+        def pub
+            priv + prot
+        end
+        def cmp(other)
+            other.prot
+        end
+        private
+        def priv
+            100
+        end
+        protected
+        def prot
+            20
+        end
+        public
+        def pub2
+            self.priv
+        end
+    end
+    class Bar < Foo
+        def cmp2(other)
+            other.prot
+        end
+    end
+    foo = Foo.new
+    assert(120, foo.pub)
+    assert(100, foo.pub2)
+    assert(20, foo.cmp(Foo.new))
+    assert(20, Bar.new.cmp2(foo))
+    assert_error { foo.priv }
+    assert_error { foo.prot }
+    assert(true, Foo.private_method_defined?(:priv))
+    assert(true, Foo.protected_method_defined?(:prot))
+    assert(true, Foo.public_method_defined?(:pub))
+    assert(false, Foo.public_method_defined?(:priv))
+    assert(false, Foo.instance_methods(false).include?(:priv))
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn visibility_with_args() {
+        let program = r#"
+    class Foo
+        def a; 1; end
+        def b; 2; end
+        def c; 3; end
+        private :a, :b
+        private def d; 4; end
+        def e; a + b + d; end
+    end
+    class Bar < Foo
+        public :a
+    end
+    foo = Foo.new
+    assert_error { foo.a }
+    assert_error { foo.b }
+    assert_error { foo.d }
+    assert(3, foo.c)
+    assert(7, foo.e)
+    assert(1, Bar.new.a)
+    assert_error { Bar.new.b }
+    assert(1, foo.send(:a))
+    assert_error { Foo.send(:private, :nothing) }
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn private_class_method() {
+        let program = r#"
+    class Foo
+        def self.create
+            new_instance
+        end
+        def self.new_instance
+            Foo.new
+        end
+        private_class_method :new_instance
+    end
+    assert(Foo, Foo.create.class)
+    assert_error { Foo.new_instance }
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn private_constant() {
+        let program = r#"
+    class Foo
+        SECRET = 42
+        OPEN = 7
+        private_constant :SECRET
+        def self.secret
+            SECRET
+        end
+    end
+    assert(42, Foo.secret)
+    assert(7, Foo::OPEN)
+    assert_error { Foo::SECRET }
+    assert([:OPEN], Foo.constants)
     "#;
         assert_script(program);
     }
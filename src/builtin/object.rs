@@ -1,3 +1,4 @@
+use crate::builtin::binding;
 use crate::*;
 
 pub fn init(globals: &mut Globals) {
@@ -108,7 +109,7 @@ fn instance_variable_set(vm: &mut VM, self_val: Value, args: &Args) -> VMResult
         },
     };
     let mut self_obj = self_val.as_object();
-    self_obj.set_var(var_id, val);
+    self_obj.set_var(&vm.globals, var_id, val);
     Ok(val)
 }
 
@@ -134,10 +135,10 @@ fn instance_variables(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     vm.check_args_num(args.len(), 0)?;
     let receiver = self_val.as_object();
     let res = receiver
-        .var_table()
-        .keys()
-        .filter(|x| vm.globals.get_ident_name(**x).chars().nth(0) == Some('@'))
-        .map(|x| Value::symbol(*x))
+        .vars()
+        .map(|(id, _)| id)
+        .filter(|x| vm.globals.get_ident_name(*x).chars().nth(0) == Some('@'))
+        .map(Value::symbol)
         .collect();
     Ok(Value::array_from(&vm.globals, res))
 }
@@ -211,22 +212,32 @@ fn send(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
 
 fn eval(vm: &mut VM, _: Value, args: &Args) -> VMResult {
     vm.check_args_range(args.len(), 1, 4)?;
-    let program = vm.expect_string(&args[0], "1st arg")?;
-    if args.len() > 1 {
-        if !args[1].is_nil() {
-            return Err(vm.error_argument("Currently, 2nd arg must be Nil."));
+    let program = vm.expect_string(&args[0], "1st arg")?.to_string();
+    let binding = if args.len() > 1 && !args[1].is_nil() {
+        args[1]
+    } else {
+        vm.create_binding()?
+    };
+    let context = match binding.as_binding() {
+        Some(context) => context,
+        None => {
+            let inspect = vm.val_inspect(binding);
+            return Err(vm.error_type(format!(
+                "wrong argument type {} (expected binding)",
+                inspect
+            )));
         }
-    }
+    };
     let env_name = if args.len() > 2 {
-        vm.expect_string(&args[2], "3rd arg must be String.")?
+        vm.expect_string(&args[2], "3rd arg")?.to_string()
     } else {
-        "(eval)"
+        "(eval)".to_string()
     };
-
-    let method = vm.parse_program_eval(std::path::PathBuf::from(env_name), program)?;
-    let args = Args::new0();
-    let res = vm.eval_block(method, &args)?;
-    Ok(res)
+    let line = match args.get(3) {
+        Some(line) => binding::line_arg(vm, *line, "4th arg")?,
+        None => 1,
+    };
+    vm.eval_binding(std::path::PathBuf::from(env_name), &program, line, context)
 }
 
 #[cfg(test)]
@@ -315,6 +326,9 @@ mod test {
         eval("b = 100; assert(100, b);")
         assert(77, eval("a = 77"))
         assert(77, a)
+        assert(1, eval("__LINE__"))
+        assert(12, eval("a\n__LINE__", nil, "(test)", 11))
+        assert_error { eval("1", nil, "(test)", 0) }
         "#;
         assert_script(program);
     }
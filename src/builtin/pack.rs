@@ -0,0 +1,812 @@
+//! The template language shared by `Array#pack`, `String#unpack` and `String#unpack1`.
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Count {
+    /// No count was given.
+    Implicit,
+    Fixed(usize),
+    Star,
+}
+
+impl Count {
+    /// The count, with `star` for `*` and 1 for no count.
+    fn or(self, star: usize) -> usize {
+        match self {
+            Count::Implicit => 1,
+            Count::Fixed(n) => n,
+            Count::Star => star,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Directive {
+    kind: char,
+    /// `_` or `!`: the native size of the platform.
+    native: bool,
+    /// `<` or `>`: explicit byte order. true for big endian.
+    big_endian: Option<bool>,
+    count: Count,
+}
+
+impl Directive {
+    /// The size in bytes, the signedness and the byte order (true for big endian) of an integer directive.
+    fn int_spec(&self) -> Option<(usize, bool, bool)> {
+        let (size, signed, order) = match self.kind {
+            'C' | 'c' => (1, self.kind == 'c', None),
+            'S' | 's' => (2, self.kind == 's', None),
+            'I' | 'i' => (4, self.kind == 'i', None),
+            'L' | 'l' if self.native => (8, self.kind == 'l', None),
+            'L' | 'l' => (4, self.kind == 'l', None),
+            'Q' | 'q' => (8, self.kind == 'q', None),
+            'J' | 'j' => (std::mem::size_of::<usize>(), self.kind == 'j', None),
+            'n' => (2, false, Some(true)),
+            'N' => (4, false, Some(true)),
+            'v' => (2, false, Some(false)),
+            'V' => (4, false, Some(false)),
+            _ => return None,
+        };
+        let native = cfg!(target_endian = "big");
+        Some((size, signed, order.or(self.big_endian).unwrap_or(native)))
+    }
+
+    /// The size in bytes and the byte order (true for big endian) of a float directive.
+    fn float_spec(&self) -> Option<(usize, bool)> {
+        let native = cfg!(target_endian = "big");
+        match self.kind {
+            'e' => Some((4, false)),
+            'E' => Some((8, false)),
+            'f' | 'F' => Some((4, native)),
+            'd' | 'D' => Some((8, native)),
+            'g' => Some((4, true)),
+            'G' => Some((8, true)),
+            _ => None,
+        }
+    }
+}
+
+fn parse_template(vm: &VM, template: &str) -> Result<Vec<Directive>, RubyError> {
+    let mut directives = vec![];
+    let mut chars = template.chars().peekable();
+    while let Some(kind) = chars.next() {
+        if kind.is_whitespace() {
+            continue;
+        }
+        if kind == '#' {
+            for ch in chars.by_ref() {
+                if ch == '\n' {
+                    break;
+                }
+            }
+            continue;
+        }
+        let mut native = false;
+        let mut big_endian = None;
+        while let Some(&ch) = chars.peek() {
+            match ch {
+                '_' | '!' | '<' | '>' => {
+                    if !"sSiIlLqQjJ".contains(kind) {
+                        return Err(vm.error_argument(format!(
+                            "'{}' allowed only after types sSiIlLqQjJ",
+                            ch
+                        )));
+                    }
+                    if ch == '_' || ch == '!' {
+                        native = true;
+                    } else {
+                        big_endian = Some(ch == '>');
+                    }
+                    chars.next();
+                }
+                _ => break,
+            }
+        }
+        let count = match chars.peek() {
+            Some('*') => {
+                chars.next();
+                Count::Star
+            }
+            Some(ch) if ch.is_ascii_digit() => {
+                let mut n = 0usize;
+                while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    n = n.saturating_mul(10).saturating_add(d as usize);
+                    chars.next();
+                }
+                Count::Fixed(n)
+            }
+            _ => Count::Implicit,
+        };
+        directives.push(Directive {
+            kind,
+            native,
+            big_endian,
+            count,
+        });
+    }
+    Ok(directives)
+}
+
+fn unknown_directive(vm: &VM, kind: char, template: &str) -> RubyError {
+    vm.error_argument(format!(
+        "unknown pack directive '{}' in '{}'",
+        kind, template
+    ))
+}
+
+fn to_integer(vm: &mut VM, val: Value) -> Result<i64, RubyError> {
+    if let Some(i) = val.as_fixnum() {
+        Ok(i)
+    } else if let Some(f) = val.as_flonum() {
+        Ok(f as i64)
+    } else {
+        let class = vm.globals.get_class_name(val);
+        Err(vm.error_type(format!("no implicit conversion of {} into Integer", class)))
+    }
+}
+
+fn to_float(vm: &mut VM, val: Value) -> Result<f64, RubyError> {
+    if let Some(i) = val.as_fixnum() {
+        Ok(i as f64)
+    } else if let Some(f) = val.as_flonum() {
+        Ok(f)
+    } else {
+        let class = vm.globals.get_class_name(val);
+        Err(vm.error_type(format!("no implicit conversion of {} into Float", class)))
+    }
+}
+
+fn to_bytes(vm: &mut VM, val: Value) -> Result<Vec<u8>, RubyError> {
+    match val.as_bytes() {
+        Some(bytes) => Ok(bytes.to_vec()),
+        None => {
+            let class = vm.globals.get_class_name(val);
+            Err(vm.error_type(format!("no implicit conversion of {} into String", class)))
+        }
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Encode `bytes` in base64, putting a newline after each `line` bytes of input.
+/// When `line` is 0, no newline is inserted.
+fn encode_base64(bytes: &[u8], line: usize, out: &mut Vec<u8>) {
+    let chunks: Vec<&[u8]> = match line {
+        0 => vec![bytes],
+        _ => bytes.chunks(line).collect(),
+    };
+    for chunk in chunks {
+        for group in chunk.chunks(3) {
+            let n = group
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= group.len() {
+                    out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize]);
+                } else {
+                    out.push(b'=');
+                }
+            }
+        }
+        if line != 0 {
+            out.push(b'\n');
+        }
+    }
+}
+
+fn decode_base64(vm: &VM, bytes: &[u8], strict: bool) -> Result<Vec<u8>, RubyError> {
+    let mut res = vec![];
+    let (mut n, mut bits) = (0u32, 0);
+    let mut padding = 0;
+    for b in bytes {
+        let v = match BASE64.iter().position(|c| c == b) {
+            Some(v) if padding == 0 => v as u32,
+            _ if *b == b'=' => {
+                padding += 1;
+                continue;
+            }
+            _ if strict => return Err(vm.error_argument("invalid base64")),
+            _ => continue,
+        };
+        n = n << 6 | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            res.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+    if strict && (bits >= 6 || (bits + padding * 6) % 8 != 0 || padding > 2) {
+        return Err(vm.error_argument("invalid base64"));
+    }
+    Ok(res)
+}
+
+/// Encode `bytes` in quoted-printable, breaking lines longer than `line` characters.
+fn encode_qp(bytes: &[u8], line: usize, out: &mut Vec<u8>) {
+    let mut n = 0;
+    let mut prev = None;
+    for &b in bytes {
+        if b > 126 || (b < 32 && b != b'\n' && b != b'\t') || b == b'=' {
+            out.extend_from_slice(&[b'=', HEX[(b >> 4) as usize], HEX[(b & 15) as usize]]);
+            n += 3;
+            prev = None;
+        } else if b == b'\n' {
+            if prev == Some(b' ') || prev == Some(b'\t') {
+                out.extend_from_slice(b"=\n");
+            }
+            out.push(b);
+            n = 0;
+            prev = Some(b);
+            continue;
+        } else {
+            out.push(b);
+            n += 1;
+            prev = Some(b);
+        }
+        if n > line {
+            out.extend_from_slice(b"=\n");
+            n = 0;
+            prev = Some(b'\n');
+        }
+    }
+    if n > 0 {
+        out.extend_from_slice(b"=\n");
+    }
+}
+
+fn decode_qp(bytes: &[u8]) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16);
+    let mut res = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'=' {
+            if bytes.get(i + 1) == Some(&b'\n') {
+                i += 2;
+                continue;
+            }
+            if bytes.get(i + 1) == Some(&b'\r') && bytes.get(i + 2) == Some(&b'\n') {
+                i += 3;
+                continue;
+            }
+            if let (Some(h), Some(l)) = (
+                bytes.get(i + 1).and_then(|b| hex(*b)),
+                bytes.get(i + 2).and_then(|b| hex(*b)),
+            ) {
+                res.push((h << 4 | l) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        res.push(bytes[i]);
+        i += 1;
+    }
+    res
+}
+
+/// Encode `bytes` with uuencode, `line` bytes of input per line.
+fn encode_uu(bytes: &[u8], line: usize, out: &mut Vec<u8>) {
+    let uu = |n: u32| if n == 0 { b'`' } else { n as u8 + b' ' };
+    for chunk in bytes.chunks(line) {
+        out.push(uu(chunk.len() as u32));
+        for group in chunk.chunks(3) {
+            let n = group
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                out.push(uu(n >> (18 - 6 * i) & 0x3f));
+            }
+        }
+        out.push(b'\n');
+    }
+}
+
+fn decode_uu(bytes: &[u8]) -> Vec<u8> {
+    let mut res = vec![];
+    for line in bytes.split(|b| *b == b'\n') {
+        let (len, body) = match line.split_first() {
+            Some((len, body)) => ((len.wrapping_sub(b' ') & 0x3f) as usize, body),
+            None => continue,
+        };
+        let mut decoded = vec![];
+        for group in body.chunks(4) {
+            let n = group.iter().enumerate().fold(0u32, |n, (i, b)| {
+                n | ((b.wrapping_sub(b' ') & 0x3f) as u32) << (18 - 6 * i)
+            });
+            decoded.extend_from_slice(&[(n >> 16) as u8, (n >> 8) as u8, n as u8]);
+        }
+        decoded.truncate(len);
+        res.extend(decoded);
+    }
+    res
+}
+
+fn next_item(vm: &VM, items: &[Value], idx: &mut usize) -> Result<Value, RubyError> {
+    match items.get(*idx) {
+        Some(item) => {
+            *idx += 1;
+            Ok(*item)
+        }
+        None => Err(vm.error_argument("too few arguments")),
+    }
+}
+
+/// Pack `items` into a binary string according to `template`.
+pub fn pack(vm: &mut VM, items: &[Value], template: &str) -> Result<RString, RubyError> {
+    let directives = parse_template(vm, template)?;
+    let mut idx = 0;
+    let mut out: Vec<u8> = vec![];
+    for d in &directives {
+        if let Some((size, _, big_endian)) = d.int_spec() {
+            for _ in 0..d.count.or(items.len() - idx) {
+                let item = next_item(vm, items, &mut idx)?;
+                let n = to_integer(vm, item)?;
+                let bytes = &n.to_le_bytes()[..size];
+                if big_endian {
+                    out.extend(bytes.iter().rev());
+                } else {
+                    out.extend_from_slice(bytes);
+                }
+            }
+            continue;
+        }
+        if let Some((size, big_endian)) = d.float_spec() {
+            for _ in 0..d.count.or(items.len() - idx) {
+                let item = next_item(vm, items, &mut idx)?;
+                let f = to_float(vm, item)?;
+                let bytes = match (size, big_endian) {
+                    (4, false) => (f as f32).to_le_bytes().to_vec(),
+                    (4, true) => (f as f32).to_be_bytes().to_vec(),
+                    (_, false) => f.to_le_bytes().to_vec(),
+                    (_, true) => f.to_be_bytes().to_vec(),
+                };
+                out.extend(bytes);
+            }
+            continue;
+        }
+        match d.kind {
+            'a' | 'A' | 'Z' => {
+                let item = next_item(vm, items, &mut idx)?;
+                let bytes = to_bytes(vm, item)?;
+                match d.count {
+                    Count::Star => {
+                        out.extend_from_slice(&bytes);
+                        if d.kind == 'Z' {
+                            out.push(0);
+                        }
+                    }
+                    count => {
+                        let len = count.or(0);
+                        let pad = if d.kind == 'A' { b' ' } else { 0 };
+                        out.extend(bytes.iter().take(len));
+                        out.extend(std::iter::repeat_n(pad, len.saturating_sub(bytes.len())));
+                    }
+                }
+            }
+            'B' | 'b' => {
+                let item = next_item(vm, items, &mut idx)?;
+                let bits = to_bytes(vm, item)?;
+                let len = d.count.or(bits.len());
+                let mut byte = 0u8;
+                for i in 0..len {
+                    let bit = bits.get(i).map_or(0, |b| b & 1);
+                    byte |= match d.kind {
+                        'B' => bit << (7 - i % 8),
+                        _ => bit << (i % 8),
+                    };
+                    if i % 8 == 7 {
+                        out.push(byte);
+                        byte = 0;
+                    }
+                }
+                if len % 8 != 0 {
+                    out.push(byte);
+                }
+            }
+            'H' | 'h' => {
+                let item = next_item(vm, items, &mut idx)?;
+                let digits = to_bytes(vm, item)?;
+                let len = d.count.or(digits.len());
+                let mut byte = 0u8;
+                for i in 0..len {
+                    let nibble = match digits.get(i) {
+                        Some(c) if c.is_ascii_alphabetic() => ((c & 15) + 9) & 15,
+                        Some(c) => c & 15,
+                        None => 0,
+                    };
+                    byte |= match (d.kind, i % 2) {
+                        ('H', 0) | ('h', 1) => nibble << 4,
+                        _ => nibble,
+                    };
+                    if i % 2 == 1 {
+                        out.push(byte);
+                        byte = 0;
+                    }
+                }
+                if len % 2 != 0 {
+                    out.push(byte);
+                }
+            }
+            'm' => {
+                let item = next_item(vm, items, &mut idx)?;
+                let bytes = to_bytes(vm, item)?;
+                let line = match d.count {
+                    Count::Fixed(0) => 0,
+                    count => match count.or(1) {
+                        n if n <= 2 => 45,
+                        n => n / 3 * 3,
+                    },
+                };
+                encode_base64(&bytes, line, &mut out);
+            }
+            'M' => {
+                let item = next_item(vm, items, &mut idx)?;
+                let bytes = match item.as_bytes() {
+                    Some(bytes) => bytes.to_vec(),
+                    None => vm.val_to_s(item).into_bytes(),
+                };
+                let line = match d.count.or(1) {
+                    n if n <= 1 => 72,
+                    n => n,
+                };
+                encode_qp(&bytes, line, &mut out);
+            }
+            'u' => {
+                let item = next_item(vm, items, &mut idx)?;
+                let bytes = to_bytes(vm, item)?;
+                let line = match d.count.or(0) {
+                    n if n <= 2 => 45,
+                    n => (n / 3 * 3).min(63),
+                };
+                encode_uu(&bytes, line, &mut out);
+            }
+            'U' => {
+                for _ in 0..d.count.or(items.len() - idx) {
+                    let item = next_item(vm, items, &mut idx)?;
+                    let n = to_integer(vm, item)?;
+                    let ch = match std::char::from_u32(n as u32) {
+                        Some(ch) if n >= 0 && n <= u32::MAX as i64 => ch,
+                        _ => return Err(vm.error_argument("pack(U): value out of range")),
+                    };
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+            'w' => {
+                for _ in 0..d.count.or(items.len() - idx) {
+                    let item = next_item(vm, items, &mut idx)?;
+                    let mut n = to_integer(vm, item)?;
+                    if n < 0 {
+                        return Err(vm.error_argument("can't compress negative numbers"));
+                    }
+                    let mut bytes = vec![(n & 0x7f) as u8];
+                    n >>= 7;
+                    while n > 0 {
+                        bytes.push((n & 0x7f) as u8 | 0x80);
+                        n >>= 7;
+                    }
+                    out.extend(bytes.iter().rev());
+                }
+            }
+            'x' => out.extend(std::iter::repeat_n(0, d.count.or(0))),
+            'X' => {
+                let len = d.count.or(0);
+                if len > out.len() {
+                    return Err(vm.error_argument("X outside of string"));
+                }
+                out.truncate(out.len() - len);
+            }
+            '@' => out.resize(d.count.or(0), 0),
+            _ => return Err(unknown_directive(vm, d.kind, template)),
+        }
+    }
+    let encoding = match directives.first() {
+        Some(d) if d.kind == 'U' => Encoding::UTF8,
+        _ => Encoding::ASCII8BIT,
+    };
+    Ok(RString::with_encoding(out, encoding))
+}
+
+/// Decode `bytes` from the byte position `offset` according to `template`.
+/// When `first` is true, stop after the first value is decoded.
+pub fn unpack(
+    vm: &mut VM,
+    bytes: &[u8],
+    template: &str,
+    offset: usize,
+    first: bool,
+) -> Result<Vec<Value>, RubyError> {
+    let directives = parse_template(vm, template)?;
+    let binary = |vm: &VM, bytes: &[u8]| {
+        let s = RString::with_encoding(bytes.to_vec(), Encoding::ASCII8BIT);
+        Value::rstring(&vm.globals, s)
+    };
+    let ascii = |vm: &VM, s: String| {
+        let s = RString::with_encoding(s.into_bytes(), Encoding::USASCII);
+        Value::rstring(&vm.globals, s)
+    };
+    if offset > bytes.len() {
+        return Err(vm.error_argument("offset outside of string"));
+    }
+    let mut res = vec![];
+    let mut pos = offset;
+    for d in &directives {
+        if first && !res.is_empty() {
+            break;
+        }
+        let rest = bytes.len() - pos;
+        if let Some((size, signed, big_endian)) = d.int_spec() {
+            for _ in 0..d.count.or(rest / size) {
+                let mut buf = match bytes.get(pos..pos + size) {
+                    Some(buf) => buf.to_vec(),
+                    None => {
+                        res.push(Value::nil());
+                        continue;
+                    }
+                };
+                pos += size;
+                if big_endian {
+                    buf.reverse();
+                }
+                let n = buf.iter().rev().fold(0u64, |n, b| n << 8 | *b as u64);
+                let shift = 64 - size * 8;
+                let n = if signed {
+                    ((n << shift) as i64) >> shift
+                } else {
+                    n as i64
+                };
+                res.push(Value::fixnum(n));
+            }
+            continue;
+        }
+        if let Some((size, big_endian)) = d.float_spec() {
+            for _ in 0..d.count.or(rest / size) {
+                let mut buf = match bytes.get(pos..pos + size) {
+                    Some(buf) => buf.to_vec(),
+                    None => {
+                        res.push(Value::nil());
+                        continue;
+                    }
+                };
+                pos += size;
+                if big_endian {
+                    buf.reverse();
+                }
+                let f = match size {
+                    4 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    _ => {
+                        let mut b = [0; 8];
+                        b.copy_from_slice(&buf);
+                        f64::from_le_bytes(b)
+                    }
+                };
+                res.push(Value::flonum(f));
+            }
+            continue;
+        }
+        match d.kind {
+            'a' | 'A' => {
+                let len = d.count.or(rest).min(rest);
+                let mut s = &bytes[pos..pos + len];
+                if d.kind == 'A' {
+                    while let Some((b' ', rest)) | Some((0, rest)) = s.split_last() {
+                        s = rest;
+                    }
+                }
+                res.push(binary(vm, s));
+                pos += len;
+            }
+            'Z' => {
+                let len = d.count.or(rest).min(rest);
+                let s = &bytes[pos..pos + len];
+                match s.iter().position(|b| *b == 0) {
+                    Some(i) => {
+                        res.push(binary(vm, &s[..i]));
+                        pos += if d.count == Count::Star { i + 1 } else { len };
+                    }
+                    None => {
+                        res.push(binary(vm, s));
+                        pos += len;
+                    }
+                }
+            }
+            'B' | 'b' => {
+                let len = d.count.or(rest * 8).min(rest * 8);
+                let bits: String = (0..len)
+                    .map(|i| {
+                        let byte = bytes[pos + i / 8];
+                        let bit = match d.kind {
+                            'B' => byte >> (7 - i % 8) & 1,
+                            _ => byte >> (i % 8) & 1,
+                        };
+                        if bit == 1 {
+                            '1'
+                        } else {
+                            '0'
+                        }
+                    })
+                    .collect();
+                res.push(ascii(vm, bits));
+                pos += len.div_ceil(8);
+            }
+            'H' | 'h' => {
+                let len = d.count.or(rest * 2).min(rest * 2);
+                let digits: String = (0..len)
+                    .map(|i| {
+                        let byte = bytes[pos + i / 2];
+                        let nibble = match (d.kind, i % 2) {
+                            ('H', 0) | ('h', 1) => byte >> 4,
+                            _ => byte & 15,
+                        };
+                        HEX[nibble as usize].to_ascii_lowercase() as char
+                    })
+                    .collect();
+                res.push(ascii(vm, digits));
+                pos += len.div_ceil(2);
+            }
+            'm' => {
+                let strict = d.count == Count::Fixed(0);
+                let decoded = decode_base64(vm, &bytes[pos..], strict)?;
+                res.push(binary(vm, &decoded));
+                pos = bytes.len();
+            }
+            'M' => {
+                let decoded = decode_qp(&bytes[pos..]);
+                res.push(binary(vm, &decoded));
+                pos = bytes.len();
+            }
+            'u' => {
+                let decoded = decode_uu(&bytes[pos..]);
+                res.push(binary(vm, &decoded));
+                pos = bytes.len();
+            }
+            'U' => {
+                let count = d.count.or(usize::MAX);
+                let mut n = 0;
+                while pos < bytes.len() && n < count {
+                    let len = match bytes[pos] {
+                        b if b < 0x80 => 1,
+                        b if b >> 5 == 0b110 => 2,
+                        b if b >> 4 == 0b1110 => 3,
+                        _ => 4,
+                    };
+                    let end = (pos + len).min(bytes.len());
+                    match std::str::from_utf8(&bytes[pos..end]) {
+                        Ok(s) => res.push(Value::fixnum(s.chars().next().unwrap() as i64)),
+                        Err(_) => return Err(vm.error_argument("malformed UTF-8 character")),
+                    }
+                    pos = end;
+                    n += 1;
+                }
+            }
+            'w' => {
+                let count = d.count.or(usize::MAX);
+                let mut n = 0;
+                while pos < bytes.len() && n < count {
+                    let mut num: i64 = 0;
+                    while let Some(b) = bytes.get(pos) {
+                        num = num << 7 | (b & 0x7f) as i64;
+                        pos += 1;
+                        if b & 0x80 == 0 {
+                            break;
+                        }
+                    }
+                    res.push(Value::fixnum(num));
+                    n += 1;
+                }
+            }
+            'x' => {
+                let len = d.count.or(0);
+                if len > rest {
+                    return Err(vm.error_argument("x outside of string"));
+                }
+                pos += len;
+            }
+            'X' => {
+                let len = d.count.or(0);
+                if len > pos - offset {
+                    return Err(vm.error_argument("X outside of string"));
+                }
+                pos -= len;
+            }
+            '@' => {
+                let len = d.count.or(0);
+                if offset + len > bytes.len() {
+                    return Err(vm.error_argument("@ outside of string"));
+                }
+                pos = offset + len;
+            }
+            _ => return Err(unknown_directive(vm, d.kind, template)),
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn pack_integer() {
+        let program = r#"
+        assert [1, 2, 255], [1, 2, 255].pack("C*").bytes
+        assert [255, 128], [-1, -128].pack("c2").bytes
+        assert [1, 2], [0x0201].pack("S").bytes
+        assert [2, 1], [0x0201].pack("S>").bytes
+        assert [2, 1], [0x0201].pack("n").bytes
+        assert [1, 2, 0, 0], [0x0201].pack("V").bytes
+        assert [0, 0, 2, 1], [0x0201].pack("N").bytes
+        assert 8, [1].pack("Q").size
+        assert 8, [1].pack("l!").size
+        assert [1, 0, 0, 0, 0, 0, 0, 0], [1].pack("q<").bytes
+        assert [0x81, 0], [128].pack("w").bytes
+        assert Encoding::ASCII_8BIT, [1].pack("C").encoding
+        assert Encoding::UTF_8, [233].pack("U").encoding
+        assert "hé", [104, 233].pack("U*")
+        assert [1, 2, 255], [1, 2, 255].pack("C*").unpack("C*")
+        assert [-1, -128], [-1, -128].pack("c*").unpack("c*")
+        assert [513, 258], [1, 2, 1, 2].pack("C*").unpack("S<S>")
+        assert [65535, -1], [-1, -1].pack("Ss").unpack("Ss")
+        assert [0x01020304], [0x01020304].pack("N").unpack("N")
+        assert [0x01020304], [0x01020304].pack("V").unpack("V")
+        assert [-2], [-2].pack("q").unpack("q")
+        assert [1, nil], [1].pack("C").unpack("CC")
+        assert [128, 1], [128, 1].pack("w*").unpack("w*")
+        assert 2, [1, 2].pack("C*").unpack1("@1C")
+        assert [2], "\x01\x02".b.unpack("C", offset: 1)
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn pack_string() {
+        let program = r#"
+        assert [97, 98, 0, 0], ["ab"].pack("a4").bytes
+        assert "ab  ", ["ab"].pack("A4")
+        assert [97, 98, 0], ["ab"].pack("Z*").bytes
+        assert "a", ["abc"].pack("a")
+        s = [97, 98, 0, 97, 98, 32, 0, 97, 98, 0].pack("C*")
+        assert ["ab", "ab", "ab"], s.unpack("Z*A4a2")
+        assert ["ab"], s.unpack("Z5")
+        assert [97, 98], ["0110000101100010"].pack("B*").bytes
+        assert ["0110000101100010"], "ab".unpack("B*")
+        assert ["10000110"], "a".unpack("b*")
+        assert [0xa1, 0xb0], ["a1b"].pack("H*").bytes
+        assert ["61626"], "abc".unpack("H5")
+        assert ["1626"], "ab".unpack("h*")
+        assert "aGVsbG8=\n", ["hello"].pack("m")
+        assert "aGVsbG8gd29ybGQ=", ["hello world"].pack("m0")
+        assert ["hello"], "aGVsbG8=\n".unpack("m")
+        assert "hello world", "aGVsbG8gd29ybGQ=".unpack1("m0")
+        assert "caf=C3=A9=\n", ["café"].pack("M")
+        assert "café", "caf=C3=A9=\n".unpack1("M").force_encoding("UTF-8")
+        assert "%:&5L;&\\`\n", ["hello"].pack("u")
+        assert "hello", "%:&5L;&\\`\n".unpack1("u")
+        assert "hello", ["hello"].pack("u").unpack1("u")
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn pack_float_position() {
+        let program = r#"
+        assert [0, 0, 128, 63], [1.0].pack("e").bytes
+        assert [63, 128, 0, 0], [1.0].pack("g").bytes
+        assert [63, 248, 0, 0, 0, 0, 0, 0], [1.5].pack("G").bytes
+        assert [1.5, 2.5], [1.5, 2.5].pack("E*").unpack("E*")
+        assert [3.0], [3].pack("E").unpack("E")
+        assert [-0.25], [-0.25].pack("d").unpack("d")
+        assert [0.5], [0.5].pack("f").unpack("F")
+        assert [1, 0, 0, 2], [1, 2].pack("Cx2C").bytes
+        assert [2], [1, 2].pack("CXC").bytes
+        assert [1, 0, 0, 0, 2], [1, 2].pack("C@4C").bytes
+        assert [1, 3], [1, 2, 3].pack("C*").unpack("Cx1C")
+        assert [1, 1], [1, 2].pack("C*").unpack("CXC")
+        assert_error { [1].pack("C2") }
+        "#;
+        assert_script(program);
+    }
+}
@@ -1,19 +1,69 @@
 use crate::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CLOCK_REALTIME: i64 = 0;
+const CLOCK_MONOTONIC: i64 = 1;
 
 pub fn init_process(globals: &mut Globals) -> Value {
     let id = globals.get_ident_id("Process");
     let class = ClassRef::from(id, globals.builtins.object);
-    let mut obj = Value::class(globals, class);
+    let obj = Value::class(globals, class);
     globals.add_builtin_class_method(obj, "clock_gettime", clock_gettime);
+    let id = globals.get_ident_id("CLOCK_REALTIME");
+    obj.set_var(globals, id, Value::fixnum(CLOCK_REALTIME));
     let id = globals.get_ident_id("CLOCK_MONOTONIC");
-    obj.set_var(id, Value::fixnum(0));
+    obj.set_var(globals, id, Value::fixnum(CLOCK_MONOTONIC));
     obj
 }
 
 // Class methods
 
+/// Process.clock_gettime(clock_id, unit = :float_second)
 fn clock_gettime(vm: &mut VM, _: Value, args: &Args) -> VMResult {
-    vm.check_args_num(args.len(), 1)?;
-    let duration = vm.globals.instant.elapsed();
-    Ok(Value::flonum(duration.as_secs_f64()))
+    vm.check_args_range(args.len(), 1, 2)?;
+    let duration = match args[0].as_fixnum() {
+        Some(CLOCK_REALTIME) => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default(),
+        Some(CLOCK_MONOTONIC) => vm.globals.instant.elapsed(),
+        _ => return Err(vm.error_argument("Invalid clock id.")),
+    };
+    let unit = match args.get(1) {
+        Some(unit) => match unit.as_symbol() {
+            Some(id) => vm.globals.get_ident_name(id).to_string(),
+            None => return Err(vm.error_argument("Unit must be a Symbol.")),
+        },
+        None => "float_second".to_string(),
+    };
+    let nanos = duration.as_nanos() as i64;
+    let val = match unit.as_str() {
+        "float_second" => Value::flonum(duration.as_secs_f64()),
+        "float_millisecond" => Value::flonum(nanos as f64 / 1e6),
+        "float_microsecond" => Value::flonum(nanos as f64 / 1e3),
+        "second" => Value::fixnum(nanos / 1_000_000_000),
+        "millisecond" => Value::fixnum(nanos / 1_000_000),
+        "microsecond" => Value::fixnum(nanos / 1_000),
+        "nanosecond" => Value::fixnum(nanos),
+        _ => return Err(vm.error_argument(format!("unexpected unit: {}", unit))),
+    };
+    Ok(val)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn clock_gettime() {
+        let program = r#"
+        a = Process.clock_gettime(Process::CLOCK_MONOTONIC)
+        b = Process.clock_gettime(Process::CLOCK_MONOTONIC)
+        assert true, a <= b
+        assert Integer, Process.clock_gettime(Process::CLOCK_MONOTONIC, :nanosecond).class
+        assert true, Process.clock_gettime(Process::CLOCK_REALTIME, :second) >= Time.now.to_i - 1
+        assert Float, Process.clock_gettime(Process::CLOCK_REALTIME, :float_millisecond).class
+        assert_error { Process.clock_gettime(Process::CLOCK_REALTIME, :hour) }
+        "#;
+        assert_script(program);
+    }
 }
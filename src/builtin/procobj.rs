@@ -2,12 +2,67 @@ use crate::*;
 
 #[derive(Debug, Clone)]
 pub struct ProcInfo {
-    pub context: ContextRef,
+    pub kind: ProcKind,
+    /// true for a lambda. A lambda checks the number of arguments strictly,
+    /// and `return` in a lambda exits from the lambda itself.
+    pub is_lambda: bool,
+    /// MethodRef for passing this Proc as a block argument.
+    pub block: Option<MethodRef>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ProcKind {
+    /// A Proc made from a block, which is evaluated in the captured context.
+    Block(ContextRef),
+    /// A Proc implemented by a builtin function, like the one made by Symbol#to_proc.
+    Builtin(BuiltinProc),
+}
+
+/// A builtin function with the values it captures.
+/// `func` is called with the Proc object itself as self, and reads the values by `captures()`.
+#[derive(Clone)]
+pub struct BuiltinProc {
+    pub func: BuiltinFunc,
+    pub captures: Vec<Value>,
+    pub arity: i64,
+}
+
+impl std::fmt::Debug for BuiltinProc {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "BuiltinProc(arity: {})", self.arity)
+    }
 }
 
 impl ProcInfo {
-    pub fn new(context: ContextRef) -> Self {
-        ProcInfo { context }
+    pub fn new(context: ContextRef, is_lambda: bool) -> Self {
+        ProcInfo {
+            kind: ProcKind::Block(context),
+            is_lambda,
+            block: None,
+        }
+    }
+
+    /// The context of a Proc made from a block, or None for a builtin Proc.
+    pub fn context(&self) -> Option<ContextRef> {
+        match &self.kind {
+            ProcKind::Block(context) => Some(*context),
+            ProcKind::Builtin(_) => None,
+        }
+    }
+
+    /// The values captured by a builtin Proc.
+    pub fn captures(&self) -> &[Value] {
+        match &self.kind {
+            ProcKind::Block(_) => &[],
+            ProcKind::Builtin(builtin) => &builtin.captures,
+        }
+    }
+
+    pub fn arity(&self) -> i64 {
+        match &self.kind {
+            ProcKind::Block(context) => context.iseq_ref.arity(self.is_lambda),
+            ProcKind::Builtin(builtin) => builtin.arity,
+        }
     }
 }
 
@@ -15,7 +70,19 @@ pub type ProcRef = Ref<ProcInfo>;
 
 impl ProcRef {
     pub fn from(context: ContextRef) -> Self {
-        ProcRef::new(ProcInfo::new(context))
+        ProcRef::new(ProcInfo::new(context, false))
+    }
+
+    pub fn from_lambda(context: ContextRef) -> Self {
+        ProcRef::new(ProcInfo::new(context, true))
+    }
+
+    pub fn from_builtin(builtin: BuiltinProc, is_lambda: bool) -> Self {
+        ProcRef::new(ProcInfo {
+            kind: ProcKind::Builtin(builtin),
+            is_lambda,
+            block: None,
+        })
     }
 }
 
@@ -24,6 +91,17 @@ pub fn init_proc(globals: &mut Globals) -> Value {
     let class = ClassRef::from(proc_id, globals.builtins.object);
     let obj = Value::class(globals, class);
     globals.add_builtin_instance_method(class, "call", proc_call);
+    globals.add_builtin_instance_method(class, "yield", proc_call);
+    globals.add_builtin_instance_method(class, "[]", proc_call);
+    globals.add_builtin_instance_method(class, "===", proc_call);
+    globals.add_builtin_instance_method(class, "to_proc", to_proc);
+    globals.add_builtin_instance_method(class, "lambda?", lambda);
+    globals.add_builtin_instance_method(class, "arity", arity);
+    globals.add_builtin_instance_method(class, "parameters", parameters);
+    globals.add_builtin_instance_method(class, "curry", curry);
+    globals.add_builtin_instance_method(class, ">>", compose_right);
+    globals.add_builtin_instance_method(class, "<<", compose_left);
+    globals.add_builtin_instance_method(class, "binding", binding);
     globals.add_builtin_class_method(obj, "new", proc_new);
     obj
 }
@@ -38,18 +116,296 @@ fn proc_new(vm: &mut VM, _: Value, args: &Args) -> VMResult {
 
 // Instance methods
 
+fn expect_proc(vm: &VM, val: Value) -> Result<ProcRef, RubyError> {
+    match val.as_proc() {
+        Some(pref) => Ok(pref),
+        None => Err(vm.error_unimplemented("Expected Proc object.")),
+    }
+}
+
 fn proc_call(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
-    let pref = match self_val.as_proc() {
-        Some(pref) => pref,
-        None => return Err(vm.error_unimplemented("Expected Proc object.")),
+    vm.eval_proc(self_val, args)
+}
+
+fn to_proc(_vm: &mut VM, self_val: Value, _args: &Args) -> VMResult {
+    Ok(self_val)
+}
+
+fn lambda(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let pref = expect_proc(vm, self_val)?;
+    Ok(Value::bool(pref.is_lambda))
+}
+
+fn arity(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let pref = expect_proc(vm, self_val)?;
+    Ok(Value::fixnum(pref.arity()))
+}
+
+fn parameters(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let pref = expect_proc(vm, self_val)?;
+    let req = if pref.is_lambda { "req" } else { "opt" };
+    let context = match pref.context() {
+        Some(context) => context,
+        None => {
+            // A builtin Proc has no parameter names, so lists only the kinds of them.
+            let arity = pref.arity();
+            let required = if arity < 0 { -arity - 1 } else { arity };
+            let mut ary = vec![];
+            for _ in 0..required {
+                let kind = Value::symbol(vm.globals.get_ident_id(req));
+                ary.push(Value::array_from(&vm.globals, vec![kind]));
+            }
+            if arity < 0 {
+                let kind = Value::symbol(vm.globals.get_ident_id("rest"));
+                ary.push(Value::array_from(&vm.globals, vec![kind]));
+            }
+            return Ok(Value::array_from(&vm.globals, ary));
+        }
     };
-    let context = Context::from_args(
-        vm,
-        self_val,
-        pref.context.iseq_ref,
-        args,
-        pref.context.outer,
-    )?;
-    let res = vm.run_context(ContextRef::from_local(&context))?;
-    Ok(res)
+    let params = &context.iseq_ref.params;
+    // Parameters are listed in the order of required, optional, rest, post-required, keyword,
+    // keyword rest and block.
+    let opt_pos = params.req_params;
+    let rest_pos = opt_pos + params.opt_params;
+    let post_pos = rest_pos + if params.rest_param { 1 } else { 0 };
+    let kw_pos = post_pos + params.post_params;
+    let kwrest_pos = kw_pos + params.keyword_params.len();
+    let block_pos = kwrest_pos + if params.kwrest_param.is_some() { 1 } else { 0 };
+    let mut ary = vec![];
+    for (i, id) in params.param_ident.iter().enumerate() {
+        let kind = if i < opt_pos {
+            req
+        } else if i < rest_pos {
+            "opt"
+        } else if i < post_pos {
+            "rest"
+        } else if i < kw_pos {
+            req
+        } else if i < kwrest_pos {
+            if params.req_keyword_params.contains(id) {
+                "keyreq"
+            } else {
+                "key"
+            }
+        } else if i < block_pos {
+            "keyrest"
+        } else {
+            "block"
+        };
+        let kind = Value::symbol(vm.globals.get_ident_id(kind));
+        // Anonymous params are named by their operators, as `[:rest, :*]`.
+        let id = if id.is_internal() {
+            let name = vm.globals.get_ident_name(*id).to_string();
+            vm.globals.get_ident_id(name)
+        } else {
+            *id
+        };
+        ary.push(Value::array_from(
+            &vm.globals,
+            vec![kind, Value::symbol(id)],
+        ));
+    }
+    Ok(Value::array_from(&vm.globals, ary))
+}
+
+fn curry(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let pref = expect_proc(vm, self_val)?;
+    let arity = pref.arity();
+    let required = if arity < 0 { -arity - 1 } else { arity };
+    let arity = if args.len() == 0 || args[0].is_nil() {
+        required
+    } else {
+        let given = vm.expect_integer(args[0], "Arity")?;
+        if pref.is_lambda && (given < required || arity >= 0 && given != arity) {
+            return Err(vm.error_argument(format!(
+                "Wrong number of arguments. (given {}, expected {})",
+                given, required
+            )));
+        }
+        given
+    };
+    let captures = vec![self_val, Value::fixnum(arity)];
+    Ok(Value::builtin_proc(
+        &vm.globals,
+        curried,
+        captures,
+        -1,
+        pref.is_lambda,
+    ))
+}
+
+/// The body of a curried Proc, which captures the original Proc, the arity and
+/// the arguments given so far.
+/// The original Proc is called when enough arguments are given, otherwise
+/// a new curried Proc which captures all of the arguments is returned.
+fn curried(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let pref = expect_proc(vm, self_val)?;
+    let mut captures = pref.captures().to_vec();
+    captures.extend(args.iter());
+    let arity = captures[1].as_fixnum().unwrap();
+    if captures.len() as i64 - 2 < arity {
+        return Ok(Value::builtin_proc(
+            &vm.globals,
+            curried,
+            captures,
+            -1,
+            pref.is_lambda,
+        ));
+    }
+    let mut args = Args::new(0);
+    for val in &captures[2..] {
+        args.push(*val);
+    }
+    vm.eval_proc(captures[0], &args)
+}
+
+/// Returns a Proc which is the composition of `first` and `second`.
+/// The arguments are passed to `first`, and the result of `first` is passed to `second`.
+fn compose(vm: &mut VM, is_lambda: bool, first: Value, second: Value) -> VMResult {
+    let captures = vec![first, second];
+    Ok(Value::builtin_proc(
+        &vm.globals,
+        composed,
+        captures,
+        -1,
+        is_lambda,
+    ))
+}
+
+/// The body of a composed Proc. Both of the functions are called by `call`,
+/// so that a Method or any other callable object can be composed.
+fn composed(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let pref = expect_proc(vm, self_val)?;
+    let (first, second) = (pref.captures()[0], pref.captures()[1]);
+    let call = vm.globals.get_ident_id("call");
+    let method = vm.get_method(first, call)?;
+    let val = vm.eval_send(method, first, args)?;
+    let method = vm.get_method(second, call)?;
+    vm.eval_send(method, second, &Args::new1(val))
+}
+
+fn compose_right(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let pref = expect_proc(vm, self_val)?;
+    compose(vm, pref.is_lambda, self_val, args[0])
+}
+
+fn compose_left(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let pref = expect_proc(vm, self_val)?;
+    compose(vm, pref.is_lambda, args[0], self_val)
+}
+
+fn binding(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let pref = expect_proc(vm, self_val)?;
+    let context = match pref.context() {
+        Some(context) => context.outer.unwrap_or(context),
+        None => return Err(vm.error_argument("Can't create Binding from builtin Proc.")),
+    };
+    Ok(vm.create_binding_from(context))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn proc_and_lambda() {
+        let program = r#"
+    pr = proc { |x, y| [x, y] }
+    la = lambda { |x, y| [x, y] }
+    assert(false, pr.lambda?)
+    assert(true, la.lambda?)
+    assert(true, ->(){}.lambda?)
+    assert([1, nil], pr.call(1))
+    assert([1, 2], pr.call([1, 2]))
+    assert([1, 2], la.call(1, 2))
+    assert_error { la.call(1) }
+    assert_error { la.call([1, 2]) }
+    assert([3, 4], la.(3, 4))
+    assert([3, 4], la[3, 4])
+    assert([3, 4], la.yield(3, 4))
+    is_even = ->(x) { x % 2 == 0 }
+    r = case 4
+        when is_even then "even"
+        else "odd"
+        end
+    assert("even", r)
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn lambda_return() {
+        let program = r#"
+    def foo
+        la = -> { return 1 }
+        a = la.call
+        [2, a]
+    end
+    assert([2, 1], foo)
+    def bar
+        pr = proc { return 1 }
+        pr.call
+        2
+    end
+    assert(1, bar)
+    def baz
+        la = lambda do
+            [1, 2].each { |x| return x * 10 }
+            0
+        end
+        la.call + 1
+    end
+    assert(11, baz)
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn proc_arity_parameters() {
+        let program = r#"
+    assert(0, proc {}.arity)
+    assert(2, proc { |x, y| }.arity)
+    assert(1, proc { |x, y = 0| }.arity)
+    assert(-2, lambda { |x, y = 0| }.arity)
+    assert(-1, proc { |*x| }.arity)
+    assert(-3, ->(x, *y, z) {}.arity)
+    assert([[:opt, :x], [:opt, :y], [:rest, :z], [:block, :b]], proc { |x, y = 1, *z, &b| }.parameters)
+    assert([[:req, :x], [:opt, :y], [:rest, :z], [:req, :w]], ->(x, y = 1, *z, w) {}.parameters)
+    assert([[:keyreq, :a], [:key, :b], [:keyrest, :c]], ->(a:, b: 1, **c) {}.parameters)
+    assert([[:req, :x], [:rest, :*], [:keyrest, :**], [:block, :&]], ->(x, *, **, &) {}.parameters)
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn proc_curry_compose() {
+        let program = r#"
+    add = ->(a, b, c) { a + b + c }
+    c = add.curry
+    assert(6, c[1][2][3])
+    assert(6, c[1, 2][3])
+    assert(6, c.(1).(2, 3))
+    assert(true, c.lambda?)
+    sum = proc { |*a| a.size }
+    assert(3, sum.curry(3)[1][2][3])
+    assert_error { add.curry(2) }
+    f = proc { |x| x * 2 }
+    g = proc { |x| x + 1 }
+    assert(7, (f >> g).call(3))
+    assert(8, (f << g).call(3))
+    assert(false, (f >> g).lambda?)
+    def triple(x)
+        x * 3
+    end
+    assert(18, (f >> method(:triple)).call(3))
+    "#;
+        assert_script(program);
+    }
 }
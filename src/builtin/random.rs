@@ -0,0 +1,189 @@
+use crate::*;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+#[derive(Debug, Clone)]
+pub struct RandomInfo {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl RandomInfo {
+    pub fn new(seed: u64) -> Self {
+        RandomInfo {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+}
+
+pub type RandomRef = Ref<RandomInfo>;
+
+pub fn init_random(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("Random");
+    let class = ClassRef::from(id, globals.builtins.object);
+    let obj = Value::class(globals, class);
+    globals.add_builtin_class_method(obj, "new", random_new);
+    globals.add_builtin_class_method(obj, "rand", random_rand);
+    globals.add_builtin_class_method(obj, "new_seed", new_seed);
+    globals.add_builtin_instance_method(class, "rand", rand);
+    globals.add_builtin_instance_method(class, "bytes", bytes);
+    globals.add_builtin_instance_method(class, "seed", seed);
+    obj
+}
+
+fn random_seed() -> u64 {
+    rand::random::<u64>() >> 1
+}
+
+/// Generate a random number for `rand(max)`.
+/// `max` may be nil for a Float in [0, 1), a positive Integer or Float for a number in [0, max),
+/// or a Range of Integer or Float.
+pub fn gen_value(vm: &mut VM, rng: &mut dyn RngCore, max: Option<Value>) -> VMResult {
+    let max = match max {
+        Some(max) if !max.is_nil() => max,
+        _ => return Ok(Value::flonum(rng.gen::<f64>())),
+    };
+    if let Some(n) = max.as_fixnum() {
+        if n > 0 {
+            return Ok(Value::fixnum(rng.gen_range(0, n)));
+        }
+    } else if let Some(f) = max.as_flonum() {
+        if f > 0.0 {
+            return Ok(Value::flonum(rng.gen::<f64>() * f));
+        }
+    } else if let Some(range) = max.as_range() {
+        match (range.start.unpack(), range.end.unpack()) {
+            (RV::Integer(start), RV::Integer(end)) => {
+                let end = if range.exclude { end } else { end + 1 };
+                if start < end {
+                    return Ok(Value::fixnum(rng.gen_range(start, end)));
+                }
+                return Ok(Value::nil());
+            }
+            (RV::Integer(_), RV::Float(_))
+            | (RV::Float(_), RV::Integer(_))
+            | (RV::Float(_), RV::Float(_)) => {
+                let to_f = |val: Value| match val.unpack() {
+                    RV::Integer(i) => i as f64,
+                    RV::Float(f) => f,
+                    _ => unreachable!(),
+                };
+                let (start, end) = (to_f(range.start), to_f(range.end));
+                if start < end || (start == end && !range.exclude) {
+                    return Ok(Value::flonum(start + rng.gen::<f64>() * (end - start)));
+                }
+                return Ok(Value::nil());
+            }
+            _ => {}
+        }
+    } else {
+        let class = vm.globals.get_class_name(max);
+        return Err(vm.error_type(format!("no implicit conversion of {} into Integer", class)));
+    }
+    let inspect = vm.val_inspect(max);
+    Err(vm.error_argument(format!("invalid argument - {}", inspect)))
+}
+
+/// The generator given by the `random:` keyword argument.
+pub fn random_arg(vm: &mut VM, args: &Args) -> Result<Option<RandomRef>, RubyError> {
+    let val = match args.kw_arg {
+        Some(opt) => {
+            let key = Value::symbol(vm.globals.get_ident_id("random"));
+            opt.as_hash().unwrap().get(&key).cloned()
+        }
+        None => None,
+    };
+    match val {
+        Some(val) => match val.as_random() {
+            Some(random) => Ok(Some(random)),
+            None => {
+                let class = vm.globals.get_class_name(val);
+                Err(vm.error_type(format!("wrong argument type {} (expected Random)", class)))
+            }
+        },
+        None => Ok(None),
+    }
+}
+
+// Class methods
+
+fn random_new(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let seed = match args.len() {
+        0 => random_seed(),
+        _ => args[0].expect_integer(vm, "Seed")? as u64,
+    };
+    let random = RandomRef::new(RandomInfo::new(seed));
+    Ok(Value::random(&vm.globals, random))
+}
+
+fn random_rand(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let max = args.first().cloned();
+    gen_value(vm, &mut rand::thread_rng(), max)
+}
+
+fn new_seed(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::fixnum(random_seed() as i64))
+}
+
+// Instance methods
+
+fn rand(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let mut random = self_val.as_random().unwrap();
+    let max = args.first().cloned();
+    gen_value(vm, &mut random.rng, max)
+}
+
+fn bytes(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let mut random = self_val.as_random().unwrap();
+    let len = args[0].expect_integer(vm, "1st arg")?;
+    if len < 0 {
+        return Err(vm.error_argument("negative string size (or size too big)"));
+    }
+    let mut bytes = vec![0; len as usize];
+    random.rng.fill_bytes(&mut bytes);
+    Ok(Value::bytes(&vm.globals, bytes))
+}
+
+fn seed(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let random = self_val.as_random().unwrap();
+    Ok(Value::fixnum(random.seed as i64))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn random() {
+        let program = r#"
+        r1 = Random.new(42)
+        r2 = Random.new(42)
+        assert 42, r1.seed
+        a = [r1.rand, r1.rand(10), r1.rand(1.5), r1.rand(3..5)]
+        b = [r2.rand, r2.rand(10), r2.rand(1.5), r2.rand(3..5)]
+        assert a, b
+        10.times do
+          x = r1.rand(3...5)
+          assert true, x == 3 || x == 4
+          f = r1.rand(2.0)
+          assert true, f >= 0 && f < 2.0
+          assert true, Random.rand(10) < 10
+        end
+        assert 8, r1.bytes(8).size
+        assert nil, r1.rand(5...5)
+        assert_error { r1.rand(-1) }
+        "#;
+        assert_script(program);
+    }
+}
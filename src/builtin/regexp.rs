@@ -1,3 +1,4 @@
+use crate::builtin::matchdata::MatchDataInfo;
 use crate::error::RubyError;
 use crate::vm::*;
 use fancy_regex::{Captures, Error, Match, Regex};
@@ -54,6 +55,8 @@ pub fn init_regexp(globals: &mut Globals) -> Value {
     globals.add_builtin_class_method(regexp, "compile", regexp_new);
     globals.add_builtin_class_method(regexp, "escape", regexp_escape);
     globals.add_builtin_class_method(regexp, "quote", regexp_escape);
+    globals.add_builtin_instance_method(classref, "match", regexp_match);
+    globals.add_builtin_instance_method(classref, "match?", regexp_match_);
     regexp
 }
 
@@ -76,6 +79,34 @@ fn regexp_escape(vm: &mut VM, _: Value, args: &Args) -> VMResult {
 
 // Instance methods
 
+fn regexp_match(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let re = self_val.as_regexp().unwrap();
+    if args[0].is_nil() {
+        return Ok(Value::nil());
+    }
+    let given = vm.expect_string(&args[0], "1st arg")?;
+    let pos = match args.len() {
+        2 => args[1].expect_integer(vm, "2nd arg")?,
+        _ => 0,
+    };
+    Regexp::match_value(vm, &re.regexp, given, pos, args.block)
+}
+
+fn regexp_match_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let re = self_val.as_regexp().unwrap();
+    if args[0].is_nil() {
+        return Ok(Value::false_val());
+    }
+    let given = vm.expect_string(&args[0], "1st arg")?;
+    let pos = match args.len() {
+        2 => args[1].expect_integer(vm, "2nd arg")?,
+        _ => 0,
+    };
+    Regexp::is_match_from(vm, &re.regexp, given, pos)
+}
+
 // Utility methods
 
 impl Regexp {
@@ -317,6 +348,60 @@ impl Regexp {
         };
     }
 
+    /// Search `given` from the byte position `pos` and set the special global variables.
+    pub fn search<'a>(
+        vm: &mut VM,
+        re: &Regexp,
+        given: &'a str,
+        pos: usize,
+    ) -> Result<Option<Captures<'a>>, RubyError> {
+        match re.captures_from_pos(given, pos) {
+            Ok(None) => Ok(None),
+            Ok(Some(captures)) => {
+                Regexp::get_captures(vm, &captures, given);
+                Ok(Some(captures))
+            }
+            Err(err) => Err(vm.error_internal(format!("Capture failed. {:?}", err))),
+        }
+    }
+
+    /// Search `given` from the character position `pos`, and return a MatchData or nil.
+    /// When `block` is given, yield the MatchData and return the value of the block.
+    pub fn match_value(
+        vm: &mut VM,
+        re: &Regexp,
+        given: &str,
+        pos: i64,
+        block: Option<MethodRef>,
+    ) -> VMResult {
+        let pos = match char_to_byte_pos(given, pos) {
+            Some(pos) => pos,
+            None => return Ok(Value::nil()),
+        };
+        let info = match Regexp::search(vm, re, given, pos)? {
+            Some(captures) => MatchDataInfo::new(re, &captures, given),
+            None => return Ok(Value::nil()),
+        };
+        let matchdata = Value::matchdata(&vm.globals, MatchDataRef::new(info));
+        match block {
+            Some(block) => vm.eval_block(block, &Args::new1(matchdata)),
+            None => Ok(matchdata),
+        }
+    }
+
+    /// Check whether `re` matches `given` from the character position `pos`,
+    /// without setting the special global variables.
+    pub fn is_match_from(vm: &VM, re: &Regexp, given: &str, pos: i64) -> VMResult {
+        let pos = match char_to_byte_pos(given, pos) {
+            Some(pos) => pos,
+            None => return Ok(Value::false_val()),
+        };
+        match re.find_from_pos(given, pos) {
+            Ok(m) => Ok(Value::bool(m.is_some())),
+            Err(err) => Err(vm.error_internal(format!("Capture failed. {:?}", err))),
+        }
+    }
+
     pub fn find_one<'a>(
         vm: &mut VM,
         re: &Regexp,
@@ -376,6 +461,20 @@ impl Regexp {
     }
 }
 
+/// Convert the character position `pos` in `given` to a byte position.
+/// A negative `pos` counts from the end. Return None if `pos` is out of the string.
+pub fn char_to_byte_pos(given: &str, pos: i64) -> Option<usize> {
+    let len = given.chars().count() as i64;
+    let pos = if pos < 0 { pos + len } else { pos };
+    if pos < 0 || pos > len {
+        return None;
+    }
+    match given.char_indices().nth(pos as usize) {
+        Some((i, _)) => Some(i),
+        None => Some(given.len()),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::test::*;
@@ -0,0 +1,184 @@
+use crate::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+pub fn init_rubyvm(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("RubyVM");
+    let class = ClassRef::from(id, globals.builtins.object);
+    let rubyvm = Value::class(globals, class);
+    let id = globals.get_ident_id("InstructionSequence");
+    let iseq_class = init_iseq(globals);
+    rubyvm.set_var(globals, id, iseq_class);
+    let id = globals.get_ident_id("JIT");
+    let jit_class = init_jit(globals);
+    rubyvm.set_var(globals, id, jit_class);
+    rubyvm
+}
+
+fn init_jit(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("RubyVM::JIT");
+    let class = ClassRef::from(id, globals.builtins.object);
+    let obj = Value::class(globals, class);
+    globals.add_builtin_class_method(obj, "enabled?", jit_enabled);
+    obj
+}
+
+fn init_iseq(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("RubyVM::InstructionSequence");
+    let class = ClassRef::from(id, globals.builtins.object);
+    let obj = Value::class(globals, class);
+    globals.add_builtin_class_method(obj, "compile", iseq_compile);
+    globals.add_builtin_class_method(obj, "of", iseq_of);
+    globals.add_builtin_class_method(obj, "compile_option", iseq_compile_option);
+    globals.add_builtin_class_method(obj, "compile_option=", iseq_set_compile_option);
+    globals.add_builtin_instance_method(class, "disasm", iseq_disasm);
+    globals.add_builtin_instance_method(class, "disassemble", iseq_disasm);
+    globals.add_builtin_instance_method(class, "eval", iseq_eval);
+    obj
+}
+
+fn new_iseq(vm: &mut VM, class: Value, method: MethodRef) -> Value {
+    let iseq = Value::ordinary_object(class);
+    let id = IdentId::ISEQ_METHOD;
+    let method: u32 = method.into();
+    iseq.set_var(&vm.globals, id, Value::fixnum(method as i64));
+    iseq
+}
+
+fn expect_iseq(vm: &mut VM, self_val: Value) -> Result<MethodRef, RubyError> {
+    let id = IdentId::ISEQ_METHOD;
+    match self_val.get_var(id).and_then(|method| method.as_fixnum()) {
+        Some(method) => Ok(MethodRef::from(method as u32)),
+        None => Err(vm.error_type("Expected InstructionSequence.")),
+    }
+}
+
+// Class methods
+
+/// Compile a source string into an InstructionSequence.
+fn iseq_compile(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let program = match args[0].as_string() {
+        Some(program) => program.to_string(),
+        None => return Err(vm.error_type("Source must be a String.")),
+    };
+    let method = vm.compile_program(PathBuf::from("<compiled>"), &program)?;
+    Ok(new_iseq(vm, self_val, method))
+}
+
+/// Returns the InstructionSequence of a Method or a Proc, or nil for builtin methods.
+fn iseq_of(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let method = if let Some(method) = args[0].as_method() {
+        match vm.globals.get_method_info(method.method) {
+            MethodInfo::RubyFunc { .. } => method.method,
+            _ => return Ok(Value::nil()),
+        }
+    } else if let Some(procobj) = args[0].as_proc() {
+        match procobj.context() {
+            Some(context) => context.iseq_ref.method,
+            None => return Ok(Value::nil()),
+        }
+    } else {
+        return Err(vm.error_type("Expected Method or Proc."));
+    };
+    Ok(new_iseq(vm, self_val, method))
+}
+
+/// Returns the options of the compiler as a Hash.
+fn iseq_compile_option(vm: &mut VM, _self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let mut option = HashMap::new();
+    let key = Value::symbol(vm.globals.get_ident_id("tailcall_optimization"));
+    let tailcall = vm.globals.compile_option.tailcall_optimization;
+    option.insert(HashKey(key), Value::bool(tailcall));
+    Ok(Value::hash_from(&vm.globals, option))
+}
+
+/// Set the options of the compiler, which apply to code compiled after this call.
+/// `tailcall_optimization: true` makes a method call in tail position reuse the frame of the caller.
+fn iseq_set_compile_option(vm: &mut VM, _self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let hash = match args[0].as_hash() {
+        Some(hash) => hash,
+        None => return Err(vm.error_type("Compile option must be a Hash.")),
+    };
+    let key = Value::symbol(vm.globals.get_ident_id("tailcall_optimization"));
+    if let Some(val) = hash.get(&key) {
+        vm.globals.compile_option.tailcall_optimization = vm.val_to_bool(*val);
+    }
+    Ok(args[0])
+}
+
+// Instance methods
+
+fn iseq_disasm(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let method = expect_iseq(vm, self_val)?;
+    let res = vm::disasm::disasm(&vm.globals, method);
+    Ok(Value::string(&vm.globals, res))
+}
+
+fn iseq_eval(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let method = expect_iseq(vm, self_val)?;
+    let main = vm.globals.main_object;
+    vm.eval_send(method, main, &Args::new0())
+}
+
+/// Returns true if the JIT compiler is enabled.
+fn jit_enabled(vm: &mut VM, _self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::bool(vm.globals.jit.enabled))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn iseq_disasm() {
+        let program = r#"
+    iseq = RubyVM::InstructionSequence.compile("a = 1\nif a > 0 then a + 2 else 0 end")
+    assert(RubyVM::InstructionSequence, iseq.class)
+    assert(3, iseq.eval)
+    asm = iseq.disasm
+    assert(true, asm.start_with?("== disasm:"))
+    assert(true, (asm =~ /local table .size: 1.: a@0/) != nil)
+    assert(true, (asm =~ /JGT L0/) != nil)
+    assert(true, (asm =~ /L1:/) != nil)
+    def foo(x, y = 2, &blk)
+        [1, 2].each { |i| x + i }
+    end
+    asm = RubyVM::InstructionSequence.of(method(:foo)).disasm
+    assert(true, (asm =~ /method 'foo'/) != nil)
+    assert(true, (asm =~ /params: req:1 opt:1 block/) != nil)
+    assert(true, (asm =~ /block in/) != nil)
+    assert(true, (asm =~ /SEND 'each' 0 items cache:/) != nil)
+    assert(nil, RubyVM::InstructionSequence.of(1.method(:to_s)))
+    pr = proc { |z| z * 3 }
+    assert(true, (RubyVM::InstructionSequence.of(pr).disasm =~ /MUL cache:/) != nil)
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn iseq_compile_option() {
+        let program = r#"
+    assert({tailcall_optimization: false}, RubyVM::InstructionSequence.compile_option)
+    RubyVM::InstructionSequence.compile_option = {tailcall_optimization: true}
+    assert({tailcall_optimization: true}, RubyVM::InstructionSequence.compile_option)
+    RubyVM::InstructionSequence.compile(
+        "def count(n, acc)\n if n == 0 then acc else count(n - 1, acc + 1) end\n end"
+    ).eval
+    assert(100000, count(100000, 0))
+    RubyVM::InstructionSequence.compile_option = {tailcall_optimization: false}
+    RubyVM::InstructionSequence.compile(
+        "def count2(n, acc)\n if n == 0 then acc else count2(n - 1, acc + 1) end\n end"
+    ).eval
+    assert(1000, count2(1000, 0))
+    assert_error { count2(100000, 0) }
+    "#;
+        assert_script(program);
+    }
+}
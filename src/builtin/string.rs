@@ -1,87 +1,219 @@
+use crate::builtin::format;
+use crate::builtin::pack;
 use crate::vm::*;
 //use std::string::FromUtf8Error;
 //#[macro_use]
 use crate::*;
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum RString {
+enum Repr {
     Str(String),
     Bytes(Vec<u8>),
 }
 
+/// A byte sequence with its encoding.
+/// The bytes are kept as `Str` while they are known to be valid as UTF-8.
+#[derive(Debug, Clone)]
+pub struct RString {
+    repr: Repr,
+    encoding: Encoding,
+}
+
 use std::cmp::Ordering;
+use std::ops::Range;
 use std::str::FromStr;
 impl RString {
     pub fn new_string(string: String) -> Self {
-        RString::Str(string)
+        RString {
+            repr: Repr::Str(string),
+            encoding: Encoding::UTF8,
+        }
     }
 
     pub fn new_bytes(bytes: Vec<u8>) -> Self {
-        RString::Bytes(bytes)
+        RString {
+            repr: Repr::Bytes(bytes),
+            encoding: Encoding::UTF8,
+        }
+    }
+
+    pub fn with_encoding(bytes: Vec<u8>, encoding: Encoding) -> Self {
+        let repr = match String::from_utf8(bytes) {
+            Ok(s) => Repr::Str(s),
+            Err(err) => Repr::Bytes(err.into_bytes()),
+        };
+        RString { repr, encoding }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
     }
 
     /// Try to take reference of String from RString.
     /// If byte sequence is invalid as UTF-8, return Err.
     /// When valid, convert the byte sequence to UTF-8 string.
     pub fn as_string(&self, vm: &VM) -> Result<&String, RubyError> {
-        match self {
-            RString::Str(s) => Ok(s),
-            RString::Bytes(bytes) => match String::from_utf8(bytes.clone()) {
+        match &self.repr {
+            Repr::Str(s) => Ok(s),
+            Repr::Bytes(bytes) => match String::from_utf8(bytes.clone()) {
                 Ok(s) => {
                     let mut_rstring = self as *const RString as *mut RString;
-                    // Convert RString::Bytes => RString::Str in place.
-                    std::mem::replace(unsafe { &mut *mut_rstring }, RString::Str(s));
-                    let s = match self {
-                        RString::Str(s) => s,
-                        RString::Bytes(_) => unreachable!(),
-                    };
-                    Ok(s)
+                    // Convert Repr::Bytes => Repr::Str in place.
+                    unsafe { (*mut_rstring).repr = Repr::Str(s) };
+                    match &self.repr {
+                        Repr::Str(s) => Ok(s),
+                        Repr::Bytes(_) => unreachable!(),
+                    }
                 }
                 Err(_) => Err(vm.error_argument("Invalid as UTF-8 string.")),
             },
         }
     }
 
+    /// Take reference of String from RString if the bytes are kept as a valid UTF-8 string.
+    pub fn as_str(&self) -> Option<&String> {
+        match &self.repr {
+            Repr::Str(s) => Some(s),
+            Repr::Bytes(_) => None,
+        }
+    }
+
     /// Take reference of [u8] from RString.
     pub fn as_bytes(&self) -> &[u8] {
-        match self {
-            RString::Str(s) => s.as_bytes(),
-            RString::Bytes(b) => b,
+        match &self.repr {
+            Repr::Str(s) => s.as_bytes(),
+            Repr::Bytes(b) => b,
+        }
+    }
+
+    /// Take mutable reference of [u8] from RString.
+    /// The bytes are no longer assumed to be valid as UTF-8.
+    pub fn as_mut_bytes(&mut self) -> &mut Vec<u8> {
+        if let Repr::Str(s) = &mut self.repr {
+            self.repr = Repr::Bytes(std::mem::take(s).into_bytes());
+        }
+        match &mut self.repr {
+            Repr::Bytes(b) => b,
+            Repr::Str(_) => unreachable!(),
+        }
+    }
+
+    pub fn is_ascii_only(&self) -> bool {
+        self.as_bytes().is_ascii()
+    }
+
+    pub fn is_valid_encoding(&self) -> bool {
+        match self.encoding {
+            Encoding::UTF8 => match &self.repr {
+                Repr::Str(_) => true,
+                Repr::Bytes(b) => std::str::from_utf8(b).is_ok(),
+            },
+            Encoding::USASCII => self.is_ascii_only(),
+            Encoding::ASCII8BIT => true,
+        }
+    }
+
+    /// Byte ranges of the characters in the string.
+    /// Characters of an ASCII-8BIT or US-ASCII string are bytes,
+    /// and each invalid byte of a UTF-8 string is a character by itself.
+    pub fn char_ranges(&self) -> Vec<Range<usize>> {
+        let bytes = self.as_bytes();
+        match (&self.repr, self.encoding) {
+            (Repr::Str(s), Encoding::UTF8) => {
+                s.char_indices().map(|(i, c)| i..i + c.len_utf8()).collect()
+            }
+            (Repr::Bytes(_), Encoding::UTF8) => {
+                let mut ranges = vec![];
+                let mut pos = 0;
+                while pos < bytes.len() {
+                    let (valid, rest) = match std::str::from_utf8(&bytes[pos..]) {
+                        Ok(s) => (s, 0),
+                        Err(err) => {
+                            let s = std::str::from_utf8(&bytes[pos..pos + err.valid_up_to()]);
+                            (s.unwrap(), 1)
+                        }
+                    };
+                    for (i, c) in valid.char_indices() {
+                        ranges.push(pos + i..pos + i + c.len_utf8());
+                    }
+                    pos += valid.len();
+                    if rest != 0 {
+                        ranges.push(pos..pos + 1);
+                        pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+                ranges
+            }
+            _ => (0..bytes.len()).map(|i| i..i + 1).collect(),
+        }
+    }
+
+    /// The number of characters.
+    pub fn char_len(&self) -> usize {
+        match (&self.repr, self.encoding) {
+            (Repr::Str(s), Encoding::UTF8) => s.chars().count(),
+            (_, Encoding::UTF8) => self.char_ranges().len(),
+            _ => self.as_bytes().len(),
+        }
+    }
+
+    /// A new string of the bytes in `range`, with the same encoding.
+    pub fn byte_slice(&self, range: Range<usize>) -> RString {
+        RString::with_encoding(self.as_bytes()[range].to_vec(), self.encoding)
+    }
+
+    /// The encoding of the concatenation of `self` and `other`, or None if they are incompatible.
+    pub fn compatible_encoding(&self, other: &RString) -> Option<Encoding> {
+        if self.encoding == other.encoding || other.is_ascii_only() {
+            Some(self.encoding)
+        } else if self.is_ascii_only() {
+            Some(other.encoding)
+        } else {
+            None
         }
     }
 
     /// Parse string as i64 or f64.
     pub fn parse<F: FromStr>(&self) -> Option<F> {
-        match self {
-            RString::Str(s) => FromStr::from_str(s).ok(),
-            RString::Bytes(bytes) => match String::from_utf8(bytes.clone()) {
-                Ok(s) => FromStr::from_str(&s).ok(),
-                Err(_) => None,
-            },
+        match std::str::from_utf8(self.as_bytes()) {
+            Ok(s) => FromStr::from_str(s).ok(),
+            Err(_) => None,
         }
     }
 
     pub fn to_s(&self) -> String {
-        match self {
-            RString::Str(s) => format!("{}", s),
-            RString::Bytes(b) => format!("{}", String::from_utf8_lossy(b)),
+        match &self.repr {
+            Repr::Str(s) => s.to_string(),
+            Repr::Bytes(b) => String::from_utf8_lossy(b).to_string(),
         }
     }
 
     pub fn inspect(&self) -> String {
-        match self {
-            RString::Str(s) => format!("\"{}\"", s.escape_debug()),
-            RString::Bytes(bytes) => match std::str::from_utf8(bytes) {
-                Ok(s) => format!("\"{}\"", s.replace("\\", "\\\\")),
-                Err(_) => {
-                    let mut s = String::new();
-                    for b in bytes {
-                        s = format!("{}\\x{:02X}", s, b);
+        if let (Repr::Str(s), Encoding::UTF8) = (&self.repr, self.encoding) {
+            return format!("\"{}\"", s.escape_debug());
+        }
+        let bytes = self.as_bytes();
+        let mut s = String::new();
+        for range in self.char_ranges() {
+            let ch = &bytes[range];
+            match std::str::from_utf8(ch) {
+                Ok(ch) if self.encoding == Encoding::UTF8 || ch.is_ascii() => {
+                    s += &ch.escape_debug().to_string()
+                }
+                _ => {
+                    for b in ch {
+                        s += &format!("\\x{:02X}", b);
                     }
-                    format!("\"{}\"", s)
                 }
-            },
+            }
         }
+        format!("\"{}\"", s)
     }
 
     pub fn cmp(&self, other: Value) -> Option<Ordering> {
@@ -90,36 +222,20 @@ impl RString {
             Some(s) => s,
             None => return None,
         };
-        if lhs.len() >= rhs.len() {
-            for (i, rhs_v) in rhs.iter().enumerate() {
-                match lhs[i].cmp(rhs_v) {
-                    Ordering::Equal => {}
-                    ord => return Some(ord),
-                }
-            }
-            if lhs.len() == rhs.len() {
-                Some(Ordering::Equal)
-            } else {
-                Some(Ordering::Greater)
-            }
-        } else {
-            for (i, lhs_v) in lhs.iter().enumerate() {
-                match lhs_v.cmp(&rhs[i]) {
-                    Ordering::Equal => {}
-                    ord => return Some(ord),
-                }
-            }
-            Some(Ordering::Less)
-        }
+        Some(lhs.cmp(rhs))
+    }
+}
+
+impl PartialEq for RString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+            && (self.encoding == other.encoding || self.is_ascii_only())
     }
 }
 
 impl std::hash::Hash for RString {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        match self {
-            RString::Str(s) => s.hash(state),
-            RString::Bytes(b) => b.hash(state),
-        };
+        self.as_bytes().hash(state);
     }
 }
 
@@ -132,24 +248,97 @@ pub fn init_string(globals: &mut Globals) -> Value {
     globals.add_builtin_instance_method(class, "*", string_mul);
     globals.add_builtin_instance_method(class, "%", string_rem);
     globals.add_builtin_instance_method(class, "[]", string_index);
+    globals.add_builtin_instance_method(class, "[]=", string_index_assign);
+    globals.add_builtin_instance_method(class, "slice", string_index);
+    globals.add_builtin_instance_method(class, "slice!", string_slice_);
+    globals.add_builtin_instance_method(class, "==", string_eq);
+    globals.add_builtin_instance_method(class, "eql?", string_eql);
+    globals.add_builtin_instance_method(class, "hash", string_hash);
     globals.add_builtin_instance_method(class, "<=>", string_cmp);
     globals.add_builtin_instance_method(class, "start_with?", string_start_with);
+    globals.add_builtin_instance_method(class, "end_with?", string_end_with);
+    globals.add_builtin_instance_method(class, "include?", string_include);
+    globals.add_builtin_instance_method(class, "index", string_index_of);
+    globals.add_builtin_instance_method(class, "rindex", string_rindex);
     globals.add_builtin_instance_method(class, "to_sym", string_to_sym);
     globals.add_builtin_instance_method(class, "intern", string_to_sym);
     globals.add_builtin_instance_method(class, "split", string_split);
     globals.add_builtin_instance_method(class, "sub", string_sub);
+    globals.add_builtin_instance_method(class, "sub!", string_sub_);
     globals.add_builtin_instance_method(class, "gsub", string_gsub);
     globals.add_builtin_instance_method(class, "gsub!", string_gsub_);
     globals.add_builtin_instance_method(class, "scan", string_scan);
     globals.add_builtin_instance_method(class, "=~", string_rmatch);
+    globals.add_builtin_instance_method(class, "match", string_match);
+    globals.add_builtin_instance_method(class, "match?", string_match_);
+    globals.add_builtin_instance_method(class, "partition", string_partition);
+    globals.add_builtin_instance_method(class, "rpartition", string_rpartition);
     globals.add_builtin_instance_method(class, "tr", string_tr);
+    globals.add_builtin_instance_method(class, "tr!", string_tr_);
+    globals.add_builtin_instance_method(class, "tr_s", string_tr_s);
+    globals.add_builtin_instance_method(class, "tr_s!", string_tr_s_);
+    globals.add_builtin_instance_method(class, "squeeze", string_squeeze);
+    globals.add_builtin_instance_method(class, "squeeze!", string_squeeze_);
+    globals.add_builtin_instance_method(class, "delete", string_delete);
+    globals.add_builtin_instance_method(class, "delete!", string_delete_);
+    globals.add_builtin_instance_method(class, "count", string_count);
     globals.add_builtin_instance_method(class, "size", string_size);
     globals.add_builtin_instance_method(class, "bytes", string_bytes);
+    globals.add_builtin_instance_method(class, "bytesize", string_bytesize);
+    globals.add_builtin_instance_method(class, "byteslice", string_byteslice);
+    globals.add_builtin_instance_method(class, "getbyte", string_getbyte);
+    globals.add_builtin_instance_method(class, "setbyte", string_setbyte);
+    globals.add_builtin_instance_method(class, "encoding", string_encoding);
+    globals.add_builtin_instance_method(class, "force_encoding", string_force_encoding);
+    globals.add_builtin_instance_method(class, "encode", string_encode);
+    globals.add_builtin_instance_method(class, "valid_encoding?", string_valid_encoding);
+    globals.add_builtin_instance_method(class, "scrub", string_scrub);
+    globals.add_builtin_instance_method(class, "b", string_b);
+    globals.add_builtin_instance_method(class, "unpack", string_unpack);
+    globals.add_builtin_instance_method(class, "unpack1", string_unpack1);
     globals.add_builtin_instance_method(class, "chars", string_chars);
+    globals.add_builtin_instance_method(class, "each_char", string_each_char);
+    globals.add_builtin_instance_method(class, "lines", string_lines);
+    globals.add_builtin_instance_method(class, "each_line", string_each_line);
     globals.add_builtin_instance_method(class, "sum", string_sum);
     globals.add_builtin_instance_method(class, "upcase", string_upcase);
+    globals.add_builtin_instance_method(class, "upcase!", string_upcase_);
+    globals.add_builtin_instance_method(class, "downcase", string_downcase);
+    globals.add_builtin_instance_method(class, "downcase!", string_downcase_);
+    globals.add_builtin_instance_method(class, "capitalize", string_capitalize);
+    globals.add_builtin_instance_method(class, "capitalize!", string_capitalize_);
+    globals.add_builtin_instance_method(class, "swapcase", string_swapcase);
+    globals.add_builtin_instance_method(class, "swapcase!", string_swapcase_);
+    globals.add_builtin_instance_method(class, "casecmp", string_casecmp);
+    globals.add_builtin_instance_method(class, "casecmp?", string_casecmp_);
+    globals.add_builtin_instance_method(class, "strip", string_strip);
+    globals.add_builtin_instance_method(class, "strip!", string_strip_);
+    globals.add_builtin_instance_method(class, "lstrip", string_lstrip);
+    globals.add_builtin_instance_method(class, "lstrip!", string_lstrip_);
+    globals.add_builtin_instance_method(class, "rstrip", string_rstrip);
+    globals.add_builtin_instance_method(class, "rstrip!", string_rstrip_);
     globals.add_builtin_instance_method(class, "chomp", string_chomp);
+    globals.add_builtin_instance_method(class, "chomp!", string_chomp_);
+    globals.add_builtin_instance_method(class, "center", string_center);
+    globals.add_builtin_instance_method(class, "ljust", string_ljust);
+    globals.add_builtin_instance_method(class, "rjust", string_rjust);
+    globals.add_builtin_instance_method(class, "insert", string_insert);
+    globals.add_builtin_instance_method(class, "replace", string_replace);
+    globals.add_builtin_instance_method(class, "reverse", string_reverse);
+    globals.add_builtin_instance_method(class, "reverse!", string_reverse_);
+    globals.add_builtin_instance_method(class, "succ", string_succ);
+    globals.add_builtin_instance_method(class, "succ!", string_succ_);
+    globals.add_builtin_instance_method(class, "next", string_succ);
+    globals.add_builtin_instance_method(class, "next!", string_succ_);
+    globals.add_builtin_instance_method(class, "upto", string_upto);
+    globals.add_builtin_instance_method(class, "ord", string_ord);
+    globals.add_builtin_instance_method(class, "unicode_normalize", string_unicode_normalize);
+    globals.add_builtin_instance_method(class, "unicode_normalize!", string_unicode_normalize_);
+    globals.add_builtin_instance_method(class, "unicode_normalized?", string_unicode_normalized);
     globals.add_builtin_instance_method(class, "to_i", string_toi);
+    globals.add_builtin_instance_method(class, "hex", string_hex);
+    globals.add_builtin_instance_method(class, "oct", string_oct);
+    globals.add_builtin_instance_method(class, "to_f", string_to_f);
     globals.add_builtin_instance_method(class, "<", lt);
     globals.add_builtin_instance_method(class, ">", gt);
 
@@ -158,8 +347,7 @@ pub fn init_string(globals: &mut Globals) -> Value {
 
 fn to_s(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     vm.check_args_num(args.len(), 0)?;
-    let self_ = self_val.as_rstring().unwrap();
-    Ok(Value::string(&vm.globals, self_.to_s()))
+    Ok(self_val)
 }
 
 fn inspect(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
@@ -174,25 +362,29 @@ fn string_add(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     let rhs = args[0]
         .as_rstring()
         .ok_or_else(|| vm.error_argument("1st arg must be String."))?;
-    match (lhs, rhs) {
-        (RString::Str(lhs), RString::Str(rhs)) => {
+    let encoding = match lhs.compatible_encoding(rhs) {
+        Some(encoding) => encoding,
+        None => {
+            return Err(vm.error_encoding(
+                EncodingErrKind::Compatibility,
+                format!(
+                    "incompatible character encodings: {} and {}",
+                    lhs.encoding().name(),
+                    rhs.encoding().name()
+                ),
+            ))
+        }
+    };
+    match (lhs.as_str(), rhs.as_str(), encoding) {
+        (Some(lhs), Some(rhs), Encoding::UTF8) => {
             let res = format!("{}{}", lhs, rhs);
             Ok(Value::string(&vm.globals, res))
         }
-        (RString::Str(lhs), RString::Bytes(rhs)) => {
-            let mut lhs = lhs.as_bytes().to_vec();
-            lhs.append(&mut rhs.to_vec());
-            Ok(Value::bytes(&vm.globals, lhs))
-        }
-        (RString::Bytes(lhs), RString::Str(rhs)) => {
-            let mut lhs = lhs.to_vec();
-            lhs.append(&mut rhs.as_bytes().to_vec());
-            Ok(Value::bytes(&vm.globals, lhs))
-        }
-        (RString::Bytes(lhs), RString::Bytes(rhs)) => {
-            let mut lhs = lhs.to_vec();
-            lhs.append(&mut rhs.to_vec());
-            Ok(Value::bytes(&vm.globals, lhs))
+        _ => {
+            let mut bytes = lhs.as_bytes().to_vec();
+            bytes.extend_from_slice(rhs.as_bytes());
+            let res = RString::with_encoding(bytes, encoding);
+            Ok(Value::rstring(&vm.globals, res))
         }
     }
 }
@@ -204,199 +396,398 @@ fn string_mul(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
         i if i < 0 => return Err(vm.error_argument("Negative argument.")),
         i => i as usize,
     };
-
-    let res = match lhs {
-        RString::Str(s) => Value::string(&vm.globals, s.repeat(rhs)),
-        RString::Bytes(b) => Value::bytes(&vm.globals, b.repeat(rhs)),
+    let mut res = match lhs.as_str() {
+        Some(s) => RString::new_string(s.repeat(rhs)),
+        None => RString::new_bytes(lhs.as_bytes().repeat(rhs)),
     };
-    Ok(res)
+    res.set_encoding(lhs.encoding());
+    Ok(Value::rstring(&vm.globals, res))
 }
 
-fn string_index(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
-    fn conv_index(i: i64, len: usize) -> Option<usize> {
-        if i >= 0 {
-            if i < len as i64 {
-                Some(i as usize)
-            } else {
-                None
-            }
-        } else {
-            match len as i64 + i {
-                n if n < 0 => None,
-                n => Some(n as usize),
-            }
+/// Resolve the arguments of `String#[]` and `String#byteslice`, which are an index,
+/// a start and a length, or a Range, into a range of positions in a sequence of `len` elements.
+/// Return None when the start is out of the sequence.
+fn index_range(vm: &VM, args: &[Value], len: usize) -> Result<Option<Range<usize>>, RubyError> {
+    let len = len as i64;
+    let start = |i: i64| if i < 0 { i + len } else { i };
+    if args.len() == 2 {
+        let i = start(args[0].expect_integer(vm, "1st arg")?);
+        let count = args[1].expect_integer(vm, "2nd arg")?;
+        if i < 0 || i > len || count < 0 {
+            return Ok(None);
         }
+        return Ok(Some(i as usize..len.min(i + count) as usize));
     }
-    vm.check_args_num(args.len(), 1)?;
-    expect_string!(lhs, vm, self_val);
-    match args[0].unpack() {
-        RV::Integer(i) => {
-            let index = match conv_index(i, lhs.chars().count()) {
-                Some(i) => i,
-                None => return Ok(Value::nil()),
-            };
-            match lhs.chars().nth(index) {
-                Some(ch) => Ok(Value::string(&vm.globals, ch.to_string())),
-                None => Ok(Value::nil()),
-            }
+    if let Some(i) = args[0].as_fixnum() {
+        let i = start(i);
+        if i < 0 || i >= len {
+            return Ok(None);
         }
-        RV::Object(oref) => match &oref.kind {
-            ObjKind::Range(info) => {
-                let len = lhs.chars().count();
-                let (start, end) = match (info.start.as_fixnum(), info.end.as_fixnum()) {
-                    (Some(start), Some(end)) => {
-                        match (conv_index(start, len), conv_index(end, len)) {
-                            (Some(start), Some(end)) if start > end => {
-                                return Ok(Value::string(&vm.globals, "".to_string()))
-                            }
-                            (Some(start), Some(end)) => (start, end),
-                            _ => return Ok(Value::nil()),
-                        }
-                    }
-                    _ => return Err(vm.error_argument("Index must be Integer.")),
-                };
-                let s: String = lhs.chars().skip(start).take(end - start + 1).collect();
-                Ok(Value::string(&vm.globals, s))
+        return Ok(Some(i as usize..i as usize + 1));
+    }
+    match args[0].as_range() {
+        Some(info) => match (info.start.as_fixnum(), info.end.as_fixnum()) {
+            (Some(first), Some(last)) => {
+                let first = start(first);
+                if first < 0 || first > len {
+                    return Ok(None);
+                }
+                let mut last = start(last);
+                if !info.exclude {
+                    last += 1;
+                }
+                Ok(Some(first as usize..last.max(first).min(len) as usize))
             }
-            _ => return Err(vm.error_argument("Bad type for index.")),
+            _ => Err(vm.error_argument("Index must be Integer.")),
         },
-        _ => return Err(vm.error_argument("Bad type for index.")),
+        None => Err(vm.error_argument("Bad type for index.")),
     }
 }
 
-fn string_cmp(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
-    vm.check_args_num(args.len(), 1)?;
-    let lhs = self_val.as_rstring().unwrap();
-    match lhs.cmp(args[0]) {
-        Some(ord) => Ok(Value::fixnum(ord as i64)),
+/// Locate the part of the string designated by the arguments of `String#[]` as a byte range,
+/// which are an index, a start and a length, a Range, a String, or a Regexp with an optional capture index.
+/// Return None if there is no such part.
+fn locate(vm: &mut VM, rstr: &RString, args: &[Value]) -> Result<Option<Range<usize>>, RubyError> {
+    if let Some(re) = args[0].as_regexp() {
+        let given = rstr.as_string(vm)?;
+        let captures = match Regexp::search(vm, &re.regexp, given, 0)? {
+            Some(captures) => captures,
+            None => return Ok(None),
+        };
+        let group = match args.len() {
+            2 => args[1].expect_integer(vm, "2nd arg")?,
+            _ => 0,
+        };
+        let group = if group < 0 {
+            group + captures.len() as i64
+        } else {
+            group
+        };
+        if group < 0 {
+            return Ok(None);
+        }
+        return Ok(captures.get(group as usize).map(|m| m.start()..m.end()));
+    }
+    if let Some(pat) = args[0].as_rstring() {
+        let (bytes, pat) = (rstr.as_bytes(), pat.as_bytes());
+        let found = if pat.is_empty() {
+            Some(0)
+        } else {
+            bytes.windows(pat.len()).position(|w| w == pat)
+        };
+        return Ok(found.map(|i| i..i + pat.len()));
+    }
+    let chars = rstr.char_ranges();
+    let range = match index_range(vm, args, chars.len())? {
+        Some(range) => range,
+        None => return Ok(None),
+    };
+    let byte_pos = |i: usize| match chars.get(i) {
+        Some(r) => r.start,
+        None => rstr.as_bytes().len(),
+    };
+    Ok(Some(byte_pos(range.start)..byte_pos(range.end)))
+}
+
+fn string_index(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let rstr = self_val.as_rstring().unwrap();
+    match locate(vm, rstr, args)? {
+        Some(range) => Ok(Value::rstring(&vm.globals, rstr.byte_slice(range))),
         None => Ok(Value::nil()),
     }
 }
 
-fn expect_char(vm: &mut VM, chars: &mut std::str::Chars) -> Result<char, RubyError> {
-    let ch = match chars.next() {
-        Some(ch) => ch,
-        None => return Err(vm.error_argument("Invalid format character")),
-    };
-    Ok(ch)
+fn string_byteslice(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let rstr = self_val.as_rstring().unwrap();
+    match index_range(vm, args, rstr.as_bytes().len())? {
+        Some(range) => Ok(Value::rstring(&vm.globals, rstr.byte_slice(range))),
+        None => Ok(Value::nil()),
+    }
 }
 
-macro_rules! next_char {
-    ($ch:ident, $chars:ident) => {
-        $ch = match $chars.next() {
-            Some(c) => c,
-            None => break,
-        };
+fn string_getbyte(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let bytes = self_val.as_bytes().unwrap();
+    let i = args[0].expect_integer(vm, "1st arg")?;
+    let i = if i < 0 { i + bytes.len() as i64 } else { i };
+    if i < 0 || i >= bytes.len() as i64 {
+        return Ok(Value::nil());
+    }
+    Ok(Value::fixnum(bytes[i as usize] as i64))
+}
+
+fn string_setbyte(vm: &mut VM, mut self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 2)?;
+    let index = args[0].expect_integer(vm, "1st arg")?;
+    let byte = args[1].expect_integer(vm, "2nd arg")?;
+    let bytes = self_val.as_mut_rstring().unwrap().as_mut_bytes();
+    let i = if index < 0 {
+        index + bytes.len() as i64
+    } else {
+        index
     };
+    if i < 0 || i >= bytes.len() as i64 {
+        return Err(vm.error_index(format!("index {} out of string", index)));
+    }
+    bytes[i as usize] = byte as u8;
+    Ok(args[1])
 }
 
-fn string_rem(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+fn string_encoding(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let encoding = self_val.as_rstring().unwrap().encoding();
+    Ok(encoding.to_value(&mut vm.globals))
+}
+
+fn string_force_encoding(vm: &mut VM, mut self_val: Value, args: &Args) -> VMResult {
     vm.check_args_num(args.len(), 1)?;
-    let arguments = match args[0].as_array() {
-        Some(ary) => ary.elements.clone(),
-        None => vec![args[0]],
-    };
-    let mut arg_no = 0;
-    let mut format_str = vec![];
-    let mut chars = self_val.as_string().unwrap().chars();
-    let mut ch = match chars.next() {
-        Some(ch) => ch,
-        None => {
-            let res = Value::string(&vm.globals, "".to_string());
-            return Ok(res);
-        }
-    };
-    loop {
-        if ch != '%' {
-            format_str.push(ch);
-            next_char!(ch, chars);
-            continue;
+    let encoding = Encoding::from_value(vm, args[0])?;
+    self_val.as_mut_rstring().unwrap().set_encoding(encoding);
+    Ok(self_val)
+}
+
+fn string_valid_encoding(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let rstr = self_val.as_rstring().unwrap();
+    Ok(Value::bool(rstr.is_valid_encoding()))
+}
+
+fn string_b(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let mut res = self_val.as_rstring().unwrap().clone();
+    res.set_encoding(Encoding::ASCII8BIT);
+    Ok(Value::rstring(&vm.globals, res))
+}
+
+/// Split the bytes of `rstr` into valid parts and invalid byte sequences, according to its encoding.
+/// Non-ASCII bytes are invalid in US-ASCII and ASCII-8BIT strings.
+fn split_invalid(rstr: &RString) -> Vec<Result<&str, &[u8]>> {
+    let mut bytes = rstr.as_bytes();
+    let mut parts = vec![];
+    if rstr.encoding() == Encoding::UTF8 {
+        while !bytes.is_empty() {
+            match std::str::from_utf8(bytes) {
+                Ok(s) => {
+                    parts.push(Ok(s));
+                    break;
+                }
+                Err(err) => {
+                    let (valid, rest) = bytes.split_at(err.valid_up_to());
+                    if !valid.is_empty() {
+                        parts.push(Ok(std::str::from_utf8(valid).unwrap()));
+                    }
+                    let (invalid, rest) = rest.split_at(err.error_len().unwrap_or(rest.len()));
+                    parts.push(Err(invalid));
+                    bytes = rest;
+                }
+            }
         }
-        match chars.next() {
-            Some(c) if c == '%' => {
-                format_str.push('%');
-                next_char!(ch, chars);
-                continue;
+    } else {
+        while !bytes.is_empty() {
+            let ascii = bytes.iter().take_while(|b| b.is_ascii()).count();
+            let (valid, rest) = bytes.split_at(ascii);
+            if !valid.is_empty() {
+                parts.push(Ok(std::str::from_utf8(valid).unwrap()));
             }
-            Some(c) => ch = c,
-            None => return Err(vm.error_argument("Incomplete format specifier. use '%%' instead.")),
-        };
-        let mut zero_flag = false;
-        // Zero-fill
-        if ch == '0' {
-            zero_flag = true;
-            ch = expect_char(vm, &mut chars)?;
-        }
-        // Width
-        let mut width = 0usize;
-        while '0' <= ch && ch <= '9' {
-            width = width * 10 + ch as usize - '0' as usize;
-            ch = expect_char(vm, &mut chars)?;
-        }
-        // Precision
-        let mut precision = 0usize;
-        if ch == '.' {
-            ch = expect_char(vm, &mut chars)?;
-            while '0' <= ch && ch <= '9' {
-                precision = precision * 10 + ch as usize - '0' as usize;
-                ch = expect_char(vm, &mut chars)?;
+            if !rest.is_empty() {
+                parts.push(Err(&rest[..1]));
+                bytes = &rest[1..];
+            } else {
+                break;
             }
-        };
-        if arguments.len() <= arg_no {
-            return Err(vm.error_argument("Too few arguments"));
-        };
-        // Specifier
-        let val = arguments[arg_no];
-        arg_no += 1;
-        let format = match ch {
-            'd' => {
-                let val = val.expect_integer(&vm, "Invalid value for placeholder of Integer.")?;
-                if zero_flag {
-                    format!("{:0w$.p$}", val, w = width, p = precision)
-                } else {
-                    format!("{:w$.p$}", val, w = width, p = precision)
+        }
+    }
+    parts
+}
+
+fn escape_bytes(bytes: &[u8]) -> String {
+    let s: String = bytes.iter().map(|b| format!("\\x{:02X}", b)).collect();
+    format!("\"{}\"", s)
+}
+
+fn string_scrub(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let rstr = self_val.as_rstring().unwrap().clone();
+    if rstr.is_valid_encoding() {
+        return Ok(Value::rstring(&vm.globals, rstr));
+    }
+    let repl = if args.len() == 1 {
+        match args[0].as_bytes() {
+            Some(bytes) => bytes.to_vec(),
+            None => return Err(vm.error_argument("Replacement must be String.")),
+        }
+    } else if rstr.encoding() == Encoding::UTF8 {
+        "\u{FFFD}".as_bytes().to_vec()
+    } else {
+        b"?".to_vec()
+    };
+    let mut res = vec![];
+    for part in split_invalid(&rstr) {
+        match part {
+            Ok(s) => res.extend_from_slice(s.as_bytes()),
+            Err(invalid) => match args.block {
+                Some(block) => {
+                    let arg = RString::with_encoding(invalid.to_vec(), rstr.encoding());
+                    let arg = Value::rstring(&vm.globals, arg);
+                    let val = vm.eval_block(block, &Args::new1(arg))?;
+                    match val.as_bytes() {
+                        Some(bytes) => res.extend_from_slice(bytes),
+                        None => return Err(vm.error_type("Block must return String.")),
+                    }
                 }
-            }
-            'b' => {
-                let val = val.expect_integer(&vm, "Invalid value for placeholder of Integer.")?;
-                if zero_flag {
-                    format!("{:0w$b}", val, w = width)
-                } else {
-                    format!("{:w$b}", val, w = width)
+                None => res.extend_from_slice(&repl),
+            },
+        }
+    }
+    let res = RString::with_encoding(res, rstr.encoding());
+    Ok(Value::rstring(&vm.globals, res))
+}
+
+fn string_encode(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 2)?;
+    let mut rstr = self_val.as_rstring().unwrap().clone();
+    if args.is_empty() {
+        return Ok(Value::rstring(&vm.globals, rstr));
+    }
+    let dst = Encoding::from_value(vm, args[0])?;
+    if args.len() == 2 {
+        let src = Encoding::from_value(vm, args[1])?;
+        rstr.set_encoding(src);
+    }
+    let src = rstr.encoding();
+    let (mut invalid_replace, mut undef_replace, mut replace) = (false, false, None);
+    if let Some(opt) = args.kw_arg {
+        let hash = opt.as_hash().unwrap();
+        let replace_sym = Value::symbol(vm.globals.get_ident_id("replace"));
+        let mut flag = |name: &str| {
+            let key = Value::symbol(vm.globals.get_ident_id(name));
+            hash.get(&key) == Some(&replace_sym)
+        };
+        invalid_replace = flag("invalid");
+        undef_replace = flag("undef");
+        let key = Value::symbol(vm.globals.get_ident_id("replace"));
+        replace = hash
+            .get(&key)
+            .and_then(|v| v.as_bytes())
+            .map(|b| b.to_vec());
+    }
+    if src == dst {
+        return Ok(Value::rstring(&vm.globals, rstr));
+    }
+    let replace = replace.unwrap_or_else(|| match dst {
+        Encoding::UTF8 => "\u{FFFD}".as_bytes().to_vec(),
+        _ => b"?".to_vec(),
+    });
+    let mut res = vec![];
+    for part in split_invalid(&rstr) {
+        match part {
+            Ok(s) => {
+                for c in s.chars() {
+                    if dst == Encoding::UTF8 || c.is_ascii() {
+                        let mut buf = [0; 4];
+                        res.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    } else if undef_replace {
+                        res.extend_from_slice(&replace);
+                    } else {
+                        return Err(vm.error_encoding(
+                            EncodingErrKind::UndefinedConversion,
+                            format!("U+{:04X} from {} to {}", c as u32, src.name(), dst.name()),
+                        ));
+                    }
                 }
             }
-            'x' => {
-                let val = val.expect_integer(&vm, "Invalid value for placeholder of Integer.")?;
-                if zero_flag {
-                    format!("{:0w$x}", val, w = width)
+            Err(bytes) if src == Encoding::ASCII8BIT => {
+                if undef_replace {
+                    res.extend_from_slice(&replace);
                 } else {
-                    format!("{:w$x}", val, w = width)
+                    return Err(vm.error_encoding(
+                        EncodingErrKind::UndefinedConversion,
+                        format!(
+                            "{} from {} to {}",
+                            escape_bytes(bytes),
+                            src.name(),
+                            dst.name()
+                        ),
+                    ));
                 }
             }
-            'X' => {
-                let val = vm.expect_integer(val, "Value for the placeholder")?;
-                if zero_flag {
-                    format!("{:0w$X}", val, w = width)
+            Err(bytes) => {
+                if invalid_replace {
+                    res.extend_from_slice(&replace);
                 } else {
-                    format!("{:w$X}", val, w = width)
+                    return Err(vm.error_encoding(
+                        EncodingErrKind::InvalidByteSequence,
+                        format!("{} on {}", escape_bytes(bytes), src.name()),
+                    ));
                 }
             }
-            'f' => {
-                let val = vm.expect_flonum(val, "Value for the placeholder")?;
-                if zero_flag {
-                    format!("{:0w$.p$}", val, w = width, p = precision)
-                } else {
-                    format!("{:w$.p$}", val, w = width, p = precision)
-                }
+        }
+    }
+    Ok(Value::rstring(
+        &vm.globals,
+        RString::with_encoding(res, dst),
+    ))
+}
+
+fn string_unpack(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    expect_string!(template, vm, args[0]);
+    let offset = unpack_offset(vm, args)?;
+    let bytes = self_val.as_bytes().unwrap();
+    let res = pack::unpack(vm, bytes, template, offset, false)?;
+    Ok(Value::array_from(&vm.globals, res))
+}
+
+fn string_unpack1(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    expect_string!(template, vm, args[0]);
+    let offset = unpack_offset(vm, args)?;
+    let bytes = self_val.as_bytes().unwrap();
+    let res = pack::unpack(vm, bytes, template, offset, true)?;
+    Ok(res.first().cloned().unwrap_or_default())
+}
+
+/// The `offset:` keyword argument of `String#unpack` and `String#unpack1`.
+fn unpack_offset(vm: &mut VM, args: &Args) -> Result<usize, RubyError> {
+    let val = match args.kw_arg {
+        Some(opt) => {
+            let key = Value::symbol(vm.globals.get_ident_id("offset"));
+            opt.as_hash().unwrap().get(&key).cloned()
+        }
+        None => None,
+    };
+    match val {
+        Some(val) => {
+            let offset = val.expect_integer(vm, "offset")?;
+            if offset < 0 {
+                return Err(vm.error_argument("offset can't be negative"));
             }
-            _ => return Err(vm.error_argument("Invalid format character.")),
-        };
-        format_str.append(&mut format.chars().collect());
-        next_char!(ch, chars);
+            Ok(offset as usize)
+        }
+        None => Ok(0),
     }
+}
 
-    let res = Value::string(&vm.globals, format_str.into_iter().collect());
-    Ok(res)
+fn string_cmp(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let lhs = self_val.as_rstring().unwrap();
+    match lhs.cmp(args[0]) {
+        Some(ord) => Ok(Value::fixnum(ord as i64)),
+        None => Ok(Value::nil()),
+    }
+}
+
+fn string_rem(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let arguments = match args[0].as_array() {
+        Some(ary) => ary.elements.clone(),
+        None => vec![args[0]],
+    };
+    let fmt = self_val.as_string().unwrap();
+    let res = format::format(vm, fmt, &arguments)?;
+    Ok(Value::string(&vm.globals, res))
 }
 
 fn string_start_with(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
@@ -563,19 +954,16 @@ fn string_rmatch(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     };
 }
 
-fn string_tr(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
-    vm.check_args_num(args.len(), 2)?;
-    expect_string!(rec, vm, self_val);
-    expect_string!(from, vm, args[0]);
-    expect_string!(to, vm, args[1]);
-    let res = rec.replace(from, to);
-    Ok(Value::string(&vm.globals, res))
+fn string_size(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let rstr = self_val.as_rstring().unwrap();
+    Ok(Value::fixnum(rstr.char_len() as i64))
 }
 
-fn string_size(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+fn string_bytesize(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     vm.check_args_num(args.len(), 0)?;
-    expect_string!(rec, vm, self_val);
-    Ok(Value::fixnum(rec.chars().count() as i64))
+    let rstr = self_val.as_rstring().unwrap();
+    Ok(Value::fixnum(rstr.as_bytes().len() as i64))
 }
 
 fn string_bytes(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
@@ -623,42 +1011,1036 @@ fn string_chomp(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
 }
 
 fn string_toi(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
-    vm.check_args_num(args.len(), 0)?;
-    let self_ = match vm.expect_string(&self_val, "Receiver") {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let radix = match args.len() {
+        1 => match args[0].expect_integer(vm, "1st arg")? {
+            radix if (2..=36).contains(&radix) => radix as u32,
+            radix => return Err(vm.error_argument(format!("invalid radix {}", radix))),
+        },
+        _ => 10,
+    };
+    let given = match vm.expect_string(&self_val, "Receiver") {
         Ok(s) => s,
         Err(_) => return Ok(Value::fixnum(0)),
     };
-    let i: i64 = self_.parse().unwrap();
-    Ok(Value::fixnum(i))
+    Ok(Value::fixnum(parse_int(given, radix)))
 }
 
-fn lt(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
-    vm.check_args_num(args.len(), 1)?;
-    let lhs = self_val.as_rstring().unwrap();
-    match lhs.cmp(args[0]) {
-        Some(ord) => Ok(Value::bool(ord == Ordering::Less)),
-        None => Ok(Value::bool(false)),
+/// Returns the successor of `s` in the manner of `String#succ`.
+pub fn succ_str(s: &str) -> String {
+    let mut chars: Vec<char> = s.chars().collect();
+    if chars.is_empty() {
+        return String::new();
+    }
+    // The position of the leftmost alphanumeric which was carried over.
+    let mut carried = None;
+    for i in (0..chars.len()).rev() {
+        let ch = chars[i];
+        let (next, carry) = match ch {
+            '0'..='8' | 'a'..='y' | 'A'..='Y' => ((ch as u8 + 1) as char, false),
+            '9' => ('0', true),
+            'z' => ('a', true),
+            'Z' => ('A', true),
+            _ => continue,
+        };
+        chars[i] = next;
+        if !carry {
+            return chars.into_iter().collect();
+        }
+        carried = Some(i);
+    }
+    match carried {
+        Some(pos) => {
+            let top = if chars[pos] == '0' { '1' } else { chars[pos] };
+            chars.insert(pos, top);
+        }
+        None => {
+            // No alphanumeric: increment the last character.
+            let last = chars.len() - 1;
+            if let Some(ch) = std::char::from_u32(chars[last] as u32 + 1) {
+                chars[last] = ch;
+            }
+        }
     }
+    chars.into_iter().collect()
 }
 
-fn gt(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
-    vm.check_args_num(args.len(), 1)?;
-    let lhs = self_val.as_rstring().unwrap();
-    match lhs.cmp(args[0]) {
-        Some(ord) => Ok(Value::bool(ord == Ordering::Greater)),
-        None => Ok(Value::bool(false)),
+/// A new String of `s` with the encoding of `rstr`.
+fn new_like(vm: &VM, rstr: &RString, s: String) -> Value {
+    Value::rstring(
+        &vm.globals,
+        RString::with_encoding(s.into_bytes(), rstr.encoding()),
+    )
+}
+
+/// Replace the bytes in `range` of the String `self_val` with `replace`.
+fn splice(mut self_val: Value, range: Range<usize>, replace: &[u8]) {
+    let rstr = self_val.as_mut_rstring().unwrap();
+    let bytes = rstr.as_bytes();
+    let mut res = bytes[..range.start].to_vec();
+    res.extend_from_slice(replace);
+    res.extend_from_slice(&bytes[range.end..]);
+    *rstr = RString::with_encoding(res, rstr.encoding());
+}
+
+/// Replace the receiver with the result of the non-destructive method `func`.
+/// Return nil if the receiver was not changed.
+fn modify(vm: &mut VM, mut self_val: Value, args: &Args, func: BuiltinFunc) -> VMResult {
+    let res = func(vm, self_val, args)?;
+    let res = res.as_rstring().unwrap().clone();
+    let rstr = self_val.as_mut_rstring().unwrap();
+    if rstr.as_bytes() == res.as_bytes() {
+        return Ok(Value::nil());
     }
+    *rstr = res;
+    Ok(self_val)
 }
 
-#[cfg(test)]
-mod test {
-    use crate::test::*;
+macro_rules! define_bang {
+    ($name:ident, $func:ident) => {
+        fn $name(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+            modify(vm, self_val, args, $func)
+        }
+    };
+}
 
-    #[test]
-    fn string_test() {
-        let program = r#"
-        assert true, "a" < "b"
-        assert false, "b" < "b"
+define_bang!(string_upcase_, string_upcase);
+define_bang!(string_downcase_, string_downcase);
+define_bang!(string_capitalize_, string_capitalize);
+define_bang!(string_swapcase_, string_swapcase);
+define_bang!(string_strip_, string_strip);
+define_bang!(string_lstrip_, string_lstrip);
+define_bang!(string_rstrip_, string_rstrip);
+define_bang!(string_chomp_, string_chomp);
+define_bang!(string_sub_, string_sub);
+define_bang!(string_squeeze_, string_squeeze);
+define_bang!(string_delete_, string_delete);
+define_bang!(string_tr_, string_tr);
+define_bang!(string_tr_s_, string_tr_s);
+define_bang!(string_unicode_normalize_, string_unicode_normalize);
+
+fn string_eq(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    Ok(Value::bool(self_val.equal(args[0])))
+}
+
+fn string_eql(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    Ok(Value::bool(HashKey(self_val) == HashKey(args[0])))
+}
+
+fn string_hash(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    use std::hash::{Hash, Hasher};
+    vm.check_args_num(args.len(), 0)?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    HashKey(self_val).hash(&mut hasher);
+    Ok(Value::fixnum(hasher.finish() as i64 >> 2))
+}
+
+fn string_downcase(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let rstr = self_val.as_rstring().unwrap();
+    let res = rstr.as_string(vm)?.to_lowercase();
+    Ok(new_like(vm, rstr, res))
+}
+
+fn string_capitalize(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let rstr = self_val.as_rstring().unwrap();
+    let mut chars = rstr.as_string(vm)?.chars();
+    let res = match chars.next() {
+        Some(ch) => ch
+            .to_uppercase()
+            .chain(chars.as_str().to_lowercase().chars())
+            .collect(),
+        None => String::new(),
+    };
+    Ok(new_like(vm, rstr, res))
+}
+
+fn string_swapcase(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let rstr = self_val.as_rstring().unwrap();
+    let mut res = String::new();
+    for ch in rstr.as_string(vm)?.chars() {
+        if ch.is_uppercase() {
+            res.extend(ch.to_lowercase());
+        } else if ch.is_lowercase() {
+            res.extend(ch.to_uppercase());
+        } else {
+            res.push(ch);
+        }
+    }
+    Ok(new_like(vm, rstr, res))
+}
+
+/// Remove leading and/or trailing whitespace and null characters.
+fn strip(vm: &mut VM, self_val: Value, args: &Args, left: bool, right: bool) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let is_space = |b: u8| matches!(b, b' ' | b'\t' | b'\n' | 0x0b | 0x0c | b'\r' | 0);
+    let rstr = self_val.as_rstring().unwrap();
+    let bytes = rstr.as_bytes();
+    let (mut start, mut end) = (0, bytes.len());
+    while left && start < end && is_space(bytes[start]) {
+        start += 1;
+    }
+    while right && end > start && is_space(bytes[end - 1]) {
+        end -= 1;
+    }
+    Ok(Value::rstring(&vm.globals, rstr.byte_slice(start..end)))
+}
+
+fn string_strip(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    strip(vm, self_val, args, true, true)
+}
+
+fn string_lstrip(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    strip(vm, self_val, args, true, false)
+}
+
+fn string_rstrip(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    strip(vm, self_val, args, false, true)
+}
+
+/// Pad the receiver to the width of the 1st argument with the optional pad string.
+/// `left` gives the number of padding characters on the left for the total number.
+fn justify(vm: &mut VM, self_val: Value, args: &Args, left: fn(usize) -> usize) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let rstr = self_val.as_rstring().unwrap();
+    let width = args[0].expect_integer(vm, "1st arg")?;
+    let pad = match args.len() {
+        2 => vm.expect_string(&args[1], "2nd arg")?.as_str(),
+        _ => " ",
+    };
+    if pad.is_empty() {
+        return Err(vm.error_argument("zero width padding"));
+    }
+    let len = rstr.char_len();
+    if width <= len as i64 {
+        return Ok(Value::rstring(&vm.globals, rstr.clone()));
+    }
+    let total = width as usize - len;
+    let left = left(total);
+    let padding = |n: usize| -> String { pad.chars().cycle().take(n).collect() };
+    let mut res = padding(left).into_bytes();
+    res.extend_from_slice(rstr.as_bytes());
+    res.extend_from_slice(padding(total - left).as_bytes());
+    Ok(Value::rstring(
+        &vm.globals,
+        RString::with_encoding(res, rstr.encoding()),
+    ))
+}
+
+fn string_center(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    justify(vm, self_val, args, |n| n / 2)
+}
+
+fn string_ljust(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    justify(vm, self_val, args, |_| 0)
+}
+
+fn string_rjust(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    justify(vm, self_val, args, |n| n)
+}
+
+/// Byte positions of the characters of `given` and its end.
+fn char_boundaries(given: &str) -> impl DoubleEndedIterator<Item = usize> + '_ {
+    given
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(given.len()))
+}
+
+/// Search `re` in `given` backward, for the match which starts at the rightmost position
+/// not after the byte position `pos`, and set the special global variables.
+fn rsearch(
+    vm: &mut VM,
+    re: &Regexp,
+    given: &str,
+    pos: usize,
+) -> Result<Option<Range<usize>>, RubyError> {
+    for start in char_boundaries(given).filter(|i| *i <= pos).rev() {
+        match re.find_from_pos(given, start) {
+            Ok(Some(m)) if m.start() == start => {
+                Regexp::search(vm, re, given, start)?;
+                return Ok(Some(m.start()..m.end()));
+            }
+            Ok(_) => {}
+            Err(err) => return Err(vm.error_internal(format!("Capture failed. {:?}", err))),
+        }
+    }
+    Ok(None)
+}
+
+/// Convert a Regexp, or a String compiled as a regular expression, to RegexpRef.
+fn expect_regexp(vm: &VM, val: Value) -> Result<RegexpRef, RubyError> {
+    if let Some(re) = val.as_regexp() {
+        return Ok(re);
+    }
+    match val.as_string() {
+        Some(s) => RegexpRef::from_string(s).map_err(|err| vm.error_regexp(err)),
+        None => Err(vm.error_type("wrong argument type (expected Regexp)")),
+    }
+}
+
+fn string_index_of(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    let pos = match args.len() {
+        2 => args[1].expect_integer(vm, "2nd arg")?,
+        _ => 0,
+    };
+    let pos = match char_to_byte_pos(given, pos) {
+        Some(pos) => pos,
+        None => return Ok(Value::nil()),
+    };
+    let found = if let Some(re) = args[0].as_regexp() {
+        Regexp::search(vm, &re.regexp, given, pos)?.map(|c| c.get(0).unwrap().start())
+    } else {
+        let pat = vm.expect_string(&args[0], "1st arg")?;
+        given[pos..].find(pat.as_str()).map(|i| i + pos)
+    };
+    match found {
+        Some(i) => Ok(Value::fixnum(given[..i].chars().count() as i64)),
+        None => Ok(Value::nil()),
+    }
+}
+
+fn string_rindex(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    let len = given.chars().count() as i64;
+    let pos = match args.len() {
+        2 => args[1].expect_integer(vm, "2nd arg")?,
+        _ => len,
+    };
+    let pos = match char_to_byte_pos(given, pos.min(len)) {
+        Some(pos) => pos,
+        None => return Ok(Value::nil()),
+    };
+    let found = if let Some(re) = args[0].as_regexp() {
+        rsearch(vm, &re.regexp, given, pos)?.map(|range| range.start)
+    } else {
+        let pat = vm.expect_string(&args[0], "1st arg")?;
+        char_boundaries(given)
+            .filter(|i| *i <= pos)
+            .rev()
+            .find(|i| given[*i..].starts_with(pat.as_str()))
+    };
+    match found {
+        Some(i) => Ok(Value::fixnum(given[..i].chars().count() as i64)),
+        None => Ok(Value::nil()),
+    }
+}
+
+fn string_include(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    let pat = vm.expect_string(&args[0], "1st arg")?;
+    Ok(Value::bool(given.contains(pat.as_str())))
+}
+
+fn string_end_with(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    for arg in args.iter() {
+        let suffix = vm.expect_string(arg, "Arg")?;
+        if given.ends_with(suffix.as_str()) {
+            return Ok(Value::true_val());
+        }
+    }
+    Ok(Value::false_val())
+}
+
+/// Split `given` into lines which end with `sep`. An empty `sep` splits `given` into paragraphs.
+fn split_lines<'a>(given: &'a str, sep: &str) -> Vec<&'a str> {
+    let mut lines = vec![];
+    let mut rest = given;
+    while !rest.is_empty() {
+        let end = if sep.is_empty() {
+            match rest.find("\n\n") {
+                Some(i) => i + 2 + rest[i + 2..].bytes().take_while(|b| *b == b'\n').count(),
+                None => rest.len(),
+            }
+        } else {
+            match rest.find(sep) {
+                Some(i) => i + sep.len(),
+                None => rest.len(),
+            }
+        };
+        lines.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    lines
+}
+
+/// The lines of the receiver for `String#each_line` and `String#lines`.
+fn lines(vm: &mut VM, self_val: Value, args: &Args) -> Result<Vec<Value>, RubyError> {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    let sep = match args.len() {
+        1 => vm.expect_string(&args[0], "1st arg")?.as_str(),
+        _ => "\n",
+    };
+    let chomp = match args.kw_arg {
+        Some(opt) => {
+            let key = Value::symbol(vm.globals.get_ident_id("chomp"));
+            let val = opt.as_hash().unwrap().get(&key).cloned();
+            vm.val_to_bool(val.unwrap_or_default())
+        }
+        None => false,
+    };
+    let lines = split_lines(given, sep)
+        .into_iter()
+        .map(|line| {
+            let line = match (chomp, sep) {
+                (false, _) => line,
+                (true, "") => line.trim_end_matches('\n'),
+                (true, "\n") => line
+                    .strip_suffix("\r\n")
+                    .or_else(|| line.strip_suffix('\n'))
+                    .unwrap_or(line),
+                (true, sep) => line.strip_suffix(sep).unwrap_or(line),
+            };
+            Value::string(&vm.globals, line.to_string())
+        })
+        .collect();
+    Ok(lines)
+}
+
+fn string_each_line(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let block = match args.block {
+        Some(block) => block,
+        None => {
+            let id = vm.globals.get_ident_id("each_line");
+            let val = Value::enumerator(&vm.globals, id, self_val, args.clone());
+            return Ok(val);
+        }
+    };
+    let lines = lines(vm, self_val, args)?;
+    if block == MethodRef::from(0) {
+        return Ok(Value::array_from(&vm.globals, lines));
+    }
+    for line in lines {
+        vm.eval_block(block, &Args::new1(line))?;
+    }
+    Ok(self_val)
+}
+
+fn string_lines(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let lines = lines(vm, self_val, args)?;
+    Ok(Value::array_from(&vm.globals, lines))
+}
+
+fn string_each_char(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let block = match args.block {
+        Some(block) => block,
+        None => {
+            let id = vm.globals.get_ident_id("each_char");
+            let val = Value::enumerator(&vm.globals, id, self_val, args.clone());
+            return Ok(val);
+        }
+    };
+    let rstr = self_val.as_rstring().unwrap();
+    let chars: Vec<Value> = rstr
+        .char_ranges()
+        .into_iter()
+        .map(|range| Value::rstring(&vm.globals, rstr.byte_slice(range)))
+        .collect();
+    if block == MethodRef::from(0) {
+        return Ok(Value::array_from(&vm.globals, chars));
+    }
+    for ch in chars {
+        vm.eval_block(block, &Args::new1(ch))?;
+    }
+    Ok(self_val)
+}
+
+fn string_index_assign(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 2, 3)?;
+    let index = &args[0..args.len() - 1];
+    let val = args[args.len() - 1];
+    let rstr = self_val.as_rstring().unwrap();
+    let range = match locate(vm, rstr, index)? {
+        Some(range) => range,
+        None if index.len() == 1 && index[0].as_fixnum() == Some(rstr.char_len() as i64) => {
+            rstr.as_bytes().len()..rstr.as_bytes().len()
+        }
+        None => {
+            let msg = if index[0].as_rstring().is_some() {
+                "string not matched".to_string()
+            } else if index[0].as_regexp().is_some() {
+                "regexp not matched".to_string()
+            } else {
+                format!("index {} out of string", vm.val_inspect(index[0]))
+            };
+            return Err(vm.error_index(msg));
+        }
+    };
+    let replace = match val.as_bytes() {
+        Some(bytes) => bytes.to_vec(),
+        None => return Err(vm.error_type("Value must be String.")),
+    };
+    splice(self_val, range, &replace);
+    Ok(val)
+}
+
+fn string_slice_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let rstr = self_val.as_rstring().unwrap();
+    let range = match locate(vm, rstr, args)? {
+        Some(range) => range,
+        None => return Ok(Value::nil()),
+    };
+    let res = rstr.byte_slice(range.clone());
+    splice(self_val, range, b"");
+    Ok(Value::rstring(&vm.globals, res))
+}
+
+fn string_insert(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 2)?;
+    let index = args[0].expect_integer(vm, "1st arg")?;
+    let other = match args[1].as_bytes() {
+        Some(bytes) => bytes.to_vec(),
+        None => return Err(vm.error_type("2nd arg must be String.")),
+    };
+    let rstr = self_val.as_rstring().unwrap();
+    let chars = rstr.char_ranges();
+    let len = chars.len() as i64;
+    let pos = if index < 0 { index + len + 1 } else { index };
+    if pos < 0 || pos > len {
+        return Err(vm.error_index(format!("index {} out of string", index)));
+    }
+    let pos = match chars.get(pos as usize) {
+        Some(range) => range.start,
+        None => rstr.as_bytes().len(),
+    };
+    splice(self_val, pos..pos, &other);
+    Ok(self_val)
+}
+
+fn string_replace(vm: &mut VM, mut self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let other = match args[0].as_rstring() {
+        Some(rstr) => rstr.clone(),
+        None => return Err(vm.error_type("1st arg must be String.")),
+    };
+    *self_val.as_mut_rstring().unwrap() = other;
+    Ok(self_val)
+}
+
+fn string_reverse(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let rstr = self_val.as_rstring().unwrap();
+    let bytes = rstr.as_bytes();
+    let mut res = vec![];
+    for range in rstr.char_ranges().into_iter().rev() {
+        res.extend_from_slice(&bytes[range]);
+    }
+    Ok(Value::rstring(
+        &vm.globals,
+        RString::with_encoding(res, rstr.encoding()),
+    ))
+}
+
+fn string_reverse_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    modify(vm, self_val, args, string_reverse)?;
+    Ok(self_val)
+}
+
+/// A set of characters in the notation of `String#tr` and `String#count`,
+/// such as "a-z", "^aeiou" and "\\-".
+struct CharSet {
+    negated: bool,
+    chars: Vec<char>,
+}
+
+impl CharSet {
+    fn new(vm: &VM, spec: &str) -> Result<Self, RubyError> {
+        match spec.strip_prefix('^') {
+            Some(rest) if !rest.is_empty() => Ok(CharSet {
+                negated: true,
+                chars: CharSet::expand(vm, rest)?,
+            }),
+            _ => Ok(CharSet {
+                negated: false,
+                chars: CharSet::expand(vm, spec)?,
+            }),
+        }
+    }
+
+    /// Expand ranges and backslash escapes in `spec` into a list of characters.
+    fn expand(vm: &VM, spec: &str) -> Result<Vec<char>, RubyError> {
+        let spec: Vec<char> = spec.chars().collect();
+        let mut chars = vec![];
+        let mut i = 0;
+        while i < spec.len() {
+            let mut ch = spec[i];
+            if ch == '\\' && i + 1 < spec.len() {
+                i += 1;
+                ch = spec[i];
+            }
+            if i + 2 < spec.len() && spec[i + 1] == '-' {
+                let last = spec[i + 2];
+                if last < ch {
+                    return Err(vm.error_argument(format!(
+                        "invalid range \"{}-{}\" in string transliteration",
+                        ch, last
+                    )));
+                }
+                chars.extend(ch..=last);
+                i += 3;
+            } else {
+                chars.push(ch);
+                i += 1;
+            }
+        }
+        Ok(chars)
+    }
+
+    fn contains(&self, ch: char) -> bool {
+        self.chars.contains(&ch) != self.negated
+    }
+}
+
+/// The intersection of the character sets given as `args`.
+fn char_sets(vm: &mut VM, args: &Args) -> Result<Vec<CharSet>, RubyError> {
+    let mut sets = vec![];
+    for arg in args.iter() {
+        let spec = vm.expect_string(arg, "Arg")?;
+        sets.push(CharSet::new(vm, spec)?);
+    }
+    Ok(sets)
+}
+
+fn string_count(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_min(args.len(), 1)?;
+    let sets = char_sets(vm, args)?;
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    let count = given
+        .chars()
+        .filter(|ch| sets.iter().all(|set| set.contains(*ch)))
+        .count();
+    Ok(Value::fixnum(count as i64))
+}
+
+fn string_delete(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_min(args.len(), 1)?;
+    let sets = char_sets(vm, args)?;
+    let rstr = self_val.as_rstring().unwrap();
+    let res = rstr
+        .as_string(vm)?
+        .chars()
+        .filter(|ch| !sets.iter().all(|set| set.contains(*ch)))
+        .collect();
+    Ok(new_like(vm, rstr, res))
+}
+
+fn string_squeeze(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let sets = char_sets(vm, args)?;
+    let rstr = self_val.as_rstring().unwrap();
+    let mut res = String::new();
+    let mut last = None;
+    for ch in rstr.as_string(vm)?.chars() {
+        if last != Some(ch) || !sets.iter().all(|set| set.contains(ch)) {
+            res.push(ch);
+        }
+        last = Some(ch);
+    }
+    Ok(new_like(vm, rstr, res))
+}
+
+/// Translate the characters of the receiver in the 1st argument to the corresponding
+/// characters in the 2nd argument. Runs of translated characters are squeezed when `squeeze` is true.
+fn translate(vm: &mut VM, self_val: Value, args: &Args, squeeze: bool) -> VMResult {
+    vm.check_args_num(args.len(), 2)?;
+    let from = vm.expect_string(&args[0], "1st arg")?;
+    let from = CharSet::new(vm, from)?;
+    let to = vm.expect_string(&args[1], "2nd arg")?;
+    let to = CharSet::expand(vm, to)?;
+    let rstr = self_val.as_rstring().unwrap();
+    let mut res = String::new();
+    let mut last = None;
+    for ch in rstr.as_string(vm)?.chars() {
+        if !from.contains(ch) {
+            res.push(ch);
+            last = None;
+            continue;
+        }
+        let pos = match from.negated {
+            true => None,
+            false => from.chars.iter().position(|c| *c == ch),
+        };
+        let translated = match pos.and_then(|i| to.get(i)) {
+            Some(ch) => Some(*ch),
+            None => to.last().cloned(),
+        };
+        if let Some(translated) = translated {
+            if !squeeze || last != Some(translated) {
+                res.push(translated);
+            }
+            last = Some(translated);
+        }
+    }
+    Ok(new_like(vm, rstr, res))
+}
+
+fn string_tr(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    translate(vm, self_val, args, false)
+}
+
+fn string_tr_s(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    translate(vm, self_val, args, true)
+}
+
+fn string_succ(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let rstr = self_val.as_rstring().unwrap();
+    let res = succ_str(rstr.as_string(vm)?);
+    Ok(new_like(vm, rstr, res))
+}
+
+fn string_succ_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    modify(vm, self_val, args, string_succ)?;
+    Ok(self_val)
+}
+
+/// The sequence of `String#upto` from `start` to `end`.
+fn upto(start: String, end: &str, exclusive: bool) -> Vec<String> {
+    let mut res = vec![];
+    // Single ASCII characters.
+    if start.len() == 1 && end.len() == 1 {
+        let (first, last) = (start.as_bytes()[0], end.as_bytes()[0]);
+        for ch in first..=last {
+            if exclusive && ch == last {
+                break;
+            }
+            res.push((ch as char).to_string());
+        }
+        return res;
+    }
+    // Numeric strings are counted up as numbers, padded to the width of `start`.
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if is_digits(&start) && is_digits(end) {
+        if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+            let width = start.len();
+            for n in first..=last {
+                if exclusive && n == last {
+                    break;
+                }
+                res.push(format!("{:0width$}", n, width = width));
+            }
+            return res;
+        }
+    }
+    if start.as_str() > end || (exclusive && start == end) {
+        return res;
+    }
+    let after_end = succ_str(end);
+    let mut current = start;
+    while current != after_end {
+        let next = if exclusive || current != end {
+            Some(succ_str(&current))
+        } else {
+            None
+        };
+        res.push(current);
+        current = match next {
+            Some(next) => next,
+            None => break,
+        };
+        if (exclusive && current == end) || current.len() > end.len() || current.is_empty() {
+            break;
+        }
+    }
+    res
+}
+
+fn string_upto(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let block = match args.block {
+        Some(block) => block,
+        None => {
+            let id = vm.globals.get_ident_id("upto");
+            let val = Value::enumerator(&vm.globals, id, self_val, args.clone());
+            return Ok(val);
+        }
+    };
+    let start = vm.expect_string(&self_val, "Receiver")?.to_string();
+    let end = vm.expect_string(&args[0], "1st arg")?;
+    let exclusive = args.len() == 2 && vm.val_to_bool(args[1]);
+    let strs = upto(start, end, exclusive)
+        .into_iter()
+        .map(|s| Value::string(&vm.globals, s));
+    if block == MethodRef::from(0) {
+        return Ok(Value::array_from(&vm.globals, strs.collect()));
+    }
+    for s in strs.collect::<Vec<_>>() {
+        vm.eval_block(block, &Args::new1(s))?;
+    }
+    Ok(self_val)
+}
+
+fn string_ord(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let rstr = self_val.as_rstring().unwrap();
+    let range = match rstr.char_ranges().first() {
+        Some(range) => range.clone(),
+        None => return Err(vm.error_argument("empty string")),
+    };
+    let bytes = &rstr.as_bytes()[range];
+    if rstr.encoding() != Encoding::UTF8 {
+        return Ok(Value::fixnum(bytes[0] as i64));
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(s) => Ok(Value::fixnum(s.chars().next().unwrap() as i64)),
+        Err(_) => Err(vm.error_argument("invalid byte sequence in UTF-8")),
+    }
+}
+
+/// Parse the leading integer of `s` in `radix` in the manner of `String#to_i`.
+/// Leading whitespace, a sign, the prefix for `radix` and underscores between digits are allowed,
+/// and the rest of `s` is ignored.
+fn parse_int(s: &str, radix: u32) -> i64 {
+    let s = s.trim_start();
+    let (negative, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let prefix = match radix {
+        2 => "0b",
+        8 => "0o",
+        16 => "0x",
+        _ => "",
+    };
+    let s = match s.get(..2) {
+        Some(p) if !prefix.is_empty() && p.eq_ignore_ascii_case(prefix) => &s[2..],
+        _ => s,
+    };
+    let mut num: i64 = 0;
+    let mut after_digit = false;
+    for ch in s.chars() {
+        match ch.to_digit(radix) {
+            Some(d) => {
+                num = num.wrapping_mul(radix as i64).wrapping_add(d as i64);
+                after_digit = true;
+            }
+            None if ch == '_' && after_digit => after_digit = false,
+            None => break,
+        }
+    }
+    if negative {
+        num.wrapping_neg()
+    } else {
+        num
+    }
+}
+
+/// Parse the leading floating point number of `s` in the manner of `String#to_f`.
+fn parse_float(s: &str) -> f64 {
+    fn digits(bytes: &[u8], pos: &mut usize, num: &mut String) -> usize {
+        let start = *pos;
+        while let Some(b) = bytes.get(*pos) {
+            match b {
+                b'0'..=b'9' => num.push(*b as char),
+                b'_' if *pos > start
+                    && matches!(bytes.get(*pos + 1), Some(b) if b.is_ascii_digit()) => {}
+                _ => break,
+            }
+            *pos += 1;
+        }
+        *pos - start
+    }
+    let bytes = s.trim_start().as_bytes();
+    let mut num = String::new();
+    let mut pos = 0;
+    if let Some(b'+') | Some(b'-') = bytes.first() {
+        num.push(bytes[0] as char);
+        pos += 1;
+    }
+    let mut len = digits(bytes, &mut pos, &mut num);
+    if bytes.get(pos) == Some(&b'.') && matches!(bytes.get(pos + 1), Some(b) if b.is_ascii_digit())
+    {
+        num.push('.');
+        pos += 1;
+        len += digits(bytes, &mut pos, &mut num);
+    }
+    if len > 0 && matches!(bytes.get(pos), Some(b'e') | Some(b'E')) {
+        let mut exp = "e".to_string();
+        let mut p = pos + 1;
+        if let Some(b'+') | Some(b'-') = bytes.get(p) {
+            exp.push(bytes[p] as char);
+            p += 1;
+        }
+        if digits(bytes, &mut p, &mut exp) > 0 {
+            num += &exp;
+        }
+    }
+    num.parse().unwrap_or(0.0)
+}
+
+fn string_hex(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    Ok(Value::fixnum(parse_int(given, 16)))
+}
+
+fn string_oct(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    let body = given.trim_start().trim_start_matches(['+', '-']);
+    let radix = match body.get(..2).map(|p| p.to_ascii_lowercase()).as_deref() {
+        Some("0x") => 16,
+        Some("0b") => 2,
+        _ => 8,
+    };
+    Ok(Value::fixnum(parse_int(given, radix)))
+}
+
+fn string_to_f(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    Ok(Value::flonum(parse_float(given)))
+}
+
+/// Split the receiver at the byte range `sep`, for `String#partition` and `String#rpartition`.
+fn partition(vm: &VM, given: &str, sep: Range<usize>) -> Value {
+    let parts = vec![&given[..sep.start], &given[sep.clone()], &given[sep.end..]];
+    let parts = parts
+        .into_iter()
+        .map(|s| Value::string(&vm.globals, s.to_string()))
+        .collect();
+    Value::array_from(&vm.globals, parts)
+}
+
+fn string_partition(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    let found = if let Some(re) = args[0].as_regexp() {
+        Regexp::search(vm, &re.regexp, given, 0)?.map(|c| {
+            let m = c.get(0).unwrap();
+            m.start()..m.end()
+        })
+    } else {
+        let sep = vm.expect_string(&args[0], "1st arg")?;
+        given.find(sep.as_str()).map(|i| i..i + sep.len())
+    };
+    let sep = found.unwrap_or(given.len()..given.len());
+    Ok(partition(vm, given, sep))
+}
+
+fn string_rpartition(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    let found = if let Some(re) = args[0].as_regexp() {
+        rsearch(vm, &re.regexp, given, given.len())?
+    } else {
+        let sep = vm.expect_string(&args[0], "1st arg")?;
+        given.rfind(sep.as_str()).map(|i| i..i + sep.len())
+    };
+    let sep = found.unwrap_or(0..0);
+    Ok(partition(vm, given, sep))
+}
+
+fn string_match(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    let re = expect_regexp(vm, args[0])?;
+    let pos = match args.len() {
+        2 => args[1].expect_integer(vm, "2nd arg")?,
+        _ => 0,
+    };
+    Regexp::match_value(vm, &re.regexp, given, pos, args.block)
+}
+
+fn string_match_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 2)?;
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    let re = expect_regexp(vm, args[0])?;
+    let pos = match args.len() {
+        2 => args[1].expect_integer(vm, "2nd arg")?,
+        _ => 0,
+    };
+    Regexp::is_match_from(vm, &re.regexp, given, pos)
+}
+
+fn string_casecmp(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let lhs = self_val.as_bytes().unwrap().to_ascii_lowercase();
+    let rhs = match args[0].as_bytes() {
+        Some(bytes) => bytes.to_ascii_lowercase(),
+        None => return Err(vm.error_type("1st arg must be String.")),
+    };
+    Ok(Value::fixnum(lhs.cmp(&rhs) as i64))
+}
+
+fn string_casecmp_(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let lhs = vm.expect_string(&self_val, "Receiver")?;
+    let rhs = vm.expect_string(&args[0], "1st arg")?;
+    Ok(Value::bool(lhs.to_lowercase() == rhs.to_lowercase()))
+}
+
+/// Normalize `given` in the form given as an optional Symbol argument, which defaults to :nfc.
+fn normalize(vm: &VM, given: &str, args: &Args) -> Result<String, RubyError> {
+    use unicode_normalization::UnicodeNormalization;
+    vm.check_args_range(args.len(), 0, 1)?;
+    let form = match args.len() {
+        0 => "nfc",
+        _ => match args[0].as_symbol() {
+            Some(id) => vm.globals.get_ident_name(id),
+            None => return Err(vm.error_type("1st arg must be Symbol.")),
+        },
+    };
+    match form {
+        "nfc" => Ok(given.nfc().collect()),
+        "nfd" => Ok(given.nfd().collect()),
+        "nfkc" => Ok(given.nfkc().collect()),
+        "nfkd" => Ok(given.nfkd().collect()),
+        _ => Err(vm.error_argument(format!("Invalid normalization form {}.", form))),
+    }
+}
+
+fn string_unicode_normalize(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    let res = normalize(vm, given, args)?;
+    Ok(Value::string(&vm.globals, res))
+}
+
+fn string_unicode_normalized(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let given = vm.expect_string(&self_val, "Receiver")?;
+    let res = normalize(vm, given, args)?;
+    Ok(Value::bool(&res == given))
+}
+
+fn lt(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let lhs = self_val.as_rstring().unwrap();
+    match lhs.cmp(args[0]) {
+        Some(ord) => Ok(Value::bool(ord == Ordering::Less)),
+        None => Ok(Value::bool(false)),
+    }
+}
+
+fn gt(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let lhs = self_val.as_rstring().unwrap();
+    match lhs.cmp(args[0]) {
+        Some(ord) => Ok(Value::bool(ord == Ordering::Greater)),
+        None => Ok(Value::bool(false)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn string_test() {
+        let program = r#"
+        assert true, "a" < "b"
+        assert false, "b" < "b"
         assert false, "c" < "b"
         assert false, "a" > "b"
         assert false, "b" > "b"
@@ -695,6 +2077,72 @@ mod test {
         assert_script(program);
     }
 
+    #[test]
+    fn string_index_encoding() {
+        let program = r#"
+        s = "héllo"
+        assert 5, s.size
+        assert 6, s.bytesize
+        assert "é", s[1]
+        assert "éll", s[1, 3]
+        assert "él", s[1..2]
+        assert "llo", s[-3..-1]
+        assert nil, s[6]
+        b = s.b
+        assert 6, b.size
+        assert [195].pack("C*").b, b[1]
+        assert "é".b, b[1..2]
+        assert "é", s.byteslice(1, 2)
+        assert false, s.byteslice(1).valid_encoding?
+        assert [195, 169], [s.getbyte(1), s.getbyte(-4)]
+        assert nil, s.getbyte(6)
+        t = "abc"
+        t.setbyte(0, 65)
+        assert "Abc", t
+        assert [104, 233, 108, 108, 111], s.unpack("U*")
+        assert [97, 98], "abc".unpack("C2")
+        assert 104, s.unpack1("C")
+        assert ["ab", "c"], "abc".unpack("a2a")
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn string_encoding() {
+        let program = r#"
+        s = "héllo"
+        assert Encoding::UTF_8, s.encoding
+        assert Encoding::ASCII_8BIT, s.b.encoding
+        assert Encoding::UTF_8, s.encoding
+        assert true, "abc".b == "abc"
+        assert false, s.b == s
+        t = s.b.force_encoding("UTF-8")
+        assert true, t == s
+        assert Encoding::US_ASCII, "abc".force_encoding(Encoding::US_ASCII).encoding
+        invalid = [104, 195, 255, 108].pack("C*").force_encoding("UTF-8")
+        assert false, invalid.valid_encoding?
+        assert true, invalid.b.valid_encoding?
+        assert 4, invalid.size
+        assert [104, 239, 191, 189, 239, 191, 189, 108], invalid.scrub.bytes
+        assert "h??l", invalid.scrub("?")
+        assert "h<195><255>l", invalid.scrub { |x| "<" + x.getbyte(0).to_s + ">" }
+        assert "h?llo", s.encode("US-ASCII", undef: :replace)
+        assert "h*llo".b, s.encode("BINARY", undef: :replace, replace: "*")
+        assert "h**l", invalid.encode("US-ASCII", invalid: :replace, replace: "*")
+        assert [104, 239, 191, 189, 239, 191, 189, 108], s.b.encode("UTF-8", undef: :replace).bytes[0, 8]
+        assert_error { s.encode("US-ASCII") }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn string_encoding_error() {
+        let program = r#"
+        assert_error { "é".b + "é" }
+        "#;
+        assert_script(program);
+    }
+
     #[test]
     fn string_format() {
         let program = r#"
@@ -821,4 +2269,262 @@ mod test {
         "#;
         assert_script(program);
     }
+
+    #[test]
+    fn string_case() {
+        let program = r#"
+        assert "ruby is great.", "RUBY Is Great.".downcase
+        assert "Ruby is great.", "rUBY IS GREAT.".capitalize
+        assert "rUBY iS gREAT.", "Ruby Is Great.".swapcase
+        assert "éa", "ÉA".downcase
+        s = "Ruby"
+        assert "RUBY", s.upcase!
+        assert nil, s.upcase!
+        assert "RUBY", s
+        assert "ruby", s.downcase!
+        assert "Ruby", s.capitalize!
+        assert "rUBY", s.swapcase!
+        assert 0, "aBc".casecmp("AbC")
+        assert(-1, "abc".casecmp("abd"))
+        assert true, "äBc".casecmp?("ÄbC")
+        assert false, "abc".casecmp?("abd")
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn string_strip_justify() {
+        let program = r#"
+        assert "abc", "  abc \n\t".strip
+        assert "abc \n", "  abc \n".lstrip
+        assert "  abc", "  abc \n".rstrip
+        s = " x "
+        assert "x", s.strip!
+        assert nil, s.strip!
+        assert nil, "abc".lstrip!
+        assert "  abc   ", "abc".center(8)
+        assert "12abc121", "abc".center(8, "12")
+        assert "abc**", "abc".ljust(5, "*")
+        assert "**abc", "abc".rjust(5, "*")
+        assert "abc", "abc".rjust(2)
+        assert "  ré", "ré".rjust(4)
+        assert_error { "abc".center(5, "") }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn string_search() {
+        let program = r#"
+        s = "héllo world"
+        assert 2, s.index("l")
+        assert 9, s.index("l", 4)
+        assert nil, s.index("z")
+        assert 7, s.index(/o/, 5)
+        assert 9, s.rindex("l")
+        assert 3, s.rindex("l", 8)
+        assert 7, s.rindex(/o/)
+        assert nil, s.rindex("h", -12)
+        assert true, s.include?("lo w")
+        assert false, s.include?("low")
+        assert true, s.end_with?("x", "world")
+        assert false, s.end_with?("worl")
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn string_lines() {
+        let program = r#"
+        s = "a\nbb\r\nccc"
+        assert ["a\n", "bb\r\n", "ccc"], s.lines
+        assert ["a", "bb", "ccc"], s.lines(chomp: true)
+        assert ["a\nb", "b", "\r\nccc"], s.lines("b")
+        assert ["p1\n\n\n", "p2\n"], "p1\n\n\np2\n".lines("")
+        res = []
+        s.each_line { |l| res << l }
+        assert ["a\n", "bb\r\n", "ccc"], res
+        assert ["a\n", "bb\r\n", "ccc"], s.each_line.map { |l| l }
+        res = []
+        "hé!".each_char { |c| res << c }
+        assert ["h", "é", "!"], res
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn string_edit() {
+        let program = r#"
+        s = "hello world"
+        assert "ell", s.slice(1, 3)
+        assert "world", s["world"]
+        assert nil, s["xyz"]
+        assert "wor", s[/w(or)/]
+        assert "or", s[/w(or)/, 1]
+        s[0] = "J"
+        assert "Jello world", s
+        s[1..4] = "ELLO"
+        assert "JELLO world", s
+        s["world"] = "there"
+        assert "JELLO there", s
+        s[/E(L+)/, 1] = "l"
+        assert "JElO there", s
+        s[9] = "!"
+        assert "JElO ther!", s
+        s[10] = "?"
+        assert "JElO ther!?", s
+        t = "hello"
+        assert "ll", t.slice!(2, 2)
+        assert "heo", t
+        assert nil, t.slice!(5)
+        assert "hXeo", t.insert(1, "X")
+        assert "hXeo!", t.insert(-1, "!")
+        assert "new", t.replace("new")
+        assert "new", t
+        assert "wén", "néw".reverse
+        u = "abc"
+        assert "cba", u.reverse!
+        assert "cba", u
+        assert_error { s["nothing"] = "x" }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn string_charset() {
+        let program = r#"
+        assert "yelow mon", "yellow moon".squeeze
+        assert "yelow moon", "yellow moon".squeeze("l")
+        assert "heo word", "hello world".delete("l", "lo")
+        assert "hll wrld", "hello world".delete("aeiou")
+        assert "eoo", "hello world".delete("^aeiou")
+        assert 2, "hello world".count("lo", "o-z")
+        assert 8, "hello world".count("^aeiou")
+        assert 2, "a-b-c".count("\\-")
+        assert "hippo", "hello".tr("el", "ip")
+        assert "*e**o", "hello".tr("^aeiou", "*")
+        assert "hEllO", "hello".tr("a-y", "A-Y").tr("A-Z", "a-z").tr("eo", "EO")
+        assert "ho", "hello".tr("el", "")
+        assert "hero", "hello".tr_s("l", "r")
+        assert "h*o", "hello".tr_s("el", "*")
+        s = "aaa"
+        assert "a", s.squeeze!
+        assert nil, s.squeeze!
+        assert "b", s.tr!("a", "b")
+        assert nil, s.delete!("x")
+        assert_error { "a".tr("z-a", "x") }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn string_succ_upto() {
+        let program = r#"
+        assert "abd", "abc".succ
+        assert "ba", "az".next
+        assert "AAA", "ZZ".succ
+        assert "2.0", "1.9".succ
+        s = "a9"
+        assert "b0", s.succ!
+        assert "b0", s
+        res = []
+        "a".upto("e") { |x| res << x }
+        assert ["a", "b", "c", "d", "e"], res
+        res = []
+        "9".upto("11") { |x| res << x }
+        assert ["9", "10", "11"], res
+        res = []
+        "08".upto("11", true) { |x| res << x }
+        assert ["08", "09", "10"], res
+        res = []
+        "a8".upto("b1") { |x| res << x }
+        assert ["a8", "a9", "b0", "b1"], res
+        res = []
+        "b".upto("a") { |x| res << x }
+        assert [], res
+        assert ["aa", "ab"], "aa".upto("ab").map { |x| x }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn string_numeric() {
+        let program = r#"
+        assert 97, "a".ord
+        assert 233, "é".ord
+        assert 255, "0xff".hex
+        assert(-26, "-1a".hex)
+        assert 0, "zz".hex
+        assert 8, "010".oct
+        assert 15, "0o17".oct
+        assert 5, "0b101".oct
+        assert(-16, "-0x10".oct)
+        assert 12, " 12abc".to_i
+        assert 1000, "1_000".to_i
+        assert 1, "1__000".to_i
+        assert(-5, "-5".to_i)
+        assert 0, "abc".to_i
+        assert 255, "ff".to_i(16)
+        assert 5, "101".to_i(2)
+        assert 1.5, "1.5".to_f
+        assert 0.5, ".5abc".to_f
+        assert 120.0, "1.2e2".to_f
+        assert(-3.0, " -3e".to_f)
+        assert 0.0, "x".to_f
+        assert_error { "".ord }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn string_partition_match() {
+        let program = r#"
+        assert ["hello", " ", "big world"], "hello big world".partition(" ")
+        assert ["hello big", " ", "world"], "hello big world".rpartition(" ")
+        assert ["hello", "", ""], "hello".partition("x")
+        assert ["", "", "hello"], "hello".rpartition("x")
+        assert ["he", "ll", "o"], "hello".partition(/l+/)
+        assert ["hel", "l", "o"], "hello".rpartition(/l+/)
+        m = "hello world".match(/(o) (w)/)
+        assert "o w", m[0]
+        assert "w", m[2]
+        assert "o w", $&
+        assert "w", $2
+        assert nil, "hello".match(/z/)
+        assert "ll", "hello".match("l+")[0]
+        assert "l", "hello".match(/l/, 3)[0]
+        assert 3, "hello".match(/l/, 3) { |m| m.begin(0) }
+        assert true, "hello".match?(/ll/)
+        assert false, "hello".match?(/ll/, 3)
+        assert true, /ll/.match?("hello")
+        assert "ll", /l+/.match("hello")[0]
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn string_normalize_eq() {
+        let program = r#"
+        nfd = "é".unicode_normalize(:nfd)
+        assert 2, nfd.size
+        assert 1, nfd.unicode_normalize.size
+        assert "é", nfd.unicode_normalize(:nfc)
+                assert "fi", "ﬁ".unicode_normalize(:nfkc)
+        assert "ﬁ", "ﬁ".unicode_normalize(:nfc)
+        assert false, nfd.unicode_normalized?
+        assert true, "é".unicode_normalized?
+        s = nfd.dup
+        assert "é", s.unicode_normalize!
+        assert "é", s
+        assert true, "abc".send(:==, "abc")
+        assert true, "abc".eql?("abc")
+        assert false, "abc".eql?(:abc)
+        assert "abc".hash, "ab".+("c").hash
+        assert "abc".hash, "abc".b.hash
+        assert true, "abc".b.eql?("abc")
+        h = {"abc" => 1}
+        assert 1, h["ab" + "c"]
+        "#;
+        assert_script(program);
+    }
 }
@@ -24,7 +24,7 @@ fn struct_new(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
         }
     };
 
-    let mut val = Value::class_from(&mut vm.globals, name, self_val);
+    let val = Value::class_from(&mut vm.globals, name, self_val);
     let class = val.as_class();
     vm.globals
         .add_builtin_instance_method(class, "initialize", initialize);
@@ -46,10 +46,8 @@ fn struct_new(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
         vec.push(v);
         attr_args[index - i] = v;
     }
-    val.set_var(
-        vm.globals.get_ident_id("_members"),
-        Value::array_from(&vm.globals, vec),
-    );
+    let members = Value::array_from(&vm.globals, vec);
+    val.set_var(&vm.globals, IdentId::STRUCT_MEMBERS, members);
     builtin::module::attr_accessor(vm, val, &attr_args)?;
 
     match args.block {
@@ -64,10 +62,10 @@ fn struct_new(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     Ok(val)
 }
 
-fn initialize(vm: &mut VM, mut self_val: Value, args: &Args) -> VMResult {
+fn initialize(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
     let class = self_val.get_class_object(&vm.globals);
     let members = class
-        .get_var(vm.globals.get_ident_id("_members"))
+        .get_var(IdentId::STRUCT_MEMBERS)
         .unwrap()
         .as_array()
         .unwrap();
@@ -77,7 +75,8 @@ fn initialize(vm: &mut VM, mut self_val: Value, args: &Args) -> VMResult {
     for (i, arg) in args.iter().enumerate() {
         let id = members.elements[i].as_symbol().unwrap();
         let var = format!("@{}", vm.globals.get_ident_name(id));
-        self_val.set_var(vm.globals.get_ident_id(var), *arg);
+        let id = vm.globals.get_ident_id(var);
+        self_val.set_var(&vm.globals, id, *arg);
     }
     Ok(Value::nil())
 }
@@ -85,7 +84,7 @@ fn initialize(vm: &mut VM, mut self_val: Value, args: &Args) -> VMResult {
 fn inspect(vm: &mut VM, self_val: Value, _args: &Args) -> VMResult {
     let members = match self_val
         .get_class_object(&vm.globals)
-        .get_var(vm.globals.get_ident_id("_members"))
+        .get_var(IdentId::STRUCT_MEMBERS)
     {
         Some(v) => match v.as_array() {
             Some(aref) => aref,
@@ -0,0 +1,346 @@
+use crate::builtin::string::succ_str;
+use crate::*;
+
+pub fn init_symbol(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("Symbol");
+    let class = ClassRef::from(id, globals.builtins.object);
+    globals.add_builtin_instance_method(class, "to_s", to_s);
+    globals.add_builtin_instance_method(class, "id2name", to_s);
+    globals.add_builtin_instance_method(class, "name", to_s);
+    globals.add_builtin_instance_method(class, "to_sym", to_sym);
+    globals.add_builtin_instance_method(class, "to_proc", to_proc);
+    globals.add_builtin_instance_method(class, "inspect", inspect);
+    globals.add_builtin_instance_method(class, "<=>", cmp);
+    globals.add_builtin_instance_method(class, "length", length);
+    globals.add_builtin_instance_method(class, "size", length);
+    globals.add_builtin_instance_method(class, "empty?", empty);
+    globals.add_builtin_instance_method(class, "upcase", upcase);
+    globals.add_builtin_instance_method(class, "downcase", downcase);
+    globals.add_builtin_instance_method(class, "capitalize", capitalize);
+    globals.add_builtin_instance_method(class, "swapcase", swapcase);
+    globals.add_builtin_instance_method(class, "start_with?", start_with);
+    globals.add_builtin_instance_method(class, "end_with?", end_with);
+    globals.add_builtin_instance_method(class, "[]", index);
+    globals.add_builtin_instance_method(class, "succ", succ);
+    globals.add_builtin_instance_method(class, "next", succ);
+    let symbol = Value::class(globals, class);
+    globals.add_builtin_class_method(symbol, "all_symbols", all_symbols);
+    symbol
+}
+
+/// Returns the representation of the symbol `name` for `Symbol#inspect`.
+/// Names which can not be written as a bare symbol literal are quoted like `:"foo bar"`.
+pub fn inspect_symbol(name: &str) -> String {
+    if is_simple_symbol(name) {
+        format!(":{}", name)
+    } else {
+        format!(":\"{}\"", name.escape_debug())
+    }
+}
+
+fn is_simple_symbol(name: &str) -> bool {
+    const OPERATORS: &[&str] = &[
+        "+", "-", "*", "/", "%", "**", "==", "===", "!=", "<", ">", "<=", ">=", "<=>", "=~", "!~",
+        "!", "<<", ">>", "&", "|", "^", "~", "+@", "-@", "[]", "[]=",
+    ];
+    if OPERATORS.contains(&name) {
+        return true;
+    }
+    let ident = if name.starts_with("@@") {
+        &name[2..]
+    } else if name.starts_with('@') || name.starts_with('$') {
+        &name[1..]
+    } else {
+        name
+    };
+    let mut chars = ident.chars().peekable();
+    match chars.next() {
+        Some(ch) if ch.is_alphabetic() || ch == '_' => {}
+        _ => return false,
+    };
+    while let Some(ch) = chars.next() {
+        if ch.is_alphanumeric() || ch == '_' {
+            continue;
+        }
+        // `?`, `!` and `=` are allowed only at the end of a method name.
+        let is_suffix = ch == '?' || ch == '!' || ch == '=';
+        return is_suffix && ident.len() == name.len() && chars.peek().is_none();
+    }
+    true
+}
+
+fn expect_symbol(vm: &VM, val: Value) -> Result<IdentId, RubyError> {
+    match val.as_symbol() {
+        Some(id) => Ok(id),
+        None => Err(vm.error_unimplemented("Expected Symbol.")),
+    }
+}
+
+fn symbol_name(vm: &VM, val: Value) -> Result<String, RubyError> {
+    let id = expect_symbol(vm, val)?;
+    Ok(vm.globals.get_ident_name(id).to_string())
+}
+
+fn new_symbol(vm: &mut VM, name: String) -> Value {
+    Value::symbol(vm.globals.get_ident_id(name))
+}
+
+// Class methods
+
+fn all_symbols(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let ary = vm
+        .globals
+        .ident_table
+        .all_ids()
+        .into_iter()
+        .map(|id| Value::symbol(id))
+        .collect();
+    Ok(Value::array_from(&vm.globals, ary))
+}
+
+// Instance methods
+
+fn to_s(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let name = symbol_name(vm, self_val)?;
+    Ok(Value::string(&vm.globals, name))
+}
+
+fn to_sym(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    expect_symbol(vm, self_val)?;
+    Ok(self_val)
+}
+
+fn to_proc(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let id = expect_symbol(vm, self_val)?;
+    if let Some(procobj) = vm.globals.symbol_proc.get(&id) {
+        return Ok(*procobj);
+    }
+    let procobj = Value::builtin_proc(&vm.globals, call_symbol, vec![self_val], -2, true);
+    vm.globals.symbol_proc.insert(id, procobj);
+    Ok(procobj)
+}
+
+/// The body of a Proc made by Symbol#to_proc, which calls the method named by the symbol
+/// on the first argument with the rest of the arguments.
+/// The method is called like a call with an explicit receiver,
+/// so a private method can not be called.
+fn call_symbol(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let id = self_val.as_proc().unwrap().captures()[0]
+        .as_symbol()
+        .unwrap();
+    let receiver = match args.first() {
+        Some(receiver) => *receiver,
+        None => return Err(vm.error_argument("No receiver given.")),
+    };
+    let method = vm.get_public_method(receiver, id)?;
+    let mut rest = Args::new(0);
+    for val in args.iter().skip(1) {
+        rest.push(*val);
+    }
+    rest.block = args.block;
+    rest.kw_arg = args.kw_arg;
+    vm.eval_send(method, receiver, &rest)
+}
+
+fn inspect(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let name = symbol_name(vm, self_val)?;
+    Ok(Value::string(&vm.globals, inspect_symbol(&name)))
+}
+
+fn cmp(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let lhs = symbol_name(vm, self_val)?;
+    let rhs = match args[0].as_symbol() {
+        Some(id) => vm.globals.get_ident_name(id),
+        None => return Ok(Value::nil()),
+    };
+    Ok(Value::fixnum(lhs.as_str().cmp(rhs) as i64))
+}
+
+fn length(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let name = symbol_name(vm, self_val)?;
+    Ok(Value::fixnum(name.chars().count() as i64))
+}
+
+fn empty(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let name = symbol_name(vm, self_val)?;
+    Ok(Value::bool(name.is_empty()))
+}
+
+fn upcase(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let name = symbol_name(vm, self_val)?;
+    Ok(new_symbol(vm, name.to_uppercase()))
+}
+
+fn downcase(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let name = symbol_name(vm, self_val)?;
+    Ok(new_symbol(vm, name.to_lowercase()))
+}
+
+fn capitalize(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let name = symbol_name(vm, self_val)?;
+    let mut chars = name.chars();
+    let res = match chars.next() {
+        Some(ch) => ch
+            .to_uppercase()
+            .chain(chars.as_str().to_lowercase().chars())
+            .collect(),
+        None => String::new(),
+    };
+    Ok(new_symbol(vm, res))
+}
+
+fn swapcase(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let name = symbol_name(vm, self_val)?;
+    let res = name
+        .chars()
+        .flat_map(|ch| {
+            if ch.is_uppercase() {
+                ch.to_lowercase().collect::<Vec<char>>()
+            } else {
+                ch.to_uppercase().collect::<Vec<char>>()
+            }
+        })
+        .collect();
+    Ok(new_symbol(vm, res))
+}
+
+fn start_with(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_min(args.len(), 1)?;
+    let name = symbol_name(vm, self_val)?;
+    for arg in args.iter() {
+        let prefix = vm.expect_string(arg, "Prefix")?;
+        if name.starts_with(prefix.as_str()) {
+            return Ok(Value::true_val());
+        }
+    }
+    Ok(Value::false_val())
+}
+
+fn end_with(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_min(args.len(), 1)?;
+    let name = symbol_name(vm, self_val)?;
+    for arg in args.iter() {
+        let suffix = vm.expect_string(arg, "Suffix")?;
+        if name.ends_with(suffix.as_str()) {
+            return Ok(Value::true_val());
+        }
+    }
+    Ok(Value::false_val())
+}
+
+/// Same as `to_s[args]`.
+fn index(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    let name = symbol_name(vm, self_val)?;
+    let string = Value::string(&vm.globals, name);
+    let id = vm.globals.get_ident_id("[]");
+    let method = vm.get_method(string, id)?;
+    vm.eval_send(method, string, args)
+}
+
+fn succ(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let name = symbol_name(vm, self_val)?;
+    Ok(new_symbol(vm, succ_str(&name)))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn symbol_to_proc() {
+        let program = r#"
+    assert(["1", "2", "3"], [1, 2, 3].map(&:to_s))
+    assert([2, 1], [[1, 2], [3]].map(&:size))
+    pr = :upcase.to_proc
+    assert(true, pr.lambda?)
+    assert("FOO", pr.call("foo"))
+    assert(-2, pr.arity)
+    assert([[:req], [:rest]], pr.parameters)
+    assert_error { pr.call }
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn symbol_to_proc_private() {
+        let program = r#"
+    class Foo
+        def pub
+            secret
+        end
+        private
+        def secret
+            7
+        end
+    end
+    assert([7], [Foo.new].map(&:pub))
+    assert_error { [Foo.new].map(&:secret) }
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn symbol_methods() {
+        let program = r#"
+    assert("foo", :foo.to_s)
+    assert(:foo, :foo.to_sym)
+    assert(-1, :abc <=> :abd)
+    assert(0, :abc <=> :abc)
+    assert(nil, :abc <=> "abc")
+    assert(3, :foo.length)
+    assert(:FOO, :foo.upcase)
+    assert(:foo, :FoO.downcase)
+    assert(:Foo, :fOO.capitalize)
+    assert(true, :foobar.start_with?("baz", "foo"))
+    assert(true, :foobar.end_with?("bar"))
+    assert("o", :foo[1])
+    assert("fo", :foo[0..1])
+    assert(:b, :a.succ)
+    assert(:ba, :az.succ)
+    assert(:"10", :"9".succ)
+    assert(:AAA, :ZZ.succ)
+    assert(true, Symbol.all_symbols.include?(:foo))
+    def fwd(...)
+    end
+    assert(true, Symbol.all_symbols.include?(:**))
+    assert(true, Symbol.all_symbols.include?(:&))
+    assert(true, Symbol.all_symbols.include?(:_members))
+    assert(true, Symbol.all_symbols.include?(:_method))
+    "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn symbol_literal() {
+        let program = r#"
+    assert(":foo", :foo.inspect)
+    assert(":foo?", :foo?.inspect)
+    assert(":@foo", :@foo.inspect)
+    assert(":+", :+.inspect)
+    assert(":[]=", :[]=.inspect)
+    assert(":<=>", :<=>.inspect)
+    assert(":\"foo bar\"", :"foo bar".inspect)
+    assert(":\"9a\"", :"9a".inspect)
+    assert(:foo, :"foo")
+    x = 3
+    assert(:foo3, :"foo#{x}")
+    assert(:"foo bar", %s(foo bar))
+    assert(:baz, %s[baz])
+    assert("ab", "a".send(:+, "b"))
+    "#;
+        assert_script(program);
+    }
+}
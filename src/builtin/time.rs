@@ -0,0 +1,1635 @@
+use crate::builtin::timezone::{LocalType, TimeZone};
+use crate::*;
+use std::cmp::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The zone which a Time shows its fields in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zone {
+    Local,
+    Utc,
+    /// A fixed offset from UTC in seconds.
+    Fixed(i64),
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeInfo {
+    /// Seconds since the Unix epoch.
+    secs: i64,
+    /// Nanoseconds in the second.
+    nsec: i64,
+    zone: Zone,
+}
+
+/// Times are equal when they are the same instant in any zone.
+impl PartialEq for TimeInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.compare(other) == Ordering::Equal
+    }
+}
+
+impl std::hash::Hash for TimeInfo {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (self.secs, self.nsec).hash(state);
+    }
+}
+
+/// Fields of a time in its zone.
+struct Civil {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    min: i64,
+    sec: i64,
+    /// The day of the week. 0 is Sunday.
+    wday: i64,
+    /// The day of the year. 1 is January 1.
+    yday: i64,
+    local: LocalType,
+}
+
+/// Times are limited to this many seconds from the epoch, about a billion years,
+/// so that the calculations of calendar fields never overflow.
+const MAX_SECS: i64 = 1 << 55;
+const MAX_YEAR: i64 = MAX_SECS / (366 * 86400);
+/// The maximum width of a strftime directive.
+const MAX_WIDTH: usize = i32::MAX as usize;
+
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const DAY_NAMES: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// Days since 1970-01-01 of the date in the proleptic Gregorian calendar.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// The date of days since 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+pub fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn local_zone() -> &'static TimeZone {
+    static LOCAL: std::sync::OnceLock<TimeZone> = std::sync::OnceLock::new();
+    LOCAL.get_or_init(TimeZone::local)
+}
+
+/// The ISO 8601 week-based year and week number.
+fn iso_week(year: i64, yday: i64, wday: i64) -> (i64, i64) {
+    let weeks = |year: i64| {
+        let jan1 = (days_from_civil(year, 1, 1) + 4).rem_euclid(7);
+        if jan1 == 4 || (jan1 == 3 && is_leap_year(year)) {
+            53
+        } else {
+            52
+        }
+    };
+    let iso_wday = if wday == 0 { 7 } else { wday };
+    let week = (yday - iso_wday + 10) / 7;
+    if week < 1 {
+        (year - 1, weeks(year - 1))
+    } else if week > weeks(year) {
+        (year + 1, 1)
+    } else {
+        (year, week)
+    }
+}
+
+/// Flags, width and colons of a strftime directive.
+#[derive(Default)]
+struct Spec {
+    pad: Option<char>,
+    no_pad: bool,
+    upcase: bool,
+    swapcase: bool,
+    width: Option<usize>,
+    colons: usize,
+}
+
+impl Spec {
+    fn num(&self, n: i64, width: usize, pad: char) -> String {
+        if self.no_pad {
+            return n.to_string();
+        }
+        let width = self.width.unwrap_or(width);
+        let pad = self.pad.unwrap_or(pad);
+        let sign = if n < 0 { "-" } else { "" };
+        let digits = n.abs().to_string();
+        let fill = width.saturating_sub(sign.len() + digits.len());
+        if pad == '0' {
+            format!("{}{}{}", sign, "0".repeat(fill), digits)
+        } else {
+            format!("{}{}{}", " ".repeat(fill), sign, digits)
+        }
+    }
+
+    fn text(&self, s: &str) -> String {
+        let s = if self.upcase {
+            s.to_uppercase()
+        } else if self.swapcase {
+            if s.chars().any(|c| c.is_lowercase()) {
+                s.to_uppercase()
+            } else {
+                s.to_lowercase()
+            }
+        } else {
+            s.to_string()
+        };
+        let fill = match self.width {
+            Some(width) if !self.no_pad => width.saturating_sub(s.chars().count()),
+            _ => 0,
+        };
+        let pad = self.pad.unwrap_or(' ').to_string();
+        format!("{}{}", pad.repeat(fill), s)
+    }
+
+    /// The first `digits` digits of the fraction of a second.
+    fn fraction(&self, nsec: i64, digits: usize) -> String {
+        let digits = self.width.unwrap_or(digits);
+        let mut frac = format!("{:09}", nsec);
+        if digits <= 9 {
+            frac.truncate(digits);
+        } else {
+            frac += &"0".repeat(digits - 9);
+        }
+        frac
+    }
+
+    fn offset(&self, offset: i64) -> String {
+        let sign = if offset < 0 { '-' } else { '+' };
+        let abs = offset.abs();
+        let (hour, min, sec) = (abs / 3600, abs % 3600 / 60, abs % 60);
+        let body = match self.colons {
+            0 => format!("{:02}{:02}", hour, min),
+            1 => format!("{:02}:{:02}", hour, min),
+            2 => format!("{:02}:{:02}:{:02}", hour, min, sec),
+            _ if sec != 0 => format!("{:02}:{:02}:{:02}", hour, min, sec),
+            _ if min != 0 => format!("{:02}:{:02}", hour, min),
+            _ => format!("{:02}", hour),
+        };
+        let fill = match self.width {
+            Some(width) if !self.no_pad => width.saturating_sub(body.len() + 1),
+            _ => 0,
+        };
+        match self.pad {
+            Some(' ') => format!("{}{}{}", " ".repeat(fill), sign, body),
+            _ => format!("{}{}{}", sign, "0".repeat(fill), body),
+        }
+    }
+}
+
+impl TimeInfo {
+    /// The time of `secs` and `nsec` since the epoch, or None if it is out of the range of Time.
+    pub fn new(secs: i64, nsec: i64, zone: Zone) -> Option<Self> {
+        let secs = secs.checked_add(nsec.div_euclid(1_000_000_000))?;
+        if !(-MAX_SECS..=MAX_SECS).contains(&secs) {
+            return None;
+        }
+        Some(TimeInfo {
+            secs,
+            nsec: nsec.rem_euclid(1_000_000_000),
+            zone,
+        })
+    }
+
+    pub fn now(zone: Zone) -> Self {
+        TimeInfo::from_system_time(SystemTime::now(), zone)
+    }
+
+    pub fn from_system_time(time: SystemTime, zone: Zone) -> Self {
+        let (secs, nsec) = match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => (d.as_secs() as i64, d.subsec_nanos() as i64),
+            Err(err) => {
+                let d = err.duration();
+                (-(d.as_secs() as i64), -(d.subsec_nanos() as i64))
+            }
+        };
+        TimeInfo::new(secs, nsec, zone).unwrap_or(TimeInfo {
+            secs: secs.clamp(-MAX_SECS, MAX_SECS),
+            nsec: 0,
+            zone,
+        })
+    }
+
+    /// The time of the fields in `zone`. The fields other than the year are within their ranges
+    /// or overflow into the larger ones a little.
+    /// Returns None if the time is out of the range of Time.
+    fn from_civil(
+        date: (i64, i64, i64),
+        time: (i64, i64, i64),
+        nsec: i64,
+        zone: Zone,
+    ) -> Option<Self> {
+        let (year, month, day) = date;
+        let (hour, min, sec) = time;
+        if !(-MAX_YEAR..=MAX_YEAR).contains(&year) {
+            return None;
+        }
+        // Months out of 1..=12 move the year.
+        let (year, month) = (
+            year + (month - 1).div_euclid(12),
+            (month - 1).rem_euclid(12) + 1,
+        );
+        let local = days_from_civil(year, month, 1) * 86400
+            + (day - 1) * 86400
+            + hour * 3600
+            + min * 60
+            + sec;
+        let secs = match zone {
+            Zone::Utc => local,
+            Zone::Fixed(offset) => local - offset,
+            Zone::Local => local_zone().to_utc(local),
+        };
+        TimeInfo::new(secs, nsec, zone)
+    }
+
+    fn local_type(&self) -> LocalType {
+        match self.zone {
+            Zone::Utc => LocalType {
+                offset: 0,
+                is_dst: false,
+                abbr: "UTC".to_string(),
+            },
+            Zone::Fixed(offset) => LocalType {
+                offset,
+                is_dst: false,
+                abbr: String::new(),
+            },
+            Zone::Local => local_zone().lookup(self.secs),
+        }
+    }
+
+    fn civil(&self) -> Civil {
+        let local = self.local_type();
+        let secs = self.secs + local.offset;
+        let days = secs.div_euclid(86400);
+        let time = secs.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        Civil {
+            year,
+            month,
+            day,
+            hour: time / 3600,
+            min: time % 3600 / 60,
+            sec: time % 60,
+            wday: (days + 4).rem_euclid(7),
+            yday: days - days_from_civil(year, 1, 1) + 1,
+            local,
+        }
+    }
+
+    pub fn compare(&self, other: &TimeInfo) -> Ordering {
+        (self.secs, self.nsec).cmp(&(other.secs, other.nsec))
+    }
+
+    /// The time `secs` and `nsec` after this, or None if it is out of the range of Time.
+    fn add(&self, secs: i64, nsec: i64) -> Option<Self> {
+        TimeInfo::new(
+            self.secs.checked_add(secs)?,
+            self.nsec.checked_add(nsec)?,
+            self.zone,
+        )
+    }
+
+    fn to_f(&self) -> f64 {
+        self.secs as f64 + self.nsec as f64 / 1e9
+    }
+
+    /// Format the time by a fixed `format`.
+    pub fn strftime(&self, format: &str) -> String {
+        self.try_strftime(format).unwrap_or_default()
+    }
+
+    /// Format the time by `format`, or returns None if a width in `format` is too large.
+    fn try_strftime(&self, format: &str) -> Option<String> {
+        let civil = self.civil();
+        let chars: Vec<char> = format.chars().collect();
+        let mut res = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] != '%' {
+                res.push(chars[i]);
+                i += 1;
+                continue;
+            }
+            let start = i;
+            i += 1;
+            let mut spec = Spec::default();
+            loop {
+                match chars.get(i) {
+                    Some('-') => spec.no_pad = true,
+                    Some('_') => spec.pad = Some(' '),
+                    Some('0') => spec.pad = Some('0'),
+                    Some('^') => spec.upcase = true,
+                    Some('#') => spec.swapcase = true,
+                    _ => break,
+                }
+                i += 1;
+            }
+            while let Some(d) = chars.get(i).and_then(|c| c.to_digit(10)) {
+                let width = spec
+                    .width
+                    .unwrap_or(0)
+                    .checked_mul(10)?
+                    .checked_add(d as usize)?;
+                if width > MAX_WIDTH {
+                    return None;
+                }
+                spec.width = Some(width);
+                i += 1;
+            }
+            while chars.get(i) == Some(&':') {
+                spec.colons += 1;
+                i += 1;
+            }
+            if matches!(chars.get(i), Some('E') | Some('O')) {
+                i += 1;
+            }
+            let conv = match chars.get(i) {
+                Some(c) => *c,
+                None => {
+                    res.extend(&chars[start..]);
+                    break;
+                }
+            };
+            i += 1;
+            match self.directive(&civil, conv, &spec) {
+                Some(s) => res += &s,
+                None => res.extend(&chars[start..i]),
+            }
+        }
+        Some(res)
+    }
+
+    fn directive(&self, c: &Civil, conv: char, spec: &Spec) -> Option<String> {
+        let hour12 = if c.hour % 12 == 0 { 12 } else { c.hour % 12 };
+        let s = match conv {
+            'Y' => spec.num(c.year, 4, '0'),
+            'C' => spec.num(c.year.div_euclid(100), 2, '0'),
+            'y' => spec.num(c.year.rem_euclid(100), 2, '0'),
+            'm' => spec.num(c.month, 2, '0'),
+            'B' => spec.text(MONTH_NAMES[c.month as usize - 1]),
+            'b' | 'h' => spec.text(&MONTH_NAMES[c.month as usize - 1][..3]),
+            'd' => spec.num(c.day, 2, '0'),
+            'e' => spec.num(c.day, 2, ' '),
+            'j' => spec.num(c.yday, 3, '0'),
+            'H' => spec.num(c.hour, 2, '0'),
+            'k' => spec.num(c.hour, 2, ' '),
+            'I' => spec.num(hour12, 2, '0'),
+            'l' => spec.num(hour12, 2, ' '),
+            'P' => spec.text(if c.hour < 12 { "am" } else { "pm" }),
+            'p' => spec.text(if c.hour < 12 { "AM" } else { "PM" }),
+            'M' => spec.num(c.min, 2, '0'),
+            'S' => spec.num(c.sec, 2, '0'),
+            'L' => spec.fraction(self.nsec, 3),
+            'N' => spec.fraction(self.nsec, 9),
+            'z' => spec.offset(c.local.offset),
+            'Z' => spec.text(&c.local.abbr),
+            'A' => spec.text(DAY_NAMES[c.wday as usize]),
+            'a' => spec.text(&DAY_NAMES[c.wday as usize][..3]),
+            'u' => spec.num(if c.wday == 0 { 7 } else { c.wday }, 1, '0'),
+            'w' => spec.num(c.wday, 1, '0'),
+            'G' => spec.num(iso_week(c.year, c.yday, c.wday).0, 4, '0'),
+            'g' => spec.num(iso_week(c.year, c.yday, c.wday).0.rem_euclid(100), 2, '0'),
+            'V' => spec.num(iso_week(c.year, c.yday, c.wday).1, 2, '0'),
+            'U' => spec.num((c.yday - 1 + 7 - c.wday) / 7, 2, '0'),
+            'W' => spec.num((c.yday - 1 + 7 - (c.wday + 6) % 7) / 7, 2, '0'),
+            's' => spec.num(self.secs, 1, '0'),
+            'n' => "\n".to_string(),
+            't' => "\t".to_string(),
+            '%' => "%".to_string(),
+            'c' => spec.text(&self.strftime("%a %b %e %H:%M:%S %Y")),
+            'D' | 'x' => spec.text(&self.strftime("%m/%d/%y")),
+            'F' => spec.text(&self.strftime("%Y-%m-%d")),
+            'T' | 'X' => spec.text(&self.strftime("%H:%M:%S")),
+            'R' => spec.text(&self.strftime("%H:%M")),
+            'r' => spec.text(&self.strftime("%I:%M:%S %p")),
+            'v' => spec.text(&self.strftime("%e-%^b-%4Y")),
+            '+' => spec.text(&self.strftime("%a %b %e %H:%M:%S %Z %Y")),
+            _ => return None,
+        };
+        Some(s)
+    }
+
+    /// The time in the ISO 8601 format with `digits` digits of the fraction of a second.
+    fn iso8601(&self, digits: usize) -> String {
+        let mut s = self.strftime("%Y-%m-%dT%H:%M:%S");
+        if digits > 0 {
+            s += &format!(".{}", Spec::default().fraction(self.nsec, digits));
+        }
+        if self.zone == Zone::Utc {
+            s + "Z"
+        } else {
+            s + &self.strftime("%:z")
+        }
+    }
+
+    pub fn to_s(&self) -> String {
+        if self.zone == Zone::Utc {
+            self.strftime("%Y-%m-%d %H:%M:%S UTC")
+        } else {
+            self.strftime("%Y-%m-%d %H:%M:%S %z")
+        }
+    }
+
+    fn inspect(&self) -> String {
+        let mut s = self.strftime("%Y-%m-%d %H:%M:%S");
+        if self.nsec != 0 {
+            let frac = format!("{:09}", self.nsec);
+            s += &format!(".{}", frac.trim_end_matches('0'));
+        }
+        if self.zone == Zone::Utc {
+            s + " UTC"
+        } else {
+            s + &self.strftime(" %z")
+        }
+    }
+}
+
+pub type TimeRef = Ref<TimeInfo>;
+
+pub fn init_time(globals: &mut Globals) -> Value {
+    let id = globals.get_ident_id("Time");
+    let class = ClassRef::from(id, globals.builtins.object);
+    let obj = Value::class(globals, class);
+    globals.builtins.time = obj;
+    globals.add_builtin_class_method(obj, "now", now);
+    globals.add_builtin_class_method(obj, "at", at);
+    globals.add_builtin_class_method(obj, "new", time_new);
+    globals.add_builtin_class_method(obj, "utc", utc);
+    globals.add_builtin_class_method(obj, "gm", utc);
+    globals.add_builtin_class_method(obj, "local", local);
+    globals.add_builtin_class_method(obj, "mktime", local);
+    globals.add_builtin_instance_method(class, "year", year);
+    globals.add_builtin_instance_method(class, "month", month);
+    globals.add_builtin_instance_method(class, "mon", month);
+    globals.add_builtin_instance_method(class, "day", day);
+    globals.add_builtin_instance_method(class, "mday", day);
+    globals.add_builtin_instance_method(class, "hour", hour);
+    globals.add_builtin_instance_method(class, "min", min);
+    globals.add_builtin_instance_method(class, "sec", sec);
+    globals.add_builtin_instance_method(class, "usec", usec);
+    globals.add_builtin_instance_method(class, "tv_usec", usec);
+    globals.add_builtin_instance_method(class, "nsec", nsec);
+    globals.add_builtin_instance_method(class, "tv_nsec", nsec);
+    globals.add_builtin_instance_method(class, "subsec", subsec);
+    globals.add_builtin_instance_method(class, "wday", wday);
+    globals.add_builtin_instance_method(class, "yday", yday);
+    globals.add_builtin_instance_method(class, "zone", zone);
+    globals.add_builtin_instance_method(class, "utc_offset", utc_offset);
+    globals.add_builtin_instance_method(class, "gmt_offset", utc_offset);
+    globals.add_builtin_instance_method(class, "gmtoff", utc_offset);
+    globals.add_builtin_instance_method(class, "utc?", is_utc);
+    globals.add_builtin_instance_method(class, "gmt?", is_utc);
+    globals.add_builtin_instance_method(class, "dst?", is_dst);
+    globals.add_builtin_instance_method(class, "isdst", is_dst);
+    globals.add_builtin_instance_method(class, "utc", to_utc);
+    globals.add_builtin_instance_method(class, "gmtime", to_utc);
+    globals.add_builtin_instance_method(class, "getutc", getutc);
+    globals.add_builtin_instance_method(class, "getgm", getutc);
+    globals.add_builtin_instance_method(class, "localtime", localtime);
+    globals.add_builtin_instance_method(class, "getlocal", getlocal);
+    globals.add_builtin_instance_method(class, "+", add);
+    globals.add_builtin_instance_method(class, "-", sub);
+    globals.add_builtin_instance_method(class, "<=>", cmp);
+    globals.add_builtin_instance_method(class, "==", eq);
+    globals.add_builtin_instance_method(class, "eql?", eq);
+    globals.add_builtin_instance_method(class, "<", lt);
+    globals.add_builtin_instance_method(class, "<=", le);
+    globals.add_builtin_instance_method(class, ">", gt);
+    globals.add_builtin_instance_method(class, ">=", ge);
+    globals.add_builtin_instance_method(class, "to_i", to_i);
+    globals.add_builtin_instance_method(class, "tv_sec", to_i);
+    globals.add_builtin_instance_method(class, "to_f", to_f);
+    globals.add_builtin_instance_method(class, "to_a", to_a);
+    globals.add_builtin_instance_method(class, "strftime", strftime);
+    globals.add_builtin_instance_method(class, "iso8601", iso8601);
+    globals.add_builtin_instance_method(class, "xmlschema", iso8601);
+    globals.add_builtin_instance_method(class, "to_s", to_s);
+    globals.add_builtin_instance_method(class, "inspect", inspect);
+    obj
+}
+
+/// Time.parse and Time.strptime, which are loaded by `require "time"`.
+pub fn init_time_lib(globals: &mut Globals) {
+    let obj = globals.builtins.time;
+    globals.add_builtin_class_method(obj, "parse", parse);
+    globals.add_builtin_class_method(obj, "strptime", strptime);
+}
+
+// Arguments
+
+/// A zone given as "+HH:MM", "-HH:MM", "UTC", "Z" or an offset in seconds.
+fn zone_arg(vm: &mut VM, val: Value) -> Result<Zone, RubyError> {
+    if val.is_nil() {
+        return Ok(Zone::Local);
+    }
+    if let Some(offset) = val.as_fixnum() {
+        if offset.abs() >= 86400 {
+            return Err(vm.error_argument("utc_offset out of range"));
+        }
+        return Ok(Zone::Fixed(offset));
+    }
+    let s = vm.expect_string(&val, "Zone")?.to_string();
+    match parse_zone(&s) {
+        Some(zone) => Ok(zone),
+        None => Err(vm.error_argument(format!(
+            "\"+HH:MM\", \"-HH:MM\" or \"UTC\" expected for utc_offset: {}",
+            s
+        ))),
+    }
+}
+
+/// Parse "UTC", "Z", "+HH", "+HHMM", "+HH:MM" or "+HH:MM:SS".
+fn parse_zone(s: &str) -> Option<Zone> {
+    match s {
+        "UTC" | "Z" | "-00:00" => return Some(Zone::Utc),
+        _ => {}
+    }
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let body = &s[1..];
+    let parts: Vec<&str> = if body.contains(':') {
+        body.split(':').collect()
+    } else if body.len() == 4 || body.len() == 6 {
+        (0..body.len() / 2)
+            .map(|i| &body[i * 2..i * 2 + 2])
+            .collect()
+    } else {
+        vec![body]
+    };
+    if parts.len() > 3 || parts.iter().any(|p| p.len() != 2) {
+        return None;
+    }
+    let mut offset = 0;
+    for (part, unit) in parts.iter().zip([3600, 60, 1].iter()) {
+        let n: i64 = part.parse().ok()?;
+        if n >= if *unit == 3600 { 24 } else { 60 } {
+            return None;
+        }
+        offset += n * unit;
+    }
+    Some(Zone::Fixed(sign * offset))
+}
+
+/// The `in:` keyword argument.
+fn in_arg(vm: &mut VM, args: &Args) -> Result<Option<Zone>, RubyError> {
+    let val = match args.kw_arg {
+        Some(opt) => {
+            let key = Value::symbol(vm.globals.get_ident_id("in"));
+            opt.as_hash().unwrap().get(&key).cloned()
+        }
+        None => None,
+    };
+    match val {
+        Some(val) => Ok(Some(zone_arg(vm, val)?)),
+        None => Ok(None),
+    }
+}
+
+/// Seconds and nanoseconds of a number.
+fn seconds_arg(vm: &mut VM, val: Value) -> Result<(i64, i64), RubyError> {
+    match val.unpack() {
+        RV::Integer(i) => Ok((i, 0)),
+        RV::Float(f) => {
+            let secs = f.floor();
+            let nsec = ((f - secs) * 1e9).round() as i64;
+            Ok((float_to_i64(vm, secs)?, nsec))
+        }
+        _ => {
+            let class = vm.globals.get_class_name(val);
+            Err(vm.error_type(format!("can't convert {} into an exact number", class)))
+        }
+    }
+}
+
+/// An integral Float as i64. Infinity and NaN raise FloatDomainError, and a Float which
+/// does not fit in i64 raises RangeError.
+fn float_to_i64(vm: &VM, f: f64) -> Result<i64, RubyError> {
+    if !f.is_finite() {
+        let s = if f.is_nan() {
+            "NaN"
+        } else if f > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        };
+        return Err(vm.error_float_domain(s));
+    }
+    // i64::MAX as f64 is 2**63, which does not fit in i64.
+    if f < i64::MIN as f64 || f >= i64::MAX as f64 {
+        return Err(vm.error_range(format!("float {:e} out of range of Time", f)));
+    }
+    Ok(f as i64)
+}
+
+/// An Integer field of a time, which may be given as a String.
+fn int_arg(vm: &mut VM, val: Value) -> Result<i64, RubyError> {
+    if let Some(s) = val.as_string() {
+        return match s.trim().parse() {
+            Ok(n) => Ok(n),
+            Err(_) => Err(vm.error_argument(format!("invalid value for Integer(): {:?}", s))),
+        };
+    }
+    match val.unpack() {
+        RV::Integer(i) => Ok(i),
+        RV::Float(f) => Ok(f.floor() as i64),
+        _ => {
+            let class = vm.globals.get_class_name(val);
+            Err(vm.error_type(format!("can't convert {} into Integer", class)))
+        }
+    }
+}
+
+/// A month given as a number or an abbreviated English name.
+fn month_arg(vm: &mut VM, val: Value) -> Result<i64, RubyError> {
+    if let Some(s) = val.as_string() {
+        let s = s.to_lowercase();
+        if let Some(i) = MONTH_NAMES
+            .iter()
+            .position(|name| name[..3].to_lowercase() == s)
+        {
+            return Ok(i as i64 + 1);
+        }
+    }
+    int_arg(vm, val)
+}
+
+/// Build a time from year, month, day, hour, min and sec arguments, where sec may be a Float.
+/// `subsec` is the nanoseconds given separately.
+fn time_from_args(vm: &mut VM, args: &[Value], subsec: i64, zone: Zone) -> VMResult {
+    let year = int_arg(vm, args[0])?;
+    let month = match args.get(1) {
+        Some(val) if !val.is_nil() => month_arg(vm, *val)?,
+        _ => 1,
+    };
+    let mut fields = [1, 0, 0, 0];
+    for (i, field) in fields.iter_mut().enumerate().take(3) {
+        if let Some(val) = args.get(i + 2) {
+            if !val.is_nil() {
+                *field = int_arg(vm, *val)?;
+            }
+        }
+    }
+    let (sec, nsec) = match args.get(5) {
+        Some(val) if val.as_string().is_some() => (int_arg(vm, *val)?, 0),
+        Some(val) if !val.is_nil() => seconds_arg(vm, *val)?,
+        _ => (0, 0),
+    };
+    let [day, hour, min, _] = fields;
+    if !(1..=12).contains(&month) {
+        return Err(vm.error_argument("mon out of range"));
+    }
+    if !(1..=31).contains(&day)
+        || !(0..=24).contains(&hour)
+        || !(0..=59).contains(&min)
+        || !(0..=60).contains(&sec)
+        || (hour == 24 && (min != 0 || sec != 0))
+    {
+        return Err(vm.error_argument("argument out of range"));
+    }
+    let nsec = match nsec.checked_add(subsec) {
+        Some(nsec) => nsec,
+        None => return Err(vm.error_range("subsecond out of range")),
+    };
+    let time = TimeInfo::from_civil((year, month, day), (hour, min, sec), nsec, zone);
+    time_value(vm, time)
+}
+
+/// A Time object of `time`, or RangeError if the time is out of the range of Time.
+fn time_value(vm: &VM, time: Option<TimeInfo>) -> VMResult {
+    match time {
+        Some(time) => Ok(Value::time(&vm.globals, TimeRef::new(time))),
+        None => Err(vm.error_range("time out of range")),
+    }
+}
+
+fn expect_time(vm: &mut VM, val: Value) -> Result<TimeRef, RubyError> {
+    match val.as_time() {
+        Some(time) => Ok(time),
+        None => {
+            let class = vm.globals.get_class_name(val);
+            Err(vm.error_argument(format!("comparison of Time with {} failed", class)))
+        }
+    }
+}
+
+// Class methods
+
+fn now(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let zone = in_arg(vm, args)?.unwrap_or(Zone::Local);
+    Ok(Value::time(&vm.globals, TimeRef::new(TimeInfo::now(zone))))
+}
+
+/// Time.at(time, subsec = 0, unit = :microsecond, in: nil)
+fn at(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 1, 3)?;
+    let zone = in_arg(vm, args)?;
+    let (secs, mut nsec, default_zone) = match args[0].as_time() {
+        Some(time) => (time.secs, time.nsec, time.zone),
+        None => {
+            let (secs, nsec) = seconds_arg(vm, args[0])?;
+            (secs, nsec, Zone::Local)
+        }
+    };
+    if let Some(subsec) = args.get(1) {
+        let scale = match args.get(2).and_then(|unit| unit.as_symbol()) {
+            Some(unit) => match vm.globals.get_ident_name(unit) {
+                "millisecond" => 1_000_000.0,
+                "usec" | "microsecond" => 1_000.0,
+                "nsec" | "nanosecond" => 1.0,
+                name => return Err(vm.error_argument(format!("unexpected unit: {}", name))),
+            },
+            None if args.len() == 3 => return Err(vm.error_argument("unexpected unit")),
+            None => 1_000.0,
+        };
+        let subsec = match subsec.unpack() {
+            RV::Integer(i) => i.checked_mul(scale as i64),
+            RV::Float(f) => Some(float_to_i64(vm, (f * scale).round())?),
+            _ => Some(seconds_arg(vm, *subsec)?.0),
+        };
+        nsec = match subsec.and_then(|subsec| nsec.checked_add(subsec)) {
+            Some(nsec) => nsec,
+            None => return Err(vm.error_range("subsecond out of range")),
+        };
+    }
+    let time = TimeInfo::new(secs, nsec, zone.unwrap_or(default_zone));
+    time_value(vm, time)
+}
+
+/// Time.new(year = nil, mon = 1, day = 1, hour = 0, min = 0, sec = 0, zone = nil, in: nil)
+/// Without arguments, this is the current time.
+fn time_new(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 7)?;
+    let zone = match args.get(6) {
+        Some(zone) => zone_arg(vm, *zone)?,
+        None => in_arg(vm, args)?.unwrap_or(Zone::Local),
+    };
+    match args.first() {
+        Some(year) if !year.is_nil() => time_from_args(vm, &args[0..args.len().min(6)], 0, zone),
+        _ => Ok(Value::time(&vm.globals, TimeRef::new(TimeInfo::now(zone)))),
+    }
+}
+
+/// Arguments of Time.utc and Time.local:
+/// (year, mon = 1, day = 1, hour = 0, min = 0, sec = 0, usec = 0), or the 10 elements of Time#to_a.
+fn civil_args(vm: &mut VM, args: &Args, zone: Zone) -> VMResult {
+    vm.check_args_range(args.len(), 1, 10)?;
+    let args: Vec<Value> = args.iter().cloned().collect();
+    if args.len() == 10 {
+        let fields = vec![args[5], args[4], args[3], args[2], args[1], args[0]];
+        return time_from_args(vm, &fields, 0, zone);
+    }
+    let usec = match args.get(6) {
+        Some(usec) => match usec.unpack() {
+            RV::Float(f) => (f * 1000.0).round() as i64,
+            _ => int_arg(vm, *usec)? * 1000,
+        },
+        None => 0,
+    };
+    let len = args.len().min(6);
+    time_from_args(vm, &args[..len], usec, zone)
+}
+
+fn utc(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    civil_args(vm, args, Zone::Utc)
+}
+
+fn local(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    civil_args(vm, args, Zone::Local)
+}
+
+// Instance methods
+
+fn year(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::fixnum(self_val.as_time().unwrap().civil().year))
+}
+
+fn month(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::fixnum(self_val.as_time().unwrap().civil().month))
+}
+
+fn day(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::fixnum(self_val.as_time().unwrap().civil().day))
+}
+
+fn hour(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::fixnum(self_val.as_time().unwrap().civil().hour))
+}
+
+fn min(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::fixnum(self_val.as_time().unwrap().civil().min))
+}
+
+fn sec(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::fixnum(self_val.as_time().unwrap().civil().sec))
+}
+
+fn usec(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::fixnum(self_val.as_time().unwrap().nsec / 1000))
+}
+
+fn nsec(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::fixnum(self_val.as_time().unwrap().nsec))
+}
+
+/// The fraction of the second as a Float, as there is no Rational.
+fn subsec(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let time = self_val.as_time().unwrap();
+    if time.nsec == 0 {
+        return Ok(Value::fixnum(0));
+    }
+    Ok(Value::flonum(time.nsec as f64 / 1e9))
+}
+
+fn wday(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::fixnum(self_val.as_time().unwrap().civil().wday))
+}
+
+fn yday(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::fixnum(self_val.as_time().unwrap().civil().yday))
+}
+
+/// The abbreviation of the zone, or nil for a fixed offset.
+fn zone(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let time = self_val.as_time().unwrap();
+    if let Zone::Fixed(_) = time.zone {
+        return Ok(Value::nil());
+    }
+    Ok(Value::string(&vm.globals, time.local_type().abbr))
+}
+
+fn utc_offset(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let time = self_val.as_time().unwrap();
+    Ok(Value::fixnum(time.local_type().offset))
+}
+
+fn is_utc(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::bool(self_val.as_time().unwrap().zone == Zone::Utc))
+}
+
+fn is_dst(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let time = self_val.as_time().unwrap();
+    Ok(Value::bool(time.local_type().is_dst))
+}
+
+/// Time#utc converts the receiver itself to UTC.
+fn to_utc(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    self_val.as_time().unwrap().zone = Zone::Utc;
+    Ok(self_val)
+}
+
+fn getutc(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let time = self_val.as_time().unwrap();
+    let time = TimeInfo::new(time.secs, time.nsec, Zone::Utc);
+    time_value(vm, time)
+}
+
+/// Time#localtime(zone = nil) converts the receiver itself to the local time or `zone`.
+fn localtime(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let zone = match args.first() {
+        Some(zone) => zone_arg(vm, *zone)?,
+        None => Zone::Local,
+    };
+    self_val.as_time().unwrap().zone = zone;
+    Ok(self_val)
+}
+
+fn getlocal(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let zone = match args.first() {
+        Some(zone) => zone_arg(vm, *zone)?,
+        None => Zone::Local,
+    };
+    let time = self_val.as_time().unwrap();
+    let time = TimeInfo::new(time.secs, time.nsec, zone);
+    time_value(vm, time)
+}
+
+fn add(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    if args[0].as_time().is_some() {
+        return Err(vm.error_type("time + time?"));
+    }
+    let (secs, nsec) = seconds_arg(vm, args[0])?;
+    let time = self_val.as_time().unwrap().add(secs, nsec);
+    time_value(vm, time)
+}
+
+/// Time - Time is the difference in seconds as a Float, and Time - Numeric is a Time.
+fn sub(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let time = self_val.as_time().unwrap();
+    if let Some(other) = args[0].as_time() {
+        let secs = (time.secs - other.secs) as f64 + (time.nsec - other.nsec) as f64 / 1e9;
+        return Ok(Value::flonum(secs));
+    }
+    let (secs, nsec) = seconds_arg(vm, args[0])?;
+    let time = secs.checked_neg().and_then(|secs| time.add(secs, -nsec));
+    time_value(vm, time)
+}
+
+fn cmp(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    match args[0].as_time() {
+        Some(other) => {
+            let ord = self_val.as_time().unwrap().compare(&other);
+            Ok(Value::fixnum(ord as i64))
+        }
+        None => Ok(Value::nil()),
+    }
+}
+
+fn eq(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    match args[0].as_time() {
+        Some(other) => {
+            let ord = self_val.as_time().unwrap().compare(&other);
+            Ok(Value::bool(ord == Ordering::Equal))
+        }
+        None => Ok(Value::false_val()),
+    }
+}
+
+fn lt(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let other = expect_time(vm, args[0])?;
+    Ok(Value::bool(
+        self_val.as_time().unwrap().compare(&other) == Ordering::Less,
+    ))
+}
+
+fn le(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let other = expect_time(vm, args[0])?;
+    Ok(Value::bool(
+        self_val.as_time().unwrap().compare(&other) != Ordering::Greater,
+    ))
+}
+
+fn gt(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let other = expect_time(vm, args[0])?;
+    Ok(Value::bool(
+        self_val.as_time().unwrap().compare(&other) == Ordering::Greater,
+    ))
+}
+
+fn ge(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let other = expect_time(vm, args[0])?;
+    Ok(Value::bool(
+        self_val.as_time().unwrap().compare(&other) != Ordering::Less,
+    ))
+}
+
+fn to_i(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::fixnum(self_val.as_time().unwrap().secs))
+}
+
+fn to_f(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    Ok(Value::flonum(self_val.as_time().unwrap().to_f()))
+}
+
+/// [sec, min, hour, day, month, year, wday, yday, isdst, zone]
+fn to_a(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let time = self_val.as_time().unwrap();
+    let c = time.civil();
+    let zone = match time.zone {
+        Zone::Fixed(_) => Value::nil(),
+        _ => Value::string(&vm.globals, c.local.abbr.clone()),
+    };
+    let ary = vec![
+        Value::fixnum(c.sec),
+        Value::fixnum(c.min),
+        Value::fixnum(c.hour),
+        Value::fixnum(c.day),
+        Value::fixnum(c.month),
+        Value::fixnum(c.year),
+        Value::fixnum(c.wday),
+        Value::fixnum(c.yday),
+        Value::bool(c.local.is_dst),
+        zone,
+    ];
+    Ok(Value::array_from(&vm.globals, ary))
+}
+
+fn strftime(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let format = vm.expect_string(&args[0], "Format")?.to_string();
+    match self_val.as_time().unwrap().try_strftime(&format) {
+        Some(s) => Ok(Value::string(&vm.globals, s)),
+        None => Err(vm.error_range("width too large")),
+    }
+}
+
+fn iso8601(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_range(args.len(), 0, 1)?;
+    let digits = match args.first() {
+        Some(digits) => digits.expect_integer(vm, "Digits")?.max(0) as usize,
+        None => 0,
+    };
+    let s = self_val.as_time().unwrap().iso8601(digits);
+    Ok(Value::string(&vm.globals, s))
+}
+
+fn to_s(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let s = self_val.as_time().unwrap().to_s();
+    Ok(Value::string(&vm.globals, s))
+}
+
+fn inspect(vm: &mut VM, self_val: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 0)?;
+    let s = self_val.as_time().unwrap().inspect();
+    Ok(Value::string(&vm.globals, s))
+}
+
+// Parsing
+
+/// Fields read by Time.parse and Time.strptime.
+#[derive(Debug, Default)]
+struct Parsed {
+    year: Option<i64>,
+    month: Option<i64>,
+    day: Option<i64>,
+    yday: Option<i64>,
+    hour: Option<i64>,
+    min: Option<i64>,
+    sec: Option<i64>,
+    nsec: Option<i64>,
+    pm: Option<bool>,
+    zone: Option<Zone>,
+    epoch: Option<(i64, i64)>,
+}
+
+impl Parsed {
+    /// The time of the fields. Missing fields larger than the given ones are those of now,
+    /// and smaller ones are the minimum values.
+    fn to_time(&self) -> Option<TimeInfo> {
+        let zone = self.zone.unwrap_or(Zone::Local);
+        if let Some((secs, nsec)) = self.epoch {
+            return TimeInfo::new(secs, nsec, zone);
+        }
+        let (month, day) = match (self.yday, self.month, self.day) {
+            (Some(yday), None, None) => {
+                let year = self
+                    .year
+                    .filter(|year| (-MAX_YEAR..=MAX_YEAR).contains(year))?;
+                let (_, month, day) = civil_from_days(days_from_civil(year, 1, 1) + yday - 1);
+                (Some(month), Some(day))
+            }
+            _ => (self.month, self.day),
+        };
+        let hour = match (self.hour, self.pm) {
+            (Some(hour), Some(true)) if hour < 12 => Some(hour + 12),
+            (Some(12), Some(false)) => Some(0),
+            (hour, _) => hour,
+        };
+        let fields = [self.year, month, day, hour, self.min, self.sec];
+        let first = fields.iter().position(|f| f.is_some())?;
+        let now = TimeInfo::now(zone).civil();
+        let current = [now.year, now.month, now.day, now.hour, now.min, now.sec];
+        let minimum = [0, 1, 1, 0, 0, 0];
+        let mut values = [0; 6];
+        for i in 0..6 {
+            values[i] = match fields[i] {
+                Some(value) => value,
+                None if i < first => current[i],
+                None => minimum[i],
+            };
+        }
+        let [year, month, day, hour, min, sec] = values;
+        if !(1..=12).contains(&month)
+            || !(1..=31).contains(&day)
+            || !(0..=24).contains(&hour)
+            || !(0..=59).contains(&min)
+            || !(0..=60).contains(&sec)
+        {
+            return None;
+        }
+        let nsec = self.nsec.unwrap_or(0);
+        TimeInfo::from_civil((year, month, day), (hour, min, sec), nsec, zone)
+    }
+}
+
+/// Well-known zone abbreviations and their offsets in hours.
+const ZONE_ABBRS: [(&str, i64); 12] = [
+    ("utc", 0),
+    ("gmt", 0),
+    ("est", -5),
+    ("edt", -4),
+    ("cst", -6),
+    ("cdt", -5),
+    ("mst", -7),
+    ("mdt", -6),
+    ("pst", -8),
+    ("pdt", -7),
+    ("jst", 9),
+    ("cet", 1),
+];
+
+fn zone_from_name(name: &str) -> Option<Zone> {
+    let name = name.to_lowercase();
+    if name == "z" || name == "utc" {
+        return Some(Zone::Utc);
+    }
+    ZONE_ABBRS
+        .iter()
+        .find(|(abbr, _)| *abbr == name)
+        .map(|(_, hours)| Zone::Fixed(hours * 3600))
+}
+
+fn month_from_name(name: &str) -> Option<i64> {
+    let name = name.to_lowercase();
+    MONTH_NAMES
+        .iter()
+        .position(|month| {
+            let month = month.to_lowercase();
+            name == month || name == month[..3]
+        })
+        .map(|i| i as i64 + 1)
+}
+
+/// The nanoseconds of the digits of a fraction of a second.
+fn fraction_nsec(digits: &str) -> i64 {
+    let mut digits = digits.to_string();
+    digits.truncate(9);
+    let scale = 10i64.pow(9 - digits.len() as u32);
+    digits.parse::<i64>().unwrap_or(0) * scale
+}
+
+/// Read the date, the time and the zone from a string in a common format such as
+/// "2020-01-02 03:04:05 +09:00", "2020-01-02T03:04:05Z" or "Sat Aug 28 02:55:50 JST 1999".
+fn parse_str(s: &str) -> Option<Parsed> {
+    use regex::Regex;
+    // Each pattern is compiled once on the first use.
+    macro_rules! regex {
+        ($pattern:expr) => {{
+            static REGEX: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+            REGEX.get_or_init(|| Regex::new(&$pattern).unwrap())
+        }};
+    }
+    let mut parsed = Parsed::default();
+    let mut rest = s.to_string();
+    // Blank out the matched part so that the other patterns do not see it again.
+    let take = |rest: &mut String, re: &Regex| -> Option<Vec<Option<String>>> {
+        let (range, groups) = {
+            let caps = re.captures(rest)?;
+            let groups = (1..caps.len())
+                .map(|i| caps.get(i).map(|m| m.as_str().to_string()))
+                .collect();
+            (caps.get(0).unwrap().range(), groups)
+        };
+        rest.replace_range(range.clone(), &" ".repeat(range.len()));
+        Some(groups)
+    };
+    let num = |s: &Option<String>| s.as_ref().and_then(|s| s.parse::<i64>().ok());
+    let months = "jan|feb|mar|apr|may|jun|jul|aug|sep|oct|nov|dec";
+    let ymd = regex!(r"([-+]?\d{4,})[-/](\d{1,2})[-/](\d{1,2})");
+    let mdy = regex!(r"\b(\d{1,2})/(\d{1,2})/(\d{4})\b");
+    let name_day = regex!(format!(
+        r"(?i)\b({})[a-z]*\.?\s+(\d{{1,2}})(?:st|nd|rd|th)?\b,?(?:\s+(\d{{4}})\b)?",
+        months
+    ));
+    let day_name = regex!(format!(
+        r"(?i)\b(\d{{1,2}})(?:st|nd|rd|th)?\s*[-\s]({})[a-z]*\.?(?:[-\s]+(\d{{4}})\b)?",
+        months
+    ));
+    if let Some(g) = take(&mut rest, ymd) {
+        parsed.year = num(&g[0]);
+        parsed.month = num(&g[1]);
+        parsed.day = num(&g[2]);
+    } else if let Some(g) = take(&mut rest, mdy) {
+        parsed.month = num(&g[0]);
+        parsed.day = num(&g[1]);
+        parsed.year = num(&g[2]);
+    } else if let Some(g) = take(&mut rest, name_day) {
+        parsed.month = month_from_name(&g[0].clone().unwrap_or_default()[..3]);
+        parsed.day = num(&g[1]);
+        parsed.year = num(&g[2]);
+    } else if let Some(g) = take(&mut rest, day_name) {
+        parsed.day = num(&g[0]);
+        parsed.month = month_from_name(&g[1].clone().unwrap_or_default()[..3]);
+        parsed.year = num(&g[2]);
+    }
+    let time = regex!(r"(?i)(\d{1,2}):(\d{2})(?::(\d{2})(?:[.,](\d+))?)?(?:\s*([ap])\.?m\b\.?)?");
+    let hour = regex!(r"(?i)\b(\d{1,2})\s*([ap])\.?m\b\.?");
+    if let Some(g) = take(&mut rest, time) {
+        parsed.hour = num(&g[0]);
+        parsed.min = num(&g[1]);
+        parsed.sec = num(&g[2]);
+        parsed.nsec = g[3].as_ref().map(|frac| fraction_nsec(frac));
+        parsed.pm = g[4].as_ref().map(|ap| ap.to_lowercase() == "p");
+    } else if let Some(g) = take(&mut rest, hour) {
+        parsed.hour = num(&g[0]);
+        parsed.pm = g[1].as_ref().map(|ap| ap.to_lowercase() == "p");
+    }
+    let offset = regex!(r"(?:^|[^\w])([-+]\d{2}(?::?\d{2}(?::?\d{2})?)?)\b");
+    let abbr = regex!(r"\b(Z|[A-Za-z]{3})\b");
+    if let Some(g) = take(&mut rest, offset) {
+        parsed.zone = parse_zone(g[0].as_ref()?);
+    } else {
+        let mut start = 0;
+        while let Some(m) = abbr.find_at(&rest.clone(), start) {
+            if let Some(zone) = zone_from_name(m.as_str()) {
+                parsed.zone = Some(zone);
+                rest.replace_range(m.range(), &" ".repeat(m.range().len()));
+                break;
+            }
+            start = m.end();
+        }
+    }
+    if parsed.year.is_none() {
+        let year = regex!(r"\b(\d{4})\b");
+        if let Some(g) = take(&mut rest, year) {
+            parsed.year = num(&g[0]);
+        }
+    }
+    Some(parsed)
+}
+
+/// Read `s` by the directives of `format` as Time.strptime does.
+fn strptime_str(s: &str, format: &str) -> Option<Parsed> {
+    // Expand the combinations of directives first.
+    let mut format = format.to_string();
+    for (from, to) in [
+        ("%c", "%a %b %e %H:%M:%S %Y"),
+        ("%D", "%m/%d/%y"),
+        ("%x", "%m/%d/%y"),
+        ("%F", "%Y-%m-%d"),
+        ("%T", "%H:%M:%S"),
+        ("%X", "%H:%M:%S"),
+        ("%R", "%H:%M"),
+        ("%r", "%I:%M:%S %p"),
+        ("%v", "%e-%b-%Y"),
+        ("%+", "%a %b %e %H:%M:%S %Z %Y"),
+    ]
+    .iter()
+    {
+        format = format.replace(from, to);
+    }
+    let input: Vec<char> = s.chars().collect();
+    let fmt: Vec<char> = format.chars().collect();
+    let mut parsed = Parsed::default();
+    let mut century = None;
+    let (mut pos, mut i) = (0, 0);
+    let skip_space = |pos: &mut usize| {
+        while *pos < input.len() && input[*pos].is_whitespace() {
+            *pos += 1;
+        }
+    };
+    let read_num = |pos: &mut usize, max: usize, signed: bool| -> Option<i64> {
+        skip_space(pos);
+        let start = *pos;
+        if signed && matches!(input.get(*pos), Some('+') | Some('-')) {
+            *pos += 1;
+        }
+        let digits_start = *pos;
+        while *pos < input.len() && *pos - digits_start < max && input[*pos].is_ascii_digit() {
+            *pos += 1;
+        }
+        if *pos == digits_start {
+            return None;
+        }
+        input[start..*pos].iter().collect::<String>().parse().ok()
+    };
+    let read_word = |pos: &mut usize| -> String {
+        let start = *pos;
+        while *pos < input.len() && input[*pos].is_ascii_alphabetic() {
+            *pos += 1;
+        }
+        input[start..*pos].iter().collect()
+    };
+    while i < fmt.len() {
+        let c = fmt[i];
+        if c.is_whitespace() {
+            skip_space(&mut pos);
+            i += 1;
+            continue;
+        }
+        if c != '%' || i + 1 == fmt.len() {
+            if input.get(pos) != Some(&c) {
+                return None;
+            }
+            pos += 1;
+            i += 1;
+            continue;
+        }
+        i += 1;
+        while matches!(
+            fmt.get(i),
+            Some('-') | Some('_') | Some('0') | Some('^') | Some('#')
+        ) {
+            i += 1;
+        }
+        let conv = *fmt.get(i)?;
+        i += 1;
+        match conv {
+            'Y' => parsed.year = Some(read_num(&mut pos, 5, true)?),
+            'C' => century = Some(read_num(&mut pos, 2, true)?),
+            'y' => {
+                let y = read_num(&mut pos, 2, false)?;
+                parsed.year = Some(if y < 69 { y + 2000 } else { y + 1900 });
+            }
+            'm' => parsed.month = Some(read_num(&mut pos, 2, false)?),
+            'd' | 'e' => parsed.day = Some(read_num(&mut pos, 2, false)?),
+            'j' => parsed.yday = Some(read_num(&mut pos, 3, false)?),
+            'H' | 'k' | 'I' | 'l' => parsed.hour = Some(read_num(&mut pos, 2, false)?),
+            'M' => parsed.min = Some(read_num(&mut pos, 2, false)?),
+            'S' => parsed.sec = Some(read_num(&mut pos, 2, false)?),
+            'L' | 'N' => {
+                let start = pos;
+                while pos < input.len() && input[pos].is_ascii_digit() {
+                    pos += 1;
+                }
+                if pos == start {
+                    return None;
+                }
+                let digits: String = input[start..pos].iter().collect();
+                parsed.nsec = Some(fraction_nsec(&digits));
+            }
+            'p' | 'P' => {
+                let start = pos;
+                let word: String = input[pos..]
+                    .iter()
+                    .take(4)
+                    .filter(|c| **c != '.')
+                    .collect::<String>()
+                    .to_lowercase();
+                parsed.pm = if word.starts_with("pm") {
+                    Some(true)
+                } else if word.starts_with("am") {
+                    Some(false)
+                } else {
+                    return None;
+                };
+                pos = start
+                    + if input.get(start + 1) == Some(&'.') {
+                        4
+                    } else {
+                        2
+                    };
+            }
+            'b' | 'B' | 'h' => {
+                let word = read_word(&mut pos);
+                parsed.month = Some(month_from_name(&word)?);
+            }
+            'a' | 'A' => {
+                let word = read_word(&mut pos).to_lowercase();
+                DAY_NAMES.iter().find(|day| {
+                    let day = day.to_lowercase();
+                    word == day || word == day[..3]
+                })?;
+            }
+            'z' | 'Z' => {
+                skip_space(&mut pos);
+                let start = pos;
+                while pos < input.len()
+                    && (input[pos].is_ascii_alphanumeric() || "+-:".contains(input[pos]))
+                {
+                    pos += 1;
+                }
+                let zone: String = input[start..pos].iter().collect();
+                parsed.zone = Some(parse_zone(&zone).or_else(|| zone_from_name(&zone))?);
+            }
+            's' => parsed.epoch = Some((read_num(&mut pos, 20, true)?, 0)),
+            'n' | 't' => skip_space(&mut pos),
+            '%' => {
+                if input.get(pos) != Some(&'%') {
+                    return None;
+                }
+                pos += 1;
+            }
+            _ => return None,
+        }
+    }
+    if pos != input.len() {
+        return None;
+    }
+    if let Some(century) = century {
+        parsed.year = Some(century * 100 + parsed.year.map_or(0, |y| y % 100));
+    }
+    Some(parsed)
+}
+
+/// Time.parse(string)
+fn parse(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 1)?;
+    let s = vm.expect_string(&args[0], "1st arg")?.to_string();
+    match parse_str(&s) {
+        Some(parsed)
+            if parsed.year.is_some() || parsed.month.is_some() || parsed.hour.is_some() =>
+        {
+            match parsed.to_time() {
+                Some(time) => Ok(Value::time(&vm.globals, TimeRef::new(time))),
+                None => Err(vm.error_argument("argument out of range")),
+            }
+        }
+        _ => Err(vm.error_argument(format!("no time information in {:?}", s))),
+    }
+}
+
+/// Time.strptime(string, format)
+fn strptime(vm: &mut VM, _: Value, args: &Args) -> VMResult {
+    vm.check_args_num(args.len(), 2)?;
+    let s = vm.expect_string(&args[0], "1st arg")?.to_string();
+    let format = vm.expect_string(&args[1], "2nd arg")?.to_string();
+    match strptime_str(&s, &format).and_then(|parsed| parsed.to_time()) {
+        Some(time) => Ok(Value::time(&vm.globals, TimeRef::new(time))),
+        None => Err(vm.error_argument(format!(
+            "invalid date or strptime format - `{}' `{}'",
+            s, format
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn time() {
+        let program = r#"
+        t = Time.utc(2000, 1, 2, 3, 4, 5.5)
+        assert [2000, 1, 2, 3, 4, 5], [t.year, t.month, t.day, t.hour, t.min, t.sec]
+        assert [500000, 500000000, 0, 2], [t.usec, t.nsec, t.wday, t.yday]
+        assert ["UTC", 0, true], [t.zone, t.utc_offset, t.utc?]
+        assert 946782245, t.to_i
+        assert 946782245.5, t.to_f
+        assert "2000-01-02 03:04:05 UTC", t.to_s
+        assert "2000-01-02 03:04:05.5 UTC", t.inspect
+        assert "2000-01-02T03:04:05Z", t.iso8601
+        assert "2000-01-02T03:04:05.500Z", t.xmlschema(3)
+        t = Time.new(2020, 2, 29, 23, 59, 59, "+09:00")
+        assert [2020, 2, 29, 23, 32400, nil], [t.year, t.month, t.day, t.hour, t.utc_offset, t.zone]
+        assert "2020-02-29 23:59:59 +0900", t.to_s
+        assert "2020-02-29T23:59:59+09:00", t.iso8601
+        u = t + 1
+        assert "2020-03-01 00:00:00 +0900", u.to_s
+        assert 1.0, u - t
+        assert "2020-02-29 23:59:58 +0900", (t - 1).to_s
+        assert "2020-02-29 14:59:59 UTC", t.getutc.to_s
+        assert "2020-02-29 09:59:59 -0500", t.getlocal("-05:00").to_s
+        assert(-1, t <=> u)
+        assert 1, u <=> t
+        assert nil, t <=> 1
+        assert true, t < u
+        assert false, t >= u
+        assert true, t == Time.utc(2020, 2, 29, 14, 59, 59)
+        assert 1, { Time.utc(2020, 2, 29, 14, 59, 59) => 1 }[t]
+        assert Time.at(0).to_i, 0
+        assert "1970-01-01 00:00:00 UTC", Time.at(0).utc.to_s
+        assert [1, 500000], [Time.at(1, 500, :millisecond).to_i, Time.at(1, 500, :millisecond).usec]
+        assert "1970-01-01 09:00:01 +0900", Time.at(1, in: "+09:00").to_s
+        assert [1, 2, 3, 5, 4, 2000, 3, 96, false, "UTC"], Time.gm(2000, "apr", 5, 3, 2, 1).to_a
+        assert "2000-01-01 00:00:00 UTC", Time.utc(1999, 12, 31, 24).to_s
+        assert "2021-03-02 00:00:00 UTC", Time.utc(2021, 2, 30).to_s
+        assert true, Time.now.year >= 2020
+        assert Time, Time.now.class
+        a = Time.local(2000, 1, 1)
+        assert 2000, a.year
+        assert a.to_i, Time.new(2000).to_i
+        assert_error { Time.utc(2000, 13) }
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn time_strftime() {
+        let program = r#"
+        t = Time.utc(2007, 11, 19, 8, 37, 48.123456789)
+        assert "Monday Mon November Nov Nov", t.strftime("%A %a %B %b %h")
+        assert "2007 20 07 11 19 19 323", t.strftime("%Y %C %y %m %d %e %j")
+        assert "08  8 08  8 AM am", t.strftime("%H %k %I %l %p %P")
+        assert "37 48 123 123456789 123456", t.strftime("%M %S %L %N %6N")
+        assert "+0000 +00:00 +00:00:00 UTC", t.strftime("%z %:z %::z %Z")
+        assert "1 1 46 47 2007 07 47", t.strftime("%u %w %U %W %G %g %V")
+        assert "1195461468 % \t \n", t.strftime("%s %% %t %n")
+        assert "Mon Nov 19 08:37:48 2007", t.strftime("%c")
+        assert "11/19/07 2007-11-19 08:37:48 08:37 08:37:48 AM", t.strftime("%D %F %T %R %r")
+        assert "19-NOV-2007", t.strftime("%v")
+        assert "8 MONDAY MON NOV 8 008   8 utc", t.strftime("%-H %^A %#a %^b %-I %03H %3k %#Z")
+        assert "%Q %", t.strftime("%Q %")
+        t = Time.new(2005, 1, 2, 15, 0, 0, "-03:30")
+        assert "-0330 -03:30 -03:30 03 PM", t.strftime("%z %:z %:::z %I %p")
+        assert "2004 53 0 01 00", t.strftime("%G %V %w %U %W")
+        assert "", t.strftime("%Z")
+        assert "01 2005 004", Time.utc(2005, 1, 4).strftime("%V %G %j")
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn time_out_of_range() {
+        let program = r#"
+        t = Time.at(0)
+        assert_error { t + 1.0 / 0 }
+        assert_error { t - 0.0 / 0.0 }
+        assert_error { t + 1e300 }
+        assert_error { t + 9223372036854775807 }
+        assert_error { Time.at(9223372036854775807, 999999999, :nsec) }
+        assert_error { Time.utc(9223372036854775807) }
+        assert_error { t.strftime("%99999999999999999999999d") }
+        assert "00000000000000000001", Time.at(1).strftime("%020s")
+        "#;
+        assert_script(program);
+    }
+
+    #[test]
+    fn time_parse() {
+        let program = r#"
+        assert_error { Time.parse("2000-01-01") }
+        "#;
+        assert_script(program);
+        let program = r#"
+        require "time"
+        assert "2020-01-02 03:04:05 UTC", Time.parse("2020-01-02T03:04:05Z").to_s
+        assert "2020-01-02 03:04:05 +0900", Time.parse("2020-01-02 03:04:05 +09:00").to_s
+        t = Time.parse("2020/01/02 03:04:05.25 -0500")
+        assert ["2020-01-02 03:04:05 -0500", 250000], [t.to_s, t.usec]
+        assert "1999-08-28 02:55:50 +0900", Time.parse("Sat Aug 28 02:55:50 JST 1999").to_s
+        assert "2001-02-03 16:00:00 UTC", Time.parse("3rd Feb 2001 4pm UTC").to_s
+        assert "2001-02-03 00:00:00 UTC", Time.parse("February 3, 2001 UTC").to_s
+        t = Time.parse("2010-10-31")
+        assert [2010, 10, 31, 0], [t.year, t.month, t.day, t.hour]
+        assert Time.now.year, Time.parse("12:30").year
+        assert 12, Time.parse("12:30 pm").hour
+        assert 0, Time.parse("12:30 am").hour
+        t = Time.strptime("2001-02-03T04:05:06+07:00", "%Y-%m-%dT%H:%M:%S%z")
+        assert "2001-02-03 04:05:06 +0700", t.to_s
+        t = Time.strptime("03/Feb/2001:04:05:06.5 PM UTC", "%d/%b/%Y:%I:%M:%S.%N %p %Z")
+        assert ["2001-02-03 16:05:06 UTC", 500000000], [t.to_s, t.nsec]
+        assert "2001-02-03 00:00:00 UTC", Time.strptime("2001 034 Z", "%Y %j %z").to_s
+        assert 1000000000, Time.strptime("1000000000", "%s").to_i
+        assert "1999-01-01 00:00:00 UTC", Time.strptime("99 +00:00", "%y %z").utc.to_s
+        assert_error { Time.strptime("2001-02-03", "%Y/%m/%d") }
+        "#;
+        assert_script(program);
+    }
+}
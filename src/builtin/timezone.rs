@@ -0,0 +1,475 @@
+//! Offsets of the local time zone, read from the `TZ` environment variable or the
+//! TZif file of the system (/etc/localtime) without an external library.
+
+/// The local time type at a moment: the offset from UTC in seconds, whether daylight
+/// saving time is in effect, and the abbreviation of the zone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalType {
+    pub offset: i64,
+    pub is_dst: bool,
+    pub abbr: String,
+}
+
+impl LocalType {
+    fn utc() -> Self {
+        LocalType {
+            offset: 0,
+            is_dst: false,
+            abbr: "UTC".to_string(),
+        }
+    }
+}
+
+/// A day of the year in a POSIX TZ rule.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RuleDay {
+    /// Jn: the Julian day n (1 <= n <= 365), which never counts February 29.
+    Julian1(i64),
+    /// n: the zero-based day of the year (0 <= n <= 365).
+    Julian0(i64),
+    /// Mm.w.d: the day d (0 is Sunday) of the week w (5 is the last one) of the month m.
+    MonthWeekDay(i64, i64, i64),
+}
+
+/// The start or the end of daylight saving time, with the local time of the change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RuleDate {
+    day: RuleDay,
+    time: i64,
+}
+
+/// A POSIX TZ rule such as "EST5EDT,M3.2.0,M11.1.0".
+#[derive(Debug, Clone, PartialEq)]
+struct PosixRule {
+    std: LocalType,
+    dst: Option<(LocalType, RuleDate, RuleDate)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeZone {
+    /// UTC times of transitions and the index of the local time type after each of them.
+    transitions: Vec<(i64, usize)>,
+    types: Vec<LocalType>,
+    /// The rule for times after the last transition.
+    rule: Option<PosixRule>,
+}
+
+impl TimeZone {
+    pub fn utc() -> Self {
+        TimeZone {
+            transitions: vec![],
+            types: vec![LocalType::utc()],
+            rule: None,
+        }
+    }
+
+    /// The local time zone given by `TZ`, or by /etc/localtime if `TZ` is not set.
+    pub fn local() -> Self {
+        match std::env::var("TZ") {
+            Ok(tz) => TimeZone::from_tz(&tz).unwrap_or_else(TimeZone::utc),
+            Err(_) => std::fs::read("/etc/localtime")
+                .ok()
+                .and_then(|data| TimeZone::from_tzif(&data))
+                .unwrap_or_else(TimeZone::utc),
+        }
+    }
+
+    /// A time zone for the value of `TZ`, which is a POSIX TZ rule or the name of a TZif file.
+    fn from_tz(tz: &str) -> Option<Self> {
+        let tz = tz.trim_start_matches(':');
+        if tz.is_empty() {
+            return Some(TimeZone::utc());
+        }
+        let path = if tz.starts_with('/') {
+            std::path::PathBuf::from(tz)
+        } else {
+            std::path::Path::new("/usr/share/zoneinfo").join(tz)
+        };
+        if !tz.contains("..") {
+            if let Some(zone) = std::fs::read(path)
+                .ok()
+                .and_then(|data| TimeZone::from_tzif(&data))
+            {
+                return Some(zone);
+            }
+        }
+        let rule = parse_posix(tz)?;
+        Some(TimeZone {
+            transitions: vec![],
+            types: vec![rule.std.clone()],
+            rule: Some(rule),
+        })
+    }
+
+    /// Read a TZif file described in RFC 8536.
+    fn from_tzif(data: &[u8]) -> Option<Self> {
+        if data.get(0..4)? != b"TZif" {
+            return None;
+        }
+        let version = *data.get(4)?;
+        let (header, v1_len) = read_header(data, 4)?;
+        if version == 0 {
+            return read_block(data, 44, &header, 4).map(|(zone, _)| zone);
+        }
+        // Skip the block of 32-bit times and read the one of 64-bit times and the footer.
+        let start = 44 + v1_len;
+        let (header, _) = read_header(&data[start..], 8)?;
+        let (mut zone, end) = read_block(data, start + 44, &header, 8)?;
+        let footer = data.get(end..)?;
+        if footer.first() == Some(&b'\n') {
+            if let Some(len) = footer[1..].iter().position(|b| *b == b'\n') {
+                let tz = std::str::from_utf8(&footer[1..len + 1]).ok()?;
+                zone.rule = parse_posix(tz);
+            }
+        }
+        Some(zone)
+    }
+
+    /// The local time type at the UTC time `time` in seconds since the Unix epoch.
+    pub fn lookup(&self, time: i64) -> LocalType {
+        // The footer rule covers the times after the last transition.
+        if let Some(rule) = &self.rule {
+            if self
+                .transitions
+                .last()
+                .is_none_or(|(last, _)| time >= *last)
+            {
+                return rule.lookup(time);
+            }
+        }
+        let i = self.transitions.partition_point(|(at, _)| *at <= time);
+        let index = if i == 0 { 0 } else { self.transitions[i - 1].1 };
+        self.types
+            .get(index)
+            .cloned()
+            .unwrap_or_else(LocalType::utc)
+    }
+
+    /// The UTC time of the local time `local`, which counts seconds as if the zone were UTC.
+    /// A time skipped by a transition is moved forward by the gap.
+    pub fn to_utc(&self, local: i64) -> i64 {
+        let offset = self.lookup(local).offset;
+        let time = local - offset;
+        let offset2 = self.lookup(time).offset;
+        if offset2 == offset {
+            return time;
+        }
+        if self.lookup(local - offset2).offset == offset2 {
+            local - offset2
+        } else {
+            time
+        }
+    }
+}
+
+struct TzifHeader {
+    isutcnt: usize,
+    isstdcnt: usize,
+    leapcnt: usize,
+    timecnt: usize,
+    typecnt: usize,
+    charcnt: usize,
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    let bytes = data.get(pos..pos + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_i64(data: &[u8], pos: usize) -> Option<i64> {
+    let bytes = data.get(pos..pos + 8)?;
+    let mut buf = [0; 8];
+    buf.copy_from_slice(bytes);
+    Some(i64::from_be_bytes(buf))
+}
+
+/// Read the counts of a TZif header, and the length of the data block for `time_size`.
+fn read_header(data: &[u8], time_size: usize) -> Option<(TzifHeader, usize)> {
+    let count = |i: usize| read_u32(data, 20 + i * 4).map(|n| n as usize);
+    let header = TzifHeader {
+        isutcnt: count(0)?,
+        isstdcnt: count(1)?,
+        leapcnt: count(2)?,
+        timecnt: count(3)?,
+        typecnt: count(4)?,
+        charcnt: count(5)?,
+    };
+    let len = header.timecnt * (time_size + 1)
+        + header.typecnt * 6
+        + header.charcnt
+        + header.leapcnt * (time_size + 4)
+        + header.isstdcnt
+        + header.isutcnt;
+    Some((header, len))
+}
+
+/// Read the data block at `pos`. Returns the time zone and the end of the block.
+fn read_block(
+    data: &[u8],
+    pos: usize,
+    header: &TzifHeader,
+    time_size: usize,
+) -> Option<(TimeZone, usize)> {
+    let times = pos;
+    let indices = times + header.timecnt * time_size;
+    let types = indices + header.timecnt;
+    let chars = types + header.typecnt * 6;
+    let end = chars
+        + header.charcnt
+        + header.leapcnt * (time_size + 4)
+        + header.isstdcnt
+        + header.isutcnt;
+    if data.len() < end || header.typecnt == 0 {
+        return None;
+    }
+    let mut transitions = vec![];
+    for i in 0..header.timecnt {
+        let time = if time_size == 4 {
+            read_u32(data, times + i * 4)? as i32 as i64
+        } else {
+            read_i64(data, times + i * 8)?
+        };
+        let index = data[indices + i] as usize;
+        if index >= header.typecnt {
+            return None;
+        }
+        transitions.push((time, index));
+    }
+    let abbrs = &data[chars..chars + header.charcnt];
+    let mut local_types = vec![];
+    for i in 0..header.typecnt {
+        let pos = types + i * 6;
+        let offset = read_u32(data, pos)? as i32 as i64;
+        let abbr_start = data[pos + 5] as usize;
+        let abbr = abbrs.get(abbr_start..)?;
+        let abbr_len = abbr.iter().position(|b| *b == 0).unwrap_or(abbr.len());
+        local_types.push(LocalType {
+            offset,
+            is_dst: data[pos + 4] != 0,
+            abbr: String::from_utf8_lossy(&abbr[..abbr_len]).to_string(),
+        });
+    }
+    let zone = TimeZone {
+        transitions,
+        types: local_types,
+        rule: None,
+    };
+    Some((zone, end))
+}
+
+// POSIX TZ rules
+
+/// Parse a POSIX TZ rule such as "JST-9" or "CET-1CEST,M3.5.0,M10.5.0/3".
+fn parse_posix(tz: &str) -> Option<PosixRule> {
+    let mut rest = tz;
+    let std_abbr = parse_abbr(&mut rest)?;
+    // The offset in a TZ rule is the one to add to the local time to get UTC.
+    let std_offset = -parse_hms(&mut rest)?;
+    let std = LocalType {
+        offset: std_offset,
+        is_dst: false,
+        abbr: std_abbr,
+    };
+    if rest.is_empty() {
+        return Some(PosixRule { std, dst: None });
+    }
+    let dst_abbr = parse_abbr(&mut rest)?;
+    let dst_offset = if rest.is_empty() || rest.starts_with(',') {
+        std_offset + 3600
+    } else {
+        -parse_hms(&mut rest)?
+    };
+    let dst = LocalType {
+        offset: dst_offset,
+        is_dst: true,
+        abbr: dst_abbr,
+    };
+    // The rules of the United States are the default.
+    let (start, end) = if rest.is_empty() {
+        (
+            RuleDate {
+                day: RuleDay::MonthWeekDay(3, 2, 0),
+                time: 7200,
+            },
+            RuleDate {
+                day: RuleDay::MonthWeekDay(11, 1, 0),
+                time: 7200,
+            },
+        )
+    } else {
+        rest = rest.strip_prefix(',')?;
+        let start = parse_rule_date(&mut rest)?;
+        rest = rest.strip_prefix(',')?;
+        let end = parse_rule_date(&mut rest)?;
+        (start, end)
+    };
+    if !rest.is_empty() {
+        return None;
+    }
+    Some(PosixRule {
+        std,
+        dst: Some((dst, start, end)),
+    })
+}
+
+/// An abbreviation of three or more letters, or any characters quoted in "<>".
+fn parse_abbr(rest: &mut &str) -> Option<String> {
+    let (abbr, remain) = if let Some(quoted) = rest.strip_prefix('<') {
+        let end = quoted.find('>')?;
+        (&quoted[..end], &quoted[end + 1..])
+    } else {
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        (&rest[..end], &rest[end..])
+    };
+    if abbr.len() < 3 {
+        return None;
+    }
+    *rest = remain;
+    Some(abbr.to_string())
+}
+
+/// `[+-]hh[:mm[:ss]]` in seconds.
+fn parse_hms(rest: &mut &str) -> Option<i64> {
+    let sign = match rest.chars().next() {
+        Some('-') => {
+            *rest = &rest[1..];
+            -1
+        }
+        Some('+') => {
+            *rest = &rest[1..];
+            1
+        }
+        _ => 1,
+    };
+    let mut secs = 0;
+    for (i, unit) in [3600, 60, 1].iter().enumerate() {
+        if i > 0 {
+            match rest.strip_prefix(':') {
+                Some(remain) => *rest = remain,
+                None => break,
+            }
+        }
+        secs += parse_num(rest)? * unit;
+    }
+    Some(sign * secs)
+}
+
+fn parse_num(rest: &mut &str) -> Option<i64> {
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let num = rest[..end].parse().ok()?;
+    *rest = &rest[end..];
+    Some(num)
+}
+
+fn parse_rule_date(rest: &mut &str) -> Option<RuleDate> {
+    let day = if let Some(remain) = rest.strip_prefix('M') {
+        *rest = remain;
+        let month = parse_num(rest)?;
+        *rest = rest.strip_prefix('.')?;
+        let week = parse_num(rest)?;
+        *rest = rest.strip_prefix('.')?;
+        let wday = parse_num(rest)?;
+        if !(1..=12).contains(&month) || !(1..=5).contains(&week) || wday > 6 {
+            return None;
+        }
+        RuleDay::MonthWeekDay(month, week, wday)
+    } else if let Some(remain) = rest.strip_prefix('J') {
+        *rest = remain;
+        RuleDay::Julian1(parse_num(rest)?)
+    } else {
+        RuleDay::Julian0(parse_num(rest)?)
+    };
+    let time = match rest.strip_prefix('/') {
+        Some(remain) => {
+            *rest = remain;
+            parse_hms(rest)?
+        }
+        None => 7200,
+    };
+    Some(RuleDate { day, time })
+}
+
+impl PosixRule {
+    fn lookup(&self, time: i64) -> LocalType {
+        let (dst, start, end) = match &self.dst {
+            Some(dst) => dst,
+            None => return self.std.clone(),
+        };
+        let (year, _, _) = super::time::civil_from_days((time + self.std.offset).div_euclid(86400));
+        // The changes are given in the local time before each of them.
+        let start = start.local_time(year) - self.std.offset;
+        let end = end.local_time(year) - dst.offset;
+        let in_dst = if start < end {
+            start <= time && time < end
+        } else {
+            !(end <= time && time < start)
+        };
+        if in_dst {
+            dst.clone()
+        } else {
+            self.std.clone()
+        }
+    }
+}
+
+impl RuleDate {
+    /// The local time of the change in `year`, which counts seconds as if the zone were UTC.
+    fn local_time(&self, year: i64) -> i64 {
+        use super::time::{days_from_civil, is_leap_year};
+        let jan1 = days_from_civil(year, 1, 1);
+        let day = match self.day {
+            RuleDay::Julian1(n) => {
+                let leap_day = if is_leap_year(year) && n >= 60 { 1 } else { 0 };
+                jan1 + n - 1 + leap_day
+            }
+            RuleDay::Julian0(n) => jan1 + n,
+            RuleDay::MonthWeekDay(month, week, wday) => {
+                let first = days_from_civil(year, month, 1);
+                let first_wday = (first + 4).rem_euclid(7);
+                let mut day = first + (wday - first_wday).rem_euclid(7) + (week - 1) * 7;
+                let next_month = if month == 12 {
+                    days_from_civil(year + 1, 1, 1)
+                } else {
+                    days_from_civil(year, month + 1, 1)
+                };
+                while day >= next_month {
+                    day -= 7;
+                }
+                day
+            }
+        };
+        day * 86400 + self.time
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn posix_rule() {
+        let zone = TimeZone::from_tz("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        // 2021-01-01 00:00:00 UTC
+        assert_eq!(-18000, zone.lookup(1609459200).offset);
+        assert_eq!("EST", zone.lookup(1609459200).abbr);
+        // 2021-07-01 00:00:00 UTC
+        assert_eq!(-14400, zone.lookup(1625097600).offset);
+        assert!(zone.lookup(1625097600).is_dst);
+        // 2021-03-14 06:59:59 UTC and 07:00:00 UTC
+        assert_eq!(-18000, zone.lookup(1615705199).offset);
+        assert_eq!(-14400, zone.lookup(1615705200).offset);
+        // 2021-03-14 02:30:00 does not exist in the local time.
+        assert_eq!(1615707000, zone.to_utc(1615689000));
+        let zone = TimeZone::from_tz("<+0330>-3:30").unwrap();
+        assert_eq!(12600, zone.lookup(0).offset);
+        assert_eq!("+0330", zone.lookup(0).abbr);
+        let zone = TimeZone::from_tz("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(39600, zone.lookup(1609459200).offset);
+        assert_eq!(36000, zone.lookup(1625097600).offset);
+        assert_eq!(None, TimeZone::from_tz("X1"));
+    }
+}
@@ -31,9 +31,24 @@ pub enum RuntimeErrKind {
     NoMethod(String),
     Argument(String),
     Index(String),
+    Key(String),
     Type(String),
+    Range(String),
+    FloatDomain(String),
     Regexp(String),
     Fiber(String),
+    IO(String),
+    EOF(String),
+    Errno(String, String),
+    SystemStack(String),
+    Encoding(EncodingErrKind, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncodingErrKind {
+    Compatibility,
+    UndefinedConversion,
+    InvalidByteSequence,
 }
 
 impl RubyError {
@@ -77,12 +92,30 @@ impl RubyError {
                 RuntimeErrKind::Name(n) => eprintln!("NoNameError ({})", n),
                 RuntimeErrKind::NoMethod(n) => eprintln!("NoMethodError ({})", n),
                 RuntimeErrKind::Type(n) => eprintln!("TypeError ({})", n),
+                RuntimeErrKind::Range(n) => eprintln!("RangeError ({})", n),
+                RuntimeErrKind::FloatDomain(n) => eprintln!("FloatDomainError ({})", n),
                 RuntimeErrKind::Unimplemented(n) => eprintln!("UnimplementedError ({})", n),
                 RuntimeErrKind::Internal(n) => eprintln!("InternalError ({})", n),
                 RuntimeErrKind::Argument(n) => eprintln!("ArgumentError ({})", n),
                 RuntimeErrKind::Index(n) => eprintln!("IndexError ({})", n),
+                RuntimeErrKind::Key(n) => eprintln!("KeyError ({})", n),
                 RuntimeErrKind::Regexp(n) => eprintln!("RegexpError ({})", n),
                 RuntimeErrKind::Fiber(n) => eprintln!("FiberError ({})", n),
+                RuntimeErrKind::IO(n) => eprintln!("IOError ({})", n),
+                RuntimeErrKind::EOF(n) => eprintln!("EOFError ({})", n),
+                RuntimeErrKind::Errno(name, n) => eprintln!("Errno::{} ({})", name, n),
+                RuntimeErrKind::SystemStack(n) => eprintln!("SystemStackError ({})", n),
+                RuntimeErrKind::Encoding(kind, n) => match kind {
+                    EncodingErrKind::Compatibility => {
+                        eprintln!("Encoding::CompatibilityError ({})", n)
+                    }
+                    EncodingErrKind::UndefinedConversion => {
+                        eprintln!("Encoding::UndefinedConversionError ({})", n)
+                    }
+                    EncodingErrKind::InvalidByteSequence => {
+                        eprintln!("Encoding::InvalidByteSequenceError ({})", n)
+                    }
+                },
             },
             RubyErrorKind::MethodReturn(_) => {
                 eprintln!("LocalJumpError");
@@ -1,6 +1,9 @@
 use crate::*;
 use std::collections::HashMap;
 
+/// Default value of `Globals::max_stack_depth`.
+pub const DEFAULT_MAX_STACK_DEPTH: usize = 10000;
+
 #[derive(Debug, Clone)]
 pub struct Globals {
     // Global info
@@ -9,14 +12,35 @@ pub struct Globals {
     method_table: GlobalMethodTable,
     inline_cache: InlineCache,
     method_cache: MethodCache,
+    const_cache: Vec<Option<ConstCacheEntry>>,
+    ivar_cache: Vec<Option<IvarCacheEntry>>,
     pub instant: std::time::Instant,
-    /// version counter: increment when new instance / class methods are defined.
+    /// version counter: increment when new instance / class methods are defined,
+    /// or method tables and ancestors of classes are changed.
+    /// Inline caches and the global method cache are invalidated by this counter.
     pub class_version: usize,
+    /// version counter: increment when constants are defined or removed, or ancestors
+    /// of classes are changed. Constant caches are invalidated by this counter.
+    pub const_version: usize,
+    /// configuration of the JIT compiler.
+    pub jit: JitConfig,
+    /// line, branch and method coverage for the Coverage module.
+    pub coverage: Coverage,
+    /// options of the compiler for `RubyVM::InstructionSequence.compile_option`.
+    pub compile_option: CompileOption,
+    /// maximum depth of method and block calls. SystemStackError is raised beyond this depth.
+    pub max_stack_depth: usize,
     pub main_object: Value,
     pub builtins: BuiltinClass,
     pub class_class: ClassRef,
     pub module_class: ClassRef,
     pub object_class: ClassRef,
+    /// The shape of objects without variables, where all shape transitions start.
+    pub root_shape: ShapeRef,
+    /// Procs created by Symbol#to_proc.
+    pub symbol_proc: HashMap<IdentId, Value>,
+    /// Names of the built-in libraries loaded by `require`.
+    pub loaded_features: Vec<&'static str>,
 
     case_dispatch: CaseDispatchMap,
 }
@@ -31,14 +55,24 @@ pub struct BuiltinClass {
     pub class: Value,
     pub module: Value,
     pub procobj: Value,
+    pub binding: Value,
     pub method: Value,
+    pub unbound_method: Value,
     pub range: Value,
     pub hash: Value,
     pub regexp: Value,
     pub string: Value,
+    pub symbol: Value,
     pub fiber: Value,
     pub object: Value,
     pub enumerator: Value,
+    pub encoding: Value,
+    pub matchdata: Value,
+    pub random: Value,
+    pub io: Value,
+    pub file: Value,
+    pub file_stat: Value,
+    pub time: Value,
 }
 
 impl BuiltinClass {
@@ -51,13 +85,23 @@ impl BuiltinClass {
             class,
             module,
             procobj: nil,
+            binding: nil,
             method: nil,
+            unbound_method: nil,
             range: nil,
             hash: nil,
             regexp: nil,
             string: nil,
+            symbol: nil,
             fiber: nil,
             enumerator: nil,
+            encoding: nil,
+            matchdata: nil,
+            random: nil,
+            io: nil,
+            file: nil,
+            file_stat: nil,
+            time: nil,
             object,
         }
     }
@@ -89,13 +133,23 @@ impl Globals {
             method_table: GlobalMethodTable::new(),
             inline_cache: InlineCache::new(),
             method_cache: MethodCache::new(),
+            const_cache: vec![],
+            ivar_cache: vec![],
             instant: std::time::Instant::now(),
             class_version: 0,
+            const_version: 0,
+            jit: JitConfig::default(),
+            coverage: Coverage::default(),
+            compile_option: CompileOption::default(),
+            max_stack_depth: DEFAULT_MAX_STACK_DEPTH,
             main_object,
             object_class,
             module_class,
             class_class,
+            root_shape: ShapeRef::new_root(),
             builtins,
+            symbol_proc: HashMap::new(),
+            loaded_features: vec![],
             case_dispatch: CaseDispatchMap::new(),
         };
         // Generate singleton class for Object
@@ -110,11 +164,18 @@ impl Globals {
         globals.builtins.float = float::init(&mut globals);
         globals.builtins.array = array::init_array(&mut globals);
         globals.builtins.procobj = procobj::init_proc(&mut globals);
+        globals.builtins.binding = binding::init_binding(&mut globals);
         globals.builtins.method = method::init_method(&mut globals);
+        globals.builtins.unbound_method = method::init_unbound_method(&mut globals);
         globals.builtins.range = range::init_range(&mut globals);
         globals.builtins.string = string::init_string(&mut globals);
+        globals.builtins.encoding = encoding::init_encoding(&mut globals);
+        globals.builtins.symbol = symbol::init_symbol(&mut globals);
         globals.builtins.hash = hash::init_hash(&mut globals);
         globals.builtins.regexp = regexp::init_regexp(&mut globals);
+        globals.builtins.matchdata = matchdata::init_matchdata(&mut globals);
+        globals.builtins.random = random::init_random(&mut globals);
+        globals.builtins.io = io::init_io(&mut globals);
         globals.builtins.fiber = fiber::init_fiber(&mut globals);
         globals.builtins.enumerator = enumerator::init_enumerator(&mut globals);
         object::init(&mut globals);
@@ -148,6 +209,9 @@ impl Globals {
     }
 
     pub fn set_method(&mut self, method: MethodRef, info: MethodInfo) {
+        if let MethodInfo::RubyFunc { iseq } = &info {
+            self.coverage.register(*iseq);
+        }
         self.method_table.set_method(method, info);
     }
 
@@ -233,48 +297,114 @@ impl Globals {
                 ObjKind::Class(_) => "Class".to_string(),
                 ObjKind::Module(_) => "Module".to_string(),
                 ObjKind::Proc(_) => "Proc".to_string(),
-                ObjKind::Method(_) => "Method".to_string(),
+                ObjKind::Method(_) => oref.class_name(self).to_string(),
                 ObjKind::Ordinary => oref.class_name(self).to_string(),
                 ObjKind::Integer(_) => "Integer".to_string(),
                 ObjKind::Float(_) => "Float".to_string(),
                 ObjKind::Fiber(_) => "Fiber".to_string(),
                 ObjKind::Enumerator(_) => "Enumerator".to_string(),
+                ObjKind::Binding(_) => "Binding".to_string(),
+                ObjKind::Encoding(_) => "Encoding".to_string(),
+                ObjKind::MatchData(_) => "MatchData".to_string(),
+                ObjKind::Random(_) => "Random".to_string(),
+                ObjKind::IO(_) => oref.class_name(self).to_string(),
+                ObjKind::FileStat(_) => "File::Stat".to_string(),
+                ObjKind::Time(_) => "Time".to_string(),
             },
         }
     }
 }
 
 impl Globals {
-    pub fn set_inline_cache_entry(&mut self, id: u32, class: Value, method: MethodRef) {
-        self.inline_cache.table[id as usize] = Some(InlineCacheEntry {
+    pub fn set_inline_cache_entry(
+        &mut self,
+        id: u32,
+        class: Value,
+        method: MethodRef,
+        visibility: Visibility,
+    ) {
+        self.inline_cache.get_mut_entry(id).add_class(
+            self.class_version,
             class,
-            version: self.class_version,
             method,
-        });
+            visibility,
+        );
     }
 
     pub fn add_inline_cache_entry(&mut self) -> u32 {
         self.inline_cache.add_entry()
     }
 
-    fn get_inline_cache_entry(&self, id: u32) -> &Option<InlineCacheEntry> {
-        self.inline_cache.get_entry(id)
-    }
-
     pub fn get_method_from_inline_cache(
         &mut self,
         cache_slot: u32,
         rec_class: Value,
-    ) -> Option<MethodRef> {
-        match self.get_inline_cache_entry(cache_slot) {
-            Some(InlineCacheEntry {
-                class,
-                version,
-                method,
-            }) if class.id() == rec_class.id() && *version == self.class_version => Some(*method),
+    ) -> Option<(MethodRef, Visibility)> {
+        let entry = self.inline_cache.get_entry(cache_slot);
+        if entry.version != self.class_version {
+            return None;
+        }
+        entry
+            .classes
+            .iter()
+            .find(|(class, _, _)| class.id() == rec_class.id())
+            .map(|(_, method, visibility)| (*method, *visibility))
+    }
+}
+
+impl Globals {
+    pub fn add_const_cache_entry(&mut self) -> u32 {
+        self.const_cache.push(None);
+        self.const_cache.len() as u32 - 1
+    }
+
+    /// The cached value of the constant at `slot`, if it was looked up in the same
+    /// lexical scope and class and no constant has been changed since.
+    pub fn get_const_from_cache(
+        &self,
+        slot: u32,
+        class_list: Option<ClassListRef>,
+        class: Value,
+    ) -> Option<Value> {
+        match self.const_cache[slot as usize] {
+            Some(entry)
+                if entry.version == self.const_version
+                    && entry.class_list == class_list
+                    && entry.class.id() == class.id() =>
+            {
+                Some(entry.value)
+            }
             _ => None,
         }
     }
+
+    pub fn set_const_cache_entry(
+        &mut self,
+        slot: u32,
+        class_list: Option<ClassListRef>,
+        class: Value,
+        value: Value,
+    ) {
+        self.const_cache[slot as usize] = Some(ConstCacheEntry {
+            version: self.const_version,
+            class_list,
+            class,
+            value,
+        });
+    }
+
+    pub fn add_ivar_cache_entry(&mut self) -> u32 {
+        self.ivar_cache.push(None);
+        self.ivar_cache.len() as u32 - 1
+    }
+
+    pub fn get_ivar_cache_entry(&self, slot: u32) -> Option<IvarCacheEntry> {
+        self.ivar_cache[slot as usize]
+    }
+
+    pub fn set_ivar_cache_entry(&mut self, slot: u32, entry: IvarCacheEntry) {
+        self.ivar_cache[slot as usize] = Some(entry);
+    }
 }
 
 impl Globals {
@@ -340,18 +470,65 @@ impl MethodCache {
 //
 //-------------------------------------------------------------------------------------------------------------
 
+/// Number of receiver classes which an inline cache entry holds.
+/// A call site which sees more classes becomes megamorphic,
+/// and methods are looked up through the global method cache.
+const INLINE_CACHE_SIZE: usize = 4;
+
 #[derive(Debug, Clone)]
 pub struct InlineCache {
-    table: Vec<Option<InlineCacheEntry>>,
+    table: Vec<InlineCacheEntry>,
     id: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct InlineCacheEntry {
-    class: Value,
     version: usize,
-    //is_class_method: bool,
-    method: MethodRef,
+    classes: Vec<(Value, MethodRef, Visibility)>,
+    megamorphic: bool,
+}
+
+impl InlineCacheEntry {
+    fn add_class(
+        &mut self,
+        version: usize,
+        class: Value,
+        method: MethodRef,
+        visibility: Visibility,
+    ) {
+        if self.version != version {
+            self.version = version;
+            self.classes.clear();
+            self.megamorphic = false;
+        }
+        if self.megamorphic {
+            return;
+        }
+        if self.classes.len() < INLINE_CACHE_SIZE {
+            self.classes.push((class, method, visibility));
+        } else {
+            self.megamorphic = true;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ConstCacheEntry {
+    version: usize,
+    class_list: Option<ClassListRef>,
+    class: Value,
+    value: Value,
+}
+
+/// Shape cache of an instance variable access site.
+#[derive(Debug, Clone, Copy)]
+pub struct IvarCacheEntry {
+    /// The shape of receivers this entry is valid for, `None` for receivers without variables.
+    pub shape: Option<ShapeRef>,
+    /// For sites which add the variable, the shape after the addition.
+    pub next: Option<ShapeRef>,
+    /// Slot index of the variable.
+    pub index: usize,
 }
 
 impl InlineCache {
@@ -363,13 +540,17 @@ impl InlineCache {
     }
     fn add_entry(&mut self) -> u32 {
         self.id += 1;
-        self.table.push(None);
+        self.table.push(InlineCacheEntry::default());
         self.id - 1
     }
 
-    fn get_entry(&self, id: u32) -> &Option<InlineCacheEntry> {
+    fn get_entry(&self, id: u32) -> &InlineCacheEntry {
         &self.table[id as usize]
     }
+
+    fn get_mut_entry(&mut self, id: u32) -> &mut InlineCacheEntry {
+        &mut self.table[id as usize]
+    }
 }
 
 //-------------------------------------------------------------------------------------------------------------
@@ -11,12 +11,18 @@ pub mod test;
 pub mod util;
 pub mod value;
 pub mod vm;
+pub use crate::builtin::encoding::Encoding;
 pub use crate::builtin::enumerator::*;
 pub use crate::builtin::fiber::*;
+pub use crate::builtin::file::StatRef;
+pub use crate::builtin::io::IORef;
+pub use crate::builtin::matchdata::MatchDataRef;
 pub use crate::builtin::procobj::*;
+pub use crate::builtin::random::RandomRef;
 pub use crate::builtin::range::*;
 pub use crate::builtin::regexp::*;
 pub use crate::builtin::string::RString;
+pub use crate::builtin::time::TimeRef;
 pub use crate::error::*;
 pub use crate::globals::*;
 pub use crate::parse::parser::{LvarCollector, LvarId, ParseResult, Parser};
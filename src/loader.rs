@@ -33,3 +33,18 @@ pub fn load_file(file_name: impl Into<String>) -> Result<(std::path::PathBuf, St
 
     Ok((absolute_path, file_body))
 }
+
+/// Reads a file as raw bytes, e.g. a compiled bytecode file.
+pub fn load_binary_file(
+    file_name: impl Into<String>,
+) -> Result<(std::path::PathBuf, Vec<u8>), LoadError> {
+    let file_name = file_name.into();
+    let absolute_path = match std::path::Path::new(&file_name).canonicalize() {
+        Ok(path) => path,
+        Err(ioerr) => return Err(LoadError::NotFound(format!("{}", ioerr))),
+    };
+    match read(&absolute_path) {
+        Ok(bytes) => Ok((absolute_path, bytes)),
+        Err(ioerr) => Err(LoadError::CouldntOpen(format!("{}", ioerr))),
+    }
+}
@@ -5,20 +5,71 @@ extern crate ruruby;
 extern crate rustyline;
 
 use clap::{App, AppSettings, Arg};
-use ruruby::loader::{load_file, LoadError};
+use ruruby::loader::{load_binary_file, load_file, LoadError};
+use std::path::{Path, PathBuf};
 use std::thread;
 mod repl;
 use repl::*;
 use ruruby::*;
 
+/// Size of the native stack of the thread which runs a program.
+const VM_THREAD_STACK_SIZE: usize = 256 * 1024 * 1024;
+
 fn main() {
     let app = App::new("ruruby")
         .version("0.0.1")
         .author("monochrome")
         .about("A toy Ruby interpreter")
         .setting(AppSettings::TrailingVarArg)
+        .arg(Arg::from_usage(
+            "--compile [SOURCE] 'Compile SOURCE into a bytecode file (.rbc)'",
+        ))
+        .arg(Arg::from_usage(
+            "-o [OUTPUT] 'Output file name for --compile'",
+        ))
+        .arg(
+            Arg::from_usage("--dump [TARGET] 'Dump TARGET of the program instead of running it'")
+                .possible_values(&["insns"]),
+        )
+        .arg(Arg::from_usage(
+            "--cache 'Cache compiled source files as .rbc next to them'",
+        ))
+        .arg(Arg::from_usage(
+            "--cache-dir [DIR] 'Cache compiled source files in DIR'",
+        ))
+        .arg(Arg::from_usage(
+            "--jit 'Compile hot methods into machine code'",
+        ))
+        .arg(Arg::from_usage(
+            "--jit-threshold [N] 'Number of calls before a method is compiled by --jit'",
+        ))
+        .arg(
+            Arg::from_usage(
+                "--profile [FORMAT] 'Report time spent in each method and line (flat, tree or collapsed)'",
+            )
+            .possible_values(&["flat", "tree", "collapsed"])
+            .min_values(0)
+            .require_equals(true),
+        )
+        .arg(Arg::from_usage(
+            "--profile-out [FILE] 'Write the report of --profile to FILE instead of stderr'",
+        ))
+        .arg(Arg::from_usage(
+            "--max-stack-depth [N] 'Maximum depth of method calls before SystemStackError is raised'",
+        ))
+        .arg(Arg::from_usage(
+            "--coverage-out [FILE] 'Write line, branch and method coverage to FILE in the SimpleCov JSON format'",
+        ))
         .arg(Arg::from_usage("[file]... 'Input file name'").multiple(true));
     let m = app.get_matches();
+    if let Some(source) = m.value_of("compile") {
+        let output = match m.value_of("o") {
+            Some(output) => PathBuf::from(output),
+            None => Path::new(source).with_extension("rbc"),
+        };
+        compile_file(source, output);
+        return;
+    }
     let args: Vec<&str> = match m.values_of("file") {
         Some(val) => val.collect(),
         None => {
@@ -26,7 +77,52 @@ fn main() {
             return;
         }
     };
+    if m.value_of("dump") == Some("insns") {
+        dump_insns(args[0]);
+        return;
+    }
     let mut vm = VMRef::new(VM::new());
+    if let Some(dir) = m.value_of("cache-dir") {
+        vm.bytecode_cache = Some(CacheLocation::Dir(PathBuf::from(dir)));
+    } else if m.is_present("cache") {
+        vm.bytecode_cache = Some(CacheLocation::SourceDir);
+    }
+    if m.is_present("jit") {
+        if JitConfig::is_supported() {
+            vm.globals.jit.enabled = true;
+        } else {
+            eprintln!("warning: JIT is not supported on this platform.");
+        }
+    }
+    if let Some(threshold) = m.value_of("jit-threshold") {
+        match threshold.parse::<usize>() {
+            Ok(threshold) => vm.globals.jit.threshold = threshold,
+            Err(_) => {
+                eprintln!("Invalid --jit-threshold. '{}'", threshold);
+                return;
+            }
+        }
+    }
+    if let Some(depth) = m.value_of("max-stack-depth") {
+        match depth.parse::<usize>() {
+            Ok(depth) if depth > 0 => vm.globals.max_stack_depth = depth,
+            _ => {
+                eprintln!("Invalid --max-stack-depth. '{}'", depth);
+                return;
+            }
+        }
+    }
+    let profile_format = if m.is_present("profile") {
+        vm.profiler = Some(Profiler::new());
+        m.value_of("profile")
+            .and_then(ProfileFormat::from_name)
+            .or(Some(ProfileFormat::Flat))
+    } else {
+        None
+    };
+    if m.is_present("coverage-out") {
+        vm.globals.coverage.start(CoverageMode::all());
+    }
     let id = vm.globals.get_ident_id("ARGV");
     let mut res: Vec<Value> = args
         .iter()
@@ -34,47 +130,131 @@ fn main() {
         .collect();
     res.remove(0);
     let argv = Value::array_from(&vm.globals, res);
-    vm.globals.builtins.object.set_var(id, argv);
+    vm.globals.builtins.object.set_var(&vm.globals, id, argv);
     exec_file(&mut vm, args[0]);
+    if let Some(format) = profile_format {
+        report_profile(&vm, format, m.value_of("profile-out"));
+    }
+    if let Some(output) = m.value_of("coverage-out") {
+        write_coverage(&vm, output);
+    }
     return;
 }
 
-fn exec_file(vm: &mut VMRef, file_name: impl Into<String>) {
-    let file_name = file_name.into();
-    let (absolute_path, program) = match load_file(file_name.clone()) {
-        Ok((path, program)) => (path, program),
-        Err(err) => match err {
-            LoadError::NotFound(msg) => {
-                eprintln!("No such file or directory --- {} (LoadError)", &file_name);
-                eprintln!("{}", msg);
-                return;
+fn write_coverage(vm: &VM, output: &str) {
+    let files = vm.globals.coverage.result(&vm.globals);
+    let json = coverage_to_json(&vm.globals, &files);
+    if let Err(ioerr) = std::fs::write(output, json) {
+        eprintln!("Cannot write file. '{}'", output);
+        eprintln!("{}", ioerr);
+    }
+}
+
+fn report_profile(vm: &VM, format: ProfileFormat, output: Option<&str>) {
+    let report = match &vm.profiler {
+        Some(profiler) => profiler.report(&vm.globals, format),
+        None => return,
+    };
+    match output {
+        Some(output) => {
+            if let Err(ioerr) = std::fs::write(output, report) {
+                eprintln!("Cannot write file. '{}'", output);
+                eprintln!("{}", ioerr);
             }
-            LoadError::CouldntOpen(msg) => {
-                eprintln!("Cannot open file. '{}'", &file_name);
-                eprintln!("{}", msg);
-                return;
+        }
+        None => eprint!("{}", report),
+    }
+}
+
+fn show_load_error(file_name: &str, err: LoadError) {
+    match err {
+        LoadError::NotFound(msg) => {
+            eprintln!("No such file or directory --- {} (LoadError)", file_name);
+            eprintln!("{}", msg);
+        }
+        LoadError::CouldntOpen(msg) => {
+            eprintln!("Cannot open file. '{}'", file_name);
+            eprintln!("{}", msg);
+        }
+    }
+}
+
+fn show_error(err: RubyError) {
+    err.show_err();
+    for i in 0..err.info.len() {
+        eprint!("{}:", i);
+        err.show_loc(i);
+    }
+}
+
+fn compile_file(file_name: &str, output: PathBuf) {
+    let (absolute_path, program) = match load_file(file_name) {
+        Ok((path, program)) => (path, program),
+        Err(err) => return show_load_error(file_name, err),
+    };
+    let mut vm = VM::new();
+    match vm.compile_to_bytecode(absolute_path, &program) {
+        Ok(bytes) => {
+            if let Err(ioerr) = std::fs::write(&output, bytes) {
+                eprintln!("Cannot write file. '{}'", output.to_string_lossy());
+                eprintln!("{}", ioerr);
             }
-        },
+        }
+        Err(err) => show_error(err),
+    }
+}
+
+fn dump_insns(file_name: &str) {
+    let (absolute_path, program) = match load_file(file_name) {
+        Ok((path, program)) => (path, program),
+        Err(err) => return show_load_error(file_name, err),
     };
+    let mut vm = VM::new();
+    match vm.compile_program(absolute_path, &program) {
+        Ok(method) => print!("{}", vm::disasm::disasm(&vm.globals, method)),
+        Err(err) => show_error(err),
+    }
+}
 
-    let root_path = absolute_path.clone();
-    #[cfg(feature = "verbose")]
-    #[cfg_attr(tarpaulin, skip)]
-    eprintln!("load file: {:?}", root_path);
-    vm.root_path.push(root_path);
-    let mut vm2 = vm.clone();
-    let res = thread::spawn(move || vm2.run(absolute_path, &program, None))
+/// Run `f` on a thread with a native stack of VM_THREAD_STACK_SIZE.
+fn spawn_vm_thread(f: impl FnOnce() -> VMResult + Send + 'static) -> VMResult {
+    thread::Builder::new()
+        .stack_size(VM_THREAD_STACK_SIZE)
+        .spawn(f)
+        .unwrap()
         .join()
-        .unwrap();
+        .unwrap()
+}
+
+fn exec_file(vm: &mut VMRef, file_name: impl Into<String>) {
+    let file_name = file_name.into();
+    // Leave a margin for the native code which runs between checks of the stack.
+    vm.native_stack_size = VM_THREAD_STACK_SIZE - DEFAULT_NATIVE_STACK_SIZE;
+    let res = if Path::new(&file_name).extension() == Some("rbc".as_ref()) {
+        let (absolute_path, bytes) = match load_binary_file(file_name.clone()) {
+            Ok((path, bytes)) => (path, bytes),
+            Err(err) => return show_load_error(&file_name, err),
+        };
+        vm.root_path.push(absolute_path);
+        let mut vm2 = vm.clone();
+        spawn_vm_thread(move || vm2.run_bytecode(&bytes, None))
+    } else {
+        let (absolute_path, program) = match load_file(file_name.clone()) {
+            Ok((path, program)) => (path, program),
+            Err(err) => return show_load_error(&file_name, err),
+        };
+
+        let root_path = absolute_path.clone();
+        #[cfg(feature = "verbose")]
+        #[cfg_attr(tarpaulin, skip)]
+        eprintln!("load file: {:?}", root_path);
+        vm.root_path.push(root_path);
+        let mut vm2 = vm.clone();
+        spawn_vm_thread(move || vm2.run(absolute_path, &program, None))
+    };
     match res {
         Ok(_) => {}
-        Err(err) => {
-            err.show_err();
-            for i in 0..err.info.len() {
-                eprint!("{}:", i);
-                err.show_loc(i);
-            }
-        }
+        Err(err) => show_error(err),
     };
     vm.root_path.pop();
 }
@@ -436,11 +436,15 @@ impl Lexer {
             }
             None => {
                 match self.get() {
+                    Ok('<') if var_kind == VarKind::GlobalVar => {
+                        tok.push('<');
+                        return Ok(self.new_global_var(tok));
+                    }
                     Ok(ch) => {
                         if ch.is_alphanumeric() || ch == '_' || ch == '&' || ch == '\'' {
                             tok.push(ch);
                         } else {
-                            return Err(self.error_unexpected(self.pos));
+                            return Err(self.error_unexpected(self.pos - 1));
                         }
                     }
                     Err(_) => {
@@ -727,6 +731,32 @@ impl Lexer {
                     ch => s.push(ch),
                 }
             }
+        } else if self.consume('s') {
+            // %s(symbol)
+            let open = self.get()?;
+            let close = match open {
+                '(' => ')',
+                '[' => ']',
+                '{' => '}',
+                '<' => '>',
+                ch if ch.is_ascii_punctuation() => ch,
+                _ => return Err(self.error_unexpected(self.pos - 1)),
+            };
+            let mut s = "".to_string();
+            let mut level = 0;
+            loop {
+                match self.get()? {
+                    ch if ch == close && level == 0 => return Ok(self.new_percent('s', s)),
+                    ch => {
+                        if ch == close {
+                            level -= 1;
+                        } else if ch == open {
+                            level += 1;
+                        }
+                        s.push(ch)
+                    }
+                }
+            }
         } else {
             return Err(self.error_unexpected(self.pos));
         }
@@ -1075,6 +1105,24 @@ mod test {
         assert_tokens(program, ans);
     }
 
+    #[test]
+    fn global_var_argf() {
+        let program = "$<foo";
+        let ans = vec![
+            Token![GlobalVar("$<"), 0, 1],
+            Token![Ident("foo", false, false), 2, 4],
+            Token![EOF, 5],
+        ];
+        assert_tokens(program, ans);
+    }
+
+    #[test]
+    fn invalid_var() {
+        for program in &["@<", "@@<", "@<foo"] {
+            assert!(Lexer::new().tokenize(program.to_string()).is_err());
+        }
+    }
+
     #[test]
     fn cmp1() {
         let program = "5 > 0";
@@ -82,6 +82,7 @@ pub enum NodeKind {
     OptionalParam(IdentId, Box<Node>),
     RestParam(IdentId),
     KeywordParam(IdentId, Box<Option<Node>>),
+    KWRestParam(IdentId),
     BlockParam(IdentId),
 
     MethodDef(IdentId, NodeVec, Box<Node>, LvarCollector), // id, params, body
@@ -105,6 +106,8 @@ pub enum NodeKind {
 pub struct SendArgs {
     pub args: NodeVec,
     pub kw_args: Vec<(IdentId, Node)>,
+    /// `**hash` arguments, each with the number of `key: value` arguments preceding it.
+    pub kw_rest: Vec<(usize, Node)>,
     pub block: Option<Box<Node>>,
 }
 
@@ -113,6 +116,7 @@ impl SendArgs {
         SendArgs {
             args: vec![],
             kw_args: vec![],
+            kw_rest: vec![],
             block: None,
         }
     }
@@ -287,6 +291,10 @@ impl Node {
         Node::new(NodeKind::KeywordParam(id, Box::new(default)), loc)
     }
 
+    pub fn new_kwrest_param(id: IdentId, loc: Loc) -> Self {
+        Node::new(NodeKind::KWRestParam(id), loc)
+    }
+
     pub fn new_block_param(id: IdentId, loc: Loc) -> Self {
         Node::new(NodeKind::BlockParam(id), loc)
     }
@@ -325,8 +333,8 @@ impl Node {
         params: Vec<Node>,
         body: Node,
         lvar: LvarCollector,
+        loc: Loc,
     ) -> Self {
-        let loc = body.loc();
         Node::new(NodeKind::MethodDef(id, params, Box::new(body), lvar), loc)
     }
 
@@ -336,8 +344,8 @@ impl Node {
         params: Vec<Node>,
         body: Node,
         lvar: LvarCollector,
+        loc: Loc,
     ) -> Self {
-        let loc = body.loc();
         Node::new(
             NodeKind::SingletonMethodDef(Box::new(singleton), id, params, Box::new(body), lvar),
             loc,
@@ -391,6 +399,7 @@ impl Node {
         let send_args = SendArgs {
             args: vec![],
             kw_args: vec![],
+            kw_rest: vec![],
             block: None,
         };
         Node::new(
@@ -81,7 +81,16 @@ impl LvarCollector {
         }
     }
 
-    fn insert(&mut self, val: IdentId) -> LvarId {
+    /// Rebuild a collector from a table of local variables and an optional block parameter.
+    pub fn from_table(table: HashMap<IdentId, LvarId>, block: Option<LvarId>) -> Self {
+        LvarCollector {
+            id: table.len(),
+            table,
+            block,
+        }
+    }
+
+    pub fn insert(&mut self, val: IdentId) -> LvarId {
         match self.table.get(&val) {
             Some(id) => *id,
             None => {
@@ -180,6 +189,7 @@ enum ContextKind {
 struct ArgList {
     args: Vec<Node>,
     kw_args: Vec<(IdentId, Node)>,
+    kw_rest: Vec<(usize, Node)>,
     block: Option<Box<Node>>,
 }
 
@@ -421,11 +431,30 @@ impl Parser {
         Ok(self.get_ident_id(name))
     }
 
+    /// Parse a reserved word used as the label of a keyword argument, such as `in:`.
+    fn parse_reserved_label(&mut self) -> Result<Option<IdentId>, RubyError> {
+        let reserved = match self.peek()?.kind {
+            TokenKind::Reserved(reserved) => reserved,
+            _ => return Ok(None),
+        };
+        self.save_state();
+        self.get()?;
+        if self.consume_punct_no_term(Punct::Colon)? {
+            self.discard_state();
+            let name = self.lexer.get_string_from_reserved(reserved).to_string();
+            Ok(Some(self.get_ident_id(name)))
+        } else {
+            self.restore_state();
+            Ok(None)
+        }
+    }
+
     fn token_as_symbol(&self, token: &Token) -> String {
         match token.kind.clone() {
             TokenKind::Ident(ident, _, _) => ident,
             TokenKind::Const(ident, _, _) => ident,
             TokenKind::InstanceVar(ident) => ident,
+            TokenKind::GlobalVar(ident) => ident,
             TokenKind::StringLit(ident) => ident,
             TokenKind::Reserved(reserved) => {
                 self.lexer.get_string_from_reserved(reserved).to_string()
@@ -503,18 +532,31 @@ impl Parser {
         Ok((node, lvar))
     }
 
+    /// Parse `program` in the scope of a binding.
+    /// `ext_lvars` are local variable tables of the binding and its outer scopes, the outermost first.
+    /// New local variables are added to the innermost table, which is returned as `lvar_collector`.
     pub fn parse_program_eval(
         mut self,
         path: PathBuf,
         program: &str,
-        ext_lvar: LvarCollector,
+        first_line: usize,
+        ext_lvars: Vec<LvarCollector>,
     ) -> Result<ParseResult, RubyError> {
         self.lexer.init(path, program);
-        self.context_stack.push(Context::new_class(Some(ext_lvar)));
-        self.context_stack.push(Context::new_block());
+        self.lexer.source_info.first_line = first_line;
+        for (i, lvar) in ext_lvars.into_iter().enumerate() {
+            if i == 0 {
+                self.context_stack.push(Context::new_class(Some(lvar)));
+            } else {
+                self.context_stack.push(Context {
+                    lvar,
+                    kind: ContextKind::Block,
+                });
+            }
+        }
         let node = self.parse_comp_stmt()?;
         let lvar = self.context_stack.pop().unwrap().lvar;
-        self.context_stack.pop().unwrap();
+        self.context_stack.clear();
         
         let tok = self.peek()?;
         if  tok.is_eof() {
@@ -715,22 +757,24 @@ impl Parser {
     fn parse_arglist(&mut self) -> Result<SendArgs, RubyError> {
         let first_arg = self.parse_arg()?;
         if self.is_line_term()? {
-            return Ok(SendArgs{args:vec![first_arg], kw_args:vec![], block:None});
+            return Ok(SendArgs{args:vec![first_arg], kw_args:vec![], kw_rest:vec![], block:None});
         }
 
         if first_arg.is_operation() && self.is_command()? {
             let args =
                 vec![self.parse_command(first_arg.as_method_name().unwrap(), first_arg.loc())?];
-            return Ok(SendArgs{args, kw_args:vec![], block:None});
+            return Ok(SendArgs{args, kw_args:vec![], kw_rest:vec![], block:None});
         }
 
         let mut args = vec![first_arg];
         let mut kw_args = vec![];
+        let mut kw_rest = vec![];
         let mut block = None;
         if self.consume_punct_no_term(Punct::Comma)? {
             let res = self.parse_argument_list(None)?;
             let mut new_args = res.args;
             kw_args = res.kw_args;
+            kw_rest = res.kw_rest;
             block = res.block;
             args.append(&mut new_args);
         }
@@ -741,7 +785,7 @@ impl Parser {
             }
             None => {}
         };
-        Ok(SendArgs{args, kw_args, block})
+        Ok(SendArgs{args, kw_args, kw_rest, block})
     }
 
     fn is_command(&mut self) -> Result<bool, RubyError> {
@@ -1069,7 +1113,7 @@ impl Parser {
         let loc = node.loc();
         if self.consume_punct_no_term(Punct::LParen)? {
             // PRIMARY-METHOD : FNAME ( ARGS ) BLOCK?
-            let ArgList{args, kw_args, mut block} = self.parse_argument_list(Punct::RParen)?;
+            let ArgList{args, kw_args, kw_rest, mut block} = self.parse_argument_list(Punct::RParen)?;
             match self.parse_block()? {
                 Some(actual_block) => {
                     if block.is_some() {return Err(self.error_unexpected(actual_block.loc(), "Both block arg and actual block given."))}
@@ -1077,7 +1121,7 @@ impl Parser {
                 }
                 None => {}
             };
-            let send_args = SendArgs {args, kw_args, block};
+            let send_args = SendArgs {args, kw_args, kw_rest, block};
 
             Ok(Node::new_send(
                 Node::new_self(loc),
@@ -1088,7 +1132,7 @@ impl Parser {
             ))
         } else if let Some(block) = self.parse_block()? {
             // PRIMARY-METHOD : FNAME BLOCK
-            let send_args = SendArgs {args:vec![], kw_args:vec![], block: Some(block)};
+            let send_args = SendArgs {args:vec![], kw_args:vec![], kw_rest:vec![], block: Some(block)};
             Ok(Node::new_send(
                 Node::new_self(loc),
                 node.as_method_name().unwrap(),
@@ -1135,41 +1179,22 @@ impl Parser {
                 // | PRIMARY . FNAME BLOCK => completed: true
                 // | PRIMARY . FNAME ( ARGS ) BLOCK? => completed: true
                 // | PRIMARY . FNAME => completed: false
-                let tok = self.get()?;
+                // | PRIMARY . ( ARGS ) => PRIMARY . call ( ARGS )
+                let tok = self.peek_no_term()?;
                 let id = match &tok.kind {
-                    TokenKind::Ident(s, has_suffix, _) => {
-                        let name = if *has_suffix {
-                            //if self.consume_punct_no_term(Punct::Question)? {
-                            //    s.clone() + "?"
-                            //} else if self.consume_punct_no_term(Punct::Not)? {
-                            //    s.clone() + "!"
-                            //} else {
-                                s.clone()
-                            //}
-                        } else {
-                            s.clone()
-                        };
-                        self.get_ident_id(name)
-                    }
-                    TokenKind::Reserved(r) => {
-                        let string = self.lexer.get_string_from_reserved(*r).to_owned();
-                        self.get_ident_id(string)
-                    }
-                    TokenKind::Punct(p) => self.parse_op_definable(p)?,
-                    _ => {
-                        return Err(
-                            self.error_unexpected(tok.loc(), "method name must be an identifier.")
-                        )
-                    }
+                    TokenKind::Punct(Punct::LParen) => self.get_ident_id("call"),
+                    _ => self.parse_method_name()?,
                 };
                 let mut args = vec![];
                 let mut kw_args = vec![];
+                let mut kw_rest = vec![];
                 let mut block = None;
                 let mut completed = false;
                 if self.consume_punct_no_term(Punct::LParen)? {
                     let res = self.parse_argument_list(Punct::RParen)?;
                     args = res.args;
                     kw_args = res.kw_args;
+                    kw_rest = res.kw_rest;
                     block = res.block;
                     completed = true;
                 }
@@ -1189,7 +1214,7 @@ impl Parser {
                     }
                     _ => node,
                 };
-                let send_args = SendArgs {args, kw_args, block};
+                let send_args = SendArgs {args, kw_args, kw_rest, block};
                 Node::new_send(
                     node,
                     id,
@@ -1214,6 +1239,38 @@ impl Parser {
         }
     }
 
+    /// Parse a method name after `.`.
+    fn parse_method_name(&mut self) -> Result<IdentId, RubyError> {
+        let tok = self.get()?;
+        let id = match &tok.kind {
+            TokenKind::Ident(s, has_suffix, _) => {
+                let name = if *has_suffix {
+                    //if self.consume_punct_no_term(Punct::Question)? {
+                    //    s.clone() + "?"
+                    //} else if self.consume_punct_no_term(Punct::Not)? {
+                    //    s.clone() + "!"
+                    //} else {
+                        s.clone()
+                    //}
+                } else {
+                    s.clone()
+                };
+                self.get_ident_id(name)
+            }
+            TokenKind::Reserved(r) => {
+                let string = self.lexer.get_string_from_reserved(*r).to_owned();
+                self.get_ident_id(string)
+            }
+            TokenKind::Punct(p) => self.parse_op_definable(p)?,
+            _ => {
+                return Err(
+                    self.error_unexpected(tok.loc(), "method name must be an identifier.")
+                )
+            }
+        };
+        Ok(id)
+    }
+
     /// Parse argument list.
     /// arg, *splat_arg, kw: kw_arg, &block <punct>
     /// punct: punctuator for terminating arg list. Set None for unparenthesized argument list.
@@ -1227,20 +1284,48 @@ impl Parser {
         };
         let mut args = vec![];
         let mut kw_args = vec![];
+        let mut kw_rest = vec![];
         let mut block = None;
         loop {
             if flag && self.consume_punct(punct)? {
-                return Ok(ArgList {args, kw_args, block});
+                return Ok(ArgList {args, kw_args, kw_rest, block});
             }
-            if self.consume_punct(Punct::Mul)? {
+            if self.consume_punct(Punct::Range3)? {
+                // argument forwarding
+                let loc = self.prev_loc();
+                let rest = self.forwarded_arg(IdentId::ANON_REST, loc)?;
+                args.push(Node::new_splat(rest, loc));
+                kw_rest.push((kw_args.len(), self.forwarded_arg(IdentId::ANON_KWREST, loc)?));
+                block = Some(Box::new(self.forwarded_arg(IdentId::ANON_BLOCK, loc)?));
+            } else if self.consume_punct(Punct::Mul)? {
                 // splat argument
                 let loc = self.prev_loc();
-                let array = self.parse_arg()?;
+                let array = if self.is_anonymous_arg(punct)? {
+                    self.forwarded_arg(IdentId::ANON_REST, loc)?
+                } else {
+                    self.parse_arg()?
+                };
                 args.push(Node::new_splat(array, loc));
+            } else if self.consume_punct(Punct::DMul)? {
+                // double splat argument
+                let loc = self.prev_loc();
+                let hash = if self.is_anonymous_arg(punct)? {
+                    self.forwarded_arg(IdentId::ANON_KWREST, loc)?
+                } else {
+                    self.parse_arg()?
+                };
+                kw_rest.push((kw_args.len(), hash));
             } else if self.consume_punct(Punct::BitAnd)? {
                 // block argument
-                let arg = self.parse_arg()?;
+                let loc = self.prev_loc();
+                let arg = if self.is_anonymous_arg(punct)? {
+                    self.forwarded_arg(IdentId::ANON_BLOCK, loc)?
+                } else {
+                    self.parse_arg()?
+                };
                 block = Some(Box::new(arg));
+            } else if let Some(id) = self.parse_reserved_label()? {
+                kw_args.push((id, self.parse_arg()?));
             } else {
                 let node = self.parse_arg()?;
                 match node.kind {
@@ -1266,7 +1351,22 @@ impl Parser {
         if flag {
             self.expect_punct(punct)?
         };
-        Ok(ArgList {args, kw_args, block})
+        Ok(ArgList {args, kw_args, kw_rest, block})
+    }
+
+    /// Examine whether the name of a `*`, `**` or `&` argument is omitted.
+    fn is_anonymous_arg(&mut self, punct: Punct) -> Result<bool, RubyError> {
+        let next = self.peek_no_term()?.kind;
+        Ok(next == TokenKind::Punct(Punct::Comma) || next == TokenKind::Punct(punct))
+    }
+
+    /// Get the hidden local variable which holds forwarded arguments.
+    fn forwarded_arg(&mut self, id: IdentId, loc: Loc) -> Result<Node, RubyError> {
+        if !self.is_local_var(id) {
+            let name = self.ident_table.get_name(id);
+            return Err(self.error_unexpected(loc, format!("No anonymous {} parameter.", name)));
+        }
+        Ok(Node::new_lvar(id, loc))
     }
 
     fn parse_block(&mut self) -> Result<Option<Box<Node>>, RubyError> {
@@ -1312,6 +1412,10 @@ impl Parser {
         let tok = self.get()?;
         let loc = tok.loc();
         match &tok.kind {
+            TokenKind::Ident(name, _, _) if name == "__LINE__" => {
+                let line = self.lexer.source_info.get_line(&loc);
+                Ok(Node::new_integer(line as i64, loc))
+            }
             TokenKind::Ident(name, has_suffix, trailing_space) => {
                 let id = self.get_ident_id(name);
                 if *has_suffix {
@@ -1390,6 +1494,13 @@ impl Parser {
                     let symbol_loc = self.prev_loc();
                     let id = match &token.kind {
                         TokenKind::Punct(punct) => self.parse_op_definable(punct)?,
+                        TokenKind::Ident(ident, true, _)
+                            if self.peek_no_term()?.kind == TokenKind::Punct(Punct::Assign) =>
+                        {
+                            // Setter method name like `:foo=`
+                            self.get()?;
+                            self.get_ident_id(format!("{}=", ident))
+                        }
                         _ if token.can_be_symbol() => {
                             let ident = self.token_as_symbol(&token);
                             self.get_ident_id(ident)
@@ -1419,14 +1530,7 @@ impl Parser {
                     self.context_stack.push(Context::new_block());
                     if self.consume_punct(Punct::LParen)? {
                         if !self.consume_punct(Punct::RParen)? {
-                            loop {
-                                let id = self.expect_ident()?;
-                                params.push(Node::new_param(id, self.prev_loc()));
-                                self.new_param(id, self.prev_loc())?;
-                                if !self.consume_punct(Punct::Comma)? {
-                                    break;
-                                }
-                            }
+                            params = self.parse_params(TokenKind::Punct(Punct::RParen))?;
                             self.expect_punct(Punct::RParen)?;
                         }
                     } else if let TokenKind::Ident(_, _, _) = self.peek()?.kind {
@@ -1434,9 +1538,16 @@ impl Parser {
                         self.new_param(id, self.prev_loc())?;
                         params.push(Node::new_param(id, self.prev_loc()));
                     };
-                    self.expect_punct(Punct::LBrace)?;
-                    let body = self.parse_comp_stmt()?;
-                    self.expect_punct(Punct::RBrace)?;
+                    let body = if self.consume_reserved(Reserved::Do)? {
+                        let body = self.parse_comp_stmt()?;
+                        self.expect_reserved(Reserved::End)?;
+                        body
+                    } else {
+                        self.expect_punct(Punct::LBrace)?;
+                        let body = self.parse_comp_stmt()?;
+                        self.expect_punct(Punct::RBrace)?;
+                        body
+                    };
                     let lvar = self.context_stack.pop().unwrap().lvar;
                     Ok(Node::new_proc(params, body, lvar, loc))
                 }
@@ -1649,7 +1760,7 @@ impl Parser {
                 _ => Ok(false),
             },
             TokenKind::Reserved(r) => match r {
-                Reserved::False | Reserved::Nil | Reserved::True | Reserved::Self_=> Ok(true),
+                Reserved::False | Reserved::Nil | Reserved::True | Reserved::Self_ | Reserved::Def => Ok(true),
                 _ => Ok(false),
             },
             _ => Ok(false),
@@ -1738,7 +1849,10 @@ impl Parser {
     fn parse_percent_notation(&mut self) -> Result<Node, RubyError> {
         let tok = self.lexer.lex_percent_notation()?;
         let loc = tok.loc;
-        if let TokenKind::PercentNotation(_kind, content) = tok.kind {
+        if let TokenKind::PercentNotation('s', content) = tok.kind {
+            let id = self.get_ident_id(content);
+            Ok(Node::new_symbol(id, loc))
+        } else if let TokenKind::PercentNotation(_kind, content) = tok.kind {
             let ary = content
                 .split(' ')
                 .map(|x| Node::new_string(x.to_string(), loc))
@@ -1849,6 +1963,7 @@ impl Parser {
         //      [else COMPSTMT]
         //      [ensure COMPSTMT]
         //  end
+        let loc = self.prev_loc();
         let mut is_singleton_method = None;
         let tok = self.get()?;
         let id = match tok.kind {
@@ -1898,9 +2013,12 @@ impl Parser {
         let args = self.parse_def_params()?;
         let body = self.parse_begin()?;
         let lvar = self.context_stack.pop().unwrap().lvar;
+        let loc = loc.merge(self.prev_loc());
         match is_singleton_method {
-            Some(singleton) => Ok(Node::new_singleton_method_decl(singleton, id, args, body, lvar)),
-            None => Ok(Node::new_method_decl(id, args, body, lvar)),
+            Some(singleton) => Ok(Node::new_singleton_method_decl(
+                singleton, id, args, body, lvar, loc,
+            )),
+            None => Ok(Node::new_method_decl(id, args, body, lvar, loc)),
         }
     }
 
@@ -1923,14 +2041,44 @@ impl Parser {
             let mut loc = self.loc();
             if self.consume_punct(Punct::BitAnd)? {
                 // Block param
-                let id = self.expect_ident()?;
+                let id = self.expect_param_ident(IdentId::ANON_BLOCK, terminator.clone())?;
                 loc = loc.merge(self.prev_loc());
                 args.push(Node::new_block_param(id, loc));
                 self.new_block_param(id, loc)?;
                 break;
+            } else if self.consume_punct(Punct::Range3)? {
+                // Argument forwarding `...` is expanded to anonymous `*`, `**` and `&` params.
+                if state >= Kind::Rest {
+                    return Err(self
+                        .error_unexpected(loc, "Argument forwarding is not allowed in ths position."));
+                }
+                let rest = IdentId::ANON_REST;
+                let kwrest = IdentId::ANON_KWREST;
+                let block = IdentId::ANON_BLOCK;
+                args.push(Node::new_splat_param(rest, loc));
+                self.new_param(rest, loc)?;
+                args.push(Node::new_kwrest_param(kwrest, loc));
+                self.new_param(kwrest, loc)?;
+                args.push(Node::new_block_param(block, loc));
+                self.new_block_param(block, loc)?;
+                break;
+            } else if self.consume_punct(Punct::DMul)? {
+                // Keyword rest param
+                let id = self.expect_param_ident(IdentId::ANON_KWREST, terminator.clone())?;
+                loc = loc.merge(self.prev_loc());
+                if state >= Kind::KWRest {
+                    return Err(self.error_unexpected(
+                        loc,
+                        "Keyword rest parameter is not allowed in ths position.",
+                    ));
+                } else {
+                    state = Kind::KWRest;
+                }
+                args.push(Node::new_kwrest_param(id, loc));
+                self.new_param(id, loc)?;
             } else if self.consume_punct(Punct::Mul)? {
                 // Splat(Rest) param
-                let id = self.expect_ident()?;
+                let id = self.expect_param_ident(IdentId::ANON_REST, terminator.clone())?;
                 loc = loc.merge(self.prev_loc());
                 if state >= Kind::Rest {
                     return Err(self
@@ -1945,7 +2093,12 @@ impl Parser {
                 let id = self.expect_ident()?;
                 if self.consume_punct(Punct::Assign)? {
                     // Optional param
-                    let default = self.parse_arg()?;
+                    // In block parameters, `|' terminates the default value.
+                    let default = if terminator == TokenKind::Punct(Punct::BitOr) {
+                        self.parse_arg_bitand()?
+                    } else {
+                        self.parse_arg()?
+                    };
                     loc = loc.merge(self.prev_loc());
                     match state {
                         Kind::Reqired => state = Kind::Optional,
@@ -1964,6 +2117,8 @@ impl Parser {
                     let next = self.peek_no_term()?.kind;
                     let default = if next == TokenKind::Punct(Punct::Comma) || next == terminator || next == TokenKind::LineTerm {
                         None
+                    } else if terminator == TokenKind::Punct(Punct::BitOr) {
+                        Some(self.parse_arg_bitand()?)
                     } else {
                         Some(self.parse_arg()?)
                     };
@@ -2007,6 +2162,21 @@ impl Parser {
         Ok(args)
     }
 
+    /// Get the name of a `*`, `**` or `&` parameter.
+    /// If the name is omitted, `anonymous` is used as a hidden name for argument forwarding.
+    fn expect_param_ident(
+        &mut self,
+        anonymous: IdentId,
+        terminator: TokenKind,
+    ) -> Result<IdentId, RubyError> {
+        let next = self.peek_no_term()?.kind;
+        if next == TokenKind::Punct(Punct::Comma) || next == terminator {
+            Ok(anonymous)
+        } else {
+            self.expect_ident()
+        }
+    }
+
     // ( )
     // ( ident [, ident]* )
     fn parse_def_params(&mut self) -> Result<Vec<Node>, RubyError> {
@@ -2085,6 +2255,28 @@ impl Parser {
                     Err(self.error_unexpected(loc, "Invalid symbol literal."))
                 }
             }
+            Punct::Plus => Ok(self.get_ident_id("+")),
+            Punct::Minus => Ok(self.get_ident_id("-")),
+            Punct::Mul => Ok(self.get_ident_id("*")),
+            Punct::Div => Ok(self.get_ident_id("/")),
+            Punct::Rem => Ok(self.get_ident_id("%")),
+            Punct::DMul => Ok(self.get_ident_id("**")),
+            Punct::Shl => Ok(self.get_ident_id("<<")),
+            Punct::Shr => Ok(self.get_ident_id(">>")),
+            Punct::BitOr => Ok(self.get_ident_id("|")),
+            Punct::BitAnd => Ok(self.get_ident_id("&")),
+            Punct::BitXor => Ok(self.get_ident_id("^")),
+            Punct::BitNot => Ok(self.get_ident_id("~")),
+            Punct::Not => Ok(self.get_ident_id("!")),
+            Punct::Eq => Ok(self.get_ident_id("==")),
+            Punct::TEq => Ok(self.get_ident_id("===")),
+            Punct::Ne => Ok(self.get_ident_id("!=")),
+            Punct::Gt => Ok(self.get_ident_id(">")),
+            Punct::Ge => Ok(self.get_ident_id(">=")),
+            Punct::Lt => Ok(self.get_ident_id("<")),
+            Punct::Le => Ok(self.get_ident_id("<=")),
+            Punct::Cmp => Ok(self.get_ident_id("<=>")),
+            Punct::Match => Ok(self.get_ident_id("=~")),
             _ => Err(self.error_unexpected(self.prev_loc(), "Invalid symbol literal.")),
         }
     }
@@ -253,6 +253,7 @@ impl Token {
             TokenKind::Const(_, _, _)
             | TokenKind::Ident(_, _, _)
             | TokenKind::InstanceVar(_)
+            | TokenKind::GlobalVar(_)
             | TokenKind::Reserved(_)
             | TokenKind::StringLit(_) => true,
             _ => false,
@@ -117,6 +117,8 @@ pub type SourceInfoRef = Ref<SourceInfo>;
 pub struct SourceInfo {
     pub path: PathBuf,
     pub code: Vec<char>,
+    /// The line number of the first line of `code`, which is given by `eval`.
+    pub first_line: usize,
 }
 
 impl SourceInfoRef {
@@ -130,15 +132,25 @@ impl SourceInfo {
         SourceInfo {
             path: path,
             code: vec![],
+            first_line: 1,
         }
     }
     pub fn show_file_name(&self) {
         eprintln!("{}", self.path.to_string_lossy());
     }
 
+    /// Get the line number (1-origin) of the Loc in the source code.
+    pub fn get_line(&self, loc: &Loc) -> usize {
+        let pos = std::cmp::min(loc.0 as usize, self.code.len());
+        self.code[..pos].iter().filter(|ch| **ch == '\n').count() + self.first_line
+    }
+
     /// Show the location of the Loc in the source code using '^^^'.
     pub fn show_loc(&self, loc: &Loc) {
-        let mut line: u32 = 1;
+        if self.code.is_empty() {
+            return;
+        }
+        let mut line = self.first_line as u32;
         let mut line_top_pos: u32 = 0;
         let mut line_pos = vec![];
         for (pos, ch) in self.code.iter().enumerate() {
@@ -186,7 +198,7 @@ impl SourceInfo {
         if !found {
             let line = match line_pos.last() {
                 Some(line) => (line.0 + 1, line.2 + 1, loc.1),
-                None => (1, 0, loc.1),
+                None => (self.first_line as u32, 0, loc.1),
             };
             let read = self.code[(line.1 as usize)..(loc.0 as usize)]
                 .iter()
@@ -290,6 +302,21 @@ impl IdentId {
     pub const _GT: IdentId = id!(13);
     pub const _GE: IdentId = id!(14);
     pub const _DIV: IdentId = id!(15);
+    /// Anonymous params, which are also used for argument forwarding `...`.
+    pub const ANON_REST: IdentId = id!(16);
+    pub const ANON_KWREST: IdentId = id!(17);
+    pub const ANON_BLOCK: IdentId = id!(18);
+    /// Hidden instance variables of Struct classes and InstructionSequences.
+    pub const STRUCT_MEMBERS: IdentId = id!(19);
+    pub const ISEQ_METHOD: IdentId = id!(20);
+}
+
+impl IdentId {
+    /// Returns true if `self` is used only inside of the VM and can not be spelled in source.
+    pub fn is_internal(&self) -> bool {
+        let id: u32 = (*self).into();
+        (IdentId::ANON_REST.into()..=IdentId::ISEQ_METHOD.into()).contains(&id)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -304,7 +331,7 @@ impl IdentifierTable {
         let mut table = IdentifierTable {
             table: HashMap::new(),
             table_rev: HashMap::new(),
-            ident_id: 20,
+            ident_id: 32,
         };
         table.set_ident_id("<null>", IdentId::from(0));
         table.set_ident_id("initialize", IdentId::INITIALIZE);
@@ -322,9 +349,19 @@ impl IdentifierTable {
         table.set_ident_id(">", IdentId::_GT);
         table.set_ident_id(">=", IdentId::_GE);
         table.set_ident_id("/", IdentId::_DIV);
+        table.set_internal_name("*", IdentId::ANON_REST);
+        table.set_internal_name("**", IdentId::ANON_KWREST);
+        table.set_internal_name("&", IdentId::ANON_BLOCK);
+        table.set_internal_name("_members", IdentId::STRUCT_MEMBERS);
+        table.set_internal_name("_method", IdentId::ISEQ_METHOD);
         table
     }
 
+    /// Names an internal identifier, which is never returned by `get_ident_id`.
+    fn set_internal_name(&mut self, name: impl Into<String>, id: IdentId) {
+        self.table_rev.insert(id.into(), name.into());
+    }
+
     fn set_ident_id(&mut self, name: impl Into<String>, id: IdentId) {
         let name = name.into();
         self.table.insert(name.clone(), id.into());
@@ -349,6 +386,15 @@ impl IdentifierTable {
         self.table_rev.get(&id.0.get()).unwrap()
     }
 
+    /// Returns all registered identifiers except internal ones.
+    pub fn all_ids(&self) -> Vec<IdentId> {
+        self.table
+            .values()
+            .filter(|id| **id != 0)
+            .map(|id| IdentId::from(*id))
+            .collect()
+    }
+
     pub fn add_postfix(&mut self, id: IdentId, postfix: &str) -> IdentId {
         let new_name = self.get_name(id).to_string() + postfix;
         self.get_ident_id(new_name)
@@ -3,8 +3,26 @@ use std::collections::HashMap;
 use std::hash::Hash;
 use std::ops::Deref;
 
+#[derive(Debug, Clone)]
+pub struct HashInfo {
+    pub table: HashTable,
+    /// The value returned for a missing key when there is no default proc.
+    pub default: Value,
+    /// The proc called with the hash and a missing key.
+    pub default_proc: Option<Value>,
+}
+
+impl PartialEq for HashInfo {
+    // Default values do not take part in Hash#==.
+    fn eq(&self, other: &Self) -> bool {
+        self.table == other.table
+    }
+}
+
+impl Eq for HashInfo {}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum HashInfo {
+pub enum HashTable {
     Map(HashMap<HashKey, Value>),
     IdentMap(HashMap<IdentKey, Value>),
 }
@@ -36,6 +54,7 @@ impl Hash for HashKey {
                     }
                 }
                 ObjKind::Method(lhs) => lhs.inner().hash(state),
+                ObjKind::Time(lhs) => lhs.inner().hash(state),
                 _ => self.0.hash(state),
             },
         }
@@ -59,6 +78,7 @@ impl PartialEq for HashKey {
                 (ObjKind::Range(lhs), ObjKind::Range(rhs)) => *lhs == *rhs,
                 (ObjKind::Hash(lhs), ObjKind::Hash(rhs)) => lhs.inner() == rhs.inner(),
                 (ObjKind::Method(lhs), ObjKind::Method(rhs)) => *lhs.inner() == *rhs.inner(),
+                (ObjKind::Time(lhs), ObjKind::Time(rhs)) => *lhs.inner() == *rhs.inner(),
                 _ => lhs.kind == rhs.kind,
             },
             _ => false,
@@ -102,9 +122,9 @@ pub enum IntoIter {
 
 impl IntoIter {
     fn new(hash: HashInfo) -> IntoIter {
-        match hash {
-            HashInfo::Map(map) => IntoIter::Map(map.into_iter()),
-            HashInfo::IdentMap(map) => IntoIter::IdentMap(map.into_iter()),
+        match hash.table {
+            HashTable::Map(map) => IntoIter::Map(map.into_iter()),
+            HashTable::IdentMap(map) => IntoIter::IdentMap(map.into_iter()),
         }
     }
 }
@@ -142,16 +162,16 @@ macro_rules! define_iter_new {
         impl<'a> $ty1<'a> {
             fn new(hash: $ty2) -> $ty1 {
                 match hash {
-                    HashInfo::Map(map) => $ty1::Map(map.$method()),
-                    HashInfo::IdentMap(map) => $ty1::IdentMap(map.$method()),
+                    HashTable::Map(map) => $ty1::Map(map.$method()),
+                    HashTable::IdentMap(map) => $ty1::IdentMap(map.$method()),
                 }
             }
         }
     };
 }
 
-define_iter_new!(Iter, &HashInfo, iter);
-define_iter_new!(IterMut, &mut HashInfo, iter_mut);
+define_iter_new!(Iter, &HashTable, iter);
+define_iter_new!(IterMut, &mut HashTable, iter_mut);
 
 macro_rules! define_iterator {
     ($ty2:ident) => {
@@ -177,19 +197,19 @@ define_iterator!(Iter);
 define_iterator!(IterMut);
 
 macro_rules! define_into_iterator {
-    ($ty1:ty, $ty2:ident) => {
+    ($ty1:ty, $ty2:ident, $method:ident) => {
         impl<'a> IntoIterator for $ty1 {
             type Item = (Value, Value);
             type IntoIter = $ty2<'a>;
             fn into_iter(self) -> $ty2<'a> {
-                $ty2::new(self)
+                self.$method()
             }
         }
     };
 }
 
-define_into_iterator!(&'a HashInfo, Iter);
-define_into_iterator!(&'a mut HashInfo, IterMut);
+define_into_iterator!(&'a HashInfo, Iter, iter);
+define_into_iterator!(&'a mut HashInfo, IterMut, iter_mut);
 
 impl IntoIterator for HashInfo {
     type Item = (Value, Value);
@@ -202,70 +222,82 @@ impl IntoIterator for HashInfo {
 
 impl HashInfo {
     pub fn new(map: HashMap<HashKey, Value>) -> Self {
-        HashInfo::Map(map)
+        HashInfo {
+            table: HashTable::Map(map),
+            default: Value::nil(),
+            default_proc: None,
+        }
     }
 
     pub fn iter(&self) -> Iter {
-        Iter::new(self)
+        Iter::new(&self.table)
     }
 
     pub fn iter_mut(&mut self) -> IterMut {
-        IterMut::new(self)
+        IterMut::new(&mut self.table)
+    }
+
+    /// The value for a missing `key`: the result of the default proc, or the default value.
+    pub fn default_value(&self, vm: &mut VM, hash: Value, key: Value) -> VMResult {
+        match self.default_proc {
+            Some(proc) => vm.eval_proc(proc, &Args::new2(hash, key)),
+            None => Ok(self.default),
+        }
     }
 
     pub fn get(&self, v: &Value) -> Option<&Value> {
-        match self {
-            HashInfo::Map(map) => map.get(&HashKey(*v)),
-            HashInfo::IdentMap(map) => map.get(&IdentKey(*v)),
+        match &self.table {
+            HashTable::Map(map) => map.get(&HashKey(*v)),
+            HashTable::IdentMap(map) => map.get(&IdentKey(*v)),
         }
     }
 
     pub fn len(&self) -> usize {
-        match self {
-            HashInfo::Map(map) => map.len(),
-            HashInfo::IdentMap(map) => map.len(),
+        match &self.table {
+            HashTable::Map(map) => map.len(),
+            HashTable::IdentMap(map) => map.len(),
         }
     }
 
     pub fn clear(&mut self) {
-        match self {
-            HashInfo::Map(map) => map.clear(),
-            HashInfo::IdentMap(map) => map.clear(),
+        match &mut self.table {
+            HashTable::Map(map) => map.clear(),
+            HashTable::IdentMap(map) => map.clear(),
         }
     }
 
     pub fn insert(&mut self, k: Value, v: Value) {
-        match self {
-            HashInfo::Map(map) => map.insert(HashKey(k), v),
-            HashInfo::IdentMap(map) => map.insert(IdentKey(k), v),
+        match &mut self.table {
+            HashTable::Map(map) => map.insert(HashKey(k), v),
+            HashTable::IdentMap(map) => map.insert(IdentKey(k), v),
         };
     }
 
     pub fn remove(&mut self, k: Value) -> Option<Value> {
-        match self {
-            HashInfo::Map(map) => map.remove(&HashKey(k)),
-            HashInfo::IdentMap(map) => map.remove(&IdentKey(k)),
+        match &mut self.table {
+            HashTable::Map(map) => map.remove(&HashKey(k)),
+            HashTable::IdentMap(map) => map.remove(&IdentKey(k)),
         }
     }
 
     pub fn contains_key(&self, k: Value) -> bool {
-        match self {
-            HashInfo::Map(map) => map.contains_key(&HashKey(k)),
-            HashInfo::IdentMap(map) => map.contains_key(&IdentKey(k)),
+        match &self.table {
+            HashTable::Map(map) => map.contains_key(&HashKey(k)),
+            HashTable::IdentMap(map) => map.contains_key(&IdentKey(k)),
         }
     }
 
     pub fn keys(&self) -> Vec<Value> {
-        match self {
-            HashInfo::Map(map) => map.keys().map(|x| x.0).collect(),
-            HashInfo::IdentMap(map) => map.keys().map(|x| x.0).collect(),
+        match &self.table {
+            HashTable::Map(map) => map.keys().map(|x| x.0).collect(),
+            HashTable::IdentMap(map) => map.keys().map(|x| x.0).collect(),
         }
     }
 
     pub fn values(&self) -> Vec<Value> {
-        match self {
-            HashInfo::Map(map) => map.values().cloned().collect(),
-            HashInfo::IdentMap(map) => map.values().cloned().collect(),
+        match &self.table {
+            HashTable::Map(map) => map.values().cloned().collect(),
+            HashTable::IdentMap(map) => map.values().cloned().collect(),
         }
     }
 
@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 //#[macro_use]
 use crate::*;
 
@@ -6,7 +5,9 @@ use crate::*;
 #[derive(Debug, Clone, PartialEq)]
 pub struct RValue {
     class: Value,
-    var_table: Box<ValueTable>,
+    /// `None` for objects without variables.
+    shape: Option<ShapeRef>,
+    vars: Vec<Value>,
     pub kind: ObjKind,
 }
 
@@ -27,6 +28,13 @@ pub enum ObjKind {
     Method(MethodObjRef),
     Fiber(FiberRef),
     Enumerator(EnumRef),
+    Binding(ContextRef),
+    Encoding(Encoding),
+    MatchData(MatchDataRef),
+    Random(RandomRef),
+    IO(IORef),
+    FileStat(StatRef),
+    Time(TimeRef),
 }
 
 impl RValue {
@@ -41,7 +49,8 @@ impl RValue {
     pub fn dup(&self) -> Self {
         RValue {
             class: self.class,
-            var_table: self.var_table.clone(),
+            shape: self.shape,
+            vars: self.vars.clone(),
             kind: match &self.kind {
                 ObjKind::Array(aref) => ObjKind::Array(aref.dup()),
                 ObjKind::Class(cref) => ObjKind::Class(cref.dup()),
@@ -58,6 +67,13 @@ impl RValue {
                 ObjKind::Regexp(rref) => ObjKind::Regexp(*rref),
                 ObjKind::Splat(v) => ObjKind::Splat(*v),
                 ObjKind::String(rstr) => ObjKind::String(rstr.clone()),
+                ObjKind::Binding(ctx) => ObjKind::Binding(*ctx),
+                ObjKind::Encoding(enc) => ObjKind::Encoding(*enc),
+                ObjKind::MatchData(mref) => ObjKind::MatchData(mref.dup()),
+                ObjKind::Random(rref) => ObjKind::Random(rref.dup()),
+                ObjKind::IO(ioref) => ObjKind::IO(*ioref),
+                ObjKind::FileStat(sref) => ObjKind::FileStat(sref.dup()),
+                ObjKind::Time(tref) => ObjKind::Time(tref.dup()),
             },
         }
     }
@@ -72,9 +88,9 @@ impl RValue {
 
     pub fn inspect(&self, vm: &mut VM) -> String {
         let mut s = format! {"#<{}:0x{:x}", self.class_name(&vm.globals), self.id()};
-        for (k, v) in self.var_table() {
-            let inspect = vm.val_to_s(*v);
-            let id = vm.globals.get_ident_name(*k);
+        for (k, v) in self.vars() {
+            let inspect = vm.val_to_s(v);
+            let id = vm.globals.get_ident_name(k);
             s = format!("{} {}={}", s, id, inspect);
         }
         format!("{}>", s)
@@ -84,14 +100,16 @@ impl RValue {
         RValue {
             class: Value::nil(), // dummy for boot strapping
             kind: ObjKind::Class(classref),
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
         }
     }
 
     pub fn new_fixnum(i: i64) -> Self {
         RValue {
             class: Value::nil(),
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Integer(i),
         }
     }
@@ -99,7 +117,8 @@ impl RValue {
     pub fn new_flonum(f: f64) -> Self {
         RValue {
             class: Value::nil(),
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Float(f),
         }
     }
@@ -107,23 +126,98 @@ impl RValue {
     pub fn new_string(globals: &Globals, s: String) -> Self {
         RValue {
             class: globals.builtins.string,
-            var_table: Box::new(HashMap::new()),
-            kind: ObjKind::String(RString::Str(s)),
+            shape: None,
+            vars: vec![],
+            kind: ObjKind::String(RString::new_string(s)),
         }
     }
 
     pub fn new_bytes(globals: &Globals, b: Vec<u8>) -> Self {
         RValue {
             class: globals.builtins.string,
-            var_table: Box::new(HashMap::new()),
-            kind: ObjKind::String(RString::Bytes(b)),
+            shape: None,
+            vars: vec![],
+            kind: ObjKind::String(RString::new_bytes(b)),
+        }
+    }
+
+    pub fn new_rstring(globals: &Globals, rstring: RString) -> Self {
+        RValue {
+            class: globals.builtins.string,
+            shape: None,
+            vars: vec![],
+            kind: ObjKind::String(rstring),
+        }
+    }
+
+    pub fn new_encoding(globals: &Globals, encoding: Encoding) -> Self {
+        RValue {
+            class: globals.builtins.encoding,
+            shape: None,
+            vars: vec![],
+            kind: ObjKind::Encoding(encoding),
+        }
+    }
+
+    pub fn new_matchdata(globals: &Globals, matchdata: MatchDataRef) -> Self {
+        RValue {
+            class: globals.builtins.matchdata,
+            shape: None,
+            vars: vec![],
+            kind: ObjKind::MatchData(matchdata),
+        }
+    }
+
+    pub fn new_random(globals: &Globals, random: RandomRef) -> Self {
+        RValue {
+            class: globals.builtins.random,
+            shape: None,
+            vars: vec![],
+            kind: ObjKind::Random(random),
+        }
+    }
+
+    pub fn new_io(globals: &Globals, io: IORef) -> Self {
+        RValue {
+            class: globals.builtins.io,
+            shape: None,
+            vars: vec![],
+            kind: ObjKind::IO(io),
+        }
+    }
+
+    pub fn new_file(globals: &Globals, io: IORef) -> Self {
+        RValue {
+            class: globals.builtins.file,
+            shape: None,
+            vars: vec![],
+            kind: ObjKind::IO(io),
+        }
+    }
+
+    pub fn new_file_stat(globals: &Globals, stat: StatRef) -> Self {
+        RValue {
+            class: globals.builtins.file_stat,
+            shape: None,
+            vars: vec![],
+            kind: ObjKind::FileStat(stat),
+        }
+    }
+
+    pub fn new_time(globals: &Globals, time: TimeRef) -> Self {
+        RValue {
+            class: globals.builtins.time,
+            shape: None,
+            vars: vec![],
+            kind: ObjKind::Time(time),
         }
     }
 
     pub fn new_ordinary(class: Value) -> Self {
         RValue {
             class,
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Ordinary,
         }
     }
@@ -131,7 +225,8 @@ impl RValue {
     pub fn new_class(globals: &Globals, classref: ClassRef) -> Self {
         RValue {
             class: globals.builtins.class,
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Class(classref),
         }
     }
@@ -139,7 +234,8 @@ impl RValue {
     pub fn new_module(globals: &Globals, classref: ClassRef) -> Self {
         RValue {
             class: globals.builtins.module,
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Module(classref),
         }
     }
@@ -147,7 +243,8 @@ impl RValue {
     pub fn new_array(globals: &Globals, arrayref: ArrayRef) -> Self {
         RValue {
             class: globals.builtins.array,
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Array(arrayref),
         }
     }
@@ -155,7 +252,8 @@ impl RValue {
     pub fn new_range(globals: &Globals, range: RangeInfo) -> Self {
         RValue {
             class: globals.builtins.range,
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Range(range),
         }
     }
@@ -163,7 +261,8 @@ impl RValue {
     pub fn new_splat(globals: &Globals, val: Value) -> Self {
         RValue {
             class: globals.builtins.array,
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Splat(val),
         }
     }
@@ -171,7 +270,8 @@ impl RValue {
     pub fn new_hash(globals: &Globals, hashref: HashRef) -> Self {
         RValue {
             class: globals.builtins.hash,
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Hash(hashref),
         }
     }
@@ -179,7 +279,8 @@ impl RValue {
     pub fn new_regexp(globals: &Globals, regexpref: RegexpRef) -> Self {
         RValue {
             class: globals.builtins.regexp,
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Regexp(regexpref),
         }
     }
@@ -187,15 +288,35 @@ impl RValue {
     pub fn new_proc(globals: &Globals, procref: ProcRef) -> Self {
         RValue {
             class: globals.builtins.procobj,
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Proc(procref),
         }
     }
 
+    pub fn new_binding(globals: &Globals, context: ContextRef) -> Self {
+        RValue {
+            class: globals.builtins.binding,
+            shape: None,
+            vars: vec![],
+            kind: ObjKind::Binding(context),
+        }
+    }
+
     pub fn new_method(globals: &Globals, methodref: MethodObjRef) -> Self {
         RValue {
             class: globals.builtins.method,
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
+            kind: ObjKind::Method(methodref),
+        }
+    }
+
+    pub fn new_unbound_method(globals: &Globals, methodref: MethodObjRef) -> Self {
+        RValue {
+            class: globals.builtins.unbound_method,
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Method(methodref),
         }
     }
@@ -210,7 +331,8 @@ impl RValue {
         let fiber = FiberInfo::new(vm, context, rec, tx);
         RValue {
             class: globals.builtins.fiber,
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Fiber(FiberRef::new(fiber)),
         }
     }
@@ -225,7 +347,8 @@ impl RValue {
         let enum_info = EnumRef::from(method, receiver, args);
         RValue {
             class: globals.builtins.enumerator,
-            var_table: Box::new(HashMap::new()),
+            shape: None,
+            vars: vec![],
             kind: ObjKind::Enumerator(enum_info),
         }
     }
@@ -264,23 +387,66 @@ impl RValue {
     }
 
     pub fn get_var(&self, id: IdentId) -> Option<Value> {
-        self.var_table.get(&id).cloned()
+        self.index_of(id).map(|index| self.vars[index])
     }
 
     pub fn get_mut_var(&mut self, id: IdentId) -> Option<&mut Value> {
-        self.var_table.get_mut(&id)
+        match self.index_of(id) {
+            Some(index) => Some(&mut self.vars[index]),
+            None => None,
+        }
+    }
+
+    pub fn set_var(&mut self, globals: &Globals, id: IdentId, val: Value) {
+        match self.index_of(id) {
+            Some(index) => self.vars[index] = val,
+            None => {
+                let shape = self.shape.unwrap_or(globals.root_shape);
+                self.push_var(shape.transition(id), val)
+            }
+        }
+    }
+
+    /// Remove the variable `id`, moving `self` to the shape without it.
+    pub fn remove_var(&mut self, globals: &Globals, id: IdentId) -> Option<Value> {
+        let index = self.index_of(id)?;
+        let mut ids = self.shape?.ids().to_vec();
+        ids.remove(index);
+        self.shape = ShapeRef::from_ids(globals.root_shape, &ids);
+        Some(self.vars.remove(index))
+    }
+
+    /// Slot index of the variable `id`.
+    fn index_of(&self, id: IdentId) -> Option<usize> {
+        self.shape?.index_of(id)
+    }
+
+    /// Variables of `self` in the order they were added.
+    pub fn vars(&self) -> impl Iterator<Item = (IdentId, Value)> + '_ {
+        let ids = match &self.shape {
+            Some(shape) => shape.ids(),
+            None => &[],
+        };
+        ids.iter().cloned().zip(self.vars.iter().cloned())
+    }
+
+    pub fn shape(&self) -> Option<ShapeRef> {
+        self.shape
     }
 
-    pub fn set_var(&mut self, id: IdentId, val: Value) {
-        self.var_table.insert(id, val);
+    /// The variable in slot `index` of the current shape.
+    pub fn var_at(&self, index: usize) -> Value {
+        self.vars[index]
     }
 
-    pub fn var_table(&self) -> &ValueTable {
-        &self.var_table
+    pub fn var_at_mut(&mut self, index: usize) -> &mut Value {
+        &mut self.vars[index]
     }
 
-    pub fn var_table_mut(&mut self) -> &mut ValueTable {
-        &mut self.var_table
+    /// Append `val` in a new slot, where `shape` is the transition from the current shape.
+    pub fn push_var(&mut self, shape: ShapeRef, val: Value) {
+        self.shape = Some(shape);
+        self.vars.push(val);
     }
 
     pub fn get_instance_method(&self, id: IdentId) -> Option<MethodRef> {
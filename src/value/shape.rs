@@ -0,0 +1,71 @@
+use crate::*;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Hidden class of an object: the variables it holds, in the order they were added.
+/// Objects which got the same variables in the same order share one shape, so
+/// the slot of a variable can be cached at an access site and keyed on the shape.
+#[derive(Debug)]
+pub struct Shape {
+    ids: Vec<IdentId>,
+    slots: HashMap<IdentId, usize>,
+    transitions: Mutex<HashMap<IdentId, ShapeRef>>,
+}
+
+pub type ShapeRef = Ref<Shape>;
+
+impl Shape {
+    fn new() -> Self {
+        Shape {
+            ids: vec![],
+            slots: HashMap::new(),
+            transitions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Slot index of the variable `id`.
+    pub fn index_of(&self, id: IdentId) -> Option<usize> {
+        self.slots.get(&id).cloned()
+    }
+
+    /// Variables in slot order.
+    pub fn ids(&self) -> &[IdentId] {
+        &self.ids
+    }
+}
+
+impl ShapeRef {
+    /// A new shape of objects without variables. Each `Globals` owns one root, which is
+    /// shared by its VM and the fibers running on other threads.
+    pub fn new_root() -> Self {
+        ShapeRef::new(Shape::new())
+    }
+
+    /// The shape of an object with `self` shape after the variable `id` is added.
+    pub fn transition(self, id: IdentId) -> Self {
+        let mut transitions = self.transitions.lock().unwrap();
+        if let Some(next) = transitions.get(&id) {
+            return *next;
+        }
+        let mut ids = self.ids.clone();
+        let mut slots = self.slots.clone();
+        slots.insert(id, ids.len());
+        ids.push(id);
+        let next = ShapeRef::new(Shape {
+            ids,
+            slots,
+            transitions: Mutex::new(HashMap::new()),
+        });
+        transitions.insert(id, next);
+        next
+    }
+
+    /// The shape of an object which holds the variables `ids`, added in this order,
+    /// starting from the `root` shape.
+    pub fn from_ids(root: ShapeRef, ids: &[IdentId]) -> Option<Self> {
+        if ids.is_empty() {
+            return None;
+        }
+        Some(ids.iter().fold(root, |shape, id| shape.transition(*id)))
+    }
+}
@@ -18,10 +18,9 @@ macro_rules! expect_string {
             None => return Err($vm.error_argument("Must be a String.")),
         };
         let $var: &str = match &oref.kind {
-            ObjKind::String(RString::Str(s)) => s,
-            ObjKind::String(RString::Bytes(b)) => match String::from_utf8_lossy(b) {
-                std::borrow::Cow::Borrowed(s) => s,
-                std::borrow::Cow::Owned(_) => return Err($vm.error_argument("Must be a String.")),
+            ObjKind::String(rstr) => match std::str::from_utf8(rstr.as_bytes()) {
+                Ok(s) => s,
+                Err(_) => return Err($vm.error_argument("Must be a String.")),
             },
             _ => return Err($vm.error_argument("Must be a String.")),
         };
@@ -36,8 +35,7 @@ macro_rules! expect_bytes {
             None => return Err($vm.error_argument("Must be a String.")),
         };
         let $var = match &oref.kind {
-            ObjKind::String(RString::Str(s)) => s.as_bytes(),
-            ObjKind::String(RString::Bytes(b)) => b,
+            ObjKind::String(rstr) => rstr.as_bytes(),
             _ => return Err($vm.error_argument("Must be a String.")),
         };
     };
@@ -96,6 +94,7 @@ impl std::hash::Hash for Value {
                     }
                 }
                 ObjKind::Method(lhs) => lhs.inner().hash(state),
+                ObjKind::Time(lhs) => lhs.inner().hash(state),
                 _ => self.0.hash(state),
             },
         }
@@ -129,11 +128,8 @@ impl PartialEq for Value {
             (ObjKind::Range(lhs), ObjKind::Range(rhs)) => {
                 lhs.start == rhs.start && lhs.end == rhs.end && lhs.exclude == rhs.exclude
             }
-            (ObjKind::Hash(lhs), ObjKind::Hash(rhs)) => match (lhs.inner(), rhs.inner()) {
-                (HashInfo::Map(lhs), HashInfo::Map(rhs)) => *lhs == *rhs,
-                (HashInfo::IdentMap(lhs), HashInfo::IdentMap(rhs)) => *lhs == *rhs,
-                _ => false,
-            },
+            (ObjKind::Hash(lhs), ObjKind::Hash(rhs)) => lhs.inner() == rhs.inner(),
+            (ObjKind::Time(lhs), ObjKind::Time(rhs)) => *lhs.inner() == *rhs.inner(),
             (_, _) => false,
         }
     }
@@ -223,7 +219,7 @@ impl Value {
                 } else if self.is_packed_num() {
                     globals.builtins.float
                 } else if self.is_packed_symbol() {
-                    globals.builtins.object
+                    globals.builtins.symbol
                 } else {
                     globals.builtins.object
                 }
@@ -240,6 +236,7 @@ impl Value {
         match self.unpack() {
             RV::Integer(_) => globals.builtins.integer,
             RV::Float(_) => globals.builtins.float,
+            RV::Symbol(_) => globals.builtins.symbol,
             RV::Object(info) => info.search_class(),
             _ => globals.builtins.object,
         }
@@ -259,8 +256,8 @@ impl Value {
         }
     }
 
-    pub fn set_var(&mut self, id: IdentId, val: Value) {
-        self.as_object().set_var(id, val);
+    pub fn set_var(&self, globals: &Globals, id: IdentId, val: Value) {
+        self.as_object().set_var(globals, id, val);
     }
 
     pub fn get_var(&self, id: IdentId) -> Option<Value> {
@@ -292,6 +289,30 @@ impl Value {
             }
         }
     }
+
+    /// Get the module which has the method table entry for `id`,
+    /// searching this module and included modules.
+    pub fn get_method_owner(&self, id: IdentId) -> Option<Value> {
+        let cref = self.as_module().unwrap();
+        if cref.method_table.contains_key(&id) {
+            return Some(*self);
+        }
+        for v in &cref.include {
+            match v.get_method_owner(id) {
+                Some(owner) => return Some(owner),
+                None => {}
+            }
+        }
+        None
+    }
+
+    /// Whether `module` is included in this module, directly or by an included module.
+    pub fn includes_module(&self, module: Value) -> bool {
+        let cref = self.as_module().unwrap();
+        cref.include
+            .iter()
+            .any(|v| v.id() == module.id() || v.includes_module(module))
+    }
 }
 
 impl Value {
@@ -300,7 +321,7 @@ impl Value {
     }
 
     pub fn is_packed_flonum(&self) -> bool {
-        self.0 & 0b10 == 2
+        self.0 & 0b11 == 2
     }
 
     pub fn is_packed_num(&self) -> bool {
@@ -389,8 +410,7 @@ impl Value {
     pub fn as_bytes(&self) -> Option<&[u8]> {
         match self.as_rvalue() {
             Some(oref) => match &oref.kind {
-                ObjKind::String(RString::Str(s)) => Some(s.as_bytes()),
-                ObjKind::String(RString::Bytes(b)) => Some(b),
+                ObjKind::String(rstr) => Some(rstr.as_bytes()),
                 _ => None,
             },
             None => None,
@@ -400,7 +420,7 @@ impl Value {
     pub fn as_string(&self) -> Option<&String> {
         match self.as_rvalue() {
             Some(oref) => match &oref.kind {
-                ObjKind::String(RString::Str(s)) => Some(s),
+                ObjKind::String(rstr) => rstr.as_str(),
                 _ => None,
             },
             None => None,
@@ -512,6 +532,76 @@ impl Value {
         }
     }
 
+    pub fn as_binding(&self) -> Option<ContextRef> {
+        match self.is_object() {
+            Some(oref) => match oref.kind {
+                ObjKind::Binding(ctx) => Some(ctx),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    pub fn as_encoding(&self) -> Option<Encoding> {
+        match self.is_object() {
+            Some(oref) => match oref.kind {
+                ObjKind::Encoding(enc) => Some(enc),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    pub fn as_matchdata(&self) -> Option<MatchDataRef> {
+        match self.is_object() {
+            Some(oref) => match oref.kind {
+                ObjKind::MatchData(mref) => Some(mref),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    pub fn as_random(&self) -> Option<RandomRef> {
+        match self.is_object() {
+            Some(oref) => match oref.kind {
+                ObjKind::Random(rref) => Some(rref),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    pub fn as_io(&self) -> Option<IORef> {
+        match self.is_object() {
+            Some(oref) => match oref.kind {
+                ObjKind::IO(ioref) => Some(ioref),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    pub fn as_file_stat(&self) -> Option<StatRef> {
+        match self.is_object() {
+            Some(oref) => match oref.kind {
+                ObjKind::FileStat(sref) => Some(sref),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    pub fn as_time(&self) -> Option<TimeRef> {
+        match self.is_object() {
+            Some(oref) => match oref.kind {
+                ObjKind::Time(tref) => Some(tref),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
     pub fn as_method(&self) -> Option<MethodObjRef> {
         match self.is_object() {
             Some(oref) => match oref.kind {
@@ -626,6 +716,38 @@ impl Value {
         Value::object(RValue::new_bytes(globals, bytes))
     }
 
+    pub fn rstring(globals: &Globals, rstring: RString) -> Self {
+        Value::object(RValue::new_rstring(globals, rstring))
+    }
+
+    pub fn encoding(globals: &Globals, encoding: Encoding) -> Self {
+        Value::object(RValue::new_encoding(globals, encoding))
+    }
+
+    pub fn matchdata(globals: &Globals, matchdata: MatchDataRef) -> Self {
+        Value::object(RValue::new_matchdata(globals, matchdata))
+    }
+
+    pub fn random(globals: &Globals, random: RandomRef) -> Self {
+        Value::object(RValue::new_random(globals, random))
+    }
+
+    pub fn io(globals: &Globals, io: IORef) -> Self {
+        Value::object(RValue::new_io(globals, io))
+    }
+
+    pub fn file(globals: &Globals, io: IORef) -> Self {
+        Value::object(RValue::new_file(globals, io))
+    }
+
+    pub fn file_stat(globals: &Globals, stat: StatRef) -> Self {
+        Value::object(RValue::new_file_stat(globals, stat))
+    }
+
+    pub fn time(globals: &Globals, time: TimeRef) -> Self {
+        Value::object(RValue::new_time(globals, time))
+    }
+
     pub fn symbol(id: IdentId) -> Self {
         let id: u32 = id.into();
         Value((id as u64) << 32 | TAG_SYMBOL)
@@ -692,10 +814,55 @@ impl Value {
         Value::object(RValue::new_proc(globals, ProcRef::from(context)))
     }
 
-    pub fn method(globals: &Globals, name: IdentId, receiver: Value, method: MethodRef) -> Self {
+    pub fn lambda(globals: &Globals, context: ContextRef) -> Self {
+        Value::object(RValue::new_proc(globals, ProcRef::from_lambda(context)))
+    }
+
+    /// A Proc implemented by the builtin function `func`, which captures `captures`.
+    pub fn builtin_proc(
+        globals: &Globals,
+        func: BuiltinFunc,
+        captures: Vec<Value>,
+        arity: i64,
+        is_lambda: bool,
+    ) -> Self {
+        let builtin = BuiltinProc {
+            func,
+            captures,
+            arity,
+        };
+        Value::object(RValue::new_proc(
+            globals,
+            ProcRef::from_builtin(builtin, is_lambda),
+        ))
+    }
+
+    pub fn binding(globals: &Globals, context: ContextRef) -> Self {
+        Value::object(RValue::new_binding(globals, context))
+    }
+
+    pub fn method(
+        globals: &Globals,
+        name: IdentId,
+        receiver: Value,
+        method: MethodRef,
+        owner: Value,
+    ) -> Self {
         Value::object(RValue::new_method(
             globals,
-            MethodObjRef::from(name, receiver, method),
+            MethodObjRef::from(name, receiver, method, owner),
+        ))
+    }
+
+    pub fn unbound_method(
+        globals: &Globals,
+        name: IdentId,
+        method: MethodRef,
+        owner: Value,
+    ) -> Self {
+        Value::object(RValue::new_unbound_method(
+            globals,
+            MethodObjRef::from(name, Value::nil(), method, owner),
         ))
     }
 
@@ -745,6 +912,7 @@ impl Value {
                 lhs.start.equal(rhs.start) && lhs.end.equal(rhs.end) && lhs.exclude == rhs.exclude
             }
             (ObjKind::Hash(lhs), ObjKind::Hash(rhs)) => lhs.inner() == rhs.inner(),
+            (ObjKind::Time(lhs), ObjKind::Time(rhs)) => *lhs.inner() == *rhs.inner(),
             (_, _) => false,
         }
     }
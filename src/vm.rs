@@ -1,17 +1,30 @@
 mod args;
+pub mod bytecode;
 mod class;
 mod codegen;
 mod context;
+mod coverage;
+mod decode;
+pub mod disasm;
 mod executor;
+mod jit;
 mod method;
+mod optimizer;
 #[cfg(feature = "perf")]
 #[cfg_attr(tarpaulin, skip)]
 mod perf;
+mod profile;
 pub mod vm_inst;
 
 pub use args::*;
+pub use bytecode::CacheLocation;
 pub use class::*;
-pub use codegen::{Codegen, ISeq, ISeqPos};
+pub use codegen::{Codegen, CompileOption, ISeq, ISeqPos};
 pub use context::*;
+pub use coverage::*;
+pub use decode::DecodedISeq;
 pub use executor::*;
+pub use jit::{JitCode, JitCodeRef, JitConfig, JitExit};
 pub use method::*;
+pub use optimizer::Optimizer;
+pub use profile::{ProfileFormat, Profiler};
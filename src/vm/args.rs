@@ -71,6 +71,49 @@ impl Args {
             ArgsArray::Vec(v) => v,
         }
     }
+
+    /// Check keyword arguments for the keyword parameters in `params`.
+    /// Missing required keywords, and unknown keywords when no `**rest` parameter exists,
+    /// raise ArgumentError.
+    pub fn check_keywords(&self, vm: &mut VM, params: &ISeqParams) -> Result<(), RubyError> {
+        let keyword = self.kw_arg.and_then(|kw| kw.as_hash());
+        let missing: Vec<Value> = params
+            .req_keyword_params
+            .iter()
+            .map(|id| Value::symbol(*id))
+            .filter(|key| match keyword {
+                Some(hash) => !hash.contains_key(*key),
+                None => true,
+            })
+            .collect();
+        if !missing.is_empty() {
+            return Err(keyword_error(vm, "missing", missing));
+        }
+        if params.kwrest_param.is_some() {
+            return Ok(());
+        }
+        let unknown: Vec<Value> = match keyword {
+            Some(hash) => hash
+                .iter()
+                .map(|(k, _)| k)
+                .filter(|k| match k.as_symbol() {
+                    Some(id) => !params.keyword_params.contains_key(&id),
+                    None => true,
+                })
+                .collect(),
+            None => vec![],
+        };
+        if !unknown.is_empty() {
+            return Err(keyword_error(vm, "unknown", unknown));
+        }
+        Ok(())
+    }
+}
+
+fn keyword_error(vm: &mut VM, kind: &str, keys: Vec<Value>) -> RubyError {
+    let plural = if keys.len() > 1 { "s" } else { "" };
+    let keys: Vec<String> = keys.into_iter().map(|k| vm.val_inspect(k)).collect();
+    vm.error_argument(format!("{} keyword{}: {}", kind, plural, keys.join(", ")))
 }
 
 impl Index<usize> for Args {
@@ -0,0 +1,719 @@
+//! Binary format for compiled instruction sequences.
+//!
+//! A bytecode image holds the top-level ISeq of a source file together with
+//! every method, class body and block ISeq reachable from it.
+//!
+//! ```text
+//! header      magic "RRBC", format version: u32, source hash: u64
+//! source      path: str, code: str
+//! idents      count: u32, (internal id: u32, name: str) ...
+//! case maps   count: u32, (entries: u32, (key: i64, disp: i32) ...) ...
+//! iseqs       count: u32, iseq ...
+//! ```
+//!
+//! All integers are little endian, and a `str` is a u32 length followed by UTF-8 bytes.
+//! The internal id of an identifier is 0 unless it can not be looked up by name.
+//! Operands which depend on the state of the VM (identifiers, method refs,
+//! case dispatch maps and inline cache slots) are rewritten to indices into
+//! the tables of the image, and are mapped back when the image is loaded.
+use crate::error::ParseErrKind;
+use crate::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use vm_inst::*;
+
+const MAGIC: &[u8; 4] = b"RRBC";
+const FORMAT_VERSION: u32 = 5;
+
+/// Where `VM::parse_program` keeps bytecode caches of source files.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CacheLocation {
+    /// `foo.rbc` next to `foo.rb`.
+    SourceDir,
+    /// A file named after the hash of the source path in the given directory.
+    Dir(PathBuf),
+}
+
+impl CacheLocation {
+    pub fn cache_path(&self, source_path: &Path) -> PathBuf {
+        match self {
+            CacheLocation::SourceDir => source_path.with_extension("rbc"),
+            CacheLocation::Dir(dir) => {
+                let name = source_path.to_string_lossy();
+                dir.join(format!("{:016x}.rbc", source_hash(&name)))
+            }
+        }
+    }
+}
+
+/// FNV-1a hash of the source code, which is stable across builds and platforms.
+pub fn source_hash(program: &str) -> u64 {
+    program.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Ident,
+    Method,
+    InlineCache,
+    ConstCache,
+    IvarCache,
+    CaseMap,
+}
+
+/// Offsets and kinds of the operands of `inst` which must be remapped.
+fn operands(inst: u8) -> &'static [(usize, Operand)] {
+    match inst {
+        Inst::PUSH_STRING
+        | Inst::PUSH_SYMBOL
+        | Inst::SET_CONST
+        | Inst::GET_CONST_TOP
+        | Inst::GET_SCOPE
+        | Inst::GET_GVAR
+        | Inst::SET_GVAR => &[(1, Operand::Ident)],
+        Inst::GET_CONST => &[(1, Operand::Ident), (5, Operand::ConstCache)],
+        Inst::GET_IVAR | Inst::SET_IVAR => &[(1, Operand::Ident), (5, Operand::IvarCache)],
+        Inst::IVAR_ADDI => &[(1, Operand::Ident), (9, Operand::IvarCache)],
+        Inst::ADD | Inst::SUB | Inst::MUL | Inst::DIV | Inst::SHL => &[(1, Operand::InlineCache)],
+        Inst::SEND | Inst::SEND_SELF => &[
+            (1, Operand::Ident),
+            (9, Operand::InlineCache),
+            (13, Operand::Method),
+        ],
+        Inst::CREATE_PROC => &[(1, Operand::Method)],
+        Inst::DEF_METHOD | Inst::DEF_SMETHOD => &[(1, Operand::Ident), (5, Operand::Method)],
+        Inst::DEF_CLASS => &[(2, Operand::Ident), (6, Operand::Method)],
+        Inst::OPT_CASE => &[(1, Operand::CaseMap)],
+        _ => &[],
+    }
+}
+
+fn read32(iseq: &ISeq, pos: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&iseq[pos..pos + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn write32(iseq: &mut ISeq, pos: usize, val: u32) {
+    iseq[pos..pos + 4].copy_from_slice(&val.to_le_bytes());
+}
+
+fn get_iseq(globals: &Globals, method: MethodRef) -> ISeqRef {
+    match globals.get_method_info(method) {
+        MethodInfo::RubyFunc { iseq } => *iseq,
+        _ => panic!("Bytecode: Illegal methodref."),
+    }
+}
+
+/// `method` followed by every method, class body and block ISeq defined in it.
+pub fn reachable_iseqs(globals: &Globals, method: MethodRef) -> Vec<MethodRef> {
+    let mut methods = vec![method];
+    let mut i = 0;
+    while i < methods.len() {
+        let info = get_iseq(globals, methods[i]);
+        let iseq = &info.iseq;
+        let mut pc = 0;
+        while pc < iseq.len() {
+            let inst = iseq[pc];
+            for (offset, operand) in operands(inst) {
+                if *operand == Operand::Method {
+                    let method = MethodRef::from(read32(iseq, pc + offset));
+                    if !method.is_none() && !methods.contains(&method) {
+                        methods.push(method);
+                    }
+                }
+            }
+            pc += Inst::inst_size(inst);
+        }
+        i += 1;
+    }
+    methods
+}
+
+//----------------------------------------------------------------------------------
+
+/// Serialize the ISeq of `method` and all ISeqs reachable from it.
+pub fn dump(globals: &Globals, method: MethodRef, path: &Path, program: &str) -> Vec<u8> {
+    let mut writer = Writer::new();
+    writer.method_index(method);
+    let mut i = 0;
+    while i < writer.methods.len() {
+        let iseq = get_iseq(globals, writer.methods[i]);
+        writer.write_iseq(iseq);
+        i += 1;
+    }
+
+    let mut buf = Buf::new();
+    buf.bytes(MAGIC);
+    buf.u32(FORMAT_VERSION);
+    buf.u64(source_hash(program));
+    buf.str(&path.to_string_lossy());
+    buf.str(program);
+    buf.u32(writer.idents.len() as u32);
+    for id in &writer.idents {
+        buf.u32(if id.is_internal() { (*id).into() } else { 0 });
+        buf.str(globals.get_ident_name(*id));
+    }
+    buf.u32(writer.case_maps.len() as u32);
+    for map_id in &writer.case_maps {
+        let mut entries: Vec<(i64, i32)> = globals
+            .get_case_dispatch_map(*map_id)
+            .iter()
+            .map(|(k, v)| (k.as_fixnum().unwrap(), *v))
+            .collect();
+        entries.sort();
+        buf.u32(entries.len() as u32);
+        for (k, v) in entries {
+            buf.u64(k as u64);
+            buf.u32(v as u32);
+        }
+    }
+    buf.u32(writer.methods.len() as u32);
+    buf.bytes(&writer.body.0);
+    buf.0
+}
+
+struct Buf(Vec<u8>);
+
+impl Buf {
+    fn new() -> Self {
+        Buf(vec![])
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, val: u8) {
+        self.0.push(val);
+    }
+
+    fn u32(&mut self, val: u32) {
+        self.bytes(&val.to_le_bytes());
+    }
+
+    fn u64(&mut self, val: u64) {
+        self.bytes(&val.to_le_bytes());
+    }
+
+    fn str(&mut self, string: &str) {
+        self.u32(string.len() as u32);
+        self.bytes(string.as_bytes());
+    }
+}
+
+struct Writer {
+    body: Buf,
+    idents: Vec<IdentId>,
+    ident_map: HashMap<IdentId, u32>,
+    methods: Vec<MethodRef>,
+    method_map: HashMap<MethodRef, u32>,
+    case_maps: Vec<u32>,
+}
+
+impl Writer {
+    fn new() -> Self {
+        Writer {
+            body: Buf::new(),
+            idents: vec![],
+            ident_map: HashMap::new(),
+            methods: vec![],
+            method_map: HashMap::new(),
+            case_maps: vec![],
+        }
+    }
+
+    fn ident_index(&mut self, id: IdentId) -> u32 {
+        match self.ident_map.get(&id) {
+            Some(i) => *i,
+            None => {
+                let i = self.idents.len() as u32;
+                self.idents.push(id);
+                self.ident_map.insert(id, i);
+                i
+            }
+        }
+    }
+
+    /// Index of `method` in the image, or 0 for no method.
+    fn method_index(&mut self, method: MethodRef) -> u32 {
+        if method.is_none() {
+            return 0;
+        }
+        match self.method_map.get(&method) {
+            Some(i) => *i,
+            None => {
+                self.methods.push(method);
+                let i = self.methods.len() as u32;
+                self.method_map.insert(method, i);
+                i
+            }
+        }
+    }
+
+    fn ident(&mut self, id: IdentId) {
+        let i = self.ident_index(id);
+        self.body.u32(i);
+    }
+
+    fn lvar(&mut self, lvar: Option<LvarId>) {
+        match lvar {
+            Some(lvar) => self.body.u32(lvar.as_u32() + 1),
+            None => self.body.u32(0),
+        }
+    }
+
+    fn write_iseq(&mut self, info: ISeqRef) {
+        let params = &info.params;
+        self.body.u32(params.req_params as u32);
+        self.body.u32(params.opt_params as u32);
+        self.body.u8(params.rest_param as u8);
+        self.body.u32(params.post_params as u32);
+        self.body.u8(params.block_param as u8);
+        self.body.u32(params.param_ident.len() as u32);
+        for id in &params.param_ident {
+            self.ident(*id);
+        }
+        let mut keyword_params: Vec<(IdentId, LvarId)> = params
+            .keyword_params
+            .iter()
+            .map(|(k, v)| (*k, *v))
+            .collect();
+        keyword_params.sort_by_key(|(_, lvar)| lvar.as_usize());
+        self.body.u32(keyword_params.len() as u32);
+        for (id, lvar) in keyword_params {
+            self.ident(id);
+            self.lvar(Some(lvar));
+        }
+        self.body.u32(params.req_keyword_params.len() as u32);
+        for id in &params.req_keyword_params {
+            self.ident(*id);
+        }
+        self.lvar(params.kwrest_param);
+
+        let mut iseq = info.iseq.clone();
+        let mut pc = 0;
+        while pc < iseq.len() {
+            let inst = iseq[pc];
+            for (offset, operand) in operands(inst) {
+                let pos = pc + offset;
+                let val = read32(&iseq, pos);
+                let new_val = match operand {
+                    Operand::Ident => self.ident_index(IdentId::from(val)),
+                    Operand::Method => self.method_index(MethodRef::from(val)),
+                    Operand::InlineCache | Operand::ConstCache | Operand::IvarCache => 0,
+                    Operand::CaseMap => {
+                        self.case_maps.push(val);
+                        self.case_maps.len() as u32 - 1
+                    }
+                };
+                write32(&mut iseq, pos, new_val);
+            }
+            pc += Inst::inst_size(inst);
+        }
+        self.body.u32(iseq.len() as u32);
+        self.body.bytes(&iseq);
+
+        let mut lvar_table: Vec<(IdentId, LvarId)> =
+            info.lvar.table().iter().map(|(k, v)| (*k, *v)).collect();
+        lvar_table.sort_by_key(|(_, lvar)| lvar.as_usize());
+        self.body.u32(lvar_table.len() as u32);
+        for (id, lvar) in lvar_table {
+            self.ident(id);
+            self.lvar(Some(lvar));
+        }
+        self.lvar(*info.lvar.block());
+        self.body.u32(info.lvars as u32);
+
+        self.body.u32(info.iseq_sourcemap.len() as u32);
+        for (pos, loc) in &info.iseq_sourcemap {
+            self.body.u32(pos.to_usize() as u32);
+            self.body.u32(loc.0);
+            self.body.u32(loc.1);
+        }
+        match &info.kind {
+            ISeqKind::Other => self.body.u8(0),
+            ISeqKind::Method(id) => {
+                self.body.u8(1);
+                self.ident(*id);
+            }
+            ISeqKind::Block(method) => {
+                self.body.u8(2);
+                let i = self.method_index(*method);
+                self.body.u32(i);
+            }
+        }
+        self.body.u32(info.loc.0);
+        self.body.u32(info.loc.1);
+        self.body.u8(info.tailcall as u8);
+    }
+}
+
+//----------------------------------------------------------------------------------
+
+/// Load a bytecode image, returning the methodref of its top-level ISeq.
+///
+/// When `expected_hash` is given, images compiled from a different source are rejected.
+pub fn load(
+    globals: &mut Globals,
+    bytes: &[u8],
+    expected_hash: Option<u64>,
+) -> Result<MethodRef, RubyError> {
+    let mut reader = Reader::new(bytes);
+    if reader.bytes(4)? != MAGIC {
+        return Err(load_error("Not a bytecode file."));
+    }
+    if reader.u32()? != FORMAT_VERSION {
+        return Err(load_error("Unsupported bytecode version."));
+    }
+    let hash = reader.u64()?;
+    if let Some(expected) = expected_hash {
+        if hash != expected {
+            return Err(load_error("Bytecode is out of date."));
+        }
+    }
+    let path = PathBuf::from(reader.str()?);
+    let program = reader.str()?;
+    let source_info = SourceInfoRef::new(SourceInfo {
+        path,
+        code: program.chars().collect(),
+        first_line: 1,
+    });
+
+    let len = reader.u32()?;
+    let mut idents = vec![];
+    for _ in 0..len {
+        let internal = reader.u32()?;
+        let name = reader.str()?;
+        let id = if internal == 0 {
+            globals.get_ident_id(name)
+        } else {
+            let id = IdentId::from(internal);
+            if !id.is_internal() {
+                return Err(broken());
+            }
+            id
+        };
+        idents.push(id);
+    }
+
+    let len = reader.u32()?;
+    let mut case_maps = vec![];
+    for _ in 0..len {
+        let map_id = globals.new_case_dispatch_map();
+        let entries = reader.u32()?;
+        for _ in 0..entries {
+            let k = reader.u64()? as i64;
+            let v = reader.u32()? as i32;
+            globals
+                .get_mut_case_dispatch_map(map_id)
+                .insert(Value::fixnum(k), v);
+        }
+        case_maps.push(map_id);
+    }
+
+    let len = reader.u32()?;
+    if len == 0 {
+        return Err(load_error("Bytecode has no instruction sequence."));
+    }
+    let methods: Vec<MethodRef> = (0..len).map(|_| globals.new_method()).collect();
+    let mut loader = Loader {
+        reader,
+        idents,
+        methods,
+        case_maps,
+    };
+    for i in 0..len as usize {
+        let info = loader.read_iseq(globals, i, source_info)?;
+        globals.set_method(
+            loader.methods[i],
+            MethodInfo::RubyFunc {
+                iseq: ISeqRef::new(info),
+            },
+        );
+    }
+    Ok(loader.methods[0])
+}
+
+fn load_error(msg: &str) -> RubyError {
+    RubyError::new_parse_err(
+        ParseErrKind::LoadError(msg.to_string()),
+        SourceInfoRef::empty(),
+        0,
+        Loc(0, 0),
+    )
+}
+
+fn broken() -> RubyError {
+    load_error("Bytecode is broken.")
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], RubyError> {
+        if self.bytes.len() - self.pos < len {
+            return Err(broken());
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, RubyError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, RubyError> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, RubyError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn str(&mut self) -> Result<String, RubyError> {
+        let len = self.u32()? as usize;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| broken())
+    }
+}
+
+struct Loader<'a> {
+    reader: Reader<'a>,
+    idents: Vec<IdentId>,
+    methods: Vec<MethodRef>,
+    case_maps: Vec<u32>,
+}
+
+impl<'a> Loader<'a> {
+    fn ident(&mut self) -> Result<IdentId, RubyError> {
+        let i = self.reader.u32()?;
+        self.ident_at(i)
+    }
+
+    fn ident_at(&self, i: u32) -> Result<IdentId, RubyError> {
+        self.idents.get(i as usize).cloned().ok_or_else(broken)
+    }
+
+    fn method_at(&self, i: u32) -> Result<MethodRef, RubyError> {
+        if i == 0 {
+            return Ok(MethodRef::from(0));
+        }
+        self.methods.get(i as usize - 1).cloned().ok_or_else(broken)
+    }
+
+    fn lvar(&mut self) -> Result<Option<LvarId>, RubyError> {
+        match self.reader.u32()? {
+            0 => Ok(None),
+            i => Ok(Some(LvarId::from_u32(i - 1))),
+        }
+    }
+
+    fn usize(&mut self) -> Result<usize, RubyError> {
+        Ok(self.reader.u32()? as usize)
+    }
+
+    fn bool(&mut self) -> Result<bool, RubyError> {
+        Ok(self.reader.u8()? != 0)
+    }
+
+    fn read_iseq(
+        &mut self,
+        globals: &mut Globals,
+        index: usize,
+        source_info: SourceInfoRef,
+    ) -> Result<ISeqInfo, RubyError> {
+        let req_params = self.usize()?;
+        let opt_params = self.usize()?;
+        let rest_param = self.bool()?;
+        let post_params = self.usize()?;
+        let block_param = self.bool()?;
+        let mut param_ident = vec![];
+        for _ in 0..self.reader.u32()? {
+            param_ident.push(self.ident()?);
+        }
+        let mut keyword_params = HashMap::new();
+        for _ in 0..self.reader.u32()? {
+            let id = self.ident()?;
+            let lvar = self.lvar()?.ok_or_else(broken)?;
+            keyword_params.insert(id, lvar);
+        }
+        let mut req_keyword_params = vec![];
+        for _ in 0..self.reader.u32()? {
+            req_keyword_params.push(self.ident()?);
+        }
+        let kwrest_param = self.lvar()?;
+
+        let len = self.usize()?;
+        let mut iseq = self.reader.bytes(len)?.to_vec();
+        let mut pc = 0;
+        while pc < iseq.len() {
+            let inst = iseq[pc];
+            let size = Inst::inst_size(inst);
+            if pc + size > iseq.len() {
+                return Err(broken());
+            }
+            for (offset, operand) in operands(inst) {
+                let pos = pc + offset;
+                let val = read32(&iseq, pos);
+                let new_val = match operand {
+                    Operand::Ident => self.ident_at(val)?.into(),
+                    Operand::Method => self.method_at(val)?.into(),
+                    Operand::InlineCache => globals.add_inline_cache_entry(),
+                    Operand::ConstCache => globals.add_const_cache_entry(),
+                    Operand::IvarCache => globals.add_ivar_cache_entry(),
+                    Operand::CaseMap => *self.case_maps.get(val as usize).ok_or_else(broken)?,
+                };
+                write32(&mut iseq, pos, new_val);
+            }
+            pc += size;
+        }
+
+        let mut table = HashMap::new();
+        for _ in 0..self.reader.u32()? {
+            let id = self.ident()?;
+            let lvar = self.lvar()?.ok_or_else(broken)?;
+            table.insert(id, lvar);
+        }
+        let block = self.lvar()?;
+        let lvar = LvarCollector::from_table(table, block);
+        let lvars = self.usize()?;
+
+        let mut iseq_sourcemap = vec![];
+        for _ in 0..self.reader.u32()? {
+            let pos = ISeqPos::from(self.usize()?);
+            let loc = Loc(self.reader.u32()?, self.reader.u32()?);
+            iseq_sourcemap.push((pos, loc));
+        }
+        let kind = match self.reader.u8()? {
+            0 => ISeqKind::Other,
+            1 => ISeqKind::Method(self.ident()?),
+            2 => {
+                let i = self.reader.u32()?;
+                ISeqKind::Block(self.method_at(i)?)
+            }
+            _ => return Err(broken()),
+        };
+        let loc = Loc(self.reader.u32()?, self.reader.u32()?);
+        let tailcall = self.bool()?;
+
+        let mut info = ISeqInfo::new(
+            self.methods[index],
+            req_params,
+            opt_params,
+            rest_param,
+            post_params,
+            block_param,
+            param_ident,
+            keyword_params,
+            req_keyword_params,
+            kwrest_param,
+            iseq,
+            lvar,
+            iseq_sourcemap,
+            source_info,
+            kind,
+            loc,
+        );
+        info.lvars = lvars;
+        info.tailcall = tailcall;
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+    use std::path::PathBuf;
+
+    fn round_trip(program: &str) -> VMResult {
+        let mut vm = VM::new();
+        let bytes = vm
+            .compile_to_bytecode(PathBuf::from("test.rb"), program)
+            .unwrap();
+        let mut vm = VM::new();
+        vm.run_bytecode(&bytes, None)
+    }
+
+    #[test]
+    fn bytecode_round_trip() {
+        let program = r#"
+        class Foo
+            attr_accessor :x
+            def initialize(x)
+                @x = x
+            end
+            def add(a, b = 2, *rest, c:, d: 4, **kw, &blk)
+                @x + a + b + rest.size + c + d + kw.size + blk.call
+            end
+        end
+        def classify(n)
+            case n
+            when 1 then :one
+            when 2, 3 then :few
+            else :many
+            end
+        end
+        foo = Foo.new(100)
+        assert(116, foo.add(1, 2, 3, c: 3, e: 5) { 4 })
+        assert([:one, :few, :few, :many], [1, 2, 3, 4].map { |n| classify(n) })
+        sum = 0
+        [1, 2, 3].each { |i| sum += i * 2 }
+        assert(12, sum)
+        assert("foo bar", "foo" + " " + "bar")
+        $g = 7
+        assert(7 << 1, $g * 2)
+        def fwd(...)
+            Foo.new(100).add(...)
+        end
+        assert(116, fwd(1, 2, 3, c: 3, e: 5) { 4 })
+        "#;
+        round_trip(program).unwrap();
+    }
+
+    #[test]
+    fn bytecode_keeps_tailcall() {
+        let program = r#"
+        def count(n)
+            return :done if n == 0
+            count(n - 1)
+        end
+        assert(:done, count(100))
+        "#;
+        let mut vm = VM::new();
+        vm.globals.compile_option.tailcall_optimization = true;
+        let bytes = vm
+            .compile_to_bytecode(PathBuf::from("test.rb"), program)
+            .unwrap();
+        let mut vm = VM::new();
+        vm.globals.max_stack_depth = 50;
+        vm.run_bytecode(&bytes, None).unwrap();
+    }
+
+    #[test]
+    fn bytecode_rejects_stale_cache() {
+        let mut vm = VM::new();
+        let path = PathBuf::from("test.rb");
+        let bytes = vm.compile_to_bytecode(path, "1 + 2").unwrap();
+        let hash = super::source_hash("1 + 3");
+        assert!(super::load(&mut vm.globals, &bytes, Some(hash)).is_err());
+        let hash = super::source_hash("1 + 2");
+        assert!(super::load(&mut vm.globals, &bytes, Some(hash)).is_ok());
+        assert!(super::load(&mut vm.globals, &bytes[..bytes.len() - 1], None).is_err());
+        assert!(super::load(&mut vm.globals, b"RUBY", None).is_err());
+    }
+}
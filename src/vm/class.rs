@@ -1,10 +1,13 @@
 use crate::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Clone)]
 pub struct ClassInfo {
     pub name: Option<IdentId>,
     pub method_table: MethodTable,
+    /// Visibility of the entries in `method_table`. Public methods are not registered.
+    pub visibility: HashMap<IdentId, Visibility>,
+    pub private_constants: HashSet<IdentId>,
     pub superclass: Value,
     pub include: Vec<Value>,
     pub is_singleton: bool,
@@ -15,11 +18,32 @@ impl ClassInfo {
         ClassInfo {
             name: name.into(),
             method_table: HashMap::new(),
+            visibility: HashMap::new(),
+            private_constants: HashSet::new(),
             superclass,
             include: vec![],
             is_singleton: false,
         }
     }
+
+    pub fn get_visibility(&self, id: IdentId) -> Visibility {
+        match self.visibility.get(&id) {
+            Some(visibility) => *visibility,
+            None => Visibility::Public,
+        }
+    }
+
+    pub fn set_visibility(&mut self, id: IdentId, visibility: Visibility) {
+        if visibility == Visibility::Public {
+            self.visibility.remove(&id);
+        } else {
+            self.visibility.insert(id, visibility);
+        }
+    }
+
+    pub fn is_private_constant(&self, id: IdentId) -> bool {
+        self.private_constants.contains(&id)
+    }
 }
 
 pub type ClassRef = Ref<ClassInfo>;
@@ -1,6 +1,6 @@
 use super::vm_inst::*;
 use crate::error::{ParseErrKind, RubyError, RuntimeErrKind};
-use crate::parse::node::{BinOp, Node, NodeKind, UnOp};
+use crate::parse::node::{BinOp, Node, NodeKind, SendArgs, UnOp};
 use crate::*;
 use std::collections::HashMap;
 
@@ -15,6 +15,13 @@ pub struct Codegen {
     pub source_info: SourceInfoRef,
 }
 
+/// Options of the compiler, which are set by `RubyVM::InstructionSequence.compile_option=`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CompileOption {
+    /// A method call in tail position replaces the frame of the caller.
+    pub tailcall_optimization: bool,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 struct LoopInfo {
     state: LoopState,
@@ -165,10 +172,27 @@ impl Codegen {
         self.save_loc(iseq, self.loc)
     }
 
+    /// Save the location of a branch of a conditional, unless the branch is omitted.
+    fn save_branch_loc(&mut self, iseq: &mut ISeq, node: &Node) {
+        match &node.kind {
+            NodeKind::CompStmt(nodes) if nodes.is_empty() => {}
+            _ => self.save_loc(iseq, node.loc()),
+        }
+    }
+
     pub fn context_push(&mut self, lvar: LvarCollector) {
         self.context_stack
             .push(Context::from(lvar.clone_table(), ContextKind::Method));
     }
+
+    pub fn block_context_push(&mut self, lvar: LvarCollector) {
+        self.context_stack
+            .push(Context::from(lvar.clone_table(), ContextKind::Block));
+    }
+
+    pub fn method_push(&mut self, method: MethodRef) {
+        self.method_stack.push(method);
+    }
 }
 
 impl Codegen {
@@ -310,10 +334,60 @@ impl Codegen {
         iseq.push(Inst::MRETURN);
     }
 
-    fn gen_yield(&mut self, iseq: &mut ISeq, args_num: usize) {
+    fn gen_yield(&mut self, iseq: &mut ISeq, args_num: usize, kw_flag: bool) {
         self.save_cur_loc(iseq);
         iseq.push(Inst::YIELD);
         Codegen::push32(iseq, args_num as u32);
+        Codegen::push32(iseq, if kw_flag { 1 } else { 0 });
+    }
+
+    /// Generate a hash of keyword arguments.
+    /// `key: value` pairs and `**hash` arguments are evaluated from left to right,
+    /// and merged by MERGE_KW so that the latter one of the same keys wins.
+    /// Returns true if keyword arguments exist.
+    fn gen_kw_args(
+        &mut self,
+        globals: &mut Globals,
+        iseq: &mut ISeq,
+        send_args: &SendArgs,
+    ) -> Result<bool, RubyError> {
+        if send_args.kw_args.is_empty() && send_args.kw_rest.is_empty() {
+            return Ok(false);
+        }
+        let mut done = 0;
+        for (i, (pos, hash)) in send_args.kw_rest.iter().enumerate() {
+            if i == 0 || done < *pos {
+                self.gen_kw_pairs(globals, iseq, &send_args.kw_args[done..*pos], i != 0)?;
+                done = *pos;
+            }
+            self.gen(globals, iseq, hash, true)?;
+            iseq.push(Inst::MERGE_KW);
+        }
+        let rest = &send_args.kw_args[done..];
+        if send_args.kw_rest.is_empty() || !rest.is_empty() {
+            self.gen_kw_pairs(globals, iseq, rest, !send_args.kw_rest.is_empty())?;
+        }
+        Ok(true)
+    }
+
+    /// Generate a hash of `key: value` pairs, which is merged into the hash on the stack
+    /// if `merge` is true.
+    fn gen_kw_pairs(
+        &mut self,
+        globals: &mut Globals,
+        iseq: &mut ISeq,
+        pairs: &[(IdentId, Node)],
+        merge: bool,
+    ) -> Result<(), RubyError> {
+        for (id, default) in pairs {
+            self.gen_symbol(iseq, *id);
+            self.gen(globals, iseq, default, true)?;
+        }
+        self.gen_create_hash(iseq, pairs.len());
+        if merge {
+            iseq.push(Inst::MERGE_KW);
+        }
+        Ok(())
     }
 
     fn gen_opt_case(&self, iseq: &mut ISeq, map_id: u32) -> ISeqPos {
@@ -371,22 +445,32 @@ impl Codegen {
         None
     }
 
-    fn gen_get_instance_var(&mut self, iseq: &mut ISeq, id: IdentId) {
+    fn gen_get_instance_var(&mut self, globals: &mut Globals, iseq: &mut ISeq, id: IdentId) {
         iseq.push(Inst::GET_IVAR);
         Codegen::push32(iseq, id.into());
+        Codegen::push32(iseq, globals.add_ivar_cache_entry());
     }
 
-    fn gen_set_instance_var(&mut self, iseq: &mut ISeq, id: IdentId) {
+    fn gen_set_instance_var(&mut self, globals: &mut Globals, iseq: &mut ISeq, id: IdentId) {
         iseq.push(Inst::SET_IVAR);
         Codegen::push32(iseq, id.into());
+        Codegen::push32(iseq, globals.add_ivar_cache_entry());
     }
 
-    fn gen_ivar_addi(&mut self, iseq: &mut ISeq, id: IdentId, val: u32, use_value: bool) {
+    fn gen_ivar_addi(
+        &mut self,
+        globals: &mut Globals,
+        iseq: &mut ISeq,
+        id: IdentId,
+        val: u32,
+        use_value: bool,
+    ) {
         iseq.push(Inst::IVAR_ADDI);
         Codegen::push32(iseq, id.into());
         Codegen::push32(iseq, val);
+        Codegen::push32(iseq, globals.add_ivar_cache_entry());
         if use_value {
-            self.gen_get_instance_var(iseq, id);
+            self.gen_get_instance_var(globals, iseq, id);
         }
     }
 
@@ -405,10 +489,11 @@ impl Codegen {
         Codegen::push32(iseq, id.into());
     }
 
-    fn gen_get_const(&mut self, iseq: &mut ISeq, id: IdentId) {
+    fn gen_get_const(&mut self, globals: &mut Globals, iseq: &mut ISeq, id: IdentId) {
         self.save_cur_loc(iseq);
         iseq.push(Inst::GET_CONST);
         Codegen::push32(iseq, id.into());
+        Codegen::push32(iseq, globals.add_const_cache_entry());
     }
 
     fn gen_get_const_top(&mut self, iseq: &mut ISeq, id: IdentId) {
@@ -485,7 +570,7 @@ impl Codegen {
                 self.gen_push_nil(iseq);
                 self.gen_set_const(iseq, *id);
             }
-            NodeKind::InstanceVar(id) => self.gen_set_instance_var(iseq, *id),
+            NodeKind::InstanceVar(id) => self.gen_set_instance_var(globals, iseq, *id),
             NodeKind::GlobalVar(id) => self.gen_set_global_var(iseq, *id),
             NodeKind::Scope(parent, id) => {
                 self.gen(globals, iseq, parent, true)?;
@@ -539,6 +624,7 @@ impl Codegen {
         nodes: &[Node],
         use_value: bool,
     ) -> Result<(), RubyError> {
+        // The location of each statement marks where a line starts, for coverage and profiling.
         match nodes.len() {
             0 => {
                 if use_value {
@@ -546,12 +632,15 @@ impl Codegen {
                 }
             }
             1 => {
+                self.save_loc(iseq, nodes[0].loc());
                 self.gen(globals, iseq, &nodes[0], use_value)?;
             }
             _ => {
                 for i in 0..nodes.len() - 1 {
+                    self.save_loc(iseq, nodes[i].loc());
                     self.gen(globals, iseq, &nodes[i], false)?;
                 }
+                self.save_loc(iseq, nodes[nodes.len() - 1].loc());
                 self.gen(globals, iseq, &nodes[nodes.len() - 1], use_value)?;
             }
         }
@@ -585,6 +674,8 @@ impl Codegen {
         let mut block_param = false;
         let mut param_ident = vec![];
         let mut keyword_params = HashMap::new();
+        let mut req_keyword_params = vec![];
+        let mut kwrest_param = None;
         let mut iseq = ISeq::new();
 
         self.context_stack
@@ -623,9 +714,13 @@ impl Codegen {
                             self.gen_set_local(&mut iseq, *id);
                             Codegen::write_disp_from_cur(&mut iseq, src1);
                         }
-                        None => {}
+                        None => req_keyword_params.push(*id),
                     }
                 }
+                NodeKind::KWRestParam(id) => {
+                    param_ident.push(*id);
+                    kwrest_param = Some(LvarId::from_usize(lvar_id));
+                }
                 NodeKind::BlockParam(id) => {
                     param_ident.push(*id);
                     block_param = true;
@@ -638,34 +733,40 @@ impl Codegen {
         let context = self.context_stack.pop().unwrap();
         let iseq_sourcemap = context.iseq_sourcemap;
         self.gen_end(&mut iseq);
+        let (iseq, iseq_sourcemap) = Optimizer::optimize(globals, iseq, iseq_sourcemap);
         self.loc = save_loc;
 
-        let info = MethodInfo::RubyFunc {
-            iseq: ISeqRef::new(ISeqInfo::new(
-                methodref,
-                req_params,
-                opt_params,
-                rest_param,
-                post_params,
-                block_param,
-                param_ident,
-                keyword_params,
-                iseq,
-                lvar_collector.clone(),
-                iseq_sourcemap,
-                self.source_info,
-                match kind {
-                    ContextKind::Block => ISeqKind::Block(*self.method_stack.last().unwrap()),
-                    ContextKind::Eval => ISeqKind::Other,
-                    ContextKind::Method => {
-                        if name.is_some() {
-                            ISeqKind::Method(name.unwrap())
-                        } else {
-                            ISeqKind::Other
-                        }
+        let mut info = ISeqInfo::new(
+            methodref,
+            req_params,
+            opt_params,
+            rest_param,
+            post_params,
+            block_param,
+            param_ident,
+            keyword_params,
+            req_keyword_params,
+            kwrest_param,
+            iseq,
+            lvar_collector.clone(),
+            iseq_sourcemap,
+            self.source_info,
+            match kind {
+                ContextKind::Block => ISeqKind::Block(*self.method_stack.last().unwrap()),
+                ContextKind::Eval => ISeqKind::Other,
+                ContextKind::Method => {
+                    if name.is_some() {
+                        ISeqKind::Method(name.unwrap())
+                    } else {
+                        ISeqKind::Other
                     }
-                },
-            )),
+                }
+            },
+            save_loc,
+        );
+        info.tailcall = globals.compile_option.tailcall_optimization;
+        let info = MethodInfo::RubyFunc {
+            iseq: ISeqRef::new(info),
         };
 
         if !is_block {
@@ -846,7 +947,7 @@ impl Codegen {
                 if *toplevel {
                     self.gen_get_const_top(iseq, *id);
                 } else {
-                    self.gen_get_const(iseq, *id);
+                    self.gen_get_const(globals, iseq, *id);
                 };
                 if !use_value {
                     self.gen_pop(iseq)
@@ -860,7 +961,7 @@ impl Codegen {
                 };
             }
             NodeKind::InstanceVar(id) => {
-                self.gen_get_instance_var(iseq, *id);
+                self.gen_get_instance_var(globals, iseq, *id);
                 if !use_value {
                     self.gen_pop(iseq)
                 };
@@ -1071,9 +1172,11 @@ impl Codegen {
             NodeKind::If { cond, then_, else_ } => {
                 self.gen(globals, iseq, &cond, true)?;
                 let src1 = self.gen_jmp_if_false(iseq);
+                self.save_branch_loc(iseq, &then_);
                 self.gen(globals, iseq, &then_, use_value)?;
                 let src2 = Codegen::gen_jmp(iseq);
                 Codegen::write_disp_from_cur(iseq, src1);
+                self.save_branch_loc(iseq, &else_);
                 self.gen(globals, iseq, &else_, use_value)?;
                 Codegen::write_disp_from_cur(iseq, src2);
             }
@@ -1273,7 +1376,13 @@ impl Codegen {
                             ) if *id1 == *id2 && *i as i32 as i64 == *i => {
                                 let loc = mlhs[0].loc.merge(mrhs[0].loc);
                                 self.save_loc(iseq, loc);
-                                self.gen_ivar_addi(iseq, *id1, *i as i32 as u32, use_value);
+                                self.gen_ivar_addi(
+                                    globals,
+                                    iseq,
+                                    *id1,
+                                    *i as i32 as u32,
+                                    use_value,
+                                );
                             }
                             _ => {
                                 self.gen(globals, iseq, &mrhs[0], true)?;
@@ -1333,14 +1442,7 @@ impl Codegen {
                 for arg in &send_args.args {
                     self.gen(globals, iseq, arg, true)?;
                 }
-                let kw_flag = send_args.kw_args.len() != 0;
-                if kw_flag {
-                    for (id, default) in &send_args.kw_args {
-                        self.gen_symbol(iseq, *id);
-                        self.gen(globals, iseq, default, true)?;
-                    }
-                    self.gen_create_hash(iseq, send_args.kw_args.len());
-                }
+                let kw_flag = self.gen_kw_args(globals, iseq, send_args)?;
                 let mut block_flag = false;
                 let block_ref = match &send_args.block {
                     Some(block) => match &block.kind {
@@ -1407,16 +1509,8 @@ impl Codegen {
                 for arg in &send_args.args {
                     self.gen(globals, iseq, arg, true)?;
                 }
-                /*
-                let kw_flag = send_args.kw_args.len() != 0;
-                if kw_flag {
-                    for (id, default) in &send_args.kw_args {
-                        self.gen_symbol(iseq, *id);
-                        self.gen(globals, iseq, default, true)?;
-                    }
-                    self.gen_create_hash(iseq, send_args.kw_args.len());
-                }*/
-                self.gen_yield(iseq, send_args.args.len());
+                let kw_flag = self.gen_kw_args(globals, iseq, send_args)?;
+                self.gen_yield(iseq, send_args.args.len(), kw_flag);
                 if !use_value {
                     self.gen_pop(iseq);
                 };
@@ -1,4 +1,5 @@
 pub use crate::*;
+use std::collections::HashMap;
 use std::ops::{Index, IndexMut, Range};
 
 const LVAR_ARRAY_SIZE: usize = 32;
@@ -67,6 +68,11 @@ impl IndexMut<usize> for Context {
 }
 
 impl Context {
+    /// Pointer to the local variables which are stored in the context itself.
+    pub fn lvar_ptr(&mut self) -> *mut Value {
+        self.lvar_ary.as_mut_ptr()
+    }
+
     pub fn new(
         self_value: Value,
         block: Option<MethodRef>,
@@ -94,6 +100,32 @@ impl Context {
         }
     }
 
+    /// Initialize `self` in the same way as `Context::new`, keeping its allocation.
+    pub fn reset(
+        &mut self,
+        self_value: Value,
+        block: Option<MethodRef>,
+        iseq_ref: ISeqRef,
+        outer: Option<ContextRef>,
+    ) {
+        let lvar_num = iseq_ref.lvars;
+        self.lvar_ary = [Value::uninitialized(); LVAR_ARRAY_SIZE];
+        self.lvar_vec.clear();
+        if lvar_num > LVAR_ARRAY_SIZE {
+            self.lvar_vec
+                .resize(lvar_num - LVAR_ARRAY_SIZE, Value::uninitialized());
+        }
+        self.is_fiber = false;
+        self.self_value = self_value;
+        self.block = block;
+        self.iseq_ref = iseq_ref;
+        self.pc = 0;
+        self.outer = outer;
+        self.on_stack = true;
+        self.stack_len = 0;
+        self.kind = iseq_ref.kind.clone();
+    }
+
     pub fn from_args(
         vm: &mut VM,
         self_value: Value,
@@ -102,47 +134,47 @@ impl Context {
         outer: Option<ContextRef>,
     ) -> Result<Self, RubyError> {
         let mut context = Context::new(self_value, args.block, iseq, outer);
+        context.set_args(vm, args)?;
+        Ok(context)
+    }
+
+    /// Set `args` to the parameters.
+    pub fn set_args(&mut self, vm: &mut VM, args: &Args) -> Result<(), RubyError> {
+        let iseq = self.iseq_ref;
         let params = &iseq.params;
-        let kw = if params.keyword_params.is_empty() {
-            args.kw_arg
-        } else {
+        let kw = if params.has_keyword() {
             None
+        } else {
+            args.kw_arg
         };
         if !iseq.is_block() {
-            let len = args.len() + if kw.is_some() { 1 } else { 0 };
-            let min = params.req_params + params.post_params;
-            if params.rest_param {
-                vm.check_args_min(len, min)?;
-            } else {
-                vm.check_args_range(len, min, min + params.opt_params)?;
-            }
+            Context::check_arity(vm, iseq, args)?;
+        }
+        self.set_arguments(&vm.globals, args, kw);
+        if params.has_keyword() {
+            args.check_keywords(vm, params)?;
+            self.set_keyword_arguments(&vm.globals, args.kw_arg);
         }
-        context.set_arguments(&vm.globals, args, kw);
-        match args.kw_arg {
-            Some(kw_arg) if kw.is_none() => {
-                let keyword = kw_arg.as_hash().unwrap();
-                for (k, v) in keyword.iter() {
-                    let id = k.as_symbol().unwrap();
-                    match params.keyword_params.get(&id) {
-                        Some(lvar) => {
-                            context[*lvar] = v;
-                        }
-                        None => return Err(vm.error_argument("Undefined keyword.")),
-                    };
-                }
-            }
-            _ => {}
-        };
         if let Some(id) = iseq.lvar.block_param() {
-            context[id] = match args.block {
-                Some(block) => {
-                    let proc_context = vm.create_block_context(block)?;
-                    Value::procobj(&vm.globals, proc_context)
-                }
+            self[id] = match args.block {
+                Some(block) => vm.create_proc(block)?,
                 None => Value::nil(),
             }
         }
-        Ok(context)
+        Ok(())
+    }
+
+    /// Check the number of `args` for the parameters of `iseq`.
+    pub fn check_arity(vm: &VM, iseq: ISeqRef, args: &Args) -> Result<(), RubyError> {
+        let params = &iseq.params;
+        let kw = !params.has_keyword() && args.kw_arg.is_some();
+        let len = args.len() + if kw { 1 } else { 0 };
+        let min = params.req_params + params.post_params;
+        if params.rest_param {
+            vm.check_args_min(len, min)
+        } else {
+            vm.check_args_range(len, min, min + params.opt_params)
+        }
     }
 
     fn set_arguments(&mut self, globals: &Globals, args: &Args, kw_arg: Option<Value>) {
@@ -167,6 +199,27 @@ impl Context {
         self.fill_arguments(globals, args, args.len(), iseq, kw_arg);
     }
 
+    /// Set keyword arguments to keyword parameters.
+    /// Keywords which do not match any parameter are collected into the `**rest` parameter.
+    fn set_keyword_arguments(&mut self, globals: &Globals, kw_arg: Option<Value>) {
+        let iseq = self.iseq_ref;
+        let params = &iseq.params;
+        let mut kwrest = HashMap::new();
+        if let Some(keyword) = kw_arg.and_then(|kw| kw.as_hash()) {
+            for (k, v) in keyword.iter() {
+                match k.as_symbol().and_then(|id| params.keyword_params.get(&id)) {
+                    Some(lvar) => self[*lvar] = v,
+                    None => {
+                        kwrest.insert(HashKey(k), v);
+                    }
+                }
+            }
+        }
+        if let Some(lvar) = params.kwrest_param {
+            self[lvar] = Value::hash_from(globals, kwrest);
+        }
+    }
+
     fn fill_arguments(
         &mut self,
         globals: &Globals,
@@ -247,9 +300,7 @@ impl ContextRef {
     pub fn adjust_lvar_size(&mut self) {
         let len = self.iseq_ref.lvars;
         if LVAR_ARRAY_SIZE < len {
-            for _ in 0..len - LVAR_ARRAY_SIZE {
-                self.lvar_vec.push(Value::nil());
-            }
+            self.lvar_vec.resize(len - LVAR_ARRAY_SIZE, Value::nil());
         }
     }
 }
@@ -0,0 +1,396 @@
+//! Line, branch and method coverage for the `Coverage` module and `--coverage-out`.
+//!
+//! While coverage is running, every ISeqInfo registered in the method table gets an
+//! ISeqCoverage, which counts the instructions starting a line (found from
+//! `iseq_sourcemap`), the outcomes of conditional jumps, and the calls of the ISeqInfo.
+//! The counters are collected per source file when the result is requested.
+use super::decode::Op;
+use crate::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// Kinds of coverage given to `Coverage.start`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CoverageMode {
+    pub lines: bool,
+    pub branches: bool,
+    pub methods: bool,
+    /// `Coverage.start` without arguments: the result is an Array of line counts per file.
+    pub legacy: bool,
+}
+
+impl CoverageMode {
+    pub fn all() -> Self {
+        CoverageMode {
+            lines: true,
+            branches: true,
+            methods: true,
+            legacy: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    pub mode: CoverageMode,
+    pub running: bool,
+    iseqs: Vec<ISeqRef>,
+}
+
+/// Counters of an ISeqInfo.
+#[derive(Debug, Clone)]
+pub struct ISeqCoverage {
+    /// The line which starts at each instruction, or 0.
+    line_starts: Vec<usize>,
+    /// Execution count of each instruction starting a line.
+    lines: Vec<usize>,
+    /// Counts of falling through (then) and jumping (else) at each conditional jump.
+    branches: HashMap<usize, [usize; 2]>,
+    pub calls: usize,
+}
+
+impl ISeqCoverage {
+    fn new(iseq: ISeqRef) -> Self {
+        let mut line_starts = vec![];
+        let mut prev = 0;
+        for loc in inst_locs(iseq) {
+            let line = match loc {
+                Some(loc) => iseq.source_info.get_line(&loc),
+                None => 0,
+            };
+            line_starts.push(if line != prev { line } else { 0 });
+            prev = line;
+        }
+        let branches = iseq
+            .decoded
+            .insts
+            .iter()
+            .enumerate()
+            .filter(|(_, inst)| branch_dest(inst.op).is_some())
+            .map(|(index, _)| (index, [0, 0]))
+            .collect();
+        ISeqCoverage {
+            lines: vec![0; line_starts.len()],
+            line_starts,
+            branches,
+            calls: 0,
+        }
+    }
+
+    /// Count the execution of the instruction at `index`.
+    pub fn line(&mut self, index: usize) {
+        if self.line_starts[index] != 0 {
+            self.lines[index] += 1;
+        }
+    }
+
+    /// Count the outcome of the conditional jump at `index`.
+    pub fn branch(&mut self, index: usize, jumped: bool) {
+        if let Some(counts) = self.branches.get_mut(&index) {
+            counts[jumped as usize] += 1;
+        }
+    }
+}
+
+/// Destination of a conditional jump, or None for other instructions.
+fn branch_dest(op: Op) -> Option<usize> {
+    match op {
+        Op::JmpIfFalse(dest) | Op::Jeq(dest) | Op::Jne(dest) | Op::Jgt(dest) | Op::Jge(dest) => {
+            Some(dest)
+        }
+        _ => None,
+    }
+}
+
+/// Location of each instruction, which is the nearest preceding one in the source map.
+fn inst_locs(iseq: ISeqRef) -> Vec<Option<Loc>> {
+    let mut sourcemap = iseq.iseq_sourcemap.clone();
+    // The sort is stable, so the last entry for an instruction is taken.
+    sourcemap.sort_by_key(|(pos, _)| pos.to_usize());
+    let mut entries = sourcemap.iter().peekable();
+    let mut loc = None;
+    iseq.decoded
+        .insts
+        .iter()
+        .map(|inst| {
+            while let Some((pos, l)) = entries.peek() {
+                if pos.to_usize() > inst.pc {
+                    break;
+                }
+                loc = Some(*l);
+                entries.next();
+            }
+            loc
+        })
+        .collect()
+}
+
+/// Line and column (1-origin line, 0-origin column) of the position `pos` in the source.
+fn position(source: &SourceInfo, pos: u32) -> (usize, usize) {
+    let pos = std::cmp::min(pos as usize, source.code.len());
+    let line_top = match source.code[..pos].iter().rposition(|ch| *ch == '\n') {
+        Some(newline) => newline + 1,
+        None => 0,
+    };
+    (
+        source.get_line(&Loc(pos as u32, pos as u32)),
+        pos - line_top,
+    )
+}
+
+/// `[start line, start column, end line, end column]` of `loc`.
+fn range(source: &SourceInfo, loc: Loc) -> [usize; 4] {
+    let (line, column) = position(source, loc.0);
+    let (end_line, end_column) = position(source, loc.1);
+    [line, column, end_line, end_column + 1]
+}
+
+/// Coverage of a source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileCoverage {
+    /// Execution count of each line, or None for lines without code.
+    pub lines: Vec<Option<usize>>,
+    pub branches: Vec<BranchCoverage>,
+    pub methods: Vec<MethodCoverage>,
+}
+
+/// A conditional branch: `[:if, id, *range] => {[:then, id, *range] => count, [:else, id, *range] => count}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BranchCoverage {
+    pub id: usize,
+    pub range: [usize; 4],
+    pub then_range: [usize; 4],
+    pub then_count: usize,
+    pub else_range: [usize; 4],
+    pub else_count: usize,
+}
+
+/// A method: `[class, :name, *range] => count`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodCoverage {
+    pub class: Value,
+    pub name: IdentId,
+    pub range: [usize; 4],
+    pub count: usize,
+}
+
+impl Coverage {
+    pub fn start(&mut self, mode: CoverageMode) {
+        self.mode = mode;
+        self.running = true;
+    }
+
+    /// Stop the measurement and discard the counters.
+    pub fn stop(&mut self) {
+        for mut iseq in std::mem::take(&mut self.iseqs) {
+            iseq.coverage = None;
+        }
+        self.running = false;
+    }
+
+    /// Give counters to `iseq`, if the measurement is running.
+    pub fn register(&mut self, mut iseq: ISeqRef) {
+        if !self.running || iseq.source_info.path.as_os_str().is_empty() {
+            return;
+        }
+        iseq.coverage = Some(Box::new(ISeqCoverage::new(iseq)));
+        self.iseqs.push(iseq);
+    }
+
+    /// Coverage of each source file, keyed by the path.
+    pub fn result(&self, globals: &Globals) -> BTreeMap<String, FileCoverage> {
+        let mut files: BTreeMap<String, FileCoverage> = BTreeMap::new();
+        for iseq in &self.iseqs {
+            let cov = match &iseq.coverage {
+                Some(cov) => cov,
+                None => continue,
+            };
+            let source = &*iseq.source_info;
+            let file = files
+                .entry(source.path.to_string_lossy().to_string())
+                .or_insert_with(|| {
+                    let mut lines = source.code.iter().filter(|ch| **ch == '\n').count();
+                    if source.code.last() != Some(&'\n') {
+                        lines += 1;
+                    }
+                    FileCoverage {
+                        lines: vec![None; lines],
+                        branches: vec![],
+                        methods: vec![],
+                    }
+                });
+            // A line executed in several ISeqInfos (e.g. `3.times { x }`) is counted once.
+            for (index, line) in cov.line_starts.iter().enumerate() {
+                if *line == 0 || *line > file.lines.len() {
+                    continue;
+                }
+                let count = file.lines[*line - 1].unwrap_or(0);
+                file.lines[*line - 1] = Some(std::cmp::max(count, cov.lines[index]));
+            }
+            let locs = inst_locs(*iseq);
+            let mut branches: Vec<_> = cov.branches.iter().collect();
+            branches.sort_by_key(|(index, _)| **index);
+            for (index, [then_count, else_count]) in branches {
+                let loc = |index: usize| locs.get(index).cloned().flatten().unwrap_or(iseq.loc);
+                let dest = branch_dest(iseq.decoded.insts[*index].op).unwrap();
+                file.branches.push(BranchCoverage {
+                    id: 0,
+                    range: range(source, loc(*index)),
+                    then_range: range(source, loc(*index + 1)),
+                    then_count: *then_count,
+                    else_range: range(source, loc(dest)),
+                    else_count: *else_count,
+                });
+            }
+            if let ISeqKind::Method(name) = iseq.kind {
+                let class = match iseq.class_defined {
+                    Some(list) => list.class,
+                    None => globals.builtins.object,
+                };
+                file.methods.push(MethodCoverage {
+                    class,
+                    name,
+                    range: range(source, iseq.loc),
+                    count: cov.calls,
+                });
+            }
+        }
+        for file in files.values_mut() {
+            file.branches.sort_by_key(|branch| branch.range);
+            for (i, branch) in file.branches.iter_mut().enumerate() {
+                branch.id = i * 3;
+            }
+            file.methods.sort_by_key(|method| method.range);
+        }
+        files
+    }
+}
+
+/// The result in the JSON format of SimpleCov's `.resultset.json`.
+pub fn coverage_to_json(globals: &Globals, files: &BTreeMap<String, FileCoverage>) -> String {
+    let mut res = "{\n  \"ruruby\": {\n    \"coverage\": {".to_string();
+    for (i, (path, file)) in files.iter().enumerate() {
+        res += if i == 0 { "\n" } else { ",\n" };
+        res += &format!("      {}: {{\n", json_string(path));
+        let lines: Vec<String> = file
+            .lines
+            .iter()
+            .map(|count| match count {
+                Some(count) => count.to_string(),
+                None => "null".to_string(),
+            })
+            .collect();
+        res += &format!("        \"lines\": [{}],\n", lines.join(", "));
+        let branches: Vec<String> = file
+            .branches
+            .iter()
+            .map(|b| {
+                format!(
+                    "{}: {{{}: {}, {}: {}}}",
+                    json_string(&branch_key("if", b.id, b.range)),
+                    json_string(&branch_key("then", b.id + 1, b.then_range)),
+                    b.then_count,
+                    json_string(&branch_key("else", b.id + 2, b.else_range)),
+                    b.else_count
+                )
+            })
+            .collect();
+        res += &format!("        \"branches\": {{{}}},\n", branches.join(", "));
+        let methods: Vec<String> = file
+            .methods
+            .iter()
+            .map(|m| {
+                let key = format!(
+                    "[{}, :{}, {}, {}, {}, {}]",
+                    module_name(globals, m.class),
+                    globals.get_ident_name(m.name),
+                    m.range[0],
+                    m.range[1],
+                    m.range[2],
+                    m.range[3]
+                );
+                format!("{}: {}", json_string(&key), m.count)
+            })
+            .collect();
+        res += &format!("        \"methods\": {{{}}}\n", methods.join(", "));
+        res += "      }";
+    }
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    res += &format!("\n    }},\n    \"timestamp\": {}\n  }}\n}}\n", timestamp);
+    res
+}
+
+fn module_name(globals: &Globals, module: Value) -> &str {
+    match module.as_module() {
+        Some(cref) => globals.get_ident_name(cref.name),
+        None => "",
+    }
+}
+
+fn branch_key(kind: &str, id: usize, range: [usize; 4]) -> String {
+    format!(
+        "[:{}, {}, {}, {}, {}, {}]",
+        kind, id, range[0], range[1], range[2], range[3]
+    )
+}
+
+fn json_string(s: &str) -> String {
+    let mut res = "\"".to_string();
+    for ch in s.chars() {
+        match ch {
+            '"' => res += "\\\"",
+            '\\' => res += "\\\\",
+            '\n' => res += "\\n",
+            '\t' => res += "\\t",
+            ch if (ch as u32) < 0x20 => res += &format!("\\u{:04x}", ch as u32),
+            ch => res.push(ch),
+        }
+    }
+    res.push('"');
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn coverage_json() {
+        let program =
+            "def foo(x)\n  if x > 1\n    1\n  else\n    2\n  end\nend\n\n3.times { foo(2) }\n";
+        let mut vm = VM::new();
+        vm.globals.coverage.start(CoverageMode::all());
+        vm.run(PathBuf::from("cov.rb"), program, None).unwrap();
+        let files = vm.globals.coverage.result(&vm.globals);
+        let file = &files["cov.rb"];
+        assert_eq!(
+            vec![
+                Some(1),
+                Some(3),
+                Some(3),
+                None,
+                Some(0),
+                None,
+                None,
+                None,
+                Some(3)
+            ],
+            file.lines
+        );
+        assert_eq!(1, file.branches.len());
+        assert_eq!(
+            (3, 0),
+            (file.branches[0].then_count, file.branches[0].else_count)
+        );
+        assert_eq!([2, 5, 2, 10], file.branches[0].range);
+        assert_eq!(3, file.methods[0].count);
+        assert_eq!([1, 0, 7, 3], file.methods[0].range);
+        let json = coverage_to_json(&vm.globals, &files);
+        assert!(json.contains("\"cov.rb\": {\n"));
+        assert!(json.contains("\"lines\": [1, 3, 3, null, 0, null, null, null, 3]"));
+        assert!(json.contains("\"[Object, :foo, 1, 0, 7, 3]\": 3"));
+    }
+}
@@ -0,0 +1,513 @@
+//! Pre-decoded form of instruction sequences, which is executed by the VM.
+//!
+//! Each instruction is decoded once when the ISeq is created, so that the VM
+//! does not have to read its operands from the byte code on every execution.
+//! Jump destinations are resolved to indices into the decoded instructions.
+use crate::*;
+use vm_inst::*;
+
+/// Maximum number of arguments of OPT_SEND and OPT_SEND_SELF,
+/// which are passed without heap allocation.
+const OPT_SEND_MAX_ARGS: u16 = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    End,
+    Return,
+    MReturn,
+    PushNil,
+    PushTrue,
+    PushFalse,
+    PushSelf,
+    PushFixnum(i64),
+    PushFlonum(f64),
+    PushString(IdentId),
+    PushSymbol(IdentId),
+
+    /// Binary operators with an inline cache slot.
+    Add(u32),
+    Sub(u32),
+    Mul(u32),
+    Div(u32),
+    Shl(u32),
+    AddI(i32),
+    SubI(i32),
+    Rem,
+    Pow,
+    Shr,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    Eq,
+    Ne,
+    Teq,
+    Gt,
+    Ge,
+    Cmp,
+    Not,
+    ConcatString,
+    ToS,
+
+    SetLocal(LvarId, u32),
+    GetLocal(LvarId, u32),
+    CheckLocal(LvarId, u32),
+    LvarAddI(LvarId, u32, i32),
+    SetConst(IdentId),
+    /// (constant, const cache)
+    GetConst(IdentId, u32),
+    GetConstTop(IdentId),
+    GetScope(IdentId),
+    /// (variable, ivar cache)
+    SetIvar(IdentId, u32),
+    GetIvar(IdentId, u32),
+    IvarAddI(IdentId, i32, u32),
+    SetGvar(IdentId),
+    GetGvar(IdentId),
+    SetIndex(usize),
+    GetIndex(usize),
+
+    Splat,
+    /// Merge a `**hash` argument into the hash of keyword arguments.
+    MergeKw,
+    CreateRange,
+    CreateArray(usize),
+    CreateProc(MethodRef),
+    CreateHash(usize),
+    CreateRegexp,
+
+    Pop,
+    Dup(usize),
+    Take(usize),
+
+    /// Jumps hold the index of the destination instruction.
+    Jmp(usize),
+    JmpIfFalse(usize),
+    Jeq(usize),
+    Jne(usize),
+    Jgt(usize),
+    Jge(usize),
+    /// Case dispatch map id and the default destination.
+    /// Destinations in the map are displacements in the byte code.
+    OptCase(u32, usize),
+
+    Send {
+        id: IdentId,
+        args: u16,
+        flag: u16,
+        cache: u32,
+        block: MethodRef,
+        self_call: bool,
+    },
+    /// A send without a block, keyword arguments or a block argument,
+    /// with no more than OPT_SEND_MAX_ARGS arguments.
+    OptSend {
+        id: IdentId,
+        args: u16,
+        cache: u32,
+        self_call: bool,
+    },
+    Yield {
+        args: usize,
+        kw: bool,
+    },
+
+    DefClass {
+        is_module: bool,
+        id: IdentId,
+        method: MethodRef,
+    },
+    DefMethod(IdentId, MethodRef),
+    DefSMethod(IdentId, MethodRef),
+}
+
+impl Op {
+    /// The opcode which is used for perf counters and tracing.
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Op::End => Inst::END,
+            Op::Return => Inst::RETURN,
+            Op::MReturn => Inst::MRETURN,
+            Op::PushNil => Inst::PUSH_NIL,
+            Op::PushTrue => Inst::PUSH_TRUE,
+            Op::PushFalse => Inst::PUSH_FALSE,
+            Op::PushSelf => Inst::PUSH_SELF,
+            Op::PushFixnum(_) => Inst::PUSH_FIXNUM,
+            Op::PushFlonum(_) => Inst::PUSH_FLONUM,
+            Op::PushString(_) => Inst::PUSH_STRING,
+            Op::PushSymbol(_) => Inst::PUSH_SYMBOL,
+            Op::Add(_) => Inst::ADD,
+            Op::Sub(_) => Inst::SUB,
+            Op::Mul(_) => Inst::MUL,
+            Op::Div(_) => Inst::DIV,
+            Op::Shl(_) => Inst::SHL,
+            Op::AddI(_) => Inst::ADDI,
+            Op::SubI(_) => Inst::SUBI,
+            Op::Rem => Inst::REM,
+            Op::Pow => Inst::POW,
+            Op::Shr => Inst::SHR,
+            Op::BitAnd => Inst::BIT_AND,
+            Op::BitOr => Inst::BIT_OR,
+            Op::BitXor => Inst::BIT_XOR,
+            Op::BitNot => Inst::BIT_NOT,
+            Op::Eq => Inst::EQ,
+            Op::Ne => Inst::NE,
+            Op::Teq => Inst::TEQ,
+            Op::Gt => Inst::GT,
+            Op::Ge => Inst::GE,
+            Op::Cmp => Inst::CMP,
+            Op::Not => Inst::NOT,
+            Op::ConcatString => Inst::CONCAT_STRING,
+            Op::ToS => Inst::TO_S,
+            Op::SetLocal(..) => Inst::SET_LOCAL,
+            Op::GetLocal(..) => Inst::GET_LOCAL,
+            Op::CheckLocal(..) => Inst::CHECK_LOCAL,
+            Op::LvarAddI(..) => Inst::LVAR_ADDI,
+            Op::SetConst(_) => Inst::SET_CONST,
+            Op::GetConst(..) => Inst::GET_CONST,
+            Op::GetConstTop(_) => Inst::GET_CONST_TOP,
+            Op::GetScope(_) => Inst::GET_SCOPE,
+            Op::SetIvar(..) => Inst::SET_IVAR,
+            Op::GetIvar(..) => Inst::GET_IVAR,
+            Op::IvarAddI(..) => Inst::IVAR_ADDI,
+            Op::SetGvar(_) => Inst::SET_GVAR,
+            Op::GetGvar(_) => Inst::GET_GVAR,
+            Op::SetIndex(_) => Inst::SET_INDEX,
+            Op::GetIndex(_) => Inst::GET_INDEX,
+            Op::Splat => Inst::SPLAT,
+            Op::MergeKw => Inst::MERGE_KW,
+            Op::CreateRange => Inst::CREATE_RANGE,
+            Op::CreateArray(_) => Inst::CREATE_ARRAY,
+            Op::CreateProc(_) => Inst::CREATE_PROC,
+            Op::CreateHash(_) => Inst::CREATE_HASH,
+            Op::CreateRegexp => Inst::CREATE_REGEXP,
+            Op::Pop => Inst::POP,
+            Op::Dup(_) => Inst::DUP,
+            Op::Take(_) => Inst::TAKE,
+            Op::Jmp(_) => Inst::JMP,
+            Op::JmpIfFalse(_) => Inst::JMP_IF_FALSE,
+            Op::Jeq(_) => Inst::JEQ,
+            Op::Jne(_) => Inst::JNE,
+            Op::Jgt(_) => Inst::JGT,
+            Op::Jge(_) => Inst::JGE,
+            Op::OptCase(..) => Inst::OPT_CASE,
+            Op::Send { self_call, .. } => {
+                if *self_call {
+                    Inst::SEND_SELF
+                } else {
+                    Inst::SEND
+                }
+            }
+            Op::OptSend { self_call, .. } => {
+                if *self_call {
+                    Inst::OPT_SEND_SELF
+                } else {
+                    Inst::OPT_SEND
+                }
+            }
+            Op::Yield { .. } => Inst::YIELD,
+            Op::DefClass { .. } => Inst::DEF_CLASS,
+            Op::DefMethod(..) => Inst::DEF_METHOD,
+            Op::DefSMethod(..) => Inst::DEF_SMETHOD,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DecodedInst {
+    pub op: Op,
+    /// Position of the instruction in the byte code.
+    pub pc: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DecodedISeq {
+    pub insts: Vec<DecodedInst>,
+}
+
+impl DecodedISeq {
+    pub fn new(iseq: &ISeq) -> Self {
+        let mut pcs = vec![];
+        let mut pc = 0;
+        while pc < iseq.len() {
+            pcs.push(pc);
+            pc += Inst::inst_size(iseq[pc]);
+        }
+        let index = |pc: usize| match pcs.binary_search(&pc) {
+            Ok(i) => i,
+            Err(_) => panic!("Illegal jump destination. {:x}", pc),
+        };
+        let dest = |pc: usize, offset: usize, disp: u32| {
+            index((pc as i64 + offset as i64 + disp as i32 as i64) as usize)
+        };
+        let id = |pc: usize| IdentId::from(Inst::read32(iseq, pc));
+        let lvar = |pc: usize| LvarId::from_usize(Inst::read32(iseq, pc) as usize);
+        let method = |pc: usize| MethodRef::from(Inst::read32(iseq, pc));
+        let insts = pcs
+            .iter()
+            .map(|pc| {
+                let pc = *pc;
+                let op = match iseq[pc] {
+                    Inst::END => Op::End,
+                    Inst::RETURN => Op::Return,
+                    Inst::MRETURN => Op::MReturn,
+                    Inst::PUSH_NIL => Op::PushNil,
+                    Inst::PUSH_TRUE => Op::PushTrue,
+                    Inst::PUSH_FALSE => Op::PushFalse,
+                    Inst::PUSH_SELF => Op::PushSelf,
+                    Inst::PUSH_FIXNUM => Op::PushFixnum(Inst::read64(iseq, pc + 1) as i64),
+                    Inst::PUSH_FLONUM => Op::PushFlonum(f64::from_bits(Inst::read64(iseq, pc + 1))),
+                    Inst::PUSH_STRING => Op::PushString(id(pc + 1)),
+                    Inst::PUSH_SYMBOL => Op::PushSymbol(id(pc + 1)),
+                    Inst::ADD => Op::Add(Inst::read32(iseq, pc + 1)),
+                    Inst::SUB => Op::Sub(Inst::read32(iseq, pc + 1)),
+                    Inst::MUL => Op::Mul(Inst::read32(iseq, pc + 1)),
+                    Inst::DIV => Op::Div(Inst::read32(iseq, pc + 1)),
+                    Inst::SHL => Op::Shl(Inst::read32(iseq, pc + 1)),
+                    Inst::ADDI => Op::AddI(Inst::read32(iseq, pc + 1) as i32),
+                    Inst::SUBI => Op::SubI(Inst::read32(iseq, pc + 1) as i32),
+                    Inst::REM => Op::Rem,
+                    Inst::POW => Op::Pow,
+                    Inst::SHR => Op::Shr,
+                    Inst::BIT_AND => Op::BitAnd,
+                    Inst::BIT_OR => Op::BitOr,
+                    Inst::BIT_XOR => Op::BitXor,
+                    Inst::BIT_NOT => Op::BitNot,
+                    Inst::EQ => Op::Eq,
+                    Inst::NE => Op::Ne,
+                    Inst::TEQ => Op::Teq,
+                    Inst::GT => Op::Gt,
+                    Inst::GE => Op::Ge,
+                    Inst::CMP => Op::Cmp,
+                    Inst::NOT => Op::Not,
+                    Inst::CONCAT_STRING => Op::ConcatString,
+                    Inst::TO_S => Op::ToS,
+                    Inst::SET_LOCAL => Op::SetLocal(lvar(pc + 1), Inst::read32(iseq, pc + 5)),
+                    Inst::GET_LOCAL => Op::GetLocal(lvar(pc + 1), Inst::read32(iseq, pc + 5)),
+                    Inst::CHECK_LOCAL => Op::CheckLocal(lvar(pc + 1), Inst::read32(iseq, pc + 5)),
+                    Inst::LVAR_ADDI => Op::LvarAddI(
+                        lvar(pc + 1),
+                        Inst::read32(iseq, pc + 5),
+                        Inst::read32(iseq, pc + 9) as i32,
+                    ),
+                    Inst::SET_CONST => Op::SetConst(id(pc + 1)),
+                    Inst::GET_CONST => Op::GetConst(id(pc + 1), Inst::read32(iseq, pc + 5)),
+                    Inst::GET_CONST_TOP => Op::GetConstTop(id(pc + 1)),
+                    Inst::GET_SCOPE => Op::GetScope(id(pc + 1)),
+                    Inst::SET_IVAR => Op::SetIvar(id(pc + 1), Inst::read32(iseq, pc + 5)),
+                    Inst::GET_IVAR => Op::GetIvar(id(pc + 1), Inst::read32(iseq, pc + 5)),
+                    Inst::IVAR_ADDI => Op::IvarAddI(
+                        id(pc + 1),
+                        Inst::read32(iseq, pc + 5) as i32,
+                        Inst::read32(iseq, pc + 9),
+                    ),
+                    Inst::SET_GVAR => Op::SetGvar(id(pc + 1)),
+                    Inst::GET_GVAR => Op::GetGvar(id(pc + 1)),
+                    Inst::SET_INDEX => Op::SetIndex(Inst::read32(iseq, pc + 1) as usize),
+                    Inst::GET_INDEX => Op::GetIndex(Inst::read32(iseq, pc + 1) as usize),
+                    Inst::SPLAT => Op::Splat,
+                    Inst::MERGE_KW => Op::MergeKw,
+                    Inst::CREATE_RANGE => Op::CreateRange,
+                    Inst::CREATE_ARRAY => Op::CreateArray(Inst::read32(iseq, pc + 1) as usize),
+                    Inst::CREATE_PROC => Op::CreateProc(method(pc + 1)),
+                    Inst::CREATE_HASH => Op::CreateHash(Inst::read32(iseq, pc + 1) as usize),
+                    Inst::CREATE_REGEXP => Op::CreateRegexp,
+                    Inst::POP => Op::Pop,
+                    Inst::DUP => Op::Dup(Inst::read32(iseq, pc + 1) as usize),
+                    Inst::TAKE => Op::Take(Inst::read32(iseq, pc + 1) as usize),
+                    Inst::JMP => Op::Jmp(dest(pc, 5, Inst::read32(iseq, pc + 1))),
+                    Inst::JMP_IF_FALSE => Op::JmpIfFalse(dest(pc, 5, Inst::read32(iseq, pc + 1))),
+                    Inst::JEQ => Op::Jeq(dest(pc, 5, Inst::read32(iseq, pc + 1))),
+                    Inst::JNE => Op::Jne(dest(pc, 5, Inst::read32(iseq, pc + 1))),
+                    Inst::JGT => Op::Jgt(dest(pc, 5, Inst::read32(iseq, pc + 1))),
+                    Inst::JGE => Op::Jge(dest(pc, 5, Inst::read32(iseq, pc + 1))),
+                    Inst::OPT_CASE => Op::OptCase(
+                        Inst::read32(iseq, pc + 1),
+                        dest(pc, 9, Inst::read32(iseq, pc + 5)),
+                    ),
+                    Inst::SEND | Inst::SEND_SELF => {
+                        let self_call = iseq[pc] == Inst::SEND_SELF;
+                        let args = Inst::read16(iseq, pc + 5);
+                        let flag = Inst::read16(iseq, pc + 7);
+                        let cache = Inst::read32(iseq, pc + 9);
+                        let block = method(pc + 13);
+                        if flag == 0 && block.is_none() && args <= OPT_SEND_MAX_ARGS {
+                            Op::OptSend {
+                                id: id(pc + 1),
+                                args,
+                                cache,
+                                self_call,
+                            }
+                        } else {
+                            Op::Send {
+                                id: id(pc + 1),
+                                args,
+                                flag,
+                                cache,
+                                block,
+                                self_call,
+                            }
+                        }
+                    }
+                    Inst::YIELD => Op::Yield {
+                        args: Inst::read32(iseq, pc + 1) as usize,
+                        kw: Inst::read32(iseq, pc + 5) == 1,
+                    },
+                    Inst::DEF_CLASS => Op::DefClass {
+                        is_module: Inst::read8(iseq, pc + 1) == 1,
+                        id: id(pc + 2),
+                        method: method(pc + 6),
+                    },
+                    Inst::DEF_METHOD => Op::DefMethod(id(pc + 1), method(pc + 5)),
+                    Inst::DEF_SMETHOD => Op::DefSMethod(id(pc + 1), method(pc + 5)),
+                    inst => panic!("Unknown instruction. {}", inst),
+                };
+                DecodedInst { op, pc }
+            })
+            .collect();
+        DecodedISeq { insts }
+    }
+
+    /// Index of the instruction at `pc` in the byte code.
+    pub fn index_of(&self, pc: usize) -> usize {
+        if pc == 0 {
+            return 0;
+        }
+        match self.insts.binary_search_by_key(&pc, |inst| inst.pc) {
+            Ok(i) => i,
+            Err(_) => panic!("Illegal pc. {:x}", pc),
+        }
+    }
+
+    /// Returns true if the method returns as soon as it reaches the instruction at `index`,
+    /// that is, a call just before `index` is in tail position.
+    pub fn is_tail(&self, index: usize) -> bool {
+        let opcode = match self.insts[index].op {
+            Op::Jmp(dest) => self.insts[dest].op.opcode(),
+            op => op.opcode(),
+        };
+        opcode == Inst::END || opcode == Inst::RETURN
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    const PROGRAM: &str = r#"
+    def f(x)
+        if x == 1 then :a elsif x != 2 then :b else :c end
+    end
+    def g(n)
+        i = 0
+        while i < n
+            i += 1
+        end
+        i >= 3 ? i : 0
+    end
+    def h(x)
+        case x
+        when 1 then :one
+        when 2, 3 then :few
+        else :many
+        end
+    end
+    class Foo
+        def foo(a, *b)
+            [a, b].map { |v| if v then 1 else 0 end }
+        end
+    end
+    [1, 2].each { |v| break if v > 1 }
+    "#;
+
+    /// Compile PROGRAM, and call `check` with each ISeq in it, including the ISeqs
+    /// of the methods, classes and blocks.
+    fn each_iseq(check: &mut dyn FnMut(ISeqRef)) {
+        fn visit(vm: &VM, method: MethodRef, check: &mut dyn FnMut(ISeqRef)) {
+            let iseq = vm.get_iseq(method).unwrap();
+            check(iseq);
+            for inst in &iseq.decoded.insts {
+                let inner = match inst.op {
+                    Op::CreateProc(method)
+                    | Op::DefMethod(_, method)
+                    | Op::DefSMethod(_, method)
+                    | Op::DefClass { method, .. } => method,
+                    Op::Send { block, .. } if !block.is_none() => block,
+                    _ => continue,
+                };
+                visit(vm, inner, check);
+            }
+        }
+        let mut vm = VM::new();
+        let method = vm
+            .compile_program(PathBuf::from("test.rb"), PROGRAM)
+            .unwrap();
+        visit(&vm, method, check);
+    }
+
+    #[test]
+    fn decode_index_of() {
+        let mut count = 0;
+        each_iseq(&mut |iseq| {
+            let decoded = &iseq.decoded;
+            let mut pc = 0;
+            for (i, inst) in decoded.insts.iter().enumerate() {
+                assert_eq!(pc, inst.pc);
+                assert_eq!(i, decoded.index_of(inst.pc));
+                let opcode = match inst.op.opcode() {
+                    Inst::OPT_SEND => Inst::SEND,
+                    Inst::OPT_SEND_SELF => Inst::SEND_SELF,
+                    opcode => opcode,
+                };
+                assert_eq!(iseq.iseq[pc], opcode);
+                pc += Inst::inst_size(iseq.iseq[pc]);
+            }
+            assert_eq!(pc, iseq.iseq.len());
+            count += 1;
+        });
+        // The toplevel, f, g, h, Foo, Foo#foo and two blocks.
+        assert_eq!(8, count);
+    }
+
+    #[test]
+    fn decode_jump_targets() {
+        let mut jumps = std::collections::HashSet::new();
+        each_iseq(&mut |iseq| {
+            let decoded = &iseq.decoded;
+            for inst in &decoded.insts {
+                let pc = inst.pc;
+                let (dest, disp_pos, size) = match inst.op {
+                    Op::Jmp(dest)
+                    | Op::JmpIfFalse(dest)
+                    | Op::Jeq(dest)
+                    | Op::Jne(dest)
+                    | Op::Jgt(dest)
+                    | Op::Jge(dest) => (dest, pc + 1, 5),
+                    Op::OptCase(_, dest) => (dest, pc + 5, 9),
+                    _ => continue,
+                };
+                let disp = Inst::read32(&iseq.iseq, disp_pos) as i32 as i64;
+                let target = (pc as i64 + size + disp) as usize;
+                assert_eq!(target, decoded.insts[dest].pc);
+                assert_eq!(dest, decoded.index_of(target));
+                jumps.insert(inst.op.opcode());
+            }
+        });
+        for opcode in &[
+            Inst::JMP,
+            Inst::JMP_IF_FALSE,
+            Inst::JEQ,
+            Inst::JNE,
+            Inst::JGT,
+            Inst::JGE,
+            Inst::OPT_CASE,
+        ] {
+            assert!(
+                jumps.contains(opcode),
+                "{} is not tested.",
+                Inst::inst_name(*opcode)
+            );
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Human-readable listing of instruction sequences.
+use crate::*;
+use std::collections::HashMap;
+use vm_inst::*;
+
+/// Disassemble the ISeq of `method` and every method and block ISeq defined in it.
+pub fn disasm(globals: &Globals, method: MethodRef) -> String {
+    let methods = bytecode::reachable_iseqs(globals, method);
+    // Blocks see the local variables of the ISeq which creates them,
+    // and class bodies are named after the class.
+    let mut parents = HashMap::new();
+    let mut names = HashMap::new();
+    for method in &methods {
+        let iseq = &get_iseq(globals, *method).iseq;
+        let mut pc = 0;
+        while pc < iseq.len() {
+            match iseq[pc] {
+                Inst::CREATE_PROC => {
+                    parents.insert(MethodRef::from(Inst::read32(iseq, pc + 1)), *method);
+                }
+                Inst::SEND | Inst::SEND_SELF => {
+                    let block = MethodRef::from(Inst::read32(iseq, pc + 13));
+                    if !block.is_none() {
+                        parents.insert(block, *method);
+                    }
+                }
+                Inst::DEF_CLASS => {
+                    let kind = if Inst::read8(iseq, pc + 1) == 1 {
+                        "module"
+                    } else {
+                        "class"
+                    };
+                    let id = IdentId::from(Inst::read32(iseq, pc + 2));
+                    let name = format!("<{}:{}>", kind, globals.get_ident_name(id));
+                    names.insert(MethodRef::from(Inst::read32(iseq, pc + 6)), name);
+                }
+                _ => {}
+            }
+            pc += Inst::inst_size(iseq[pc]);
+        }
+    }
+
+    let mut res = String::new();
+    for method in methods {
+        let mut scopes = vec![get_iseq(globals, method)];
+        let mut scope = method;
+        while let Some(parent) = parents.get(&scope) {
+            scopes.push(get_iseq(globals, *parent));
+            scope = *parent;
+        }
+        let name = names.get(&method).map(|name| name.as_str());
+        res += &disasm_iseq(globals, method, &scopes, name);
+    }
+    res
+}
+
+fn get_iseq(globals: &Globals, method: MethodRef) -> ISeqRef {
+    match globals.get_method_info(method) {
+        MethodInfo::RubyFunc { iseq } => *iseq,
+        _ => panic!("Disasm: Illegal methodref."),
+    }
+}
+
+fn disasm_iseq(
+    globals: &Globals,
+    method: MethodRef,
+    scopes: &[ISeqRef],
+    name: Option<&str>,
+) -> String {
+    let iseq_ref = scopes[0];
+    let info = &*iseq_ref;
+    let source_info = info.source_info;
+    let kind = match (&info.kind, name) {
+        (_, Some(name)) => name.to_string(),
+        (ISeqKind::Other, None) => "<main>".to_string(),
+        (ISeqKind::Method(id), None) => format!("method '{}'", globals.get_ident_name(*id)),
+        (ISeqKind::Block(outer), None) => format!("block in {:?}", outer),
+    };
+    let mut res = format!(
+        "== disasm: {:?} {} ({}:{})\n",
+        method,
+        kind,
+        source_info.path.to_string_lossy(),
+        source_info.get_line(&info.loc)
+    );
+
+    let params = &info.params;
+    let mut param_info = vec![];
+    if params.req_params != 0 {
+        param_info.push(format!("req:{}", params.req_params));
+    }
+    if params.opt_params != 0 {
+        param_info.push(format!("opt:{}", params.opt_params));
+    }
+    if params.rest_param {
+        param_info.push("rest".to_string());
+    }
+    if params.post_params != 0 {
+        param_info.push(format!("post:{}", params.post_params));
+    }
+    if params.has_keyword() {
+        let mut keywords: Vec<(&IdentId, &LvarId)> = params.keyword_params.iter().collect();
+        keywords.sort_by_key(|(_, lvar)| lvar.as_usize());
+        let keywords: Vec<&str> = keywords
+            .iter()
+            .map(|(id, _)| globals.get_ident_name(**id))
+            .collect();
+        param_info.push(format!("kw:[{}]", keywords.join(", ")));
+    }
+    if params.kwrest_param.is_some() {
+        param_info.push("kwrest".to_string());
+    }
+    if params.block_param {
+        param_info.push("block".to_string());
+    }
+    if !param_info.is_empty() {
+        res += &format!("params: {}\n", param_info.join(" "));
+    }
+
+    let mut lvars: Vec<(&IdentId, &LvarId)> = info.lvar.table().iter().collect();
+    lvars.sort_by_key(|(_, lvar)| lvar.as_usize());
+    let lvars: Vec<String> = lvars
+        .iter()
+        .map(|(id, lvar)| {
+            let prefix = if Some(**lvar) == *info.lvar.block() {
+                "&"
+            } else {
+                ""
+            };
+            format!(
+                "{}{}@{}",
+                prefix,
+                globals.get_ident_name(**id),
+                lvar.as_usize()
+            )
+        })
+        .collect();
+    res += &format!("local table (size: {}): {}\n", info.lvars, lvars.join(" "));
+
+    let iseq = &info.iseq;
+    let mut dests = vec![];
+    let mut pc = 0;
+    while pc < iseq.len() {
+        dests.append(&mut Inst::jump_dests(globals, iseq, pc));
+        pc += Inst::inst_size(iseq[pc]);
+    }
+    dests.sort();
+    dests.dedup();
+    let labels: HashMap<usize, usize> = dests
+        .iter()
+        .enumerate()
+        .map(|(label, pos)| (*pos, label))
+        .collect();
+
+    let mut lines = HashMap::new();
+    for (pos, loc) in info.iseq_sourcemap.iter() {
+        lines.insert(pos.to_usize(), source_info.get_line(loc));
+    }
+
+    let mut pc = 0;
+    while pc < iseq.len() {
+        if let Some(label) = labels.get(&pc) {
+            res += &format!("L{}:\n", label);
+        }
+        let inst = Inst::inst_info_with_labels(globals, scopes, pc, &labels);
+        match lines.get(&pc) {
+            Some(line) => res += &format!("{:04x} {:<48} ({:>4})\n", pc, inst, line),
+            None => res += &format!("{:04x} {}\n", pc, inst),
+        }
+        pc += Inst::inst_size(iseq[pc]);
+    }
+    res += "\n";
+    res
+}
@@ -1,4 +1,5 @@
 use super::codegen::ContextKind;
+use super::decode::Op;
 use crate::*;
 
 #[cfg(feature = "perf")]
@@ -7,6 +8,7 @@ use super::perf::*;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::mpsc::{Receiver, SyncSender};
+#[cfg(feature = "trace")]
 use vm_inst::*;
 
 pub type ValueTable = HashMap<IdentId, Value>;
@@ -17,6 +19,8 @@ pub struct VM {
     // Global info
     pub globals: GlobalsRef,
     pub root_path: Vec<PathBuf>,
+    /// Where compiled source files are cached, or None to disable the cache.
+    pub bytecode_cache: Option<CacheLocation>,
     // VM state
     fiber_state: FiberState,
     exec_context: Vec<ContextRef>,
@@ -25,6 +29,17 @@ pub struct VM {
     exception: bool,
     pc: usize,
     pub channel: Option<(SyncSender<VMResult>, Receiver<usize>)>,
+    /// Per-method profiler enabled by `--profile`, or None.
+    pub profiler: Option<Profiler>,
+    /// Size of the native stack which the VM may use. SystemStackError is raised beyond this size.
+    pub native_stack_size: usize,
+    /// The lowest address of the native stack which the VM may use,
+    /// which is set when the VM starts to run on its thread.
+    stack_limit: usize,
+    /// Allocations of contexts which were executed by `run_frame`, for reuse.
+    /// Contexts are boxed so that they do not move while `ContextRef`s point to them.
+    #[allow(clippy::vec_box)]
+    context_pool: Vec<Box<Context>>,
     #[cfg(feature = "perf")]
     #[cfg_attr(tarpaulin, skip)]
     perf: Perf,
@@ -32,6 +47,13 @@ pub struct VM {
 
 pub type VMRef = Ref<VM>;
 
+/// Default value of `VM::native_stack_size`.
+/// Threads are spawned with 2 MiB of stack by default, and the VM uses a half of it.
+pub const DEFAULT_NATIVE_STACK_SIZE: usize = 1024 * 1024;
+
+/// Maximum number of allocations of contexts kept for reuse.
+const CONTEXT_POOL_SIZE: usize = 64;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FiberState {
     Created,
@@ -42,12 +64,14 @@ pub enum FiberState {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DefineMode {
     module_function: bool,
+    visibility: Visibility,
 }
 
 impl DefineMode {
     pub fn default() -> Self {
         DefineMode {
             module_function: false,
+            visibility: Visibility::Public,
         }
     }
 }
@@ -65,7 +89,7 @@ impl VM {
                 globals
                     .builtins
                     .object
-                    .set_var(id, globals.builtins.$class_object);
+                    .set_var(&globals, id, globals.builtins.$class_object);
             };
         }
 
@@ -73,7 +97,7 @@ impl VM {
             ($name:expr, $class_object:expr) => {
                 let id = globals.get_ident_id($name);
                 let object = $class_object;
-                globals.builtins.object.set_var(id, object);
+                globals.builtins.object.set_var(&globals, id, object);
             };
         }
 
@@ -84,24 +108,40 @@ impl VM {
         set_builtin_class!("Float", float);
         set_builtin_class!("Array", array);
         set_builtin_class!("Proc", procobj);
+        set_builtin_class!("Binding", binding);
         set_builtin_class!("Range", range);
         set_builtin_class!("String", string);
+        set_builtin_class!("Symbol", symbol);
         set_builtin_class!("Hash", hash);
         set_builtin_class!("Method", method);
+        set_builtin_class!("UnboundMethod", unbound_method);
         set_builtin_class!("Regexp", regexp);
+        set_builtin_class!("MatchData", matchdata);
+        set_builtin_class!("Random", random);
+        set_builtin_class!("IO", io);
         set_builtin_class!("Fiber", fiber);
         set_builtin_class!("Enumerator", enumerator);
+        set_builtin_class!("Encoding", encoding);
 
         set_class!("Math", math::init_math(&mut globals));
         set_class!("File", file::init_file(&mut globals));
+        set_class!("Dir", dir::init_dir(&mut globals));
+        set_class!("Time", time::init_time(&mut globals));
         set_class!("Process", process::init_process(&mut globals));
         set_class!("Struct", structobj::init_struct(&mut globals));
+        set_class!("RubyVM", rubyvm::init_rubyvm(&mut globals));
+        set_class!("Coverage", coverage::init_coverage(&mut globals));
         set_class!("StandardError", Value::class(&globals, globals.class_class));
         set_class!("RuntimeError", errorobj::init_error(&mut globals));
+        set_class!(
+            "SystemStackError",
+            errorobj::init_system_stack_error(&mut globals)
+        );
 
         let vm = VM {
             globals: GlobalsRef::new(globals),
             root_path: vec![],
+            bytecode_cache: None,
             fiber_state: FiberState::Created,
             class_context: vec![(Value::nil(), DefineMode::default())],
             exec_context: vec![],
@@ -109,6 +149,10 @@ impl VM {
             exception: false,
             pc: 0,
             channel: None,
+            profiler: None,
+            native_stack_size: DEFAULT_NATIVE_STACK_SIZE,
+            stack_limit: 0,
+            context_pool: vec![],
             #[cfg(feature = "perf")]
             #[cfg_attr(tarpaulin, skip)]
             perf: Perf::new(),
@@ -121,6 +165,7 @@ impl VM {
         VM {
             globals: self.globals.clone(),
             root_path: self.root_path.clone(),
+            bytecode_cache: self.bytecode_cache.clone(),
             fiber_state: FiberState::Created,
             exec_context: vec![],
             class_context: self.class_context.clone(),
@@ -128,6 +173,10 @@ impl VM {
             exception: false,
             pc: 0,
             channel: Some((tx, rx)),
+            profiler: None,
+            native_stack_size: DEFAULT_NATIVE_STACK_SIZE,
+            stack_limit: 0,
+            context_pool: vec![],
             #[cfg(feature = "perf")]
             #[cfg_attr(tarpaulin, skip)]
             perf: self.perf.clone(),
@@ -174,6 +223,20 @@ impl VM {
         self.exec_stack.pop().unwrap()
     }
 
+    /// The top of the stack for JIT-compiled code, which may push `additional` values
+    /// without growing the stack.
+    pub fn jit_stack_top(&mut self, additional: usize) -> *mut Value {
+        self.exec_stack.reserve(additional);
+        let len = self.exec_stack.len();
+        unsafe { self.exec_stack.as_mut_ptr().add(len) }
+    }
+
+    /// Set the length of the stack to the top of the stack `sp` of JIT-compiled code.
+    pub unsafe fn jit_set_stack_top(&mut self, sp: *mut Value) {
+        let len = sp.offset_from(self.exec_stack.as_ptr()) as usize;
+        self.exec_stack.set_len(len);
+    }
+
     pub fn context_push(&mut self, ctx: ContextRef) {
         self.exec_context.push(ctx);
     }
@@ -226,6 +289,14 @@ impl VM {
         self.class_context.last_mut().unwrap().1.module_function = flag;
     }
 
+    /// Set default visibility for methods defined after this point in the current class definition.
+    /// This also cancels `module_function` mode.
+    pub fn default_visibility(&mut self, visibility: Visibility) {
+        let mode = &mut self.class_context.last_mut().unwrap().1;
+        mode.visibility = visibility;
+        mode.module_function = false;
+    }
+
     pub fn get_pc(&mut self) -> usize {
         self.pc
     }
@@ -234,53 +305,44 @@ impl VM {
         self.pc = pc;
     }
 
-    pub fn jump_pc(&mut self, inst_offset: i64, disp: i64) {
-        self.pc = ((self.pc as i64) + inst_offset + disp) as usize;
-    }
-
-    fn read16(&self, iseq: &ISeq, offset: usize) -> u16 {
-        let pc = self.pc + offset;
-        let ptr = iseq[pc..pc + 1].as_ptr() as *const u16;
-        unsafe { *ptr }
-    }
-
-    fn read32(&self, iseq: &ISeq, offset: usize) -> u32 {
-        let pc = self.pc + offset;
-        let ptr = iseq[pc..pc + 1].as_ptr() as *const u32;
-        unsafe { *ptr }
-    }
-
-    fn read_usize(&self, iseq: &ISeq, offset: usize) -> usize {
-        self.read32(iseq, offset) as usize
-    }
-
-    fn read_id(&self, iseq: &ISeq, offset: usize) -> IdentId {
-        IdentId::from(self.read32(iseq, offset))
-    }
-
-    fn read_lvar_id(&self, iseq: &ISeq, offset: usize) -> LvarId {
-        LvarId::from_usize(self.read_usize(iseq, offset))
-    }
-
-    fn read_methodref(&self, iseq: &ISeq, offset: usize) -> MethodRef {
-        MethodRef::from(self.read32(iseq, offset))
-    }
-
-    fn read64(&self, iseq: &ISeq, offset: usize) -> u64 {
-        let pc = self.pc + offset;
-        let ptr = iseq[pc..pc + 1].as_ptr() as *const u64;
-        unsafe { *ptr }
-    }
-
-    fn read8(&self, iseq: &ISeq, offset: usize) -> u8 {
-        iseq[self.pc + offset]
+    pub fn parse_program(&mut self, path: PathBuf, program: &str) -> Result<MethodRef, RubyError> {
+        let cache_path = match &self.bytecode_cache {
+            Some(location) if path.is_file() => Some(location.cache_path(&path)),
+            _ => None,
+        };
+        if let Some(cache_path) = &cache_path {
+            if let Ok(bytes) = std::fs::read(cache_path) {
+                let hash = bytecode::source_hash(program);
+                if let Ok(method) = bytecode::load(&mut self.globals, &bytes, Some(hash)) {
+                    return Ok(method);
+                }
+            }
+        }
+        let method = self.compile_program(path.clone(), program)?;
+        if let Some(cache_path) = cache_path {
+            let bytes = bytecode::dump(&self.globals, method, &path, program);
+            // A cache which could not be written is simply rebuilt next time.
+            let _ = std::fs::write(cache_path, bytes);
+        }
+        Ok(method)
     }
 
-    fn read_disp(&self, iseq: &ISeq, offset: usize) -> i64 {
-        self.read32(iseq, offset) as i32 as i64
+    /// Compile `program` and serialize it into a bytecode image.
+    pub fn compile_to_bytecode(
+        &mut self,
+        path: PathBuf,
+        program: &str,
+    ) -> Result<Vec<u8>, RubyError> {
+        let method = self.compile_program(path.clone(), program)?;
+        Ok(bytecode::dump(&self.globals, method, &path, program))
     }
 
-    pub fn parse_program(&mut self, path: PathBuf, program: &str) -> Result<MethodRef, RubyError> {
+    /// Compile `program` without looking up the bytecode cache.
+    pub fn compile_program(
+        &mut self,
+        path: PathBuf,
+        program: &str,
+    ) -> Result<MethodRef, RubyError> {
         let mut parser = Parser::new();
         std::mem::swap(&mut parser.ident_table, &mut self.globals.ident_table);
         let result = parser.parse_program(path, program)?;
@@ -303,29 +365,59 @@ impl VM {
         Ok(methodref)
     }
 
+    /// Parse and compile `program` in the scope of `binding`, where `program` starts at `line`.
+    /// Local variables newly assigned in `program` are added to `binding`.
     pub fn parse_program_eval(
         &mut self,
         path: PathBuf,
         program: &str,
+        line: usize,
+        mut binding: ContextRef,
     ) -> Result<MethodRef, RubyError> {
+        // Collect local variable tables from the binding to the nearest method scope.
+        let mut scopes = vec![];
+        let mut context = Some(binding);
+        while let Some(ctx) = context {
+            scopes.push(ctx);
+            if let ISeqKind::Method(_) = ctx.kind {
+                break;
+            }
+            context = ctx.outer;
+        }
+        scopes.reverse();
+        let mut ext_lvars: Vec<LvarCollector> =
+            scopes.iter().map(|ctx| ctx.iseq_ref.lvar.clone()).collect();
+
         let mut parser = Parser::new();
         std::mem::swap(&mut parser.ident_table, &mut self.globals.ident_table);
-        let ext_lvar = self.context().iseq_ref.lvar.clone();
-        let result = parser.parse_program_eval(path, program, ext_lvar.clone())?;
+        let result = parser.parse_program_eval(path, program, line, ext_lvars.clone())?;
         self.globals.ident_table = result.ident_table;
 
+        let mut iseq = binding.iseq_ref;
+        iseq.lvars = result.lvar_collector.len();
+        iseq.lvar = result.lvar_collector.clone();
+        binding.adjust_lvar_size();
+        *ext_lvars.last_mut().unwrap() = result.lvar_collector;
+
         #[cfg(feature = "perf")]
         #[cfg_attr(tarpaulin, skip)]
         {
             self.perf.set_prev_inst(Perf::INVALID);
         }
         let mut codegen = Codegen::new(result.source_info);
-        codegen.context_push(ext_lvar);
+        codegen.method_push(scopes[0].iseq_ref.method);
+        for (i, lvar) in ext_lvars.into_iter().enumerate() {
+            if i == 0 {
+                codegen.context_push(lvar);
+            } else {
+                codegen.block_context_push(lvar);
+            }
+        }
         let method = codegen.gen_iseq(
             &mut self.globals,
             &vec![],
             &result.node,
-            &result.lvar_collector,
+            &LvarCollector::new(),
             true,
             ContextKind::Eval,
             None,
@@ -333,8 +425,31 @@ impl VM {
         Ok(method)
     }
 
+    /// Evaluate `program` in the scope of `binding`.
+    pub fn eval_binding(
+        &mut self,
+        path: PathBuf,
+        program: &str,
+        line: usize,
+        binding: ContextRef,
+    ) -> VMResult {
+        let method = self.parse_program_eval(path, program, line, binding)?;
+        let args = Args::new0();
+        self.eval_method(method, binding.self_value, Some(binding), &args)
+    }
+
     pub fn run(&mut self, path: PathBuf, program: &str, self_value: Option<Value>) -> VMResult {
         let method = self.parse_program(path, program)?;
+        self.run_toplevel(method, self_value)
+    }
+
+    /// Run a bytecode image produced by `compile_to_bytecode`.
+    pub fn run_bytecode(&mut self, bytes: &[u8], self_value: Option<Value>) -> VMResult {
+        let method = bytecode::load(&mut self.globals, bytes, None)?;
+        self.run_toplevel(method, self_value)
+    }
+
+    fn run_toplevel(&mut self, method: MethodRef, self_value: Option<Value>) -> VMResult {
         let self_value = match self_value {
             Some(val) => val,
             None => self.globals.main_object,
@@ -402,38 +517,104 @@ macro_rules! try_err {
     ($self:ident, $eval:expr) => {
         match $eval {
             Ok(val) => $self.stack_push(val),
-            Err(err) if err.kind == RubyErrorKind::BlockReturn => {}
-            Err(mut err) => {
-                let m = $self.context().iseq_ref.method;
-                let res = if RubyErrorKind::MethodReturn(m) == err.kind {
-                    let result = $self.stack_pop();
-                    let prev_len = $self.context().stack_len;
-                    $self.exec_stack.truncate(prev_len);
-                    $self.unwind_context(&mut err);
-                    #[cfg(feature = "trace")]
-                    {
-                        println!("<--- METHOD_RETURN Ok({})", $self.val_inspect(result),);
-                    }
-                    Ok(result)
-                } else {
-                    $self.unwind_context(&mut err);
-                    #[cfg(feature = "trace")]
-                    {
-                        println!("<--- Err({:?})", err.kind);
-                    }
-                    Err(err)
-                };
-                $self.fiberstate_dead();
-                $self.fiber_send_to_parent(res.clone());
-                return res;
+            Err(err) => {
+                if let Some(res) = $self.push_result(Err(err)) {
+                    return res;
+                }
             }
-        };
+        }
     };
 }
 
+/// Execution state of a method or block in `run_frame`.
+/// While a Ruby method called from the frame runs in the same `run_frame`,
+/// the state of the caller is saved in this struct.
+struct Frame {
+    context: ContextRef,
+    /// The context of a method called without recursion of `run_context`, owned by this frame.
+    owned: Option<Box<Context>>,
+    index: usize,
+    jit_code: Option<JitCodeRef>,
+}
+
+/// A method call prepared by SEND, OPT_SEND or YIELD.
+pub(super) enum Invoke {
+    /// The result of the method which was already evaluated.
+    Value(Value),
+    /// A new context of a Ruby method or block which is to be executed.
+    Context(Box<Context>),
+}
+
 impl VM {
     /// Main routine for VM execution.
     pub fn run_context(&mut self, context: ContextRef) -> VMResult {
+        if self.profiler.is_none() {
+            return self.run_context_main(context);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter(context.iseq_ref);
+        }
+        let res = self.run_context_main(context);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit();
+        }
+        res
+    }
+
+    /// Execute `context` and Ruby methods called from it.
+    /// Calls between Ruby methods and blocks are executed by one `run_frame`
+    /// without recursion on the native stack, and their results are handled here
+    /// in the same way as `try_err!` does for recursive calls.
+    /// This, `push_frame` and `run_frame` are inlined into `run_context`,
+    /// since every block call from a builtin method goes through all of them.
+    /// They are not inlined in debug builds, where the frame of `run_context` would
+    /// become too large for the limit of the native stack.
+    #[cfg_attr(not(debug_assertions), inline(always))]
+    fn run_context_main(&mut self, context: ContextRef) -> VMResult {
+        if self.exec_context.len() >= self.globals.max_stack_depth || self.native_stack_exhausted()
+        {
+            return Err(self.error_system_stack());
+        }
+        let mut frame = self.push_frame(context, None);
+        let mut frames = vec![];
+        loop {
+            let mut res = match self.run_frame(frame, &mut frames) {
+                // The usual case, which is returned without copying the whole result.
+                Ok(val) if frames.is_empty() => return Ok(val),
+                res => res,
+            };
+            loop {
+                let caller = match frames.pop() {
+                    Some(caller) => caller,
+                    None => return res,
+                };
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.exit();
+                }
+                match self.push_result(res) {
+                    Some(caller_res) => res = caller_res,
+                    None => {
+                        frame = caller;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if the native stack is used beyond `native_stack_size`.
+    fn native_stack_exhausted(&mut self) -> bool {
+        let marker = 0u8;
+        let sp = &marker as *const u8 as usize;
+        if self.stack_limit == 0 {
+            self.stack_limit = sp.saturating_sub(self.native_stack_size);
+        }
+        sp < self.stack_limit
+    }
+
+    /// Push `context` onto the context stack, and returns a frame which starts its execution.
+    #[cfg_attr(not(debug_assertions), inline(always))]
+    fn push_frame(&mut self, context: ContextRef, owned: Option<Box<Context>>) -> Frame {
         #[cfg(feature = "trace")]
         {
             if context.is_fiber {
@@ -448,435 +629,525 @@ impl VM {
         };
         self.context_push(context);
         self.pc = context.pc;
-        let iseq = &context.iseq_ref.iseq;
-        let mut self_oref = context.self_value.as_object();
-        loop {
-            #[cfg(feature = "perf")]
-            #[cfg_attr(tarpaulin, skip)]
+        let mut iseq_ref = context.iseq_ref;
+        if let Some(coverage) = &mut iseq_ref.coverage {
+            coverage.calls += 1;
+        }
+        Frame {
+            context,
+            owned,
+            index: iseq_ref.decoded.index_of(self.pc),
+            jit_code: self.get_jit_code(iseq_ref),
+        }
+    }
+
+    /// Start the execution of `callee` in the current `run_frame`.
+    /// `frame` is saved in `frames` until `callee` returns, or is discarded for a tail call.
+    fn enter_callee(
+        &mut self,
+        callee: Box<Context>,
+        tail: bool,
+        frame: &mut Frame,
+        frames: &mut Vec<Frame>,
+        index: usize,
+    ) -> Result<(), RubyError> {
+        let context = ContextRef::from_local(&callee);
+        if tail {
+            self.context_pop().unwrap();
+            if let Some(caller) = self.exec_context.last() {
+                self.pc = caller.pc;
+            }
+            if let Some(profiler) = &mut self.profiler {
+                profiler.exit();
+            }
+        } else if self.exec_context.len() >= self.globals.max_stack_depth {
+            return Err(self.error_system_stack());
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter(context.iseq_ref);
+        }
+        let callee = self.push_frame(context, Some(callee));
+        let caller = std::mem::replace(frame, callee);
+        if !tail {
+            frames.push(Frame { index, ..caller });
+        }
+        Ok(())
+    }
+
+    /// Handle the result of a call in the current context.
+    /// Returns None if the execution of the current context continues,
+    /// or the result of the current context if it exits with an error.
+    fn push_result(&mut self, res: VMResult) -> Option<VMResult> {
+        let mut err = match res {
+            Ok(val) => {
+                self.stack_push(val);
+                return None;
+            }
+            Err(err) if err.kind == RubyErrorKind::BlockReturn => return None,
+            Err(err) => err,
+        };
+        let m = self.context().iseq_ref.method;
+        let res = if RubyErrorKind::MethodReturn(m) == err.kind {
+            let result = self.stack_pop();
+            let prev_len = self.context().stack_len;
+            self.exec_stack.truncate(prev_len);
+            self.unwind_context(&mut err);
+            #[cfg(feature = "trace")]
             {
-                self.perf.get_perf(iseq[self.pc]);
+                println!("<--- METHOD_RETURN Ok({})", self.val_inspect(result),);
             }
+            Ok(result)
+        } else {
+            self.unwind_context(&mut err);
             #[cfg(feature = "trace")]
             {
-                println!(
-                    "{:>4x}:{:<15} stack:{}",
-                    self.pc,
-                    Inst::inst_name(iseq[self.pc]),
-                    self.exec_stack.len()
-                );
+                println!("<--- Err({:?})", err.kind);
             }
-            match iseq[self.pc] {
-                Inst::END => {
-                    // reached the end of the method or block.
-                    // - the end of the method or block.
-                    // - `next` in block AND outer of loops.
-                    if self.exec_context.len() == 1 {
-                        // if in the final context, the fiber becomes DEAD.
-                        self.fiberstate_dead();
-                        self.fiber_send_to_parent(Err(self.error_fiber("Dead fiber called.")));
-                    };
-                    let _context = self.context_pop().unwrap();
-                    let val = self.stack_pop();
-                    #[cfg(feature = "trace")]
-                    {
-                        if _context.is_fiber {
-                            println!("<=== Ok({})", self.val_inspect(val));
-                        } else {
-                            println!("<--- Ok({})", self.val_inspect(val));
+            Err(err)
+        };
+        self.fiberstate_dead();
+        self.fiber_send_to_parent(res.clone());
+        Some(res)
+    }
+
+    /// Execute instructions of `frame` until the frame returns.
+    /// A Ruby method or block called from the frame is executed in this loop,
+    /// with the caller saved in `frames`.
+    #[cfg_attr(not(debug_assertions), inline(always))]
+    fn run_frame(&mut self, mut frame: Frame, frames: &mut Vec<Frame>) -> VMResult {
+        'frame: loop {
+            let context = frame.context;
+            let decoded = &context.iseq_ref.decoded;
+            let mut index = frame.index;
+            let self_oref = context.self_value.as_object();
+            let mut iseq_ref = context.iseq_ref;
+            let jit_code = frame.jit_code;
+            let insts = &decoded.insts[..];
+            // Compiled code, the profiler and coverage take over or observe every instruction.
+            let hooked =
+                jit_code.is_some() || self.profiler.is_some() || iseq_ref.coverage.is_some();
+            // Execute a Ruby method or block in this loop, or push the result of other methods.
+            macro_rules! invoke {
+                ($invoke:expr) => {
+                    match $invoke {
+                        Ok(Invoke::Context(callee)) => {
+                            let tail = iseq_ref.tailcall
+                                && callee.block.is_none()
+                                && !callee.iseq_ref.is_block()
+                                && !context.iseq_ref.is_block()
+                                && decoded.is_tail(index);
+                            match self.enter_callee(callee, tail, &mut frame, frames, index) {
+                                Ok(()) => continue 'frame,
+                                Err(err) => try_err!(self, Err(err)),
+                            }
                         }
+                        Ok(Invoke::Value(val)) => self.stack_push(val),
+                        Err(err) => try_err!(self, Err(err)),
                     }
-                    if !self.exec_context.is_empty() {
-                        self.pc = self.context().pc;
-                    };
-                    return Ok(val);
-                }
-                Inst::RETURN => {
-                    // 'Inst::RETURN' is executed.
-                    // - `return` in method.
-                    // - `break` outer of loops.
-                    let res = if let ISeqKind::Block(_) = context.kind {
-                        // if in block context, exit with Err(BLOCK_RETURN).
-                        let err = self.error_block_return();
-                        #[cfg(feature = "trace")]
-                        {
-                            println!("<--- Err({:?})", err.kind);
-                        }
-                        Err(err)
-                    } else {
-                        // if in method context, exit with Ok(rerurn_value).
-                        let val = self.stack_pop();
-                        #[cfg(feature = "trace")]
-                        {
-                            println!("<--- Ok({})", self.val_inspect(val));
+                };
+            }
+            loop {
+                if hooked {
+                    if let Some(jit_code) = jit_code {
+                        match jit_code.run(self, index) {
+                            JitExit::Exit(next) => index = next,
+                            JitExit::Raise(next, err) => {
+                                index = next;
+                                try_err!(self, Err::<Value, RubyError>(err));
+                                continue;
+                            }
+                            JitExit::Error(err) => return Err(err),
                         }
-                        Ok(val)
-                    };
-
-                    self.context_pop().unwrap();
-                    if !self.exec_context.is_empty() {
-                        self.pc = self.context().pc;
                     }
-                    return res;
-                }
-                Inst::MRETURN => {
-                    // 'METHOD_RETURN' is executed.
-                    // - `return` in block
-                    let res = if let ISeqKind::Block(method) = context.kind {
-                        // exit with Err(METHOD_RETURN).
-                        let err = self.error_method_return(method);
-                        #[cfg(feature = "trace")]
-                        {
-                            println!("<--- Err({:?})", err.kind);
-                        }
-                        Err(err)
-                    } else {
-                        unreachable!()
-                    };
-                    self.context_pop().unwrap();
-                    if !self.exec_context.is_empty() {
-                        self.pc = self.context().pc;
+                    if let Some(profiler) = &mut self.profiler {
+                        profiler.instruction(index);
+                    }
+                    if let Some(coverage) = &mut iseq_ref.coverage {
+                        coverage.line(index);
                     }
-                    return res;
-                }
-                Inst::PUSH_NIL => {
-                    self.stack_push(Value::nil());
-                    self.pc += 1;
-                }
-                Inst::PUSH_TRUE => {
-                    self.stack_push(Value::true_val());
-                    self.pc += 1;
-                }
-                Inst::PUSH_FALSE => {
-                    self.stack_push(Value::false_val());
-                    self.pc += 1;
-                }
-                Inst::PUSH_SELF => {
-                    self.stack_push(context.self_value);
-                    self.pc += 1;
-                }
-                Inst::PUSH_FIXNUM => {
-                    let num = self.read64(iseq, 1);
-                    self.pc += 9;
-                    self.stack_push(Value::fixnum(num as i64));
-                }
-                Inst::PUSH_FLONUM => {
-                    let num = f64::from_bits(self.read64(iseq, 1));
-                    self.pc += 9;
-                    self.stack_push(Value::flonum(num));
-                }
-                Inst::PUSH_STRING => {
-                    let id = self.read_id(iseq, 1);
-                    let string = self.globals.get_ident_name(id).to_string();
-                    self.stack_push(Value::string(&self.globals, string));
-                    self.pc += 5;
-                }
-                Inst::PUSH_SYMBOL => {
-                    let id = self.read_id(iseq, 1);
-                    self.stack_push(Value::symbol(id));
-                    self.pc += 5;
-                }
-                Inst::ADD => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    try_err!(self, self.eval_add(lhs, rhs, iseq));
-                    self.pc += 5;
-                }
-                Inst::ADDI => {
-                    let lhs = self.stack_pop();
-                    let i = self.read32(iseq, 1) as i32;
-                    let val = self.eval_addi(lhs, i)?;
-                    self.stack_push(val);
-                    self.pc += 5;
-                }
-                Inst::SUB => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    try_err!(self, self.eval_sub(lhs, rhs, iseq));
-                    self.pc += 5;
-                }
-                Inst::SUBI => {
-                    let lhs = self.stack_pop();
-                    let i = self.read32(iseq, 1) as i32;
-                    let val = self.eval_subi(lhs, i)?;
-                    self.stack_push(val);
-                    self.pc += 5;
-                }
-                Inst::MUL => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = self.eval_mul(lhs, rhs, iseq)?;
-                    self.stack_push(val);
-                    self.pc += 5;
-                }
-                Inst::POW => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = self.eval_exp(lhs, rhs)?;
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::DIV => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = self.eval_div(lhs, rhs, iseq)?;
-                    self.stack_push(val);
-                    self.pc += 5;
-                }
-                Inst::REM => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = self.eval_rem(lhs, rhs)?;
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::SHR => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = self.eval_shr(lhs, rhs)?;
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::SHL => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = self.eval_shl(lhs, rhs, iseq)?;
-                    self.stack_push(val);
-                    self.pc += 5;
-                }
-                Inst::BIT_AND => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = self.eval_bitand(lhs, rhs)?;
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::BIT_OR => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = self.eval_bitor(lhs, rhs)?;
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::BIT_XOR => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = self.eval_bitxor(lhs, rhs)?;
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::BIT_NOT => {
-                    let lhs = self.stack_pop();
-                    let val = self.eval_bitnot(lhs)?;
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::EQ => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = Value::bool(self.eval_eq(rhs, lhs)?);
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::NE => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = Value::bool(!self.eval_eq(rhs, lhs)?);
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::TEQ => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let res = self.eval_teq(rhs, lhs)?;
-                    let val = Value::bool(res);
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::GT => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = self.eval_gt(lhs, rhs)?;
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::GE => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = self.eval_ge(lhs, rhs)?;
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::CMP => {
-                    let lhs = self.stack_pop();
-                    let rhs = self.stack_pop();
-                    let val = self.eval_cmp(rhs, lhs)?;
-                    self.stack_push(val);
-                    self.pc += 1;
                 }
-                Inst::NOT => {
-                    let lhs = self.stack_pop();
-                    let val = Value::bool(!self.val_to_bool(lhs));
-                    self.stack_push(val);
-                    self.pc += 1;
+                let inst = &insts[index];
+                self.pc = inst.pc;
+                index += 1;
+                #[cfg(feature = "perf")]
+                #[cfg_attr(tarpaulin, skip)]
+                {
+                    self.perf.get_perf(inst.op.opcode());
                 }
-                Inst::CONCAT_STRING => {
-                    let rhs = self.stack_pop();
-                    let lhs = self.stack_pop();
-                    let val = match (lhs.as_string(), rhs.as_string()) {
-                        (Some(lhs), Some(rhs)) => {
-                            Value::string(&self.globals, format!("{}{}", lhs, rhs))
+                #[cfg(feature = "trace")]
+                {
+                    println!(
+                        "{:>4x}:{:<15} stack:{}",
+                        self.pc,
+                        Inst::inst_name(inst.op.opcode()),
+                        self.exec_stack.len()
+                    );
+                }
+                match inst.op {
+                    Op::End => {
+                        // reached the end of the method or block.
+                        // - the end of the method or block.
+                        // - `next` in block AND outer of loops.
+                        if self.exec_context.len() == 1 {
+                            // if in the final context, the fiber becomes DEAD.
+                            self.fiberstate_dead();
+                            self.fiber_send_to_parent(Err(self.error_fiber("Dead fiber called.")));
+                        };
+                        let _context = self.context_pop().unwrap();
+                        let val = self.stack_pop();
+                        #[cfg(feature = "trace")]
+                        {
+                            if _context.is_fiber {
+                                println!("<=== Ok({})", self.val_inspect(val));
+                            } else {
+                                println!("<--- Ok({})", self.val_inspect(val));
+                            }
                         }
-                        (_, _) => unreachable!("Illegal CAONCAT_STRING arguments."),
-                    };
-                    self.stack_push(val);
-                    self.pc += 1;
-                }
-                Inst::SET_LOCAL => {
-                    let id = self.read_lvar_id(iseq, 1);
-                    let outer = self.read32(iseq, 5);
-                    let val = self.stack_pop();
-                    let mut cref = self.get_outer_context(outer);
-                    cref[id] = val;
-                    self.pc += 9;
-                }
-                Inst::GET_LOCAL => {
-                    let id = self.read_lvar_id(iseq, 1);
-                    let outer = self.read32(iseq, 5);
-                    let cref = self.get_outer_context(outer);
-                    let val = cref[id];
-                    self.stack_push(val);
-                    self.pc += 9;
-                }
-                Inst::CHECK_LOCAL => {
-                    let id = self.read_lvar_id(iseq, 1);
-                    let outer = self.read32(iseq, 5);
-                    let cref = self.get_outer_context(outer);
-                    let val = cref[id].is_uninitialized();
-                    self.stack_push(Value::bool(val));
-                    self.pc += 9;
-                }
-                Inst::SET_CONST => {
-                    let id = self.read_id(iseq, 1);
-                    let mut parent = match self.stack_pop() {
-                        v if v == Value::nil() => self.class(),
-                        v => v,
-                    };
-                    let val = self.stack_pop();
-                    match val.as_module() {
-                        Some(mut cref) => {
-                            if cref.name == None {
-                                cref.name = Some(id);
+                        if !self.exec_context.is_empty() {
+                            self.pc = self.context().pc;
+                        };
+                        // return to the caller in this loop.
+                        if let Some(caller) = frames.pop() {
+                            if let Some(profiler) = &mut self.profiler {
+                                profiler.exit();
+                            }
+                            self.stack_push(val);
+                            let callee = std::mem::replace(&mut frame, caller);
+                            if let Some(context) = callee.owned {
+                                self.free_context(context);
                             }
+                            continue 'frame;
                         }
-                        None => {}
+                        return Ok(val);
                     }
-                    parent.set_var(id, val);
-                    self.pc += 5;
-                }
-                Inst::GET_CONST => {
-                    let id = self.read_id(iseq, 1);
-                    let val = match self.get_env_const(id) {
-                        Some(val) => val,
-                        None => self.get_super_const(self.class(), id)?,
-                    };
-                    self.stack_push(val);
-                    self.pc += 5;
-                }
-                Inst::GET_CONST_TOP => {
-                    let id = self.read_id(iseq, 1);
-                    let class = self.globals.builtins.object;
-                    let val = self.get_super_const(class, id)?;
-                    self.stack_push(val);
-                    self.pc += 5;
-                }
-                Inst::GET_SCOPE => {
-                    let parent = self.stack_pop();
-                    let id = self.read_id(iseq, 1);
-                    let val = self.get_super_const(parent, id)?;
-                    self.stack_push(val);
-                    self.pc += 5;
-                }
-                Inst::SET_IVAR => {
-                    let var_id = self.read_id(iseq, 1);
-                    let new_val = self.stack_pop();
-                    self_oref.set_var(var_id, new_val);
-                    self.pc += 5;
-                }
-                Inst::GET_IVAR => {
-                    let var_id = self.read_id(iseq, 1);
-                    let val = match self_oref.get_var(var_id) {
-                        Some(val) => val.clone(),
-                        None => Value::nil(),
-                    };
-                    self.stack_push(val);
-                    self.pc += 5;
-                }
-                Inst::IVAR_ADDI => {
-                    let var_id = self.read_id(iseq, 1);
-                    let i = self.read32(iseq, 5) as i32;
-                    match self_oref.get_mut_var(var_id) {
-                        Some(val) => {
-                            let new_val = self.eval_addi(*val, i)?;
-                            *val = new_val;
+                    Op::Return => {
+                        // 'Inst::RETURN' is executed.
+                        // - `return` in method.
+                        // - `break` outer of loops.
+                        let res = if let ISeqKind::Block(_) = context.kind {
+                            // if in block context, exit with Err(BLOCK_RETURN).
+                            let err = self.error_block_return();
+                            #[cfg(feature = "trace")]
+                            {
+                                println!("<--- Err({:?})", err.kind);
+                            }
+                            Err(err)
+                        } else {
+                            // if in method context, exit with Ok(rerurn_value).
+                            let val = self.stack_pop();
+                            #[cfg(feature = "trace")]
+                            {
+                                println!("<--- Ok({})", self.val_inspect(val));
+                            }
+                            Ok(val)
+                        };
+
+                        self.context_pop().unwrap();
+                        if !self.exec_context.is_empty() {
+                            self.pc = self.context().pc;
                         }
-                        None => {
-                            let new_val = self.eval_addi(Value::nil(), i)?;
-                            self_oref.set_var(var_id, new_val);
+                        return res;
+                    }
+                    Op::MReturn => {
+                        // 'METHOD_RETURN' is executed.
+                        // - `return` in block
+                        let res = if let ISeqKind::Block(method) = context.kind {
+                            // exit with Err(METHOD_RETURN).
+                            let err = self.error_method_return(method);
+                            #[cfg(feature = "trace")]
+                            {
+                                println!("<--- Err({:?})", err.kind);
+                            }
+                            Err(err)
+                        } else {
+                            unreachable!()
+                        };
+                        self.context_pop().unwrap();
+                        if !self.exec_context.is_empty() {
+                            self.pc = self.context().pc;
                         }
-                    };
-
-                    self.pc += 9;
-                }
-                Inst::SET_GVAR => {
-                    let var_id = self.read_id(iseq, 1);
-                    let new_val = self.stack_pop();
-                    self.set_global_var(var_id, new_val);
-                    self.pc += 5;
-                }
-                Inst::GET_GVAR => {
-                    let var_id = self.read_id(iseq, 1);
-                    let val = self.get_global_var(var_id);
-                    self.stack_push(val);
-                    self.pc += 5;
-                }
-                Inst::SET_INDEX => {
-                    let arg_num = self.read_usize(iseq, 1);
-                    let mut args = self.pop_args_to_ary(arg_num);
-                    let receiver = self.stack_pop();
-                    let val = self.stack_pop();
-                    match receiver.is_object() {
-                        Some(oref) => {
-                            match &oref.kind {
-                                ObjKind::Array(mut aref) => {
-                                    args.push(val);
-                                    aref.set_elem(self, &args)?;
+                        return res;
+                    }
+                    Op::PushNil => self.stack_push(Value::nil()),
+                    Op::PushTrue => self.stack_push(Value::true_val()),
+                    Op::PushFalse => self.stack_push(Value::false_val()),
+                    Op::PushSelf => self.stack_push(context.self_value),
+                    Op::PushFixnum(num) => self.stack_push(Value::fixnum(num)),
+                    Op::PushFlonum(num) => self.stack_push(Value::flonum(num)),
+                    Op::PushString(id) => {
+                        let string = self.globals.get_ident_name(id).to_string();
+                        self.stack_push(Value::string(&self.globals, string));
+                    }
+                    Op::PushSymbol(id) => self.stack_push(Value::symbol(id)),
+                    Op::Add(cache) => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        try_err!(self, self.eval_add(lhs, rhs, cache));
+                    }
+                    Op::AddI(i) => {
+                        let lhs = self.stack_pop();
+                        let val = self.eval_addi(lhs, i)?;
+                        self.stack_push(val);
+                    }
+                    Op::Sub(cache) => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        try_err!(self, self.eval_sub(lhs, rhs, cache));
+                    }
+                    Op::SubI(i) => {
+                        let lhs = self.stack_pop();
+                        let val = self.eval_subi(lhs, i)?;
+                        self.stack_push(val);
+                    }
+                    Op::Mul(cache) => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = self.eval_mul(lhs, rhs, cache)?;
+                        self.stack_push(val);
+                    }
+                    Op::Pow => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = self.eval_exp(lhs, rhs)?;
+                        self.stack_push(val);
+                    }
+                    Op::Div(cache) => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = self.eval_div(lhs, rhs, cache)?;
+                        self.stack_push(val);
+                    }
+                    Op::Rem => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = self.eval_rem(lhs, rhs)?;
+                        self.stack_push(val);
+                    }
+                    Op::Shr => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = self.eval_shr(lhs, rhs)?;
+                        self.stack_push(val);
+                    }
+                    Op::Shl(cache) => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = self.eval_shl(lhs, rhs, cache)?;
+                        self.stack_push(val);
+                    }
+                    Op::BitAnd => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = self.eval_bitand(lhs, rhs)?;
+                        self.stack_push(val);
+                    }
+                    Op::BitOr => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = self.eval_bitor(lhs, rhs)?;
+                        self.stack_push(val);
+                    }
+                    Op::BitXor => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = self.eval_bitxor(lhs, rhs)?;
+                        self.stack_push(val);
+                    }
+                    Op::BitNot => {
+                        let lhs = self.stack_pop();
+                        let val = self.eval_bitnot(lhs)?;
+                        self.stack_push(val);
+                    }
+                    Op::Eq => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = Value::bool(self.eval_eq(rhs, lhs)?);
+                        self.stack_push(val);
+                    }
+                    Op::Ne => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = Value::bool(!self.eval_eq(rhs, lhs)?);
+                        self.stack_push(val);
+                    }
+                    Op::Teq => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let res = self.eval_teq(rhs, lhs)?;
+                        let val = Value::bool(res);
+                        self.stack_push(val);
+                    }
+                    Op::Gt => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = self.eval_gt(lhs, rhs)?;
+                        self.stack_push(val);
+                    }
+                    Op::Ge => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = self.eval_ge(lhs, rhs)?;
+                        self.stack_push(val);
+                    }
+                    Op::Cmp => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let val = self.eval_cmp(rhs, lhs)?;
+                        self.stack_push(val);
+                    }
+                    Op::Not => {
+                        let lhs = self.stack_pop();
+                        let val = Value::bool(!self.val_to_bool(lhs));
+                        self.stack_push(val);
+                    }
+                    Op::ConcatString => {
+                        let rhs = self.stack_pop();
+                        let lhs = self.stack_pop();
+                        let val = match (lhs.as_string(), rhs.as_string()) {
+                            (Some(lhs), Some(rhs)) => {
+                                Value::string(&self.globals, format!("{}{}", lhs, rhs))
+                            }
+                            (_, _) => unreachable!("Illegal CAONCAT_STRING arguments."),
+                        };
+                        self.stack_push(val);
+                    }
+                    Op::SetLocal(id, outer) => {
+                        let val = self.stack_pop();
+                        let mut cref = self.get_outer_context(outer);
+                        cref[id] = val;
+                    }
+                    Op::GetLocal(id, outer) => {
+                        let cref = self.get_outer_context(outer);
+                        let val = cref[id];
+                        self.stack_push(val);
+                    }
+                    Op::LvarAddI(id, outer, i) => {
+                        let mut cref = self.get_outer_context(outer);
+                        let val = self.eval_addi(cref[id], i)?;
+                        cref[id] = val;
+                    }
+                    Op::CheckLocal(id, outer) => {
+                        let cref = self.get_outer_context(outer);
+                        let val = cref[id].is_uninitialized();
+                        self.stack_push(Value::bool(val));
+                    }
+                    Op::SetConst(id) => {
+                        let parent = match self.stack_pop() {
+                            v if v == Value::nil() => self.class(),
+                            v => v,
+                        };
+                        let val = self.stack_pop();
+                        match val.as_module() {
+                            Some(mut cref) => {
+                                if cref.name == None {
+                                    cref.name = Some(id);
                                 }
-                                ObjKind::Hash(mut href) => href.insert(args[0], val),
-                                _ => return Err(self.error_undefined_method("[]=", receiver)),
-                            };
+                            }
+                            None => {}
                         }
-                        None => return Err(self.error_undefined_method("[]=", receiver)),
+                        parent.set_var(&self.globals, id, val);
+                        self.globals.const_version += 1;
                     }
-
-                    self.pc += 5;
-                }
-                Inst::GET_INDEX => {
-                    let arg_num = self.read_usize(iseq, 1);
-                    let args = self.pop_args_to_ary(arg_num);
-                    let arg_num = args.len();
-                    let receiver = self.stack_pop();
-                    let val = match receiver.is_object() {
-                        Some(oref) => match &oref.kind {
-                            ObjKind::Array(aref) => aref.get_elem(self, &args)?,
-                            ObjKind::Hash(href) => {
-                                self.check_args_range(arg_num, 1, 1)?;
-                                match href.get(&args[0]) {
-                                    Some(val) => *val,
-                                    None => Value::nil(),
-                                }
+                    Op::GetConst(id, slot) => {
+                        let val = self.get_const(id, slot)?;
+                        self.stack_push(val);
+                    }
+                    Op::GetConstTop(id) => {
+                        let class = self.globals.builtins.object;
+                        let val = self.get_super_const(class, id)?;
+                        self.stack_push(val);
+                    }
+                    Op::GetScope(id) => {
+                        let parent = self.stack_pop();
+                        try_err!(self, self.get_scope_const(parent, id));
+                    }
+                    Op::SetIvar(var_id, slot) => {
+                        let new_val = self.stack_pop();
+                        self.set_ivar(self_oref, var_id, slot, new_val);
+                    }
+                    Op::GetIvar(var_id, slot) => {
+                        let val = self.get_ivar(self_oref, var_id, slot);
+                        self.stack_push(val);
+                    }
+                    Op::IvarAddI(var_id, i, slot) => {
+                        let val = self.get_ivar(self_oref, var_id, slot);
+                        let new_val = self.eval_addi(val, i)?;
+                        self.set_ivar(self_oref, var_id, slot, new_val);
+                    }
+                    Op::SetGvar(var_id) => {
+                        let new_val = self.stack_pop();
+                        self.set_global_var(var_id, new_val);
+                    }
+                    Op::GetGvar(var_id) => {
+                        let val = self.get_global_var(var_id);
+                        self.stack_push(val);
+                    }
+                    Op::SetIndex(arg_num) => {
+                        let mut args = self.pop_args_to_ary(arg_num);
+                        let receiver = self.stack_pop();
+                        let val = self.stack_pop();
+                        match receiver.is_object() {
+                            Some(oref) => {
+                                match &oref.kind {
+                                    ObjKind::Array(mut aref) => {
+                                        args.push(val);
+                                        aref.set_elem(self, &args)?;
+                                    }
+                                    ObjKind::Hash(mut href) => href.insert(args[0], val),
+                                    _ => {
+                                        args.push(val);
+                                        let id = self.globals.get_ident_id("[]=");
+                                        match self.get_method(receiver, id) {
+                                            Ok(mref) => self.eval_send(mref, receiver, &args)?,
+                                            Err(_) => {
+                                                return Err(
+                                                    self.error_undefined_method("[]=", receiver)
+                                                )
+                                            }
+                                        };
+                                    }
+                                };
                             }
-                            ObjKind::Method(mref) => {
-                                self.eval_send(mref.method, mref.receiver, &args)?
+                            None => return Err(self.error_undefined_method("[]=", receiver)),
+                        }
+                    }
+                    Op::GetIndex(arg_num) => {
+                        let args = self.pop_args_to_ary(arg_num);
+                        let arg_num = args.len();
+                        let receiver = self.stack_pop();
+                        let val = match receiver.is_object() {
+                            Some(oref) => match &oref.kind {
+                                ObjKind::Array(aref) => aref.get_elem(self, &args)?,
+                                ObjKind::Hash(href) => {
+                                    self.check_args_range(arg_num, 1, 1)?;
+                                    match href.get(&args[0]) {
+                                        Some(val) => *val,
+                                        None => href.default_value(self, receiver, args[0])?,
+                                    }
+                                }
+                                ObjKind::Method(mref) => {
+                                    self.eval_send(mref.method, mref.receiver, &args)?
+                                }
+                                _ => {
+                                    let id = self.globals.get_ident_id("[]");
+                                    match self.get_method(receiver, id) {
+                                        Ok(mref) => self.eval_send(mref, receiver, &args)?,
+                                        Err(_) => {
+                                            return Err(self.error_undefined_method("[]", receiver))
+                                        }
+                                    }
+                                }
+                            },
+                            None if receiver.is_packed_fixnum() => {
+                                let i = receiver.as_packed_fixnum();
+                                self.check_args_range(arg_num, 1, 1)?;
+                                let index = args[0].expect_integer(&self, "Index")?;
+                                let val = if index < 0 || 63 < index {
+                                    0
+                                } else {
+                                    (i >> index) & 1
+                                };
+                                Value::fixnum(val)
                             }
                             _ => {
                                 let id = self.globals.get_ident_id("[]");
@@ -887,226 +1158,233 @@ impl VM {
                                     }
                                 }
                             }
-                        },
-                        None if receiver.is_packed_fixnum() => {
-                            let i = receiver.as_packed_fixnum();
-                            self.check_args_range(arg_num, 1, 1)?;
-                            let index = args[0].expect_integer(&self, "Index")?;
-                            let val = if index < 0 || 63 < index {
-                                0
-                            } else {
-                                (i >> index) & 1
-                            };
-                            Value::fixnum(val)
+                        };
+                        self.stack_push(val);
+                    }
+                    Op::Splat => {
+                        let val = self.stack_pop();
+                        let res = Value::splat(&self.globals, val);
+                        self.stack_push(res);
+                    }
+                    Op::MergeKw => {
+                        let val = self.stack_pop();
+                        let hash = self.stack_pop();
+                        let merged = self.merge_kw(hash, val)?;
+                        self.stack_push(merged);
+                    }
+                    Op::CreateRange => {
+                        let start = self.stack_pop();
+                        let end = self.stack_pop();
+                        if !start.is_packed_fixnum() || !end.is_packed_fixnum() {
+                            return Err(self.error_argument("Bad value for range."));
+                        };
+                        let exclude_val = self.stack_pop();
+                        let exclude_end = self.val_to_bool(exclude_val);
+                        let range = Value::range(&self.globals, start, end, exclude_end);
+                        self.stack_push(range);
+                    }
+                    Op::CreateArray(arg_num) => {
+                        let elems = self.pop_args_to_ary(arg_num).into_vec();
+                        let array = Value::array_from(&self.globals, elems);
+                        self.stack_push(array);
+                    }
+                    Op::CreateProc(method) => {
+                        // A lambda literal `->`.
+                        let proc_obj = self.create_lambda(method)?;
+                        self.stack_push(proc_obj);
+                    }
+                    Op::CreateHash(arg_num) => {
+                        let key_value = self.pop_key_value_pair(arg_num);
+                        let hash = Value::hash(&self.globals, HashRef::from(key_value));
+                        self.stack_push(hash);
+                    }
+                    Op::CreateRegexp => {
+                        let arg = self.stack_pop();
+                        let regexp = self.create_regexp(arg)?;
+                        self.stack_push(regexp);
+                    }
+                    Op::Jmp(dest) => index = dest,
+                    Op::JmpIfFalse(dest) => {
+                        let val = self.stack_pop();
+                        let cond = self.val_to_bool(val);
+                        if let Some(coverage) = &mut iseq_ref.coverage {
+                            coverage.branch(index - 1, !cond);
+                        }
+                        if !cond {
+                            index = dest;
                         }
-                        _ => return Err(self.error_undefined_method("[]", receiver)),
-                    };
-                    self.stack_push(val);
-                    self.pc += 5;
-                }
-                Inst::SPLAT => {
-                    let val = self.stack_pop();
-                    let res = Value::splat(&self.globals, val);
-                    self.stack_push(res);
-                    self.pc += 1;
-                }
-                Inst::CREATE_RANGE => {
-                    let start = self.stack_pop();
-                    let end = self.stack_pop();
-                    if !start.is_packed_fixnum() || !end.is_packed_fixnum() {
-                        return Err(self.error_argument("Bad value for range."));
-                    };
-                    let exclude_val = self.stack_pop();
-                    let exclude_end = self.val_to_bool(exclude_val);
-                    let range = Value::range(&self.globals, start, end, exclude_end);
-                    self.stack_push(range);
-                    self.pc += 1;
-                }
-                Inst::CREATE_ARRAY => {
-                    let arg_num = self.read_usize(iseq, 1);
-                    let elems = self.pop_args_to_ary(arg_num).into_vec();
-                    let array = Value::array_from(&self.globals, elems);
-                    self.stack_push(array);
-                    self.pc += 5;
-                }
-                Inst::CREATE_PROC => {
-                    let method = self.read_methodref(iseq, 1);
-                    let proc_obj = self.create_proc(method)?;
-                    self.stack_push(proc_obj);
-                    self.pc += 5;
-                }
-                Inst::CREATE_HASH => {
-                    let arg_num = self.read_usize(iseq, 1);
-                    let key_value = self.pop_key_value_pair(arg_num);
-                    let hash = Value::hash(&self.globals, HashRef::from(key_value));
-                    self.stack_push(hash);
-                    self.pc += 5;
-                }
-                Inst::CREATE_REGEXP => {
-                    let arg = self.stack_pop();
-                    let regexp = self.create_regexp(arg)?;
-                    self.stack_push(regexp);
-                    self.pc += 1;
-                }
-                Inst::JMP => {
-                    let disp = self.read_disp(iseq, 1);
-                    self.jump_pc(5, disp);
-                }
-                Inst::JMP_IF_FALSE => {
-                    let val = self.stack_pop();
-                    if self.val_to_bool(val) {
-                        self.jump_pc(5, 0);
-                    } else {
-                        let disp = self.read_disp(iseq, 1);
-                        self.jump_pc(5, disp);
                     }
-                }
-                Inst::OPT_CASE => {
-                    let val = self.stack_pop();
-                    let map = self.globals.get_case_dispatch_map(self.read32(iseq, 1));
-                    let disp = match map.get(&val) {
-                        Some(disp) => *disp as i64,
-                        None => self.read_disp(iseq, 5),
-                    };
-                    self.jump_pc(9, disp);
-                }
-                Inst::SEND => {
-                    let receiver = self.stack_pop();
-                    try_err!(self, self.vm_send(iseq, receiver));
-                    self.pc += 17;
-                }
-                Inst::SEND_SELF => {
-                    let receiver = context.self_value;
-                    try_err!(self, self.vm_send(iseq, receiver));
-                    self.pc += 17;
-                }
-                Inst::YIELD => {
-                    try_err!(self, self.eval_yield(iseq));
-                    self.pc += 5;
-                }
-                Inst::DEF_CLASS => {
-                    let is_module = self.read8(iseq, 1) == 1;
-                    let id = self.read_id(iseq, 2);
-                    let method = self.read_methodref(iseq, 6);
-                    let super_val = self.stack_pop();
-                    let val = match self.globals.builtins.object.get_var(id) {
-                        Some(val) => {
-                            if val.is_module().is_some() != is_module {
-                                return Err(self.error_type(format!(
-                                    "{} is not {}.",
-                                    self.globals.get_ident_name(id),
-                                    if is_module { "module" } else { "class" },
-                                )));
-                            };
-                            let classref = self.expect_module(val.clone())?;
-                            if !super_val.is_nil() && classref.superclass.id() != super_val.id() {
-                                return Err(self.error_type(format!(
-                                    "superclass mismatch for class {}.",
-                                    self.globals.get_ident_name(id),
-                                )));
-                            };
-                            val.clone()
+                    Op::Jeq(dest) | Op::Jne(dest) | Op::Jgt(dest) | Op::Jge(dest) => {
+                        let lhs = self.stack_pop();
+                        let rhs = self.stack_pop();
+                        let cond = match inst.op {
+                            Op::Jeq(_) => self.eval_eq(rhs, lhs)?,
+                            Op::Jne(_) => !self.eval_eq(rhs, lhs)?,
+                            Op::Jgt(_) => {
+                                let val = self.eval_gt(lhs, rhs)?;
+                                self.val_to_bool(val)
+                            }
+                            _ => {
+                                let val = self.eval_ge(lhs, rhs)?;
+                                self.val_to_bool(val)
+                            }
+                        };
+                        if let Some(coverage) = &mut iseq_ref.coverage {
+                            coverage.branch(index - 1, !cond);
                         }
-                        None => {
-                            let super_val = if super_val.is_nil() {
-                                self.globals.builtins.object
-                            } else {
-                                self.expect_class(super_val, "Superclass")?;
-                                super_val
-                            };
-                            let classref = ClassRef::from(id, super_val);
-                            let val = if is_module {
-                                Value::module(&mut self.globals, classref)
-                            } else {
-                                Value::class(&mut self.globals, classref)
-                            };
-                            self.class().set_var(id, val);
-                            val
+                        if !cond {
+                            index = dest;
                         }
-                    };
-
-                    self.class_push(val);
-                    let mut iseq = self.get_iseq(method)?;
-                    iseq.class_defined = self.gen_class_defined(val);
-                    let arg = Args::new0();
-                    try_err!(self, self.eval_send(method, val, &arg));
-                    self.pc += 10;
-                    self.class_pop();
-                }
-                Inst::DEF_METHOD => {
-                    let id = self.read_id(iseq, 1);
-                    let method = self.read_methodref(iseq, 5);
-                    let mut iseq = self.get_iseq(method)?;
-                    iseq.class_defined = self.gen_class_defined(None);
-                    self.define_method(id, method);
-                    if self.define_mode().module_function {
-                        self.define_singleton_method(self.class(), id, method)?;
-                    };
-                    self.pc += 9;
-                }
-                Inst::DEF_SMETHOD => {
-                    let id = self.read_id(iseq, 1);
-                    let method = self.read_methodref(iseq, 5);
-                    let mut iseq = self.get_iseq(method)?;
-                    iseq.class_defined = self.gen_class_defined(None);
-                    let singleton = self.stack_pop();
-                    self.define_singleton_method(singleton, id, method)?;
-                    if self.define_mode().module_function {
+                    }
+                    Op::OptCase(map_id, default) => {
+                        let val = self.stack_pop();
+                        let map = self.globals.get_case_dispatch_map(map_id);
+                        index = match map.get(&val) {
+                            Some(disp) => {
+                                decoded.index_of((self.pc as i64 + 9 + *disp as i64) as usize)
+                            }
+                            None => default,
+                        };
+                    }
+                    Op::Send {
+                        id,
+                        args,
+                        flag,
+                        cache,
+                        block,
+                        self_call,
+                    } => {
+                        invoke!(self.vm_send_invoke(id, args, flag, cache, block, self_call));
+                    }
+                    Op::OptSend {
+                        id,
+                        args,
+                        cache,
+                        self_call,
+                    } => {
+                        let receiver = if self_call {
+                            context.self_value
+                        } else {
+                            self.stack_pop()
+                        };
+                        invoke!(self.vm_opt_send_invoke(receiver, id, args, cache, self_call));
+                    }
+                    Op::Yield { args, kw } => {
+                        invoke!(self.eval_yield_invoke(args, kw));
+                    }
+                    Op::DefClass {
+                        is_module,
+                        id,
+                        method,
+                    } => {
+                        let super_val = self.stack_pop();
+                        let val = match self.globals.builtins.object.get_var(id) {
+                            Some(val) => {
+                                if val.is_module().is_some() != is_module {
+                                    return Err(self.error_type(format!(
+                                        "{} is not {}.",
+                                        self.globals.get_ident_name(id),
+                                        if is_module { "module" } else { "class" },
+                                    )));
+                                };
+                                let classref = self.expect_module(val.clone())?;
+                                if !super_val.is_nil() && classref.superclass.id() != super_val.id()
+                                {
+                                    return Err(self.error_type(format!(
+                                        "superclass mismatch for class {}.",
+                                        self.globals.get_ident_name(id),
+                                    )));
+                                };
+                                val.clone()
+                            }
+                            None => {
+                                let super_val = if super_val.is_nil() {
+                                    self.globals.builtins.object
+                                } else {
+                                    self.expect_class(super_val, "Superclass")?;
+                                    super_val
+                                };
+                                let classref = ClassRef::from(id, super_val);
+                                let val = if is_module {
+                                    Value::module(&mut self.globals, classref)
+                                } else {
+                                    Value::class(&mut self.globals, classref)
+                                };
+                                self.class().set_var(&self.globals, id, val);
+                                self.globals.const_version += 1;
+                                val
+                            }
+                        };
+
+                        self.class_push(val);
+                        let mut iseq = self.get_iseq(method)?;
+                        iseq.class_defined = self.gen_class_defined(val);
+                        let arg = Args::new0();
+                        try_err!(self, self.eval_send(method, val, &arg));
+                        self.class_pop();
+                    }
+                    Op::DefMethod(id, method) => {
+                        let mut iseq = self.get_iseq(method)?;
+                        iseq.class_defined = self.gen_class_defined(None);
                         self.define_method(id, method);
-                    };
-                    self.pc += 9;
-                }
-                Inst::TO_S => {
-                    let val = self.stack_pop();
-                    let s = self.val_to_s(val);
-                    let res = Value::string(&self.globals, s);
-                    self.stack_push(res);
-                    self.pc += 1;
-                }
-                Inst::POP => {
-                    self.stack_pop();
-                    self.pc += 1;
-                }
-                Inst::DUP => {
-                    let len = self.read_usize(iseq, 1);
-                    let stack_len = self.exec_stack.len();
-                    for i in stack_len - len..stack_len {
-                        let val = self.exec_stack[i];
-                        self.stack_push(val);
+                        if self.define_mode().module_function {
+                            self.define_singleton_method(self.class(), id, method)?;
+                            self.set_method_visibility(self.class(), id, Visibility::Private)?;
+                        };
                     }
-                    self.pc += 5;
-                }
-                Inst::TAKE => {
-                    let len = self.read_usize(iseq, 1);
-                    let val = self.stack_pop();
-                    match val.as_array() {
-                        Some(info) => {
-                            let elem = &info.elements;
-                            let ary_len = elem.len();
-                            if len <= ary_len {
-                                for i in 0..len {
-                                    self.stack_push(elem[i]);
-                                }
-                            } else {
-                                for i in 0..ary_len {
-                                    self.stack_push(elem[i]);
+                    Op::DefSMethod(id, method) => {
+                        let mut iseq = self.get_iseq(method)?;
+                        iseq.class_defined = self.gen_class_defined(None);
+                        let singleton = self.stack_pop();
+                        self.define_singleton_method(singleton, id, method)?;
+                        if self.define_mode().module_function {
+                            self.define_method(id, method);
+                        };
+                    }
+                    Op::ToS => {
+                        let val = self.stack_pop();
+                        let s = self.val_to_s(val);
+                        let res = Value::string(&self.globals, s);
+                        self.stack_push(res);
+                    }
+                    Op::Pop => {
+                        self.stack_pop();
+                    }
+                    Op::Dup(len) => {
+                        let stack_len = self.exec_stack.len();
+                        for i in stack_len - len..stack_len {
+                            let val = self.exec_stack[i];
+                            self.stack_push(val);
+                        }
+                    }
+                    Op::Take(len) => {
+                        let val = self.stack_pop();
+                        match val.as_array() {
+                            Some(info) => {
+                                let elem = &info.elements;
+                                let ary_len = elem.len();
+                                if len <= ary_len {
+                                    for i in 0..len {
+                                        self.stack_push(elem[i]);
+                                    }
+                                } else {
+                                    for i in 0..ary_len {
+                                        self.stack_push(elem[i]);
+                                    }
+                                    for _ in ary_len..len {
+                                        self.stack_push(Value::nil());
+                                    }
                                 }
-                                for _ in ary_len..len {
+                            }
+                            None => {
+                                self.stack_push(val);
+                                for _ in 0..len - 1 {
                                     self.stack_push(Value::nil());
                                 }
                             }
                         }
-                        None => {
-                            self.stack_push(val);
-                            for _ in 0..len - 1 {
-                                self.stack_push(Value::nil());
-                            }
-                        }
                     }
-
-                    self.pc += 5;
                 }
-                _ => return Err(self.error_unimplemented("Unimplemented instruction.")),
             }
         }
     }
@@ -1197,14 +1475,74 @@ impl VM {
         )
     }
 
+    pub fn error_range(&self, msg: impl Into<String>) -> RubyError {
+        let loc = self.get_loc();
+        RubyError::new_runtime_err(RuntimeErrKind::Range(msg.into()), self.source_info(), loc)
+    }
+
+    pub fn error_float_domain(&self, msg: impl Into<String>) -> RubyError {
+        let loc = self.get_loc();
+        RubyError::new_runtime_err(
+            RuntimeErrKind::FloatDomain(msg.into()),
+            self.source_info(),
+            loc,
+        )
+    }
+
     pub fn error_index(&self, msg: impl Into<String>) -> RubyError {
         let loc = self.get_loc();
         RubyError::new_runtime_err(RuntimeErrKind::Index(msg.into()), self.source_info(), loc)
     }
 
-    pub fn error_fiber(&self, msg: impl Into<String>) -> RubyError {
+    pub fn error_key(&self, msg: impl Into<String>) -> RubyError {
+        let loc = self.get_loc();
+        RubyError::new_runtime_err(RuntimeErrKind::Key(msg.into()), self.source_info(), loc)
+    }
+
+    pub fn error_fiber(&self, msg: impl Into<String>) -> RubyError {
+        let loc = self.get_loc();
+        RubyError::new_runtime_err(RuntimeErrKind::Fiber(msg.into()), self.source_info(), loc)
+    }
+
+    pub fn error_io(&self, msg: impl Into<String>) -> RubyError {
+        let loc = self.get_loc();
+        RubyError::new_runtime_err(RuntimeErrKind::IO(msg.into()), self.source_info(), loc)
+    }
+
+    pub fn error_eof(&self) -> RubyError {
+        let loc = self.get_loc();
+        RubyError::new_runtime_err(
+            RuntimeErrKind::EOF("end of file reached".to_string()),
+            self.source_info(),
+            loc,
+        )
+    }
+
+    pub fn error_errno(&self, name: &str, msg: impl Into<String>) -> RubyError {
+        let loc = self.get_loc();
+        RubyError::new_runtime_err(
+            RuntimeErrKind::Errno(name.to_string(), msg.into()),
+            self.source_info(),
+            loc,
+        )
+    }
+
+    pub fn error_encoding(&self, kind: EncodingErrKind, msg: impl Into<String>) -> RubyError {
+        let loc = self.get_loc();
+        RubyError::new_runtime_err(
+            RuntimeErrKind::Encoding(kind, msg.into()),
+            self.source_info(),
+            loc,
+        )
+    }
+
+    pub fn error_system_stack(&self) -> RubyError {
         let loc = self.get_loc();
-        RubyError::new_runtime_err(RuntimeErrKind::Fiber(msg.into()), self.source_info(), loc)
+        RubyError::new_runtime_err(
+            RuntimeErrKind::SystemStack("stack level too deep".to_string()),
+            self.source_info(),
+            loc,
+        )
     }
 
     pub fn error_method_return(&self, method: MethodRef) -> RubyError {
@@ -1348,6 +1686,7 @@ impl VM {
         let sourcemap = &self.context().iseq_ref.iseq_sourcemap;
         sourcemap
             .iter()
+            .rev()
             .find(|x| x.0 == ISeqPos::from(self.pc))
             .unwrap_or(&(ISeqPos::from(0), Loc(0, 0)))
             .1
@@ -1381,6 +1720,74 @@ impl VM {
     }
 
     // Search class stack for the constant.
+    /// Look up the constant `id` from the current lexical scope and class,
+    /// using and refilling the constant cache `slot`.
+    pub(super) fn get_const(&mut self, id: IdentId, slot: u32) -> VMResult {
+        let class_list = self.get_nearest_class_stack();
+        let class = self.class();
+        if let Some(val) = self.globals.get_const_from_cache(slot, class_list, class) {
+            return Ok(val);
+        }
+        let val = match self.get_env_const(id) {
+            Some(val) => val,
+            None => self.get_super_const(class, id)?,
+        };
+        self.globals
+            .set_const_cache_entry(slot, class_list, class, val);
+        Ok(val)
+    }
+
+    /// Read the instance variable `id` of `obj` through the shape cache `slot`.
+    pub(super) fn get_ivar(&mut self, obj: ObjectRef, id: IdentId, slot: u32) -> Value {
+        let shape = match obj.shape() {
+            Some(shape) => shape,
+            None => return Value::nil(),
+        };
+        if let Some(entry) = self.globals.get_ivar_cache_entry(slot) {
+            if entry.shape == Some(shape) && entry.next.is_none() {
+                return obj.var_at(entry.index);
+            }
+        }
+        match shape.index_of(id) {
+            Some(index) => {
+                let entry = IvarCacheEntry {
+                    shape: Some(shape),
+                    next: None,
+                    index,
+                };
+                self.globals.set_ivar_cache_entry(slot, entry);
+                obj.var_at(index)
+            }
+            None => Value::nil(),
+        }
+    }
+
+    /// Write the instance variable `id` of `obj` through the shape cache `slot`.
+    pub(super) fn set_ivar(&mut self, mut obj: ObjectRef, id: IdentId, slot: u32, val: Value) {
+        let shape = obj.shape();
+        if let Some(entry) = self.globals.get_ivar_cache_entry(slot) {
+            if entry.shape == shape {
+                match entry.next {
+                    Some(next) => obj.push_var(next, val),
+                    None => *obj.var_at_mut(entry.index) = val,
+                }
+                return;
+            }
+        }
+        obj.set_var(&self.globals, id, val);
+        let next = obj.shape().unwrap();
+        let entry = IvarCacheEntry {
+            shape,
+            next: if Some(next) == shape {
+                None
+            } else {
+                Some(next)
+            },
+            index: next.index_of(id).unwrap(),
+        };
+        self.globals.set_ivar_cache_entry(slot, entry);
+    }
+
     fn get_env_const(&self, id: IdentId) -> Option<Value> {
         let mut class_list = match self.get_nearest_class_stack() {
             Some(list) => list,
@@ -1418,6 +1825,38 @@ impl VM {
         }
     }
 
+    /// Search class inheritance chain for the constant referenced with explicit scope (`Foo::Bar`).
+    /// Private constants are not accessible in this way.
+    pub fn get_scope_const(&mut self, mut class: Value, id: IdentId) -> VMResult {
+        loop {
+            match class.get_var(id) {
+                Some(val) => {
+                    match class.as_module() {
+                        Some(cref) if cref.is_private_constant(id) => {
+                            let class_name = self.val_inspect(class);
+                            let name = self.globals.get_ident_name(id);
+                            return Err(self.error_name(format!(
+                                "private constant {}::{} referenced",
+                                class_name, name
+                            )));
+                        }
+                        _ => {}
+                    }
+                    return Ok(val);
+                }
+                None => match class.superclass() {
+                    Some(superclass) => {
+                        class = superclass;
+                    }
+                    None => {
+                        let name = self.globals.get_ident_name(id);
+                        return Err(self.error_name(format!("Uninitialized constant {}.", name)));
+                    }
+                },
+            }
+        }
+    }
+
     pub fn get_global_var(&self, id: IdentId) -> Value {
         match self.globals.global_var.get(&id) {
             Some(val) => val.clone(),
@@ -1435,28 +1874,63 @@ impl VM {
 impl VM {
     /// Get a method from the method cache if saved in it.
     /// Otherwise, search a class chain for the method.
+    /// Private and protected methods are available only when `self_call` is true
+    /// or the method was called in the permitted context.
     fn get_method_from_cache(
         &mut self,
         cache_slot: u32,
         receiver: Value,
         method_id: IdentId,
+        self_call: bool,
     ) -> Result<MethodRef, RubyError> {
         let rec_class = receiver.get_class_object_for_method(&self.globals);
         if rec_class.is_nil() {
             return Err(self.error_unimplemented("receiver's class is nil."));
         };
-        match self
+        let (method, visibility) = match self
             .globals
             .get_method_from_inline_cache(cache_slot, rec_class)
         {
-            Some(method) => Ok(method),
+            Some(entry) => entry,
             _ => {
                 let method = self.get_instance_method(rec_class, method_id)?;
+                let visibility = self.get_method_visibility(rec_class, method_id);
                 self.globals
-                    .set_inline_cache_entry(cache_slot, rec_class, method);
-                Ok(method)
+                    .set_inline_cache_entry(cache_slot, rec_class, method, visibility);
+                (method, visibility)
             }
+        };
+        if !self_call && visibility != Visibility::Public {
+            self.check_visibility(receiver, rec_class, method_id, visibility)?;
         }
+        Ok(method)
+    }
+
+    /// Examine whether the private or protected method can be called from the current context.
+    fn check_visibility(
+        &mut self,
+        receiver: Value,
+        rec_class: Value,
+        method_id: IdentId,
+        visibility: Visibility,
+    ) -> Result<(), RubyError> {
+        let kind = match visibility {
+            Visibility::Public => return Ok(()),
+            Visibility::Private => "private",
+            Visibility::Protected => {
+                let self_value = self.context().self_value;
+                match self.get_method_owner(rec_class, method_id) {
+                    Some(owner) if self.is_kind_of(self_value, owner) => return Ok(()),
+                    _ => "protected",
+                }
+            }
+        };
+        let inspect = self.val_inspect(receiver);
+        let method_name = self.globals.get_ident_name(method_id);
+        Err(self.error_nomethod(format!(
+            "{} method `{}' called for {}",
+            kind, method_name, inspect
+        )))
     }
 
     fn fallback_to_method(&mut self, method: IdentId, lhs: Value, rhs: Value) -> VMResult {
@@ -1480,14 +1954,14 @@ impl VM {
         method: IdentId,
         cache: u32,
     ) -> VMResult {
-        let methodref = self.get_method_from_cache(cache, lhs, method)?;
+        let methodref = self.get_method_from_cache(cache, lhs, method, false)?;
         let arg = Args::new1(rhs);
         self.eval_send(methodref, lhs, &arg)
     }
 }
 
 macro_rules! eval_op_i {
-    ($vm:ident, $iseq:ident, $lhs:expr, $i:ident, $op:ident, $id:expr) => {
+    ($vm:ident, $lhs:expr, $i:ident, $op:ident, $id:expr) => {
         if $lhs.is_packed_fixnum() {
             return Ok(Value::fixnum($lhs.as_packed_fixnum().$op($i as i64)));
         } else if $lhs.is_packed_num() {
@@ -1503,15 +1977,14 @@ macro_rules! eval_op_i {
 }
 
 macro_rules! eval_op {
-    ($vm:ident, $iseq:ident, $rhs:expr, $lhs:expr, $op:ident, $id:expr) => {
+    ($vm:ident, $cache:ident, $rhs:expr, $lhs:expr, $op:ident, $id:expr) => {
         let val = match ($lhs.unpack(), $rhs.unpack()) {
             (RV::Integer(lhs), RV::Integer(rhs)) => Value::fixnum(lhs.$op(rhs)),
             (RV::Integer(lhs), RV::Float(rhs)) => Value::flonum((lhs as f64).$op(rhs)),
             (RV::Float(lhs), RV::Integer(rhs)) => Value::flonum(lhs.$op(rhs as f64)),
             (RV::Float(lhs), RV::Float(rhs)) => Value::flonum(lhs.$op(rhs)),
             _ => {
-                let cache = $vm.read32($iseq, 1);
-                return $vm.fallback_to_method_with_cache($lhs, $rhs, $id, cache);
+                return $vm.fallback_to_method_with_cache($lhs, $rhs, $id, $cache);
             }
         };
         return Ok(val);
@@ -1519,34 +1992,39 @@ macro_rules! eval_op {
 }
 
 impl VM {
-    fn eval_add(&mut self, rhs: Value, lhs: Value, iseq: &ISeq) -> VMResult {
+    // The arithmetic operators are also called from compiled code,
+    // but they should be inlined into run_frame.
+    #[inline(always)]
+    pub(super) fn eval_add(&mut self, rhs: Value, lhs: Value, cache: u32) -> VMResult {
         use std::ops::Add;
-        eval_op!(self, iseq, rhs, lhs, add, IdentId::_ADD);
+        eval_op!(self, cache, rhs, lhs, add, IdentId::_ADD);
     }
 
-    fn eval_sub(&mut self, rhs: Value, lhs: Value, iseq: &ISeq) -> VMResult {
+    #[inline(always)]
+    pub(super) fn eval_sub(&mut self, rhs: Value, lhs: Value, cache: u32) -> VMResult {
         use std::ops::Sub;
-        eval_op!(self, iseq, rhs, lhs, sub, IdentId::_SUB);
+        eval_op!(self, cache, rhs, lhs, sub, IdentId::_SUB);
     }
 
-    fn eval_mul(&mut self, rhs: Value, lhs: Value, iseq: &ISeq) -> VMResult {
+    #[inline(always)]
+    pub(super) fn eval_mul(&mut self, rhs: Value, lhs: Value, cache: u32) -> VMResult {
         use std::ops::Mul;
-        eval_op!(self, iseq, rhs, lhs, mul, IdentId::_MUL);
+        eval_op!(self, cache, rhs, lhs, mul, IdentId::_MUL);
     }
 
-    fn eval_addi(&mut self, lhs: Value, i: i32) -> VMResult {
+    pub(super) fn eval_addi(&mut self, lhs: Value, i: i32) -> VMResult {
         use std::ops::Add;
-        eval_op_i!(self, iseq, lhs, i, add, IdentId::_ADD);
+        eval_op_i!(self, lhs, i, add, IdentId::_ADD);
     }
 
-    fn eval_subi(&mut self, lhs: Value, i: i32) -> VMResult {
+    pub(super) fn eval_subi(&mut self, lhs: Value, i: i32) -> VMResult {
         use std::ops::Sub;
-        eval_op_i!(self, iseq, lhs, i, sub, IdentId::_SUB);
+        eval_op_i!(self, lhs, i, sub, IdentId::_SUB);
     }
 
-    fn eval_div(&mut self, rhs: Value, lhs: Value, iseq: &ISeq) -> VMResult {
+    pub(super) fn eval_div(&mut self, rhs: Value, lhs: Value, cache: u32) -> VMResult {
         use std::ops::Div;
-        eval_op!(self, iseq, rhs, lhs, div, IdentId::_DIV);
+        eval_op!(self, cache, rhs, lhs, div, IdentId::_DIV);
     }
 
     fn eval_rem(&mut self, rhs: Value, lhs: Value) -> VMResult {
@@ -1589,7 +2067,7 @@ impl VM {
         Ok(val)
     }
 
-    fn eval_shl(&mut self, rhs: Value, lhs: Value, iseq: &ISeq) -> VMResult {
+    fn eval_shl(&mut self, rhs: Value, lhs: Value, cache: u32) -> VMResult {
         if lhs.is_packed_fixnum() && rhs.is_packed_fixnum() {
             return Ok(Value::fixnum(
                 lhs.as_packed_fixnum() << rhs.as_packed_fixnum(),
@@ -1611,7 +2089,6 @@ impl VM {
             },
             _ => {}
         };
-        let cache = self.read32(iseq, 1);
         let val = self.fallback_to_method_with_cache(lhs, rhs, IdentId::_SHL, cache)?;
         Ok(val)
     }
@@ -1624,7 +2101,10 @@ impl VM {
         }
         match (lhs.unpack(), rhs.unpack()) {
             (RV::Integer(lhs), RV::Integer(rhs)) => Ok(Value::fixnum(lhs >> rhs)),
-            (_, _) => return Err(self.error_undefined_op(">>", rhs, lhs)),
+            (_, _) => {
+                let id = self.globals.get_ident_id(">>");
+                self.fallback_to_method(id, lhs, rhs)
+            }
         }
     }
 
@@ -1636,7 +2116,10 @@ impl VM {
         }
         match (lhs.unpack(), rhs.unpack()) {
             (RV::Integer(lhs), RV::Integer(rhs)) => Ok(Value::fixnum(lhs & rhs)),
-            (_, _) => return Err(self.error_undefined_op("&", rhs, lhs)),
+            (_, _) => {
+                let id = self.globals.get_ident_id("&");
+                self.fallback_to_method(id, lhs, rhs)
+            }
         }
     }
 
@@ -1648,7 +2131,10 @@ impl VM {
         }
         match (lhs.unpack(), rhs.unpack()) {
             (RV::Integer(lhs), RV::Integer(rhs)) => Ok(Value::fixnum(lhs | rhs)),
-            (_, _) => return Err(self.error_undefined_op("|", rhs, lhs)),
+            (_, _) => {
+                let id = self.globals.get_ident_id("|");
+                self.fallback_to_method(id, lhs, rhs)
+            }
         }
     }
 
@@ -1660,7 +2146,10 @@ impl VM {
         }
         match (lhs.unpack(), rhs.unpack()) {
             (RV::Integer(lhs), RV::Integer(rhs)) => Ok(Value::fixnum(lhs ^ rhs)),
-            (_, _) => return Err(self.error_undefined_op("^", rhs, lhs)),
+            (_, _) => {
+                let id = self.globals.get_ident_id("^");
+                self.fallback_to_method(id, lhs, rhs)
+            }
         }
     }
 
@@ -1709,13 +2198,17 @@ impl VM {
                     let res = Regexp::find_one(self, &re.regexp, &given)?.is_some();
                     Ok(res)
                 }
+                ObjKind::Proc(_) => {
+                    let res = self.eval_proc(lhs, &Args::new1(rhs))?;
+                    Ok(self.val_to_bool(res))
+                }
                 _ => Ok(self.eval_eq(lhs, rhs).unwrap_or(false)),
             },
             None => Ok(self.eval_eq(lhs, rhs).unwrap_or(false)),
         }
     }
 
-    fn eval_ge(&mut self, rhs: Value, lhs: Value) -> VMResult {
+    pub(super) fn eval_ge(&mut self, rhs: Value, lhs: Value) -> VMResult {
         eval_cmp!(self, rhs, lhs, ge, IdentId::_GE)
     }
 
@@ -1813,6 +2306,8 @@ impl VM {
             RV::Symbol(i) => format!("{}", self.globals.get_ident_name(i)),
             RV::Object(oref) => match &oref.kind {
                 ObjKind::String(s) => s.to_s(),
+                ObjKind::Encoding(enc) => enc.name().to_string(),
+                ObjKind::MatchData(mref) => mref.to_s(),
                 ObjKind::Class(cref) => match cref.name {
                     Some(id) => format! {"{}", self.globals.get_ident_name(id)},
                     None => format! {"#<Class:0x{:x}>", cref.id()},
@@ -1822,6 +2317,7 @@ impl VM {
                 ObjKind::Range(rinfo) => rinfo.to_s(self),
                 ObjKind::Regexp(rref) => format!("({})", rref.regexp.as_str().to_string()),
                 ObjKind::Hash(href) => href.to_s(self),
+                ObjKind::Time(tref) => tref.to_s(),
                 _ => format!("{:?}", oref.kind),
             },
         }
@@ -1843,7 +2339,9 @@ impl VM {
                     f.to_string()
                 }
             }
-            RV::Symbol(sym) => format!(":{}", self.globals.get_ident_name(sym)),
+            RV::Symbol(sym) => {
+                crate::builtin::symbol::inspect_symbol(self.globals.get_ident_name(sym))
+            }
             RV::Object(oref) => match &oref.kind {
                 ObjKind::String(s) => s.inspect(),
                 ObjKind::Range(rinfo) => rinfo.inspect(self),
@@ -1858,7 +2356,13 @@ impl VM {
                 ObjKind::Array(aref) => aref.to_s(self),
                 ObjKind::Regexp(rref) => format!("/{}/", rref.regexp.as_str().to_string()),
                 ObjKind::Ordinary => oref.inspect(self),
-                ObjKind::Proc(pref) => format!("#<Proc:0x{:x}>", pref.id()),
+                ObjKind::Proc(pref) => {
+                    if pref.is_lambda {
+                        format!("#<Proc:0x{:x} (lambda)>", pref.id())
+                    } else {
+                        format!("#<Proc:0x{:x}>", pref.id())
+                    }
+                }
                 ObjKind::Hash(href) => href.to_s(self),
                 _ => {
                     let id = self.globals.get_ident_id("inspect");
@@ -1881,40 +2385,193 @@ impl VM {
 }
 
 impl VM {
-    fn vm_send(&mut self, iseq: &ISeq, receiver: Value) -> VMResult {
-        let method_id = self.read_id(iseq, 1);
-        let args_num = self.read16(iseq, 5);
-        let flag = self.read16(iseq, 7);
-        let cache_slot = self.read32(iseq, 9);
-        let block = self.read32(iseq, 13);
-        let methodref = self.get_method_from_cache(cache_slot, receiver, method_id)?;
+    /// Call a method. `self_call` is true for a method call without an explicit receiver or with `self`.
+    pub(super) fn vm_send(
+        &mut self,
+        method_id: IdentId,
+        args_num: u16,
+        flag: u16,
+        cache_slot: u32,
+        block: MethodRef,
+        self_call: bool,
+    ) -> VMResult {
+        let invoke =
+            self.vm_send_invoke(method_id, args_num, flag, cache_slot, block, self_call)?;
+        self.run_invoke(invoke)
+    }
 
-        let keyword = if flag & 0b01 == 1 {
-            let val = self.stack_pop();
-            Some(val)
+    fn vm_send_invoke(
+        &mut self,
+        method_id: IdentId,
+        args_num: u16,
+        flag: u16,
+        cache_slot: u32,
+        block: MethodRef,
+        self_call: bool,
+    ) -> Result<Invoke, RubyError> {
+        let receiver = if self_call {
+            self.context().self_value
         } else {
-            None
+            self.stack_pop()
         };
-        let mut args = self.pop_args_to_ary(args_num as usize);
-        let block = if block != 0 {
-            Some(MethodRef::from(block))
+        let methodref = self.get_method_from_cache(cache_slot, receiver, method_id, self_call)?;
+
+        let block = if !block.is_none() {
+            Some(block)
         } else if flag & 0b10 == 2 {
             let val = self.stack_pop();
-            let method = val
-                .as_proc()
-                .ok_or_else(|| self.error_argument("Block argument must be Proc."))?
-                .context
-                .iseq_ref
-                .method;
-            Some(method)
+            if val.is_nil() {
+                None
+            } else {
+                Some(self.proc_to_block(val)?)
+            }
+        } else {
+            None
+        };
+        let keyword = if flag & 0b01 == 1 {
+            self.pop_kw_arg()
         } else {
             None
         };
+        let mut args = self.pop_args_to_ary(args_num as usize);
         args.block = block;
         args.kw_arg = keyword;
-        let val = self.eval_send(methodref, receiver, &args)?;
+        self.invoke(methodref, receiver, None, &args)
+    }
+
+    /// Call a method with no block or keyword arguments.
+    /// Arguments are passed to a method with only required parameters without building `Args`,
+    /// and to other methods without going through `pop_args_to_ary` when possible.
+    pub(super) fn vm_opt_send(
+        &mut self,
+        receiver: Value,
+        method_id: IdentId,
+        args_num: u16,
+        cache_slot: u32,
+        self_call: bool,
+    ) -> VMResult {
+        let invoke =
+            self.vm_opt_send_invoke(receiver, method_id, args_num, cache_slot, self_call)?;
+        self.run_invoke(invoke)
+    }
+
+    fn vm_opt_send_invoke(
+        &mut self,
+        receiver: Value,
+        method_id: IdentId,
+        args_num: u16,
+        cache_slot: u32,
+        self_call: bool,
+    ) -> Result<Invoke, RubyError> {
+        let methodref = self.get_method_from_cache(cache_slot, receiver, method_id, self_call)?;
+        let stack_len = self.exec_stack.len();
+        let args_num = args_num as usize;
+        if self.exec_stack[stack_len - args_num..]
+            .iter()
+            .any(|arg| arg.as_splat().is_some())
+        {
+            let args = self.pop_args_to_ary(args_num);
+            return self.invoke(methodref, receiver, None, &args);
+        }
+        if let MethodInfo::RubyFunc { iseq } = self.globals.get_method_info(methodref) {
+            let iseq = *iseq;
+            if !iseq.is_block() && iseq.params.is_plain() && iseq.params.req_params == args_num {
+                let mut context = self.alloc_context(receiver, None, iseq, None);
+                for i in 0..args_num {
+                    context[i] = self.stack_pop();
+                }
+                return Ok(Invoke::Context(context));
+            }
+        }
+        let args = match args_num {
+            0 => Args::new0(),
+            1 => Args::new1(self.stack_pop()),
+            2 => {
+                let arg0 = self.stack_pop();
+                let arg1 = self.stack_pop();
+                Args::new2(arg0, arg1)
+            }
+            _ => self.pop_args_to_ary(args_num),
+        };
+        self.invoke(methodref, receiver, None, &args)
+    }
+
+    /// Prepare a call of `methodref`.
+    /// A Ruby method or block is not evaluated here, and its new context is returned.
+    fn invoke(
+        &mut self,
+        methodref: MethodRef,
+        self_val: Value,
+        outer: Option<ContextRef>,
+        args: &Args,
+    ) -> Result<Invoke, RubyError> {
+        if !methodref.is_none() {
+            if let MethodInfo::RubyFunc { iseq } = self.globals.get_method_info(methodref) {
+                let iseq = *iseq;
+                let mut context = self.alloc_context(self_val, args.block, iseq, outer);
+                context.set_args(self, args)?;
+                return Ok(Invoke::Context(context));
+            }
+        }
+        let val = self.eval_method(methodref, self_val, outer, args)?;
+        Ok(Invoke::Value(val))
+    }
+
+    /// A new context on the heap, reusing an allocation of a finished context if any.
+    fn alloc_context(
+        &mut self,
+        self_value: Value,
+        block: Option<MethodRef>,
+        iseq: ISeqRef,
+        outer: Option<ContextRef>,
+    ) -> Box<Context> {
+        match self.context_pool.pop() {
+            Some(mut context) => {
+                context.reset(self_value, block, iseq, outer);
+                context
+            }
+            None => Box::new(Context::new(self_value, block, iseq, outer)),
+        }
+    }
+
+    /// Keep the allocation of a finished context for `alloc_context`.
+    fn free_context(&mut self, context: Box<Context>) {
+        if self.context_pool.len() < CONTEXT_POOL_SIZE {
+            self.context_pool.push(context);
+        }
+    }
+
+    /// Evaluate a call prepared by `invoke`, with recursion of `run_context`.
+    fn run_invoke(&mut self, invoke: Invoke) -> VMResult {
+        let context = match invoke {
+            Invoke::Value(val) => return Ok(val),
+            Invoke::Context(context) => context,
+        };
+        #[allow(unused_variables, unused_mut)]
+        let mut inst: u8;
+        #[cfg(feature = "perf")]
+        #[cfg_attr(tarpaulin, skip)]
+        {
+            inst = self.perf.get_prev_inst();
+        }
+        let val = self.run_context(ContextRef::from_local(&context))?;
+        #[cfg(feature = "perf")]
+        #[cfg_attr(tarpaulin, skip)]
+        {
+            self.perf.get_perf_no_count(inst);
+        }
         Ok(val)
     }
+
+    /// Pop a hash of keyword arguments.
+    /// An empty hash (e.g. `**{}` or forwarded `...` without keywords) is treated as no keyword arguments.
+    fn pop_kw_arg(&mut self) -> Option<Value> {
+        let val = self.stack_pop();
+        match val.as_hash() {
+            Some(hash) if hash.len() == 0 => None,
+            _ => Some(val),
+        }
+    }
 }
 
 impl VM {
@@ -1929,10 +2586,11 @@ impl VM {
         self.eval_method(methodref, context.self_value, Some(context), args)
     }
 
-    /// Evaluate method with self_val of current context, caller context as outer context, and given `args`.
-    fn eval_yield(&mut self, iseq: &ISeq) -> VMResult {
-        let args_num = self.read32(iseq, 1) as usize;
-        let args = self.pop_args_to_ary(args_num);
+    /// Prepare a call of the block with self_val of current context, caller context as outer context, and given `args`.
+    fn eval_yield_invoke(&mut self, args_num: usize, kw: bool) -> Result<Invoke, RubyError> {
+        let keyword = if kw { self.pop_kw_arg() } else { None };
+        let mut args = self.pop_args_to_ary(args_num);
+        args.kw_arg = keyword;
         let mut context = self.context();
         loop {
             if let ISeqKind::Method(_) = context.kind {
@@ -1945,13 +2603,12 @@ impl VM {
         let method = context
             .block
             .ok_or_else(|| self.error_unimplemented("No block given."))?;
-        let res = self.eval_method(
+        self.invoke(
             method,
             self.context().self_value,
             Some(self.caller_context()),
             &args,
-        )?;
-        Ok(res)
+        )
     }
 
     /// Evaluate method with given `self_val`, `outer` context, and `args`.
@@ -2005,11 +2662,15 @@ impl VM {
             },
             MethodInfo::AttrWriter { id } => match self_val.is_object() {
                 Some(mut oref) => {
-                    oref.set_var(*id, args[0]);
+                    oref.set_var(&self.globals, *id, args[0]);
                     args[0]
                 }
                 None => unreachable!("AttrReader must be used only for class instance."),
             },
+            MethodInfo::ProcBlock { procobj } => {
+                let procobj = *procobj;
+                self.eval_proc(procobj, args)?
+            }
             MethodInfo::RubyFunc { iseq } => {
                 let iseq = *iseq;
                 let context = Context::from_args(self, self_val, iseq, args, outer)?;
@@ -2025,6 +2686,67 @@ impl VM {
         Ok(val)
     }
 
+    /// Evaluate Proc object `procobj` with given `args`.
+    /// A Proc is evaluated with its own self and outer context.
+    /// `return` in a lambda exits from the lambda itself.
+    pub fn eval_proc(&mut self, procobj: Value, args: &Args) -> VMResult {
+        let pref = match procobj.as_proc() {
+            Some(pref) => pref,
+            None => return Err(self.error_unimplemented("Expected Proc object.")),
+        };
+        let outer = match &pref.kind {
+            ProcKind::Block(context) => *context,
+            ProcKind::Builtin(builtin) => {
+                if pref.is_lambda {
+                    let arity = builtin.arity;
+                    if arity < 0 {
+                        self.check_args_min(args.len(), (-arity - 1) as usize)?;
+                    } else {
+                        self.check_args_num(args.len(), arity as usize)?;
+                    }
+                }
+                return (builtin.func)(self, procobj, args);
+            }
+        };
+        let iseq = outer.iseq_ref;
+        if pref.is_lambda {
+            Context::check_arity(self, iseq, args)?;
+        }
+        let context = Context::from_args(self, outer.self_value, iseq, args, outer.outer)?;
+        let stack_len = self.exec_stack.len();
+        match self.run_context(ContextRef::from_local(&context)) {
+            Err(err) if pref.is_lambda => match err.kind {
+                RubyErrorKind::MethodReturn(_) => {
+                    let val = self.stack_pop();
+                    self.exec_stack.truncate(stack_len);
+                    Ok(val)
+                }
+                _ => Err(err),
+            },
+            res => res,
+        }
+    }
+
+    /// Merge the `**hash` argument `val` into `hash`, the hash of keyword arguments
+    /// which is created for the call.
+    /// The entries of `val` override the former entries, without calling Hash#merge.
+    fn merge_kw(&mut self, hash: Value, val: Value) -> VMResult {
+        let other = match val.as_hash() {
+            Some(other) => other,
+            None => {
+                let inspect = self.val_inspect(val);
+                return Err(
+                    self.error_type(format!("No implicit conversion of {} into Hash.", inspect))
+                );
+            }
+        };
+        let mut href = hash.as_hash().unwrap();
+        for (k, v) in other.iter() {
+            href.insert(k, v);
+        }
+        Ok(hash)
+    }
+
     pub fn eval_enumerator(&mut self, eref: EnumRef) -> VMResult {
         eref.eval(self)
     }
@@ -2039,7 +2761,11 @@ impl VM {
             self.add_object_method(id, method);
         } else {
             // A method defined in a class definition is registered as an instance method of the class.
-            self.add_instance_method(self.class(), id, method);
+            // Its visibility follows the current default visibility.
+            let class = self.class();
+            let visibility = self.define_mode().visibility;
+            self.add_instance_method(class, id, method);
+            class.as_module().unwrap().set_visibility(id, visibility);
         }
     }
 
@@ -2097,6 +2823,20 @@ impl VM {
         Ok(method)
     }
 
+    /// Get method(MethodRef) for receiver as a call with an explicit receiver.
+    /// Calling a private method, or a protected method from outside, raises NoMethodError.
+    pub fn get_public_method(
+        &mut self,
+        receiver: Value,
+        method_id: IdentId,
+    ) -> Result<MethodRef, RubyError> {
+        let rec_class = receiver.get_class_object_for_method(&self.globals);
+        let method = self.get_instance_method(rec_class, method_id)?;
+        let visibility = self.get_method_visibility(rec_class, method_id);
+        self.check_visibility(receiver, rec_class, method_id, visibility)?;
+        Ok(method)
+    }
+
     /// Get instance method(MethodRef) for the class object.
     pub fn get_instance_method(
         &mut self,
@@ -2112,7 +2852,7 @@ impl VM {
             None => {}
         };
         let original_class = class;
-        let mut singleton_flag = original_class.as_class().is_singleton;
+        let mut singleton_flag = original_class.as_module().unwrap().is_singleton;
         loop {
             match class.get_instance_method(method) {
                 Some(methodref) => {
@@ -2140,6 +2880,86 @@ impl VM {
         }
     }
 
+    /// Get the class or module which has the method table entry of `method` for the class object.
+    pub fn get_method_owner(&self, mut class: Value, method: IdentId) -> Option<Value> {
+        let original_class = class;
+        let mut singleton_flag = original_class.as_module().unwrap().is_singleton;
+        loop {
+            match class.get_method_owner(method) {
+                Some(owner) => return Some(owner),
+                None => match class.superclass() {
+                    Some(superclass) => class = superclass,
+                    None => {
+                        if singleton_flag {
+                            singleton_flag = false;
+                            class = original_class.as_object().class();
+                        } else {
+                            return None;
+                        }
+                    }
+                },
+            };
+        }
+    }
+
+    /// Get visibility of the instance method for the class object.
+    pub fn get_method_visibility(&self, class: Value, method: IdentId) -> Visibility {
+        match self.get_method_owner(class, method) {
+            Some(owner) => owner.as_module().unwrap().get_visibility(method),
+            None => Visibility::Public,
+        }
+    }
+
+    /// Set visibility of the instance method of the class object.
+    /// If the method is inherited, the method is copied to the class with new visibility.
+    pub fn set_method_visibility(
+        &mut self,
+        class: Value,
+        method: IdentId,
+        visibility: Visibility,
+    ) -> Result<(), RubyError> {
+        let mut cref = self.expect_module(class)?;
+        if !cref.method_table.contains_key(&method) {
+            let methodref = match self.get_instance_method(class, method) {
+                Ok(methodref) => methodref,
+                Err(_) => {
+                    let inspect = self.val_inspect(class);
+                    let method_name = self.globals.get_ident_name(method);
+                    return Err(self.error_name(format!(
+                        "undefined method `{}' for {}",
+                        method_name, inspect
+                    )));
+                }
+            };
+            cref.method_table.insert(method, methodref);
+        }
+        self.globals.class_version += 1;
+        cref.set_visibility(method, visibility);
+        Ok(())
+    }
+
+    /// Examine whether `class` is in the ancestors of the class of `obj`.
+    pub fn is_kind_of(&self, obj: Value, class: Value) -> bool {
+        let mut target = obj.get_class_object(&self.globals);
+        loop {
+            if target.id() == class.id() {
+                return true;
+            }
+            match target.as_module() {
+                Some(cref) => {
+                    if target.includes_module(class) {
+                        return true;
+                    }
+                    target = cref.superclass;
+                }
+                None => return false,
+            }
+            if target.is_nil() {
+                return false;
+            }
+        }
+    }
+
     pub fn get_singleton_class(&mut self, obj: Value) -> VMResult {
         self.globals
             .get_singleton_class(obj)
@@ -2195,12 +3015,14 @@ impl VM {
         }
     }
 
+    /// Pop `arg_num` pairs of key and value.
+    /// The pairs are popped from the last one, so the latter one of the same keys wins.
     fn pop_key_value_pair(&mut self, arg_num: usize) -> HashMap<HashKey, Value> {
         let mut hash = HashMap::new();
         for _ in 0..arg_num {
             let value = self.stack_pop();
             let key = self.stack_pop();
-            hash.insert(HashKey(key), value);
+            hash.entry(HashKey(key)).or_insert(value);
         }
         hash
     }
@@ -2244,12 +3066,56 @@ impl VM {
 
     /// Create new Proc object from `method`,
     /// moving outer `Context`s on stack to heap.
+    /// If `method` is a Proc passed as a block argument, returns the Proc itself.
     pub fn create_proc(&mut self, method: MethodRef) -> VMResult {
+        if let MethodInfo::ProcBlock { procobj } = self.globals.get_method_info(method) {
+            return Ok(*procobj);
+        }
         self.move_outer_to_heap();
         let context = self.create_block_context(method)?;
         Ok(Value::procobj(&self.globals, context))
     }
 
+    /// Create new lambda from `method`,
+    /// moving outer `Context`s on stack to heap.
+    /// If `method` is a Proc passed as a block argument, returns the Proc itself.
+    pub fn create_lambda(&mut self, method: MethodRef) -> VMResult {
+        if let MethodInfo::ProcBlock { procobj } = self.globals.get_method_info(method) {
+            return Ok(*procobj);
+        }
+        self.move_outer_to_heap();
+        let context = self.create_block_context(method)?;
+        Ok(Value::lambda(&self.globals, context))
+    }
+
+    /// Get MethodRef for passing `val` as a block argument.
+    /// `val` is converted to Proc by `to_proc` if it is not a Proc.
+    pub fn proc_to_block(&mut self, val: Value) -> Result<MethodRef, RubyError> {
+        let procobj = if val.as_proc().is_some() {
+            val
+        } else {
+            let id = self.globals.get_ident_id("to_proc");
+            self.send0(val, id)?
+        };
+        let mut pref = match procobj.as_proc() {
+            Some(pref) => pref,
+            None => {
+                let inspect = self.val_inspect(val);
+                return Err(
+                    self.error_type(format!("Wrong argument type {} (expected Proc).", inspect))
+                );
+            }
+        };
+        match pref.block {
+            Some(method) => Ok(method),
+            None => {
+                let method = self.globals.add_method(MethodInfo::ProcBlock { procobj });
+                pref.block = Some(method);
+                Ok(method)
+            }
+        }
+    }
+
     /// Move outer execution contexts on the stack to the heap.
     fn move_outer_to_heap(&mut self) {
         let mut prev_ctx: Option<ContextRef> = None;
@@ -2257,6 +3123,12 @@ impl VM {
             if !context.on_stack {
                 break;
             };
+            if let Some(ctx) = prev_ctx {
+                // The outer context is not on the execution stack (e.g. a binding).
+                if ctx.outer != Some(*context) {
+                    break;
+                }
+            };
             let mut heap_context = context.dup();
             heap_context.on_stack = false;
             *context = heap_context;
@@ -2270,8 +3142,39 @@ impl VM {
         }
     }
 
+    /// Create new Binding object which captures the current context.
+    pub fn create_binding(&mut self) -> VMResult {
+        self.move_outer_to_heap();
+        let context = self.context();
+        Ok(self.create_binding_from(context))
+    }
+
+    /// Create new Binding object for `outer` context.
+    /// The binding has its own scope for local variables which are newly defined by eval.
+    pub fn create_binding_from(&mut self, outer: ContextRef) -> Value {
+        let mut info = ISeqInfo::default(MethodRef::from(0));
+        info.kind = ISeqKind::Block(outer.iseq_ref.method);
+        let iseq = ISeqRef::new(info);
+        let context = ContextRef::from(outer.self_value, outer.block, iseq, Some(outer));
+        Value::binding(&self.globals, context)
+    }
+
     /// Create a new execution context for a block.
     pub fn create_block_context(&mut self, method: MethodRef) -> Result<ContextRef, RubyError> {
+        if let MethodInfo::ProcBlock { procobj } = self.globals.get_method_info(method) {
+            let context = match procobj.as_proc().unwrap().context() {
+                Some(context) => context,
+                None => {
+                    return Err(self.error_argument("Can't run a builtin Proc in a new context."))
+                }
+            };
+            return Ok(ContextRef::from(
+                context.self_value,
+                None,
+                context.iseq_ref,
+                context.outer,
+            ));
+        }
         self.move_outer_to_heap();
         let iseq = self.get_iseq(method)?;
         let outer = self.context();
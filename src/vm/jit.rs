@@ -0,0 +1,1111 @@
+//! Baseline JIT compiler for x86-64 Linux.
+//!
+//! Methods which were called `JitConfig::threshold` times are compiled into
+//! machine code which works on the same VM stack and local variables as the
+//! interpreter does, so that execution can move between the two at any
+//! instruction boundary.
+//!
+//! - Pushes, pops, local variable accesses and jumps are inlined.
+//! - Arithmetic and comparison operators have an inlined fast path for Integers,
+//!   a call to a leaf function for Floats, and fall back to `VM::eval_*`.
+//! - Sends, constants and instance variables call into the VM.
+//! - Any other instruction is a side exit: the compiled code returns the index
+//!   of the instruction, which is executed by the interpreter, and the
+//!   interpreter re-enters the compiled code at the next instruction.
+//!
+//! The compiled code keeps the frame in `rbx`, the stack pointer in `r12` and
+//! the local variables of the current context in `r13`. The stack pointer is
+//! written back to the frame whenever the VM is called.
+use super::decode::Op;
+use crate::*;
+use std::convert::TryFrom;
+
+/// Settings of the JIT compiler given by `--jit` and `--jit-threshold`.
+#[derive(Debug, Clone, Copy)]
+pub struct JitConfig {
+    pub enabled: bool,
+    /// Number of calls after which a method is compiled.
+    pub threshold: usize,
+}
+
+impl Default for JitConfig {
+    fn default() -> Self {
+        JitConfig {
+            enabled: false,
+            threshold: 100,
+        }
+    }
+}
+
+impl JitConfig {
+    /// Whether the JIT compiler supports the current platform.
+    pub fn is_supported() -> bool {
+        cfg!(all(target_arch = "x86_64", target_os = "linux"))
+    }
+}
+
+/// Frame of compiled code, shared between the machine code and the VM.
+#[repr(C)]
+pub struct JitFrame {
+    /// Top of the VM stack.
+    sp: *mut Value,
+    /// Local variables of the current context.
+    lvars: *mut Value,
+    self_value: Value,
+    vm: VMRef,
+    /// Stack slots to be reserved for the compiled code after a VM call.
+    reserve: usize,
+    error: Option<(RubyError, bool)>,
+}
+
+const FRAME_SP: i32 = 0;
+const FRAME_LVARS: i32 = 8;
+const FRAME_SELF: i32 = 16;
+
+/// Local variables which are stored in the array of the context,
+/// and can be accessed from compiled code.
+const INLINE_LVARS: usize = 32;
+
+pub type JitEntry = extern "C" fn(*mut JitFrame, u64) -> u64;
+
+#[derive(Debug)]
+pub struct JitCode {
+    entry: JitEntry,
+    reserve: usize,
+}
+
+pub type JitCodeRef = Ref<JitCode>;
+
+/// How the execution of compiled code ended.
+pub enum JitExit {
+    /// The instruction at the index is to be executed by the interpreter.
+    Exit(usize),
+    /// An error was raised by a send or an operator which may call a method.
+    /// The index is of the next instruction.
+    Raise(usize, RubyError),
+    /// An error was raised by other instructions.
+    Error(RubyError),
+}
+
+impl JitCode {
+    pub fn run(&self, vm: &mut VM, index: usize) -> JitExit {
+        let mut context = vm.context();
+        let mut frame = JitFrame {
+            sp: vm.jit_stack_top(self.reserve),
+            lvars: context.lvar_ptr(),
+            self_value: context.self_value,
+            vm: VMRef::from_ref(vm),
+            reserve: self.reserve,
+            error: None,
+        };
+        let next = (self.entry)(&mut frame, index as u64) as usize;
+        unsafe { vm.jit_set_stack_top(frame.sp) };
+        match frame.error.take() {
+            None => JitExit::Exit(next),
+            Some((err, true)) => JitExit::Raise(next, err),
+            Some((err, false)) => JitExit::Error(err),
+        }
+    }
+}
+
+impl VM {
+    /// Count a call of `iseq`, and return its compiled code if it is hot.
+    /// Compiled code is not used while profiling or measuring coverage, as it does not
+    /// report each line.
+    pub fn get_jit_code(&mut self, mut iseq: ISeqRef) -> Option<JitCodeRef> {
+        if !self.globals.jit.enabled || self.profiler.is_some() || iseq.coverage.is_some() {
+            return None;
+        }
+        // Tail calls are only eliminated by the interpreter.
+        if iseq.tailcall {
+            return None;
+        }
+        match iseq.kind {
+            ISeqKind::Method(_) => {}
+            _ => return None,
+        }
+        if iseq.jit_code.is_none() && iseq.jit_counter < self.globals.jit.threshold {
+            iseq.jit_counter += 1;
+            if iseq.jit_counter == self.globals.jit.threshold {
+                iseq.jit_code = compile(&iseq.decoded);
+            }
+        }
+        iseq.jit_code
+    }
+}
+
+//----------------------------------------------------------------------------------
+
+/// Compile `decoded`, or return None if executable memory is not available.
+pub fn compile(decoded: &DecodedISeq) -> Option<JitCodeRef> {
+    let mut compiler = Compiler::new(decoded.insts.len());
+    let mut reserve = 1;
+    for (index, inst) in decoded.insts.iter().enumerate() {
+        reserve += compiler.gen(index, inst.op);
+    }
+    let code = compiler.finish();
+    let entry = map_executable(&code)?;
+    let entry: JitEntry = unsafe { std::mem::transmute(entry) };
+    Some(JitCodeRef::new(JitCode { entry, reserve }))
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn map_executable(code: &[u8]) -> Option<*const u8> {
+    extern "C" {
+        fn mmap(addr: *mut u8, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut u8;
+        fn mprotect(addr: *mut u8, len: usize, prot: i32) -> i32;
+    }
+    const PROT_READ: i32 = 1;
+    const PROT_WRITE: i32 = 2;
+    const PROT_EXEC: i32 = 4;
+    const MAP_PRIVATE: i32 = 2;
+    const MAP_ANONYMOUS: i32 = 0x20;
+    unsafe {
+        let ptr = mmap(
+            std::ptr::null_mut(),
+            code.len(),
+            PROT_READ | PROT_WRITE,
+            MAP_PRIVATE | MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        if ptr as isize == -1 {
+            return None;
+        }
+        std::ptr::copy_nonoverlapping(code.as_ptr(), ptr, code.len());
+        if mprotect(ptr, code.len(), PROT_READ | PROT_EXEC) != 0 {
+            return None;
+        }
+        Some(ptr)
+    }
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+fn map_executable(_code: &[u8]) -> Option<*const u8> {
+    None
+}
+
+//----------------------------------------------------------------------------------
+
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RDX: u8 = 2;
+const RBX: u8 = 3;
+const RSP: u8 = 4;
+const RBP: u8 = 5;
+const RSI: u8 = 6;
+const RDI: u8 = 7;
+const R12: u8 = 12;
+const R13: u8 = 13;
+const R14: u8 = 14;
+
+// Condition codes.
+const CC_O: u8 = 0x0;
+const CC_E: u8 = 0x4;
+const CC_NE: u8 = 0x5;
+const CC_A: u8 = 0x7;
+const CC_L: u8 = 0xc;
+const CC_LE: u8 = 0xe;
+
+type Label = usize;
+/// A function called from compiled code with the frame and the index of the instruction.
+type VmFn = extern "C" fn(&mut JitFrame, u64) -> u64;
+/// A leaf function for numeric operations on two values.
+type NumFn = extern "C" fn(u64, u64) -> u64;
+
+/// A minimal x86-64 assembler.
+struct Asm {
+    code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    /// Positions of rel32 displacements and their destinations.
+    fixups: Vec<(usize, Label)>,
+}
+
+impl Asm {
+    fn new() -> Self {
+        Asm {
+            code: vec![],
+            labels: vec![],
+            fixups: vec![],
+        }
+    }
+
+    fn label(&mut self) -> Label {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn bind(&mut self, label: Label) {
+        self.labels[label] = Some(self.code.len());
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn emit32(&mut self, val: u32) {
+        self.emit(&val.to_le_bytes());
+    }
+
+    fn rel32(&mut self, label: Label) {
+        self.fixups.push((self.code.len(), label));
+        self.emit32(0);
+    }
+
+    fn rex_w(&mut self, reg: u8, base: u8) {
+        self.emit(&[0x48 | (reg >> 3) << 2 | base >> 3]);
+    }
+
+    /// ModRM (and SIB) for `[base + disp]`.
+    fn mem(&mut self, reg: u8, base: u8, disp: i32) {
+        self.emit(&[0x80 | (reg & 7) << 3 | base & 7]);
+        if base & 7 == 4 {
+            self.emit(&[0x24]);
+        }
+        self.emit32(disp as u32);
+    }
+
+    fn push(&mut self, reg: u8) {
+        if reg >= 8 {
+            self.emit(&[0x41]);
+        }
+        self.emit(&[0x50 | reg & 7]);
+    }
+
+    fn pop(&mut self, reg: u8) {
+        if reg >= 8 {
+            self.emit(&[0x41]);
+        }
+        self.emit(&[0x58 | reg & 7]);
+    }
+
+    /// mov dst, src
+    fn mov_rr(&mut self, dst: u8, src: u8) {
+        self.op_rr(0x89, dst, src);
+    }
+
+    /// mov dst, imm64
+    fn mov_ri(&mut self, dst: u8, imm: u64) {
+        self.rex_w(0, dst);
+        self.emit(&[0xb8 | dst & 7]);
+        self.emit(&imm.to_le_bytes());
+    }
+
+    /// mov dst, [base + disp]
+    fn load(&mut self, dst: u8, base: u8, disp: i32) {
+        self.rex_w(dst, base);
+        self.emit(&[0x8b]);
+        self.mem(dst, base, disp);
+    }
+
+    /// mov [base + disp], src
+    fn store(&mut self, base: u8, disp: i32, src: u8) {
+        self.rex_w(src, base);
+        self.emit(&[0x89]);
+        self.mem(src, base, disp);
+    }
+
+    /// `op dst, src` of two registers, where `op` is the /r form with r/m as destination.
+    fn op_rr(&mut self, op: u8, dst: u8, src: u8) {
+        self.rex_w(src, dst);
+        self.emit(&[op, 0xc0 | (src & 7) << 3 | dst & 7]);
+    }
+
+    fn add_rr(&mut self, dst: u8, src: u8) {
+        self.op_rr(0x01, dst, src);
+    }
+
+    fn sub_rr(&mut self, dst: u8, src: u8) {
+        self.op_rr(0x29, dst, src);
+    }
+
+    fn and_rr(&mut self, dst: u8, src: u8) {
+        self.op_rr(0x21, dst, src);
+    }
+
+    fn cmp_rr(&mut self, dst: u8, src: u8) {
+        self.op_rr(0x39, dst, src);
+    }
+
+    fn test_rr(&mut self, dst: u8, src: u8) {
+        self.op_rr(0x85, dst, src);
+    }
+
+    /// `op dst, imm32` of the 0x81 group.
+    fn op_ri(&mut self, ext: u8, dst: u8, imm: i32) {
+        self.rex_w(0, dst);
+        self.emit(&[0x81, 0xc0 | ext << 3 | dst & 7]);
+        self.emit32(imm as u32);
+    }
+
+    fn add_ri(&mut self, dst: u8, imm: i32) {
+        self.op_ri(0, dst, imm);
+    }
+
+    fn sub_ri(&mut self, dst: u8, imm: i32) {
+        self.op_ri(5, dst, imm);
+    }
+
+    fn cmp_ri(&mut self, dst: u8, imm: i32) {
+        self.op_ri(7, dst, imm);
+    }
+
+    /// test dst, imm32
+    fn test_ri(&mut self, dst: u8, imm: i32) {
+        self.rex_w(0, dst);
+        self.emit(&[0xf7, 0xc0 | dst & 7]);
+        self.emit32(imm as u32);
+    }
+
+    fn jcc(&mut self, cc: u8, label: Label) {
+        self.emit(&[0x0f, 0x80 | cc]);
+        self.rel32(label);
+    }
+
+    fn jmp(&mut self, label: Label) {
+        self.emit(&[0xe9]);
+        self.rel32(label);
+    }
+
+    fn call(&mut self, func: usize) {
+        self.mov_ri(RAX, func as u64);
+        self.emit(&[0xff, 0xd0]);
+    }
+
+    fn ret(&mut self) {
+        self.emit(&[0xc3]);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for (pos, label) in std::mem::take(&mut self.fixups) {
+            let dest = self.labels[label].expect("JIT: unbound label.");
+            let disp = dest as i32 - (pos as i32 + 4);
+            self.code[pos..pos + 4].copy_from_slice(&disp.to_le_bytes());
+        }
+        self.code
+    }
+}
+
+//----------------------------------------------------------------------------------
+
+#[derive(Clone, Copy, PartialEq)]
+enum Arith {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Cmp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    /// The condition code of the negated comparison of two Integers.
+    fn not_cc(self) -> u8 {
+        match self {
+            Cmp::Eq => CC_NE,
+            Cmp::Ne => CC_E,
+            Cmp::Gt => CC_LE,
+            Cmp::Ge => CC_L,
+        }
+    }
+}
+
+struct Compiler {
+    asm: Asm,
+    /// Entry of each instruction.
+    insts: Vec<Label>,
+    epilogue: Label,
+    table: Label,
+}
+
+impl Compiler {
+    fn new(len: usize) -> Self {
+        let mut asm = Asm::new();
+        let insts = (0..len).map(|_| asm.label()).collect();
+        let epilogue = asm.label();
+        let table = asm.label();
+        let mut compiler = Compiler {
+            asm,
+            insts,
+            epilogue,
+            table,
+        };
+        compiler.prologue();
+        compiler
+    }
+
+    fn prologue(&mut self) {
+        let asm = &mut self.asm;
+        asm.push(RBP);
+        asm.mov_rr(RBP, RSP);
+        asm.push(RBX);
+        asm.push(R12);
+        asm.push(R13);
+        asm.push(R14);
+        asm.mov_rr(RBX, RDI);
+        asm.load(R12, RBX, FRAME_SP);
+        asm.load(R13, RBX, FRAME_LVARS);
+        // Jump to the entry of the instruction at index `rsi`.
+        // lea rax, [rip + table]
+        asm.emit(&[0x48, 0x8d, 0x05]);
+        asm.rel32(self.table);
+        // movsxd rcx, dword [rax + rsi * 4]
+        asm.emit(&[0x48, 0x63, 0x0c, 0xb0]);
+        asm.add_rr(RAX, RCX);
+        // jmp rax
+        asm.emit(&[0xff, 0xe0]);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let asm = &mut self.asm;
+        asm.bind(self.epilogue);
+        asm.store(RBX, FRAME_SP, R12);
+        asm.pop(R14);
+        asm.pop(R13);
+        asm.pop(R12);
+        asm.pop(RBX);
+        asm.pop(RBP);
+        asm.ret();
+        while asm.code.len() & 3 != 0 {
+            asm.emit(&[0xcc]);
+        }
+        asm.bind(self.table);
+        let table = asm.code.len();
+        for label in &self.insts {
+            let dest = asm.labels[*label].unwrap();
+            asm.emit32((dest as i32 - table as i32) as u32);
+        }
+        self.asm.finish()
+    }
+
+    /// Generate code for the instruction at `index`.
+    /// Return the maximum number of values the inlined code pushes.
+    fn gen(&mut self, index: usize, op: Op) -> usize {
+        self.asm.bind(self.insts[index]);
+        match op {
+            Op::PushNil => self.push_imm(Value::nil()),
+            Op::PushTrue => self.push_imm(Value::true_val()),
+            Op::PushFalse => self.push_imm(Value::false_val()),
+            Op::PushSymbol(id) => self.push_imm(Value::symbol(id)),
+            Op::PushFixnum(num) if Value::fixnum(num).is_packed_fixnum() => {
+                self.push_imm(Value::fixnum(num))
+            }
+            Op::PushFlonum(num) if Value::flonum(num).is_packed_flonum() => {
+                self.push_imm(Value::flonum(num))
+            }
+            Op::PushSelf => {
+                self.asm.load(RAX, RBX, FRAME_SELF);
+                self.push_reg(RAX);
+            }
+            Op::Pop => self.asm.sub_ri(R12, 8),
+            Op::Dup(len) => {
+                for _ in 0..len {
+                    self.asm.load(RAX, R12, -8 * len as i32);
+                    self.push_reg(RAX);
+                }
+                return len;
+            }
+            Op::GetLocal(id, 0) if id.as_usize() < INLINE_LVARS => {
+                self.asm.load(RAX, R13, 8 * id.as_usize() as i32);
+                self.push_reg(RAX);
+            }
+            Op::SetLocal(id, 0) if id.as_usize() < INLINE_LVARS => {
+                self.pop_reg(RAX);
+                self.asm.store(R13, 8 * id.as_usize() as i32, RAX);
+            }
+            Op::LvarAddI(id, 0, i) if id.as_usize() < INLINE_LVARS => {
+                self.gen_lvar_addi(index, 8 * id.as_usize() as i32, i)
+            }
+            Op::Jmp(dest) => {
+                let dest = self.insts[dest];
+                self.asm.jmp(dest);
+            }
+            Op::JmpIfFalse(dest) => {
+                self.pop_reg(RAX);
+                let dest = self.insts[dest];
+                let truthy = self.asm.label();
+                self.jmp_if_truthy(RAX, truthy);
+                self.asm.jmp(dest);
+                self.asm.bind(truthy);
+            }
+            Op::Not => {
+                self.pop_reg(RAX);
+                let truthy = self.asm.label();
+                let done = self.asm.label();
+                self.jmp_if_truthy(RAX, truthy);
+                self.asm.mov_ri(RAX, Value::true_val().id());
+                self.asm.jmp(done);
+                self.asm.bind(truthy);
+                self.asm.mov_ri(RAX, Value::false_val().id());
+                self.asm.bind(done);
+                self.push_reg(RAX);
+            }
+            Op::Add(_) => self.gen_arith(index, Arith::Add),
+            Op::Sub(_) => self.gen_arith(index, Arith::Sub),
+            Op::Mul(_) => self.gen_arith(index, Arith::Mul),
+            Op::Div(_) => self.gen_arith(index, Arith::Div),
+            Op::AddI(i) => self.gen_arith_i(index, i as i64),
+            Op::SubI(i) => self.gen_arith_i(index, -(i as i64)),
+            Op::Eq => self.gen_cmp(index, Cmp::Eq),
+            Op::Ne => self.gen_cmp(index, Cmp::Ne),
+            Op::Gt => self.gen_cmp(index, Cmp::Gt),
+            Op::Ge => self.gen_cmp(index, Cmp::Ge),
+            Op::Jeq(dest) => self.gen_cmp_jmp(index, Cmp::Eq, dest),
+            Op::Jne(dest) => self.gen_cmp_jmp(index, Cmp::Ne, dest),
+            Op::Jgt(dest) => self.gen_cmp_jmp(index, Cmp::Gt, dest),
+            Op::Jge(dest) => self.gen_cmp_jmp(index, Cmp::Ge, dest),
+            Op::Send { .. }
+            | Op::OptSend { .. }
+            | Op::GetConst(..)
+            | Op::GetIvar(..)
+            | Op::SetIvar(..)
+            | Op::IvarAddI(..) => self.call_exec(index),
+            _ => self.exit(index),
+        }
+        1
+    }
+
+    fn push_reg(&mut self, reg: u8) {
+        self.asm.store(R12, 0, reg);
+        self.asm.add_ri(R12, 8);
+    }
+
+    fn pop_reg(&mut self, reg: u8) {
+        self.asm.sub_ri(R12, 8);
+        self.asm.load(reg, R12, 0);
+    }
+
+    fn push_imm(&mut self, val: Value) {
+        self.asm.mov_ri(RAX, val.id());
+        self.push_reg(RAX);
+    }
+
+    /// Jump to `label` unless `reg` is nil, false or uninitialized (0x08, 0x00 and 0x04).
+    fn jmp_if_truthy(&mut self, reg: u8, label: Label) {
+        self.asm.cmp_ri(reg, 8);
+        self.asm.jcc(CC_A, label);
+        self.asm.test_ri(reg, 3);
+        self.asm.jcc(CC_NE, label);
+    }
+
+    /// Jump to `label` unless both `lhs` and `rhs` are packed Integers.
+    fn jmp_if_not_fixnums(&mut self, lhs: u8, rhs: u8, label: Label) {
+        self.asm.mov_rr(RCX, lhs);
+        self.asm.and_rr(RCX, rhs);
+        self.asm.test_ri(RCX, 1);
+        self.asm.jcc(CC_E, label);
+    }
+
+    /// Return to the interpreter, which executes the instruction at `index`.
+    fn exit(&mut self, index: usize) {
+        self.asm.mov_ri(RAX, index as u64);
+        let epilogue = self.epilogue;
+        self.asm.jmp(epilogue);
+    }
+
+    /// Call `func(frame, index)`, writing back and reloading the stack pointer and locals.
+    fn call_vm(&mut self, func: VmFn, index: usize) {
+        self.asm.store(RBX, FRAME_SP, R12);
+        self.asm.mov_rr(RDI, RBX);
+        self.asm.mov_ri(RSI, index as u64);
+        self.asm.call(func as usize);
+        self.asm.load(R12, RBX, FRAME_SP);
+        self.asm.load(R13, RBX, FRAME_LVARS);
+    }
+
+    /// Execute the instruction at `index` in the VM, and return to the interpreter on error.
+    fn call_exec(&mut self, index: usize) {
+        self.call_vm(jit_exec, index);
+        let ok = self.asm.label();
+        self.asm.test_rr(RAX, RAX);
+        self.asm.jcc(CC_E, ok);
+        self.exit(index + 1);
+        self.asm.bind(ok);
+    }
+
+    fn gen_arith(&mut self, index: usize, kind: Arith) {
+        let slow = self.asm.label();
+        let generic = self.asm.label();
+        let done = self.asm.label();
+        let asm = &mut self.asm;
+        asm.load(RAX, R12, -16);
+        asm.load(RDX, R12, -8);
+        if kind == Arith::Add || kind == Arith::Sub {
+            self.jmp_if_not_fixnums(RAX, RDX, slow);
+            let asm = &mut self.asm;
+            asm.mov_rr(RCX, RAX);
+            if kind == Arith::Add {
+                // (2a + 1) + (2b + 1) - 1
+                asm.sub_ri(RCX, 1);
+                asm.add_rr(RCX, RDX);
+            } else {
+                // (2a + 1) - (2b + 1) + 1
+                asm.sub_rr(RCX, RDX);
+                asm.jcc(CC_O, slow);
+                asm.add_ri(RCX, 1);
+            }
+            asm.jcc(CC_O, slow);
+            asm.sub_ri(R12, 8);
+            asm.store(R12, -8, RCX);
+            asm.jmp(done);
+        }
+        let asm = &mut self.asm;
+        asm.bind(slow);
+        asm.mov_rr(RDI, RAX);
+        asm.mov_rr(RSI, RDX);
+        let func: NumFn = match kind {
+            Arith::Add => num_add,
+            Arith::Sub => num_sub,
+            Arith::Mul => num_mul,
+            Arith::Div => num_div,
+        };
+        asm.call(func as usize);
+        asm.test_rr(RAX, RAX);
+        asm.jcc(CC_E, generic);
+        asm.sub_ri(R12, 8);
+        asm.store(R12, -8, RAX);
+        asm.jmp(done);
+        asm.bind(generic);
+        self.call_exec(index);
+        self.asm.bind(done);
+    }
+
+    fn gen_arith_i(&mut self, index: usize, i: i64) {
+        let generic = self.asm.label();
+        let done = self.asm.label();
+        let imm = match i32::try_from(i * 2) {
+            Ok(imm) => imm,
+            Err(_) => return self.call_exec(index),
+        };
+        let asm = &mut self.asm;
+        asm.load(RAX, R12, -8);
+        asm.test_ri(RAX, 1);
+        asm.jcc(CC_E, generic);
+        asm.add_ri(RAX, imm);
+        asm.jcc(CC_O, generic);
+        asm.store(R12, -8, RAX);
+        asm.jmp(done);
+        asm.bind(generic);
+        self.call_exec(index);
+        self.asm.bind(done);
+    }
+
+    fn gen_lvar_addi(&mut self, index: usize, disp: i32, i: i32) {
+        let generic = self.asm.label();
+        let done = self.asm.label();
+        let imm = match i.checked_mul(2) {
+            Some(imm) => imm,
+            None => return self.call_exec(index),
+        };
+        let asm = &mut self.asm;
+        asm.load(RAX, R13, disp);
+        asm.test_ri(RAX, 1);
+        asm.jcc(CC_E, generic);
+        asm.add_ri(RAX, imm);
+        asm.jcc(CC_O, generic);
+        asm.store(R13, disp, RAX);
+        asm.jmp(done);
+        asm.bind(generic);
+        self.call_exec(index);
+        self.asm.bind(done);
+    }
+
+    /// Compare the two values on the top of the stack, and jump to `is_true` or `is_false`
+    /// with the operands popped, or to `generic` with the operands left if they are not numbers.
+    fn gen_compare(&mut self, kind: Cmp, is_true: Label, is_false: Label, generic: Label) {
+        let slow = self.asm.label();
+        let asm = &mut self.asm;
+        asm.load(RAX, R12, -16);
+        asm.load(RDX, R12, -8);
+        self.jmp_if_not_fixnums(RAX, RDX, slow);
+        let asm = &mut self.asm;
+        asm.sub_ri(R12, 16);
+        asm.cmp_rr(RAX, RDX);
+        asm.jcc(kind.not_cc(), is_false);
+        asm.jmp(is_true);
+        asm.bind(slow);
+        match kind {
+            Cmp::Gt | Cmp::Ge => {
+                asm.mov_rr(RDI, RAX);
+                asm.mov_rr(RSI, RDX);
+                let func: NumFn = if kind == Cmp::Gt { num_gt } else { num_ge };
+                asm.call(func as usize);
+                asm.cmp_ri(RAX, 2);
+                asm.jcc(CC_E, generic);
+                asm.sub_ri(R12, 16);
+                asm.test_rr(RAX, RAX);
+                asm.jcc(CC_E, is_false);
+                asm.jmp(is_true);
+            }
+            _ => asm.jmp(generic),
+        }
+    }
+
+    fn gen_cmp(&mut self, index: usize, kind: Cmp) {
+        let is_true = self.asm.label();
+        let is_false = self.asm.label();
+        let generic = self.asm.label();
+        let push = self.asm.label();
+        let done = self.asm.label();
+        self.gen_compare(kind, is_true, is_false, generic);
+        let asm = &mut self.asm;
+        asm.bind(is_true);
+        asm.mov_ri(RAX, Value::true_val().id());
+        asm.jmp(push);
+        asm.bind(is_false);
+        asm.mov_ri(RAX, Value::false_val().id());
+        asm.bind(push);
+        self.push_reg(RAX);
+        self.asm.jmp(done);
+        self.asm.bind(generic);
+        self.call_exec(index);
+        self.asm.bind(done);
+    }
+
+    fn gen_cmp_jmp(&mut self, index: usize, kind: Cmp, dest: usize) {
+        let is_true = self.asm.label();
+        let generic = self.asm.label();
+        let dest = self.insts[dest];
+        self.gen_compare(kind, is_true, dest, generic);
+        self.asm.bind(generic);
+        self.call_vm(jit_branch, index);
+        let ok = self.asm.label();
+        self.asm.cmp_ri(RAX, 2);
+        self.asm.jcc(CC_NE, ok);
+        self.exit(index + 1);
+        let asm = &mut self.asm;
+        asm.bind(ok);
+        asm.test_rr(RAX, RAX);
+        asm.jcc(CC_E, dest);
+        asm.bind(is_true);
+    }
+}
+
+//----------------------------------------------------------------------------------
+// Leaf functions for Floats, which return 0 (not a valid result) when not applicable.
+
+fn as_f64(val: Value) -> Option<f64> {
+    if val.is_packed_fixnum() {
+        Some(val.as_packed_fixnum() as f64)
+    } else if val.is_packed_flonum() {
+        Some(val.as_packed_flonum())
+    } else {
+        None
+    }
+}
+
+fn float_op(lhs: u64, rhs: u64, op: fn(f64, f64) -> f64) -> u64 {
+    let (lhs, rhs) = (Value::from(lhs), Value::from(rhs));
+    if lhs.is_packed_fixnum() && rhs.is_packed_fixnum() {
+        return 0;
+    }
+    match (as_f64(lhs), as_f64(rhs)) {
+        (Some(lhs), Some(rhs)) => Value::flonum(op(lhs, rhs)).id(),
+        _ => 0,
+    }
+}
+
+extern "C" fn num_add(lhs: u64, rhs: u64) -> u64 {
+    float_op(lhs, rhs, |lhs, rhs| lhs + rhs)
+}
+
+extern "C" fn num_sub(lhs: u64, rhs: u64) -> u64 {
+    float_op(lhs, rhs, |lhs, rhs| lhs - rhs)
+}
+
+extern "C" fn num_mul(lhs: u64, rhs: u64) -> u64 {
+    let (l, r) = (Value::from(lhs), Value::from(rhs));
+    if l.is_packed_fixnum() && r.is_packed_fixnum() {
+        return match l.as_packed_fixnum().checked_mul(r.as_packed_fixnum()) {
+            Some(num) => Value::fixnum(num).id(),
+            None => 0,
+        };
+    }
+    float_op(lhs, rhs, |lhs, rhs| lhs * rhs)
+}
+
+extern "C" fn num_div(lhs: u64, rhs: u64) -> u64 {
+    float_op(lhs, rhs, |lhs, rhs| lhs / rhs)
+}
+
+/// 1 if `lhs > rhs`, 0 if not, or 2 when not applicable.
+extern "C" fn num_gt(lhs: u64, rhs: u64) -> u64 {
+    match (as_f64(Value::from(lhs)), as_f64(Value::from(rhs))) {
+        (Some(lhs), Some(rhs)) => (lhs > rhs) as u64,
+        _ => 2,
+    }
+}
+
+/// 1 if `lhs >= rhs`, 0 if not, or 2 when not applicable.
+extern "C" fn num_ge(lhs: u64, rhs: u64) -> u64 {
+    match (as_f64(Value::from(lhs)), as_f64(Value::from(rhs))) {
+        (Some(lhs), Some(rhs)) => (lhs >= rhs) as u64,
+        _ => 2,
+    }
+}
+
+//----------------------------------------------------------------------------------
+// Calls into the VM.
+
+impl JitFrame {
+    /// Run `f` for the instruction at `index` with the stack of the frame,
+    /// and update the frame for the compiled code.
+    fn run<T>(
+        &mut self,
+        index: u64,
+        f: impl FnOnce(&mut VM, Op) -> Result<T, RubyError>,
+    ) -> Result<T, RubyError> {
+        let mut vm = self.vm;
+        let inst = vm.context().iseq_ref.decoded.insts[index as usize];
+        vm.set_pc(inst.pc);
+        unsafe { vm.jit_set_stack_top(self.sp) };
+        let res = f(&mut vm, inst.op);
+        self.sp = vm.jit_stack_top(self.reserve);
+        self.lvars = vm.context().lvar_ptr();
+        res
+    }
+}
+
+/// Execute an instruction. Return 0, or 1 on error.
+extern "C" fn jit_exec(frame: &mut JitFrame, index: u64) -> u64 {
+    let mut raise = false;
+    let res = frame.run(index, |vm, op| {
+        let val = match op {
+            Op::Add(cache) => {
+                raise = true;
+                let lhs = vm.stack_pop();
+                let rhs = vm.stack_pop();
+                vm.eval_add(lhs, rhs, cache)?
+            }
+            Op::Sub(cache) => {
+                raise = true;
+                let lhs = vm.stack_pop();
+                let rhs = vm.stack_pop();
+                vm.eval_sub(lhs, rhs, cache)?
+            }
+            Op::Mul(cache) => {
+                let lhs = vm.stack_pop();
+                let rhs = vm.stack_pop();
+                vm.eval_mul(lhs, rhs, cache)?
+            }
+            Op::Div(cache) => {
+                let lhs = vm.stack_pop();
+                let rhs = vm.stack_pop();
+                vm.eval_div(lhs, rhs, cache)?
+            }
+            Op::AddI(i) => {
+                let lhs = vm.stack_pop();
+                vm.eval_addi(lhs, i)?
+            }
+            Op::SubI(i) => {
+                let lhs = vm.stack_pop();
+                vm.eval_subi(lhs, i)?
+            }
+            Op::Eq | Op::Ne => {
+                let lhs = vm.stack_pop();
+                let rhs = vm.stack_pop();
+                let eq = vm.eval_eq(rhs, lhs)?;
+                Value::bool(if let Op::Eq = op { eq } else { !eq })
+            }
+            Op::Gt => {
+                let lhs = vm.stack_pop();
+                let rhs = vm.stack_pop();
+                vm.eval_gt(lhs, rhs)?
+            }
+            Op::Ge => {
+                let lhs = vm.stack_pop();
+                let rhs = vm.stack_pop();
+                vm.eval_ge(lhs, rhs)?
+            }
+            Op::LvarAddI(id, _, i) => {
+                let mut context = vm.context();
+                context[id] = vm.eval_addi(context[id], i)?;
+                return Ok(());
+            }
+            Op::Send {
+                id,
+                args,
+                flag,
+                cache,
+                block,
+                self_call,
+            } => {
+                raise = true;
+                vm.vm_send(id, args, flag, cache, block, self_call)?
+            }
+            Op::OptSend {
+                id,
+                args,
+                cache,
+                self_call,
+            } => {
+                raise = true;
+                let receiver = if self_call {
+                    vm.context().self_value
+                } else {
+                    vm.stack_pop()
+                };
+                vm.vm_opt_send(receiver, id, args, cache, self_call)?
+            }
+            Op::GetConst(id, slot) => vm.get_const(id, slot)?,
+            Op::GetIvar(id, slot) => {
+                let obj = vm.context().self_value.as_object();
+                vm.get_ivar(obj, id, slot)
+            }
+            Op::SetIvar(id, slot) => {
+                let obj = vm.context().self_value.as_object();
+                let val = vm.stack_pop();
+                vm.set_ivar(obj, id, slot, val);
+                return Ok(());
+            }
+            Op::IvarAddI(id, i, slot) => {
+                let obj = vm.context().self_value.as_object();
+                let val = vm.get_ivar(obj, id, slot);
+                let val = vm.eval_addi(val, i)?;
+                vm.set_ivar(obj, id, slot, val);
+                return Ok(());
+            }
+            _ => unreachable!("JIT: illegal instruction {:?}", op),
+        };
+        vm.stack_push(val);
+        Ok(())
+    });
+    match res {
+        Ok(()) => 0,
+        Err(err) => {
+            frame.error = Some((err, raise));
+            1
+        }
+    }
+}
+
+/// Evaluate the condition of a conditional jump. Return 1 if true, 0 if false, or 2 on error.
+extern "C" fn jit_branch(frame: &mut JitFrame, index: u64) -> u64 {
+    let res = frame.run(index, |vm, op| {
+        let lhs = vm.stack_pop();
+        let rhs = vm.stack_pop();
+        match op {
+            Op::Jeq(_) => vm.eval_eq(rhs, lhs),
+            Op::Jne(_) => Ok(!vm.eval_eq(rhs, lhs)?),
+            Op::Jgt(_) => {
+                let val = vm.eval_gt(lhs, rhs)?;
+                Ok(vm.val_to_bool(val))
+            }
+            _ => {
+                let val = vm.eval_ge(lhs, rhs)?;
+                Ok(vm.val_to_bool(val))
+            }
+        }
+    });
+    match res {
+        Ok(cond) => cond as u64,
+        Err(err) => {
+            frame.error = Some((err, false));
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+    use crate::*;
+    use std::path::PathBuf;
+
+    fn assert_jit_script(program: &str) {
+        let mut vm = VM::new();
+        vm.globals.jit = JitConfig {
+            enabled: true,
+            threshold: 2,
+        };
+        if let Err(err) = vm.run(PathBuf::from(""), program, None) {
+            err.show_err();
+            err.show_loc(0);
+            panic!("Got error: {:?}", err);
+        }
+    }
+
+    #[test]
+    fn jit_arith() {
+        let program = r#"
+        assert true, RubyVM::JIT.enabled?
+        def fib(n)
+            if n < 2
+                n
+            else
+                fib(n - 1) + fib(n - 2)
+            end
+        end
+        assert 6765, fib(20)
+        def calc(a, b)
+            [a + b, a - b, a * b, a / b, a > b, a >= b, a == b, a != b, !a]
+        end
+        10.times do
+            assert [7, 3, 10, 2, true, true, false, true, false], calc(5, 2)
+            assert [3.5, -0.5, 3.0, 0.75, false, false, false, true, false], calc(1.5, 2.0)
+            assert [3.5, 0.5, 3.0, 1.3333333333333333, true, true, false, true, false], calc(2, 1.5)
+        end
+        def add(a, b)
+            a + b
+        end
+        5.times do
+            assert "ab", add("a", "b")
+            assert 4611686018427387904, add(4611686018427387903, 1)
+        end
+        def loop_sum(n)
+            i = 0
+            sum = 0
+            while i < n
+                sum += i
+                i += 1
+            end
+            sum
+        end
+        5.times { assert 4950, loop_sum(100) }
+        "#;
+        assert_jit_script(program);
+    }
+
+    #[test]
+    fn jit_side_exit() {
+        let program = r##"
+        class Foo
+            def initialize
+                @a = 0
+            end
+            def inc(x)
+                @a += x
+                s = "#{@a}"
+                a = [1, 2, 3].map { |y| y * x }
+                return s if @a > 100
+                a[0] + a[1] + a[2] + @a
+            end
+        end
+        foo = Foo.new
+        assert 7, foo.inc(1)
+        assert 15, foo.inc(2)
+        assert 24, foo.inc(3)
+        assert "106", foo.inc(100)
+        def err(x)
+            x + 1
+        end
+        3.times { err(1) }
+        assert_error { err(nil) }
+        "##;
+        assert_jit_script(program);
+    }
+
+    #[test]
+    fn jit_enabled() {
+        let program = r#"
+        assert false, RubyVM::JIT.enabled?
+        "#;
+        assert_script(program);
+    }
+}
@@ -5,6 +5,14 @@ pub type BuiltinFunc = fn(vm: &mut VM, self_val: Value, args: &Args) -> VMResult
 
 pub type MethodTable = HashMap<IdentId, MethodRef>;
 
+/// Visibility of a method table entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    Protected,
+    Private,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MethodRef(u32);
 
@@ -38,6 +46,7 @@ pub enum MethodInfo {
     AttrReader { id: IdentId },
     AttrWriter { id: IdentId },
     BuiltinFunc { name: String, func: BuiltinFunc },
+    ProcBlock { procobj: Value },
 }
 
 impl MethodInfo {
@@ -48,10 +57,13 @@ impl MethodInfo {
     }
 
     pub fn as_iseq(&self, vm: &VM) -> Result<ISeqRef, RubyError> {
-        if let MethodInfo::RubyFunc { iseq } = self {
-            Ok(*iseq)
-        } else {
-            Err(vm.error_unimplemented("Methodref is illegal."))
+        match self {
+            MethodInfo::RubyFunc { iseq } => Ok(*iseq),
+            MethodInfo::ProcBlock { procobj } => match procobj.as_proc().unwrap().context() {
+                Some(context) => Ok(context.iseq_ref),
+                None => Err(vm.error_unimplemented("Builtin Proc has no ISeq.")),
+            },
+            _ => Err(vm.error_unimplemented("Methodref is illegal.")),
         }
     }
     /*
@@ -69,6 +81,7 @@ impl std::fmt::Debug for MethodInfo {
             MethodInfo::AttrReader { id } => write!(f, "AttrReader {:?}", id),
             MethodInfo::AttrWriter { id } => write!(f, "AttrWriter {:?}", id),
             MethodInfo::BuiltinFunc { name, .. } => write!(f, "BuiltinFunc {:?}", name),
+            MethodInfo::ProcBlock { procobj } => write!(f, "ProcBlock {:?}", procobj),
         }
     }
 }
@@ -80,6 +93,8 @@ pub struct ISeqInfo {
     pub method: MethodRef,
     pub params: ISeqParams,
     pub iseq: ISeq,
+    /// `iseq` decoded for execution.
+    pub decoded: DecodedISeq,
     pub lvar: LvarCollector,
     pub lvars: usize,
     /// The Class where this method was described.
@@ -90,6 +105,15 @@ pub struct ISeqInfo {
     pub iseq_sourcemap: Vec<(ISeqPos, Loc)>,
     pub source_info: SourceInfoRef,
     pub kind: ISeqKind,
+    /// The location where this method or block was described.
+    pub loc: Loc,
+    /// Number of calls counted for the JIT compiler.
+    pub jit_counter: usize,
+    pub jit_code: Option<JitCodeRef>,
+    /// Counters for the Coverage module, while it is running.
+    pub coverage: Option<Box<ISeqCoverage>>,
+    /// Calls in tail position replace the frame of this method.
+    pub tailcall: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -101,6 +125,25 @@ pub struct ISeqParams {
     pub block_param: bool,
     pub param_ident: Vec<IdentId>,
     pub keyword_params: HashMap<IdentId, LvarId>,
+    /// Keyword parameters without default values, in the declared order.
+    pub req_keyword_params: Vec<IdentId>,
+    pub kwrest_param: Option<LvarId>,
+}
+
+impl ISeqParams {
+    /// Returns true if the parameters accept keyword arguments.
+    pub fn has_keyword(&self) -> bool {
+        !self.keyword_params.is_empty() || self.kwrest_param.is_some()
+    }
+
+    /// Returns true if the parameters are only required ones.
+    pub fn is_plain(&self) -> bool {
+        self.opt_params == 0
+            && !self.rest_param
+            && self.post_params == 0
+            && !self.block_param
+            && !self.has_keyword()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -136,13 +179,17 @@ impl ISeqInfo {
         block_param: bool,
         param_ident: Vec<IdentId>,
         keyword_params: HashMap<IdentId, LvarId>,
+        req_keyword_params: Vec<IdentId>,
+        kwrest_param: Option<LvarId>,
         iseq: ISeq,
         lvar: LvarCollector,
         iseq_sourcemap: Vec<(ISeqPos, Loc)>,
         source_info: SourceInfoRef,
         kind: ISeqKind,
+        loc: Loc,
     ) -> Self {
         let lvars = lvar.len();
+        let decoded = DecodedISeq::new(&iseq);
         ISeqInfo {
             method,
             params: ISeqParams {
@@ -153,14 +200,22 @@ impl ISeqInfo {
                 block_param,
                 param_ident,
                 keyword_params,
+                req_keyword_params,
+                kwrest_param,
             },
             iseq,
+            decoded,
             lvar,
             lvars,
             class_defined: None,
             iseq_sourcemap,
             source_info,
             kind,
+            loc,
+            jit_counter: 0,
+            jit_code: None,
+            coverage: None,
+            tailcall: false,
         }
     }
 
@@ -175,10 +230,13 @@ impl ISeqInfo {
             vec![],
             std::collections::HashMap::new(),
             vec![],
+            None,
+            vec![],
             LvarCollector::new(),
             vec![],
             SourceInfoRef::empty(),
             ISeqKind::Method(IdentId::from(0)),
+            Loc(0, 0),
         )
     }
 
@@ -188,6 +246,26 @@ impl ISeqInfo {
             _ => false,
         }
     }
+
+    /// Number of mandatory arguments, or -(n+1) for `n` mandatory arguments
+    /// when optional arguments are accepted.
+    /// Optional arguments of a proc (not a lambda) do not make the arity negative.
+    /// Required keywords count as one mandatory argument.
+    pub fn arity(&self, is_lambda: bool) -> i64 {
+        let params = &self.params;
+        let req_kw = if params.req_keyword_params.is_empty() {
+            0
+        } else {
+            1
+        };
+        let req = (params.req_params + params.post_params + req_kw) as i64;
+        let opt_kw = req_kw == 0 && params.has_keyword();
+        if params.rest_param || (is_lambda && (params.opt_params != 0 || opt_kw)) {
+            -req - 1
+        } else {
+            req
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -238,16 +316,20 @@ impl GlobalMethodTable {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct MethodObjInfo {
     pub name: IdentId,
+    /// The receiver of the method. nil for UnboundMethod.
     pub receiver: Value,
     pub method: MethodRef,
+    /// The class or module where the method was defined.
+    pub owner: Value,
 }
 
 impl MethodObjInfo {
-    pub fn new(name: IdentId, receiver: Value, method: MethodRef) -> Self {
+    pub fn new(name: IdentId, receiver: Value, method: MethodRef, owner: Value) -> Self {
         MethodObjInfo {
             name,
             receiver,
             method,
+            owner,
         }
     }
 }
@@ -255,7 +337,7 @@ impl MethodObjInfo {
 pub type MethodObjRef = Ref<MethodObjInfo>;
 
 impl MethodObjRef {
-    pub fn from(name: IdentId, receiver: Value, method: MethodRef) -> Self {
-        MethodObjRef::new(MethodObjInfo::new(name, receiver, method))
+    pub fn from(name: IdentId, receiver: Value, method: MethodRef, owner: Value) -> Self {
+        MethodObjRef::new(MethodObjInfo::new(name, receiver, method, owner))
     }
 }
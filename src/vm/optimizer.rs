@@ -0,0 +1,591 @@
+//! Peephole optimizer for instruction sequences.
+//!
+//! The instructions are decoded into a list, where jump destinations are held
+//! as indices into the list. Each pass marks instructions as removed or replaces
+//! them, and the list is encoded again with new displacements and a new sourcemap.
+use crate::*;
+use std::collections::HashSet;
+use vm_inst::*;
+
+const MAX_PASSES: usize = 8;
+
+#[derive(Debug, Clone)]
+struct Insn {
+    /// The instruction with its operands.
+    code: Vec<u8>,
+    loc: Option<Loc>,
+    /// Destination of a jump instruction, or the default destination of OPT_CASE.
+    dest: Option<usize>,
+    /// Destinations of OPT_CASE for each case value.
+    case_dests: Vec<(Value, usize)>,
+    removed: bool,
+}
+
+impl Insn {
+    fn new(code: Vec<u8>, loc: Option<Loc>) -> Self {
+        Insn {
+            code,
+            loc,
+            dest: None,
+            case_dests: vec![],
+            removed: false,
+        }
+    }
+
+    fn inst(&self) -> u8 {
+        self.code[0]
+    }
+
+    fn fixnum(&self) -> Option<i64> {
+        match self.inst() {
+            Inst::PUSH_FIXNUM => Some(Inst::read64(&self.code, 1) as i64),
+            _ => None,
+        }
+    }
+
+    fn flonum(&self) -> Option<f64> {
+        match self.inst() {
+            Inst::PUSH_FLONUM => Some(f64::from_bits(Inst::read64(&self.code, 1))),
+            Inst::PUSH_FIXNUM => Some(Inst::read64(&self.code, 1) as i64 as f64),
+            _ => None,
+        }
+    }
+
+    fn push_fixnum(num: i64) -> Vec<u8> {
+        let mut code = vec![Inst::PUSH_FIXNUM];
+        code.extend_from_slice(&(num as u64).to_le_bytes());
+        code
+    }
+
+    fn push_flonum(num: f64) -> Vec<u8> {
+        let mut code = vec![Inst::PUSH_FLONUM];
+        code.extend_from_slice(&num.to_bits().to_le_bytes());
+        code
+    }
+
+    fn push_string(id: IdentId) -> Vec<u8> {
+        let id: u32 = id.into();
+        let mut code = vec![Inst::PUSH_STRING];
+        code.extend_from_slice(&id.to_le_bytes());
+        code
+    }
+}
+
+pub struct Optimizer<'a> {
+    globals: &'a mut Globals,
+    insns: Vec<Insn>,
+}
+
+impl<'a> Optimizer<'a> {
+    /// Optimize `iseq`, returning the new ISeq and its sourcemap.
+    /// Dispatch maps of OPT_CASE in `iseq` are updated in place.
+    pub fn optimize(
+        globals: &mut Globals,
+        iseq: ISeq,
+        sourcemap: Vec<(ISeqPos, Loc)>,
+    ) -> (ISeq, Vec<(ISeqPos, Loc)>) {
+        let insns = match Optimizer::decode(globals, &iseq, &sourcemap) {
+            Some(insns) => insns,
+            None => return (iseq, sourcemap),
+        };
+        let mut optimizer = Optimizer { globals, insns };
+        for _ in 0..MAX_PASSES {
+            let mut changed = optimizer.thread_jumps();
+            changed |= optimizer.remove_dead_code();
+            changed |= optimizer.fold_constants();
+            changed |= optimizer.remove_push_pop();
+            changed |= optimizer.fuse();
+            if !changed {
+                break;
+            }
+        }
+        optimizer.encode()
+    }
+
+    fn decode(globals: &Globals, iseq: &ISeq, sourcemap: &[(ISeqPos, Loc)]) -> Option<Vec<Insn>> {
+        let mut pcs = vec![];
+        let mut pc = 0;
+        while pc < iseq.len() {
+            pcs.push(pc);
+            pc += Inst::inst_size(iseq[pc]);
+        }
+        if pc != iseq.len() {
+            return None;
+        }
+        let index = |pc: usize| pcs.binary_search(&pc).ok();
+        let mut insns = vec![];
+        for pc in &pcs {
+            let pc = *pc;
+            let code = iseq[pc..pc + Inst::inst_size(iseq[pc])].to_vec();
+            let loc = sourcemap
+                .iter()
+                .rev()
+                .find(|(pos, _)| pos.to_usize() == pc)
+                .map(|(_, loc)| *loc);
+            let mut insn = Insn::new(code, loc);
+            match iseq[pc] {
+                Inst::JMP | Inst::JMP_IF_FALSE => {
+                    insn.dest = Some(index(Inst::jump_dests(globals, iseq, pc)[0])?);
+                }
+                Inst::OPT_CASE => {
+                    let map = globals.get_case_dispatch_map(Inst::read32(iseq, pc + 1));
+                    for (k, disp) in map {
+                        let dest = index((pc as i32 + 9 + disp) as usize)?;
+                        insn.case_dests.push((*k, dest));
+                    }
+                    let disp = Inst::read32(iseq, pc + 5) as i32;
+                    insn.dest = Some(index((pc as i32 + 9 + disp) as usize)?);
+                }
+                _ => {}
+            }
+            insns.push(insn);
+        }
+        Some(insns)
+    }
+
+    fn encode(self) -> (ISeq, Vec<(ISeqPos, Loc)>) {
+        let mut pcs = vec![0; self.insns.len()];
+        let mut pc = 0;
+        for (i, insn) in self.insns.iter().enumerate() {
+            pcs[i] = pc;
+            if !insn.removed {
+                pc += insn.code.len();
+            }
+        }
+        let mut iseq = ISeq::new();
+        let mut sourcemap = vec![];
+        for (i, insn) in self.insns.iter().enumerate() {
+            if insn.removed {
+                continue;
+            }
+            let pc = pcs[i];
+            let mut code = insn.code.clone();
+            if let Some(dest) = insn.dest {
+                let dest = pcs[self.resolve(dest)] as i32;
+                match insn.inst() {
+                    Inst::OPT_CASE => {
+                        let case_disps: Vec<(Value, i32)> = insn
+                            .case_dests
+                            .iter()
+                            .map(|(k, d)| (*k, pcs[self.resolve(*d)] as i32 - (pc as i32 + 9)))
+                            .collect();
+                        let map_id = Inst::read32(&code, 1);
+                        let map = self.globals.get_mut_case_dispatch_map(map_id);
+                        for (k, disp) in case_disps {
+                            map.insert(k, disp);
+                        }
+                        let disp = dest - (pc as i32 + 9);
+                        code[5..9].copy_from_slice(&disp.to_le_bytes());
+                    }
+                    _ => {
+                        let disp = dest - (pc as i32 + 5);
+                        code[1..5].copy_from_slice(&disp.to_le_bytes());
+                    }
+                }
+            }
+            if let Some(loc) = insn.loc {
+                sourcemap.push((ISeqPos::from(pc), loc));
+            }
+            iseq.append(&mut code);
+        }
+        (iseq, sourcemap)
+    }
+
+    /// The first instruction at or after `i` which is not removed.
+    fn resolve(&self, mut i: usize) -> usize {
+        while i < self.insns.len() - 1 && self.insns[i].removed {
+            i += 1;
+        }
+        i
+    }
+
+    fn live(&self) -> Vec<usize> {
+        (0..self.insns.len())
+            .filter(|i| !self.insns[*i].removed)
+            .collect()
+    }
+
+    fn targets(&self) -> HashSet<usize> {
+        let mut targets = HashSet::new();
+        for insn in self.insns.iter().filter(|insn| !insn.removed) {
+            if let Some(dest) = insn.dest {
+                targets.insert(self.resolve(dest));
+            }
+            for (_, dest) in &insn.case_dests {
+                targets.insert(self.resolve(*dest));
+            }
+        }
+        targets
+    }
+
+    /// Replace the instructions `seq` with `code`, which takes the place of the first one.
+    fn replace(&mut self, seq: &[usize], code: Vec<u8>) {
+        let loc = seq.iter().filter_map(|i| self.insns[*i].loc).next();
+        for i in seq {
+            self.insns[*i].removed = true;
+        }
+        self.insns[seq[0]] = Insn::new(code, loc);
+    }
+
+    fn remove(&mut self, seq: &[usize]) {
+        for i in seq {
+            self.insns[*i].removed = true;
+        }
+    }
+
+    /// Jumps to an unconditional jump go directly to its destination,
+    /// and jumps to the next instruction are removed.
+    fn thread_jumps(&mut self) -> bool {
+        let mut changed = false;
+        let live = self.live();
+        for (n, i) in live.iter().enumerate() {
+            let i = *i;
+            let dest = match self.insns[i].dest {
+                Some(dest) => self.final_dest(i, dest),
+                None => continue,
+            };
+            if Some(dest) != self.insns[i].dest {
+                self.insns[i].dest = Some(dest);
+                changed = true;
+            }
+            let case_dests: Vec<(Value, usize)> = self.insns[i]
+                .case_dests
+                .iter()
+                .map(|(k, d)| (*k, self.final_dest(i, *d)))
+                .collect();
+            if case_dests
+                .iter()
+                .zip(&self.insns[i].case_dests)
+                .any(|(a, b)| a.1 != b.1)
+            {
+                self.insns[i].case_dests = case_dests;
+                changed = true;
+            }
+            if live.get(n + 1) == Some(&dest) {
+                match self.insns[i].inst() {
+                    Inst::JMP => {
+                        self.remove(&[i]);
+                        changed = true;
+                    }
+                    Inst::JMP_IF_FALSE => {
+                        self.replace(&[i], vec![Inst::POP]);
+                        changed = true;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        changed
+    }
+
+    fn final_dest(&self, from: usize, dest: usize) -> usize {
+        let mut dest = self.resolve(dest);
+        for _ in 0..self.insns.len() {
+            let insn = &self.insns[dest];
+            if insn.inst() != Inst::JMP || dest == from {
+                break;
+            }
+            dest = self.resolve(insn.dest.unwrap());
+        }
+        dest
+    }
+
+    /// Remove instructions after an unconditional jump or a return, up to the next jump destination.
+    fn remove_dead_code(&mut self) -> bool {
+        let mut changed = false;
+        let targets = self.targets();
+        let live = self.live();
+        let last = live.last().cloned();
+        let mut dead = false;
+        for i in live {
+            if targets.contains(&i) {
+                dead = false;
+            }
+            if dead && Some(i) != last {
+                self.remove(&[i]);
+                changed = true;
+                continue;
+            }
+            match self.insns[i].inst() {
+                Inst::JMP | Inst::RETURN | Inst::MRETURN | Inst::END => dead = true,
+                _ => {}
+            }
+        }
+        changed
+    }
+
+    /// Fold arithmetic and string concatenation of literals.
+    fn fold_constants(&mut self) -> bool {
+        let mut changed = false;
+        let targets = self.targets();
+        let live = self.live();
+        let mut n = 0;
+        while n < live.len() {
+            // "..#{x}a" "b" is concatenated as (("..x" + "a") + "b").
+            let seq4 = live
+                .get(n..n + 4)
+                .filter(|s| s[1..].iter().all(|i| !targets.contains(i)));
+            if let Some(seq) = seq4 {
+                let insts: Vec<u8> = seq.iter().map(|i| self.insns[*i].inst()).collect();
+                if insts
+                    == [
+                        Inst::PUSH_STRING,
+                        Inst::CONCAT_STRING,
+                        Inst::PUSH_STRING,
+                        Inst::CONCAT_STRING,
+                    ]
+                {
+                    let code = self.concat(seq[0], seq[2]);
+                    self.replace(&seq[0..3], code);
+                    changed = true;
+                    n += 4;
+                    continue;
+                }
+            }
+            let seq3 = live
+                .get(n..n + 3)
+                .filter(|s| !targets.contains(&s[1]) && !targets.contains(&s[2]));
+            if let Some(seq) = seq3 {
+                if let Some(code) = self.fold3(seq) {
+                    self.replace(seq, code);
+                    changed = true;
+                    n += 3;
+                    continue;
+                }
+            }
+            let seq2 = live.get(n..n + 2).filter(|s| !targets.contains(&s[1]));
+            if let Some(seq) = seq2 {
+                if let Some(code) = self.fold2(seq) {
+                    self.replace(seq, code);
+                    changed = true;
+                    n += 2;
+                    continue;
+                }
+            }
+            n += 1;
+        }
+        changed
+    }
+
+    fn fold3(&mut self, seq: &[usize]) -> Option<Vec<u8>> {
+        let (lhs, rhs, op) = (
+            &self.insns[seq[0]],
+            &self.insns[seq[1]],
+            &self.insns[seq[2]],
+        );
+        match op.inst() {
+            Inst::ADD | Inst::SUB | Inst::MUL | Inst::DIV => {
+                if let (Some(l), Some(r)) = (lhs.fixnum(), rhs.fixnum()) {
+                    let res = match op.inst() {
+                        Inst::ADD => l.checked_add(r),
+                        Inst::SUB => l.checked_sub(r),
+                        Inst::MUL => l.checked_mul(r),
+                        _ => l.checked_div(r),
+                    };
+                    return res.map(Insn::push_fixnum);
+                }
+                let (l, r) = (lhs.flonum()?, rhs.flonum()?);
+                let res = match op.inst() {
+                    Inst::ADD => l + r,
+                    Inst::SUB => l - r,
+                    Inst::MUL => l * r,
+                    _ => l / r,
+                };
+                Some(Insn::push_flonum(res))
+            }
+            Inst::CONCAT_STRING => {
+                if lhs.inst() != Inst::PUSH_STRING || rhs.inst() != Inst::PUSH_STRING {
+                    return None;
+                }
+                Some(self.concat(seq[0], seq[1]))
+            }
+            _ => None,
+        }
+    }
+
+    /// PUSH_STRING of the concatenation of the strings pushed by `lhs` and `rhs`.
+    fn concat(&mut self, lhs: usize, rhs: usize) -> Vec<u8> {
+        let l = IdentId::from(Inst::read32(&self.insns[lhs].code, 1));
+        let r = IdentId::from(Inst::read32(&self.insns[rhs].code, 1));
+        let s = format!(
+            "{}{}",
+            self.globals.get_ident_name(l),
+            self.globals.get_ident_name(r)
+        );
+        Insn::push_string(self.globals.get_ident_id(s))
+    }
+
+    fn fold2(&mut self, seq: &[usize]) -> Option<Vec<u8>> {
+        let (val, op) = (&self.insns[seq[0]], &self.insns[seq[1]]);
+        match op.inst() {
+            Inst::ADDI | Inst::SUBI => {
+                let i = Inst::read32(&op.code, 1) as i32 as i64;
+                let i = if op.inst() == Inst::ADDI { i } else { -i };
+                if let Some(l) = val.fixnum() {
+                    return l.checked_add(i).map(Insn::push_fixnum);
+                }
+                match val.inst() {
+                    Inst::PUSH_FLONUM => Some(Insn::push_flonum(val.flonum()? + i as f64)),
+                    _ => None,
+                }
+            }
+            Inst::TO_S => match val.inst() {
+                Inst::PUSH_STRING => Some(val.code.clone()),
+                Inst::PUSH_FIXNUM => {
+                    let s = val.fixnum()?.to_string();
+                    Some(Insn::push_string(self.globals.get_ident_id(s)))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Remove values which are pushed and discarded immediately.
+    fn remove_push_pop(&mut self) -> bool {
+        let mut changed = false;
+        let targets = self.targets();
+        let live = self.live();
+        let mut n = 0;
+        while n + 1 < live.len() {
+            let (push, pop) = (live[n], live[n + 1]);
+            let is_push = [
+                Inst::PUSH_NIL,
+                Inst::PUSH_TRUE,
+                Inst::PUSH_FALSE,
+                Inst::PUSH_SELF,
+                Inst::PUSH_FIXNUM,
+                Inst::PUSH_FLONUM,
+                Inst::PUSH_STRING,
+                Inst::PUSH_SYMBOL,
+                Inst::GET_LOCAL,
+            ]
+            .contains(&self.insns[push].inst());
+            if is_push && self.insns[pop].inst() == Inst::POP && !targets.contains(&pop) {
+                self.remove(&[push, pop]);
+                changed = true;
+                n += 2;
+            } else {
+                n += 1;
+            }
+        }
+        changed
+    }
+
+    /// Fuse a comparison and a conditional jump, and an increment of a local variable.
+    fn fuse(&mut self) -> bool {
+        let mut changed = false;
+        let targets = self.targets();
+        let live = self.live();
+        let mut n = 0;
+        while n < live.len() {
+            if let Some(seq) = live.get(n..n + 2) {
+                let jump = match self.insns[seq[0]].inst() {
+                    Inst::EQ => Inst::JEQ,
+                    Inst::NE => Inst::JNE,
+                    Inst::GT => Inst::JGT,
+                    Inst::GE => Inst::JGE,
+                    _ => 0,
+                };
+                if jump != 0
+                    && self.insns[seq[1]].inst() == Inst::JMP_IF_FALSE
+                    && !targets.contains(&seq[1])
+                {
+                    let dest = self.insns[seq[1]].dest;
+                    let mut code = vec![jump];
+                    code.extend_from_slice(&self.insns[seq[1]].code[1..5]);
+                    self.replace(seq, code);
+                    self.insns[seq[0]].dest = dest;
+                    changed = true;
+                    n += 2;
+                    continue;
+                }
+            }
+            if let Some(seq) = live.get(n..n + 3) {
+                if let Some(code) = self.fuse_lvar_addi(seq, &targets) {
+                    // The location of ADDI is kept for errors.
+                    let loc = self.insns[seq[1]].loc;
+                    self.replace(seq, code);
+                    self.insns[seq[0]].loc = loc;
+                    changed = true;
+                    n += 3;
+                    continue;
+                }
+            }
+            n += 1;
+        }
+        changed
+    }
+
+    fn fuse_lvar_addi(&self, seq: &[usize], targets: &HashSet<usize>) -> Option<Vec<u8>> {
+        let (get, op, set) = (
+            &self.insns[seq[0]],
+            &self.insns[seq[1]],
+            &self.insns[seq[2]],
+        );
+        if get.inst() != Inst::GET_LOCAL
+            || set.inst() != Inst::SET_LOCAL
+            || get.code[1..9] != set.code[1..9]
+            || targets.contains(&seq[1])
+            || targets.contains(&seq[2])
+        {
+            return None;
+        }
+        let i = Inst::read32(&op.code, 1) as i32;
+        let i = match op.inst() {
+            Inst::ADDI => i,
+            Inst::SUBI => i.checked_neg()?,
+            _ => return None,
+        };
+        let mut code = vec![Inst::LVAR_ADDI];
+        code.extend_from_slice(&get.code[1..9]);
+        code.extend_from_slice(&i.to_le_bytes());
+        Some(code)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::*;
+
+    #[test]
+    fn optimizer() {
+        let program = r##"
+    def disasm(src)
+        RubyVM::InstructionSequence.compile(src).disasm
+    end
+    asm = disasm("a = 3 + 4 * 2 - 1.5")
+    assert(true, (asm =~ /PUSH_FLONUM 9.5/) != nil)
+    asm = disasm("x = 5; s = \"a" + "#" + "{x}b" + "#" + "{1}c\"")
+    assert(true, (asm =~ /PUSH_STRING "b1c"/) != nil)
+    asm = disasm("x = 0; while x < 10 do x += 1 end")
+    assert(true, (asm =~ /JGT/) != nil)
+    assert(true, (asm =~ /LVAR_ADDI 'x' outer:0 LvarId:0 1/) != nil)
+    assert(nil, asm =~ /JMP_IF_FALSE/)
+    assert(nil, asm =~ /POP/)
+
+    x = 0
+    while x < 10 do x += 1 end
+    assert(10, x)
+    y = 20
+    y -= 3
+    assert(17, y)
+    assert(11, 3 + 4 * 2)
+    assert(2.5, 1 + 1.5)
+    assert("a1b2.5c", "a#{1}b#{2.5}c")
+    def f(n)
+        return n * 2
+        n * 3
+    end
+    assert(8, f(4))
+    def g(n)
+        if n == 1 then :one elsif n != 3 then :other else :three end
+    end
+    assert([:one, :other, :three], [1, 2, 3].map { |n| g(n) })
+    z = 5
+    w = if z >= 5 then 1 else 2 end
+    assert(1, w)
+    "##;
+        assert_script(program);
+    }
+}
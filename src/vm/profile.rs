@@ -0,0 +1,451 @@
+//! Per-method and per-line profiler enabled by `--profile`.
+//!
+//! The VM reports every entry to and exit from a method or block context and every
+//! instruction it dispatches. The time between them is charged to the ISeqInfo and
+//! the source line being executed, and to the node of the call tree for the current
+//! stack of ISeqInfos, from which the call-tree and collapsed-stack reports are made.
+use crate::*;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProfileFormat {
+    /// Methods and lines sorted by self time.
+    Flat,
+    /// Call tree with total and self time of each node.
+    Tree,
+    /// Collapsed stacks (`main;foo;bar 123`) in microseconds, for flamegraph tools.
+    Collapsed,
+}
+
+impl ProfileFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "flat" => Some(ProfileFormat::Flat),
+            "tree" => Some(ProfileFormat::Tree),
+            "collapsed" => Some(ProfileFormat::Collapsed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct ProfileStat {
+    /// Number of calls of a method, or number of times a line was entered.
+    count: usize,
+    self_time: Duration,
+    total_time: Duration,
+    /// Number of activations on the stack, so that recursive calls are not
+    /// counted twice in the total time.
+    active: usize,
+}
+
+impl ProfileStat {
+    fn enter(&mut self) {
+        self.count += 1;
+        self.active += 1;
+    }
+
+    fn exit(&mut self, total: Duration, child: Duration) {
+        self.active -= 1;
+        if self.active == 0 {
+            self.total_time += total;
+        }
+        self.self_time += total.checked_sub(child).unwrap_or_default();
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TreeNode {
+    iseq: Option<ISeqRef>,
+    children: HashMap<ISeqRef, usize>,
+    stat: ProfileStat,
+}
+
+impl TreeNode {
+    fn new(iseq: Option<ISeqRef>) -> Self {
+        TreeNode {
+            iseq,
+            children: HashMap::new(),
+            stat: ProfileStat::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    iseq: ISeqRef,
+    node: usize,
+    start: Instant,
+    child: Duration,
+    /// Source line of each instruction of the ISeqInfo.
+    lines: Rc<Vec<usize>>,
+    line: Option<usize>,
+    line_start: Instant,
+    line_child: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    methods: HashMap<ISeqRef, ProfileStat>,
+    lines: HashMap<(ISeqRef, usize), ProfileStat>,
+    line_tables: HashMap<ISeqRef, Rc<Vec<usize>>>,
+    /// Nodes of the call tree. The first one is the root.
+    tree: Vec<TreeNode>,
+    stack: Vec<Frame>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            methods: HashMap::new(),
+            lines: HashMap::new(),
+            line_tables: HashMap::new(),
+            tree: vec![TreeNode::new(None)],
+            stack: vec![],
+        }
+    }
+
+    /// Called when the VM starts to execute a context of `iseq`.
+    pub fn enter(&mut self, iseq: ISeqRef) {
+        let parent = match self.stack.last() {
+            Some(frame) => frame.node,
+            None => 0,
+        };
+        let node = match self.tree[parent].children.get(&iseq) {
+            Some(node) => *node,
+            None => {
+                let node = self.tree.len();
+                self.tree.push(TreeNode::new(Some(iseq)));
+                self.tree[parent].children.insert(iseq, node);
+                node
+            }
+        };
+        self.tree[node].stat.enter();
+        self.methods.entry(iseq).or_default().enter();
+        let lines = self
+            .line_tables
+            .entry(iseq)
+            .or_insert_with(|| Rc::new(line_table(iseq)))
+            .clone();
+        let now = Instant::now();
+        self.stack.push(Frame {
+            iseq,
+            node,
+            start: now,
+            child: Duration::default(),
+            lines,
+            line: None,
+            line_start: now,
+            line_child: Duration::default(),
+        });
+    }
+
+    /// Called when the VM finished the context which was entered last.
+    pub fn exit(&mut self) {
+        let mut frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+        self.exit_line(&mut frame);
+        let total = frame.start.elapsed();
+        self.tree[frame.node].stat.exit(total, frame.child);
+        if let Some(stat) = self.methods.get_mut(&frame.iseq) {
+            stat.exit(total, frame.child);
+        }
+        if let Some(parent) = self.stack.last_mut() {
+            parent.child += total;
+            parent.line_child += total;
+        }
+    }
+
+    /// Called when the VM dispatches the instruction at `index` of the current context.
+    pub fn instruction(&mut self, index: usize) {
+        let mut frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+        let line = frame.lines.get(index).cloned();
+        if line != frame.line {
+            self.exit_line(&mut frame);
+            if let Some(line) = line {
+                self.lines.entry((frame.iseq, line)).or_default().enter();
+                frame.line = Some(line);
+                frame.line_start = Instant::now();
+                frame.line_child = Duration::default();
+            }
+        }
+        self.stack.push(frame);
+    }
+
+    fn exit_line(&mut self, frame: &mut Frame) {
+        if let Some(line) = frame.line.take() {
+            if let Some(stat) = self.lines.get_mut(&(frame.iseq, line)) {
+                stat.exit(frame.line_start.elapsed(), frame.line_child);
+            }
+        }
+    }
+
+    pub fn report(&self, globals: &Globals, format: ProfileFormat) -> String {
+        match format {
+            ProfileFormat::Flat => self.report_flat(globals),
+            ProfileFormat::Tree => self.report_tree(globals),
+            ProfileFormat::Collapsed => self.report_collapsed(globals),
+        }
+    }
+
+    fn total_time(&self) -> Duration {
+        self.tree[0]
+            .children
+            .values()
+            .map(|node| self.tree[*node].stat.total_time)
+            .sum()
+    }
+
+    fn report_flat(&self, globals: &Globals) -> String {
+        let total = self.total_time();
+        let percent = |d: Duration| {
+            if total.as_nanos() == 0 {
+                0.0
+            } else {
+                d.as_nanos() as f64 * 100.0 / total.as_nanos() as f64
+            }
+        };
+        let mut res = format!("Flat profile (total {:.3} ms):\n", as_ms(total));
+        res += &format!(
+            "{:>7} {:>12} {:>12} {:>10}  {}\n",
+            "%self", "self(ms)", "total(ms)", "calls", "method"
+        );
+        let mut methods: Vec<_> = self.methods.iter().collect();
+        methods.sort_by_key(|(_, stat)| std::cmp::Reverse(stat.self_time));
+        for (iseq, stat) in methods {
+            res += &format!(
+                "{:>7.2} {:>12.3} {:>12.3} {:>10}  {} ({})\n",
+                percent(stat.self_time),
+                as_ms(stat.self_time),
+                as_ms(stat.total_time),
+                stat.count,
+                iseq_name(globals, *iseq),
+                iseq_location(*iseq),
+            );
+        }
+        res += "\nLine profile:\n";
+        res += &format!(
+            "{:>7} {:>12} {:>12} {:>10}  {}\n",
+            "%self", "self(ms)", "total(ms)", "count", "line"
+        );
+        let mut lines: Vec<_> = self.lines.iter().collect();
+        lines.sort_by_key(|(_, stat)| std::cmp::Reverse(stat.self_time));
+        for ((iseq, line), stat) in lines {
+            res += &format!(
+                "{:>7.2} {:>12.3} {:>12.3} {:>10}  {}:{} in {}\n",
+                percent(stat.self_time),
+                as_ms(stat.self_time),
+                as_ms(stat.total_time),
+                stat.count,
+                iseq.source_info.path.to_string_lossy(),
+                line,
+                iseq_name(globals, *iseq),
+            );
+        }
+        res
+    }
+
+    fn report_tree(&self, globals: &Globals) -> String {
+        let mut res = format!(
+            "{:>12} {:>12} {:>10}  {}\n",
+            "total(ms)", "self(ms)", "calls", "method"
+        );
+        self.tree_node(globals, 0, 0, &mut res);
+        res
+    }
+
+    fn tree_node(&self, globals: &Globals, node: usize, depth: usize, res: &mut String) {
+        let node = &self.tree[node];
+        if let Some(iseq) = node.iseq {
+            *res += &format!(
+                "{:>12.3} {:>12.3} {:>10}  {}{} ({})\n",
+                as_ms(node.stat.total_time),
+                as_ms(node.stat.self_time),
+                node.stat.count,
+                "  ".repeat(depth),
+                iseq_name(globals, iseq),
+                iseq_location(iseq),
+            );
+        }
+        let depth = if node.iseq.is_some() {
+            depth + 1
+        } else {
+            depth
+        };
+        let mut children: Vec<_> = node.children.values().cloned().collect();
+        children.sort_by(|a, b| {
+            self.tree[*b]
+                .stat
+                .total_time
+                .cmp(&self.tree[*a].stat.total_time)
+        });
+        for child in children {
+            self.tree_node(globals, child, depth, res);
+        }
+    }
+
+    fn report_collapsed(&self, globals: &Globals) -> String {
+        let mut stacks = vec![];
+        self.collapsed_node(globals, 0, &mut vec![], &mut stacks);
+        stacks.sort();
+        let mut res = String::new();
+        for (stack, micros) in stacks {
+            res += &format!("{} {}\n", stack, micros);
+        }
+        res
+    }
+
+    fn collapsed_node(
+        &self,
+        globals: &Globals,
+        node: usize,
+        path: &mut Vec<String>,
+        stacks: &mut Vec<(String, u128)>,
+    ) {
+        let node = &self.tree[node];
+        if let Some(iseq) = node.iseq {
+            // ';' separates frames in the collapsed-stack format.
+            path.push(iseq_name(globals, iseq).replace(';', ":"));
+            let micros = node.stat.self_time.as_micros();
+            if micros != 0 {
+                stacks.push((path.join(";"), micros));
+            }
+        }
+        for child in node.children.values() {
+            self.collapsed_node(globals, *child, path, stacks);
+        }
+        if node.iseq.is_some() {
+            path.pop();
+        }
+    }
+}
+
+fn as_ms(d: Duration) -> f64 {
+    d.as_nanos() as f64 / 1_000_000.0
+}
+
+/// Source line of each instruction in `iseq`. An instruction without an entry
+/// in the source map belongs to the line of the preceding instruction.
+fn line_table(iseq: ISeqRef) -> Vec<usize> {
+    let mut lines = HashMap::new();
+    for (pos, loc) in iseq.iseq_sourcemap.iter() {
+        lines.insert(pos.to_usize(), iseq.source_info.get_line(loc));
+    }
+    let mut line = iseq.source_info.get_line(&iseq.loc);
+    iseq.decoded
+        .insts
+        .iter()
+        .map(|inst| {
+            if let Some(l) = lines.get(&inst.pc) {
+                line = *l;
+            }
+            line
+        })
+        .collect()
+}
+
+fn iseq_location(iseq: ISeqRef) -> String {
+    format!(
+        "{}:{}",
+        iseq.source_info.path.to_string_lossy(),
+        iseq.source_info.get_line(&iseq.loc)
+    )
+}
+
+/// Name of the method or block of `iseq`, like `Foo#bar` or `block in Foo#bar`.
+fn iseq_name(globals: &Globals, iseq: ISeqRef) -> String {
+    match iseq.kind {
+        ISeqKind::Other => "<main>".to_string(),
+        ISeqKind::Method(id) => {
+            let class = match iseq.class_defined {
+                Some(list) => match list.class.as_module() {
+                    Some(cref) => globals.get_ident_name(cref.name).to_string(),
+                    None => "Object".to_string(),
+                },
+                None => "Object".to_string(),
+            };
+            format!("{}#{}", class, globals.get_ident_name(id))
+        }
+        ISeqKind::Block(outer) => match globals.get_method_info(outer) {
+            MethodInfo::RubyFunc { iseq } => format!("block in {}", iseq_name(globals, *iseq)),
+            _ => "block".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+    use std::path::PathBuf;
+
+    fn profile(program: &str) -> VM {
+        let mut vm = VM::new();
+        vm.profiler = Some(Profiler::new());
+        if let Err(err) = vm.run(PathBuf::from("profile.rb"), program, None) {
+            err.show_err();
+            err.show_loc(0);
+            panic!("Got error: {:?}", err);
+        }
+        vm
+    }
+
+    #[test]
+    fn profile_reports() {
+        let program = r#"
+        class Foo
+            def fib(n)
+                if n < 2
+                    n
+                else
+                    fib(n - 1) + fib(n - 2)
+                end
+            end
+        end
+        def run
+            3.times { Foo.new.fib(10) }
+        end
+        run
+        "#;
+        let vm = profile(program);
+        let profiler = vm.profiler.as_ref().unwrap();
+        let globals = &vm.globals;
+
+        let flat = profiler.report(globals, ProfileFormat::Flat);
+        let fib = flat.lines().find(|l| l.contains("Foo#fib")).unwrap();
+        let calls: Vec<&str> = fib.split_whitespace().collect();
+        assert_eq!("531", calls[3]);
+        assert!(flat.contains("profile.rb:7 in Foo#fib"));
+        assert!(flat.contains("block in Object#run"));
+
+        let tree = profiler.report(globals, ProfileFormat::Tree);
+        let names: Vec<&str> = tree
+            .lines()
+            .skip(1)
+            .map(|l| l.split_whitespace().nth(3).unwrap())
+            .collect();
+        assert_eq!(
+            vec!["<main>", "Object#run", "block", "Foo#fib", "Foo#fib"],
+            names[..5].to_vec()
+        );
+
+        let collapsed = profiler.report(globals, ProfileFormat::Collapsed);
+        for line in collapsed.lines() {
+            let (stack, micros) = line.split_at(line.rfind(' ').unwrap());
+            assert!(stack.starts_with("<main>"));
+            micros.trim().parse::<u128>().unwrap();
+        }
+    }
+}
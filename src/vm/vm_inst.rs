@@ -1,4 +1,5 @@
 use crate::*;
+use std::collections::HashMap;
 
 pub struct Inst;
 impl Inst {
@@ -34,6 +35,7 @@ impl Inst {
     pub const ADDI: u8 = 30;
     pub const SUBI: u8 = 31;
     pub const IVAR_ADDI: u8 = 32;
+    pub const LVAR_ADDI: u8 = 33;
 
     pub const SET_LOCAL: u8 = 40;
     pub const GET_LOCAL: u8 = 41;
@@ -53,6 +55,9 @@ impl Inst {
 
     pub const SEND: u8 = 60;
     pub const SEND_SELF: u8 = 61;
+    /// Specialised sends which only appear in decoded instructions.
+    pub const OPT_SEND: u8 = 62;
+    pub const OPT_SEND_SELF: u8 = 63;
 
     pub const CREATE_RANGE: u8 = 70;
     pub const CREATE_ARRAY: u8 = 71;
@@ -66,6 +71,7 @@ impl Inst {
     pub const SPLAT: u8 = 83;
     pub const CONCAT_STRING: u8 = 84;
     pub const TO_S: u8 = 85;
+    pub const MERGE_KW: u8 = 86;
 
     pub const DEF_CLASS: u8 = 90;
     pub const DEF_METHOD: u8 = 91;
@@ -78,6 +84,10 @@ impl Inst {
     pub const OPT_CASE: u8 = 104;
     pub const MRETURN: u8 = 105;
     pub const YIELD: u8 = 106;
+    pub const JEQ: u8 = 107;
+    pub const JNE: u8 = 108;
+    pub const JGT: u8 = 109;
+    pub const JGE: u8 = 110;
 }
 
 #[allow(dead_code)]
@@ -116,6 +126,7 @@ impl Inst {
             Inst::ADDI => "ADDI",
             Inst::SUBI => "SUBI",
             Inst::IVAR_ADDI => "IVAR_ADDI",
+            Inst::LVAR_ADDI => "LVAR_ADDI",
 
             Inst::SET_LOCAL => "SET_LOCAL",
             Inst::GET_LOCAL => "GET_LOCAL",
@@ -133,6 +144,8 @@ impl Inst {
 
             Inst::SEND => "SEND",
             Inst::SEND_SELF => "SEND_SELF",
+            Inst::OPT_SEND => "OPT_SEND",
+            Inst::OPT_SEND_SELF => "OPT_SEND_SELF",
 
             Inst::CHECK_LOCAL => "CHECK_LOCAL",
 
@@ -148,6 +161,7 @@ impl Inst {
             Inst::SPLAT => "SPLAT",
             Inst::CONCAT_STRING => "CONCAT_STR",
             Inst::TO_S => "TO_S",
+            Inst::MERGE_KW => "MERGE_KW",
 
             Inst::DEF_CLASS => "DEF_CLASS",
             Inst::DEF_METHOD => "DEF_METHOD",
@@ -160,6 +174,10 @@ impl Inst {
             Inst::OPT_CASE => "OPT_CASE",
             Inst::MRETURN => "MRETURN",
             Inst::YIELD => "YIELD",
+            Inst::JEQ => "JEQ",
+            Inst::JNE => "JNE",
+            Inst::JGT => "JGT",
+            Inst::JGE => "JGE",
 
             _ => "undefined",
         }
@@ -176,6 +194,7 @@ impl Inst {
             | Inst::POW
             | Inst::EQ
             | Inst::NE
+            | Inst::TEQ
             | Inst::GT
             | Inst::GE
             | Inst::CMP
@@ -190,18 +209,16 @@ impl Inst {
             | Inst::CREATE_REGEXP
             | Inst::TO_S
             | Inst::SPLAT
+            | Inst::MERGE_KW
             | Inst::POP
             | Inst::RETURN
             | Inst::MRETURN => 1,
                                         // operand
             Inst::PUSH_STRING           // IdentId: u32
             | Inst::PUSH_SYMBOL         // IdentId: u32
-            | Inst::GET_CONST           // IdentId: u32
             | Inst::SET_CONST           // IdentId: u32
             | Inst::GET_CONST_TOP       // IdentId: u32
             | Inst::GET_SCOPE           // IdentId: u32
-            | Inst::GET_IVAR            // IdentId: u32
-            | Inst::SET_IVAR            // IdentId: u32
             | Inst::GET_GVAR            // IdentId: u32
             | Inst::SET_GVAR            // IdentId: u32
             | Inst::GET_INDEX
@@ -210,6 +227,10 @@ impl Inst {
             | Inst::CREATE_PROC
             | Inst::JMP                 // disp: u32
             | Inst::JMP_IF_FALSE        // disp: u32
+            | Inst::JEQ                 // disp: u32
+            | Inst::JNE                 // disp: u32
+            | Inst::JGT                 // disp: u32
+            | Inst::JGE                 // disp: u32
             | Inst::DUP                 // number of items: u32
             | Inst::TAKE                // number of items: u32
             | Inst::ADD                 // inline cache: u32
@@ -220,44 +241,61 @@ impl Inst {
             | Inst::SUBI                // inline cache: u32
             | Inst::SHL                 // inline cache: u32
             | Inst::CREATE_HASH         // number of items: u32
-            | Inst::YIELD               // number of items: u32
             => 5,
 
-            Inst::PUSH_FIXNUM
+            Inst::GET_CONST             // IdentId: u32 / const cache: u32
+            | Inst::GET_IVAR            // IdentId: u32 / ivar cache: u32
+            | Inst::SET_IVAR            // IdentId: u32 / ivar cache: u32
+            | Inst::PUSH_FIXNUM
             | Inst::PUSH_FLONUM
             | Inst::SET_LOCAL
             | Inst::GET_LOCAL
+            | Inst::CHECK_LOCAL
             | Inst::DEF_METHOD
             | Inst::DEF_SMETHOD
             | Inst::OPT_CASE
-            | Inst::IVAR_ADDI => 9,
+            | Inst::YIELD => 9,
             Inst::DEF_CLASS => 10,
+            Inst::LVAR_ADDI | Inst::IVAR_ADDI => 13,
             Inst::SEND | Inst::SEND_SELF => 17,
             _ => 1,
         }
     }
 
-    pub fn inst_info(globals: &mut Globals, iseq_ref: ISeqRef, pc: usize) -> String {
-        let iseq = &iseq_ref.iseq;
+    pub fn inst_info(globals: &Globals, iseq_ref: ISeqRef, pc: usize) -> String {
+        Inst::inst_info_with_labels(globals, &[iseq_ref], pc, &HashMap::new())
+    }
+
+    /// Format the instruction at `pc` of `scopes[0]`.
+    /// `scopes` lists the ISeq and its enclosing ISeqs, which are used to name outer local variables.
+    /// Jump destinations found in `labels` are shown as `L<n>`, others as addresses.
+    pub fn inst_info_with_labels(
+        globals: &Globals,
+        scopes: &[ISeqRef],
+        pc: usize,
+        labels: &HashMap<usize, usize>,
+    ) -> String {
+        let iseq = &scopes[0].iseq;
+        let dest = |pos: usize| match labels.get(&pos) {
+            Some(label) => format!("L{}", label),
+            None => format!("{:>05x}", pos),
+        };
         match iseq[pc] {
             Inst::END
             | Inst::PUSH_NIL
             | Inst::PUSH_TRUE
             | Inst::PUSH_FALSE
             | Inst::PUSH_SELF
-            | Inst::ADD
-            | Inst::SUB
-            | Inst::MUL
-            | Inst::DIV
             | Inst::REM
+            | Inst::POW
             | Inst::EQ
             | Inst::NE
+            | Inst::TEQ
             | Inst::GT
             | Inst::GE
             | Inst::CMP
             | Inst::NOT
             | Inst::SHR
-            | Inst::SHL
             | Inst::BIT_OR
             | Inst::BIT_AND
             | Inst::BIT_XOR
@@ -266,116 +304,180 @@ impl Inst {
             | Inst::CREATE_RANGE
             | Inst::CREATE_REGEXP
             | Inst::RETURN
+            | Inst::MRETURN
             | Inst::TO_S
             | Inst::SPLAT
-            | Inst::POP
-            | Inst::YIELD => format!("{}", Inst::inst_name(iseq[pc])),
-            Inst::PUSH_STRING => format!("PUSH_STRING {}", Inst::read32(iseq, pc + 1) as i32),
-            Inst::PUSH_SYMBOL => format!("PUSH_SYMBOL {}", Inst::read32(iseq, pc + 1) as i32),
+            | Inst::MERGE_KW
+            | Inst::POP => format!("{}", Inst::inst_name(iseq[pc])),
+            Inst::ADD | Inst::SUB | Inst::MUL | Inst::DIV | Inst::SHL => format!(
+                "{} cache:{}",
+                Inst::inst_name(iseq[pc]),
+                Inst::read32(iseq, pc + 1)
+            ),
+            Inst::PUSH_STRING => {
+                format!("PUSH_STRING {:?}", Inst::ident_name(globals, iseq, pc + 1))
+            }
+            Inst::PUSH_SYMBOL => format!(
+                "PUSH_SYMBOL {}",
+                crate::builtin::symbol::inspect_symbol(Inst::ident_name(globals, iseq, pc + 1))
+            ),
             Inst::ADDI => format!("ADDI {}", Inst::read32(iseq, pc + 1) as i32),
             Inst::SUBI => format!("SUBI {}", Inst::read32(iseq, pc + 1) as i32),
+            Inst::IVAR_ADDI => format!(
+                "IVAR_ADDI '{}' {}",
+                Inst::ident_name(globals, iseq, pc + 1),
+                Inst::read32(iseq, pc + 5) as i32
+            ),
             Inst::PUSH_FIXNUM => format!("PUSH_FIXNUM {}", Inst::read64(iseq, pc + 1) as i64),
+            Inst::YIELD => format!(
+                "YIELD args:{} kw:{}",
+                Inst::read32(iseq, pc + 1),
+                Inst::read32(iseq, pc + 5)
+            ),
             Inst::PUSH_FLONUM => {
                 format!("PUSH_FLONUM {}", f64::from_bits(Inst::read64(iseq, pc + 1)))
             }
 
-            Inst::JMP => format!(
-                "JMP {:>05x}",
-                pc as i32 + 5 + Inst::read32(iseq, pc + 1) as i32
-            ),
-            Inst::JMP_IF_FALSE => format!(
-                "JMP_IF_FALSE {:>05x}",
-                pc as i32 + 5 + Inst::read32(iseq, pc + 1) as i32
-            ),
-            Inst::OPT_CASE => {
-                //let val = Value::from(Inst::read64(iseq, pc + 1));
-                //let info = val.as_hash().unwrap();
-                /*let map = match info.inner_mut() {
-                    HashInfo::Map(map) => map,
-                    _ => panic!(),
-                };*/
+            Inst::JMP | Inst::JMP_IF_FALSE | Inst::JEQ | Inst::JNE | Inst::JGT | Inst::JGE => {
                 format!(
-                    "OPT_CASE {:>05}",
-                    pc as i32 + 13 + Inst::read32(iseq, pc + 9) as i32,
+                    "{} {}",
+                    Inst::inst_name(iseq[pc]),
+                    dest(Inst::jump_dests(globals, iseq, pc)[0])
                 )
             }
-            Inst::SET_LOCAL => {
-                let frame = Inst::read32(iseq, pc + 5);
-                let id = Inst::read32(iseq, pc + 1) as usize;
-                let ident_id = iseq_ref.lvar.get_name(LvarId::from_usize(id));
-                let name = globals.get_ident_name(ident_id);
-                format!("SET_LOCAL '{}' outer:{} LvarId:{}", name, frame, id)
-            }
-            Inst::GET_LOCAL => {
-                let frame = Inst::read32(iseq, pc + 5);
-                let id = Inst::read32(iseq, pc + 1) as usize;
-                let ident_id = iseq_ref.lvar.get_name(LvarId::from_usize(id));
-                let name = globals.get_ident_name(ident_id);
-                format!("GET_LOCAL '{}' outer:{} LvarId:{}", name, frame, id)
+            Inst::OPT_CASE => {
+                let map = globals.get_case_dispatch_map(Inst::read32(iseq, pc + 1));
+                let mut entries: Vec<(i64, i32)> = map
+                    .iter()
+                    .map(|(k, v)| (k.as_fixnum().unwrap(), *v))
+                    .collect();
+                entries.sort();
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(k, v)| format!("{}=>{}", k, dest((pc as i32 + 9 + v) as usize)))
+                    .collect();
+                format!(
+                    "OPT_CASE {{{}}} else {}",
+                    entries.join(", "),
+                    dest((pc as i32 + 9 + Inst::read32(iseq, pc + 5) as i32) as usize)
+                )
             }
-            Inst::CHECK_LOCAL => {
+            Inst::SET_LOCAL | Inst::GET_LOCAL | Inst::CHECK_LOCAL | Inst::LVAR_ADDI => {
                 let frame = Inst::read32(iseq, pc + 5);
                 let id = Inst::read32(iseq, pc + 1) as usize;
-                let ident_id = iseq_ref.lvar.get_name(LvarId::from_usize(id));
-                let name = globals.get_ident_name(ident_id);
-                format!("CHECK_LOCAL '{}' outer:{} LvarId:{}", name, frame, id)
+                let name = match scopes.get(frame as usize) {
+                    Some(scope) => match scope.lvar.get_name(LvarId::from_usize(id)) {
+                        Some(ident_id) => globals.get_ident_name(ident_id),
+                        None => "?",
+                    },
+                    None => "?",
+                };
+                let operand = format!(
+                    "{} '{}' outer:{} LvarId:{}",
+                    Inst::inst_name(iseq[pc]),
+                    name,
+                    frame,
+                    id
+                );
+                match iseq[pc] {
+                    Inst::LVAR_ADDI => format!("{} {}", operand, Inst::read32(iseq, pc + 9) as i32),
+                    _ => operand,
+                }
             }
-            Inst::GET_CONST => format!("GET_CONST '{}'", Inst::ident_name(globals, iseq, pc + 1)),
-            Inst::GET_CONST_TOP => format!(
-                "GET_CONST_TOP '{}'",
+            Inst::GET_CONST
+            | Inst::GET_CONST_TOP
+            | Inst::SET_CONST
+            | Inst::GET_SCOPE
+            | Inst::GET_IVAR
+            | Inst::SET_IVAR
+            | Inst::GET_GVAR
+            | Inst::SET_GVAR => format!(
+                "{} '{}'",
+                Inst::inst_name(iseq[pc]),
                 Inst::ident_name(globals, iseq, pc + 1)
             ),
-            Inst::SET_CONST => format!("SET_CONST '{}'", Inst::ident_name(globals, iseq, pc + 1)),
-            Inst::GET_SCOPE => format!("GET_SCOPE '{}'", Inst::ident_name(globals, iseq, pc + 1)),
-            Inst::GET_IVAR => format!("GET_IVAR '{}'", Inst::ident_name(globals, iseq, pc + 1)),
-            Inst::SET_IVAR => format!("SET_IVAR '{}'", Inst::ident_name(globals, iseq, pc + 1)),
             Inst::GET_INDEX => format!("GET_INDEX {} items", Inst::read32(iseq, pc + 1)),
             Inst::SET_INDEX => format!("SET_INDEX {} items", Inst::read32(iseq, pc + 1)),
-            Inst::SEND => format!(
-                "SEND '{}' {} items",
-                Inst::ident_name(globals, iseq, pc + 1),
-                Inst::read32(iseq, pc + 5)
-            ),
-            Inst::SEND_SELF => format!(
-                "SEND_SELF '{}' {} items",
-                Inst::ident_name(globals, iseq, pc + 1),
-                Inst::read32(iseq, pc + 5)
-            ),
+            Inst::SEND | Inst::SEND_SELF => {
+                let flag = Inst::read16(iseq, pc + 7);
+                let block = MethodRef::from(Inst::read32(iseq, pc + 13));
+                format!(
+                    "{} '{}' {} items{}{} cache:{}{}",
+                    Inst::inst_name(iseq[pc]),
+                    Inst::ident_name(globals, iseq, pc + 1),
+                    Inst::read16(iseq, pc + 5),
+                    if flag & 0b01 != 0 { " kw" } else { "" },
+                    if flag & 0b10 != 0 { " &block" } else { "" },
+                    Inst::read32(iseq, pc + 9),
+                    if block.is_none() {
+                        "".to_string()
+                    } else {
+                        format!(" block:{:?}", block)
+                    }
+                )
+            }
 
             Inst::CREATE_ARRAY => format!("CREATE_ARRAY {} items", Inst::read32(iseq, pc + 1)),
-            Inst::CREATE_PROC => format!("CREATE_PROC method:{}", Inst::read32(iseq, pc + 1)),
+            Inst::CREATE_PROC => format!(
+                "CREATE_PROC {:?}",
+                MethodRef::from(Inst::read32(iseq, pc + 1))
+            ),
             Inst::CREATE_HASH => format!("CREATE_HASH {} items", Inst::read32(iseq, pc + 1)),
             Inst::DUP => format!("DUP {}", Inst::read32(iseq, pc + 1)),
             Inst::TAKE => format!("TAKE {}", Inst::read32(iseq, pc + 1)),
             Inst::DEF_CLASS => format!(
-                "DEF_CLASS {} '{}' method:{}",
+                "DEF_CLASS {} '{}' {:?}",
                 if Inst::read8(iseq, pc + 1) == 1 {
                     "module"
                 } else {
                     "class"
                 },
                 Inst::ident_name(globals, iseq, pc + 2),
-                Inst::read32(iseq, pc)
+                MethodRef::from(Inst::read32(iseq, pc + 6))
+            ),
+            Inst::DEF_METHOD | Inst::DEF_SMETHOD => format!(
+                "{} '{}' {:?}",
+                Inst::inst_name(iseq[pc]),
+                Inst::ident_name(globals, iseq, pc + 1),
+                MethodRef::from(Inst::read32(iseq, pc + 5))
             ),
-            Inst::DEF_METHOD => format!("DEF_METHOD '{}'", Inst::ident_name(globals, iseq, pc + 1)),
-            Inst::DEF_SMETHOD => {
-                format!("DEF_SMETHOD '{}'", Inst::ident_name(globals, iseq, pc + 1))
-            }
             _ => format!("undefined"),
         }
     }
 
-    fn read64(iseq: &ISeq, pc: usize) -> u64 {
+    /// Destinations of the instruction at `pc` if it is a jump.
+    pub fn jump_dests(globals: &Globals, iseq: &ISeq, pc: usize) -> Vec<usize> {
+        let dest = |base: usize, disp: i32| (base as i32 + disp) as usize;
+        match iseq[pc] {
+            Inst::JMP | Inst::JMP_IF_FALSE | Inst::JEQ | Inst::JNE | Inst::JGT | Inst::JGE => {
+                vec![dest(pc + 5, Inst::read32(iseq, pc + 1) as i32)]
+            }
+            Inst::OPT_CASE => {
+                let map = globals.get_case_dispatch_map(Inst::read32(iseq, pc + 1));
+                let mut dests: Vec<usize> = map.values().map(|disp| dest(pc + 9, *disp)).collect();
+                dests.push(dest(pc + 9, Inst::read32(iseq, pc + 5) as i32));
+                dests
+            }
+            _ => vec![],
+        }
+    }
+
+    pub fn read64(iseq: &ISeq, pc: usize) -> u64 {
         let ptr = iseq[pc..pc + 1].as_ptr() as *const u64;
         unsafe { *ptr }
     }
 
-    fn read32(iseq: &ISeq, pc: usize) -> u32 {
+    pub fn read32(iseq: &ISeq, pc: usize) -> u32 {
         let ptr = iseq[pc..pc + 1].as_ptr() as *const u32;
         unsafe { *ptr }
     }
 
-    fn read8(iseq: &ISeq, pc: usize) -> u8 {
+    pub fn read16(iseq: &ISeq, pc: usize) -> u16 {
+        let ptr = iseq[pc..pc + 1].as_ptr() as *const u16;
+        unsafe { *ptr }
+    }
+
+    pub fn read8(iseq: &ISeq, pc: usize) -> u8 {
         iseq[pc]
     }
 
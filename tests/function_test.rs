@@ -102,6 +102,101 @@ fn parameters() {
     assert_script(program);
 }
 
+#[test]
+fn keyword_parameters() {
+    let program = "
+        def fn(a, b:, c: 2)
+            [a, b, c]
+        end
+
+        assert([1, 5, 2], fn(1, b: 5))
+        assert([1, 5, 7], fn(1, c: 7, b: 5))
+        assert_error { fn(1) }
+        assert_error { fn(1, b: 5, d: 3) }
+        assert(2, method(:fn).arity)
+
+        def kw(a: 1, **opts)
+            [a, opts]
+        end
+
+        assert([1, {}], kw)
+        assert([3, {b: 4, c: 5}], kw(a: 3, b: 4, c: 5))
+        h = {a: 10, z: 20}
+        assert([10, {z: 20}], kw(**h))
+        assert([10, {z: 20, y: 30}], kw(y: 30, **h))
+        assert([1, {}], kw(**{}))
+        assert(-1, method(:kw).arity)
+        ";
+    assert_script(program);
+}
+
+#[test]
+fn keyword_splat_order() {
+    let program = "
+        def f(**kw)
+            kw
+        end
+
+        assert({a: 2}, f(a: 1, **{a: 2}))
+        assert({a: 1}, f(**{a: 2}, a: 1))
+        assert({a: 3, b: 2}, f(a: 1, **{a: 2, b: 2}, a: 3))
+        assert({a: 2, b: 1}, f(**{a: 1, b: 1}, **{a: 2}))
+        assert({a: 2}, f(a: 1, a: 2))
+        assert({a: 2}, {a: 1, a: 2})
+        class Hash
+            def merge(other)
+                {}
+            end
+        end
+        assert({a: 1, b: 2}, f(a: 1, **{b: 2}))
+        order = []
+        f(a: (order << 1), **(order << 2; {}), c: (order << 3))
+        assert([1, 2, 3], order)
+        ";
+    assert_script(program);
+}
+
+#[test]
+fn block_keyword_parameters() {
+    let program = "
+        def fn
+            yield 1, b: 2
+        end
+
+        assert([1, 2, 3], fn { |a, b:, c: 3| [a, b, c] })
+        assert([1, {b: 2}], fn { |a, **kw| [a, kw] })
+        assert_error { fn { |a, c:| c } }
+        ";
+    assert_script(program);
+}
+
+#[test]
+fn argument_forwarding() {
+    let program = "
+        def target(*args, **kw, &block)
+            [args, kw, block ? block.call : nil]
+        end
+
+        def fwd(...)
+            target(...)
+        end
+
+        def fwd_lead(a, ...)
+            target(a * 10, ...)
+        end
+
+        def anon(*, **, &)
+            target(*, **, &)
+        end
+
+        assert([[1, 2], {k: 3}, 4], fwd(1, 2, k: 3) { 4 })
+        assert([[], {}, nil], fwd)
+        assert([[10, 2], {k: 3}, nil], fwd_lead(1, 2, k: 3))
+        assert([[1], {k: 2}, 3], anon(1, k: 2) { 3 })
+        ";
+    assert_script(program);
+}
+
 #[test]
 fn return1() {
     let program = "
@@ -137,3 +232,166 @@ fn splat_argument() {
     "#;
     assert_script(program);
 }
+
+#[test]
+fn polymorphic_call_site() {
+    let program = r#"
+        class A; def name; "A"; end; end
+        class B; def name; "B"; end; end
+        class C; def name; "C"; end; end
+        class D; def name; "D"; end; end
+        class E; def name; "E"; end; end
+        class F < E; end
+        class G; def name; "G"; end; end
+        def names(objs)
+            objs.map { |o| o.name }
+        end
+        objs = [A.new, B.new, C.new, D.new, E.new, F.new, G.new]
+        assert ["A", "B", "C", "D", "E", "E", "G"], names(objs)
+        assert ["A", "B", "C", "D", "E", "E", "G"], names(objs)
+        def add(a, b, c)
+            a + b * c
+        end
+        assert 7, add(1, 2, 3)
+        assert 7, add(*[1, 2], 3)
+        assert_error { add(1, 2) }
+    "#;
+    assert_script(program);
+}
+
+#[test]
+fn method_cache_invalidation() {
+    let program = r#"
+        class Foo; def greet; "foo"; end; end
+        class Bar < Foo; end
+        def call(o)
+            o.greet
+        end
+        bar = Bar.new
+        assert "foo", call(bar)
+        class Bar; def greet; "bar"; end; end
+        assert "bar", call(bar)
+        Bar.remove_method(:greet)
+        assert "foo", call(bar)
+        module Hello; def greet; "hello"; end; end
+        class Bar; include Hello; end
+        assert "hello", call(bar)
+        assert_error { Bar.remove_method(:greet) }
+    "#;
+    assert_script(program);
+}
+
+#[test]
+fn constant_cache_invalidation() {
+    let program = r#"
+        X = 1
+        def x; X; end
+        assert 1, x
+        X = 2
+        assert 2, x
+        Object.const_set(:X, 3)
+        assert 3, x
+        class Foo
+            Y = 10
+            class Bar
+                def y; Y; end
+            end
+        end
+        assert 10, Foo::Bar.new.y
+        Foo::Bar.const_set(:Y, 20)
+        assert 20, Foo::Bar.new.y
+        assert 20, Foo::Bar.remove_const(:Y)
+        assert 10, Foo::Bar.new.y
+        assert_error { Foo::Bar.remove_const(:Y) }
+        assert_error { Foo.const_set(:z, 1) }
+    "#;
+    assert_script(program);
+}
+
+#[test]
+fn instance_variable_shapes() {
+    let program = r#"
+        class Point
+            def initialize(x, y, z = nil)
+                @z = z if z
+                @x = x
+                @y = y
+            end
+            def sum; @x + @y; end
+            def inc; @x += 1; end
+        end
+        a = Point.new(1, 2)
+        b = Point.new(3, 4, 5)
+        assert 3, a.sum
+        assert 7, b.sum
+        assert 3, a.sum
+        a.inc
+        b.inc
+        assert 4, a.sum
+        assert 8, b.sum
+        assert [:@x, :@y], a.instance_variables
+        assert [:@z, :@x, :@y], b.instance_variables
+        a.instance_variable_set(:@y, 10)
+        assert 12, a.sum
+    "#;
+    assert_script(program);
+}
+
+#[test]
+fn deep_recursion() {
+    let program = r#"
+        def sum(n)
+            if n == 0
+                0
+            else
+                n + sum(n - 1)
+            end
+        end
+        assert 40504500, sum(9000)
+        def each_down(n, &block)
+            yield n
+            each_down(n - 1, &block) if n > 0
+        end
+        a = []
+        each_down(3) { |i| a << i }
+        assert [3, 2, 1, 0], a
+        def find_first(n)
+            each_down(n) { |i| return i if i % 7 == 0 && i != n }
+            nil
+        end
+        assert 98, find_first(100)
+        assert Class, SystemStackError.class
+        def infinite(n)
+            infinite(n + 1)
+        end
+        assert_error { infinite(0) }
+    "#;
+    assert_script(program);
+}
+
+#[test]
+fn instance_variable_shapes_in_fiber() {
+    let program = r#"
+        class Point
+            def initialize(x, y)
+                @x = x
+                @y = y
+            end
+            def sum; @x + @y; end
+        end
+        a = Point.new(1, 2)
+        f = Fiber.new do
+            a.instance_variable_set(:@z, 5)
+            Fiber.yield Point.new(3, 4)
+        end
+        b = f.resume
+        assert 7, b.sum
+        assert 3, a.sum
+        b.instance_variable_set(:@z, 6)
+        assert [:@x, :@y, :@z], a.instance_variables
+        assert [:@x, :@y, :@z], b.instance_variables
+        assert 5, a.instance_variable_get(:@z)
+        assert 6, b.instance_variable_get(:@z)
+    "#;
+    assert_script(program);
+}